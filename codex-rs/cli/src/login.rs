@@ -6,6 +6,10 @@ use codex_login::CLIENT_ID;
 use codex_login::CodexAuth;
 use codex_login::OPENAI_API_KEY_ENV_VAR;
 use codex_login::ServerOptions;
+use codex_login::credential_store::CredentialStoreMode;
+use codex_login::credential_store::get_credential_store_mode;
+use codex_login::credential_store::list_credentials;
+use codex_login::credential_store::migrate_credentials;
 use codex_login::login_with_api_key;
 use codex_login::logout;
 use codex_login::run_login_server;
@@ -115,6 +119,49 @@ pub async fn run_logout(cli_config_overrides: CliConfigOverrides) -> ! {
     }
 }
 
+pub async fn run_auth_status(cli_config_overrides: CliConfigOverrides) -> ! {
+    let config = load_config_or_exit(cli_config_overrides);
+
+    eprintln!(
+        "Credential store: {}",
+        get_credential_store_mode(&config.codex_home)
+    );
+    match list_credentials(&config.codex_home) {
+        Ok(names) if names.is_empty() => {
+            eprintln!("No stored credentials");
+            std::process::exit(0);
+        }
+        Ok(names) => {
+            for name in names {
+                eprintln!("   {name}");
+            }
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("Error reading credentials: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+pub async fn run_auth_migrate(
+    cli_config_overrides: CliConfigOverrides,
+    to: CredentialStoreMode,
+) -> ! {
+    let config = load_config_or_exit(cli_config_overrides);
+
+    match migrate_credentials(&config.codex_home, to) {
+        Ok(names) => {
+            eprintln!("Moved {} credential(s) to the {to} store", names.len());
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("Error migrating credentials: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
 fn load_config_or_exit(cli_config_overrides: CliConfigOverrides) -> Config {
     let cli_overrides = match cli_config_overrides.parse_overrides() {
         Ok(v) => v,
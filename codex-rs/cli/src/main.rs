@@ -7,13 +7,19 @@ use codex_chatgpt::apply_command::ApplyCommand;
 use codex_chatgpt::apply_command::run_apply_command;
 use codex_cli::LandlockCommand;
 use codex_cli::SeatbeltCommand;
+use codex_cli::login::run_auth_migrate;
+use codex_cli::login::run_auth_status;
 use codex_cli::login::run_login_status;
 use codex_cli::login::run_login_with_api_key;
 use codex_cli::login::run_login_with_chatgpt;
 use codex_cli::login::run_logout;
 use codex_cli::proto;
+use codex_cli::scheduler::SchedulerCli;
 use codex_common::CliConfigOverrides;
 use codex_exec::Cli as ExecCli;
+use codex_login::credential_store::CredentialStoreMode;
+use codex_mcp_server::Cli as McpCli;
+use codex_mcp_server::McpCommand;
 use codex_tui::Cli as TuiCli;
 use std::path::PathBuf;
 
@@ -56,13 +62,19 @@ enum Subcommand {
     /// Remove stored authentication credentials.
     Logout(LogoutCommand),
 
+    /// Manage where stored credentials are kept.
+    Auth(AuthCommand),
+
     /// Experimental: run Codex as an MCP server.
-    Mcp,
+    Mcp(McpCli),
 
     /// Run the Protocol stream via stdin/stdout
     #[clap(visible_alias = "p")]
     Proto(ProtoCli),
 
+    /// Run configured prompts and tool pipelines on a cron schedule.
+    Scheduler(SchedulerCli),
+
     /// Generate shell completion scripts.
     Completion(CompletionCommand),
 
@@ -124,6 +136,28 @@ struct LogoutCommand {
     config_overrides: CliConfigOverrides,
 }
 
+#[derive(Debug, Parser)]
+struct AuthCommand {
+    #[clap(skip)]
+    config_overrides: CliConfigOverrides,
+
+    #[command(subcommand)]
+    action: AuthSubcommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum AuthSubcommand {
+    /// Show the active credential store and the credentials it holds.
+    Status,
+
+    /// Move all stored credentials into another store.
+    Migrate {
+        /// Target store: `file`, `encrypted-file`, or `keyring`.
+        #[arg(long = "to", value_name = "STORE")]
+        to: CredentialStoreMode,
+    },
+}
+
 #[derive(Debug, Parser)]
 struct GenerateTsCommand {
     /// Output directory where .ts files will be written
@@ -158,8 +192,19 @@ async fn cli_main(codex_linux_sandbox_exe: Option<PathBuf>) -> anyhow::Result<()
             prepend_config_flags(&mut exec_cli.config_overrides, cli.config_overrides);
             codex_exec::run_main(exec_cli, codex_linux_sandbox_exe).await?;
         }
-        Some(Subcommand::Mcp) => {
-            codex_mcp_server::run_main(codex_linux_sandbox_exe, cli.config_overrides).await?;
+        Some(Subcommand::Mcp(mcp_cli)) => {
+            if let Some(McpCommand::Selftest { json }) = mcp_cli.command {
+                codex_mcp_server::run_selftest(cli.config_overrides, mcp_cli.mode(), json).await?;
+                return Ok(());
+            }
+            codex_mcp_server::run_main(
+                codex_linux_sandbox_exe,
+                cli.config_overrides,
+                mcp_cli.transport(),
+                mcp_cli.mode(),
+                mcp_cli.allow_unauthenticated,
+            )
+            .await?;
         }
         Some(Subcommand::Login(mut login_cli)) => {
             prepend_config_flags(&mut login_cli.config_overrides, cli.config_overrides);
@@ -180,10 +225,23 @@ async fn cli_main(codex_linux_sandbox_exe: Option<PathBuf>) -> anyhow::Result<()
             prepend_config_flags(&mut logout_cli.config_overrides, cli.config_overrides);
             run_logout(logout_cli.config_overrides).await;
         }
+        Some(Subcommand::Auth(mut auth_cli)) => {
+            prepend_config_flags(&mut auth_cli.config_overrides, cli.config_overrides);
+            match auth_cli.action {
+                AuthSubcommand::Status => run_auth_status(auth_cli.config_overrides).await,
+                AuthSubcommand::Migrate { to } => {
+                    run_auth_migrate(auth_cli.config_overrides, to).await
+                }
+            }
+        }
         Some(Subcommand::Proto(mut proto_cli)) => {
             prepend_config_flags(&mut proto_cli.config_overrides, cli.config_overrides);
             proto::run_main(proto_cli).await?;
         }
+        Some(Subcommand::Scheduler(mut scheduler_cli)) => {
+            prepend_config_flags(&mut scheduler_cli.config_overrides, cli.config_overrides);
+            codex_cli::scheduler::run_main(scheduler_cli, codex_linux_sandbox_exe).await?;
+        }
         Some(Subcommand::Completion(completion_cli)) => {
             print_completion(completion_cli);
         }
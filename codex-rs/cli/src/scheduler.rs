@@ -0,0 +1,138 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::Parser;
+use codex_common::CliConfigOverrides;
+use codex_core::config::Config;
+use codex_core::config::ConfigOverrides;
+use codex_core::scheduler::RunStatus;
+use codex_core::scheduler::Scheduler;
+use codex_core::scheduler::TaskRun;
+use codex_core::scheduler::history_path;
+use codex_core::scheduler::read_history;
+use codex_login::AuthManager;
+
+#[derive(Debug, Parser)]
+pub struct SchedulerCli {
+    #[clap(skip)]
+    pub config_overrides: CliConfigOverrides,
+
+    #[command(subcommand)]
+    pub action: SchedulerSubcommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum SchedulerSubcommand {
+    /// Run the tasks of the [scheduler] table on their schedules until
+    /// interrupted.
+    Start,
+
+    /// Run one task now.
+    Run {
+        /// Name of the task.
+        task: String,
+    },
+
+    /// List the tasks and when they run next.
+    List,
+
+    /// Show the most recent runs.
+    History {
+        /// Number of runs to show.
+        #[arg(short = 'n', long = "limit", default_value_t = 20)]
+        limit: usize,
+
+        /// Print the runs as JSON lines.
+        #[arg(long = "json", default_value_t = false)]
+        json: bool,
+    },
+}
+
+pub async fn run_main(
+    cli: SchedulerCli,
+    codex_linux_sandbox_exe: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let SchedulerCli {
+        config_overrides,
+        action,
+    } = cli;
+    let cli_overrides = config_overrides
+        .parse_overrides()
+        .map_err(anyhow::Error::msg)?;
+    let config = Config::load_with_cli_overrides(
+        cli_overrides.clone(),
+        ConfigOverrides {
+            codex_linux_sandbox_exe,
+            ..Default::default()
+        },
+    )?;
+
+    let scheduler = || {
+        let auth_manager =
+            AuthManager::shared(config.codex_home.clone(), config.preferred_auth_method);
+        Scheduler::new(&config, cli_overrides.clone(), auth_manager)
+    };
+    match action {
+        SchedulerSubcommand::Start => {
+            let scheduler = scheduler()?;
+            tracing_subscriber::fmt()
+                .with_max_level(tracing::Level::INFO)
+                .with_writer(std::io::stderr)
+                .init();
+            tokio::select! {
+                _ = Arc::new(scheduler).run() => {}
+                _ = tokio::signal::ctrl_c() => {
+                    eprintln!("Stopping the scheduler");
+                }
+            }
+        }
+        SchedulerSubcommand::Run { task } => {
+            let run = scheduler()?.run_task(&task).await?;
+            print_run(&run);
+            if run.status != RunStatus::Succeeded {
+                std::process::exit(1);
+            }
+        }
+        SchedulerSubcommand::List => {
+            for (name, next) in scheduler()?.next_runs() {
+                match next {
+                    Some(next) => println!("{name}\tnext run {}", next.format("%Y-%m-%d %H:%M")),
+                    None => println!("{name}\tdisabled"),
+                }
+            }
+        }
+        SchedulerSubcommand::History { limit, json } => {
+            let runs = read_history(&history_path(&config.codex_home), limit)?;
+            if runs.is_empty() && !json {
+                eprintln!("No runs yet");
+            }
+            for run in runs {
+                if json {
+                    println!("{}", serde_json::to_string(&run)?);
+                } else {
+                    print_run(&run);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_run(run: &TaskRun) {
+    let status = match run.status {
+        RunStatus::Succeeded => "succeeded",
+        RunStatus::Failed => "failed",
+        RunStatus::TimedOut => "timed out",
+    };
+    println!(
+        "{}  {}  {status} after {}s",
+        run.started_at.format("%Y-%m-%d %H:%M UTC"),
+        run.task,
+        (run.finished_at - run.started_at).num_seconds()
+    );
+    if let Some(text) = run.error.as_ref().or(run.output.as_ref()) {
+        for line in text.lines() {
+            println!("    {line}");
+        }
+    }
+}
@@ -0,0 +1,470 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bytes::Bytes;
+use eventsource_stream::Eventsource;
+use futures::Stream;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tracing::trace;
+
+use crate::ModelProviderInfo;
+use crate::chat_completions::post_sse_with_retries;
+use crate::client_common::Prompt;
+use crate::client_common::ResponseEvent;
+use crate::client_common::ResponseStream;
+use crate::client_common::add_anthropic_cache_breakpoints;
+use crate::error::CodexErr;
+use crate::error::Result;
+use crate::model_family::ModelFamily;
+use crate::openai_tools::create_tools_json_for_anthropic_api;
+use crate::protocol::TokenUsage;
+use codex_protocol::models::ContentItem;
+use codex_protocol::models::ReasoningItemContent;
+use codex_protocol::models::ResponseItem;
+
+/// Anthropic requires `max_tokens` on every request; used when the config
+/// does not set `model_max_output_tokens`.
+const DEFAULT_MAX_TOKENS: u64 = 8192;
+
+/// Implementation for the Anthropic Messages API.
+pub(crate) async fn stream_anthropic_messages(
+    prompt: &Prompt,
+    model_family: &ModelFamily,
+    max_output_tokens: Option<u64>,
+    prompt_caching: bool,
+    client: &reqwest::Client,
+    provider: &ModelProviderInfo,
+) -> Result<ResponseStream> {
+    let messages = build_messages(&prompt.get_formatted_input());
+    let tools_json = create_tools_json_for_anthropic_api(&prompt.tools)?;
+
+    let mut payload = json!({
+        "model": model_family.slug,
+        "system": prompt.get_full_instructions_for_api(model_family, provider.wire_api),
+        "messages": messages,
+        "max_tokens": max_output_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        "stream": true,
+    });
+    if !tools_json.is_empty() {
+        payload["tools"] = json!(tools_json);
+    }
+    if prompt_caching {
+        add_anthropic_cache_breakpoints(&mut payload);
+    }
+
+    let resp = post_sse_with_retries(client, provider, &None, &model_family.slug, &payload).await?;
+    let (tx_event, rx_event) = mpsc::channel::<Result<ResponseEvent>>(1600);
+    let stream = resp.bytes_stream().map_err(CodexErr::Reqwest);
+    tokio::spawn(process_anthropic_sse(
+        stream,
+        tx_event,
+        provider.stream_idle_timeout(),
+    ));
+    Ok(ResponseStream { rx_event })
+}
+
+/// Convert the conversation history into Anthropic `messages`. Anthropic
+/// only knows `user` and `assistant` turns and requires them to alternate,
+/// so consecutive items with the same role are merged into one message.
+fn build_messages(input: &[ResponseItem]) -> Vec<serde_json::Value> {
+    let mut messages = Vec::<(String, Vec<serde_json::Value>)>::new();
+    let mut push = |role: &str, block: serde_json::Value| match messages.last_mut() {
+        Some((last_role, blocks)) if last_role == role => blocks.push(block),
+        _ => messages.push((role.to_string(), vec![block])),
+    };
+
+    for item in input {
+        match item {
+            ResponseItem::Message { role, content, .. } => {
+                let role = if role == "assistant" {
+                    "assistant"
+                } else {
+                    "user"
+                };
+                for c in content {
+                    match c {
+                        ContentItem::InputText { text } | ContentItem::OutputText { text } => {
+                            if !text.is_empty() {
+                                push(role, json!({"type": "text", "text": text}));
+                            }
+                        }
+                        ContentItem::InputImage { image_url } => {
+                            let source = match split_data_url(image_url) {
+                                Some((media_type, data)) => json!({
+                                    "type": "base64",
+                                    "media_type": media_type,
+                                    "data": data,
+                                }),
+                                None => json!({"type": "url", "url": image_url}),
+                            };
+                            push(role, json!({"type": "image", "source": source}));
+                        }
+                    }
+                }
+            }
+            ResponseItem::FunctionCall {
+                name,
+                arguments,
+                call_id,
+                ..
+            } => {
+                let input = serde_json::from_str::<serde_json::Value>(arguments)
+                    .unwrap_or_else(|_| json!({}));
+                push(
+                    "assistant",
+                    json!({"type": "tool_use", "id": call_id, "name": name, "input": input}),
+                );
+            }
+            ResponseItem::FunctionCallOutput { call_id, output } => {
+                push(
+                    "user",
+                    json!({
+                        "type": "tool_result",
+                        "tool_use_id": call_id,
+                        "content": output.content,
+                        "is_error": output.success == Some(false),
+                    }),
+                );
+            }
+            ResponseItem::Reasoning { .. }
+            | ResponseItem::LocalShellCall { .. }
+            | ResponseItem::CustomToolCall { .. }
+            | ResponseItem::CustomToolCallOutput { .. }
+            | ResponseItem::WebSearchCall { .. }
+            | ResponseItem::Other => {
+                // Omit these items from the conversation history.
+                continue;
+            }
+        }
+    }
+
+    messages
+        .into_iter()
+        .map(|(role, content)| json!({"role": role, "content": content}))
+        .collect()
+}
+
+/// Split a `data:<media type>;base64,<data>` URL into its media type and
+/// payload.
+pub(crate) fn split_data_url(url: &str) -> Option<(&str, &str)> {
+    let rest = url.strip_prefix("data:")?;
+    let (media_type, data) = rest.split_once(";base64,")?;
+    Some((media_type, data))
+}
+
+/// Content block currently being streamed, keyed by its `index`.
+enum Block {
+    Text(String),
+    Thinking(String),
+    ToolUse {
+        id: String,
+        name: String,
+        arguments: String,
+    },
+}
+
+/// SSE processor for the Anthropic Messages streaming format. Events are
+/// mapped onto [`ResponseEvent`] the same way the Chat Completions processor
+/// does, so the aggregation adapter works unchanged.
+async fn process_anthropic_sse<S>(
+    stream: S,
+    tx_event: mpsc::Sender<Result<ResponseEvent>>,
+    idle_timeout: Duration,
+) where
+    S: Stream<Item = Result<Bytes>> + Unpin,
+{
+    let mut stream = stream.eventsource();
+    let mut blocks = HashMap::<u64, Block>::new();
+    let mut response_id = String::new();
+    let mut input_tokens = 0;
+    let mut cached_input_tokens = None;
+    let mut output_tokens = 0;
+
+    loop {
+        let sse = match timeout(idle_timeout, stream.next()).await {
+            Ok(Some(Ok(ev))) => ev,
+            Ok(Some(Err(e))) => {
+                let _ = tx_event
+                    .send(Err(CodexErr::Stream(e.to_string(), None)))
+                    .await;
+                return;
+            }
+            Ok(None) => {
+                let _ = tx_event
+                    .send(Err(CodexErr::Stream(
+                        "stream closed before message_stop".into(),
+                        None,
+                    )))
+                    .await;
+                return;
+            }
+            Err(_) => {
+                let _ = tx_event
+                    .send(Err(CodexErr::Stream(
+                        "idle timeout waiting for SSE".into(),
+                        None,
+                    )))
+                    .await;
+                return;
+            }
+        };
+
+        let event: serde_json::Value = match serde_json::from_str(&sse.data) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        trace!("anthropic received SSE event: {event:?}");
+
+        match event
+            .get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or_default()
+        {
+            "message_start" => {
+                let message = &event["message"];
+                response_id = message["id"].as_str().unwrap_or_default().to_string();
+                let usage = &message["usage"];
+                input_tokens = usage["input_tokens"].as_u64().unwrap_or_default();
+                cached_input_tokens = usage["cache_read_input_tokens"].as_u64();
+                output_tokens = usage["output_tokens"].as_u64().unwrap_or_default();
+                let _ = tx_event.send(Ok(ResponseEvent::Created)).await;
+            }
+            "content_block_start" => {
+                let Some(index) = event["index"].as_u64() else {
+                    continue;
+                };
+                let block = &event["content_block"];
+                let block = match block["type"].as_str() {
+                    Some("text") => Block::Text(String::new()),
+                    Some("thinking") => Block::Thinking(String::new()),
+                    Some("tool_use") => Block::ToolUse {
+                        id: block["id"].as_str().unwrap_or_default().to_string(),
+                        name: block["name"].as_str().unwrap_or_default().to_string(),
+                        arguments: String::new(),
+                    },
+                    _ => continue,
+                };
+                blocks.insert(index, block);
+            }
+            "content_block_delta" => {
+                let Some(block) = event["index"].as_u64().and_then(|i| blocks.get_mut(&i)) else {
+                    continue;
+                };
+                let delta = &event["delta"];
+                match (block, delta["type"].as_str()) {
+                    (Block::Text(text), Some("text_delta")) => {
+                        let delta = delta["text"].as_str().unwrap_or_default();
+                        text.push_str(delta);
+                        let _ = tx_event
+                            .send(Ok(ResponseEvent::OutputTextDelta(delta.to_string())))
+                            .await;
+                    }
+                    (Block::Thinking(text), Some("thinking_delta")) => {
+                        let delta = delta["thinking"].as_str().unwrap_or_default();
+                        text.push_str(delta);
+                        let _ = tx_event
+                            .send(Ok(ResponseEvent::ReasoningContentDelta(delta.to_string())))
+                            .await;
+                    }
+                    (Block::ToolUse { arguments, .. }, Some("input_json_delta")) => {
+                        arguments.push_str(delta["partial_json"].as_str().unwrap_or_default());
+                    }
+                    _ => {}
+                }
+            }
+            "content_block_stop" => {
+                let Some(block) = event["index"].as_u64().and_then(|i| blocks.remove(&i)) else {
+                    continue;
+                };
+                let item = match block {
+                    Block::Text(text) => ResponseItem::Message {
+                        id: None,
+                        role: "assistant".to_string(),
+                        content: vec![ContentItem::OutputText { text }],
+                    },
+                    Block::Thinking(text) => ResponseItem::Reasoning {
+                        id: String::new(),
+                        summary: Vec::new(),
+                        content: Some(vec![ReasoningItemContent::ReasoningText { text }]),
+                        encrypted_content: None,
+                    },
+                    Block::ToolUse {
+                        id,
+                        name,
+                        arguments,
+                    } => ResponseItem::FunctionCall {
+                        id: None,
+                        name,
+                        // A tool without parameters streams no input at all.
+                        arguments: if arguments.is_empty() {
+                            "{}".to_string()
+                        } else {
+                            arguments
+                        },
+                        call_id: id,
+                    },
+                };
+                let _ = tx_event.send(Ok(ResponseEvent::OutputItemDone(item))).await;
+            }
+            "message_delta" => {
+                if let Some(tokens) = event["usage"]["output_tokens"].as_u64() {
+                    output_tokens = tokens;
+                }
+            }
+            "message_stop" => {
+                let _ = tx_event
+                    .send(Ok(ResponseEvent::Completed {
+                        response_id: std::mem::take(&mut response_id),
+                        token_usage: Some(TokenUsage {
+                            input_tokens,
+                            cached_input_tokens,
+                            output_tokens,
+                            reasoning_output_tokens: None,
+                            total_tokens: input_tokens + output_tokens,
+                        }),
+                    }))
+                    .await;
+                return;
+            }
+            "error" => {
+                let message = event["error"]["message"]
+                    .as_str()
+                    .unwrap_or("unknown error")
+                    .to_string();
+                let _ = tx_event.send(Err(CodexErr::Stream(message, None))).await;
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use codex_protocol::models::FunctionCallOutputPayload;
+    use pretty_assertions::assert_eq;
+
+    async fn collect_events(body: &str) -> Vec<Result<ResponseEvent>> {
+        let (tx, mut rx) = mpsc::channel(16);
+        let chunks = vec![Ok::<_, CodexErr>(Bytes::from(body.to_string()))];
+        process_anthropic_sse(futures::stream::iter(chunks), tx, Duration::from_secs(5)).await;
+        let mut events = Vec::new();
+        while let Some(ev) = rx.recv().await {
+            events.push(ev);
+        }
+        events
+    }
+
+    #[test]
+    fn merges_consecutive_turns_and_maps_tool_calls() {
+        let input = vec![
+            ResponseItem::Message {
+                id: None,
+                role: "user".to_string(),
+                content: vec![ContentItem::InputText {
+                    text: "env context".to_string(),
+                }],
+            },
+            ResponseItem::Message {
+                id: None,
+                role: "user".to_string(),
+                content: vec![ContentItem::InputText {
+                    text: "list files".to_string(),
+                }],
+            },
+            ResponseItem::FunctionCall {
+                id: None,
+                name: "shell".to_string(),
+                arguments: r#"{"command":["ls"]}"#.to_string(),
+                call_id: "toolu_1".to_string(),
+            },
+            ResponseItem::FunctionCallOutput {
+                call_id: "toolu_1".to_string(),
+                output: FunctionCallOutputPayload {
+                    content: "README.md".to_string(),
+                    success: Some(true),
+                },
+            },
+        ];
+
+        assert_eq!(
+            build_messages(&input),
+            vec![
+                json!({"role": "user", "content": [
+                    {"type": "text", "text": "env context"},
+                    {"type": "text", "text": "list files"},
+                ]}),
+                json!({"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "toolu_1", "name": "shell", "input": {"command": ["ls"]}},
+                ]}),
+                json!({"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": "README.md", "is_error": false},
+                ]}),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn maps_text_and_tool_use_events() {
+        let body = [
+            r#"{"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":10,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"shell","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"command\":"}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"[\"ls\"]}"}}"#,
+            r#"{"type":"content_block_stop","index":1}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":7}}"#,
+            r#"{"type":"message_stop"}"#,
+        ]
+        .iter()
+        .map(|data| format!("data: {data}\n\n"))
+        .collect::<String>();
+
+        let events = collect_events(&body).await;
+        let events: Vec<ResponseEvent> = events.into_iter().map(|e| e.unwrap()).collect();
+
+        assert_eq!(events.len(), 5);
+        assert!(matches!(events[0], ResponseEvent::Created));
+        assert!(matches!(&events[1], ResponseEvent::OutputTextDelta(d) if d == "Hi"));
+        assert!(matches!(
+            &events[2],
+            ResponseEvent::OutputItemDone(ResponseItem::Message { content, .. })
+                if content == &vec![ContentItem::OutputText { text: "Hi".to_string() }]
+        ));
+        assert!(matches!(
+            &events[3],
+            ResponseEvent::OutputItemDone(ResponseItem::FunctionCall { name, arguments, call_id, .. })
+                if name == "shell" && arguments == r#"{"command":["ls"]}"# && call_id == "toolu_1"
+        ));
+        match &events[4] {
+            ResponseEvent::Completed {
+                response_id,
+                token_usage,
+            } => {
+                assert_eq!(response_id, "msg_1");
+                let usage = token_usage.as_ref().unwrap();
+                assert_eq!(usage.input_tokens, 10);
+                assert_eq!(usage.output_tokens, 7);
+                assert_eq!(usage.total_tokens, 17);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn surfaces_error_events() {
+        let body = "data: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n";
+        let events = collect_events(body).await;
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], Err(CodexErr::Stream(msg, _)) if msg == "Overloaded"));
+    }
+}
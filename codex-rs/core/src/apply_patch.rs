@@ -1,9 +1,12 @@
+use crate::audit_log::AuditAction;
+use crate::audit_log::AuditApproval;
 use crate::codex::Session;
 use crate::codex::TurnContext;
 use crate::protocol::FileChange;
 use crate::protocol::ReviewDecision;
 use crate::safety::SafetyCheck;
 use crate::safety::assess_patch_safety;
+use crate::safety::get_patch_sandbox_for_backend;
 use codex_apply_patch::ApplyPatchAction;
 use codex_apply_patch::ApplyPatchFileChange;
 use codex_protocol::models::FunctionCallOutputPayload;
@@ -51,6 +54,7 @@ pub(crate) async fn apply_patch(
         turn_context.approval_policy,
         &turn_context.sandbox_policy,
         &turn_context.cwd,
+        get_patch_sandbox_for_backend(turn_context.sandbox_backend),
     ) {
         SafetyCheck::AutoApprove { .. } => {
             InternalApplyPatchInvocation::DelegateToExec(ApplyPatchExec {
@@ -77,6 +81,8 @@ pub(crate) async fn apply_patch(
                     })
                 }
                 ReviewDecision::Denied | ReviewDecision::Abort => {
+                    sess.record_audit(audit_patch_action(&action), AuditApproval::Denied, None)
+                        .await;
                     ResponseInputItem::FunctionCallOutput {
                         call_id: call_id.to_owned(),
                         output: FunctionCallOutputPayload {
@@ -88,14 +94,34 @@ pub(crate) async fn apply_patch(
                 }
             }
         }
-        SafetyCheck::Reject { reason } => ResponseInputItem::FunctionCallOutput {
-            call_id: call_id.to_owned(),
-            output: FunctionCallOutputPayload {
-                content: format!("patch rejected: {reason}"),
-                success: Some(false),
-            },
+        SafetyCheck::Reject { reason } => {
+            sess.record_audit(
+                audit_patch_action(&action),
+                AuditApproval::Rejected {
+                    reason: reason.clone(),
+                },
+                None,
+            )
+            .await;
+            ResponseInputItem::FunctionCallOutput {
+                call_id: call_id.to_owned(),
+                output: FunctionCallOutputPayload {
+                    content: format!("patch rejected: {reason}"),
+                    success: Some(false),
+                },
+            }
+            .into()
         }
-        .into(),
+    }
+}
+
+/// Audit log entry for `action`, listing the files it touches.
+pub(crate) fn audit_patch_action(action: &ApplyPatchAction) -> AuditAction {
+    let mut files: Vec<PathBuf> = action.changes().keys().cloned().collect();
+    files.sort();
+    AuditAction::Patch {
+        cwd: action.cwd.clone(),
+        files,
     }
 }
 
@@ -0,0 +1,255 @@
+//! Approvals requested from an HTTP service, for runs without anyone at the
+//! terminal (CI jobs, servers).
+//!
+//! Every approval request is posted as JSON to `request_url`:
+//!
+//! ```json
+//! {"id": "…", "token": "…", "session_id": "…", "turn_id": "…",
+//!  "type": "exec_approval_request", "command": ["cargo", "publish"], …}
+//! ```
+//!
+//! The request's fields follow `type`, as in the corresponding event. Codex
+//! then polls `decision_url` until it answers `200` with
+//! `{"decision": "approved" | "approved_for_session" | "denied" | "abort",
+//! "token": "…"}`, where `token` has to be the token of the request, which
+//! only those who received the request know. Any other answer counts as
+//! "no decision yet".
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config_types::ApprovalHttpConfig;
+use crate::config_types::ApprovalTimeoutPolicy;
+use crate::protocol::EventMsg;
+use crate::protocol::ReviewDecision;
+
+/// How long a single HTTP request to the service may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+pub(crate) struct HttpApprovals {
+    config: ApprovalHttpConfig,
+    client: reqwest::Client,
+    session_id: Uuid,
+}
+
+/// An approval request that was accepted by the service.
+pub(crate) struct PendingHttpApproval {
+    pub(crate) id: Uuid,
+    token: Uuid,
+}
+
+#[derive(Deserialize)]
+struct Decision {
+    decision: ReviewDecision,
+    token: String,
+}
+
+impl HttpApprovals {
+    pub(crate) fn new(config: ApprovalHttpConfig, session_id: Uuid) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            session_id,
+        }
+    }
+
+    pub(crate) fn request_url(&self) -> &str {
+        &self.config.request_url
+    }
+
+    /// Posts the approval request in `msg` to the service.
+    pub(crate) async fn send(
+        &self,
+        turn_id: &str,
+        msg: &EventMsg,
+    ) -> anyhow::Result<PendingHttpApproval> {
+        let pending = PendingHttpApproval {
+            id: Uuid::new_v4(),
+            token: Uuid::new_v4(),
+        };
+        let mut body = json!({
+            "id": pending.id,
+            "token": pending.token,
+            "session_id": self.session_id,
+            "turn_id": turn_id,
+        });
+        if let (Some(body), serde_json::Value::Object(request)) =
+            (body.as_object_mut(), serde_json::to_value(msg)?)
+        {
+            body.extend(request);
+        }
+        self.with_headers(self.client.post(&self.config.request_url))
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(pending)
+    }
+
+    /// Polls the service until it decides on `pending`, or until the timeout
+    /// policy applies.
+    pub(crate) async fn decision(&self, pending: &PendingHttpApproval) -> ReviewDecision {
+        let url = match &self.config.decision_url {
+            Some(url) => url.replace("{id}", &pending.id.to_string()),
+            None => format!(
+                "{}/{}",
+                self.config.request_url.trim_end_matches('/'),
+                pending.id
+            ),
+        };
+        let poll = async {
+            loop {
+                match self.poll(&url, pending).await {
+                    Ok(Some(decision)) => return decision,
+                    Ok(None) => {}
+                    Err(e) => warn!("failed to poll for approval {}: {e:#}", pending.id),
+                }
+                tokio::time::sleep(Duration::from_millis(self.config.poll_interval_ms)).await;
+            }
+        };
+        match tokio::time::timeout(Duration::from_millis(self.config.timeout_ms), poll).await {
+            Ok(decision) => decision,
+            Err(_) => {
+                warn!("no decision on approval {} in time", pending.id);
+                match self.config.on_timeout {
+                    ApprovalTimeoutPolicy::Deny => ReviewDecision::Denied,
+                    ApprovalTimeoutPolicy::Abort => ReviewDecision::Abort,
+                }
+            }
+        }
+    }
+
+    async fn poll(
+        &self,
+        url: &str,
+        pending: &PendingHttpApproval,
+    ) -> anyhow::Result<Option<ReviewDecision>> {
+        let response = self.with_headers(self.client.get(url)).send().await?;
+        if response.status() != reqwest::StatusCode::OK {
+            return Ok(None);
+        }
+        let Ok(decision) = response.json::<Decision>().await else {
+            return Ok(None);
+        };
+        if decision.token != pending.token.to_string() {
+            warn!(
+                "ignoring decision on approval {} with a wrong token",
+                pending.id
+            );
+            return Ok(None);
+        }
+        Ok(Some(decision.decision))
+    }
+
+    fn with_headers(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let headers: &HashMap<String, String> = &self.config.headers;
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        request.timeout(REQUEST_TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ExecApprovalRequestEvent;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path_regex;
+
+    fn approvals(server: &MockServer, timeout_ms: u64) -> HttpApprovals {
+        HttpApprovals::new(
+            ApprovalHttpConfig {
+                request_url: format!("{}/approvals", server.uri()),
+                decision_url: None,
+                headers: HashMap::from([("authorization".to_string(), "Bearer t".to_string())]),
+                poll_interval_ms: 10,
+                timeout_ms,
+                on_timeout: ApprovalTimeoutPolicy::Abort,
+            },
+            Uuid::nil(),
+        )
+    }
+
+    fn exec_request() -> EventMsg {
+        EventMsg::ExecApprovalRequest(ExecApprovalRequestEvent {
+            call_id: "call".to_string(),
+            command: vec!["cargo".to_string(), "publish".to_string()],
+            cwd: PathBuf::from("/repo"),
+            reason: None,
+            matched_rule: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn decision_with_the_request_token_is_used() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let approvals = approvals(&server, 5_000);
+
+        let pending = approvals.send("7", &exec_request()).await.unwrap();
+        let requests = server.received_requests().await.unwrap();
+        let body: serde_json::Value = requests[0].body_json().unwrap();
+        assert_eq!(body["type"], "exec_approval_request");
+        assert_eq!(body["turn_id"], "7");
+        assert_eq!(body["command"], json!(["cargo", "publish"]));
+        assert_eq!(body["id"], json!(pending.id));
+        assert_eq!(requests[0].headers["authorization"], "Bearer t");
+
+        // A decision with a wrong token is ignored.
+        Mock::given(method("GET"))
+            .and(path_regex("^/approvals/"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"decision": "approved", "token": "guess"})),
+            )
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path_regex(format!("^/approvals/{}$", pending.id)))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(
+                    json!({"decision": "approved_for_session", "token": pending.token}),
+                ),
+            )
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            approvals.decision(&pending).await,
+            ReviewDecision::ApprovedForSession
+        );
+    }
+
+    #[tokio::test]
+    async fn timeout_policy_applies_without_a_decision() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        let approvals = approvals(&server, 100);
+        let pending = PendingHttpApproval {
+            id: Uuid::new_v4(),
+            token: Uuid::new_v4(),
+        };
+
+        assert_eq!(approvals.decision(&pending).await, ReviewDecision::Abort);
+    }
+}
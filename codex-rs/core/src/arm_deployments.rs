@@ -0,0 +1,1003 @@
+//! Azure Resource Manager deployment tools: preview an ARM or Bicep template
+//! against a resource group with what-if, deploy it, list the deployments of
+//! a resource group and explain why one failed. Deploying shows the what-if
+//! preview in the approval request, so changes are reviewed before they are
+//! made.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use chrono::Utc;
+use codex_protocol::models::FunctionCallOutputPayload;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use serde_json::json;
+
+use crate::azure_auth::AzureAuth;
+use crate::config_types::ArmDeploymentsConfig;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+
+pub(crate) const ARM_WHAT_IF_TOOL_NAME: &str = "arm_what_if";
+pub(crate) const ARM_DEPLOY_TOOL_NAME: &str = "arm_deploy";
+pub(crate) const ARM_LIST_DEPLOYMENTS_TOOL_NAME: &str = "arm_list_deployments";
+pub(crate) const ARM_DEPLOYMENT_ERRORS_TOOL_NAME: &str = "arm_deployment_errors";
+
+const DEPLOYMENTS_API_VERSION: &str = "2021-04-01";
+
+/// Wait between polls of a running deployment or what-if operation when the
+/// service does not say.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Provisioning states after which a deployment no longer changes.
+const TERMINAL_STATES: [&str; 3] = ["Succeeded", "Failed", "Canceled"];
+
+pub(crate) struct ArmDeployments {
+    config: ArmDeploymentsConfig,
+    azure_auth: Arc<AzureAuth>,
+    client: reqwest::Client,
+}
+
+/// Arguments of `arm_what_if` and `arm_deploy`.
+#[derive(Debug, Deserialize)]
+struct TemplateParams {
+    #[serde(default)]
+    subscription_id: Option<String>,
+    resource_group: String,
+    template: String,
+    #[serde(default)]
+    parameters_file: Option<String>,
+    #[serde(default)]
+    parameters: BTreeMap<String, Value>,
+    #[serde(default)]
+    deployment_name: Option<String>,
+    #[serde(default)]
+    mode: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListDeploymentsParams {
+    #[serde(default)]
+    subscription_id: Option<String>,
+    resource_group: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeploymentErrorsParams {
+    #[serde(default)]
+    subscription_id: Option<String>,
+    resource_group: String,
+    deployment_name: String,
+}
+
+/// A template ready to deploy, with the what-if preview shown for approval.
+pub(crate) struct PreparedDeployment {
+    pub(crate) resource_group: String,
+    pub(crate) name: String,
+    pub(crate) template: String,
+    pub(crate) what_if: String,
+    url: String,
+    body: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    #[serde(default = "Vec::new")]
+    value: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhatIfResult {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    properties: WhatIfProperties,
+    #[serde(default)]
+    error: Option<ArmError>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct WhatIfProperties {
+    #[serde(default)]
+    changes: Vec<WhatIfChange>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WhatIfChange {
+    resource_id: String,
+    change_type: String,
+    #[serde(default)]
+    delta: Vec<PropertyChange>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PropertyChange {
+    path: String,
+    property_change_type: String,
+    #[serde(default)]
+    before: Option<Value>,
+    #[serde(default)]
+    after: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Deployment {
+    name: String,
+    #[serde(default)]
+    properties: DeploymentProperties,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeploymentProperties {
+    provisioning_state: Option<String>,
+    timestamp: Option<String>,
+    duration: Option<String>,
+    mode: Option<String>,
+    error: Option<ArmError>,
+    outputs: Option<BTreeMap<String, Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeploymentOperation {
+    #[serde(default)]
+    properties: OperationProperties,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OperationProperties {
+    provisioning_state: Option<String>,
+    status_code: Option<String>,
+    status_message: Option<Value>,
+    target_resource: Option<TargetResource>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TargetResource {
+    #[serde(default)]
+    resource_type: Option<String>,
+    #[serde(default)]
+    resource_name: Option<String>,
+}
+
+/// An ARM error, with the errors that caused it.
+#[derive(Debug, Clone, Deserialize)]
+struct ArmError {
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    details: Vec<ArmError>,
+}
+
+/// The body of an ARM error response.
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ArmError,
+}
+
+impl ArmDeployments {
+    pub(crate) fn new(config: ArmDeploymentsConfig, azure_auth: Arc<AzureAuth>) -> Self {
+        Self {
+            config,
+            azure_auth,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Answers a call to one of the tools that change nothing. `arm_deploy`
+    /// needs approval and goes through [`ArmDeployments::prepare`] and
+    /// [`ArmDeployments::deploy`] instead.
+    pub(crate) async fn handle_function_call(
+        &self,
+        name: &str,
+        arguments: &str,
+        cwd: &Path,
+    ) -> FunctionCallOutputPayload {
+        let result = match name {
+            ARM_WHAT_IF_TOOL_NAME => self
+                .prepare(arguments, cwd)
+                .await
+                .map(|deployment| deployment.what_if),
+            ARM_LIST_DEPLOYMENTS_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.list_deployments(params).await,
+                Err(e) => Err(e),
+            },
+            ARM_DEPLOYMENT_ERRORS_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.deployment_errors(params).await,
+                Err(e) => Err(e),
+            },
+            _ => Err(format!("unknown ARM deployment tool `{name}`")),
+        };
+        match result {
+            Ok(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(true),
+            },
+            Err(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(false),
+            },
+        }
+    }
+
+    /// Loads the template and parameters of an `arm_what_if` or `arm_deploy`
+    /// call and previews the deployment with what-if, which also validates
+    /// it.
+    pub(crate) async fn prepare(
+        &self,
+        arguments: &str,
+        cwd: &Path,
+    ) -> Result<PreparedDeployment, String> {
+        let params: TemplateParams = parse(arguments)?;
+        let mode = params.mode.unwrap_or_else(|| "Incremental".to_string());
+        if mode != "Incremental" && mode != "Complete" {
+            return Err("mode must be Incremental or Complete".to_string());
+        }
+        let template = self.load_template(&cwd.join(&params.template)).await?;
+        let mut parameters = match &params.parameters_file {
+            Some(path) => load_parameters_file(&cwd.join(path)).await?,
+            None => serde_json::Map::new(),
+        };
+        for (name, value) in params.parameters {
+            parameters.insert(name, json!({ "value": value }));
+        }
+        let name = params
+            .deployment_name
+            .unwrap_or_else(|| format!("codex-{}", Utc::now().format("%Y%m%d-%H%M%S")));
+        let subscription_id = self.subscription_id(params.subscription_id)?;
+        let url = format!(
+            "{}/subscriptions/{subscription_id}/resourcegroups/{}/providers/Microsoft.Resources/deployments/{name}",
+            self.resource_manager(),
+            params.resource_group
+        );
+        let body = json!({
+            "properties": {
+                "mode": mode,
+                "template": template,
+                "parameters": parameters,
+            },
+        });
+
+        let response = self
+            .send(
+                self.client
+                    .post(format!("{url}/whatIf"))
+                    .query(&[("api-version", DEPLOYMENTS_API_VERSION)])
+                    .json(&body),
+            )
+            .await?;
+        let response = self.wait_for_operation(response).await?;
+        let result: WhatIfResult = response
+            .json()
+            .await
+            .map_err(|e| format!("failed to parse the what-if response: {e}"))?;
+        if let Some(error) = &result.error {
+            return Err(format!("what-if failed:\n{}", format_error(error, 0)));
+        }
+        if let Some(status) = result.status.as_deref()
+            && status != "Succeeded"
+        {
+            return Err(format!("what-if ended as {status}"));
+        }
+        let what_if = format_what_if(&params.resource_group, &name, &result.properties.changes);
+        Ok(PreparedDeployment {
+            resource_group: params.resource_group,
+            name,
+            template: params.template,
+            what_if,
+            url,
+            body,
+        })
+    }
+
+    /// Starts `deployment` and waits for it to finish, up to
+    /// `deployment_timeout_secs`.
+    pub(crate) async fn deploy(&self, deployment: &PreparedDeployment) -> Result<String, String> {
+        let query = [("api-version", DEPLOYMENTS_API_VERSION)];
+        let response = self
+            .send(
+                self.client
+                    .put(&deployment.url)
+                    .query(&query)
+                    .json(&deployment.body),
+            )
+            .await?;
+        let mut state: Deployment = response
+            .json()
+            .await
+            .map_err(|e| format!("failed to parse the deployment response: {e}"))?;
+        let deadline = Instant::now() + Duration::from_secs(self.config.deployment_timeout_secs);
+        loop {
+            let provisioning_state = state
+                .properties
+                .provisioning_state
+                .clone()
+                .unwrap_or_default();
+            if TERMINAL_STATES.contains(&provisioning_state.as_str()) {
+                break;
+            }
+            if Instant::now() + DEFAULT_POLL_INTERVAL > deadline {
+                return Ok(format!(
+                    "Deployment `{}` is still {provisioning_state} after {} seconds; follow it \
+                     with {ARM_LIST_DEPLOYMENTS_TOOL_NAME}.",
+                    deployment.name, self.config.deployment_timeout_secs
+                ));
+            }
+            tokio::time::sleep(DEFAULT_POLL_INTERVAL).await;
+            state = self.get(&deployment.url, &query).await?;
+        }
+
+        let properties = &state.properties;
+        if properties.provisioning_state.as_deref() == Some("Succeeded") {
+            let mut out = format!(
+                "Deployment `{}` to {} succeeded",
+                deployment.name, deployment.resource_group
+            );
+            if let Some(duration) = &properties.duration {
+                let _ = write!(out, " in {duration}");
+            }
+            out.push_str(".\n");
+            if let Some(outputs) = properties.outputs.as_ref().filter(|o| !o.is_empty()) {
+                out.push_str("Outputs:\n");
+                for (name, output) in outputs {
+                    let value = output.get("value").unwrap_or(output);
+                    let _ = writeln!(out, "  {name} = {value}");
+                }
+            }
+            return Ok(out);
+        }
+        let mut out = format!(
+            "Deployment `{}` {}",
+            deployment.name,
+            properties.provisioning_state.as_deref().unwrap_or("failed")
+        );
+        if let Some(error) = &properties.error {
+            let _ = write!(out, ":\n{}", format_error(error, 0));
+        }
+        let operations = self.failed_operations(&deployment.url).await?;
+        out.push_str(&operations);
+        Err(out)
+    }
+
+    async fn list_deployments(&self, params: ListDeploymentsParams) -> Result<String, String> {
+        let subscription_id = self.subscription_id(params.subscription_id)?;
+        let url = format!(
+            "{}/subscriptions/{subscription_id}/resourcegroups/{}/providers/Microsoft.Resources/deployments",
+            self.resource_manager(),
+            params.resource_group
+        );
+        let query = [
+            ("api-version", DEPLOYMENTS_API_VERSION.to_string()),
+            ("$top", self.config.max_results.to_string()),
+        ];
+        let page: Page<Deployment> = self.get(&url, &query).await?;
+        let mut deployments = page.value;
+        // Newest first.
+        deployments.sort_by(|a, b| b.properties.timestamp.cmp(&a.properties.timestamp));
+        Ok(format_deployments(&params.resource_group, &deployments))
+    }
+
+    async fn deployment_errors(&self, params: DeploymentErrorsParams) -> Result<String, String> {
+        let subscription_id = self.subscription_id(params.subscription_id)?;
+        let url = format!(
+            "{}/subscriptions/{subscription_id}/resourcegroups/{}/providers/Microsoft.Resources/deployments/{}",
+            self.resource_manager(),
+            params.resource_group,
+            params.deployment_name
+        );
+        let deployment: Deployment = self
+            .get(&url, &[("api-version", DEPLOYMENTS_API_VERSION)])
+            .await?;
+        let properties = &deployment.properties;
+        let mut out = format!(
+            "Deployment `{}`: {}",
+            deployment.name,
+            properties
+                .provisioning_state
+                .as_deref()
+                .unwrap_or("Unknown")
+        );
+        if let Some(timestamp) = &properties.timestamp {
+            let _ = write!(out, " at {timestamp}");
+        }
+        out.push('\n');
+        match &properties.error {
+            Some(error) => out.push_str(&format_error(error, 0)),
+            None => out.push_str("No deployment error.\n"),
+        }
+        out.push_str(&self.failed_operations(&url).await?);
+        Ok(out)
+    }
+
+    /// The failed operations of the deployment at `url`, formatted.
+    async fn failed_operations(&self, url: &str) -> Result<String, String> {
+        let page: Page<DeploymentOperation> = self
+            .get(
+                &format!("{url}/operations"),
+                &[("api-version", DEPLOYMENTS_API_VERSION)],
+            )
+            .await?;
+        Ok(format_failed_operations(&page.value))
+    }
+
+    async fn load_template(&self, path: &Path) -> Result<Value, String> {
+        let text = if path.extension().is_some_and(|ext| ext == "bicep") {
+            let output = tokio::process::Command::new(&self.config.bicep_command)
+                .arg("build")
+                .arg("--stdout")
+                .arg(path)
+                .output()
+                .await
+                .map_err(|e| format!("failed to run `{}`: {e}", self.config.bicep_command))?;
+            if !output.status.success() {
+                return Err(format!(
+                    "failed to build {}:\n{}",
+                    path.display(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        } else {
+            tokio::fs::read_to_string(path)
+                .await
+                .map_err(|e| format!("failed to read {}: {e}", path.display()))?
+        };
+        serde_json::from_str(&text)
+            .map_err(|e| format!("{} is not a JSON template: {e}", path.display()))
+    }
+
+    /// Follows an asynchronous ARM operation until it completes.
+    async fn wait_for_operation(
+        &self,
+        mut response: reqwest::Response,
+    ) -> Result<reqwest::Response, String> {
+        let deadline = Instant::now() + Duration::from_secs(self.config.deployment_timeout_secs);
+        while response.status() == StatusCode::ACCEPTED {
+            let headers = response.headers();
+            let Some(location) = headers
+                .get("location")
+                .and_then(|location| location.to_str().ok())
+                .map(str::to_string)
+            else {
+                break;
+            };
+            let delay = headers
+                .get("retry-after")
+                .and_then(|delay| delay.to_str().ok())
+                .and_then(|delay| delay.parse().ok())
+                .map_or(DEFAULT_POLL_INTERVAL, Duration::from_secs);
+            if Instant::now() + delay > deadline {
+                return Err(format!(
+                    "the operation did not finish within {} seconds",
+                    self.config.deployment_timeout_secs
+                ));
+            }
+            tokio::time::sleep(delay).await;
+            response = self.send(self.client.get(location)).await?;
+        }
+        Ok(response)
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &[(&str, impl serde::Serialize)],
+    ) -> Result<T, String> {
+        self.send(self.client.get(url).query(query))
+            .await?
+            .json()
+            .await
+            .map_err(|e| format!("failed to parse the Azure Resource Manager response: {e}"))
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let scope = format!("{}/.default", self.resource_manager());
+        let token = self
+            .azure_auth
+            .get_token(&scope)
+            .await
+            .map_err(|e| format!("failed to get an Azure token: {e}"))?;
+        let response = request
+            .bearer_auth(token.token)
+            .send()
+            .await
+            .map_err(|e| format!("failed to reach Azure Resource Manager: {e}"))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let detail = match serde_json::from_str::<ErrorResponse>(&body) {
+                Ok(ErrorResponse { error }) => format_error(&error, 0),
+                Err(_) => body,
+            };
+            return Err(format!(
+                "Azure Resource Manager request failed ({status}):\n{detail}"
+            ));
+        }
+        Ok(response)
+    }
+
+    fn resource_manager(&self) -> &str {
+        self.config.resource_manager.trim_end_matches('/')
+    }
+
+    fn subscription_id(&self, requested: Option<String>) -> Result<String, String> {
+        requested
+            .or_else(|| self.config.subscription_id.clone())
+            .ok_or_else(|| {
+                "no subscription: pass subscription_id or set arm_deployments.subscription_id"
+                    .to_string()
+            })
+    }
+}
+
+fn parse<T: DeserializeOwned>(arguments: &str) -> Result<T, String> {
+    serde_json::from_str(arguments).map_err(|e| format!("failed to parse function arguments: {e}"))
+}
+
+/// The `parameters` of an ARM parameters file, or the whole file when it is
+/// a bare parameters object.
+async fn load_parameters_file(path: &Path) -> Result<serde_json::Map<String, Value>, String> {
+    if path.extension().is_some_and(|ext| ext == "bicepparam") {
+        return Err(format!(
+            "{} is a .bicepparam file; pass a JSON parameters file or inline parameters",
+            path.display()
+        ));
+    }
+    let text = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let file: Value = serde_json::from_str(&text)
+        .map_err(|e| format!("{} is not a JSON parameters file: {e}", path.display()))?;
+    match file.get("parameters").unwrap_or(&file) {
+        Value::Object(parameters) => Ok(parameters.clone()),
+        _ => Err(format!("{} holds no parameters object", path.display())),
+    }
+}
+
+fn format_what_if(resource_group: &str, name: &str, changes: &[WhatIfChange]) -> String {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for change in changes {
+        *counts.entry(change.change_type.as_str()).or_default() += 1;
+    }
+    let mut out = format!("What-if for deployment `{name}` to {resource_group}: ");
+    if counts.is_empty() {
+        out.push_str("no resources.\n");
+        return out;
+    }
+    let summary: Vec<String> = counts
+        .iter()
+        .map(|(change_type, count)| format!("{count} {change_type}"))
+        .collect();
+    let _ = writeln!(out, "{}", summary.join(", "));
+    for change in changes
+        .iter()
+        .filter(|change| change.change_type != "NoChange" && change.change_type != "Ignore")
+    {
+        let _ = writeln!(out, "{}  {}", change.change_type, change.resource_id);
+        for delta in &change.delta {
+            let value = |value: &Option<Value>| {
+                value
+                    .as_ref()
+                    .map_or_else(|| "(none)".to_string(), Value::to_string)
+            };
+            let _ = writeln!(
+                out,
+                "  {} {}: {} -> {}",
+                delta.property_change_type,
+                delta.path,
+                value(&delta.before),
+                value(&delta.after)
+            );
+        }
+    }
+    out
+}
+
+fn format_deployments(resource_group: &str, deployments: &[Deployment]) -> String {
+    if deployments.is_empty() {
+        return format!("No deployments in {resource_group}.");
+    }
+    let mut out = format!(
+        "{} deployments in {resource_group}, newest first:\n",
+        deployments.len()
+    );
+    for deployment in deployments {
+        let properties = &deployment.properties;
+        let _ = write!(
+            out,
+            "  {}  {}  {}",
+            deployment.name,
+            properties
+                .provisioning_state
+                .as_deref()
+                .unwrap_or("Unknown"),
+            properties.timestamp.as_deref().unwrap_or("?")
+        );
+        if let Some(duration) = &properties.duration {
+            let _ = write!(out, "  {duration}");
+        }
+        if let Some(mode) = &properties.mode {
+            let _ = write!(out, "  {mode}");
+        }
+        if let Some(error) = &properties.error {
+            let _ = write!(out, "  {}", error.code);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// `error` and the errors under it, indented by depth.
+fn format_error(error: &ArmError, depth: usize) -> String {
+    let mut out = format!(
+        "{}{}: {}\n",
+        "  ".repeat(depth),
+        error.code,
+        error.message.trim()
+    );
+    for detail in &error.details {
+        out.push_str(&format_error(detail, depth + 1));
+    }
+    out
+}
+
+fn format_failed_operations(operations: &[DeploymentOperation]) -> String {
+    let failed: Vec<&OperationProperties> = operations
+        .iter()
+        .map(|operation| &operation.properties)
+        .filter(|properties| properties.provisioning_state.as_deref() == Some("Failed"))
+        .collect();
+    if failed.is_empty() {
+        return String::new();
+    }
+    let mut out = format!("{} failed operations:\n", failed.len());
+    for operation in failed {
+        let target = operation.target_resource.as_ref();
+        let _ = writeln!(
+            out,
+            "- {} {} ({})",
+            target
+                .and_then(|target| target.resource_type.as_deref())
+                .unwrap_or("?"),
+            target
+                .and_then(|target| target.resource_name.as_deref())
+                .unwrap_or("?"),
+            operation.status_code.as_deref().unwrap_or("?")
+        );
+        let error = operation
+            .status_message
+            .as_ref()
+            .and_then(|message| message.get("error"))
+            .and_then(|error| serde_json::from_value::<ArmError>(error.clone()).ok());
+        match (error, &operation.status_message) {
+            (Some(error), _) => out.push_str(&format_error(&error, 1)),
+            (None, Some(message)) => {
+                let _ = writeln!(out, "  {message}");
+            }
+            (None, None) => {}
+        }
+    }
+    out
+}
+
+pub(crate) fn create_arm_deployment_tools() -> Vec<ResponsesApiTool> {
+    let string = |description: &str| JsonSchema::String {
+        description: Some(description.to_string()),
+    };
+    let object = |properties: BTreeMap<String, JsonSchema>, required: &[&str]| JsonSchema::Object {
+        properties,
+        required: Some(required.iter().map(|r| r.to_string()).collect()),
+        additional_properties: Some(false),
+    };
+    let subscription_id =
+        || string("Subscription id; defaults to the configured arm_deployments.subscription_id.");
+    let resource_group = || string("Resource group name.");
+    let template = || {
+        BTreeMap::from([
+            ("subscription_id".to_string(), subscription_id()),
+            ("resource_group".to_string(), resource_group()),
+            (
+                "template".to_string(),
+                string(
+                    "Path of the ARM JSON template or .bicep file, relative to the working directory.",
+                ),
+            ),
+            (
+                "parameters_file".to_string(),
+                string("Path of a JSON parameters file."),
+            ),
+            (
+                "parameters".to_string(),
+                JsonSchema::Object {
+                    properties: BTreeMap::new(),
+                    required: None,
+                    additional_properties: Some(true),
+                },
+            ),
+            (
+                "deployment_name".to_string(),
+                string("Deployment name; a timestamped codex- name by default."),
+            ),
+            (
+                "mode".to_string(),
+                string(
+                    "Incremental (default) or Complete, which deletes resources not in the template.",
+                ),
+            ),
+        ])
+    };
+    let list_deployments = BTreeMap::from([
+        ("subscription_id".to_string(), subscription_id()),
+        ("resource_group".to_string(), resource_group()),
+    ]);
+    let deployment_errors = BTreeMap::from([
+        ("subscription_id".to_string(), subscription_id()),
+        ("resource_group".to_string(), resource_group()),
+        (
+            "deployment_name".to_string(),
+            string("Name of the deployment."),
+        ),
+    ]);
+
+    vec![
+        ResponsesApiTool {
+            name: ARM_WHAT_IF_TOOL_NAME.to_string(),
+            description: "Validates an ARM or Bicep template against a resource group and \
+                previews the resources a deployment would create, modify or delete, without \
+                changing anything. `parameters` maps parameter names to values and overrides \
+                the parameters file."
+                .to_string(),
+            strict: false,
+            parameters: object(template(), &["resource_group", "template"]),
+        },
+        ResponsesApiTool {
+            name: ARM_DEPLOY_TOOL_NAME.to_string(),
+            description: "Deploys an ARM or Bicep template to a resource group and waits for \
+                the result. The user approves the deployment after seeing its what-if preview. \
+                Takes the same arguments as arm_what_if."
+                .to_string(),
+            strict: false,
+            parameters: object(template(), &["resource_group", "template"]),
+        },
+        ResponsesApiTool {
+            name: ARM_LIST_DEPLOYMENTS_TOOL_NAME.to_string(),
+            description: "Lists the deployments of a resource group, newest first, with their \
+                state and duration."
+                .to_string(),
+            strict: false,
+            parameters: object(list_deployments, &["resource_group"]),
+        },
+        ResponsesApiTool {
+            name: ARM_DEPLOYMENT_ERRORS_TOOL_NAME.to_string(),
+            description: "Explains why a deployment failed: its error and the failed \
+                operations with the resources they targeted."
+                .to_string(),
+            strict: false,
+            parameters: object(deployment_errors, &["resource_group", "deployment_name"]),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::config_types::AzureAuthConfig;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::body_partial_json;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+
+    const DEPLOYMENT_PATH: &str =
+        "/subscriptions/sub-1/resourcegroups/rg/providers/Microsoft.Resources/deployments/web";
+
+    fn deployments(server: &MockServer, codex_home: &TempDir) -> ArmDeployments {
+        let scope = format!("{}/.default", server.uri());
+        let azure_auth = AzureAuth::new(AzureAuthConfig::default(), codex_home.path())
+            .with_token_for_tests(&scope, "arm-token");
+        ArmDeployments::new(
+            ArmDeploymentsConfig {
+                subscription_id: Some("sub-1".to_string()),
+                resource_manager: server.uri(),
+                bicep_command: "bicep".to_string(),
+                deployment_timeout_secs: 60,
+                max_results: 20,
+            },
+            Arc::new(azure_auth),
+        )
+    }
+
+    fn write_template(dir: &TempDir) {
+        std::fs::write(
+            dir.path().join("main.json"),
+            json!({
+                "$schema": "https://schema.management.azure.com/schemas/2019-04-01/deploymentTemplate.json#",
+                "contentVersion": "1.0.0.0",
+                "parameters": { "sku": { "type": "string" }, "name": { "type": "string" } },
+                "resources": [],
+            })
+            .to_string(),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("main.parameters.json"),
+            json!({ "parameters": { "sku": { "value": "B1" }, "name": { "value": "app" } } })
+                .to_string(),
+        )
+        .unwrap();
+    }
+
+    async fn mount_what_if(server: &MockServer) {
+        Mock::given(method("POST"))
+            .and(path(format!("{DEPLOYMENT_PATH}/whatIf")))
+            .and(body_partial_json(json!({
+                "properties": {
+                    "mode": "Incremental",
+                    "parameters": { "sku": { "value": "P1v3" }, "name": { "value": "app" } },
+                },
+            })))
+            .respond_with(
+                ResponseTemplate::new(202)
+                    .insert_header("location", format!("{}/operations/what-if-1", server.uri()))
+                    .insert_header("retry-after", "0"),
+            )
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/operations/what-if-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "status": "Succeeded",
+                "properties": { "changes": [
+                    {
+                        "resourceId": "/subscriptions/sub-1/resourceGroups/rg/providers/Microsoft.Web/serverfarms/app-plan",
+                        "changeType": "Modify",
+                        "delta": [{
+                            "path": "sku.name",
+                            "propertyChangeType": "Modify",
+                            "before": "B1",
+                            "after": "P1v3",
+                        }],
+                    },
+                    {
+                        "resourceId": "/subscriptions/sub-1/resourceGroups/rg/providers/Microsoft.Web/sites/app",
+                        "changeType": "NoChange",
+                    },
+                ]},
+            })))
+            .mount(server)
+            .await;
+    }
+
+    fn template_arguments() -> String {
+        json!({
+            "resource_group": "rg",
+            "template": "main.json",
+            "parameters_file": "main.parameters.json",
+            "parameters": { "sku": "P1v3" },
+            "deployment_name": "web",
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn previews_templates_with_what_if() {
+        let server = MockServer::start().await;
+        let codex_home = TempDir::new().unwrap();
+        let cwd = TempDir::new().unwrap();
+        write_template(&cwd);
+        mount_what_if(&server).await;
+
+        let output = deployments(&server, &codex_home)
+            .handle_function_call(ARM_WHAT_IF_TOOL_NAME, &template_arguments(), cwd.path())
+            .await;
+
+        assert_eq!(output.success, Some(true), "{}", output.content);
+        assert_eq!(
+            output.content,
+            "What-if for deployment `web` to rg: 1 Modify, 1 NoChange\n\
+             Modify  /subscriptions/sub-1/resourceGroups/rg/providers/Microsoft.Web/serverfarms/app-plan\n  \
+             Modify sku.name: \"B1\" -> \"P1v3\"\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_failed_deployments_with_their_operations() {
+        let server = MockServer::start().await;
+        let codex_home = TempDir::new().unwrap();
+        let cwd = TempDir::new().unwrap();
+        write_template(&cwd);
+        mount_what_if(&server).await;
+        Mock::given(method("PUT"))
+            .and(path(DEPLOYMENT_PATH))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "name": "web",
+                "properties": {
+                    "provisioningState": "Failed",
+                    "error": {
+                        "code": "DeploymentFailed",
+                        "message": "At least one resource deployment operation failed.",
+                        "details": [{ "code": "Conflict", "message": "SKU not available." }],
+                    },
+                },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{DEPLOYMENT_PATH}/operations")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "value": [
+                    { "properties": { "provisioningState": "Succeeded" } },
+                    { "properties": {
+                        "provisioningState": "Failed",
+                        "statusCode": "Conflict",
+                        "statusMessage": { "status": "Failed", "error": {
+                            "code": "SkuNotAvailable",
+                            "message": "P1v3 is not available in westeurope.",
+                        }},
+                        "targetResource": {
+                            "resourceType": "Microsoft.Web/serverfarms",
+                            "resourceName": "app-plan",
+                        },
+                    }},
+                ],
+            })))
+            .mount(&server)
+            .await;
+        let deployments = deployments(&server, &codex_home);
+
+        let deployment = deployments
+            .prepare(&template_arguments(), cwd.path())
+            .await
+            .unwrap();
+        assert_eq!(deployment.name, "web");
+        let err = deployments.deploy(&deployment).await.unwrap_err();
+
+        assert_eq!(
+            err,
+            "Deployment `web` Failed:\n\
+             DeploymentFailed: At least one resource deployment operation failed.\n  \
+             Conflict: SKU not available.\n\
+             1 failed operations:\n\
+             - Microsoft.Web/serverfarms app-plan (Conflict)\n  \
+             SkuNotAvailable: P1v3 is not available in westeurope.\n"
+        );
+    }
+
+    #[test]
+    fn formats_deployments() {
+        let deployments: Vec<Deployment> = serde_json::from_value(json!([{
+            "name": "web",
+            "properties": {
+                "provisioningState": "Succeeded",
+                "timestamp": "2024-05-01T08:00:00Z",
+                "duration": "PT1M2S",
+                "mode": "Incremental",
+            },
+        }]))
+        .unwrap();
+        assert_eq!(
+            format_deployments("rg", &deployments),
+            "1 deployments in rg, newest first:\n  \
+             web  Succeeded  2024-05-01T08:00:00Z  PT1M2S  Incremental\n"
+        );
+        assert_eq!(format_deployments("rg", &[]), "No deployments in rg.");
+    }
+}
@@ -0,0 +1,445 @@
+//! Saves tool outputs that are too large to send to the model as files under
+//! the session's artifacts directory, and implements the `read_artifact`
+//! tool the model reads them back with.
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::path::PathBuf;
+
+use codex_protocol::models::FunctionCallOutputPayload;
+use codex_protocol::models::ResponseInputItem;
+use mcp_types::CallToolResult;
+use mcp_types::ContentBlock;
+use mcp_types::TextContent;
+use serde::Deserialize;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config_types::ArtifactsConfig;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+use crate::protocol::ArtifactCreatedEvent;
+
+pub(crate) const READ_ARTIFACT_TOOL_NAME: &str = "read_artifact";
+
+/// Bytes returned by `read_artifact` when the call does not say.
+const DEFAULT_READ_BYTES: usize = 8 * 1024;
+
+/// Most lines `read_artifact` returns for a `pattern`.
+const MAX_MATCHES: usize = 200;
+
+pub(crate) struct Artifacts {
+    dir: PathBuf,
+    config: ArtifactsConfig,
+}
+
+#[derive(Deserialize)]
+struct ReadArtifactParams {
+    artifact: String,
+    #[serde(default)]
+    offset: Option<usize>,
+    #[serde(default)]
+    max_bytes: Option<usize>,
+    #[serde(default)]
+    pattern: Option<String>,
+    #[serde(default)]
+    json_pointer: Option<String>,
+}
+
+impl Artifacts {
+    /// `None` when `[artifacts]` is not enabled.
+    pub(crate) fn new(
+        codex_home: &Path,
+        session_id: Uuid,
+        config: ArtifactsConfig,
+    ) -> Option<Self> {
+        config.enabled.then(|| Self {
+            dir: codex_home.join("artifacts").join(session_id.to_string()),
+            config,
+        })
+    }
+
+    /// `response` as is when its output fits in `inline_max_bytes`.
+    /// Otherwise the output is saved as an artifact and replaced by its start
+    /// and end and the name to read the rest with. Outputs that cannot be
+    /// saved are sent whole.
+    pub(crate) fn offload(
+        &self,
+        response: ResponseInputItem,
+    ) -> (ResponseInputItem, Option<ArtifactCreatedEvent>) {
+        match response {
+            ResponseInputItem::FunctionCallOutput { call_id, output }
+                if output.content.len() > self.config.inline_max_bytes =>
+            {
+                match self.save(&call_id, "txt", &output.content) {
+                    Some((content, event)) => (
+                        ResponseInputItem::FunctionCallOutput {
+                            call_id,
+                            output: FunctionCallOutputPayload {
+                                content,
+                                success: output.success,
+                            },
+                        },
+                        Some(event),
+                    ),
+                    None => (
+                        ResponseInputItem::FunctionCallOutput { call_id, output },
+                        None,
+                    ),
+                }
+            }
+            ResponseInputItem::CustomToolCallOutput { call_id, output }
+                if output.len() > self.config.inline_max_bytes =>
+            {
+                match self.save(&call_id, "txt", &output) {
+                    Some((output, event)) => (
+                        ResponseInputItem::CustomToolCallOutput { call_id, output },
+                        Some(event),
+                    ),
+                    None => (
+                        ResponseInputItem::CustomToolCallOutput { call_id, output },
+                        None,
+                    ),
+                }
+            }
+            ResponseInputItem::McpToolCallOutput {
+                call_id,
+                result: Ok(result),
+            } => {
+                let inline_len = serde_json::to_string(&result).map_or(0, |json| json.len());
+                let saved = (inline_len > self.config.inline_max_bytes)
+                    .then(|| serde_json::to_string_pretty(&result).ok())
+                    .flatten()
+                    .and_then(|json| self.save(&call_id, "json", &json));
+                match saved {
+                    Some((text, event)) => (
+                        ResponseInputItem::McpToolCallOutput {
+                            call_id,
+                            result: Ok(CallToolResult {
+                                content: vec![ContentBlock::TextContent(TextContent {
+                                    annotations: None,
+                                    text,
+                                    r#type: "text".to_string(),
+                                })],
+                                is_error: result.is_error,
+                                structured_content: None,
+                            }),
+                        },
+                        Some(event),
+                    ),
+                    None => (
+                        ResponseInputItem::McpToolCallOutput {
+                            call_id,
+                            result: Ok(result),
+                        },
+                        None,
+                    ),
+                }
+            }
+            response => (response, None),
+        }
+    }
+
+    /// Write `output` to a file named after `call_id` and return the preview
+    /// sent to the model in its place.
+    fn save(
+        &self,
+        call_id: &str,
+        extension: &str,
+        output: &str,
+    ) -> Option<(String, ArtifactCreatedEvent)> {
+        let stem: String = call_id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let name = format!("{stem}.{extension}");
+        let path = self.dir.join(&name);
+        if let Err(e) =
+            std::fs::create_dir_all(&self.dir).and_then(|()| std::fs::write(&path, output))
+        {
+            warn!("failed to save artifact {}: {e}", path.display());
+            return None;
+        }
+
+        let preview = self.config.preview_bytes;
+        let head = &output[..floor_char_boundary(output, preview)];
+        let tail = &output[floor_char_boundary(output, output.len().saturating_sub(preview))..];
+        let content = format!(
+            "The output is {} bytes ({} lines), too large to include, and was saved as \
+             artifact `{name}`. Use the {READ_ARTIFACT_TOOL_NAME} tool to read more of it.\n\
+             --- start of output ---\n{head}\n[...]\n--- end of output ---\n{tail}",
+            output.len(),
+            output.lines().count(),
+        );
+        let mime_type = if extension == "json" {
+            "application/json"
+        } else {
+            "text/plain"
+        };
+        let event = ArtifactCreatedEvent {
+            call_id: call_id.to_string(),
+            name,
+            path,
+            bytes: output.len() as u64,
+            mime_type: mime_type.to_string(),
+        };
+        Some((content, event))
+    }
+
+    pub(crate) fn handle_read_artifact(&self, arguments: &str) -> FunctionCallOutputPayload {
+        match self.read(arguments) {
+            Ok(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(true),
+            },
+            Err(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(false),
+            },
+        }
+    }
+
+    fn read(&self, arguments: &str) -> Result<String, String> {
+        let params: ReadArtifactParams = serde_json::from_str(arguments)
+            .map_err(|e| format!("failed to parse function arguments: {e}"))?;
+        // Only files directly in the artifacts directory can be read.
+        let name = params.artifact.as_str();
+        if name.starts_with('.') || Path::new(name).file_name() != Some(OsStr::new(name)) {
+            return Err(format!("invalid artifact name: {name}"));
+        }
+        let text = std::fs::read_to_string(self.dir.join(name))
+            .map_err(|e| format!("failed to read artifact {name}: {e}"))?;
+        let max_bytes = params
+            .max_bytes
+            .unwrap_or(DEFAULT_READ_BYTES)
+            .min(self.config.inline_max_bytes);
+
+        let text = match &params.json_pointer {
+            Some(pointer) => {
+                let value: serde_json::Value = serde_json::from_str(&text)
+                    .map_err(|e| format!("artifact {name} is not JSON: {e}"))?;
+                let value = value
+                    .pointer(pointer)
+                    .ok_or_else(|| format!("nothing at {pointer} in artifact {name}"))?;
+                serde_json::to_string_pretty(value)
+                    .map_err(|e| format!("failed to serialize response: {e}"))?
+            }
+            None => text,
+        };
+
+        if let Some(pattern) = &params.pattern {
+            let mut matches = String::new();
+            let (mut count, mut shown) = (0, 0);
+            for (number, line) in text.lines().enumerate() {
+                if !line.contains(pattern.as_str()) {
+                    continue;
+                }
+                count += 1;
+                let entry = format!("{}: {line}\n", number + 1);
+                if shown < MAX_MATCHES && matches.len() + entry.len() <= max_bytes {
+                    matches.push_str(&entry);
+                    shown += 1;
+                }
+            }
+            return Ok(match count {
+                0 => format!("no lines match `{pattern}`"),
+                _ if shown < count => {
+                    format!("{count} lines match `{pattern}`, showing {shown}:\n{matches}")
+                }
+                _ => format!("{count} lines match `{pattern}`:\n{matches}"),
+            });
+        }
+
+        let start = floor_char_boundary(&text, params.offset.unwrap_or(0));
+        let end = floor_char_boundary(&text, start.saturating_add(max_bytes));
+        Ok(format!(
+            "bytes {start}-{end} of {}:\n{}",
+            text.len(),
+            &text[start..end]
+        ))
+    }
+}
+
+/// The largest char boundary of `s` at or before `index`.
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    let mut index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+pub(crate) fn create_read_artifact_tool() -> ResponsesApiTool {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "artifact".to_string(),
+        JsonSchema::String {
+            description: Some("Name of the artifact, as given in the tool output.".to_string()),
+        },
+    );
+    properties.insert(
+        "offset".to_string(),
+        JsonSchema::Number {
+            description: Some("Byte offset to start reading at. Defaults to 0.".to_string()),
+        },
+    );
+    properties.insert(
+        "max_bytes".to_string(),
+        JsonSchema::Number {
+            description: Some(format!(
+                "How many bytes to return. Defaults to {DEFAULT_READ_BYTES}."
+            )),
+        },
+    );
+    properties.insert(
+        "pattern".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Return only the lines containing this text, with their line numbers.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "json_pointer".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "For JSON artifacts, read only the value at this JSON pointer, such as \
+                 `/content/0/text`."
+                    .to_string(),
+            ),
+        },
+    );
+
+    ResponsesApiTool {
+        name: READ_ARTIFACT_TOOL_NAME.to_string(),
+        description: "Reads part of a tool output that was too large to include and was saved \
+            as an artifact: a range of bytes, the lines matching a pattern, or a value of a \
+            JSON artifact."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["artifact".to_string()]),
+            additional_properties: Some(false),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn artifacts(codex_home: &TempDir) -> Artifacts {
+        Artifacts::new(
+            codex_home.path(),
+            Uuid::new_v4(),
+            ArtifactsConfig {
+                enabled: true,
+                inline_max_bytes: 100,
+                preview_bytes: 10,
+            },
+        )
+        .unwrap()
+    }
+
+    fn function_output(content: String) -> ResponseInputItem {
+        ResponseInputItem::FunctionCallOutput {
+            call_id: "call_1".to_string(),
+            output: FunctionCallOutputPayload {
+                content,
+                success: Some(true),
+            },
+        }
+    }
+
+    #[test]
+    fn keeps_small_outputs_inline() {
+        let codex_home = TempDir::new().unwrap();
+        let response = function_output("ok".to_string());
+
+        let (offloaded, event) = artifacts(&codex_home).offload(response.clone());
+
+        assert_eq!(offloaded, response);
+        assert!(event.is_none());
+    }
+
+    #[test]
+    fn saves_large_outputs_and_reads_them_back() {
+        let codex_home = TempDir::new().unwrap();
+        let artifacts = artifacts(&codex_home);
+        let output: String = (1..=50).map(|n| format!("line {n}\n")).collect();
+
+        let (offloaded, event) = artifacts.offload(function_output(output.clone()));
+
+        let event = event.unwrap();
+        assert_eq!(event.name, "call_1.txt");
+        assert_eq!(std::fs::read_to_string(&event.path).unwrap(), output);
+        let ResponseInputItem::FunctionCallOutput {
+            output: payload, ..
+        } = offloaded
+        else {
+            panic!("expected a function call output");
+        };
+        assert!(payload.content.contains("artifact `call_1.txt`"));
+        assert!(
+            payload
+                .content
+                .contains("--- start of output ---\nline 1\nlin\n")
+        );
+        assert!(payload.content.ends_with("line 50\n"));
+
+        let read = artifacts.read(r#"{"artifact":"call_1.txt","offset":7,"max_bytes":14}"#);
+        assert_eq!(read, Ok("bytes 7-21 of 391:\nline 2\nline 3\n".to_string()));
+        let read = artifacts.read(r#"{"artifact":"call_1.txt","pattern":"line 5"}"#);
+        assert_eq!(
+            read,
+            Ok("2 lines match `line 5`:\n5: line 5\n50: line 50\n".to_string())
+        );
+    }
+
+    #[test]
+    fn reads_values_of_mcp_results() {
+        let codex_home = TempDir::new().unwrap();
+        let artifacts = artifacts(&codex_home);
+        let result: CallToolResult = serde_json::from_value(json!({
+            "content": [{"type": "text", "text": "x".repeat(200)}],
+            "structuredContent": {"rows": [{"id": 1}, {"id": 2}]},
+        }))
+        .unwrap();
+        let response = ResponseInputItem::McpToolCallOutput {
+            call_id: "call_2".to_string(),
+            result: Ok(result),
+        };
+
+        let (_, event) = artifacts.offload(response);
+
+        assert_eq!(event.unwrap().mime_type, "application/json");
+        let read = artifacts
+            .read(r#"{"artifact":"call_2.json","json_pointer":"/structuredContent/rows/1"}"#);
+        assert_eq!(read, Ok("bytes 0-13 of 13:\n{\n  \"id\": 2\n}".to_string()));
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_artifacts_directory() {
+        let codex_home = TempDir::new().unwrap();
+
+        let read = artifacts(&codex_home).read(r#"{"artifact":"../config.toml"}"#);
+
+        assert_eq!(
+            read,
+            Err("invalid artifact name: ../config.toml".to_string())
+        );
+    }
+}
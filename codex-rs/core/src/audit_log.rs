@@ -0,0 +1,584 @@
+//! Append-only, tamper-evident log of the privileged actions taken on the
+//! user's behalf: shell commands, file patches, MCP tool calls, secret reads
+//! and the built-in tools that change remote systems.
+//!
+//! The log is stored at `~/.codex/audit/audit.jsonl` with one record per line.
+//! Every record carries the SHA-256 hash of the record before it, so editing,
+//! reordering or removing a record breaks the chain from that point on, which
+//! [`verify_audit_log`] reports. As with the message history, writers take an
+//! advisory lock on the file so that concurrent sessions do not interleave.
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::io::Result;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::DateTime;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tracing::warn;
+use uuid::Uuid;
+use wildmatch::WildMatchPattern;
+
+use crate::config_types::AuditConfig;
+use crate::exec::SandboxType;
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+/// Directory inside `~/.codex` that holds the audit log.
+pub const AUDIT_SUBDIR: &str = "audit";
+
+const AUDIT_FILENAME: &str = "audit.jsonl";
+
+/// `prev_hash` of the first record.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Bytes read at a time when looking for the last record.
+const TAIL_CHUNK: u64 = 4096;
+
+const MAX_RETRIES: usize = 10;
+const RETRY_SLEEP: Duration = Duration::from_millis(100);
+
+/// A privileged action taken by the agent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuditAction {
+    /// A `shell` command, or a job started with `background_start`.
+    Exec {
+        command: Vec<String>,
+        cwd: PathBuf,
+        #[serde(default)]
+        background: bool,
+    },
+    /// An `apply_patch` call.
+    Patch { cwd: PathBuf, files: Vec<PathBuf> },
+    /// A call to a tool of an MCP server.
+    McpToolCall {
+        server: String,
+        tool: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        arguments: Option<serde_json::Value>,
+    },
+    /// A Key Vault secret read into the environment of later commands.
+    SecretRead { vault: String, name: String },
+    /// A built-in tool call that changes a remote system, such as an ARM
+    /// deployment, a blob upload, a ticket change or a channel post, with
+    /// the request shown for approval.
+    RemoteWrite {
+        tool: String,
+        request: serde_json::Value,
+    },
+}
+
+impl AuditAction {
+    pub fn kind(&self) -> AuditActionKind {
+        match self {
+            AuditAction::Exec { .. } => AuditActionKind::Exec,
+            AuditAction::Patch { .. } => AuditActionKind::Patch,
+            AuditAction::McpToolCall { .. } => AuditActionKind::McpToolCall,
+            AuditAction::SecretRead { .. } => AuditActionKind::SecretRead,
+            AuditAction::RemoteWrite { .. } => AuditActionKind::RemoteWrite,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditActionKind {
+    Exec,
+    Patch,
+    McpToolCall,
+    SecretRead,
+    RemoteWrite,
+}
+
+/// How an action was approved, or why it was not run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum AuditApproval {
+    /// Allowed by the approval policy or a command rule without asking.
+    Auto { sandbox: SandboxType },
+    /// Run without an approval check, as most MCP tool calls are.
+    NotRequired,
+    /// Approved by the user for this call.
+    Approved,
+    /// Approved by the user for the rest of the session.
+    ApprovedForSession,
+    /// Denied by the user.
+    Denied,
+    /// Rejected by the approval policy or a command rule.
+    Rejected { reason: String },
+}
+
+/// The result of an action that was run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditOutcome {
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditOutcome {
+    pub(crate) fn exit_code(exit_code: i32) -> Self {
+        Self {
+            success: exit_code == 0,
+            exit_code: Some(exit_code),
+            error: None,
+        }
+    }
+
+    pub(crate) fn success() -> Self {
+        Self {
+            success: true,
+            exit_code: None,
+            error: None,
+        }
+    }
+
+    pub(crate) fn error(error: impl ToString) -> Self {
+        Self {
+            success: false,
+            exit_code: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Position in the log, starting at 1.
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    pub session_id: Uuid,
+    pub action: AuditAction,
+    pub approval: AuditApproval,
+    /// `None` when the action was not run.
+    pub outcome: Option<AuditOutcome>,
+    /// Hash of the previous record.
+    pub prev_hash: String,
+}
+
+/// A line of the log: a record and the hash of its serialized form.
+#[derive(Serialize, Deserialize)]
+struct AuditEntry {
+    #[serde(flatten)]
+    record: AuditRecord,
+    hash: String,
+}
+
+impl AuditEntry {
+    fn new(record: AuditRecord) -> Result<Self> {
+        let hash = hash_record(&record)?;
+        Ok(Self { record, hash })
+    }
+}
+
+/// Filter for [`query_audit_log`]. Unset fields match every record.
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub session_id: Option<Uuid>,
+    pub kind: Option<AuditActionKind>,
+    /// Only return the most recent `limit` matching records.
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        self.since.is_none_or(|since| record.timestamp >= since)
+            && self.until.is_none_or(|until| record.timestamp <= until)
+            && self.session_id.is_none_or(|id| record.session_id == id)
+            && self.kind.is_none_or(|kind| record.action.kind() == kind)
+    }
+}
+
+/// Result of [`verify_audit_log`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditVerification {
+    /// Every record is unmodified and chained to the one before it.
+    Intact { records: u64 },
+    /// The chain is broken at `line` (1-based) of the log.
+    Broken { line: usize, reason: String },
+}
+
+/// Writes the audit records of a session.
+#[derive(Debug, Clone)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    session_id: Uuid,
+    mcp_tools: Vec<String>,
+}
+
+impl AuditLog {
+    /// `None` when auditing is disabled.
+    pub(crate) fn new(codex_home: &Path, session_id: Uuid, config: &AuditConfig) -> Option<Self> {
+        config.enabled.then(|| Self {
+            path: audit_log_path(codex_home),
+            session_id,
+            mcp_tools: config.mcp_tools.clone(),
+        })
+    }
+
+    /// Whether calls to `tool` of `server` are recorded.
+    pub(crate) fn audits_mcp_tool(&self, server: &str, tool: &str) -> bool {
+        let name = format!("{server}/{tool}");
+        self.mcp_tools
+            .iter()
+            .any(|pattern| WildMatchPattern::<'*', '?'>::new(pattern).matches(&name))
+    }
+
+    /// Append a record. Failures are logged rather than returned so that a
+    /// broken log never blocks the action it describes.
+    pub(crate) async fn record(
+        &self,
+        action: AuditAction,
+        approval: AuditApproval,
+        outcome: Option<AuditOutcome>,
+    ) {
+        if let Err(e) = self.append(action, approval, outcome).await {
+            warn!(
+                "failed to write audit record to {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+
+    async fn append(
+        &self,
+        action: AuditAction,
+        approval: AuditApproval,
+        outcome: Option<AuditOutcome>,
+    ) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut options = OpenOptions::new();
+        options.append(true).read(true).create(true);
+        #[cfg(unix)]
+        {
+            options.mode(0o600);
+        }
+        let mut file = options.open(&self.path)?;
+        acquire_exclusive_lock_with_retry(&file).await?;
+
+        let session_id = self.session_id;
+        // The lock is held until `file` is dropped at the end of the closure,
+        // so no other writer can append between reading the last record and
+        // writing the new one.
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let (seq, prev_hash) = match read_last_line(&mut file)? {
+                Some(line) => {
+                    let last: AuditEntry = serde_json::from_str(&line).map_err(|e| {
+                        std::io::Error::other(format!("last audit record is corrupt: {e}"))
+                    })?;
+                    (last.record.seq + 1, last.hash)
+                }
+                None => (1, GENESIS_HASH.to_string()),
+            };
+            let entry = AuditEntry::new(AuditRecord {
+                seq,
+                timestamp: Utc::now(),
+                session_id,
+                action,
+                approval,
+                outcome,
+                prev_hash,
+            })?;
+            let mut line = serde_json::to_string(&entry).map_err(std::io::Error::other)?;
+            line.push('\n');
+            file.write_all(line.as_bytes())?;
+            file.flush()
+        })
+        .await?
+    }
+}
+
+pub fn audit_log_path(codex_home: &Path) -> PathBuf {
+    codex_home.join(AUDIT_SUBDIR).join(AUDIT_FILENAME)
+}
+
+/// Records of the audit log in `codex_home` matching `query`, oldest first.
+/// Lines that cannot be parsed are skipped; use [`verify_audit_log`] to check
+/// the integrity of the log.
+pub async fn query_audit_log(codex_home: &Path, query: &AuditQuery) -> Result<Vec<AuditRecord>> {
+    let mut records: Vec<AuditRecord> = read_log_lines(codex_home)
+        .await?
+        .iter()
+        .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+        .map(|entry| entry.record)
+        .filter(|record| query.matches(record))
+        .collect();
+    if let Some(limit) = query.limit {
+        records.drain(..records.len().saturating_sub(limit));
+    }
+    Ok(records)
+}
+
+/// Check that no record of the audit log in `codex_home` was modified,
+/// reordered or removed.
+pub async fn verify_audit_log(codex_home: &Path) -> Result<AuditVerification> {
+    let lines = read_log_lines(codex_home).await?;
+    let mut prev_hash = GENESIS_HASH.to_string();
+    for (index, line) in lines.iter().enumerate() {
+        let broken = |reason: String| AuditVerification::Broken {
+            line: index + 1,
+            reason,
+        };
+        let entry = match serde_json::from_str::<AuditEntry>(line) {
+            Ok(entry) => entry,
+            Err(e) => return Ok(broken(format!("record cannot be parsed: {e}"))),
+        };
+        let expected_seq = index as u64 + 1;
+        if entry.record.seq != expected_seq {
+            return Ok(broken(format!(
+                "expected record {expected_seq}, found record {}",
+                entry.record.seq
+            )));
+        }
+        if entry.record.prev_hash != prev_hash {
+            return Ok(broken(
+                "record does not follow the previous one".to_string(),
+            ));
+        }
+        if hash_record(&entry.record)? != entry.hash {
+            return Ok(broken("record was modified".to_string()));
+        }
+        prev_hash = entry.hash;
+    }
+    Ok(AuditVerification::Intact {
+        records: lines.len() as u64,
+    })
+}
+
+async fn read_log_lines(codex_home: &Path) -> Result<Vec<String>> {
+    let contents = match tokio::fs::read_to_string(audit_log_path(codex_home)).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+fn hash_record(record: &AuditRecord) -> Result<String> {
+    let json = serde_json::to_string(record).map_err(std::io::Error::other)?;
+    Ok(format!("{:x}", Sha256::digest(json.as_bytes())))
+}
+
+/// The last non-empty line of `file`, read backwards from the end so that
+/// appending does not get slower as the log grows.
+fn read_last_line(file: &mut File) -> Result<Option<String>> {
+    let mut pos = file.seek(SeekFrom::End(0))?;
+    let mut buf: Vec<u8> = Vec::new();
+    loop {
+        let end = buf.iter().rposition(|&b| b != b'\n').map(|i| i + 1);
+        if let Some(end) = end
+            && let Some(start) = buf[..end].iter().rposition(|&b| b == b'\n')
+        {
+            return Ok(Some(
+                String::from_utf8_lossy(&buf[start + 1..end]).into_owned(),
+            ));
+        }
+        if pos == 0 {
+            return Ok(end.map(|end| String::from_utf8_lossy(&buf[..end]).into_owned()));
+        }
+        let start = pos.saturating_sub(TAIL_CHUNK);
+        let mut chunk = vec![0; (pos - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&buf);
+        buf = chunk;
+        pos = start;
+    }
+}
+
+async fn acquire_exclusive_lock_with_retry(file: &File) -> Result<()> {
+    for _ in 0..MAX_RETRIES {
+        match file.try_lock() {
+            Ok(()) => return Ok(()),
+            Err(std::fs::TryLockError::WouldBlock) => tokio::time::sleep(RETRY_SLEEP).await,
+            Err(other) => return Err(other.into()),
+        }
+    }
+
+    Err(std::io::Error::new(
+        std::io::ErrorKind::WouldBlock,
+        "could not acquire exclusive lock on audit log after multiple attempts",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    fn exec(command: &str) -> AuditAction {
+        AuditAction::Exec {
+            command: vec!["bash".to_string(), "-lc".to_string(), command.to_string()],
+            cwd: PathBuf::from("/work"),
+            background: false,
+        }
+    }
+
+    async fn write_records(home: &TempDir, session_id: Uuid) -> AuditLog {
+        let log = AuditLog::new(home.path(), session_id, &AuditConfig::default()).unwrap();
+        log.record(
+            exec("echo hi"),
+            AuditApproval::Auto {
+                sandbox: SandboxType::LinuxSeccomp,
+            },
+            Some(AuditOutcome::exit_code(0)),
+        )
+        .await;
+        log.record(
+            AuditAction::Patch {
+                cwd: PathBuf::from("/work"),
+                files: vec![PathBuf::from("/work/a.txt")],
+            },
+            AuditApproval::Denied,
+            None,
+        )
+        .await;
+        log.record(
+            AuditAction::McpToolCall {
+                server: "kusto".to_string(),
+                tool: "drop_table".to_string(),
+                arguments: Some(serde_json::json!({ "table": "events" })),
+            },
+            AuditApproval::NotRequired,
+            Some(AuditOutcome::error("permission denied")),
+        )
+        .await;
+        log
+    }
+
+    #[tokio::test]
+    async fn records_are_chained_and_queryable() {
+        let home = TempDir::new().unwrap();
+        let session_id = Uuid::new_v4();
+        write_records(&home, session_id).await;
+        // A second session continues the same chain.
+        let other = AuditLog::new(home.path(), Uuid::new_v4(), &AuditConfig::default()).unwrap();
+        other
+            .record(exec("ls"), AuditApproval::Approved, None)
+            .await;
+
+        assert_eq!(
+            verify_audit_log(home.path()).await.unwrap(),
+            AuditVerification::Intact { records: 4 }
+        );
+
+        let all = query_audit_log(home.path(), &AuditQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            all.iter().map(|r| r.seq).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert_eq!(all[0].prev_hash, GENESIS_HASH);
+
+        let query = AuditQuery {
+            session_id: Some(session_id),
+            kind: Some(AuditActionKind::Exec),
+            ..AuditQuery::default()
+        };
+        let execs = query_audit_log(home.path(), &query).await.unwrap();
+        assert_eq!(execs.len(), 1);
+        assert_eq!(execs[0].action, exec("echo hi"));
+        assert_eq!(execs[0].outcome, Some(AuditOutcome::exit_code(0)));
+
+        let query = AuditQuery {
+            limit: Some(2),
+            ..AuditQuery::default()
+        };
+        let latest = query_audit_log(home.path(), &query).await.unwrap();
+        assert_eq!(latest.iter().map(|r| r.seq).collect::<Vec<_>>(), vec![3, 4]);
+    }
+
+    #[tokio::test]
+    async fn tampering_is_detected() {
+        let home = TempDir::new().unwrap();
+        write_records(&home, Uuid::new_v4()).await;
+        let path = audit_log_path(home.path());
+        let original = std::fs::read_to_string(&path).unwrap();
+
+        std::fs::write(&path, original.replace("echo hi", "rm -rf /")).unwrap();
+        assert_eq!(
+            verify_audit_log(home.path()).await.unwrap(),
+            AuditVerification::Broken {
+                line: 1,
+                reason: "record was modified".to_string()
+            }
+        );
+
+        let without_second: Vec<&str> = original
+            .lines()
+            .enumerate()
+            .filter(|(i, _)| *i != 1)
+            .map(|(_, line)| line)
+            .collect();
+        std::fs::write(&path, without_second.join("\n")).unwrap();
+        assert!(matches!(
+            verify_audit_log(home.path()).await.unwrap(),
+            AuditVerification::Broken { line: 2, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn remote_writes_are_recorded_by_kind() {
+        let home = TempDir::new().unwrap();
+        write_records(&home, Uuid::new_v4()).await;
+        let log = AuditLog::new(home.path(), Uuid::new_v4(), &AuditConfig::default()).unwrap();
+        let deploy = AuditAction::RemoteWrite {
+            tool: "arm_deploy".to_string(),
+            request: serde_json::json!({ "resource_group": "rg", "template": "main.bicep" }),
+        };
+        log.record(
+            deploy.clone(),
+            AuditApproval::Approved,
+            Some(AuditOutcome::success()),
+        )
+        .await;
+
+        let line = std::fs::read_to_string(audit_log_path(home.path())).unwrap();
+        assert!(line.contains(r#""type":"remote_write""#), "{line}");
+        let query = AuditQuery {
+            kind: Some(AuditActionKind::RemoteWrite),
+            ..AuditQuery::default()
+        };
+        let writes = query_audit_log(home.path(), &query).await.unwrap();
+        assert_eq!(writes.len(), 1);
+        assert_eq!(writes[0].action, deploy);
+        assert_eq!(writes[0].outcome, Some(AuditOutcome::success()));
+    }
+
+    #[test]
+    fn mcp_tool_patterns() {
+        let config = AuditConfig {
+            mcp_tools: vec!["kusto/*".to_string(), "*/delete_*".to_string()],
+            ..AuditConfig::default()
+        };
+        let log = AuditLog::new(Path::new("/tmp"), Uuid::new_v4(), &config).unwrap();
+        assert!(log.audits_mcp_tool("kusto", "query"));
+        assert!(log.audits_mcp_tool("ado", "delete_repo"));
+        assert!(!log.audits_mcp_tool("ado", "list_repos"));
+    }
+}
@@ -0,0 +1,911 @@
+//! Shared Azure (Entra ID) authentication.
+//!
+//! Every Azure integration obtains bearer tokens through [`AzureAuth`] so a
+//! single login covers all of them. Tokens are cached per `(tenant, scope)`
+//! both in memory and as the `azure_auth.json` credential (see
+//! [`codex_login::credential_store`]); refresh tokens are
+//! reused across scopes of the same tenant so a device-code login for one
+//! resource silently yields tokens for any other.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::DateTime;
+use chrono::Utc;
+use codex_login::credential_store;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::config_types::AzureAuthConfig;
+use crate::config_types::AzureAuthMethod;
+
+const AZURE_AUTH_FILE: &str = "azure_auth.json";
+
+/// Tokens this close to expiry are treated as expired so callers never
+/// receive a token that lapses mid-request.
+const EXPIRY_SKEW: chrono::Duration = chrono::Duration::minutes(5);
+
+const IMDS_ENDPOINT: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+#[derive(Debug, thiserror::Error)]
+pub enum AzureAuthError {
+    #[error("azure token request failed: {0}")]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error("azure token request was rejected: {error}: {description}")]
+    TokenEndpoint {
+        status: reqwest::StatusCode,
+        error: String,
+        description: String,
+    },
+
+    #[error("device code expired before the login was completed")]
+    DeviceCodeExpired,
+
+    #[error("environment variable `{0}` with the Azure client secret is not set")]
+    MissingClientSecret(String),
+
+    #[error("managed identity returned an invalid expiry `{0}`")]
+    InvalidExpiry(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl AzureAuthError {
+    /// Whether trying again later may succeed. Requests the token endpoint
+    /// rejected for good, such as a revoked refresh token or a wrong client
+    /// secret, and configuration errors are not.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AzureAuthError::Reqwest(_) | AzureAuthError::Io(_) => true,
+            AzureAuthError::TokenEndpoint { status, error, .. } => {
+                status.is_server_error()
+                    || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    || error == "temporarily_unavailable"
+            }
+            AzureAuthError::DeviceCodeExpired
+            | AzureAuthError::MissingClientSecret(_)
+            | AzureAuthError::InvalidExpiry(_) => false,
+        }
+    }
+}
+
+/// Prompt shown to the user during a device-code login.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceCodePrompt {
+    pub user_code: String,
+    pub verification_uri: String,
+    pub message: String,
+}
+
+pub type DeviceCodeHandler = Arc<dyn Fn(&DeviceCodePrompt) + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AzureAccessToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct CachedToken {
+    tenant_id: String,
+    scope: String,
+    access_token: String,
+    expires_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TokenCacheFile {
+    #[serde(default)]
+    tokens: Vec<CachedToken>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+    refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenErrorResponse {
+    error: String,
+    #[serde(default)]
+    error_description: String,
+}
+
+#[derive(Deserialize)]
+struct DeviceCodeResponse {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    message: String,
+    expires_in: u64,
+    #[serde(default = "default_poll_interval")]
+    interval: u64,
+}
+
+fn default_poll_interval() -> u64 {
+    5
+}
+
+/// Managed identity endpoints return numbers as strings.
+#[derive(Deserialize)]
+struct ManagedIdentityResponse {
+    access_token: String,
+    expires_on: String,
+}
+
+/// Token provider shared by all Azure integrations.
+pub struct AzureAuth {
+    config: AzureAuthConfig,
+    cache_file: PathBuf,
+    client: reqwest::Client,
+    tokens: Mutex<HashMap<(String, String), CachedToken>>,
+    /// Held while a token is acquired with the configured flow, so
+    /// concurrent callers wait for one login instead of each starting their
+    /// own. `tokens` is not held meanwhile, so cached tokens and silent
+    /// refreshes stay available during a device-code login.
+    login: Mutex<()>,
+    device_code_handler: DeviceCodeHandler,
+}
+
+impl std::fmt::Debug for AzureAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AzureAuth")
+            .field("config", &self.config)
+            .field("cache_file", &self.cache_file)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AzureAuth {
+    pub fn new(config: AzureAuthConfig, codex_home: &Path) -> Self {
+        let cache_file = codex_home.join(AZURE_AUTH_FILE);
+        let tokens = read_cache_file(&cache_file)
+            .into_iter()
+            .map(|t| ((t.tenant_id.clone(), t.scope.clone()), t))
+            .collect();
+        Self {
+            config,
+            cache_file,
+            client: reqwest::Client::new(),
+            tokens: Mutex::new(tokens),
+            login: Mutex::new(()),
+            device_code_handler: Arc::new(|prompt: &DeviceCodePrompt| {
+                tracing::warn!("{}", prompt.message);
+            }),
+        }
+    }
+
+    /// Replace the default handler (which logs the prompt) used to show the
+    /// device-code instructions to the user.
+    pub fn with_device_code_handler(mut self, handler: DeviceCodeHandler) -> Self {
+        self.device_code_handler = handler;
+        self
+    }
+
+    /// Token for `scope` in the configured tenant.
+    pub async fn get_token(&self, scope: &str) -> Result<AzureAccessToken, AzureAuthError> {
+        let tenant_id = self.config.tenant_id.clone();
+        self.get_token_for_tenant(&tenant_id, scope).await
+    }
+
+    /// Token for `scope` in `tenant_id`, served from the cache when still
+    /// valid, otherwise silently refreshed or acquired with the configured
+    /// flow.
+    pub async fn get_token_for_tenant(
+        &self,
+        tenant_id: &str,
+        scope: &str,
+    ) -> Result<AzureAccessToken, AzureAuthError> {
+        let key = (tenant_id.to_string(), scope.to_string());
+        if let Some(token) = self.cached_token(&key).await {
+            return Ok(token);
+        }
+
+        let mut tried = Vec::new();
+        let _login;
+        let token = match self.redeem_refresh_tokens(&key, &mut tried).await {
+            Some(token) => token,
+            None => {
+                _login = self.login.lock().await;
+                // Another caller may have logged in while this one waited.
+                if let Some(token) = self.cached_token(&key).await {
+                    return Ok(token);
+                }
+                match self.redeem_refresh_tokens(&key, &mut tried).await {
+                    Some(token) => token,
+                    None => match self.config.method {
+                        AzureAuthMethod::DeviceCode => self.device_code(tenant_id, scope).await?,
+                        AzureAuthMethod::ClientSecret => {
+                            self.client_secret(tenant_id, scope).await?
+                        }
+                        AzureAuthMethod::ManagedIdentity => {
+                            self.managed_identity(tenant_id, scope).await?
+                        }
+                    },
+                }
+            }
+        };
+
+        let result = AzureAccessToken {
+            token: token.access_token.clone(),
+            expires_at: token.expires_at,
+        };
+        let mut tokens = self.tokens.lock().await;
+        tokens.insert(key, token);
+        self.persist(&tokens);
+        Ok(result)
+    }
+
+    /// Redeem the cached refresh tokens of the tenant of `key`, other than
+    /// those in `tried`, for its scope. Any refresh token for the tenant can
+    /// be redeemed for a new scope.
+    async fn redeem_refresh_tokens(
+        &self,
+        key: &(String, String),
+        tried: &mut Vec<String>,
+    ) -> Option<CachedToken> {
+        let (tenant_id, scope) = key;
+        let mut refresh_tokens: Vec<String> = Vec::new();
+        {
+            let tokens = self.tokens.lock().await;
+            for cached in tokens
+                .get(key)
+                .into_iter()
+                .chain(tokens.values().filter(|t| &t.tenant_id == tenant_id))
+            {
+                if let Some(rt) = &cached.refresh_token
+                    && !refresh_tokens.contains(rt)
+                    && !tried.contains(rt)
+                {
+                    refresh_tokens.push(rt.clone());
+                }
+            }
+        }
+        for refresh_token in refresh_tokens {
+            match self.refresh(tenant_id, scope, &refresh_token).await {
+                Ok(token) => return Some(token),
+                Err(e) => tracing::debug!("azure refresh token rejected: {e}"),
+            }
+            tried.push(refresh_token);
+        }
+        None
+    }
+
+    /// The cached token for `key`, unless it is about to expire.
+    async fn cached_token(&self, key: &(String, String)) -> Option<AzureAccessToken> {
+        let tokens = self.tokens.lock().await;
+        tokens
+            .get(key)
+            .filter(|cached| cached.expires_at - EXPIRY_SKEW > Utc::now())
+            .map(|cached| AzureAccessToken {
+                token: cached.access_token.clone(),
+                expires_at: cached.expires_at,
+            })
+    }
+
+    /// Earliest expiry among cached tokens that can be renewed without user
+    /// interaction, if any.
+    pub async fn next_expiration(&self) -> Option<DateTime<Utc>> {
+        let tokens = self.tokens.lock().await;
+        tokens
+            .values()
+            .filter(|t| self.can_renew_silently(t))
+            .map(|t| t.expires_at)
+            .min()
+    }
+
+    /// Renew every cached token that expires before `deadline` without
+    /// prompting the user. Returns how many tokens were renewed; an error is
+    /// returned only if every attempted renewal failed.
+    pub async fn refresh_expiring(&self, deadline: DateTime<Utc>) -> Result<usize, AzureAuthError> {
+        let due: Vec<CachedToken> = self
+            .tokens
+            .lock()
+            .await
+            .values()
+            .filter(|t| t.expires_at <= deadline && self.can_renew_silently(t))
+            .cloned()
+            .collect();
+
+        let mut renewed = 0;
+        let mut last_err = None;
+        for cached in due {
+            let key = (cached.tenant_id.clone(), cached.scope.clone());
+            let result = match &cached.refresh_token {
+                Some(refresh_token) => {
+                    self.refresh(&cached.tenant_id, &cached.scope, refresh_token)
+                        .await
+                }
+                None => match self.config.method {
+                    AzureAuthMethod::ClientSecret => {
+                        self.client_secret(&cached.tenant_id, &cached.scope).await
+                    }
+                    AzureAuthMethod::ManagedIdentity => {
+                        self.managed_identity(&cached.tenant_id, &cached.scope)
+                            .await
+                    }
+                    AzureAuthMethod::DeviceCode => continue,
+                },
+            };
+            let mut tokens = self.tokens.lock().await;
+            match result {
+                Ok(token) => {
+                    tokens.insert(key, token);
+                    renewed += 1;
+                }
+                Err(e) => {
+                    // A rejected refresh token will never work again; stop
+                    // scheduling it so the next interactive use logs in anew.
+                    if cached.refresh_token.is_some()
+                        && matches!(e, AzureAuthError::TokenEndpoint { .. })
+                        && !e.is_retryable()
+                        && let Some(entry) = tokens.get_mut(&key)
+                    {
+                        entry.refresh_token = None;
+                    }
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        self.persist(&*self.tokens.lock().await);
+        match last_err {
+            Some(e) if renewed == 0 => Err(e),
+            _ => Ok(renewed),
+        }
+    }
+
+    fn can_renew_silently(&self, token: &CachedToken) -> bool {
+        token.refresh_token.is_some() || self.config.method != AzureAuthMethod::DeviceCode
+    }
+
+    fn persist(&self, tokens: &HashMap<(String, String), CachedToken>) {
+        let snapshot = TokenCacheFile {
+            tokens: tokens.values().cloned().collect(),
+        };
+        if let Err(e) = write_cache_file(&self.cache_file, &snapshot) {
+            tracing::warn!("failed to persist azure token cache: {e}");
+        }
+    }
+
+    /// Holds `access_token` for `scope` in the configured tenant, for the
+    /// tests of the integrations that use the login.
+    #[cfg(test)]
+    pub(crate) fn with_token_for_tests(mut self, scope: &str, access_token: &str) -> Self {
+        let token = CachedToken {
+            tenant_id: self.config.tenant_id.clone(),
+            scope: scope.to_string(),
+            access_token: access_token.to_string(),
+            expires_at: Utc::now() + chrono::Duration::hours(1),
+            refresh_token: None,
+        };
+        self.tokens
+            .get_mut()
+            .insert((token.tenant_id.clone(), token.scope.clone()), token);
+        self
+    }
+
+    /// Forget every cached token. Returns `true` if a stored cache was removed.
+    pub async fn logout(&self) -> std::io::Result<bool> {
+        self.tokens.lock().await.clear();
+        credential_store::delete_credential(&self.cache_file)
+    }
+
+    fn endpoint(&self, tenant_id: &str, path: &str) -> String {
+        let authority = self.config.authority_host.trim_end_matches('/');
+        format!("{authority}/{tenant_id}/oauth2/v2.0/{path}")
+    }
+
+    async fn refresh(
+        &self,
+        tenant_id: &str,
+        scope: &str,
+        refresh_token: &str,
+    ) -> Result<CachedToken, AzureAuthError> {
+        let scopes = format!("{scope} offline_access");
+        let form = [
+            ("grant_type", "refresh_token"),
+            ("client_id", self.config.client_id.as_str()),
+            ("refresh_token", refresh_token),
+            ("scope", scopes.as_str()),
+        ];
+        let response = self.request_token(tenant_id, &form).await?;
+        Ok(to_cached(tenant_id, scope, response))
+    }
+
+    async fn client_secret(
+        &self,
+        tenant_id: &str,
+        scope: &str,
+    ) -> Result<CachedToken, AzureAuthError> {
+        let var = &self.config.client_secret_env_var;
+        let secret = std::env::var(var)
+            .ok()
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| AzureAuthError::MissingClientSecret(var.clone()))?;
+        let form = [
+            ("grant_type", "client_credentials"),
+            ("client_id", self.config.client_id.as_str()),
+            ("client_secret", secret.as_str()),
+            ("scope", scope),
+        ];
+        let response = self.request_token(tenant_id, &form).await?;
+        Ok(to_cached(tenant_id, scope, response))
+    }
+
+    async fn device_code(
+        &self,
+        tenant_id: &str,
+        scope: &str,
+    ) -> Result<CachedToken, AzureAuthError> {
+        let scopes = format!("{scope} offline_access");
+        let response = self
+            .client
+            .post(self.endpoint(tenant_id, "devicecode"))
+            .form(&[
+                ("client_id", self.config.client_id.as_str()),
+                ("scope", scopes.as_str()),
+            ])
+            .send()
+            .await?;
+        let device: DeviceCodeResponse = parse_response(response).await?;
+        (self.device_code_handler)(&DeviceCodePrompt {
+            user_code: device.user_code,
+            verification_uri: device.verification_uri,
+            message: device.message,
+        });
+
+        let deadline = Utc::now() + chrono::Duration::seconds(device.expires_in as i64);
+        let mut interval = device.interval;
+        let form = [
+            ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+            ("client_id", self.config.client_id.as_str()),
+            ("device_code", device.device_code.as_str()),
+        ];
+        loop {
+            if Utc::now() > deadline {
+                return Err(AzureAuthError::DeviceCodeExpired);
+            }
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            match self.request_token(tenant_id, &form).await {
+                Ok(response) => return Ok(to_cached(tenant_id, scope, response)),
+                Err(AzureAuthError::TokenEndpoint { error, .. })
+                    if error == "authorization_pending" => {}
+                Err(AzureAuthError::TokenEndpoint { error, .. }) if error == "slow_down" => {
+                    interval += 5;
+                }
+                Err(AzureAuthError::TokenEndpoint { error, .. }) if error == "expired_token" => {
+                    return Err(AzureAuthError::DeviceCodeExpired);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn managed_identity(
+        &self,
+        tenant_id: &str,
+        scope: &str,
+    ) -> Result<CachedToken, AzureAuthError> {
+        let resource = scope.trim_end_matches("/.default");
+        // App Service and Functions expose their own endpoint; everything
+        // else goes through the instance metadata service.
+        let request = match (
+            std::env::var("IDENTITY_ENDPOINT"),
+            std::env::var("IDENTITY_HEADER"),
+        ) {
+            (Ok(endpoint), Ok(header)) => self
+                .client
+                .get(endpoint)
+                .header("X-IDENTITY-HEADER", header)
+                .query(&[("api-version", "2019-08-01"), ("resource", resource)]),
+            _ => self
+                .client
+                .get(IMDS_ENDPOINT)
+                .header("Metadata", "true")
+                .query(&[("api-version", "2018-02-01"), ("resource", resource)]),
+        };
+        let response: ManagedIdentityResponse = parse_response(request.send().await?).await?;
+        Ok(CachedToken {
+            tenant_id: tenant_id.to_string(),
+            scope: scope.to_string(),
+            access_token: response.access_token,
+            expires_at: parse_expires_on(&response.expires_on)?,
+            refresh_token: None,
+        })
+    }
+
+    async fn request_token(
+        &self,
+        tenant_id: &str,
+        form: &[(&str, &str)],
+    ) -> Result<TokenResponse, AzureAuthError> {
+        let response = self
+            .client
+            .post(self.endpoint(tenant_id, "token"))
+            .form(form)
+            .send()
+            .await?;
+        parse_response(response).await
+    }
+}
+
+/// Managed identity expiries are Unix timestamps in a string.
+fn parse_expires_on(expires_on: &str) -> Result<DateTime<Utc>, AzureAuthError> {
+    expires_on
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .ok_or_else(|| AzureAuthError::InvalidExpiry(expires_on.to_string()))
+}
+
+async fn parse_response<T: serde::de::DeserializeOwned>(
+    response: reqwest::Response,
+) -> Result<T, AzureAuthError> {
+    if response.status().is_success() {
+        return Ok(response.json().await?);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(match serde_json::from_str::<TokenErrorResponse>(&body) {
+        Ok(err) => AzureAuthError::TokenEndpoint {
+            status,
+            error: err.error,
+            description: err.error_description,
+        },
+        Err(_) => AzureAuthError::TokenEndpoint {
+            status,
+            error: status.to_string(),
+            description: body,
+        },
+    })
+}
+
+fn to_cached(tenant_id: &str, scope: &str, response: TokenResponse) -> CachedToken {
+    CachedToken {
+        tenant_id: tenant_id.to_string(),
+        scope: scope.to_string(),
+        access_token: response.access_token,
+        expires_at: Utc::now() + chrono::Duration::seconds(response.expires_in as i64),
+        refresh_token: response.refresh_token,
+    }
+}
+
+fn read_cache_file(path: &Path) -> Vec<CachedToken> {
+    credential_store::read_credential(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<TokenCacheFile>(&contents).ok())
+        .map(|file| file.tokens)
+        .unwrap_or_default()
+}
+
+fn write_cache_file(path: &Path, cache: &TokenCacheFile) -> std::io::Result<()> {
+    let json_data = serde_json::to_string_pretty(cache)?;
+    credential_store::write_credential(path, &json_data)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::body_string_contains;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+
+    const SCOPE: &str = "https://kusto.kusto.windows.net/.default";
+
+    fn config(server: &MockServer, method: AzureAuthMethod) -> AzureAuthConfig {
+        AzureAuthConfig {
+            tenant_id: "contoso".to_string(),
+            client_id: "client".to_string(),
+            method,
+            client_secret_env_var: "CODEX_TEST_AZURE_SECRET_UNSET".to_string(),
+            authority_host: server.uri(),
+        }
+    }
+
+    fn cached(scope: &str, access_token: &str, expires_in: i64) -> CachedToken {
+        CachedToken {
+            tenant_id: "contoso".to_string(),
+            scope: scope.to_string(),
+            access_token: access_token.to_string(),
+            expires_at: Utc::now() + chrono::Duration::seconds(expires_in),
+            refresh_token: Some("rt-1".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn returns_cached_token_without_network() {
+        let server = MockServer::start().await;
+        let home = TempDir::new().unwrap();
+        write_cache_file(
+            &home.path().join(AZURE_AUTH_FILE),
+            &TokenCacheFile {
+                tokens: vec![cached(SCOPE, "cached-at", 3600)],
+            },
+        )
+        .unwrap();
+
+        let auth = AzureAuth::new(config(&server, AzureAuthMethod::DeviceCode), home.path());
+        let token = auth.get_token(SCOPE).await.unwrap();
+
+        assert_eq!(token.token, "cached-at");
+        assert!(server.received_requests().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn refresh_token_is_reused_for_other_scopes_in_tenant() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/contoso/oauth2/v2.0/token"))
+            .and(body_string_contains("grant_type=refresh_token"))
+            .and(body_string_contains("refresh_token=rt-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "devops-at",
+                "expires_in": 3600,
+                "refresh_token": "rt-2",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let home = TempDir::new().unwrap();
+        write_cache_file(
+            &home.path().join(AZURE_AUTH_FILE),
+            &TokenCacheFile {
+                tokens: vec![cached(SCOPE, "kusto-at", 3600)],
+            },
+        )
+        .unwrap();
+
+        let auth = AzureAuth::new(config(&server, AzureAuthMethod::DeviceCode), home.path());
+        let devops_scope = "499b84ac-1321-427f-aa17-267ca6975798/.default";
+        let token = auth.get_token(devops_scope).await.unwrap();
+        assert_eq!(token.token, "devops-at");
+
+        // The second call is served from the in-memory cache and the new
+        // token has been persisted for the next process.
+        auth.get_token(devops_scope).await.unwrap();
+        let persisted = read_cache_file(&home.path().join(AZURE_AUTH_FILE));
+        assert!(
+            persisted
+                .iter()
+                .any(|t| t.scope == devops_scope && t.refresh_token.as_deref() == Some("rt-2"))
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_token_without_refresh_uses_configured_flow() {
+        let server = MockServer::start().await;
+        let home = TempDir::new().unwrap();
+        let mut expired = cached(SCOPE, "old", -60);
+        expired.refresh_token = None;
+        write_cache_file(
+            &home.path().join(AZURE_AUTH_FILE),
+            &TokenCacheFile {
+                tokens: vec![expired],
+            },
+        )
+        .unwrap();
+
+        let auth = AzureAuth::new(config(&server, AzureAuthMethod::ClientSecret), home.path());
+        let err = auth.get_token(SCOPE).await.unwrap_err();
+        assert!(matches!(err, AzureAuthError::MissingClientSecret(_)));
+    }
+
+    #[tokio::test]
+    async fn device_code_flow_polls_until_authorized() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/contoso/oauth2/v2.0/devicecode"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "device_code": "dc",
+                "user_code": "ABCD",
+                "verification_uri": "https://microsoft.com/devicelogin",
+                "message": "Enter ABCD",
+                "expires_in": 900,
+                "interval": 0,
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/contoso/oauth2/v2.0/token"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": "authorization_pending",
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/contoso/oauth2/v2.0/token"))
+            .and(body_string_contains("device_code=dc"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "fresh",
+                "expires_in": 3600,
+                "refresh_token": "rt",
+            })))
+            .mount(&server)
+            .await;
+
+        let home = TempDir::new().unwrap();
+        let shown = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let shown_clone = shown.clone();
+        let auth = AzureAuth::new(config(&server, AzureAuthMethod::DeviceCode), home.path())
+            .with_device_code_handler(Arc::new(move |prompt: &DeviceCodePrompt| {
+                shown_clone.lock().unwrap().push(prompt.user_code.clone());
+            }));
+
+        let token = auth.get_token(SCOPE).await.unwrap();
+        assert_eq!(token.token, "fresh");
+        assert_eq!(*shown.lock().unwrap(), vec!["ABCD".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn cache_stays_available_during_device_code_login() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/contoso/oauth2/v2.0/devicecode"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "device_code": "dc",
+                "user_code": "ABCD",
+                "verification_uri": "https://microsoft.com/devicelogin",
+                "message": "Enter ABCD",
+                "expires_in": 900,
+                "interval": 1,
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/contoso/oauth2/v2.0/token"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": "authorization_pending",
+            })))
+            .mount(&server)
+            .await;
+
+        let home = TempDir::new().unwrap();
+        let mut other = cached("https://management.azure.com/.default", "arm-at", 3600);
+        other.refresh_token = None;
+        write_cache_file(
+            &home.path().join(AZURE_AUTH_FILE),
+            &TokenCacheFile {
+                tokens: vec![other],
+            },
+        )
+        .unwrap();
+        let (prompted_tx, prompted_rx) = tokio::sync::oneshot::channel();
+        let prompted_tx = std::sync::Mutex::new(Some(prompted_tx));
+        let auth = Arc::new(
+            AzureAuth::new(config(&server, AzureAuthMethod::DeviceCode), home.path())
+                .with_device_code_handler(Arc::new(move |_: &DeviceCodePrompt| {
+                    if let Some(tx) = prompted_tx.lock().unwrap().take() {
+                        let _ = tx.send(());
+                    }
+                })),
+        );
+
+        let login = tokio::spawn({
+            let auth = auth.clone();
+            async move { auth.get_token(SCOPE).await }
+        });
+        prompted_rx.await.unwrap();
+
+        let token = tokio::time::timeout(
+            Duration::from_secs(1),
+            auth.get_token("https://management.azure.com/.default"),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(token.token, "arm-at");
+        tokio::time::timeout(Duration::from_secs(1), auth.next_expiration())
+            .await
+            .unwrap();
+        login.abort();
+    }
+
+    #[test]
+    fn invalid_managed_identity_expiry_is_an_error() {
+        assert_eq!(
+            parse_expires_on("1700000000").unwrap(),
+            DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+        );
+        assert!(matches!(
+            parse_expires_on("soon"),
+            Err(AzureAuthError::InvalidExpiry(value)) if value == "soon"
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_expiring_renews_only_due_tokens() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/contoso/oauth2/v2.0/token"))
+            .and(body_string_contains("grant_type=refresh_token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "renewed",
+                "expires_in": 3600,
+                "refresh_token": "rt-2",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let home = TempDir::new().unwrap();
+        let later_scope = "https://management.azure.com/.default";
+        write_cache_file(
+            &home.path().join(AZURE_AUTH_FILE),
+            &TokenCacheFile {
+                tokens: vec![
+                    cached(SCOPE, "soon", 60),
+                    cached(later_scope, "later", 7200),
+                ],
+            },
+        )
+        .unwrap();
+
+        let auth = AzureAuth::new(config(&server, AzureAuthMethod::DeviceCode), home.path());
+        let deadline = Utc::now() + chrono::Duration::minutes(10);
+        assert!(auth.next_expiration().await.unwrap() < deadline);
+
+        assert_eq!(auth.refresh_expiring(deadline).await.unwrap(), 1);
+        assert!(auth.next_expiration().await.unwrap() > deadline);
+    }
+
+    #[tokio::test]
+    async fn only_rejected_refresh_tokens_are_dropped() {
+        let server = MockServer::start().await;
+        let home = TempDir::new().unwrap();
+        write_cache_file(
+            &home.path().join(AZURE_AUTH_FILE),
+            &TokenCacheFile {
+                tokens: vec![cached(SCOPE, "soon", 60)],
+            },
+        )
+        .unwrap();
+        let auth = AzureAuth::new(config(&server, AzureAuthMethod::DeviceCode), home.path());
+        let deadline = Utc::now() + chrono::Duration::minutes(10);
+
+        let outage = Mock::given(method("POST"))
+            .and(path("/contoso/oauth2/v2.0/token"))
+            .respond_with(ResponseTemplate::new(503).set_body_json(serde_json::json!({
+                "error": "temporarily_unavailable",
+                "error_description": "try again later",
+            })))
+            .mount_as_scoped(&server)
+            .await;
+        let err = auth.refresh_expiring(deadline).await.unwrap_err();
+        assert!(err.is_retryable());
+        assert!(auth.next_expiration().await.is_some());
+        drop(outage);
+
+        Mock::given(method("POST"))
+            .and(path("/contoso/oauth2/v2.0/token"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": "invalid_grant",
+                "error_description": "the refresh token was revoked",
+            })))
+            .mount(&server)
+            .await;
+        let err = auth.refresh_expiring(deadline).await.unwrap_err();
+        assert!(!err.is_retryable());
+        assert_eq!(auth.next_expiration().await, None);
+    }
+}
@@ -0,0 +1,934 @@
+//! Azure Monitor tools for incident triage: the platform metrics of a
+//! resource, the alerts that fired in a subscription and its activity log.
+//! They read Azure Resource Manager with the shared Azure login and change
+//! nothing, which makes them a natural first step before querying logs.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::SecondsFormat;
+use chrono::Utc;
+use codex_protocol::models::FunctionCallOutputPayload;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::azure_auth::AzureAuth;
+use crate::config_types::AzureMonitorConfig;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+
+pub(crate) const AZURE_MONITOR_METRICS_TOOL_NAME: &str = "azure_monitor_metrics";
+pub(crate) const AZURE_MONITOR_ALERTS_TOOL_NAME: &str = "azure_monitor_alerts";
+pub(crate) const AZURE_MONITOR_ACTIVITY_LOG_TOOL_NAME: &str = "azure_monitor_activity_log";
+
+const METRICS_API_VERSION: &str = "2023-10-01";
+const ALERTS_API_VERSION: &str = "2019-05-05-preview";
+const ACTIVITY_LOG_API_VERSION: &str = "2015-04-01";
+
+/// Time ranges the alerts API accepts.
+const ALERT_TIME_RANGES: [&str; 4] = ["1h", "1d", "7d", "30d"];
+
+/// Points shown per time series; the most recent are kept.
+const MAX_POINTS_PER_SERIES: usize = 120;
+
+pub(crate) struct AzureMonitor {
+    config: AzureMonitorConfig,
+    azure_auth: Arc<AzureAuth>,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetricsParams {
+    resource_id: String,
+    metrics: Vec<String>,
+    #[serde(default)]
+    timespan: Option<String>,
+    #[serde(default)]
+    interval: Option<String>,
+    #[serde(default)]
+    aggregation: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AlertsParams {
+    #[serde(default)]
+    subscription_id: Option<String>,
+    #[serde(default)]
+    resource_group: Option<String>,
+    #[serde(default)]
+    resource_id: Option<String>,
+    #[serde(default)]
+    time_range: Option<String>,
+    #[serde(default)]
+    include_resolved: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ActivityLogParams {
+    #[serde(default)]
+    subscription_id: Option<String>,
+    #[serde(default)]
+    start: Option<String>,
+    #[serde(default)]
+    end: Option<String>,
+    #[serde(default)]
+    resource_group: Option<String>,
+    #[serde(default)]
+    resource_id: Option<String>,
+}
+
+/// A page of an ARM list.
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    #[serde(default = "Vec::new")]
+    value: Vec<T>,
+    #[serde(default, rename = "nextLink")]
+    next_link: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetricsResponse {
+    #[serde(default)]
+    timespan: Option<String>,
+    #[serde(default)]
+    value: Vec<Metric>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Metric {
+    name: LocalizedName,
+    #[serde(default)]
+    unit: Option<String>,
+    #[serde(default)]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LocalizedName {
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TimeSeries {
+    #[serde(default)]
+    metadatavalues: Vec<MetadataValue>,
+    #[serde(default)]
+    data: Vec<MetricValue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetadataValue {
+    name: LocalizedName,
+    #[serde(default)]
+    value: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetricValue {
+    time_stamp: String,
+    #[serde(default)]
+    average: Option<f64>,
+    #[serde(default)]
+    minimum: Option<f64>,
+    #[serde(default)]
+    maximum: Option<f64>,
+    #[serde(default)]
+    total: Option<f64>,
+    #[serde(default)]
+    count: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Alert {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    properties: AlertProperties,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AlertProperties {
+    #[serde(default)]
+    essentials: AlertEssentials,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AlertEssentials {
+    severity: Option<String>,
+    monitor_condition: Option<String>,
+    alert_state: Option<String>,
+    start_date_time: Option<String>,
+    target_resource_name: Option<String>,
+    target_resource_type: Option<String>,
+    monitor_service: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActivityLogEvent {
+    #[serde(default)]
+    event_timestamp: Option<String>,
+    #[serde(default)]
+    operation_name: Option<LocalizedName>,
+    #[serde(default)]
+    status: Option<LocalizedName>,
+    #[serde(default)]
+    sub_status: Option<LocalizedName>,
+    #[serde(default)]
+    caller: Option<String>,
+    #[serde(default)]
+    resource_id: Option<String>,
+    #[serde(default)]
+    level: Option<String>,
+}
+
+/// The body of an ARM error response.
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetail {
+    code: String,
+    message: String,
+}
+
+impl AzureMonitor {
+    pub(crate) fn new(config: AzureMonitorConfig, azure_auth: Arc<AzureAuth>) -> Self {
+        Self {
+            config,
+            azure_auth,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Answers a call to one of the Azure Monitor tools.
+    pub(crate) async fn handle_function_call(
+        &self,
+        name: &str,
+        arguments: &str,
+    ) -> FunctionCallOutputPayload {
+        let result = match name {
+            AZURE_MONITOR_METRICS_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.metrics(params).await,
+                Err(e) => Err(e),
+            },
+            AZURE_MONITOR_ALERTS_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.alerts(params).await,
+                Err(e) => Err(e),
+            },
+            AZURE_MONITOR_ACTIVITY_LOG_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.activity_log(params).await,
+                Err(e) => Err(e),
+            },
+            _ => Err(format!("unknown Azure Monitor tool `{name}`")),
+        };
+        match result {
+            Ok(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(true),
+            },
+            Err(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(false),
+            },
+        }
+    }
+
+    async fn metrics(&self, params: MetricsParams) -> Result<String, String> {
+        if params.metrics.is_empty() {
+            return Err("name at least one metric".to_string());
+        }
+        let url = format!(
+            "{}{}/providers/Microsoft.Insights/metrics",
+            self.resource_manager(),
+            normalize_resource_id(&params.resource_id)
+        );
+        let query = [
+            ("api-version", METRICS_API_VERSION.to_string()),
+            ("metricnames", params.metrics.join(",")),
+            (
+                "timespan",
+                params.timespan.unwrap_or_else(|| "PT1H".to_string()),
+            ),
+            (
+                "interval",
+                params.interval.unwrap_or_else(|| "PT5M".to_string()),
+            ),
+            (
+                "aggregation",
+                params
+                    .aggregation
+                    .unwrap_or_else(|| "Average,Maximum".to_string()),
+            ),
+        ];
+        let response: MetricsResponse = self.get(&url, &query).await?;
+        Ok(format_metrics(&response))
+    }
+
+    async fn alerts(&self, params: AlertsParams) -> Result<String, String> {
+        let subscription_id = self.subscription_id(params.subscription_id)?;
+        let time_range = params.time_range.unwrap_or_else(|| "1d".to_string());
+        if !ALERT_TIME_RANGES.contains(&time_range.as_str()) {
+            return Err(format!(
+                "time_range must be one of {}",
+                ALERT_TIME_RANGES.join(", ")
+            ));
+        }
+        let url = format!(
+            "{}/subscriptions/{subscription_id}/providers/Microsoft.AlertsManagement/alerts",
+            self.resource_manager()
+        );
+        let mut query = vec![
+            ("api-version", ALERTS_API_VERSION.to_string()),
+            ("timeRange", time_range.clone()),
+            ("sortBy", "startDateTime".to_string()),
+            ("sortOrder", "desc".to_string()),
+        ];
+        if !params.include_resolved {
+            query.push(("monitorCondition", "Fired".to_string()));
+        }
+        if let Some(resource_group) = params.resource_group {
+            query.push(("targetResourceGroup", resource_group));
+        }
+        if let Some(resource_id) = params.resource_id {
+            query.push(("targetResource", normalize_resource_id(&resource_id)));
+        }
+        let alerts: Vec<Alert> = self.list(&url, &query).await?;
+        let heading = if params.include_resolved {
+            format!("Alerts in the last {time_range}")
+        } else {
+            format!("Alerts fired in the last {time_range}")
+        };
+        Ok(format_alerts(&heading, &alerts))
+    }
+
+    async fn activity_log(&self, params: ActivityLogParams) -> Result<String, String> {
+        let subscription_id = self.subscription_id(params.subscription_id)?;
+        let end = match params.end {
+            Some(end) => parse_time("end", &end)?,
+            None => Utc::now(),
+        };
+        let start = match params.start {
+            Some(start) => parse_time("start", &start)?,
+            None => end - Duration::days(1),
+        };
+        if start >= end {
+            return Err("start must be before end".to_string());
+        }
+        let mut filter = format!(
+            "eventTimestamp ge '{}' and eventTimestamp le '{}'",
+            start.to_rfc3339_opts(SecondsFormat::Secs, true),
+            end.to_rfc3339_opts(SecondsFormat::Secs, true)
+        );
+        if let Some(resource_group) = params.resource_group {
+            let _ = write!(
+                filter,
+                " and resourceGroupName eq '{}'",
+                quote(&resource_group)
+            );
+        }
+        if let Some(resource_id) = params.resource_id {
+            let _ = write!(
+                filter,
+                " and resourceUri eq '{}'",
+                quote(&normalize_resource_id(&resource_id))
+            );
+        }
+        let url = format!(
+            "{}/subscriptions/{subscription_id}/providers/Microsoft.Insights/eventtypes/management/values",
+            self.resource_manager()
+        );
+        let query = [
+            ("api-version", ACTIVITY_LOG_API_VERSION.to_string()),
+            ("$filter", filter),
+            (
+                "$select",
+                "eventTimestamp,operationName,status,subStatus,caller,resourceId,level".to_string(),
+            ),
+        ];
+        let events: Vec<ActivityLogEvent> = self.list(&url, &query).await?;
+        Ok(format_activity_log(&events))
+    }
+
+    /// Every item of an ARM list, following `nextLink` up to `max_results`.
+    async fn list<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &[(&str, String)],
+    ) -> Result<Vec<T>, String> {
+        let mut page: Page<T> = self.get(url, query).await?;
+        let mut items = Vec::new();
+        loop {
+            items.append(&mut page.value);
+            if items.len() >= self.config.max_results {
+                items.truncate(self.config.max_results);
+                break;
+            }
+            match page.next_link.take() {
+                Some(next_link) => page = self.get(&next_link, &[]).await?,
+                None => break,
+            }
+        }
+        Ok(items)
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, String> {
+        let scope = format!("{}/.default", self.resource_manager());
+        let token = self
+            .azure_auth
+            .get_token(&scope)
+            .await
+            .map_err(|e| format!("failed to get an Azure token: {e}"))?;
+        let response = self
+            .client
+            .get(url)
+            .query(query)
+            .bearer_auth(token.token)
+            .send()
+            .await
+            .map_err(|e| format!("failed to reach Azure Resource Manager: {e}"))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let detail = match serde_json::from_str::<ErrorResponse>(&body) {
+                Ok(ErrorResponse { error }) => format!("{}: {}", error.code, error.message),
+                Err(_) => body,
+            };
+            return Err(format!("Azure Monitor request failed ({status}): {detail}"));
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| format!("failed to parse the Azure Monitor response: {e}"))
+    }
+
+    fn resource_manager(&self) -> &str {
+        self.config.resource_manager.trim_end_matches('/')
+    }
+
+    fn subscription_id(&self, requested: Option<String>) -> Result<String, String> {
+        requested
+            .or_else(|| self.config.subscription_id.clone())
+            .ok_or_else(|| {
+                "no subscription: pass subscription_id or set azure_monitor.subscription_id"
+                    .to_string()
+            })
+    }
+}
+
+fn parse<T: DeserializeOwned>(arguments: &str) -> Result<T, String> {
+    serde_json::from_str(arguments).map_err(|e| format!("failed to parse function arguments: {e}"))
+}
+
+fn parse_time(name: &str, value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| format!("{name} must be an RFC 3339 time such as 2024-05-01T08:00:00Z: {e}"))
+}
+
+/// `resource_id` with the leading slash ARM paths need.
+fn normalize_resource_id(resource_id: &str) -> String {
+    format!("/{}", resource_id.trim().trim_matches('/'))
+}
+
+/// `value` for a single-quoted OData string literal.
+fn quote(value: &str) -> String {
+    value.replace('\'', "''")
+}
+
+fn format_metrics(response: &MetricsResponse) -> String {
+    let mut out = String::new();
+    if let Some(timespan) = &response.timespan {
+        let _ = writeln!(out, "Timespan: {timespan}");
+    }
+    for metric in &response.value {
+        let unit = metric.unit.as_deref().unwrap_or("Unspecified");
+        if metric.timeseries.is_empty() {
+            let _ = writeln!(out, "\n{} ({unit}): no data", metric.name.value);
+        }
+        for series in &metric.timeseries {
+            let dimensions: Vec<String> = series
+                .metadatavalues
+                .iter()
+                .map(|m| format!("{}={}", m.name.value, m.value.as_deref().unwrap_or("")))
+                .collect();
+            let _ = write!(out, "\n{} ({unit})", metric.name.value);
+            if !dimensions.is_empty() {
+                let _ = write!(out, " [{}]", dimensions.join(", "));
+            }
+            out.push('\n');
+
+            let points: Vec<&MetricValue> = series
+                .data
+                .iter()
+                .filter(|point| !point.aggregates().is_empty())
+                .collect();
+            if points.is_empty() {
+                out.push_str("  no data\n");
+                continue;
+            }
+            let skipped = points.len().saturating_sub(MAX_POINTS_PER_SERIES);
+            if skipped > 0 {
+                let _ = writeln!(out, "  ({skipped} earlier points omitted)");
+            }
+            for point in &points[skipped..] {
+                let _ = writeln!(out, "  {}  {}", point.time_stamp, point.aggregates());
+            }
+            if let Some(peak) = points
+                .iter()
+                .filter_map(|point| point.maximum.or(point.average).map(|v| (v, point)))
+                .max_by(|a, b| a.0.total_cmp(&b.0))
+            {
+                let _ = writeln!(
+                    out,
+                    "  peak {} at {}",
+                    format_number(peak.0),
+                    peak.1.time_stamp
+                );
+            }
+        }
+    }
+    if response.value.is_empty() {
+        out.push_str("No metrics returned.\n");
+    }
+    out
+}
+
+impl MetricValue {
+    fn aggregates(&self) -> String {
+        let values: BTreeMap<&str, f64> = [
+            ("average", self.average),
+            ("minimum", self.minimum),
+            ("maximum", self.maximum),
+            ("total", self.total),
+            ("count", self.count),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| (name, value)))
+        .collect();
+        values
+            .iter()
+            .map(|(name, value)| format!("{name}={}", format_number(*value)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn format_number(value: f64) -> String {
+    let rounded = format!("{value:.3}");
+    rounded
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+fn format_alerts(heading: &str, alerts: &[Alert]) -> String {
+    if alerts.is_empty() {
+        return format!("{heading}: none.");
+    }
+    let mut out = format!("{heading}: {}\n", alerts.len());
+    for alert in alerts {
+        let e = &alert.properties.essentials;
+        let _ = write!(
+            out,
+            "\n{} {} ({}): {}\n  started {}",
+            e.severity.as_deref().unwrap_or("Sev?"),
+            e.monitor_condition.as_deref().unwrap_or("Unknown"),
+            e.alert_state.as_deref().unwrap_or("Unknown"),
+            alert.name,
+            e.start_date_time.as_deref().unwrap_or("unknown"),
+        );
+        if let Some(target) = &e.target_resource_name {
+            let _ = write!(out, ", on {target}");
+            if let Some(kind) = &e.target_resource_type {
+                let _ = write!(out, " ({kind})");
+            }
+        }
+        if let Some(service) = &e.monitor_service {
+            let _ = write!(out, ", from {service}");
+        }
+        out.push('\n');
+        if let Some(description) = e.description.as_deref().filter(|d| !d.is_empty()) {
+            let _ = writeln!(out, "  {description}");
+        }
+    }
+    out
+}
+
+fn format_activity_log(events: &[ActivityLogEvent]) -> String {
+    if events.is_empty() {
+        return "No activity log events in the time range.".to_string();
+    }
+    let mut out = format!("{} activity log events, newest first:\n", events.len());
+    for event in events {
+        let status = match (&event.status, &event.sub_status) {
+            (Some(status), Some(sub)) if !sub.value.is_empty() => {
+                format!("{} ({})", status.value, sub.value)
+            }
+            (Some(status), _) => status.value.clone(),
+            (None, _) => "Unknown".to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "{}  {}  {}  {}  by {}  on {}",
+            event.event_timestamp.as_deref().unwrap_or("?"),
+            event.level.as_deref().unwrap_or("?"),
+            status,
+            event
+                .operation_name
+                .as_ref()
+                .map_or("?", |name| name.value.as_str()),
+            event.caller.as_deref().unwrap_or("?"),
+            event.resource_id.as_deref().unwrap_or("?"),
+        );
+    }
+    out
+}
+
+pub(crate) fn create_azure_monitor_tools() -> Vec<ResponsesApiTool> {
+    let string = |description: &str| JsonSchema::String {
+        description: Some(description.to_string()),
+    };
+    let object = |properties: BTreeMap<String, JsonSchema>, required: &[&str]| JsonSchema::Object {
+        properties,
+        required: Some(required.iter().map(|r| r.to_string()).collect()),
+        additional_properties: Some(false),
+    };
+    let subscription_id =
+        || string("Subscription id; defaults to the configured azure_monitor.subscription_id.");
+
+    let metrics = BTreeMap::from([
+        (
+            "resource_id".to_string(),
+            string("ARM id of the resource, such as /subscriptions/…/resourceGroups/…/providers/Microsoft.Compute/virtualMachines/vm1."),
+        ),
+        (
+            "metrics".to_string(),
+            JsonSchema::Array {
+                items: Box::new(JsonSchema::String { description: None }),
+                description: Some(
+                    "Metric names, such as \"Percentage CPU\", \"Available Memory Bytes\" or \"Requests\"."
+                        .to_string(),
+                ),
+            },
+        ),
+        (
+            "timespan".to_string(),
+            string("ISO 8601 duration back from now, such as PT6H, or start/end interval. Default PT1H."),
+        ),
+        (
+            "interval".to_string(),
+            string("ISO 8601 granularity of the points, such as PT1M or PT1H. Default PT5M."),
+        ),
+        (
+            "aggregation".to_string(),
+            string("Comma-separated aggregations among Average, Minimum, Maximum, Total and Count. Default Average,Maximum."),
+        ),
+    ]);
+    let alerts = BTreeMap::from([
+        ("subscription_id".to_string(), subscription_id()),
+        (
+            "resource_group".to_string(),
+            string("Only alerts on resources in this resource group."),
+        ),
+        (
+            "resource_id".to_string(),
+            string("Only alerts on this resource."),
+        ),
+        (
+            "time_range".to_string(),
+            string("How far back to look: 1h, 1d, 7d or 30d. Default 1d."),
+        ),
+        (
+            "include_resolved".to_string(),
+            JsonSchema::Boolean {
+                description: Some(
+                    "Include alerts that have since resolved. Default false.".to_string(),
+                ),
+            },
+        ),
+    ]);
+    let activity_log = BTreeMap::from([
+        ("subscription_id".to_string(), subscription_id()),
+        (
+            "start".to_string(),
+            string("RFC 3339 start of the range. Default one day before end."),
+        ),
+        (
+            "end".to_string(),
+            string("RFC 3339 end of the range. Default now."),
+        ),
+        (
+            "resource_group".to_string(),
+            string("Only events in this resource group."),
+        ),
+        (
+            "resource_id".to_string(),
+            string("Only events on this resource."),
+        ),
+    ]);
+
+    vec![
+        ResponsesApiTool {
+            name: AZURE_MONITOR_METRICS_TOOL_NAME.to_string(),
+            description: "Reads Azure Monitor platform metrics of a resource, such as CPU, memory \
+                or request rates, as time series with their peak. Use it to see when a resource \
+                started misbehaving."
+                .to_string(),
+            strict: false,
+            parameters: object(metrics, &["resource_id", "metrics"]),
+        },
+        ResponsesApiTool {
+            name: AZURE_MONITOR_ALERTS_TOOL_NAME.to_string(),
+            description: "Lists the Azure Monitor alerts that fired in a subscription, newest \
+                first, with their severity, state and target resource."
+                .to_string(),
+            strict: false,
+            parameters: object(alerts, &[]),
+        },
+        ResponsesApiTool {
+            name: AZURE_MONITOR_ACTIVITY_LOG_TOOL_NAME.to_string(),
+            description: "Lists the Azure activity log events of a subscription in a time range, \
+                such as deployments, restarts and configuration changes, with who made them. Use \
+                it to find what changed before an incident."
+                .to_string(),
+            strict: false,
+            parameters: object(activity_log, &[]),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::config_types::AzureAuthConfig;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::TempDir;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+    use wiremock::matchers::query_param;
+
+    fn monitor(server: &MockServer, codex_home: &TempDir, max_results: usize) -> AzureMonitor {
+        let scope = format!("{}/.default", server.uri());
+        let azure_auth = AzureAuth::new(AzureAuthConfig::default(), codex_home.path())
+            .with_token_for_tests(&scope, "arm-token");
+        AzureMonitor::new(
+            AzureMonitorConfig {
+                subscription_id: Some("sub-1".to_string()),
+                resource_manager: server.uri(),
+                max_results,
+            },
+            Arc::new(azure_auth),
+        )
+    }
+
+    #[tokio::test]
+    async fn reads_metrics_of_a_resource() {
+        let server = MockServer::start().await;
+        let codex_home = TempDir::new().unwrap();
+        Mock::given(method("GET"))
+            .and(path(
+                "/subscriptions/sub-1/resourceGroups/rg/providers/Microsoft.Compute/virtualMachines/vm1/providers/Microsoft.Insights/metrics",
+            ))
+            .and(header("authorization", "Bearer arm-token"))
+            .and(query_param("metricnames", "Percentage CPU"))
+            .and(query_param("timespan", "PT1H"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "timespan": "2024-05-01T08:00:00Z/2024-05-01T09:00:00Z",
+                "value": [{
+                    "name": { "value": "Percentage CPU" },
+                    "unit": "Percent",
+                    "timeseries": [{
+                        "metadatavalues": [],
+                        "data": [
+                            { "timeStamp": "2024-05-01T08:00:00Z", "average": 12.5, "maximum": 40.0 },
+                            { "timeStamp": "2024-05-01T08:05:00Z", "average": 85.25, "maximum": 99.5 },
+                            { "timeStamp": "2024-05-01T08:10:00Z" },
+                        ],
+                    }],
+                }],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let output = monitor(&server, &codex_home, 50)
+            .handle_function_call(
+                AZURE_MONITOR_METRICS_TOOL_NAME,
+                &json!({
+                    "resource_id": "subscriptions/sub-1/resourceGroups/rg/providers/Microsoft.Compute/virtualMachines/vm1",
+                    "metrics": ["Percentage CPU"],
+                })
+                .to_string(),
+            )
+            .await;
+
+        assert_eq!(output.success, Some(true), "{}", output.content);
+        assert_eq!(
+            output.content,
+            "Timespan: 2024-05-01T08:00:00Z/2024-05-01T09:00:00Z\n\
+             \n\
+             Percentage CPU (Percent)\n  \
+             2024-05-01T08:00:00Z  average=12.5 maximum=40\n  \
+             2024-05-01T08:05:00Z  average=85.25 maximum=99.5\n  \
+             peak 99.5 at 2024-05-01T08:05:00Z\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn follows_activity_log_pages_up_to_the_limit() {
+        let server = MockServer::start().await;
+        let codex_home = TempDir::new().unwrap();
+        let event = |operation: &str| {
+            json!({
+                "eventTimestamp": "2024-05-01T08:30:00Z",
+                "operationName": { "value": operation },
+                "status": { "value": "Succeeded" },
+                "caller": "ops@contoso.com",
+                "resourceId": "/subscriptions/sub-1/resourceGroups/rg/providers/Microsoft.Web/sites/app",
+                "level": "Informational",
+            })
+        };
+        let events_path =
+            "/subscriptions/sub-1/providers/Microsoft.Insights/eventtypes/management/values";
+        Mock::given(method("GET"))
+            .and(path(events_path))
+            .and(query_param(
+                "$filter",
+                "eventTimestamp ge '2024-05-01T00:00:00Z' and eventTimestamp le '2024-05-02T00:00:00Z' and resourceGroupName eq 'o''brien'",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "value": [event("Microsoft.Web/sites/restart/action")],
+                "nextLink": format!("{}{events_path}?page=2", server.uri()),
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(events_path))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "value": [event("Microsoft.Web/sites/write"), event("Microsoft.Web/sites/stop/action")],
+                "nextLink": format!("{}{events_path}?page=3", server.uri()),
+            })))
+            .mount(&server)
+            .await;
+
+        let output = monitor(&server, &codex_home, 2)
+            .handle_function_call(
+                AZURE_MONITOR_ACTIVITY_LOG_TOOL_NAME,
+                &json!({
+                    "start": "2024-05-01T00:00:00Z",
+                    "end": "2024-05-02T00:00:00+00:00",
+                    "resource_group": "o'brien",
+                })
+                .to_string(),
+            )
+            .await;
+
+        assert_eq!(output.success, Some(true), "{}", output.content);
+        let lines: Vec<&str> = output.content.lines().collect();
+        assert_eq!(lines[0], "2 activity log events, newest first:");
+        assert_eq!(
+            lines[1],
+            "2024-05-01T08:30:00Z  Informational  Succeeded  Microsoft.Web/sites/restart/action  \
+             by ops@contoso.com  on /subscriptions/sub-1/resourceGroups/rg/providers/Microsoft.Web/sites/app"
+        );
+        assert!(lines[2].contains("Microsoft.Web/sites/write"));
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn reports_arm_errors_and_bad_arguments() {
+        let server = MockServer::start().await;
+        let codex_home = TempDir::new().unwrap();
+        Mock::given(method("GET"))
+            .and(path(
+                "/subscriptions/sub-2/providers/Microsoft.AlertsManagement/alerts",
+            ))
+            .and(query_param("monitorCondition", "Fired"))
+            .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+                "error": { "code": "AuthorizationFailed", "message": "no access to sub-2" },
+            })))
+            .mount(&server)
+            .await;
+        let monitor = monitor(&server, &codex_home, 50);
+
+        let output = monitor
+            .handle_function_call(
+                AZURE_MONITOR_ALERTS_TOOL_NAME,
+                &json!({ "subscription_id": "sub-2" }).to_string(),
+            )
+            .await;
+        assert_eq!(output.success, Some(false));
+        assert_eq!(
+            output.content,
+            "Azure Monitor request failed (403 Forbidden): AuthorizationFailed: no access to sub-2"
+        );
+
+        let output = monitor
+            .handle_function_call(
+                AZURE_MONITOR_ALERTS_TOOL_NAME,
+                &json!({ "time_range": "2d" }).to_string(),
+            )
+            .await;
+        assert_eq!(output.content, "time_range must be one of 1h, 1d, 7d, 30d");
+
+        let output = monitor
+            .handle_function_call(
+                AZURE_MONITOR_ACTIVITY_LOG_TOOL_NAME,
+                &json!({ "start": "yesterday" }).to_string(),
+            )
+            .await;
+        assert_eq!(output.success, Some(false));
+        assert!(output.content.starts_with("start must be an RFC 3339 time"));
+    }
+
+    #[test]
+    fn formats_fired_alerts() {
+        let alerts: Vec<Alert> = serde_json::from_value(json!([{
+            "name": "High CPU on vm1",
+            "properties": { "essentials": {
+                "severity": "Sev1",
+                "monitorCondition": "Fired",
+                "alertState": "New",
+                "startDateTime": "2024-05-01T08:07:00Z",
+                "targetResourceName": "vm1",
+                "targetResourceType": "virtualmachines",
+                "monitorService": "Platform",
+                "description": "CPU above 90% for 5 minutes",
+            }},
+        }]))
+        .unwrap();
+        assert_eq!(
+            format_alerts("Alerts fired in the last 1d", &alerts),
+            "Alerts fired in the last 1d: 1\n\
+             \n\
+             Sev1 Fired (New): High CPU on vm1\n  \
+             started 2024-05-01T08:07:00Z, on vm1 (virtualmachines), from Platform\n  \
+             CPU above 90% for 5 minutes\n"
+        );
+        assert_eq!(
+            format_alerts("Alerts fired in the last 1h", &[]),
+            "Alerts fired in the last 1h: none."
+        );
+    }
+}
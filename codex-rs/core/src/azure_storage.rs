@@ -0,0 +1,1112 @@
+//! Azure Storage blob tools: list containers and blobs, read small blobs,
+//! upload local files as blobs and hand out SAS URLs. They call the blob
+//! service with the shared Azure login, so restore files can be staged and
+//! build artifacts published without storage account keys. Uploads and SAS
+//! URLs that grant more than read access ask for approval first.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::Arc;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+use codex_protocol::models::FunctionCallOutputPayload;
+use reqwest::Url;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use serde_json::json;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::azure_auth::AzureAuth;
+use crate::config_types::AzureStorageConfig;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+
+pub(crate) const AZURE_STORAGE_LIST_CONTAINERS_TOOL_NAME: &str = "azure_storage_list_containers";
+pub(crate) const AZURE_STORAGE_LIST_BLOBS_TOOL_NAME: &str = "azure_storage_list_blobs";
+pub(crate) const AZURE_STORAGE_READ_BLOB_TOOL_NAME: &str = "azure_storage_read_blob";
+pub(crate) const AZURE_STORAGE_UPLOAD_BLOB_TOOL_NAME: &str = "azure_storage_upload_blob";
+pub(crate) const AZURE_STORAGE_GENERATE_SAS_TOOL_NAME: &str = "azure_storage_generate_sas";
+
+const STORAGE_SCOPE: &str = "https://storage.azure.com/.default";
+const STORAGE_API_VERSION: &str = "2021-08-06";
+
+/// SAS permissions in the order the service expects them.
+const SAS_PERMISSIONS: &str = "racwdl";
+
+/// Permissions a SAS URL may grant without approval.
+const READ_ONLY_SAS_PERMISSIONS: &str = "rl";
+
+pub(crate) struct AzureStorage {
+    config: AzureStorageConfig,
+    azure_auth: Arc<AzureAuth>,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListContainersParams {
+    #[serde(default)]
+    account: Option<String>,
+    #[serde(default)]
+    prefix: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListBlobsParams {
+    #[serde(default)]
+    account: Option<String>,
+    container: String,
+    #[serde(default)]
+    prefix: Option<String>,
+    #[serde(default)]
+    recursive: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReadBlobParams {
+    #[serde(default)]
+    account: Option<String>,
+    container: String,
+    blob: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadBlobParams {
+    #[serde(default)]
+    account: Option<String>,
+    container: String,
+    blob: String,
+    path: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GenerateSasParams {
+    #[serde(default)]
+    account: Option<String>,
+    container: String,
+    #[serde(default)]
+    blob: Option<String>,
+    #[serde(default)]
+    permissions: Option<String>,
+    #[serde(default)]
+    expiry_minutes: Option<i64>,
+}
+
+/// A storage account and its blob service endpoint.
+struct Account {
+    name: String,
+    url: Url,
+}
+
+/// The key that signs user delegation SAS tokens.
+#[derive(Debug, PartialEq)]
+struct UserDelegationKey {
+    signed_oid: String,
+    signed_tid: String,
+    signed_start: String,
+    signed_expiry: String,
+    signed_service: String,
+    signed_version: String,
+    value: String,
+}
+
+/// A listed blob or virtual directory.
+#[derive(Debug, PartialEq)]
+struct BlobEntry {
+    name: String,
+    /// `None` for virtual directories.
+    size: Option<u64>,
+    last_modified: Option<String>,
+}
+
+impl AzureStorage {
+    pub(crate) fn new(config: AzureStorageConfig, azure_auth: Arc<AzureAuth>) -> Self {
+        Self {
+            config,
+            azure_auth,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Answers a call to one of the Azure Storage tools. Approval of uploads
+    /// and writable SAS URLs, see [`write_request`], is up to the caller.
+    pub(crate) async fn handle_function_call(
+        &self,
+        name: &str,
+        arguments: &str,
+        cwd: &Path,
+    ) -> FunctionCallOutputPayload {
+        let result = match name {
+            AZURE_STORAGE_LIST_CONTAINERS_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.list_containers(params).await,
+                Err(e) => Err(e),
+            },
+            AZURE_STORAGE_LIST_BLOBS_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.list_blobs(params).await,
+                Err(e) => Err(e),
+            },
+            AZURE_STORAGE_READ_BLOB_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.read_blob(params).await,
+                Err(e) => Err(e),
+            },
+            AZURE_STORAGE_UPLOAD_BLOB_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.upload_blob(params, cwd).await,
+                Err(e) => Err(e),
+            },
+            AZURE_STORAGE_GENERATE_SAS_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.generate_sas(params).await,
+                Err(e) => Err(e),
+            },
+            _ => Err(format!("unknown Azure Storage tool `{name}`")),
+        };
+        match result {
+            Ok(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(true),
+            },
+            Err(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(false),
+            },
+        }
+    }
+
+    async fn list_containers(&self, params: ListContainersParams) -> Result<String, String> {
+        let account = self.account(params.account.as_deref())?;
+        let mut query = vec![("comp", "list".to_string())];
+        if let Some(prefix) = &params.prefix {
+            query.push(("prefix", prefix.clone()));
+        }
+        let pages = self.list(account.url.clone(), query).await?;
+        let mut containers = Vec::new();
+        for page in &pages {
+            for container in xml_elements(page, "Container") {
+                containers.push((
+                    xml_text(container, "Name").unwrap_or_default(),
+                    xml_text(container, "Last-Modified"),
+                ));
+            }
+        }
+        containers.truncate(self.config.max_results);
+        if containers.is_empty() {
+            return Ok(format!("No containers in {}.", account.name));
+        }
+        let mut out = format!("{} containers in {}:\n", containers.len(), account.name);
+        for (name, last_modified) in containers {
+            let _ = write!(out, "  {name}");
+            if let Some(last_modified) = last_modified {
+                let _ = write!(out, "  modified {last_modified}");
+            }
+            out.push('\n');
+        }
+        Ok(out)
+    }
+
+    async fn list_blobs(&self, params: ListBlobsParams) -> Result<String, String> {
+        let account = self.account(params.account.as_deref())?;
+        let url = blob_url(&account, &params.container, None)?;
+        let mut query = vec![
+            ("restype", "container".to_string()),
+            ("comp", "list".to_string()),
+        ];
+        if let Some(prefix) = &params.prefix {
+            query.push(("prefix", prefix.clone()));
+        }
+        if !params.recursive {
+            query.push(("delimiter", "/".to_string()));
+        }
+        let pages = self.list(url, query).await?;
+        let mut entries: Vec<BlobEntry> = pages.iter().flat_map(|page| parse_blobs(page)).collect();
+        let truncated = entries.len() > self.config.max_results;
+        entries.truncate(self.config.max_results);
+        Ok(format_blobs(
+            &params.container,
+            params.prefix.as_deref(),
+            &entries,
+            truncated,
+        ))
+    }
+
+    async fn read_blob(&self, params: ReadBlobParams) -> Result<String, String> {
+        let account = self.account(params.account.as_deref())?;
+        let url = blob_url(&account, &params.container, Some(&params.blob))?;
+        let properties = self.send(self.client.head(url.clone())).await?;
+        let size = properties
+            .headers()
+            .get("content-length")
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse::<u64>().ok())
+            .unwrap_or(0);
+        if size == 0 {
+            return Ok(format!("{} is empty.", params.blob));
+        }
+        let max_bytes = self.config.max_read_bytes;
+        let end = size.min(max_bytes) - 1;
+        let response = self
+            .send(
+                self.client
+                    .get(url)
+                    .header("x-ms-range", format!("bytes=0-{end}")),
+            )
+            .await?;
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| format!("failed to read {}: {e}", params.blob))?;
+        let Ok(text) = String::from_utf8(bytes.to_vec()) else {
+            return Err(format!(
+                "{} is binary ({size} bytes); use azure_storage_generate_sas to download it",
+                params.blob
+            ));
+        };
+        if size > max_bytes {
+            Ok(format!(
+                "{text}\n[showing the first {max_bytes} of {size} bytes of {}]",
+                params.blob
+            ))
+        } else {
+            Ok(text)
+        }
+    }
+
+    async fn upload_blob(&self, params: UploadBlobParams, cwd: &Path) -> Result<String, String> {
+        let account = self.account(params.account.as_deref())?;
+        let url = blob_url(&account, &params.container, Some(&params.blob))?;
+        let path = cwd.join(&params.path);
+        let size = tokio::fs::metadata(&path)
+            .await
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?
+            .len();
+        if size > self.config.max_upload_bytes {
+            return Err(format!(
+                "{} is {size} bytes, over the {}-byte upload limit",
+                path.display(),
+                self.config.max_upload_bytes
+            ));
+        }
+        let body = tokio::fs::read(&path)
+            .await
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let content_type = mime_guess::from_path(&params.blob)
+            .first_or_octet_stream()
+            .to_string();
+        self.send(
+            self.client
+                .put(url.clone())
+                .header("x-ms-blob-type", "BlockBlob")
+                .header("content-type", content_type)
+                .body(body),
+        )
+        .await?;
+        Ok(format!(
+            "Uploaded {} ({size} bytes) to {url}",
+            path.display()
+        ))
+    }
+
+    async fn generate_sas(&self, params: GenerateSasParams) -> Result<String, String> {
+        let account = self.account(params.account.as_deref())?;
+        let permissions = sas_permissions(params.permissions.as_deref())?;
+        let expiry_minutes = params.expiry_minutes.unwrap_or(60);
+        if expiry_minutes <= 0 || expiry_minutes > self.config.max_sas_expiry_minutes {
+            return Err(format!(
+                "expiry_minutes must be between 1 and {}",
+                self.config.max_sas_expiry_minutes
+            ));
+        }
+        // Start a little in the past to allow for clock skew.
+        let start = Utc::now() - Duration::minutes(5);
+        let expiry = Utc::now() + Duration::minutes(expiry_minutes);
+        let key = self.user_delegation_key(&account, start, expiry).await?;
+
+        let mut url = blob_url(&account, &params.container, params.blob.as_deref())?;
+        let resource = SasResource {
+            account: &account.name,
+            container: &params.container,
+            blob: params.blob.as_deref(),
+        };
+        let query = sas_query(&key, &resource, &permissions, start, expiry)?;
+        url.query_pairs_mut().extend_pairs(&query);
+        Ok(format!(
+            "SAS URL with permissions `{permissions}`, valid until {}:\n{url}",
+            format_time(expiry)
+        ))
+    }
+
+    async fn user_delegation_key(
+        &self,
+        account: &Account,
+        start: DateTime<Utc>,
+        expiry: DateTime<Utc>,
+    ) -> Result<UserDelegationKey, String> {
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+             <KeyInfo><Start>{}</Start><Expiry>{}</Expiry></KeyInfo>",
+            format_time(start),
+            format_time(expiry)
+        );
+        let response = self
+            .send(
+                self.client
+                    .post(account.url.clone())
+                    .query(&[("restype", "service"), ("comp", "userdelegationkey")])
+                    .body(body),
+            )
+            .await?;
+        let xml = response
+            .text()
+            .await
+            .map_err(|e| format!("failed to read the user delegation key: {e}"))?;
+        parse_user_delegation_key(&xml)
+    }
+
+    /// The response bodies of a list, following `NextMarker` up to
+    /// `max_results` items.
+    async fn list(&self, url: Url, query: Vec<(&str, String)>) -> Result<Vec<String>, String> {
+        let mut pages = Vec::new();
+        let mut marker: Option<String> = None;
+        let mut items = 0;
+        loop {
+            let mut page_query = query.clone();
+            page_query.push(("maxresults", self.config.max_results.to_string()));
+            if let Some(marker) = marker.take() {
+                page_query.push(("marker", marker));
+            }
+            let response = self
+                .send(self.client.get(url.clone()).query(&page_query))
+                .await?;
+            let page = response
+                .text()
+                .await
+                .map_err(|e| format!("failed to read the Azure Storage response: {e}"))?;
+            items += xml_elements(&page, "Name").len();
+            marker = xml_text(&page, "NextMarker").filter(|marker| !marker.is_empty());
+            pages.push(page);
+            if items > self.config.max_results || marker.is_none() {
+                break;
+            }
+        }
+        Ok(pages)
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let token = self
+            .azure_auth
+            .get_token(STORAGE_SCOPE)
+            .await
+            .map_err(|e| format!("failed to get an Azure token: {e}"))?;
+        let response = request
+            .bearer_auth(token.token)
+            .header("x-ms-version", STORAGE_API_VERSION)
+            .header(
+                "x-ms-date",
+                Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+            )
+            .send()
+            .await
+            .map_err(|e| format!("failed to reach Azure Storage: {e}"))?;
+        let status = response.status();
+        if !status.is_success() {
+            // HEAD responses carry the error code in a header only.
+            let code = response
+                .headers()
+                .get("x-ms-error-code")
+                .and_then(|code| code.to_str().ok())
+                .map(str::to_string);
+            let body = response.text().await.unwrap_or_default();
+            let detail = match (xml_text(&body, "Code").or(code), xml_text(&body, "Message")) {
+                (Some(code), Some(message)) => format!("{code}: {}", first_line(&message)),
+                (Some(code), None) => code,
+                (None, _) => body,
+            };
+            return Err(format!("Azure Storage request failed ({status}): {detail}"));
+        }
+        Ok(response)
+    }
+
+    /// The account named in a call, or the configured default.
+    fn account(&self, requested: Option<&str>) -> Result<Account, String> {
+        let account = requested
+            .or(self.config.account.as_deref())
+            .ok_or_else(|| "no account: pass account or set azure_storage.account".to_string())?
+            .trim();
+        if account.starts_with("https://") || account.starts_with("http://") {
+            let url = Url::parse(account).map_err(|e| format!("invalid account URL: {e}"))?;
+            let name = url
+                .host_str()
+                .and_then(|host| host.split('.').next())
+                .unwrap_or_default()
+                .to_string();
+            return Ok(Account { name, url });
+        }
+        let valid = (3..=24).contains(&account.len())
+            && account
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+        if !valid {
+            return Err(format!(
+                "`{account}` is not a storage account name; pass a name such as contosobackups or \
+                 the blob endpoint URL"
+            ));
+        }
+        let url = Url::parse(&format!(
+            "https://{account}.{}",
+            self.config.blob_endpoint_suffix
+        ))
+        .map_err(|e| format!("invalid blob endpoint: {e}"))?;
+        Ok(Account {
+            name: account.to_string(),
+            url,
+        })
+    }
+}
+
+/// The request `name` would make that needs approval: uploads and SAS URLs
+/// granting more than read access. `None` for the other calls.
+pub(crate) fn write_request(name: &str, arguments: &str) -> Option<Value> {
+    match name {
+        AZURE_STORAGE_UPLOAD_BLOB_TOOL_NAME => {
+            let params: UploadBlobParams = parse(arguments).ok()?;
+            Some(json!({
+                "method": "PUT",
+                "account": params.account,
+                "container": params.container,
+                "blob": params.blob,
+                "path": params.path,
+            }))
+        }
+        AZURE_STORAGE_GENERATE_SAS_TOOL_NAME => {
+            let params: GenerateSasParams = parse(arguments).ok()?;
+            let permissions = sas_permissions(params.permissions.as_deref()).ok()?;
+            if permissions
+                .chars()
+                .all(|p| READ_ONLY_SAS_PERMISSIONS.contains(p))
+            {
+                return None;
+            }
+            Some(json!({
+                "sas": permissions,
+                "account": params.account,
+                "container": params.container,
+                "blob": params.blob,
+                "expiry_minutes": params.expiry_minutes,
+            }))
+        }
+        _ => None,
+    }
+}
+
+fn parse<T: DeserializeOwned>(arguments: &str) -> Result<T, String> {
+    serde_json::from_str(arguments).map_err(|e| format!("failed to parse function arguments: {e}"))
+}
+
+/// The URL of a container, or of a blob in it. Slashes in blob names are
+/// kept as path separators.
+fn blob_url(account: &Account, container: &str, blob: Option<&str>) -> Result<Url, String> {
+    if container.is_empty() || container.contains('/') {
+        return Err(format!("`{container}` is not a container name"));
+    }
+    let mut url = account.url.clone();
+    {
+        let mut segments = url
+            .path_segments_mut()
+            .map_err(|()| format!("{} cannot hold blobs", account.url))?;
+        segments.pop_if_empty().push(container);
+        if let Some(blob) = blob {
+            segments.extend(blob.split('/'));
+        }
+    }
+    Ok(url)
+}
+
+/// `permissions` in service order, read-only when not given.
+fn sas_permissions(permissions: Option<&str>) -> Result<String, String> {
+    let permissions = permissions.unwrap_or("r");
+    if let Some(unknown) = permissions.chars().find(|p| !SAS_PERMISSIONS.contains(*p)) {
+        return Err(format!(
+            "unknown SAS permission `{unknown}`; use letters of {SAS_PERMISSIONS}"
+        ));
+    }
+    let ordered: String = SAS_PERMISSIONS
+        .chars()
+        .filter(|p| permissions.contains(*p))
+        .collect();
+    if ordered.is_empty() {
+        return Err("name at least one SAS permission".to_string());
+    }
+    Ok(ordered)
+}
+
+/// What a SAS token grants access to.
+struct SasResource<'a> {
+    account: &'a str,
+    container: &'a str,
+    blob: Option<&'a str>,
+}
+
+/// The query parameters of a user delegation SAS.
+fn sas_query(
+    key: &UserDelegationKey,
+    resource: &SasResource<'_>,
+    permissions: &str,
+    start: DateTime<Utc>,
+    expiry: DateTime<Utc>,
+) -> Result<Vec<(&'static str, String)>, String> {
+    let start = format_time(start);
+    let expiry = format_time(expiry);
+    let signed_resource = if resource.blob.is_some() { "b" } else { "c" };
+    let string_to_sign = sas_string_to_sign(key, resource, permissions, &start, &expiry);
+    let key_bytes = BASE64
+        .decode(&key.value)
+        .map_err(|e| format!("invalid user delegation key: {e}"))?;
+    let signature = BASE64.encode(hmac_sha256(&key_bytes, string_to_sign.as_bytes()));
+    Ok(vec![
+        ("sp", permissions.to_string()),
+        ("st", start),
+        ("se", expiry),
+        ("skoid", key.signed_oid.clone()),
+        ("sktid", key.signed_tid.clone()),
+        ("skt", key.signed_start.clone()),
+        ("ske", key.signed_expiry.clone()),
+        ("sks", key.signed_service.clone()),
+        ("skv", key.signed_version.clone()),
+        ("spr", "https".to_string()),
+        ("sv", STORAGE_API_VERSION.to_string()),
+        ("sr", signed_resource.to_string()),
+        ("sig", signature),
+    ])
+}
+
+/// The string a user delegation SAS signs, for service version 2020-12-06
+/// and later.
+fn sas_string_to_sign(
+    key: &UserDelegationKey,
+    resource: &SasResource<'_>,
+    permissions: &str,
+    start: &str,
+    expiry: &str,
+) -> String {
+    let mut canonicalized = format!("/blob/{}/{}", resource.account, resource.container);
+    if let Some(blob) = resource.blob {
+        let _ = write!(canonicalized, "/{blob}");
+    }
+    let signed_resource = if resource.blob.is_some() { "b" } else { "c" };
+    [
+        permissions,
+        start,
+        expiry,
+        canonicalized.as_str(),
+        key.signed_oid.as_str(),
+        key.signed_tid.as_str(),
+        key.signed_start.as_str(),
+        key.signed_expiry.as_str(),
+        key.signed_service.as_str(),
+        key.signed_version.as_str(),
+        // Authorized and unauthorized user object ids, correlation id.
+        "",
+        "",
+        "",
+        // Allowed IPs.
+        "",
+        "https",
+        STORAGE_API_VERSION,
+        signed_resource,
+        // Snapshot time and encryption scope.
+        "",
+        "",
+        // Response header overrides.
+        "",
+        "",
+        "",
+        "",
+        "",
+    ]
+    .join("\n")
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.map(|b| b ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+fn parse_user_delegation_key(xml: &str) -> Result<UserDelegationKey, String> {
+    let field = |tag: &str| {
+        xml_text(xml, tag).ok_or_else(|| format!("user delegation key response has no {tag}"))
+    };
+    Ok(UserDelegationKey {
+        signed_oid: field("SignedOid")?,
+        signed_tid: field("SignedTid")?,
+        signed_start: field("SignedStart")?,
+        signed_expiry: field("SignedExpiry")?,
+        signed_service: field("SignedService")?,
+        signed_version: field("SignedVersion")?,
+        value: field("Value")?,
+    })
+}
+
+fn parse_blobs(xml: &str) -> Vec<BlobEntry> {
+    let prefixes = xml_elements(xml, "BlobPrefix")
+        .into_iter()
+        .map(|prefix| BlobEntry {
+            name: xml_text(prefix, "Name").unwrap_or_default(),
+            size: None,
+            last_modified: None,
+        });
+    let blobs = xml_elements(xml, "Blob").into_iter().map(|blob| BlobEntry {
+        name: xml_text(blob, "Name").unwrap_or_default(),
+        size: xml_text(blob, "Content-Length").and_then(|size| size.parse().ok()),
+        last_modified: xml_text(blob, "Last-Modified"),
+    });
+    prefixes.chain(blobs).collect()
+}
+
+fn format_blobs(
+    container: &str,
+    prefix: Option<&str>,
+    entries: &[BlobEntry],
+    truncated: bool,
+) -> String {
+    let location = match prefix {
+        Some(prefix) => format!("{container} under `{prefix}`"),
+        None => container.to_string(),
+    };
+    if entries.is_empty() {
+        return format!("No blobs in {location}.");
+    }
+    let mut out = format!("{} entries in {location}:\n", entries.len());
+    for entry in entries {
+        match entry.size {
+            Some(size) => {
+                let _ = write!(out, "  {}  {size} bytes", entry.name);
+                if let Some(last_modified) = &entry.last_modified {
+                    let _ = write!(out, "  modified {last_modified}");
+                }
+                out.push('\n');
+            }
+            None => {
+                let _ = writeln!(out, "  {}  (directory)", entry.name);
+            }
+        }
+    }
+    if truncated {
+        out.push_str("More entries exist; narrow the prefix to see them.\n");
+    }
+    out
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+/// The contents of each `<tag>` element in `xml`, in order. Elements of the
+/// same name must not nest, which holds for the blob service responses.
+fn xml_elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{tag}");
+    let close = format!("</{tag}>");
+    let mut elements = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after_name = &rest[start + open.len()..];
+        // Skip longer names that start with `tag`, such as `Blobs` for `Blob`.
+        if !after_name.starts_with(['>', ' ']) {
+            rest = after_name;
+            continue;
+        }
+        let Some(content_start) = after_name.find('>') else {
+            break;
+        };
+        if after_name[..content_start].ends_with('/') {
+            elements.push("");
+            rest = &after_name[content_start + 1..];
+            continue;
+        }
+        let content = &after_name[content_start + 1..];
+        let Some(end) = content.find(&close) else {
+            break;
+        };
+        elements.push(&content[..end]);
+        rest = &content[end + close.len()..];
+    }
+    elements
+}
+
+/// The unescaped text of the first `<tag>` element in `xml`.
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    xml_elements(xml, tag).first().map(|text| {
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    })
+}
+
+pub(crate) fn create_azure_storage_tools() -> Vec<ResponsesApiTool> {
+    let string = |description: &str| JsonSchema::String {
+        description: Some(description.to_string()),
+    };
+    let object = |properties: BTreeMap<String, JsonSchema>, required: &[&str]| JsonSchema::Object {
+        properties,
+        required: Some(required.iter().map(|r| r.to_string()).collect()),
+        additional_properties: Some(false),
+    };
+    let account = || {
+        string(
+            "Storage account name or blob endpoint URL; defaults to the configured \
+             azure_storage.account.",
+        )
+    };
+    let container = || string("Container name.");
+    let blob = || string("Blob name, such as backups/2024-05-01/db.bak.");
+
+    let list_containers = BTreeMap::from([
+        ("account".to_string(), account()),
+        (
+            "prefix".to_string(),
+            string("Only containers whose name starts with this."),
+        ),
+    ]);
+    let list_blobs = BTreeMap::from([
+        ("account".to_string(), account()),
+        ("container".to_string(), container()),
+        (
+            "prefix".to_string(),
+            string("Only blobs whose name starts with this, such as backups/2024-05-."),
+        ),
+        (
+            "recursive".to_string(),
+            JsonSchema::Boolean {
+                description: Some(
+                    "List every blob under the prefix instead of one directory level. Default false."
+                        .to_string(),
+                ),
+            },
+        ),
+    ]);
+    let read_blob = BTreeMap::from([
+        ("account".to_string(), account()),
+        ("container".to_string(), container()),
+        ("blob".to_string(), blob()),
+    ]);
+    let upload_blob = BTreeMap::from([
+        ("account".to_string(), account()),
+        ("container".to_string(), container()),
+        ("blob".to_string(), blob()),
+        (
+            "path".to_string(),
+            string("Local file to upload, relative to the working directory."),
+        ),
+    ]);
+    let generate_sas = BTreeMap::from([
+        ("account".to_string(), account()),
+        ("container".to_string(), container()),
+        (
+            "blob".to_string(),
+            string("Blob the URL points at; the whole container when omitted."),
+        ),
+        (
+            "permissions".to_string(),
+            string("Letters of racwdl: read, add, create, write, delete, list. Default r."),
+        ),
+        (
+            "expiry_minutes".to_string(),
+            JsonSchema::Number {
+                description: Some("How long the URL is valid. Default 60.".to_string()),
+            },
+        ),
+    ]);
+
+    vec![
+        ResponsesApiTool {
+            name: AZURE_STORAGE_LIST_CONTAINERS_TOOL_NAME.to_string(),
+            description: "Lists the blob containers of an Azure Storage account.".to_string(),
+            strict: false,
+            parameters: object(list_containers, &[]),
+        },
+        ResponsesApiTool {
+            name: AZURE_STORAGE_LIST_BLOBS_TOOL_NAME.to_string(),
+            description: "Lists the blobs of a container with their size and last \
+                modification, one directory level at a time unless recursive."
+                .to_string(),
+            strict: false,
+            parameters: object(list_blobs, &["container"]),
+        },
+        ResponsesApiTool {
+            name: AZURE_STORAGE_READ_BLOB_TOOL_NAME.to_string(),
+            description: "Reads a small text blob, such as a manifest or log. Large blobs are \
+                cut at a size limit and binary blobs are refused."
+                .to_string(),
+            strict: false,
+            parameters: object(read_blob, &["container", "blob"]),
+        },
+        ResponsesApiTool {
+            name: AZURE_STORAGE_UPLOAD_BLOB_TOOL_NAME.to_string(),
+            description: "Uploads a local file as a blob, replacing any blob of that name. \
+                Asks the user for approval."
+                .to_string(),
+            strict: false,
+            parameters: object(upload_blob, &["container", "blob", "path"]),
+        },
+        ResponsesApiTool {
+            name: AZURE_STORAGE_GENERATE_SAS_TOOL_NAME.to_string(),
+            description: "Creates a time-limited SAS URL for a blob or container, signed with \
+                the user's Azure login. URLs granting more than read and list access ask the \
+                user for approval."
+                .to_string(),
+            strict: false,
+            parameters: object(generate_sas, &["container"]),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::config_types::AzureAuthConfig;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::body_string;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+    use wiremock::matchers::query_param;
+
+    fn storage(server: &MockServer, codex_home: &TempDir, max_read_bytes: u64) -> AzureStorage {
+        let azure_auth = AzureAuth::new(AzureAuthConfig::default(), codex_home.path())
+            .with_token_for_tests(STORAGE_SCOPE, "storage-token");
+        AzureStorage::new(
+            AzureStorageConfig {
+                account: Some(server.uri()),
+                blob_endpoint_suffix: "blob.core.windows.net".to_string(),
+                max_results: 100,
+                max_read_bytes,
+                max_upload_bytes: 1024,
+                max_sas_expiry_minutes: 1440,
+            },
+            Arc::new(azure_auth),
+        )
+    }
+
+    #[tokio::test]
+    async fn lists_blobs_one_level_under_a_prefix() {
+        let server = MockServer::start().await;
+        let codex_home = TempDir::new().unwrap();
+        Mock::given(method("GET"))
+            .and(path("/backups"))
+            .and(header("authorization", "Bearer storage-token"))
+            .and(header("x-ms-version", STORAGE_API_VERSION))
+            .and(query_param("comp", "list"))
+            .and(query_param("prefix", "db/"))
+            .and(query_param("delimiter", "/"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <EnumerationResults ContainerName=\"backups\"><Prefix>db/</Prefix><Blobs>\
+                 <Blob><Name>db/full&amp;diff.bak</Name><Properties>\
+                 <Last-Modified>Wed, 01 May 2024 02:00:00 GMT</Last-Modified>\
+                 <Content-Length>1048576</Content-Length></Properties></Blob>\
+                 <BlobPrefix><Name>db/logs/</Name></BlobPrefix>\
+                 </Blobs><NextMarker /></EnumerationResults>",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let output = storage(&server, &codex_home, 1024)
+            .handle_function_call(
+                AZURE_STORAGE_LIST_BLOBS_TOOL_NAME,
+                &json!({ "container": "backups", "prefix": "db/" }).to_string(),
+                Path::new("/"),
+            )
+            .await;
+
+        assert_eq!(output.success, Some(true), "{}", output.content);
+        assert_eq!(
+            output.content,
+            "2 entries in backups under `db/`:\n  \
+             db/logs/  (directory)\n  \
+             db/full&diff.bak  1048576 bytes  modified Wed, 01 May 2024 02:00:00 GMT\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_blobs_up_to_the_size_cap() {
+        let server = MockServer::start().await;
+        let codex_home = TempDir::new().unwrap();
+        Mock::given(method("HEAD"))
+            .and(path("/backups/manifest.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("abcdefghijklmnopqrstuvwxyz"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/backups/manifest.txt"))
+            .and(header("x-ms-range", "bytes=0-9"))
+            .respond_with(ResponseTemplate::new(206).set_body_string("abcdefghij"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let output = storage(&server, &codex_home, 10)
+            .handle_function_call(
+                AZURE_STORAGE_READ_BLOB_TOOL_NAME,
+                &json!({ "container": "backups", "blob": "manifest.txt" }).to_string(),
+                Path::new("/"),
+            )
+            .await;
+
+        assert_eq!(output.success, Some(true), "{}", output.content);
+        assert_eq!(
+            output.content,
+            "abcdefghij\n[showing the first 10 of 26 bytes of manifest.txt]"
+        );
+    }
+
+    #[tokio::test]
+    async fn uploads_files_and_reports_storage_errors() {
+        let server = MockServer::start().await;
+        let codex_home = TempDir::new().unwrap();
+        let cwd = TempDir::new().unwrap();
+        std::fs::write(cwd.path().join("report.json"), "{\"ok\":true}").unwrap();
+        Mock::given(method("PUT"))
+            .and(path("/artifacts/runs/1/report.json"))
+            .and(header("x-ms-blob-type", "BlockBlob"))
+            .and(header("content-type", "application/json"))
+            .and(body_string("{\"ok\":true}"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/locked/report.json"))
+            .respond_with(ResponseTemplate::new(403).set_body_string(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?><Error>\
+                 <Code>AuthorizationPermissionMismatch</Code>\
+                 <Message>This request is not authorized.\nRequestId:1</Message></Error>",
+            ))
+            .mount(&server)
+            .await;
+        let storage = storage(&server, &codex_home, 1024);
+
+        let output = storage
+            .handle_function_call(
+                AZURE_STORAGE_UPLOAD_BLOB_TOOL_NAME,
+                &json!({
+                    "container": "artifacts",
+                    "blob": "runs/1/report.json",
+                    "path": "report.json",
+                })
+                .to_string(),
+                cwd.path(),
+            )
+            .await;
+        assert_eq!(output.success, Some(true), "{}", output.content);
+
+        let output = storage
+            .handle_function_call(
+                AZURE_STORAGE_UPLOAD_BLOB_TOOL_NAME,
+                &json!({ "container": "locked", "blob": "report.json", "path": "report.json" })
+                    .to_string(),
+                cwd.path(),
+            )
+            .await;
+        assert_eq!(output.success, Some(false));
+        assert_eq!(
+            output.content,
+            "Azure Storage request failed (403 Forbidden): AuthorizationPermissionMismatch: \
+             This request is not authorized."
+        );
+    }
+
+    #[test]
+    fn only_uploads_and_writable_sas_urls_need_approval() {
+        let sas = |permissions: &str| {
+            write_request(
+                AZURE_STORAGE_GENERATE_SAS_TOOL_NAME,
+                &json!({ "container": "c", "permissions": permissions }).to_string(),
+            )
+        };
+        assert_eq!(sas("lr"), None);
+        assert_eq!(sas("wr").unwrap()["sas"], "rw");
+        assert!(
+            write_request(
+                AZURE_STORAGE_UPLOAD_BLOB_TOOL_NAME,
+                &json!({ "container": "c", "blob": "b", "path": "p" }).to_string(),
+            )
+            .is_some()
+        );
+        assert_eq!(
+            write_request(AZURE_STORAGE_LIST_BLOBS_TOOL_NAME, "{}"),
+            None
+        );
+        assert_eq!(
+            sas_permissions(Some("rx")),
+            Err("unknown SAS permission `x`; use letters of racwdl".to_string())
+        );
+    }
+
+    #[test]
+    fn signs_user_delegation_sas_tokens() {
+        // RFC 4231, test case 2.
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        let hex: String = mac.iter().map(|b| format!("{b:02x}")).collect();
+        assert_eq!(
+            hex,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        let key = parse_user_delegation_key(
+            "<UserDelegationKey><SignedOid>oid</SignedOid><SignedTid>tid</SignedTid>\
+             <SignedStart>2024-05-01T00:00:00Z</SignedStart>\
+             <SignedExpiry>2024-05-01T01:00:00Z</SignedExpiry>\
+             <SignedService>b</SignedService><SignedVersion>2021-08-06</SignedVersion>\
+             <Value>a2V5</Value></UserDelegationKey>",
+        )
+        .unwrap();
+        let resource = SasResource {
+            account: "acct",
+            container: "backups",
+            blob: Some("db.bak"),
+        };
+        assert_eq!(
+            sas_string_to_sign(
+                &key,
+                &resource,
+                "r",
+                "2024-05-01T00:00:00Z",
+                "2024-05-01T01:00:00Z"
+            ),
+            "r\n2024-05-01T00:00:00Z\n2024-05-01T01:00:00Z\n/blob/acct/backups/db.bak\n\
+             oid\ntid\n2024-05-01T00:00:00Z\n2024-05-01T01:00:00Z\nb\n2021-08-06\n\
+             \n\n\n\nhttps\n2021-08-06\nb\n\n\n\n\n\n\n"
+        );
+    }
+}
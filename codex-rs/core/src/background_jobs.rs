@@ -0,0 +1,512 @@
+//! Commands the agent keeps running in the background, such as a dev server
+//! or a file watcher, while it carries on with other tool calls. Jobs are
+//! started, inspected and stopped through the `background_*` tools and live
+//! until they exit, are killed, or the session ends.
+
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+use async_channel::Sender;
+use codex_protocol::models::FunctionCallOutputPayload;
+use serde::Deserialize;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::process::Child;
+use tokio::sync::Notify;
+
+use crate::config_types::ExecLimits;
+use crate::exec_limits::ProcessLimits;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+use crate::protocol::BackgroundJobEndedEvent;
+use crate::protocol::Event;
+use crate::protocol::EventMsg;
+
+pub(crate) const BACKGROUND_START_TOOL_NAME: &str = "background_start";
+pub(crate) const BACKGROUND_LIST_TOOL_NAME: &str = "background_list";
+pub(crate) const BACKGROUND_OUTPUT_TOOL_NAME: &str = "background_output";
+pub(crate) const BACKGROUND_KILL_TOOL_NAME: &str = "background_kill";
+
+/// Output retained per job; older output is dropped.
+const MAX_RECENT_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// Output returned by `background_output` unless the call asks for more.
+const DEFAULT_OUTPUT_BYTES: usize = 8 * 1024;
+
+const READ_CHUNK_BYTES: usize = 8 * 1024;
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct BackgroundStartParams {
+    pub(crate) command: Vec<String>,
+    #[serde(default)]
+    pub(crate) workdir: Option<String>,
+    #[serde(default)]
+    pub(crate) with_escalated_permissions: Option<bool>,
+    #[serde(default)]
+    pub(crate) justification: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct BackgroundOutputParams {
+    pub(crate) job_id: u32,
+    #[serde(default)]
+    pub(crate) max_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct BackgroundKillParams {
+    pub(crate) job_id: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub(crate) enum JobStatus {
+    Running,
+    Exited { exit_code: Option<i32> },
+    Killed,
+}
+
+/// Summary of a job as reported to the model.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct JobInfo {
+    pub(crate) job_id: u32,
+    pub(crate) command: Vec<String>,
+    pub(crate) status: JobStatus,
+    pub(crate) running_secs: u64,
+}
+
+/// Response of `background_output`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct JobOutput {
+    #[serde(flatten)]
+    pub(crate) info: JobInfo,
+    /// Bytes of earlier output that are not included.
+    pub(crate) omitted_bytes: usize,
+    pub(crate) output: String,
+}
+
+struct Job {
+    command: Vec<String>,
+    started: Instant,
+    state: Mutex<JobState>,
+    kill: Notify,
+}
+
+struct JobState {
+    status: JobStatus,
+    finished: Option<Instant>,
+    output: VecDeque<u8>,
+    /// Output dropped from the front of `output` to stay within
+    /// [`MAX_RECENT_OUTPUT_BYTES`].
+    dropped: usize,
+}
+
+impl Job {
+    fn info(&self, job_id: u32) -> JobInfo {
+        let state = lock(&self.state);
+        let end = state.finished.unwrap_or_else(Instant::now);
+        JobInfo {
+            job_id,
+            command: self.command.clone(),
+            status: state.status,
+            running_secs: end.duration_since(self.started).as_secs(),
+        }
+    }
+
+    fn append_output(&self, bytes: &[u8]) {
+        let mut state = lock(&self.state);
+        state.output.extend(bytes);
+        if state.output.len() > MAX_RECENT_OUTPUT_BYTES {
+            let excess = state.output.len() - MAX_RECENT_OUTPUT_BYTES;
+            state.output.drain(..excess);
+            state.dropped += excess;
+        }
+    }
+
+    fn finish(&self, status: JobStatus) {
+        let mut state = lock(&self.state);
+        state.status = status;
+        state.finished = Some(Instant::now());
+    }
+}
+
+/// The background jobs of one session.
+#[derive(Default)]
+pub(crate) struct BackgroundJobs {
+    next_id: AtomicU32,
+    jobs: Mutex<BTreeMap<u32, Arc<Job>>>,
+}
+
+impl BackgroundJobs {
+    /// Take ownership of `child` as a new job and return its id. Stdout and
+    /// stderr are collected into the job's recent output, and
+    /// `EventMsg::BackgroundJobEnded` is sent on `tx_event` once it stops.
+    pub(crate) fn start(
+        &self,
+        mut child: Child,
+        command: Vec<String>,
+        limits: &ExecLimits,
+        sub_id: String,
+        tx_event: Sender<Event>,
+    ) -> std::io::Result<u32> {
+        let process_limits = ProcessLimits::apply(&child, limits)?;
+        let job_id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let job = Arc::new(Job {
+            command,
+            started: Instant::now(),
+            state: Mutex::new(JobState {
+                status: JobStatus::Running,
+                finished: None,
+                output: VecDeque::new(),
+                dropped: 0,
+            }),
+            kill: Notify::new(),
+        });
+
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(collect_output(stdout, job.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(collect_output(stderr, job.clone()));
+        }
+
+        lock(&self.jobs).insert(job_id, job.clone());
+
+        tokio::spawn(async move {
+            let status = tokio::select! {
+                exit_status = child.wait() => JobStatus::Exited {
+                    exit_code: exit_status.ok().and_then(|status| status.code()),
+                },
+                _ = job.kill.notified() => {
+                    if let Err(err) = process_limits.kill(&mut child) {
+                        tracing::warn!("failed to kill background job {job_id}: {err}");
+                    }
+                    let _ = child.wait().await;
+                    JobStatus::Killed
+                }
+            };
+            job.finish(status);
+
+            let (exit_code, killed) = match status {
+                JobStatus::Exited { exit_code } => (exit_code, false),
+                JobStatus::Running | JobStatus::Killed => (None, true),
+            };
+            let event = Event {
+                id: sub_id,
+                msg: EventMsg::BackgroundJobEnded(BackgroundJobEndedEvent {
+                    job_id,
+                    exit_code,
+                    killed,
+                }),
+            };
+            let _ = tx_event.send(event).await;
+        });
+
+        Ok(job_id)
+    }
+
+    pub(crate) fn list(&self) -> Vec<JobInfo> {
+        let jobs = lock(&self.jobs);
+        jobs.iter().map(|(id, job)| job.info(*id)).collect()
+    }
+
+    /// The last `max_bytes` (default 8 KiB) of output of `job_id`.
+    pub(crate) fn output(&self, job_id: u32, max_bytes: Option<usize>) -> Option<JobOutput> {
+        let job = self.get(job_id)?;
+        let info = job.info(job_id);
+        let state = lock(&job.state);
+        let len = max_bytes
+            .unwrap_or(DEFAULT_OUTPUT_BYTES)
+            .min(state.output.len());
+        let skip = state.output.len() - len;
+        let tail: Vec<u8> = state.output.iter().skip(skip).copied().collect();
+        Some(JobOutput {
+            info,
+            omitted_bytes: state.dropped + skip,
+            output: String::from_utf8_lossy(&tail).into_owned(),
+        })
+    }
+
+    /// Kill `job_id` and everything it started. Returns `false` when there is
+    /// no such job.
+    pub(crate) fn kill(&self, job_id: u32) -> bool {
+        match self.get(job_id) {
+            Some(job) => {
+                job.kill.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Kill every job that is still running.
+    pub(crate) fn kill_all(&self) {
+        let jobs = lock(&self.jobs);
+        for job in jobs.values() {
+            job.kill.notify_one();
+        }
+    }
+
+    /// Handle the `background_list`, `background_output` and
+    /// `background_kill` tools. Starting jobs needs approval and is handled by
+    /// the session.
+    pub(crate) fn handle_tool_call(
+        &self,
+        name: &str,
+        arguments: &str,
+    ) -> FunctionCallOutputPayload {
+        let unknown_job = |job_id: u32| format!("no background job with id {job_id}");
+        let result = match name {
+            BACKGROUND_LIST_TOOL_NAME => to_json(&self.list()),
+            BACKGROUND_OUTPUT_TOOL_NAME => parse_arguments::<BackgroundOutputParams>(arguments)
+                .and_then(|params| {
+                    let output = self
+                        .output(params.job_id, params.max_bytes)
+                        .ok_or_else(|| unknown_job(params.job_id))?;
+                    to_json(&output)
+                }),
+            BACKGROUND_KILL_TOOL_NAME => parse_arguments::<BackgroundKillParams>(arguments)
+                .and_then(|params| {
+                    if self.kill(params.job_id) {
+                        Ok(format!("killing background job {}", params.job_id))
+                    } else {
+                        Err(unknown_job(params.job_id))
+                    }
+                }),
+            _ => Err(format!("unsupported call: {name}")),
+        };
+        match result {
+            Ok(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(true),
+            },
+            Err(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(false),
+            },
+        }
+    }
+
+    fn get(&self, job_id: u32) -> Option<Arc<Job>> {
+        let jobs = lock(&self.jobs);
+        jobs.get(&job_id).cloned()
+    }
+}
+
+fn parse_arguments<T: DeserializeOwned>(arguments: &str) -> Result<T, String> {
+    serde_json::from_str(arguments).map_err(|e| format!("failed to parse function arguments: {e}"))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| format!("failed to serialize response: {e}"))
+}
+
+/// Job state stays consistent across a panic in another holder, so a
+/// poisoned lock is still usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+async fn collect_output<R: AsyncRead + Unpin>(mut reader: R, job: Arc<Job>) {
+    let mut buf = vec![0u8; READ_CHUNK_BYTES];
+    while let Ok(n) = reader.read(&mut buf).await {
+        if n == 0 {
+            break;
+        }
+        job.append_output(&buf[..n]);
+    }
+}
+
+pub(crate) fn create_background_tools() -> Vec<ResponsesApiTool> {
+    let job_id = || {
+        (
+            "job_id".to_string(),
+            JsonSchema::Number {
+                description: Some("The id returned by background_start.".to_string()),
+            },
+        )
+    };
+
+    let mut start_properties = BTreeMap::new();
+    start_properties.insert(
+        "command".to_string(),
+        JsonSchema::Array {
+            items: Box::new(JsonSchema::String { description: None }),
+            description: Some("The command to execute".to_string()),
+        },
+    );
+    start_properties.insert(
+        "workdir".to_string(),
+        JsonSchema::String {
+            description: Some("The working directory to execute the command in".to_string()),
+        },
+    );
+
+    let mut output_properties = BTreeMap::from([job_id()]);
+    output_properties.insert(
+        "max_bytes".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "How much of the most recent output to return. Defaults to 8192.".to_string(),
+            ),
+        },
+    );
+
+    vec![
+        ResponsesApiTool {
+            name: BACKGROUND_START_TOOL_NAME.to_string(),
+            description: "Starts a long-running command, such as a dev server or a file \
+                watcher, in the background and returns its job id without waiting for it to \
+                exit. Use background_output to read what it printed and background_kill to stop \
+                it."
+            .to_string(),
+            strict: false,
+            parameters: JsonSchema::Object {
+                properties: start_properties,
+                required: Some(vec!["command".to_string()]),
+                additional_properties: Some(false),
+            },
+        },
+        ResponsesApiTool {
+            name: BACKGROUND_LIST_TOOL_NAME.to_string(),
+            description: "Lists the background jobs of this session and whether they are still \
+                running."
+                .to_string(),
+            strict: false,
+            parameters: JsonSchema::Object {
+                properties: BTreeMap::new(),
+                required: None,
+                additional_properties: Some(false),
+            },
+        },
+        ResponsesApiTool {
+            name: BACKGROUND_OUTPUT_TOOL_NAME.to_string(),
+            description: "Returns the status and the most recent output of a background job."
+                .to_string(),
+            strict: false,
+            parameters: JsonSchema::Object {
+                properties: output_properties,
+                required: Some(vec!["job_id".to_string()]),
+                additional_properties: Some(false),
+            },
+        },
+        ResponsesApiTool {
+            name: BACKGROUND_KILL_TOOL_NAME.to_string(),
+            description: "Stops a background job and the processes it started.".to_string(),
+            strict: false,
+            parameters: JsonSchema::Object {
+                properties: BTreeMap::from([job_id()]),
+                required: Some(vec!["job_id".to_string()]),
+                additional_properties: Some(false),
+            },
+        },
+    ]
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::spawn::StdioPolicy;
+    use crate::spawn::spawn_child_async;
+    use pretty_assertions::assert_eq;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    #[expect(clippy::unwrap_used)]
+    async fn spawn_sh(script: &str) -> Child {
+        spawn_child_async(
+            PathBuf::from("/bin/sh"),
+            vec!["-c".to_string(), script.to_string()],
+            None,
+            std::env::temp_dir(),
+            &crate::protocol::SandboxPolicy::DangerFullAccess,
+            StdioPolicy::RedirectForShellTool,
+            HashMap::new(),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[expect(clippy::unwrap_used)]
+    async fn next_ended(rx: &async_channel::Receiver<Event>) -> BackgroundJobEndedEvent {
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if let EventMsg::BackgroundJobEnded(ended) = event.msg {
+                return ended;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn jobs_report_output_and_can_be_killed() {
+        let jobs = BackgroundJobs::default();
+        let (tx, rx) = async_channel::unbounded();
+
+        let server = spawn_sh("printf 'listening\\n'; exec sleep 30").await;
+        let server_id = jobs
+            .start(
+                server,
+                vec!["server".to_string()],
+                &ExecLimits::default(),
+                "sub".to_string(),
+                tx.clone(),
+            )
+            .unwrap();
+        let short = spawn_sh("printf 'one\\ntwo\\n'; exit 3").await;
+        let short_id = jobs
+            .start(
+                short,
+                vec!["short".to_string()],
+                &ExecLimits::default(),
+                "sub".to_string(),
+                tx,
+            )
+            .unwrap();
+        assert_eq!((server_id, short_id), (1, 2));
+
+        let ended = next_ended(&rx).await;
+        assert_eq!(ended.job_id, short_id);
+        assert_eq!(ended.exit_code, Some(3));
+        assert!(!ended.killed);
+
+        // The output readers may trail the exit slightly.
+        let mut output = jobs.output(short_id, Some(4)).unwrap();
+        for _ in 0..50 {
+            if output.output == "two\n" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            output = jobs.output(short_id, Some(4)).unwrap();
+        }
+        assert_eq!(output.output, "two\n");
+        assert_eq!(output.omitted_bytes, 4);
+        assert_eq!(output.info.status, JobStatus::Exited { exit_code: Some(3) });
+
+        let statuses: Vec<JobStatus> = jobs.list().into_iter().map(|job| job.status).collect();
+        assert_eq!(
+            statuses,
+            vec![JobStatus::Running, JobStatus::Exited { exit_code: Some(3) }]
+        );
+
+        assert!(jobs.kill(server_id));
+        assert!(!jobs.kill(42));
+        let ended = next_ended(&rx).await;
+        assert_eq!(ended.job_id, server_id);
+        assert!(ended.killed);
+        assert_eq!(jobs.list()[0].status, JobStatus::Killed);
+        assert_eq!(jobs.output(server_id, None).unwrap().output, "listening\n");
+    }
+}
@@ -0,0 +1,407 @@
+//! Limits on the requests, tokens and cost of a session and of all sessions of
+//! a day, configured in `[budget]`.
+//!
+//! [`ModelClient`](crate::client::ModelClient) checks the budget before every
+//! request and records the usage of every completed one. The usage of a day
+//! is kept in `~/.codex/usage/<date>.jsonl`, one line per completed request,
+//! so that every session on the machine counts towards the daily budget.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+
+use serde::Deserialize;
+use serde::Serialize;
+use time::OffsetDateTime;
+use time::macros::format_description;
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::config_types::BudgetConfig;
+use crate::config_types::BudgetLimits;
+use crate::protocol::BudgetEvent;
+use crate::protocol::BudgetResource;
+use crate::protocol::BudgetScope;
+use crate::protocol::TokenUsage;
+
+const USAGE_SUBDIR: &str = "usage";
+
+#[derive(Debug)]
+pub(crate) struct Budget {
+    config: BudgetConfig,
+    session_id: Uuid,
+    usage_dir: PathBuf,
+    state: Mutex<BudgetState>,
+}
+
+#[derive(Debug, Default)]
+struct BudgetState {
+    session: Usage,
+    /// Limits the user approved going over, for the rest of the session and
+    /// for the rest of the turn.
+    lifted_for_session: HashSet<(BudgetScope, BudgetResource)>,
+    lifted_for_turn: HashSet<(BudgetScope, BudgetResource)>,
+    warnings: Vec<BudgetEvent>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Usage {
+    #[serde(default)]
+    requests: u64,
+    #[serde(default)]
+    tokens: u64,
+    #[serde(default)]
+    cost_usd: f64,
+}
+
+impl Usage {
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            requests: self.requests + other.requests,
+            tokens: self.tokens + other.tokens,
+            cost_usd: self.cost_usd + other.cost_usd,
+        }
+    }
+
+    fn get(&self, resource: BudgetResource) -> f64 {
+        match resource {
+            BudgetResource::Requests => self.requests as f64,
+            BudgetResource::Tokens => self.tokens as f64,
+            BudgetResource::CostUsd => self.cost_usd,
+        }
+    }
+}
+
+/// A line of the daily usage file.
+#[derive(Serialize, Deserialize)]
+struct UsageRecord {
+    session_id: Uuid,
+    model: String,
+    #[serde(flatten)]
+    usage: Usage,
+}
+
+impl Budget {
+    pub(crate) fn new(config: &Config, session_id: Uuid) -> Self {
+        Self {
+            config: config.budget.clone(),
+            session_id,
+            usage_dir: config.codex_home.join(USAGE_SUBDIR),
+            state: Mutex::new(BudgetState::default()),
+        }
+    }
+
+    /// Forgets the limits that were lifted for the previous turn.
+    pub(crate) fn start_turn(&self) {
+        self.lock().lifted_for_turn.clear();
+    }
+
+    /// Lets requests go over the limit that `exceeded` reports, for the rest
+    /// of the session or only for the rest of the turn.
+    pub(crate) fn lift(&self, exceeded: &BudgetEvent, for_session: bool) {
+        let key = (exceeded.scope, exceeded.resource);
+        let mut state = self.lock();
+        if for_session {
+            state.lifted_for_session.insert(key);
+        } else {
+            state.lifted_for_turn.insert(key);
+        }
+    }
+
+    /// Returns the first limit that is used up and was not lifted.
+    pub(crate) async fn check(&self) -> Result<(), BudgetEvent> {
+        if self.config.session.is_empty() && self.config.daily.is_empty() {
+            return Ok(());
+        }
+        let daily = self.daily_usage().await;
+        let state = self.lock();
+        for (scope, limits, usage) in [
+            (BudgetScope::Session, &self.config.session, state.session),
+            (BudgetScope::Daily, &self.config.daily, daily),
+        ] {
+            for (resource, limit) in limit_values(limits) {
+                let key = (scope, resource);
+                if usage.get(resource) >= limit
+                    && !state.lifted_for_session.contains(&key)
+                    && !state.lifted_for_turn.contains(&key)
+                {
+                    return Err(BudgetEvent {
+                        scope,
+                        resource,
+                        used: usage.get(resource),
+                        limit,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts a completed request to `model` and queues a warning for every
+    /// limit whose warning threshold it crossed.
+    pub(crate) async fn record(&self, model: &str, token_usage: &TokenUsage) {
+        if self.config.session.is_empty() && self.config.daily.is_empty() {
+            return;
+        }
+        let usage = Usage {
+            requests: 1,
+            tokens: token_usage.total_tokens,
+            cost_usd: self.cost(model, token_usage),
+        };
+        let daily_before = if self.config.daily.is_empty() {
+            None
+        } else {
+            let before = self.daily_usage().await;
+            if let Err(e) = self.append_daily_usage(model, usage).await {
+                warn!("failed to record daily usage: {e}");
+            }
+            Some(before)
+        };
+
+        let mut state = self.lock();
+        let session_before = state.session;
+        state.session = session_before.add(usage);
+        let mut warnings = self.crossed(
+            BudgetScope::Session,
+            &self.config.session,
+            session_before,
+            state.session,
+        );
+        if let Some(before) = daily_before {
+            warnings.extend(self.crossed(
+                BudgetScope::Daily,
+                &self.config.daily,
+                before,
+                before.add(usage),
+            ));
+        }
+        state.warnings.extend(warnings);
+    }
+
+    pub(crate) fn take_warnings(&self) -> Vec<BudgetEvent> {
+        std::mem::take(&mut self.lock().warnings)
+    }
+
+    fn cost(&self, model: &str, usage: &TokenUsage) -> f64 {
+        let Some(price) = self.config.prices.get(model) else {
+            return 0.0;
+        };
+        let cached_price = price.cached_input.unwrap_or(price.input);
+        (usage.non_cached_input() as f64 * price.input
+            + usage.cached_input() as f64 * cached_price
+            + usage.output_tokens as f64 * price.output)
+            / 1_000_000.0
+    }
+
+    /// The limits whose warning threshold lies in `(before, after]`.
+    fn crossed(
+        &self,
+        scope: BudgetScope,
+        limits: &BudgetLimits,
+        before: Usage,
+        after: Usage,
+    ) -> Vec<BudgetEvent> {
+        let share = f64::from(self.config.warn_at_percent) / 100.0;
+        limit_values(limits)
+            .into_iter()
+            .filter(|(resource, limit)| {
+                let threshold = limit * share;
+                before.get(*resource) < threshold && after.get(*resource) >= threshold
+            })
+            .map(|(resource, limit)| BudgetEvent {
+                scope,
+                resource,
+                used: after.get(resource),
+                limit,
+            })
+            .collect()
+    }
+
+    fn daily_usage_path(&self) -> PathBuf {
+        let format = format_description!("[year]-[month]-[day]");
+        let today = OffsetDateTime::now_local()
+            .unwrap_or_else(|_| OffsetDateTime::now_utc())
+            .format(format)
+            .unwrap_or_default();
+        self.usage_dir.join(format!("{today}.jsonl"))
+    }
+
+    /// Usage of all sessions today. A missing or unreadable file counts as no
+    /// usage.
+    async fn daily_usage(&self) -> Usage {
+        let text = match tokio::fs::read_to_string(self.daily_usage_path()).await {
+            Ok(text) => text,
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("failed to read daily usage: {e}");
+                }
+                return Usage::default();
+            }
+        };
+        text.lines()
+            .filter_map(|line| serde_json::from_str::<UsageRecord>(line).ok())
+            .fold(Usage::default(), |total, record| total.add(record.usage))
+    }
+
+    async fn append_daily_usage(&self, model: &str, usage: Usage) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(&self.usage_dir).await?;
+        let mut line = serde_json::to_string(&UsageRecord {
+            session_id: self.session_id,
+            model: model.to_string(),
+            usage,
+        })?;
+        line.push('\n');
+        // Written with a single append so that lines of concurrent sessions
+        // do not interleave.
+        tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.daily_usage_path())
+            .await?
+            .write_all(line.as_bytes())
+            .await
+    }
+
+    /// Budget state stays consistent across a panic in another holder, so a
+    /// poisoned lock is still usable.
+    fn lock(&self) -> MutexGuard<'_, BudgetState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn limit_values(limits: &BudgetLimits) -> Vec<(BudgetResource, f64)> {
+    [
+        (
+            BudgetResource::Requests,
+            limits.max_requests.map(|n| n as f64),
+        ),
+        (BudgetResource::Tokens, limits.max_tokens.map(|n| n as f64)),
+        (BudgetResource::CostUsd, limits.max_cost_usd),
+    ]
+    .into_iter()
+    .filter_map(|(resource, limit)| Some((resource, limit?)))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::config::ConfigOverrides;
+    use crate::config::ConfigToml;
+    use crate::config_types::ModelPrice;
+    use pretty_assertions::assert_eq;
+
+    fn budget(codex_home: &tempfile::TempDir, config: BudgetConfig) -> Budget {
+        let mut cfg = Config::load_from_base_config_with_overrides(
+            ConfigToml::default(),
+            ConfigOverrides::default(),
+            codex_home.path().to_path_buf(),
+        )
+        .unwrap();
+        cfg.budget = config;
+        Budget::new(&cfg, Uuid::new_v4())
+    }
+
+    fn tokens(input: u64, output: u64) -> TokenUsage {
+        TokenUsage {
+            input_tokens: input,
+            cached_input_tokens: None,
+            output_tokens: output,
+            reasoning_output_tokens: None,
+            total_tokens: input + output,
+        }
+    }
+
+    #[tokio::test]
+    async fn session_limit_warns_then_stops_until_lifted() {
+        let home = tempfile::TempDir::new().unwrap();
+        let budget = budget(
+            &home,
+            BudgetConfig {
+                session: BudgetLimits {
+                    max_requests: Some(5),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+
+        for _ in 0..3 {
+            budget.record("gpt-5", &tokens(10, 10)).await;
+        }
+        assert_eq!(budget.take_warnings(), Vec::new());
+        budget.record("gpt-5", &tokens(10, 10)).await;
+        let warning = BudgetEvent {
+            scope: BudgetScope::Session,
+            resource: BudgetResource::Requests,
+            used: 4.0,
+            limit: 5.0,
+        };
+        assert_eq!(budget.take_warnings(), vec![warning]);
+        assert_eq!(budget.check().await, Ok(()));
+
+        budget.record("gpt-5", &tokens(10, 10)).await;
+        let exceeded = budget.check().await.unwrap_err();
+        assert_eq!(
+            exceeded.to_string(),
+            "session request budget: 5 of 5 requests used"
+        );
+
+        budget.lift(&exceeded, false);
+        assert_eq!(budget.check().await, Ok(()));
+        budget.start_turn();
+        assert_eq!(budget.check().await, Err(exceeded.clone()));
+        budget.lift(&exceeded, true);
+        budget.start_turn();
+        assert_eq!(budget.check().await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn daily_cost_counts_every_session() {
+        let home = tempfile::TempDir::new().unwrap();
+        let config = BudgetConfig {
+            daily: BudgetLimits {
+                max_cost_usd: Some(1.0),
+                ..Default::default()
+            },
+            prices: [(
+                "gpt-5".to_string(),
+                ModelPrice {
+                    input: 1.0,
+                    cached_input: None,
+                    output: 10.0,
+                },
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let first = budget(&home, config.clone());
+        let second = budget(&home, config);
+
+        // $0.50 each: 100k input tokens and 40k output tokens.
+        first.record("gpt-5", &tokens(100_000, 40_000)).await;
+        second.record("unpriced", &tokens(100_000, 40_000)).await;
+        assert_eq!(second.check().await, Ok(()));
+        second.record("gpt-5", &tokens(100_000, 40_000)).await;
+
+        assert_eq!(
+            second.check().await.unwrap_err().to_string(),
+            "daily cost budget: $1.00 of $1.00 used"
+        );
+        assert_eq!(
+            second.take_warnings(),
+            vec![BudgetEvent {
+                scope: BudgetScope::Daily,
+                resource: BudgetResource::CostUsd,
+                used: 1.0,
+                limit: 1.0,
+            }]
+        );
+    }
+}
@@ -14,16 +14,20 @@ use tokio::sync::mpsc;
 use tokio::time::timeout;
 use tracing::debug;
 use tracing::trace;
+use tracing::warn;
 
 use crate::ModelProviderInfo;
 use crate::client_common::Prompt;
 use crate::client_common::ResponseEvent;
 use crate::client_common::ResponseStream;
+use crate::client_common::create_chat_response_format;
 use crate::error::CodexErr;
 use crate::error::Result;
 use crate::model_family::ModelFamily;
 use crate::openai_tools::create_tools_json_for_chat_completions_api;
 use crate::util::backoff;
+use crate::util::retry_after;
+use codex_login::CodexAuth;
 use codex_protocol::models::ContentItem;
 use codex_protocol::models::ReasoningItemContent;
 use codex_protocol::models::ResponseItem;
@@ -34,11 +38,12 @@ pub(crate) async fn stream_chat_completions(
     model_family: &ModelFamily,
     client: &reqwest::Client,
     provider: &ModelProviderInfo,
+    auth: &Option<CodexAuth>,
 ) -> Result<ResponseStream> {
     // Build messages array
     let mut messages = Vec::<serde_json::Value>::new();
 
-    let full_instructions = prompt.get_full_instructions(model_family);
+    let full_instructions = prompt.get_full_instructions_for_api(model_family, provider.wire_api);
     messages.push(json!({"role": "system", "content": full_instructions}));
 
     let input = prompt.get_formatted_input();
@@ -46,17 +51,7 @@ pub(crate) async fn stream_chat_completions(
     for item in &input {
         match item {
             ResponseItem::Message { role, content, .. } => {
-                let mut text = String::new();
-                for c in content {
-                    match c {
-                        ContentItem::InputText { text: t }
-                        | ContentItem::OutputText { text: t } => {
-                            text.push_str(t);
-                        }
-                        _ => {}
-                    }
-                }
-                messages.push(json!({"role": role, "content": text}));
+                messages.push(json!({"role": role, "content": message_content(content)}));
             }
             ResponseItem::FunctionCall {
                 name,
@@ -139,17 +134,133 @@ pub(crate) async fn stream_chat_completions(
     }
 
     let tools_json = create_tools_json_for_chat_completions_api(&prompt.tools)?;
-    let payload = json!({
+    let mut payload = json!({
         "model": model_family.slug,
         "messages": messages,
         "stream": true,
         "tools": tools_json,
     });
+    if let Some(format) = &prompt.response_format {
+        payload["response_format"] = create_chat_response_format(format);
+    }
+
+    let resp = post_sse_with_retries(client, provider, auth, &model_family.slug, &payload).await?;
+    let (tx_event, rx_event) = mpsc::channel::<Result<ResponseEvent>>(1600);
+    let stream = resp.bytes_stream().map_err(CodexErr::Reqwest);
+    let resume = ChatResume {
+        client: client.clone(),
+        provider: provider.clone(),
+        auth: auth.clone(),
+        model: model_family.slug.clone(),
+        payload,
+        remaining: provider.stream_max_retries(),
+    };
+    tokio::spawn(process_chat_sse(
+        stream,
+        tx_event,
+        provider.stream_idle_timeout(),
+        Some(resume),
+    ));
+    Ok(ResponseStream { rx_event })
+}
+
+/// Sent after the partial assistant message when a dropped response is
+/// resumed.
+const CONTINUE_PROMPT: &str = "Your previous response was cut off. Continue it exactly where it stopped, without repeating anything you already wrote.";
+
+/// What is needed to resume a Chat Completions response whose stream dropped
+/// part way through an assistant message: the original request is sent again
+/// with the text received so far and [`CONTINUE_PROMPT`], and the new stream
+/// continues the same message.
+struct ChatResume {
+    client: reqwest::Client,
+    provider: ModelProviderInfo,
+    auth: Option<CodexAuth>,
+    model: String,
+    payload: serde_json::Value,
+    /// Resumes left, from the provider's `stream_max_retries`.
+    remaining: u64,
+}
+
+impl ChatResume {
+    /// Request the rest of the message that starts with `partial`. Returns
+    /// `None` when no resumes are left or the request failed, in which case
+    /// the drop is reported as before.
+    async fn reconnect(
+        &mut self,
+        partial: &str,
+    ) -> Option<impl Stream<Item = Result<Bytes>> + Send + 'static> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let mut payload = self.payload.clone();
+        let messages = payload.get_mut("messages")?.as_array_mut()?;
+        messages.push(json!({"role": "assistant", "content": partial}));
+        messages.push(json!({"role": "user", "content": CONTINUE_PROMPT}));
+        match post_sse_with_retries(
+            &self.client,
+            &self.provider,
+            &self.auth,
+            &self.model,
+            &payload,
+        )
+        .await
+        {
+            Ok(resp) => Some(resp.bytes_stream().map_err(CodexErr::Reqwest)),
+            Err(e) => {
+                warn!("failed to resume the chat completions stream: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// The `content` of a chat message: plain text, or a list of text and
+/// `image_url` parts when the message has images.
+fn message_content(content: &[ContentItem]) -> serde_json::Value {
+    let has_images = content
+        .iter()
+        .any(|c| matches!(c, ContentItem::InputImage { .. }));
+    if !has_images {
+        let mut text = String::new();
+        for c in content {
+            if let ContentItem::InputText { text: t } | ContentItem::OutputText { text: t } = c {
+                text.push_str(t);
+            }
+        }
+        return json!(text);
+    }
+    let parts: Vec<serde_json::Value> = content
+        .iter()
+        .map(|c| match c {
+            ContentItem::InputText { text } | ContentItem::OutputText { text } => {
+                json!({"type": "text", "text": text})
+            }
+            ContentItem::InputImage { image_url } => {
+                json!({"type": "image_url", "image_url": {"url": image_url}})
+            }
+        })
+        .collect();
+    json!(parts)
+}
 
+/// POST `payload` to the provider's streaming endpoint, retrying on 429,
+/// 5xx and transport errors up to the provider's retry budget. Shared by
+/// every wire API that authenticates with a static key (or an Azure Entra
+/// token passed as `auth`) rather than Codex login.
+pub(crate) async fn post_sse_with_retries(
+    client: &reqwest::Client,
+    provider: &ModelProviderInfo,
+    auth: &Option<CodexAuth>,
+    model: &str,
+    payload: &serde_json::Value,
+) -> Result<reqwest::Response> {
     debug!(
         "POST to {}: {}",
-        provider.get_full_url(&None),
-        serde_json::to_string_pretty(&payload).unwrap_or_default()
+        provider.get_full_url(auth, model),
+        serde_json::to_string_pretty(payload).unwrap_or_default()
     );
 
     let mut attempt = 0;
@@ -157,25 +268,16 @@ pub(crate) async fn stream_chat_completions(
     loop {
         attempt += 1;
 
-        let req_builder = provider.create_request_builder(client, &None).await?;
+        let req_builder = provider.create_request_builder(client, auth, model).await?;
 
         let res = req_builder
             .header(reqwest::header::ACCEPT, "text/event-stream")
-            .json(&payload)
+            .json(payload)
             .send()
             .await;
 
         match res {
-            Ok(resp) if resp.status().is_success() => {
-                let (tx_event, rx_event) = mpsc::channel::<Result<ResponseEvent>>(1600);
-                let stream = resp.bytes_stream().map_err(CodexErr::Reqwest);
-                tokio::spawn(process_chat_sse(
-                    stream,
-                    tx_event,
-                    provider.stream_idle_timeout(),
-                ));
-                return Ok(ResponseStream { rx_event });
-            }
+            Ok(resp) if resp.status().is_success() => return Ok(resp),
             Ok(res) => {
                 let status = res.status();
                 if !(status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()) {
@@ -187,15 +289,7 @@ pub(crate) async fn stream_chat_completions(
                     return Err(CodexErr::RetryLimit(status));
                 }
 
-                let retry_after_secs = res
-                    .headers()
-                    .get(reqwest::header::RETRY_AFTER)
-                    .and_then(|v| v.to_str().ok())
-                    .and_then(|s| s.parse::<u64>().ok());
-
-                let delay = retry_after_secs
-                    .map(|s| Duration::from_millis(s * 1_000))
-                    .unwrap_or_else(|| backoff(attempt));
+                let delay = retry_after(res.headers()).unwrap_or_else(|| backoff(attempt));
                 tokio::time::sleep(delay).await;
             }
             Err(e) => {
@@ -212,14 +306,19 @@ pub(crate) async fn stream_chat_completions(
 /// Lightweight SSE processor for the Chat Completions streaming format. The
 /// output is mapped onto Codex's internal [`ResponseEvent`] so that the rest
 /// of the pipeline can stay agnostic of the underlying wire format.
+///
+/// When the stream drops while an assistant message is being written and
+/// `resume` is set, the rest of the message is requested and streamed on as
+/// if nothing happened, so the message ends up in history once and whole.
 async fn process_chat_sse<S>(
     stream: S,
     tx_event: mpsc::Sender<Result<ResponseEvent>>,
     idle_timeout: Duration,
+    mut resume: Option<ChatResume>,
 ) where
-    S: Stream<Item = Result<Bytes>> + Unpin,
+    S: Stream<Item = Result<Bytes>> + Send + 'static,
 {
-    let mut stream = stream.eventsource();
+    let mut stream = stream.eventsource().boxed();
 
     // State to accumulate a function call across streaming chunks.
     // OpenAI may split the `arguments` string over multiple `delta` events
@@ -239,7 +338,20 @@ async fn process_chat_sse<S>(
     let mut reasoning_text = String::new();
 
     loop {
-        let sse = match timeout(idle_timeout, stream.next()).await {
+        let next = timeout(idle_timeout, stream.next()).await;
+        // A response that stops part way through an assistant message is
+        // resumed; tool calls are not, their arguments cannot be continued.
+        if !matches!(next, Ok(Some(Ok(_))))
+            && !assistant_text.is_empty()
+            && !fn_call_state.active
+            && let Some(resume) = resume.as_mut()
+            && let Some(resumed) = resume.reconnect(&assistant_text).await
+        {
+            debug!("chat completions stream dropped; resuming the assistant message");
+            stream = resumed.eventsource().boxed();
+            continue;
+        }
+        let sse = match next {
             Ok(Some(Ok(ev))) => ev,
             Ok(Some(Err(e))) => {
                 let _ = tx_event
@@ -676,3 +788,103 @@ impl<S> AggregatedChatStream<S> {
         Self::new(inner, AggregateMode::Streaming)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::model_family::find_family_for_model;
+    use crate::model_provider_info::create_oss_provider_with_base_url;
+    use pretty_assertions::assert_eq;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::body_string_contains;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+
+    fn sse(chunks: &[&str]) -> ResponseTemplate {
+        let body: String = chunks.iter().map(|c| format!("data: {c}\n\n")).collect();
+        ResponseTemplate::new(200).set_body_raw(body, "text/event-stream")
+    }
+
+    #[test]
+    fn sends_images_as_content_parts() {
+        let text = [ContentItem::InputText {
+            text: "hi".to_string(),
+        }];
+        assert_eq!(message_content(&text), json!("hi"));
+
+        let with_image = [
+            ContentItem::InputText {
+                text: "what is this?".to_string(),
+            },
+            ContentItem::InputImage {
+                image_url: "data:image/png;base64,AAAA".to_string(),
+            },
+        ];
+        assert_eq!(
+            message_content(&with_image),
+            json!([
+                {"type": "text", "text": "what is this?"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn resumes_a_message_cut_off_mid_stream() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(body_string_contains("cut off"))
+            .and(body_string_contains(r#""Hel""#))
+            .respond_with(sse(&[
+                r#"{"choices":[{"delta":{"content":"lo"}}]}"#,
+                r#"{"choices":[{"delta":{},"finish_reason":"stop"}]}"#,
+            ]))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // The first response ends without a finish reason.
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(sse(&[r#"{"choices":[{"delta":{"content":"Hel"}}]}"#]))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+
+        let provider = create_oss_provider_with_base_url(&format!("{}/v1", server.uri()));
+        let mut stream = stream_chat_completions(
+            &Prompt::default(),
+            &find_family_for_model("gpt-4o").unwrap(),
+            &reqwest::Client::new(),
+            &provider,
+            &None,
+        )
+        .await
+        .unwrap();
+
+        let mut deltas = Vec::new();
+        let mut messages = Vec::new();
+        while let Some(event) = stream.next().await {
+            match event.unwrap() {
+                ResponseEvent::OutputTextDelta(delta) => deltas.push(delta),
+                ResponseEvent::OutputItemDone(ResponseItem::Message { content, .. }) => {
+                    messages.push(content)
+                }
+                ResponseEvent::Completed { .. } => break,
+                _ => {}
+            }
+        }
+
+        assert_eq!(deltas, vec!["Hel".to_string(), "lo".to_string()]);
+        assert_eq!(
+            messages,
+            vec![vec![ContentItem::OutputText {
+                text: "Hello".to_string()
+            }]]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Takes a model provider out of use after repeated failures, so turns fail
+//! fast (or go to the failover provider) instead of retrying against a
+//! provider that is down.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::config_types::ProviderCircuitBreakerConfig;
+
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    config: ProviderCircuitBreakerConfig,
+    consecutive_failures: u32,
+    /// Set while the provider is unhealthy. Once it has passed, one request
+    /// is let through to find out whether the provider recovered.
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(config: ProviderCircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            consecutive_failures: 0,
+            open_until: None,
+        }
+    }
+
+    /// `Err` with the remaining cooldown while requests must not be sent.
+    pub(crate) fn check(&self, now: Instant) -> Result<(), Duration> {
+        match self.open_until {
+            Some(open_until) if open_until > now => Err(open_until - now),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns `true` when the provider was unhealthy until now.
+    pub(crate) fn record_success(&mut self) -> bool {
+        self.consecutive_failures = 0;
+        self.open_until.take().is_some()
+    }
+
+    /// Returns the cooldown when this failure made the provider unhealthy.
+    /// A failed request after the cooldown starts a new one without
+    /// reporting the provider again.
+    pub(crate) fn record_failure(&mut self, now: Instant) -> Option<Duration> {
+        if self.config.failure_threshold == 0 {
+            return None;
+        }
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.consecutive_failures < self.config.failure_threshold {
+            return None;
+        }
+        let cooldown = Duration::from_millis(self.config.cooldown_ms);
+        let was_open = self.open_until.replace(now + cooldown).is_some();
+        (!was_open).then_some(cooldown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn breaker(failure_threshold: u32) -> CircuitBreaker {
+        CircuitBreaker::new(ProviderCircuitBreakerConfig {
+            failure_threshold,
+            cooldown_ms: 1_000,
+        })
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures_and_closes_on_success() {
+        let now = Instant::now();
+        let mut breaker = breaker(2);
+        assert_eq!(breaker.record_failure(now), None);
+        assert_eq!(breaker.check(now), Ok(()));
+        assert_eq!(
+            breaker.record_failure(now),
+            Some(Duration::from_millis(1_000))
+        );
+        assert_eq!(
+            breaker.check(now + Duration::from_millis(400)),
+            Err(Duration::from_millis(600))
+        );
+
+        // After the cooldown one request is let through; when it fails the
+        // provider stays unhealthy without being reported again.
+        let later = now + Duration::from_millis(1_000);
+        assert_eq!(breaker.check(later), Ok(()));
+        assert_eq!(breaker.record_failure(later), None);
+        assert!(breaker.check(later).is_err());
+
+        assert!(breaker.record_success());
+        assert_eq!(breaker.check(later), Ok(()));
+        assert!(!breaker.record_success());
+    }
+
+    #[test]
+    fn successes_reset_the_failure_count() {
+        let now = Instant::now();
+        let mut breaker = breaker(2);
+        assert_eq!(breaker.record_failure(now), None);
+        assert!(!breaker.record_success());
+        assert_eq!(breaker.record_failure(now), None);
+    }
+
+    #[test]
+    fn zero_threshold_disables_the_breaker() {
+        let now = Instant::now();
+        let mut breaker = breaker(0);
+        for _ in 0..10 {
+            assert_eq!(breaker.record_failure(now), None);
+        }
+        assert_eq!(breaker.check(now), Ok(()));
+    }
+}
@@ -5,6 +5,7 @@ use std::time::Duration;
 use bytes::Bytes;
 use codex_login::AuthManager;
 use codex_login::AuthMode;
+use codex_login::CodexAuth;
 use eventsource_stream::Eventsource;
 use futures::prelude::*;
 use reqwest::StatusCode;
@@ -19,6 +20,9 @@ use tracing::trace;
 use tracing::warn;
 use uuid::Uuid;
 
+use crate::anthropic::stream_anthropic_messages;
+use crate::azure_auth::AzureAuth;
+use crate::budget::Budget;
 use crate::chat_completions::AggregateStreamExt;
 use crate::chat_completions::stream_chat_completions;
 use crate::client_common::Prompt;
@@ -27,19 +31,26 @@ use crate::client_common::ResponseStream;
 use crate::client_common::ResponsesApiRequest;
 use crate::client_common::create_reasoning_param_for_request;
 use crate::client_common::create_text_param_for_request;
+use crate::client_common::select_model_route;
 use crate::config::Config;
+use crate::config_types::ProviderCircuitBreakerConfig;
 use crate::error::CodexErr;
 use crate::error::Result;
 use crate::error::UsageLimitReachedError;
 use crate::flags::CODEX_RS_SSE_FIXTURE;
+use crate::gemini::stream_gemini;
 use crate::model_family::ModelFamily;
+use crate::model_family::find_family_for_model;
+use crate::model_provider_info::AZURE_OPENAI_SCOPE;
 use crate::model_provider_info::ModelProviderInfo;
 use crate::model_provider_info::WireApi;
+use crate::ollama_chat::stream_ollama_chat;
 use crate::openai_model_info::get_model_info;
 use crate::openai_tools::create_tools_json_for_responses_api;
 use crate::protocol::TokenUsage;
 use crate::user_agent::get_codex_user_agent;
 use crate::util::backoff;
+use crate::util::retry_after;
 use codex_protocol::config_types::ReasoningEffort as ReasoningEffortConfig;
 use codex_protocol::config_types::ReasoningSummary as ReasoningSummaryConfig;
 use codex_protocol::models::ResponseItem;
@@ -60,6 +71,10 @@ struct Error {
     resets_in_seconds: Option<u64>,
 }
 
+/// Share of the context window the history may fill before it is compacted,
+/// leaving room for the next request's output.
+const AUTO_COMPACT_DEFAULT_PERCENT: u64 = 80;
+
 #[derive(Debug, Clone)]
 pub struct ModelClient {
     config: Arc<Config>,
@@ -69,9 +84,12 @@ pub struct ModelClient {
     session_id: Uuid,
     effort: ReasoningEffortConfig,
     summary: ReasoningSummaryConfig,
+    azure_auth: Arc<AzureAuth>,
+    budget: Arc<Budget>,
 }
 
 impl ModelClient {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: Arc<Config>,
         auth_manager: Option<Arc<AuthManager>>,
@@ -79,6 +97,8 @@ impl ModelClient {
         effort: ReasoningEffortConfig,
         summary: ReasoningSummaryConfig,
         session_id: Uuid,
+        azure_auth: Arc<AzureAuth>,
+        budget: Arc<Budget>,
     ) -> Self {
         Self {
             config,
@@ -88,6 +108,8 @@ impl ModelClient {
             session_id,
             effort,
             summary,
+            azure_auth,
+            budget,
         }
     }
 
@@ -97,10 +119,124 @@ impl ModelClient {
             .or_else(|| get_model_info(&self.config.model_family).map(|info| info.context_window))
     }
 
+    /// Estimated history size at which older turns are compacted, or `None`
+    /// when automatic compaction is disabled or the context window is
+    /// unknown.
+    pub fn get_auto_compact_token_limit(&self) -> Option<u64> {
+        match self.config.model_auto_compact_token_limit {
+            Some(0) => None,
+            Some(limit) => Some(limit),
+            None => self
+                .get_model_context_window()
+                .map(|window| window * AUTO_COMPACT_DEFAULT_PERCENT / 100),
+        }
+    }
+
+    /// Identifies the provider and model for health tracking.
+    pub(crate) fn provider_key(&self) -> String {
+        format!("{}/{}", self.provider.name, self.config.model)
+    }
+
+    pub(crate) fn get_provider_circuit_breaker(&self) -> ProviderCircuitBreakerConfig {
+        self.config.provider_circuit_breaker
+    }
+
+    /// A client for the failover provider and model of the config, if any.
+    /// Model routes do not apply to it.
+    pub(crate) fn failover_client(&self) -> Option<Self> {
+        let failover = self.config.failover.as_ref()?;
+        let mut client = self.with_model(
+            failover.model.clone(),
+            failover.model_family.clone(),
+            failover.model_provider_id.clone(),
+            failover.model_provider.clone(),
+        );
+        let config = Arc::make_mut(&mut client.config);
+        config.failover = None;
+        config.model_routes = Vec::new();
+        Some(client)
+    }
+
+    /// A client for the model that the first matching `[[model_routes]]`
+    /// entry chooses for `prompt`, with the name of the route. `None` when no
+    /// route matches or the route does not change the model, provider or
+    /// reasoning effort.
+    pub(crate) fn routed_client(&self, prompt: &Prompt) -> Option<(String, Self)> {
+        let route = select_model_route(&self.config.model_routes, prompt)?;
+        let model = route
+            .model
+            .clone()
+            .unwrap_or_else(|| self.config.model.clone());
+        let (model_provider_id, provider) = match &route.model_provider {
+            Some(id) => (id.clone(), self.config.model_providers.get(id)?.clone()),
+            None => (self.config.model_provider_id.clone(), self.provider.clone()),
+        };
+        let effort = route.model_reasoning_effort.unwrap_or(self.effort);
+        if model == self.config.model
+            && model_provider_id == self.config.model_provider_id
+            && effort == self.effort
+        {
+            return None;
+        }
+        let model_family = if model == self.config.model {
+            self.config.model_family.clone()
+        } else {
+            find_family_for_model(&model).unwrap_or_else(|| ModelFamily {
+                slug: model.clone(),
+                family: model.clone(),
+                needs_special_apply_patch_instructions: false,
+                supports_reasoning_summaries: false,
+                uses_local_shell_tool: false,
+                apply_patch_tool_type: None,
+            })
+        };
+        let mut client = self.with_model(model.clone(), model_family, model_provider_id, provider);
+        client.effort = effort;
+        Some((route.name.clone().unwrap_or(model), client))
+    }
+
+    fn with_model(
+        &self,
+        model: String,
+        model_family: ModelFamily,
+        model_provider_id: String,
+        provider: ModelProviderInfo,
+    ) -> Self {
+        let mut config = (*self.config).clone();
+        config.model_family = model_family;
+        if let Some(model_info) = get_model_info(&config.model_family) {
+            config.model_context_window = Some(model_info.context_window);
+        }
+        config.model = model;
+        config.model_provider_id = model_provider_id;
+        config.model_provider = provider.clone();
+        Self {
+            config: Arc::new(config),
+            provider,
+            ..self.clone()
+        }
+    }
+
+    /// Most tool calls of one response that run at the same time.
+    pub fn get_max_parallel_tool_calls(&self) -> usize {
+        self.config.max_parallel_tool_calls
+    }
+
+    /// Sends `prompt` unless a budget is used up, and records the usage of
+    /// the response against the budgets.
+    pub async fn stream(&self, prompt: &Prompt) -> Result<ResponseStream> {
+        self.budget
+            .check()
+            .await
+            .map_err(CodexErr::BudgetExceeded)?;
+        let response_stream = self.stream_for_wire_api(prompt).await?;
+        Ok(self.metered_stream(response_stream))
+    }
+
     /// Dispatches to either the Responses or Chat implementation depending on
     /// the provider config.  Public callers always invoke `stream()` – the
     /// specialised helpers are private to avoid accidental misuse.
-    pub async fn stream(&self, prompt: &Prompt) -> Result<ResponseStream> {
+    async fn stream_for_wire_api(&self, prompt: &Prompt) -> Result<ResponseStream> {
         match self.provider.wire_api {
             WireApi::Responses => self.stream_responses(prompt).await,
             WireApi::Chat => {
@@ -110,35 +246,108 @@ impl ModelClient {
                     &self.config.model_family,
                     &self.client,
                     &self.provider,
+                    &self.azure_entra_auth().await?,
+                )
+                .await?;
+                Ok(self.aggregate_stream(response_stream))
+            }
+            WireApi::Anthropic => {
+                let response_stream = stream_anthropic_messages(
+                    prompt,
+                    &self.config.model_family,
+                    self.config.model_max_output_tokens,
+                    self.config.prompt_caching,
+                    &self.client,
+                    &self.provider,
+                )
+                .await?;
+                Ok(self.aggregate_stream(response_stream))
+            }
+            WireApi::Gemini => {
+                let response_stream = stream_gemini(
+                    prompt,
+                    &self.config.model_family,
+                    self.config.model_max_output_tokens,
+                    &self.client,
+                    &self.provider,
                 )
                 .await?;
+                Ok(self.aggregate_stream(response_stream))
+            }
+            WireApi::Ollama => {
+                let response_stream = stream_ollama_chat(
+                    prompt,
+                    &self.config.model_family,
+                    self.config.model_max_output_tokens,
+                    self.config.model_context_window,
+                    &self.client,
+                    &self.provider,
+                )
+                .await?;
+                Ok(self.aggregate_stream(response_stream))
+            }
+        }
+    }
 
-                // Wrap it with the aggregation adapter so callers see *only*
-                // the final assistant message per turn (matching the
-                // behaviour of the Responses API).
-                let mut aggregated = if self.config.show_raw_agent_reasoning {
-                    crate::chat_completions::AggregatedChatStream::streaming_mode(response_stream)
-                } else {
-                    response_stream.aggregate()
-                };
+    /// Wrap a delta-based stream with the aggregation adapter so callers see
+    /// *only* the final assistant message per turn (matching the behaviour
+    /// of the Responses API).
+    fn aggregate_stream(&self, response_stream: ResponseStream) -> ResponseStream {
+        let mut aggregated = if self.config.show_raw_agent_reasoning {
+            crate::chat_completions::AggregatedChatStream::streaming_mode(response_stream)
+        } else {
+            response_stream.aggregate()
+        };
 
-                // Bridge the aggregated stream back into a standard
-                // `ResponseStream` by forwarding events through a channel.
-                let (tx, rx) = mpsc::channel::<Result<ResponseEvent>>(16);
+        // Bridge the aggregated stream back into a standard
+        // `ResponseStream` by forwarding events through a channel.
+        let (tx, rx) = mpsc::channel::<Result<ResponseEvent>>(16);
 
-                tokio::spawn(async move {
-                    use futures::StreamExt;
-                    while let Some(ev) = aggregated.next().await {
-                        // Exit early if receiver hung up.
-                        if tx.send(ev).await.is_err() {
-                            break;
-                        }
-                    }
-                });
+        tokio::spawn(async move {
+            use futures::StreamExt;
+            while let Some(ev) = aggregated.next().await {
+                // Exit early if receiver hung up.
+                if tx.send(ev).await.is_err() {
+                    break;
+                }
+            }
+        });
 
-                Ok(ResponseStream { rx_event: rx })
+        ResponseStream { rx_event: rx }
+    }
+
+    /// Forwards `response_stream`, recording the token usage of the completed
+    /// response before passing the completion on, so that budget warnings
+    /// are ready when the caller sees it.
+    fn metered_stream(&self, mut response_stream: ResponseStream) -> ResponseStream {
+        let (tx, rx) = mpsc::channel::<Result<ResponseEvent>>(16);
+        let budget = Arc::clone(&self.budget);
+        let model = self.config.model.clone();
+        tokio::spawn(async move {
+            while let Some(ev) = response_stream.next().await {
+                if let Ok(ResponseEvent::Completed {
+                    token_usage: Some(token_usage),
+                    ..
+                }) = &ev
+                {
+                    budget.record(&model, token_usage).await;
+                }
+                if tx.send(ev).await.is_err() {
+                    break;
+                }
             }
+        });
+        ResponseStream { rx_event: rx }
+    }
+
+    /// Entra ID token for Azure OpenAI providers configured with
+    /// `auth = "entra"`, wrapped so it is sent as a bearer token.
+    async fn azure_entra_auth(&self) -> Result<Option<CodexAuth>> {
+        if !self.provider.uses_azure_entra_auth() {
+            return Ok(None);
         }
+        let token = self.azure_auth.get_token(AZURE_OPENAI_SCOPE).await?;
+        Ok(Some(CodexAuth::from_api_key(&token.token)))
     }
 
     /// Implementation for the OpenAI *Responses* experimental API.
@@ -178,8 +387,8 @@ impl ModelClient {
         let input_with_instructions = prompt.get_formatted_input();
 
         // Only include `text.verbosity` for GPT-5 family models
-        let text = if self.config.model_family.family == "gpt-5" {
-            create_text_param_for_request(self.config.model_verbosity)
+        let verbosity = if self.config.model_family.family == "gpt-5" {
+            self.config.model_verbosity
         } else {
             if self.config.model_verbosity.is_some() {
                 warn!(
@@ -189,19 +398,23 @@ impl ModelClient {
             }
             None
         };
+        let text = create_text_param_for_request(verbosity, prompt.response_format.as_ref());
 
         let payload = ResponsesApiRequest {
-            model: &self.config.model,
+            model: self.provider.request_model(&self.config.model),
             instructions: &full_instructions,
             input: &input_with_instructions,
             tools: &tools_json,
             tool_choice: "auto",
-            parallel_tool_calls: false,
+            parallel_tool_calls: self.config.max_parallel_tool_calls > 1,
             reasoning,
             store,
             stream: true,
             include,
-            prompt_cache_key: Some(self.session_id.to_string()),
+            prompt_cache_key: self
+                .config
+                .prompt_caching
+                .then(|| self.session_id.to_string()),
             text,
         };
 
@@ -212,17 +425,20 @@ impl ModelClient {
             attempt += 1;
 
             // Always fetch the latest auth in case a prior attempt refreshed the token.
-            let auth = auth_manager.as_ref().and_then(|m| m.auth());
+            let auth = match self.azure_entra_auth().await? {
+                Some(auth) => Some(auth),
+                None => auth_manager.as_ref().and_then(|m| m.auth()),
+            };
 
             trace!(
                 "POST to {}: {}",
-                self.provider.get_full_url(&auth),
+                self.provider.get_full_url(&auth, &self.config.model),
                 serde_json::to_string(&payload)?
             );
 
             let mut req_builder = self
                 .provider
-                .create_request_builder(&self.client, &auth)
+                .create_request_builder(&self.client, &auth, &self.config.model)
                 .await?;
 
             req_builder = req_builder
@@ -272,11 +488,8 @@ impl ModelClient {
                     let status = res.status();
 
                     // Pull out Retry‑After header if present.
-                    let retry_after_secs = res
-                        .headers()
-                        .get(reqwest::header::RETRY_AFTER)
-                        .and_then(|v| v.to_str().ok())
-                        .and_then(|s| s.parse::<u64>().ok());
+
+                    let retry_after_delay = retry_after(res.headers());
 
                     if status == StatusCode::UNAUTHORIZED
                         && let Some(manager) = auth_manager.as_ref()
@@ -330,9 +543,7 @@ impl ModelClient {
                         return Err(CodexErr::RetryLimit(status));
                     }
 
-                    let delay = retry_after_secs
-                        .map(|s| Duration::from_millis(s * 1_000))
-                        .unwrap_or_else(|| backoff(attempt));
+                    let delay = retry_after_delay.unwrap_or_else(|| backoff(attempt));
                     tokio::time::sleep(delay).await;
                 }
                 Err(e) => {
@@ -765,6 +976,7 @@ mod tests {
             stream_max_retries: Some(0),
             stream_idle_timeout_ms: Some(1000),
             requires_openai_auth: false,
+            azure: None,
         };
 
         let events = collect_events(
@@ -825,6 +1037,7 @@ mod tests {
             stream_max_retries: Some(0),
             stream_idle_timeout_ms: Some(1000),
             requires_openai_auth: false,
+            azure: None,
         };
 
         let events = collect_events(&[sse1.as_bytes()], provider).await;
@@ -859,6 +1072,7 @@ mod tests {
             stream_max_retries: Some(0),
             stream_idle_timeout_ms: Some(1000),
             requires_openai_auth: false,
+            azure: None,
         };
 
         let events = collect_events(&[sse1.as_bytes()], provider).await;
@@ -964,6 +1178,7 @@ mod tests {
                 stream_max_retries: Some(0),
                 stream_idle_timeout_ms: Some(1000),
                 requires_openai_auth: false,
+                azure: None,
             };
 
             let out = run_sse(evs, provider).await;
@@ -1,15 +1,22 @@
+use crate::config_types::ModelRoute;
 use crate::config_types::Verbosity as VerbosityConfig;
+use crate::conversation_history::is_turn_start;
 use crate::error::Result;
 use crate::model_family::ModelFamily;
+use crate::model_provider_info::WireApi;
 use crate::openai_tools::OpenAiTool;
+use crate::protocol::ResponseFormat;
 use crate::protocol::TokenUsage;
+use crate::response_format::response_format_instructions;
 use codex_apply_patch::APPLY_PATCH_TOOL_INSTRUCTIONS;
 use codex_protocol::config_types::ReasoningEffort as ReasoningEffortConfig;
 use codex_protocol::config_types::ReasoningSummary as ReasoningSummaryConfig;
 use codex_protocol::models::ContentItem;
 use codex_protocol::models::ResponseItem;
 use futures::Stream;
+use serde::Deserialize;
 use serde::Serialize;
+use serde_json::json;
 use std::borrow::Cow;
 use std::pin::Pin;
 use std::task::Context;
@@ -21,7 +28,7 @@ use tokio::sync::mpsc;
 const BASE_INSTRUCTIONS: &str = include_str!("../prompt.md");
 
 /// wraps user instructions message in a tag for the model to parse more easily.
-const USER_INSTRUCTIONS_START: &str = "<user_instructions>\n\n";
+pub(crate) const USER_INSTRUCTIONS_START: &str = "<user_instructions>\n\n";
 const USER_INSTRUCTIONS_END: &str = "\n\n</user_instructions>";
 
 /// API request payload for a single model turn
@@ -39,6 +46,9 @@ pub struct Prompt {
 
     /// Optional override for the built-in BASE_INSTRUCTIONS.
     pub base_instructions_override: Option<String>,
+
+    /// JSON schema the final message has to match.
+    pub response_format: Option<ResponseFormat>,
 }
 
 impl Prompt {
@@ -65,6 +75,23 @@ impl Prompt {
         Cow::Owned(sections.join("\n"))
     }
 
+    /// The instructions for a request to `wire_api`. APIs that cannot enforce
+    /// `response_format` get the schema described in the instructions.
+    pub(crate) fn get_full_instructions_for_api(
+        &self,
+        model: &ModelFamily,
+        wire_api: WireApi,
+    ) -> Cow<'_, str> {
+        let instructions = self.get_full_instructions(model);
+        match &self.response_format {
+            Some(format) if !wire_api.supports_response_format() => Cow::Owned(format!(
+                "{instructions}\n\n{}",
+                response_format_instructions(format)
+            )),
+            _ => instructions,
+        }
+    }
+
     pub(crate) fn get_formatted_input(&self) -> Vec<ResponseItem> {
         self.input.clone()
     }
@@ -81,6 +108,59 @@ impl Prompt {
     }
 }
 
+/// Marks the parts of an Anthropic Messages request that stay the same from
+/// one request to the next with `cache_control` breakpoints: the tool
+/// definitions, the system prompt, the project doc and the conversation up to
+/// the latest message. Anthropic allows at most four breakpoints per request.
+pub(crate) fn add_anthropic_cache_breakpoints(payload: &mut serde_json::Value) {
+    let cache_control = json!({"type": "ephemeral"});
+
+    if let Some(tool) = payload
+        .get_mut("tools")
+        .and_then(|tools| tools.as_array_mut())
+        .and_then(|tools| tools.last_mut())
+    {
+        tool["cache_control"] = cache_control.clone();
+    }
+
+    if let Some(system) = payload.get("system").and_then(|s| s.as_str())
+        && !system.is_empty()
+    {
+        payload["system"] = json!([{
+            "type": "text",
+            "text": system,
+            "cache_control": cache_control,
+        }]);
+    }
+
+    let Some(messages) = payload
+        .get_mut("messages")
+        .and_then(|messages| messages.as_array_mut())
+    else {
+        return;
+    };
+    let is_project_doc = |block: &serde_json::Value| {
+        block["text"]
+            .as_str()
+            .is_some_and(|text| text.starts_with(USER_INSTRUCTIONS_START))
+    };
+    if let Some(block) = messages
+        .iter_mut()
+        .filter_map(|message| message["content"].as_array_mut())
+        .flatten()
+        .find(|block| is_project_doc(block))
+    {
+        block["cache_control"] = cache_control.clone();
+    }
+    if let Some(block) = messages
+        .last_mut()
+        .and_then(|message| message["content"].as_array_mut())
+        .and_then(|content| content.last_mut())
+    {
+        block["cache_control"] = cache_control;
+    }
+}
+
 #[derive(Debug)]
 pub enum ResponseEvent {
     Created,
@@ -104,11 +184,34 @@ pub(crate) struct Reasoning {
     pub(crate) summary: ReasoningSummaryConfig,
 }
 
-/// Controls under the `text` field in the Responses API for GPT-5.
-#[derive(Debug, Serialize, Default, Clone, Copy)]
+/// Controls under the `text` field in the Responses API.
+#[derive(Debug, Serialize, Default, Clone)]
 pub(crate) struct TextControls {
+    /// Only sent to GPT-5 models.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) verbosity: Option<OpenAiVerbosity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) format: Option<TextFormat>,
+}
+
+/// A JSON schema for the response, as `text.format` of the Responses API.
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct TextFormat {
+    r#type: &'static str,
+    name: String,
+    schema: serde_json::Value,
+    strict: bool,
+}
+
+impl From<&ResponseFormat> for TextFormat {
+    fn from(format: &ResponseFormat) -> Self {
+        Self {
+            r#type: "json_schema",
+            name: format.name.clone(),
+            schema: format.schema.clone(),
+            strict: format.strict,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Default, Clone, Copy)]
@@ -168,12 +271,149 @@ pub(crate) fn create_reasoning_param_for_request(
 
 pub(crate) fn create_text_param_for_request(
     verbosity: Option<VerbosityConfig>,
+    response_format: Option<&ResponseFormat>,
 ) -> Option<TextControls> {
-    verbosity.map(|v| TextControls {
-        verbosity: Some(v.into()),
+    if verbosity.is_none() && response_format.is_none() {
+        return None;
+    }
+    Some(TextControls {
+        verbosity: verbosity.map(OpenAiVerbosity::from),
+        format: response_format.map(TextFormat::from),
+    })
+}
+
+/// `response_format` of a Chat Completions request.
+pub(crate) fn create_chat_response_format(format: &ResponseFormat) -> serde_json::Value {
+    json!({
+        "type": "json_schema",
+        "json_schema": {
+            "name": format.name,
+            "schema": format.schema,
+            "strict": format.strict,
+        },
+    })
+}
+
+/// The first of `routes` that matches the request for `prompt`. Routes look
+/// at the user's latest message and at the tool calls made since then.
+pub(crate) fn select_model_route<'a>(
+    routes: &'a [ModelRoute],
+    prompt: &Prompt,
+) -> Option<&'a ModelRoute> {
+    if routes.is_empty() {
+        return None;
+    }
+    let index = prompt.input.iter().rposition(is_turn_start)?;
+    let message = match &prompt.input[index] {
+        ResponseItem::Message { content, .. } => content
+            .iter()
+            .filter_map(|c| match c {
+                ContentItem::InputText { text } => Some(text.to_lowercase()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    };
+    let tool_calls = prompt.input[index + 1..]
+        .iter()
+        .filter(|item| {
+            matches!(
+                item,
+                ResponseItem::FunctionCall { .. }
+                    | ResponseItem::CustomToolCall { .. }
+                    | ResponseItem::LocalShellCall { .. }
+            )
+        })
+        .count();
+    routes.iter().find(|route| {
+        (route.prompt_contains.is_empty()
+            || route
+                .prompt_contains
+                .iter()
+                .any(|phrase| message.contains(&phrase.to_lowercase())))
+            && route.min_tool_calls.is_none_or(|min| tool_calls >= min)
     })
 }
 
+/// Body of a request to an OpenAI-compatible `/embeddings` endpoint.
+#[derive(Debug, Serialize)]
+pub(crate) struct EmbeddingsApiRequest<'a> {
+    pub(crate) model: &'a str,
+    pub(crate) input: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) dimensions: Option<u32>,
+    pub(crate) encoding_format: &'static str,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EmbeddingsApiResponse {
+    pub(crate) data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EmbeddingData {
+    pub(crate) index: usize,
+    pub(crate) embedding: Vec<f32>,
+}
+
+/// Body of a request to a `/rerank` endpoint, in the shape served by Cohere,
+/// Jina, vLLM and Text Embeddings Inference.
+#[derive(Debug, Serialize)]
+pub(crate) struct RerankApiRequest<'a> {
+    pub(crate) model: &'a str,
+    pub(crate) query: &'a str,
+    pub(crate) documents: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) top_n: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RerankApiResponse {
+    pub(crate) results: Vec<RerankApiResult>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RerankApiResult {
+    pub(crate) index: usize,
+    pub(crate) relevance_score: f32,
+}
+
+/// A document's position in the list that was reranked, with its relevance
+/// to the query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RankedDocument {
+    pub index: usize,
+    pub score: f32,
+}
+
+/// Rank `documents` by the cosine similarity of their embeddings to `query`,
+/// most similar first, keeping at most `top_n`.
+pub(crate) fn rank_by_similarity(
+    query: &[f32],
+    documents: &[Vec<f32>],
+    top_n: Option<usize>,
+) -> Vec<RankedDocument> {
+    let mut ranked: Vec<RankedDocument> = documents
+        .iter()
+        .enumerate()
+        .map(|(index, document)| RankedDocument {
+            index,
+            score: cosine_similarity(query, document),
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+    ranked.truncate(top_n.unwrap_or(ranked.len()));
+    ranked
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
 pub(crate) struct ResponseStream {
     pub(crate) rx_event: mpsc::Receiver<Result<ResponseEvent>>,
 }
@@ -192,6 +432,124 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn marks_stable_anthropic_request_parts_as_cacheable() {
+        let project_doc = format!("{USER_INSTRUCTIONS_START}be brief{USER_INSTRUCTIONS_END}");
+        let mut payload = json!({
+            "system": "You are Codex.",
+            "tools": [{"name": "shell"}, {"name": "update_plan"}],
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": project_doc},
+                    {"type": "text", "text": "list files"},
+                ]},
+                {"role": "assistant", "content": [{"type": "text", "text": "ok"}]},
+                {"role": "user", "content": [{"type": "text", "text": "thanks"}]},
+            ],
+        });
+        add_anthropic_cache_breakpoints(&mut payload);
+
+        let cache_control = json!({"type": "ephemeral"});
+        assert_eq!(
+            payload,
+            json!({
+                "system": [{"type": "text", "text": "You are Codex.", "cache_control": cache_control}],
+                "tools": [{"name": "shell"}, {"name": "update_plan", "cache_control": cache_control}],
+                "messages": [
+                    {"role": "user", "content": [
+                        {"type": "text", "text": project_doc, "cache_control": cache_control},
+                        {"type": "text", "text": "list files"},
+                    ]},
+                    {"role": "assistant", "content": [{"type": "text", "text": "ok"}]},
+                    {"role": "user", "content": [
+                        {"type": "text", "text": "thanks", "cache_control": cache_control},
+                    ]},
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn cache_breakpoints_leave_missing_parts_alone() {
+        let mut payload = json!({"system": "", "messages": []});
+        add_anthropic_cache_breakpoints(&mut payload);
+        assert_eq!(payload, json!({"system": "", "messages": []}));
+    }
+
+    fn user_message(text: &str) -> ResponseItem {
+        ResponseItem::Message {
+            id: None,
+            role: "user".to_string(),
+            content: vec![ContentItem::InputText {
+                text: text.to_string(),
+            }],
+        }
+    }
+
+    fn tool_call(call_id: &str) -> ResponseItem {
+        ResponseItem::FunctionCall {
+            id: None,
+            name: "shell".to_string(),
+            arguments: "{}".to_string(),
+            call_id: call_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn model_routes_match_the_latest_user_message_and_tool_calls() {
+        let route = |name: &str, prompt_contains: &[&str], min_tool_calls| ModelRoute {
+            name: Some(name.to_string()),
+            model: None,
+            model_provider: None,
+            model_reasoning_effort: None,
+            prompt_contains: prompt_contains.iter().map(|s| s.to_string()).collect(),
+            min_tool_calls,
+        };
+        let routes = vec![
+            route("planning", &["plan", "design"], None),
+            route("tool-loop", &[], Some(2)),
+        ];
+        let selected = |input: Vec<ResponseItem>| {
+            let prompt = Prompt {
+                input,
+                ..Default::default()
+            };
+            select_model_route(&routes, &prompt).and_then(|route| route.name.clone())
+        };
+
+        assert_eq!(selected(vec![user_message("Fix the typo")]), None);
+        assert_eq!(
+            selected(vec![user_message("Write a PLAN for the migration")]),
+            Some("planning".to_string())
+        );
+        // Tool calls of earlier turns do not count.
+        assert_eq!(
+            selected(vec![
+                tool_call("1"),
+                tool_call("2"),
+                user_message("Fix the typo"),
+                tool_call("3"),
+            ]),
+            None
+        );
+        assert_eq!(
+            selected(vec![
+                user_message("Fix the typo"),
+                tool_call("1"),
+                tool_call("2"),
+            ]),
+            Some("tool-loop".to_string())
+        );
+        // Session context is not the user's message.
+        assert_eq!(
+            selected(vec![
+                user_message("Fix the typo"),
+                Prompt::format_user_instructions_message("plan carefully"),
+            ]),
+            None
+        );
+    }
+
     #[test]
     fn get_full_instructions_no_user_content() {
         let prompt = Prompt {
@@ -221,6 +579,7 @@ mod tests {
             prompt_cache_key: None,
             text: Some(TextControls {
                 verbosity: Some(OpenAiVerbosity::Low),
+                format: None,
             }),
         };
 
@@ -233,6 +592,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn serializes_response_format_as_text_format() {
+        let format = ResponseFormat {
+            name: "plan".to_string(),
+            schema: json!({"type": "object"}),
+            strict: true,
+        };
+        let text = create_text_param_for_request(None, Some(&format));
+        assert_eq!(
+            serde_json::to_value(text).expect("json"),
+            json!({"format": {
+                "type": "json_schema",
+                "name": "plan",
+                "schema": {"type": "object"},
+                "strict": true,
+            }})
+        );
+        assert!(create_text_param_for_request(None, None).is_none());
+    }
+
+    #[test]
+    fn describes_response_format_only_where_it_cannot_be_enforced() {
+        let model_family = find_family_for_model("gpt-4.1").expect("known model slug");
+        let prompt = Prompt {
+            response_format: Some(ResponseFormat {
+                name: "plan".to_string(),
+                schema: json!({"type": "object"}),
+                strict: false,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            prompt.get_full_instructions_for_api(&model_family, WireApi::Responses),
+            prompt.get_full_instructions(&model_family)
+        );
+        let instructions = prompt.get_full_instructions_for_api(&model_family, WireApi::Anthropic);
+        assert!(instructions.ends_with(
+            r#"named `plan`:
+{"type":"object"}"#
+        ));
+    }
+
     #[test]
     fn omits_text_when_not_set() {
         let input: Vec<ResponseItem> = vec![];
@@ -255,4 +656,18 @@ mod tests {
         let v = serde_json::to_value(&req).expect("json");
         assert!(v.get("text").is_none());
     }
+
+    #[test]
+    fn ranks_documents_by_embedding_similarity() {
+        let documents = vec![vec![0.0, 1.0], vec![1.0, 0.1], vec![0.0, 0.0]];
+
+        let ranked = rank_by_similarity(&[1.0, 0.0], &documents, Some(2));
+
+        assert_eq!(
+            ranked.iter().map(|doc| doc.index).collect::<Vec<_>>(),
+            vec![1, 0]
+        );
+        assert!(ranked[0].score > 0.99);
+        assert_eq!(ranked[1].score, 0.0);
+    }
 }
@@ -7,23 +7,31 @@ use std::sync::Mutex;
 use std::sync::MutexGuard;
 use std::sync::atomic::AtomicU64;
 use std::time::Duration;
+use std::time::Instant;
 
 use async_channel::Receiver;
 use async_channel::Sender;
+use chrono::Utc;
 use codex_apply_patch::ApplyPatchAction;
 use codex_apply_patch::MaybeApplyPatchVerified;
 use codex_apply_patch::maybe_parse_apply_patch_verified;
 use codex_login::AuthManager;
+use codex_protocol::protocol::ConversationBranchEvent;
+use codex_protocol::protocol::ConversationExportEvent;
 use codex_protocol::protocol::ConversationHistoryResponseEvent;
 use codex_protocol::protocol::TaskStartedEvent;
 use codex_protocol::protocol::TurnAbortReason;
 use codex_protocol::protocol::TurnAbortedEvent;
+use futures::future::BoxFuture;
 use futures::prelude::*;
+use futures::stream::FuturesUnordered;
 use mcp_types::CallToolResult;
+use mcp_types::ReadResourceResult;
 use serde::Serialize;
 use serde_json;
 use tokio::sync::oneshot;
-use tokio::task::AbortHandle;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use tracing::debug;
 use tracing::error;
 use tracing::info;
@@ -36,14 +44,72 @@ use crate::apply_patch;
 use crate::apply_patch::ApplyPatchExec;
 use crate::apply_patch::CODEX_APPLY_PATCH_ARG1;
 use crate::apply_patch::InternalApplyPatchInvocation;
+use crate::apply_patch::audit_patch_action;
 use crate::apply_patch::convert_apply_patch_to_protocol;
+use crate::approval_http::HttpApprovals;
+use crate::arm_deployments::ARM_DEPLOY_TOOL_NAME;
+use crate::arm_deployments::ARM_DEPLOYMENT_ERRORS_TOOL_NAME;
+use crate::arm_deployments::ARM_LIST_DEPLOYMENTS_TOOL_NAME;
+use crate::arm_deployments::ARM_WHAT_IF_TOOL_NAME;
+use crate::arm_deployments::ArmDeployments;
+use crate::arm_deployments::create_arm_deployment_tools;
+use crate::artifacts::Artifacts;
+use crate::artifacts::READ_ARTIFACT_TOOL_NAME;
+use crate::artifacts::create_read_artifact_tool;
+use crate::audit_log::AuditAction;
+use crate::audit_log::AuditApproval;
+use crate::audit_log::AuditLog;
+use crate::audit_log::AuditOutcome;
+use crate::azure_auth::AzureAuth;
+use crate::azure_monitor::AZURE_MONITOR_ACTIVITY_LOG_TOOL_NAME;
+use crate::azure_monitor::AZURE_MONITOR_ALERTS_TOOL_NAME;
+use crate::azure_monitor::AZURE_MONITOR_METRICS_TOOL_NAME;
+use crate::azure_monitor::AzureMonitor;
+use crate::azure_monitor::create_azure_monitor_tools;
+use crate::azure_storage;
+use crate::azure_storage::AZURE_STORAGE_GENERATE_SAS_TOOL_NAME;
+use crate::azure_storage::AZURE_STORAGE_LIST_BLOBS_TOOL_NAME;
+use crate::azure_storage::AZURE_STORAGE_LIST_CONTAINERS_TOOL_NAME;
+use crate::azure_storage::AZURE_STORAGE_READ_BLOB_TOOL_NAME;
+use crate::azure_storage::AZURE_STORAGE_UPLOAD_BLOB_TOOL_NAME;
+use crate::azure_storage::AzureStorage;
+use crate::azure_storage::create_azure_storage_tools;
+use crate::background_jobs::BACKGROUND_KILL_TOOL_NAME;
+use crate::background_jobs::BACKGROUND_LIST_TOOL_NAME;
+use crate::background_jobs::BACKGROUND_OUTPUT_TOOL_NAME;
+use crate::background_jobs::BACKGROUND_START_TOOL_NAME;
+use crate::background_jobs::BackgroundJobs;
+use crate::background_jobs::BackgroundStartParams;
+use crate::budget::Budget;
+use crate::circuit_breaker::CircuitBreaker;
 use crate::client::ModelClient;
 use crate::client_common::Prompt;
 use crate::client_common::ResponseEvent;
+use crate::command_rules::evaluate_command_rules;
 use crate::config::Config;
+use crate::config_types::CommandRule;
+use crate::config_types::CustomToolConfig;
+use crate::config_types::DockerSandbox;
+use crate::config_types::ExecLimits;
+use crate::config_types::ExecOutputConfig;
+use crate::config_types::HookEvent;
+use crate::config_types::ImagesConfig;
+use crate::config_types::McpServerConfig;
+use crate::config_types::McpToolApproval;
+use crate::config_types::SandboxBackend;
 use crate::config_types::ShellEnvironmentPolicy;
+use crate::conversation_branches::BranchRecord;
+use crate::conversation_branches::ConversationBranches;
+use crate::conversation_export::export_conversation;
 use crate::conversation_history::ConversationHistory;
+use crate::custom_tools;
+use crate::custom_tools::CustomTools;
+use crate::dry_run::dry_run_output;
+use crate::dry_run::pushes_to_remote;
+use crate::edit_files::EDIT_FILES_TOOL_NAME;
 use crate::environment_context::EnvironmentContext;
+use crate::environment_snapshot::EnvironmentSnapshot;
+use crate::environment_snapshot::REFRESH_ENVIRONMENT_TOOL_NAME;
 use crate::error::CodexErr;
 use crate::error::Result as CodexResult;
 use crate::error::SandboxErr;
@@ -53,24 +119,63 @@ use crate::exec::ExecToolCallOutput;
 use crate::exec::SandboxType;
 use crate::exec::StdoutStream;
 use crate::exec::StreamOutput;
-use crate::exec::process_exec_tool_call;
+use crate::exec::process_exec_tool_call_with_cancellation;
+use crate::exec::spawn_exec_child;
 use crate::exec_command::EXEC_COMMAND_TOOL_NAME;
 use crate::exec_command::ExecCommandParams;
 use crate::exec_command::ExecSessionManager;
 use crate::exec_command::WRITE_STDIN_TOOL_NAME;
 use crate::exec_command::WriteStdinParams;
 use crate::exec_env::create_env;
+use crate::git_tools::GIT_COMMIT_TOOL_NAME;
+use crate::git_tools::GIT_DIFF_TOOL_NAME;
+use crate::git_tools::GIT_STASH_TOOL_NAME;
+use crate::git_tools::GIT_STATUS_TOOL_NAME;
+use crate::git_tools::GitToolCall;
+use crate::hooks;
+use crate::hooks::HookContext;
+use crate::hooks::HookRun;
+use crate::hooks::Hooks;
+use crate::images;
+use crate::key_vault::KEY_VAULT_CHECK_EXPIRY_TOOL_NAME;
+use crate::key_vault::KEY_VAULT_GET_SECRET_TOOL_NAME;
+use crate::key_vault::KEY_VAULT_LIST_ITEMS_TOOL_NAME;
+use crate::key_vault::KEY_VAULT_LIST_VAULTS_TOOL_NAME;
+use crate::key_vault::KeyVault;
+use crate::key_vault::create_key_vault_tools;
+use crate::key_vault::parse_get_secret;
+use crate::knowledge_base::KB_SEARCH_TOOL_NAME;
+use crate::knowledge_base::KnowledgeBase;
+use crate::knowledge_base::create_kb_search_tool;
+use crate::kubernetes::K8S_DESCRIBE_TOOL_NAME;
+use crate::kubernetes::K8S_EVENTS_TOOL_NAME;
+use crate::kubernetes::K8S_GET_TOOL_NAME;
+use crate::kubernetes::K8S_LOGS_TOOL_NAME;
+use crate::kubernetes::Kubernetes;
+use crate::kubernetes::create_kubernetes_tools;
 use crate::mcp_connection_manager::McpConnectionManager;
+use crate::mcp_tool_call::READ_RESOURCE_TOOL_NAME;
+use crate::mcp_tool_call::create_read_resource_tool;
 use crate::mcp_tool_call::handle_mcp_tool_call;
+use crate::mcp_tool_call::handle_read_resource;
+use crate::messaging::Messaging;
+use crate::messaging::POST_MESSAGE_TOOL_NAME;
+use crate::messaging::create_post_message_tool;
 use crate::model_family::find_family_for_model;
 use crate::openai_model_info::get_model_info;
 use crate::openai_tools::ApplyPatchToolArgs;
+use crate::openai_tools::OpenAiTool;
 use crate::openai_tools::ToolsConfig;
 use crate::openai_tools::ToolsConfigParams;
 use crate::openai_tools::get_openai_tools;
+use crate::openai_tools::mcp_tools_to_openai_tools;
+use crate::openai_tools::trim_tool_descriptions;
 use crate::parse_command::parse_command;
 use crate::plan_tool::handle_update_plan;
-use crate::project_doc::get_user_instructions;
+use crate::project_doc::NestedProjectDocs;
+use crate::project_doc::ProjectDocs;
+use crate::project_doc::load_project_docs;
+use crate::project_doc::truncation_report;
 use crate::protocol::AgentMessageDeltaEvent;
 use crate::protocol::AgentMessageEvent;
 use crate::protocol::AgentReasoningDeltaEvent;
@@ -81,6 +186,8 @@ use crate::protocol::AgentReasoningSectionBreakEvent;
 use crate::protocol::ApplyPatchApprovalRequestEvent;
 use crate::protocol::AskForApproval;
 use crate::protocol::BackgroundEventEvent;
+use crate::protocol::BackgroundJobStartedEvent;
+use crate::protocol::ErrorCategory;
 use crate::protocol::ErrorEvent;
 use crate::protocol::Event;
 use crate::protocol::EventMsg;
@@ -90,26 +197,70 @@ use crate::protocol::ExecCommandEndEvent;
 use crate::protocol::FileChange;
 use crate::protocol::InputItem;
 use crate::protocol::ListCustomPromptsResponseEvent;
+use crate::protocol::McpGetPromptResponseEvent;
+use crate::protocol::McpListPromptsResponseEvent;
+use crate::protocol::McpPromptInfo;
+use crate::protocol::McpServerStatus;
+use crate::protocol::McpServerStatusEvent;
+use crate::protocol::ModelRoutedEvent;
 use crate::protocol::Op;
 use crate::protocol::PatchApplyBeginEvent;
 use crate::protocol::PatchApplyEndEvent;
+use crate::protocol::ProviderHealthEvent;
+use crate::protocol::ProviderHealthStatus;
+use crate::protocol::ResponseFormat;
 use crate::protocol::ReviewDecision;
 use crate::protocol::SandboxPolicy;
 use crate::protocol::SessionConfiguredEvent;
 use crate::protocol::StreamErrorEvent;
 use crate::protocol::Submission;
 use crate::protocol::TaskCompleteEvent;
-use crate::protocol::TurnDiffEvent;
+use crate::protocol::ToolPipelineStep;
 use crate::protocol::WebSearchBeginEvent;
 use crate::protocol::WebSearchEndEvent;
+use crate::redaction::Redactor;
+use crate::response_format::MAX_RESPONSE_FORMAT_RETRIES;
+use crate::response_format::response_format_retry_message;
+use crate::response_format::validate_response;
 use crate::rollout::RolloutRecorder;
+use crate::rollout::SessionStateSnapshot;
 use crate::safety::SafetyCheck;
 use crate::safety::assess_command_safety;
 use crate::safety::assess_safety_for_untrusted_command;
+use crate::safety::get_patch_sandbox_for_backend;
+use crate::safety::get_sandbox_for_backend;
 use crate::shell;
+use crate::sql::SQL_DESCRIBE_TABLE_TOOL_NAME;
+use crate::sql::SQL_LIST_DATABASES_TOOL_NAME;
+use crate::sql::SQL_LIST_TABLES_TOOL_NAME;
+use crate::sql::SQL_QUERY_TOOL_NAME;
+use crate::sql::Sql;
+use crate::sql::create_sql_tools;
+use crate::ticketing;
+use crate::ticketing::TICKET_COMMENT_TOOL_NAME;
+use crate::ticketing::TICKET_CREATE_TOOL_NAME;
+use crate::ticketing::TICKET_GET_TOOL_NAME;
+use crate::ticketing::TICKET_SEARCH_TOOL_NAME;
+use crate::ticketing::Ticketing;
+use crate::ticketing::create_ticketing_tools;
+use crate::token_refresh::TokenRefresher;
+use crate::tool_scheduler;
+use crate::tool_scheduler::Resource;
+use crate::tool_scheduler::ToolCallAccess;
+use crate::tool_scheduler::ToolCallScheduler;
+use crate::turn_diff_tracker::SharedTurnDiffTracker;
 use crate::turn_diff_tracker::TurnDiffTracker;
+use crate::turn_diff_tracker::WorktreeSnapshot;
+use crate::turn_diff_tracker::turn_changes_message;
+use crate::user_notification::Notifier;
 use crate::user_notification::UserNotification;
 use crate::util::backoff;
+use crate::workflow::StepLog;
+use crate::workflow::StepLogEntry;
+use crate::workflow::StepStatus;
+use crate::workflow::Workflow;
+use crate::workflow::WorkflowRun;
+use crate::workflow::WorkflowStep;
 use codex_protocol::config_types::ReasoningEffort as ReasoningEffortConfig;
 use codex_protocol::config_types::ReasoningSummary as ReasoningSummaryConfig;
 use codex_protocol::custom_prompts::CustomPrompt;
@@ -174,7 +325,11 @@ impl Codex {
         let (tx_sub, rx_sub) = async_channel::bounded(SUBMISSION_CHANNEL_CAPACITY);
         let (tx_event, rx_event) = async_channel::unbounded();
 
-        let user_instructions = get_user_instructions(&config).await;
+        let ProjectDocs {
+            user_instructions,
+            nested: nested_project_docs,
+            truncated: truncated_project_docs,
+        } = load_project_docs(&config).await;
 
         let config = Arc::new(config);
         let resume_path = config.experimental_resume.clone();
@@ -185,6 +340,8 @@ impl Codex {
             model_reasoning_effort: config.model_reasoning_effort,
             model_reasoning_summary: config.model_reasoning_summary,
             user_instructions,
+            nested_project_docs,
+            truncated_project_docs,
             base_instructions: config.base_instructions.clone(),
             approval_policy: config.approval_policy,
             sandbox_policy: config.sandbox_policy.clone(),
@@ -225,6 +382,22 @@ impl Codex {
         Ok(CodexSpawnOk { codex, session_id })
     }
 
+    /// Resume the session recorded under `session_id` in `config.codex_home`.
+    /// The history is replayed from the rollout and the configuration captured
+    /// in its latest state snapshot takes precedence over `config`.
+    pub async fn resume(
+        mut config: Config,
+        auth_manager: Arc<AuthManager>,
+        session_id: Uuid,
+    ) -> CodexResult<CodexSpawnOk> {
+        let path = crate::rollout::find_rollout_path(&config.codex_home, session_id)?
+            .ok_or(CodexErr::ConversationNotFound(session_id))?;
+        let saved = crate::rollout::read_saved_session(&path).await?;
+        apply_session_state(&mut config, saved.state);
+        config.experimental_resume = Some(path);
+        Self::spawn(config, auth_manager, None).await
+    }
+
     /// Submit the `op` wrapped in a `Submission` with a unique ID.
     pub async fn submit(&self, op: Op) -> CodexResult<String> {
         let id = self
@@ -260,10 +433,25 @@ impl Codex {
 #[derive(Default)]
 struct State {
     approved_commands: HashSet<Vec<String>>,
+    /// `server/tool` names of MCP tools approved for the rest of the session.
+    approved_mcp_tools: HashSet<String>,
     current_task: Option<AgentTask>,
     pending_approvals: HashMap<String, oneshot::Sender<ReviewDecision>>,
     pending_input: Vec<ResponseInputItem>,
     history: ConversationHistory,
+    /// Configuration recorded alongside the rollout so the session can be
+    /// resumed with the same settings.
+    rollout_state: SessionStateSnapshot,
+    /// Branches left behind by [`Op::Rewind`]; `history` holds the current one.
+    branches: ConversationBranches,
+    /// Health of the providers used so far, keyed by `provider/model`.
+    provider_breakers: HashMap<String, CircuitBreaker>,
+    /// Cancelled when the task with this sub_id is interrupted; shared by its
+    /// model stream, commands and MCP tool calls.
+    cancellation_tokens: HashMap<String, CancellationToken>,
+    /// Secrets read with `key_vault_get_secret`, by the environment variable
+    /// that holds them in later commands.
+    secret_env: HashMap<String, String>,
 }
 
 /// Context for an initialized model agent
@@ -276,18 +464,77 @@ pub(crate) struct Session {
     /// Manager for external MCP servers/tools.
     mcp_connection_manager: McpConnectionManager,
     session_manager: ExecSessionManager,
+    /// Long-running commands started with the `background_start` tool.
+    background_jobs: BackgroundJobs,
 
-    /// External notifier command (will be passed as args to exec()). When
-    /// `None` this feature is disabled.
-    notify: Option<Vec<String>>,
+    /// Delivers notifications to the `notify` program, the desktop and
+    /// webhooks.
+    notifier: Notifier,
 
     /// Optional rollout recorder for persisting the conversation transcript so
     /// sessions can be replayed or inspected later.
     rollout: Mutex<Option<RolloutRecorder>>,
     state: Mutex<State>,
     codex_linux_sandbox_exe: Option<PathBuf>,
+    docker_sandbox: DockerSandbox,
+    command_rules: Vec<CommandRule>,
+    /// Commands run around tool calls and turns.
+    hooks: Hooks,
+    /// Function tools declared in config.
+    custom_tools: CustomTools,
+    /// Masks secrets in items before they are recorded or sent to the model.
+    redactor: Redactor,
+    /// `AGENTS.md` files below the working directory not yet in the
+    /// conversation.
+    nested_project_docs: Mutex<NestedProjectDocs>,
+    /// Tamper-evident log of privileged actions; `None` when disabled.
+    audit_log: Option<AuditLog>,
     user_shell: shell::Shell,
     show_raw_agent_reasoning: bool,
+    /// Limits applied to the images attached to user messages.
+    images: ImagesConfig,
+    /// Where tool outputs too large to send to the model are saved; `None`
+    /// when disabled.
+    artifacts: Option<Artifacts>,
+    /// Documentation searched by the `kb_search` tool; `None` when not
+    /// configured.
+    knowledge_base: Option<KnowledgeBase>,
+    /// Reads metrics, alerts and the activity log for the Azure Monitor
+    /// tools; `None` when not configured.
+    azure_monitor: Option<AzureMonitor>,
+    /// Lists vault metadata and reads secrets for the Key Vault tools; `None`
+    /// when not configured.
+    key_vault: Option<KeyVault>,
+    /// Lists, reads and uploads blobs for the Azure Storage tools; `None`
+    /// when not configured.
+    azure_storage: Option<AzureStorage>,
+    arm_deployments: Option<ArmDeployments>,
+    ticketing: Option<Ticketing>,
+    messaging: Option<Messaging>,
+    sql: Option<Sql>,
+    kubernetes: Option<Kubernetes>,
+    tool_description_max_tokens: Option<usize>,
+    exec_output: ExecOutputConfig,
+
+    /// Shared Azure login, used for Entra-authenticated model providers.
+    azure_auth: Arc<AzureAuth>,
+
+    /// Request, token and cost limits shared by every turn's model client.
+    budget: Arc<Budget>,
+
+    /// Service that approval requests are sent to, for headless runs.
+    http_approvals: Option<HttpApprovals>,
+
+    /// Held while an approval request is outstanding, see
+    /// [`Session::request_approval`].
+    approval_gate: Arc<tokio::sync::Mutex<()>>,
+
+    /// Held by the running task. An interrupted task keeps it until it has
+    /// recorded its partial results, so the next task starts after that.
+    task_gate: tokio::sync::Mutex<()>,
+
+    /// Renews OAuth tokens ahead of expiry for as long as the session lives.
+    _token_refresher: TokenRefresher,
 }
 
 /// The context needed for a single turn of the conversation.
@@ -303,15 +550,24 @@ pub(crate) struct TurnContext {
     pub(crate) approval_policy: AskForApproval,
     pub(crate) sandbox_policy: SandboxPolicy,
     pub(crate) shell_environment_policy: ShellEnvironmentPolicy,
+    pub(crate) exec_limits: ExecLimits,
+    pub(crate) sandbox_backend: SandboxBackend,
     pub(crate) disable_response_storage: bool,
     pub(crate) tools_config: ToolsConfig,
+    /// JSON schema the final message of the turn has to match.
+    pub(crate) response_format: Option<ResponseFormat>,
+    /// Tools that change remote systems return the request they would send
+    /// instead of sending it.
+    pub(crate) dry_run: bool,
 }
 
 impl TurnContext {
     fn resolve_path(&self, path: Option<String>) -> PathBuf {
-        path.as_ref()
+        let path = path
+            .as_ref()
             .map(PathBuf::from)
-            .map_or_else(|| self.cwd.clone(), |p| self.cwd.join(p))
+            .map_or_else(|| self.cwd.clone(), |p| self.cwd.join(p));
+        crate::windows_path::normalize_path(&path)
     }
 }
 
@@ -328,6 +584,10 @@ struct ConfigureSession {
 
     /// Model instructions that are appended to the base instructions.
     user_instructions: Option<String>,
+    /// `AGENTS.md` files that apply to parts of the working directory only.
+    nested_project_docs: NestedProjectDocs,
+    /// Project docs that did not fit into `project_doc_max_bytes`.
+    truncated_project_docs: Vec<String>,
 
     /// Base instructions override.
     base_instructions: Option<String>,
@@ -370,6 +630,8 @@ impl Session {
             model_reasoning_effort,
             model_reasoning_summary,
             user_instructions,
+            nested_project_docs,
+            truncated_project_docs,
             base_instructions,
             approval_policy,
             sandbox_policy,
@@ -385,6 +647,14 @@ impl Session {
 
         // Error messages to dispatch after SessionConfigured is sent.
         let mut post_session_configured_error_events = Vec::<Event>::new();
+        if !truncated_project_docs.is_empty() {
+            post_session_configured_error_events.push(Event {
+                id: INITIAL_SUBMIT_ID.to_owned(),
+                msg: EventMsg::BackgroundEvent(BackgroundEventEvent {
+                    message: truncation_report(&truncated_project_docs),
+                }),
+            });
+        }
 
         // Kick off independent async setup tasks in parallel to reduce startup latency.
         //
@@ -392,9 +662,10 @@ impl Session {
         // - spin up MCP connection manager
         // - perform default shell discovery
         // - load history metadata
+        // - collect the environment snapshot
         let rollout_fut = async {
             match resume_path.as_ref() {
-                Some(path) => RolloutRecorder::resume(path, cwd.clone())
+                Some(path) => RolloutRecorder::resume(&config, path, cwd.clone())
                     .await
                     .map(|(rec, saved)| (saved.session_id, Some(saved), rec)),
                 None => {
@@ -409,21 +680,43 @@ impl Session {
         let mcp_fut = McpConnectionManager::new(config.mcp_servers.clone());
         let default_shell_fut = shell::default_user_shell();
         let history_meta_fut = crate::message_history::history_metadata(&config);
+        let environment_snapshot_fut = async {
+            if config.environment_snapshot {
+                let env = create_env(&config.shell_environment_policy);
+                Some(EnvironmentSnapshot::collect(&cwd, &env).await)
+            } else {
+                None
+            }
+        };
 
         // Join all independent futures.
-        let (rollout_res, mcp_res, default_shell, (history_log_id, history_entry_count)) =
-            tokio::join!(rollout_fut, mcp_fut, default_shell_fut, history_meta_fut);
+        let (
+            rollout_res,
+            mcp_res,
+            default_shell,
+            (history_log_id, history_entry_count),
+            environment_snapshot,
+        ) = tokio::join!(
+            rollout_fut,
+            mcp_fut,
+            default_shell_fut,
+            history_meta_fut,
+            environment_snapshot_fut
+        );
 
         // Handle rollout result, which determines the session_id.
         struct RolloutResult {
             session_id: Uuid,
             rollout_recorder: Option<RolloutRecorder>,
             restored_items: Option<Vec<ResponseItem>>,
+            branches: ConversationBranches,
         }
         let rollout_result = match rollout_res {
             Ok((session_id, maybe_saved, recorder)) => {
+                let mut branches = ConversationBranches::default();
                 let restored_items: Option<Vec<ResponseItem>> = initial_history.or_else(|| {
                     maybe_saved.and_then(|saved_session| {
+                        branches = saved_session.branches;
                         if saved_session.items.is_empty() {
                             None
                         } else {
@@ -435,6 +728,7 @@ impl Session {
                     session_id,
                     rollout_recorder: Some(recorder),
                     restored_items,
+                    branches,
                 }
             }
             Err(e) => {
@@ -449,6 +743,7 @@ impl Session {
                     id: INITIAL_SUBMIT_ID.to_owned(),
                     msg: EventMsg::Error(ErrorEvent {
                         message: message.clone(),
+                        ..Default::default()
                     }),
                 });
                 warn!("{message}");
@@ -457,6 +752,7 @@ impl Session {
                     session_id: Uuid::new_v4(),
                     rollout_recorder: None,
                     restored_items: None,
+                    branches: ConversationBranches::default(),
                 }
             }
         };
@@ -465,11 +761,13 @@ impl Session {
             session_id,
             rollout_recorder,
             restored_items,
+            branches,
         } = rollout_result;
 
         // Create the mutable state for the Session.
         let mut state = State {
             history: ConversationHistory::new(),
+            branches,
             ..Default::default()
         };
         if let Some(restored_items) = restored_items {
@@ -484,7 +782,10 @@ impl Session {
                 error!("{message}");
                 post_session_configured_error_events.push(Event {
                     id: INITIAL_SUBMIT_ID.to_owned(),
-                    msg: EventMsg::Error(ErrorEvent { message }),
+                    msg: EventMsg::Error(ErrorEvent {
+                        message,
+                        ..Default::default()
+                    }),
                 });
                 (McpConnectionManager::default(), Default::default())
             }
@@ -497,13 +798,21 @@ impl Session {
                 error!("{message}");
                 post_session_configured_error_events.push(Event {
                     id: INITIAL_SUBMIT_ID.to_owned(),
-                    msg: EventMsg::Error(ErrorEvent { message }),
+                    msg: EventMsg::Error(ErrorEvent {
+                        message,
+                        ..Default::default()
+                    }),
                 });
             }
         }
 
         // Now that `session_id` is final (may have been updated by resume),
         // construct the model client.
+        let azure_auth = Arc::new(AzureAuth::new(
+            config.azure_auth.clone(),
+            &config.codex_home,
+        ));
+        let budget = Arc::new(Budget::new(&config, session_id));
         let client = ModelClient::new(
             config.clone(),
             Some(auth_manager.clone()),
@@ -511,6 +820,8 @@ impl Session {
             model_reasoning_effort,
             model_reasoning_summary,
             session_id,
+            azure_auth.clone(),
+            budget.clone(),
         );
         let turn_context = TurnContext {
             client,
@@ -523,31 +834,157 @@ impl Session {
                 include_web_search_request: config.tools_web_search_request,
                 use_streamable_shell_tool: config.use_experimental_streamable_shell_tool,
                 include_view_image_tool: config.include_view_image_tool,
+                include_background_jobs_tool: config.include_background_jobs_tool,
+                include_git_tools: config.include_git_tools,
+                include_edit_files_tool: config.include_edit_files_tool,
+                include_refresh_environment_tool: config.environment_snapshot,
             }),
             user_instructions,
             base_instructions,
             approval_policy,
             sandbox_policy,
             shell_environment_policy: config.shell_environment_policy.clone(),
+            exec_limits: config.exec_limits,
+            sandbox_backend: config.sandbox_backend,
             cwd,
             disable_response_storage,
+            response_format: None,
+            dry_run: config.dry_run,
         };
+        state.rollout_state = session_state_snapshot(&config, &turn_context);
+        let redactor = Redactor::new(&config.redaction)?;
+        let mut builtin_tools = get_openai_tools(&turn_context.tools_config, None);
+        builtin_tools.push(create_read_resource_tool(&[]));
+        builtin_tools.push(OpenAiTool::Function(create_read_artifact_tool()));
+        builtin_tools.push(OpenAiTool::Function(create_kb_search_tool()));
+        builtin_tools.extend(
+            create_azure_monitor_tools()
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+        builtin_tools.extend(
+            create_key_vault_tools()
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+        builtin_tools.extend(
+            create_azure_storage_tools()
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+        builtin_tools.extend(
+            create_arm_deployment_tools()
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+        builtin_tools.extend(
+            create_ticketing_tools()
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+        builtin_tools.push(OpenAiTool::Function(create_post_message_tool(
+            &HashMap::new(),
+        )));
+        builtin_tools.extend(
+            create_sql_tools(&HashMap::new())
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+        let (custom_tools, custom_tool_errors) =
+            CustomTools::new(&config.custom_tools, &builtin_tools);
+        for message in custom_tool_errors {
+            error!("{message}");
+            post_session_configured_error_events.push(Event {
+                id: INITIAL_SUBMIT_ID.to_owned(),
+                msg: EventMsg::Error(ErrorEvent {
+                    message,
+                    ..Default::default()
+                }),
+            });
+        }
         let sess = Arc::new(Session {
             session_id,
             tx_event: tx_event.clone(),
             mcp_connection_manager,
             session_manager: ExecSessionManager::default(),
-            notify,
+            background_jobs: BackgroundJobs::default(),
+            notifier: Notifier::new(notify, config.notifications.clone()),
             state: Mutex::new(state),
             rollout: Mutex::new(rollout_recorder),
             codex_linux_sandbox_exe: config.codex_linux_sandbox_exe.clone(),
+            docker_sandbox: config.sandbox_docker.clone(),
+            command_rules: config.command_rules.clone(),
+            hooks: Hooks::new(config.hooks.clone()),
+            custom_tools,
+            redactor,
+            nested_project_docs: Mutex::new(nested_project_docs),
+            audit_log: AuditLog::new(&config.codex_home, session_id, &config.audit),
             user_shell: default_shell,
             show_raw_agent_reasoning: config.show_raw_agent_reasoning,
+            images: config.images,
+            artifacts: Artifacts::new(&config.codex_home, session_id, config.artifacts),
+            knowledge_base: KnowledgeBase::new(&config, Some(auth_manager.clone())),
+            azure_monitor: config
+                .azure_monitor
+                .clone()
+                .map(|azure_monitor| AzureMonitor::new(azure_monitor, azure_auth.clone())),
+            key_vault: config
+                .key_vault
+                .clone()
+                .map(|key_vault| KeyVault::new(key_vault, azure_auth.clone())),
+            azure_storage: config
+                .azure_storage
+                .clone()
+                .map(|azure_storage| AzureStorage::new(azure_storage, azure_auth.clone())),
+            arm_deployments: config
+                .arm_deployments
+                .clone()
+                .map(|arm_deployments| ArmDeployments::new(arm_deployments, azure_auth.clone())),
+            ticketing: config.ticketing.clone().map(Ticketing::new),
+            messaging: config
+                .messaging
+                .clone()
+                .filter(|messaging| !messaging.channels.is_empty())
+                .map(Messaging::new),
+            sql: config
+                .sql
+                .clone()
+                .filter(|sql| !sql.profiles.is_empty())
+                .map(Sql::new),
+            kubernetes: config
+                .kubernetes
+                .clone()
+                .filter(|kubernetes| !kubernetes.contexts.is_empty())
+                .map(Kubernetes::new),
+            tool_description_max_tokens: config.tool_description_max_tokens,
+            exec_output: config.exec_output,
+            http_approvals: config
+                .approval_http
+                .clone()
+                .map(|approval_http| HttpApprovals::new(approval_http, session_id)),
+            approval_gate: Arc::new(tokio::sync::Mutex::new(())),
+            task_gate: tokio::sync::Mutex::new(()),
+            _token_refresher: TokenRefresher::spawn(auth_manager.clone(), azure_auth.clone()),
+            azure_auth,
+            budget,
         });
 
+        let tx_mcp_status = tx_event.clone();
+        sess.mcp_connection_manager
+            .spawn_health_checks(config.mcp_health, move |status| {
+                let tx_event = tx_mcp_status.clone();
+                async move {
+                    let event = Event {
+                        id: INITIAL_SUBMIT_ID.to_owned(),
+                        msg: EventMsg::McpServerStatus(status),
+                    };
+                    tx_event.send(event).await.ok();
+                }
+            });
+
         // record the initial user instructions and environment context,
         // regardless of whether we restored items.
-        let mut conversation_items = Vec::<ResponseItem>::with_capacity(2);
+        let mut conversation_items = Vec::<ResponseItem>::with_capacity(3);
         if let Some(user_instructions) = turn_context.user_instructions.as_deref() {
             conversation_items.push(Prompt::format_user_instructions_message(user_instructions));
         }
@@ -557,6 +994,9 @@ impl Session {
             Some(turn_context.sandbox_policy.clone()),
             Some(sess.user_shell.clone()),
         )));
+        if let Some(environment_snapshot) = &environment_snapshot {
+            conversation_items.push(ResponseItem::from(environment_snapshot));
+        }
         sess.record_conversation_items(&conversation_items).await;
 
         // Dispatch the SessionConfiguredEvent first and then report any errors.
@@ -582,6 +1022,9 @@ impl Session {
     pub fn set_task(&self, task: AgentTask) {
         let mut state = self.state.lock_unchecked();
         if let Some(current_task) = state.current_task.take() {
+            if current_task.sub_id != task.sub_id {
+                state.cancellation_tokens.remove(&current_task.sub_id);
+            }
             current_task.abort(TurnAbortReason::Replaced);
         }
         state.current_task = Some(task);
@@ -589,6 +1032,7 @@ impl Session {
 
     pub fn remove_task(&self, sub_id: &str) {
         let mut state = self.state.lock_unchecked();
+        state.cancellation_tokens.remove(sub_id);
         if let Some(task) = &state.current_task
             && task.sub_id == sub_id
         {
@@ -596,6 +1040,98 @@ impl Session {
         }
     }
 
+    /// Wait for the previous task to finish, see [`Session::task_gate`].
+    /// Returns `None` when `cancel` fires first.
+    async fn enter_task(
+        &self,
+        cancel: &CancellationToken,
+    ) -> Option<tokio::sync::MutexGuard<'_, ()>> {
+        tokio::select! {
+            guard = self.task_gate.lock() => Some(guard),
+            _ = cancel.cancelled() => None,
+        }
+    }
+
+    /// The token that is cancelled when the task `sub_id` is interrupted.
+    /// Work outside of a task gets a token that is never cancelled.
+    pub(crate) fn cancellation_token(&self, sub_id: &str) -> CancellationToken {
+        let state = self.state.lock_unchecked();
+        state
+            .cancellation_tokens
+            .get(sub_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Drop the last `num_turns` user turns from the history and continue on
+    /// a new branch. The branch being left is kept so it can be switched
+    /// back to.
+    async fn rewind(&self, num_turns: usize) -> Result<ConversationBranchEvent, String> {
+        let (record, event) = {
+            let mut state = self.state.lock_unchecked();
+            if state.current_task.is_some() {
+                return Err("cannot rewind while a task is running".to_string());
+            }
+            let previous_branch_id = state.branches.current();
+            let mut items = state.history.contents();
+            let Some(branch_id) = state.branches.rewind(&mut items, num_turns) else {
+                return Err(format!(
+                    "cannot rewind {num_turns} turns: the conversation has {} turns",
+                    state.history.turn_count()
+                ));
+            };
+            state.history.replace(items);
+            (
+                BranchRecord::Rewind {
+                    branch_id,
+                    num_turns,
+                },
+                ConversationBranchEvent {
+                    branch_id,
+                    previous_branch_id,
+                    num_turns: state.history.turn_count(),
+                },
+            )
+        };
+        self.record_branch(record).await;
+        Ok(event)
+    }
+
+    /// Continue on a branch left by an earlier rewind.
+    async fn switch_branch(&self, branch_id: u32) -> Result<ConversationBranchEvent, String> {
+        let event = {
+            let mut state = self.state.lock_unchecked();
+            if state.current_task.is_some() {
+                return Err("cannot switch branches while a task is running".to_string());
+            }
+            let previous_branch_id = state.branches.current();
+            let mut items = state.history.contents();
+            if !state.branches.switch(&mut items, branch_id) {
+                return Err(format!("unknown conversation branch: {branch_id}"));
+            }
+            state.history.replace(items);
+            ConversationBranchEvent {
+                branch_id,
+                previous_branch_id,
+                num_turns: state.history.turn_count(),
+            }
+        };
+        self.record_branch(BranchRecord::Switch { branch_id }).await;
+        Ok(event)
+    }
+
+    async fn record_branch(&self, record: BranchRecord) {
+        let recorder = {
+            let guard = self.rollout.lock_unchecked();
+            guard.as_ref().cloned()
+        };
+        if let Some(rec) = recorder
+            && let Err(e) = rec.record_branch(record).await
+        {
+            error!("failed to record rollout branch: {e:#}");
+        }
+    }
+
     /// Sends the given event to the client and swallows the send event, if
     /// any, logging it as an error.
     pub(crate) async fn send_event(&self, event: Event) {
@@ -611,23 +1147,19 @@ impl Session {
         command: Vec<String>,
         cwd: PathBuf,
         reason: Option<String>,
+        matched_rule: Option<String>,
     ) -> oneshot::Receiver<ReviewDecision> {
-        let (tx_approve, rx_approve) = oneshot::channel();
-        let event = Event {
-            id: sub_id.clone(),
-            msg: EventMsg::ExecApprovalRequest(ExecApprovalRequestEvent {
+        self.request_approval(
+            sub_id,
+            EventMsg::ExecApprovalRequest(ExecApprovalRequestEvent {
                 call_id,
                 command,
                 cwd,
                 reason,
+                matched_rule,
             }),
-        };
-        let _ = self.tx_event.send(event).await;
-        {
-            let mut state = self.state.lock_unchecked();
-            state.pending_approvals.insert(sub_id, tx_approve);
-        }
-        rx_approve
+        )
+        .await
     }
 
     pub async fn request_patch_approval(
@@ -638,22 +1170,125 @@ impl Session {
         reason: Option<String>,
         grant_root: Option<PathBuf>,
     ) -> oneshot::Receiver<ReviewDecision> {
-        let (tx_approve, rx_approve) = oneshot::channel();
-        let event = Event {
-            id: sub_id.clone(),
-            msg: EventMsg::ApplyPatchApprovalRequest(ApplyPatchApprovalRequestEvent {
+        self.request_file_changes_approval(
+            sub_id,
+            call_id,
+            convert_apply_patch_to_protocol(action),
+            reason,
+            grant_root,
+        )
+        .await
+    }
+
+    pub(crate) async fn request_file_changes_approval(
+        &self,
+        sub_id: String,
+        call_id: String,
+        changes: HashMap<PathBuf, FileChange>,
+        reason: Option<String>,
+        grant_root: Option<PathBuf>,
+    ) -> oneshot::Receiver<ReviewDecision> {
+        self.request_approval(
+            sub_id,
+            EventMsg::ApplyPatchApprovalRequest(ApplyPatchApprovalRequestEvent {
                 call_id,
-                changes: convert_apply_patch_to_protocol(action),
+                changes,
                 reason,
                 grant_root,
             }),
+        )
+        .await
+    }
+
+    /// Send an approval request and return the receiver of the decision.
+    /// Decisions are routed by `sub_id`, which the tool calls of a turn
+    /// share, so only one request is outstanding at a time: the others wait
+    /// until it is answered. With `[approval_http]` configured, the request
+    /// also goes to the approval service and whichever decides first wins.
+    async fn request_approval(
+        &self,
+        sub_id: String,
+        msg: EventMsg,
+    ) -> oneshot::Receiver<ReviewDecision> {
+        let gate = Arc::clone(&self.approval_gate).lock_owned().await;
+        let (tx_approve, rx_approve) = oneshot::channel();
+        if let Some(summary) = approval_summary(&msg) {
+            self.maybe_notify(UserNotification::ApprovalRequested {
+                turn_id: sub_id.clone(),
+                summary,
+            });
+        }
+        let http_decision = match &self.http_approvals {
+            Some(http_approvals) => Some(
+                self.request_http_approval(http_approvals, &sub_id, &msg)
+                    .await,
+            ),
+            None => None,
+        };
+        let event = Event {
+            id: sub_id.clone(),
+            msg,
         };
         let _ = self.tx_event.send(event).await;
         {
             let mut state = self.state.lock_unchecked();
             state.pending_approvals.insert(sub_id, tx_approve);
         }
-        rx_approve
+        let (tx_decision, rx_decision) = oneshot::channel();
+        tokio::spawn(async move {
+            // The sender is dropped, and the gate released, when the task is
+            // interrupted.
+            let decision = match http_decision {
+                Some(mut http_decision) => {
+                    let decision = tokio::select! {
+                        decision = rx_approve => decision.ok(),
+                        decision = &mut http_decision => decision.ok(),
+                    };
+                    http_decision.abort();
+                    decision
+                }
+                None => rx_approve.await.ok(),
+            };
+            if let Some(decision) = decision {
+                let _ = tx_decision.send(decision);
+            }
+            drop(gate);
+        });
+        rx_decision
+    }
+
+    /// Sends the approval request in `msg` to the approval service and
+    /// returns the task waiting for its decision. When the service cannot be
+    /// reached, the request is denied.
+    async fn request_http_approval(
+        &self,
+        http_approvals: &HttpApprovals,
+        sub_id: &str,
+        msg: &EventMsg,
+    ) -> tokio::task::JoinHandle<ReviewDecision> {
+        let pending = match http_approvals.send(sub_id, msg).await {
+            Ok(pending) => pending,
+            Err(e) => {
+                let message = format!(
+                    "failed to send approval request to {}: {e:#}",
+                    http_approvals.request_url()
+                );
+                error!("{message}");
+                self.notify_background_event(sub_id, message).await;
+                return tokio::spawn(async { ReviewDecision::Denied });
+            }
+        };
+        self.notify_background_event(
+            sub_id,
+            format!(
+                "waiting for approval {} from {}",
+                pending.id,
+                http_approvals.request_url()
+            ),
+        )
+        .await;
+        let http_approvals = http_approvals.clone();
+        tokio::spawn(async move { http_approvals.decision(&pending).await })
     }
 
     pub fn notify_approval(&self, sub_id: &str, decision: ReviewDecision) {
@@ -676,17 +1311,78 @@ impl Session {
         state.approved_commands.insert(cmd);
     }
 
+    /// Whether calls to `tool` of `server` still need the user's approval.
+    pub(crate) fn mcp_tool_needs_approval(&self, server: &str, tool: &str) -> bool {
+        self.mcp_connection_manager.tool_approval(server, tool) == McpToolApproval::Prompt
+            && !self
+                .state
+                .lock_unchecked()
+                .approved_mcp_tools
+                .contains(&format!("{server}/{tool}"))
+    }
+
+    pub(crate) fn add_approved_mcp_tool(&self, server: &str, tool: &str) {
+        let mut state = self.state.lock_unchecked();
+        state.approved_mcp_tools.insert(format!("{server}/{tool}"));
+    }
+
+    /// Set `value` as `env_var` in the environment of later commands and
+    /// mask it in everything recorded from now on.
+    fn add_secret_env(&self, env_var: String, value: String) {
+        self.redactor.add_secret(&value);
+        let mut state = self.state.lock_unchecked();
+        state.secret_env.insert(env_var, value);
+    }
+
+    /// The secrets read with `key_vault_get_secret`, for the environment of a
+    /// command.
+    fn secret_env(&self) -> HashMap<String, String> {
+        self.state.lock_unchecked().secret_env.clone()
+    }
+
+    /// Append a record to the audit log, if it is enabled.
+    pub(crate) async fn record_audit(
+        &self,
+        action: AuditAction,
+        approval: AuditApproval,
+        outcome: Option<AuditOutcome>,
+    ) {
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.record(action, approval, outcome).await;
+        }
+    }
+
+    /// Run the hooks for `context`, reporting the ones that fail to the
+    /// client.
+    async fn run_hooks(&self, sub_id: &str, context: &HookContext<'_>) -> Vec<HookRun> {
+        let runs = self.hooks.run(context).await;
+        for run in runs.iter().filter(|run| !run.success) {
+            self.notify_background_event(sub_id, run.describe()).await;
+        }
+        runs
+    }
+
+    /// Whether calls to `tool` of the MCP `server` go in the audit log.
+    pub(crate) fn audits_mcp_tool(&self, server: &str, tool: &str) -> bool {
+        self.audit_log
+            .as_ref()
+            .is_some_and(|audit_log| audit_log.audits_mcp_tool(server, tool))
+    }
+
     /// Records items to both the rollout and the chat completions/ZDR
-    /// transcript, if enabled.
+    /// transcript, if enabled. Secrets in the items are masked first; the
+    /// rollout also gets the model's own messages and tool calls masked.
     async fn record_conversation_items(&self, items: &[ResponseItem]) {
+        let items = self.redactor.redact_items(items);
         debug!("Recording items for conversation: {items:?}");
-        self.record_state_snapshot(items).await;
+        self.record_state_snapshot(&self.redactor.redact_rollout_items(&items))
+            .await;
 
-        self.state.lock_unchecked().history.record_items(items);
+        self.state.lock_unchecked().history.record_items(&items);
     }
 
     async fn record_state_snapshot(&self, items: &[ResponseItem]) {
-        let snapshot = { crate::rollout::SessionStateSnapshot {} };
+        let snapshot = self.state.lock_unchecked().rollout_state.clone();
 
         let recorder = {
             let guard = self.rollout.lock_unchecked();
@@ -705,7 +1401,7 @@ impl Session {
 
     async fn on_exec_command_begin(
         &self,
-        turn_diff_tracker: &mut TurnDiffTracker,
+        turn_diff_tracker: &SharedTurnDiffTracker,
         exec_command_context: ExecCommandContext,
     ) {
         let ExecCommandContext {
@@ -714,13 +1410,19 @@ impl Session {
             command_for_display,
             cwd,
             apply_patch,
+            ..
         } = exec_command_context;
+        let touched_paths = match &apply_patch {
+            Some(ApplyPatchCommandContext { changes, .. }) => changes.keys().cloned().collect(),
+            None => vec![cwd.clone()],
+        };
+        self.add_scoped_project_docs(&sub_id, &touched_paths).await;
         let msg = match apply_patch {
             Some(ApplyPatchCommandContext {
                 user_explicitly_approved_this_action,
                 changes,
             }) => {
-                turn_diff_tracker.on_patch_begin(&changes);
+                turn_diff_tracker.lock_unchecked().on_patch_begin(&changes);
 
                 EventMsg::PatchApplyBegin(PatchApplyBeginEvent {
                     call_id,
@@ -745,9 +1447,32 @@ impl Session {
         let _ = self.tx_event.send(event).await;
     }
 
+    /// Adds the nested `AGENTS.md` files that apply to `paths` to the
+    /// conversation, unless they were added before.
+    async fn add_scoped_project_docs(&self, sub_id: &str, paths: &[PathBuf]) {
+        let docs = self
+            .nested_project_docs
+            .lock_unchecked()
+            .take_for_paths(paths);
+        for doc in docs {
+            if let Some(message) = doc.message
+                && let Err(input) = self.inject_input(vec![InputItem::Text { text: message }])
+            {
+                // No task is running, so the doc has to wait for the next
+                // one; record it right away instead.
+                self.record_conversation_items(&[ResponseInputItem::from(input).into()])
+                    .await;
+            }
+            if let Some(truncated) = doc.truncated {
+                self.notify_background_event(sub_id, truncation_report(&[truncated]))
+                    .await;
+            }
+        }
+    }
+
     async fn on_exec_command_end(
         &self,
-        turn_diff_tracker: &mut TurnDiffTracker,
+        turn_diff_tracker: &SharedTurnDiffTracker,
         sub_id: &str,
         call_id: &str,
         output: &ExecToolCallOutput,
@@ -794,15 +1519,23 @@ impl Session {
         // If this is an apply_patch, after we emit the end patch, emit a second event
         // with the full turn diff if there is one.
         if is_apply_patch {
-            let unified_diff = turn_diff_tracker.get_unified_diff();
-            if let Ok(Some(unified_diff)) = unified_diff {
-                let msg = EventMsg::TurnDiff(TurnDiffEvent { unified_diff });
-                let event = Event {
-                    id: sub_id.into(),
-                    msg,
-                };
-                let _ = self.tx_event.send(event).await;
-            }
+            self.send_turn_diff(turn_diff_tracker, sub_id).await;
+        }
+    }
+
+    /// Sends the diff of the files changed so far in the turn, if any were.
+    pub(crate) async fn send_turn_diff(
+        &self,
+        turn_diff_tracker: &SharedTurnDiffTracker,
+        sub_id: &str,
+    ) {
+        let turn_diff = turn_diff_tracker.lock_unchecked().get_turn_diff();
+        if let Some(turn_diff) = turn_diff {
+            let event = Event {
+                id: sub_id.to_string(),
+                msg: EventMsg::TurnDiff(turn_diff),
+            };
+            let _ = self.tx_event.send(event).await;
         }
     }
     /// Runs the exec tool call and emits events for the begin and end of the
@@ -811,7 +1544,7 @@ impl Session {
     /// Returns the output of the exec tool call.
     async fn run_exec_with_events<'a>(
         &self,
-        turn_diff_tracker: &mut TurnDiffTracker,
+        turn_diff_tracker: &SharedTurnDiffTracker,
         begin_ctx: ExecCommandContext,
         exec_args: ExecInvokeArgs<'a>,
     ) -> crate::error::Result<ExecToolCallOutput> {
@@ -819,22 +1552,41 @@ impl Session {
         let sub_id = begin_ctx.sub_id.clone();
         let call_id = begin_ctx.call_id.clone();
 
+        // Patches are tracked by their changes; for other commands, compare
+        // the worktree before and after.
+        let worktree_snapshot = if is_apply_patch {
+            None
+        } else {
+            let cwd = begin_ctx.cwd.clone();
+            tokio::task::spawn_blocking(move || WorktreeSnapshot::capture(&cwd))
+                .await
+                .ok()
+                .flatten()
+        };
+
         self.on_exec_command_begin(turn_diff_tracker, begin_ctx.clone())
             .await;
 
-        let result = process_exec_tool_call(
+        let result = process_exec_tool_call_with_cancellation(
             exec_args.params,
             exec_args.sandbox_type,
             exec_args.sandbox_policy,
             exec_args.codex_linux_sandbox_exe,
+            exec_args.docker_sandbox,
             exec_args.stdout_stream,
+            self.cancellation_token(&sub_id),
         )
         .await;
 
         let output_stderr;
-        let borrowed: &ExecToolCallOutput = match &result {
-            Ok(output) => output,
-            Err(e) => {
+        let partial_output = match &result {
+            Err(CodexErr::Sandbox(error)) => error.partial_output(),
+            _ => None,
+        };
+        let borrowed: &ExecToolCallOutput = match (&result, partial_output) {
+            (Ok(output), _) => output,
+            (Err(_), Some(output)) => output,
+            (Err(e), None) => {
                 output_stderr = ExecToolCallOutput {
                     exit_code: -1,
                     stdout: StreamOutput::new(String::new()),
@@ -854,6 +1606,14 @@ impl Session {
         )
         .await;
 
+        if let Some(snapshot) = worktree_snapshot
+            && let Ok(changes) = tokio::task::spawn_blocking(move || snapshot.changes()).await
+            && !changes.is_empty()
+        {
+            turn_diff_tracker.lock_unchecked().on_exec_end(changes);
+            self.send_turn_diff(turn_diff_tracker, &sub_id).await;
+        }
+
         result
     }
 
@@ -870,6 +1630,77 @@ impl Session {
         let _ = self.tx_event.send(event).await;
     }
 
+    async fn notify_provider_health(
+        &self,
+        sub_id: &str,
+        client: &ModelClient,
+        status: ProviderHealthStatus,
+        message: String,
+    ) {
+        let event = Event {
+            id: sub_id.to_string(),
+            msg: EventMsg::ProviderHealth(ProviderHealthEvent {
+                provider: client.get_provider().name,
+                model: client.get_model(),
+                status,
+                message,
+            }),
+        };
+        let _ = self.tx_event.send(event).await;
+    }
+
+    /// Remaining cooldown while the provider of `client` is unhealthy.
+    fn provider_cooldown(&self, client: &ModelClient) -> Option<Duration> {
+        let state = self.state.lock_unchecked();
+        state
+            .provider_breakers
+            .get(&client.provider_key())
+            .and_then(|breaker| breaker.check(Instant::now()).err())
+    }
+
+    async fn record_provider_success(&self, sub_id: &str, client: &ModelClient) {
+        let recovered = {
+            let mut state = self.state.lock_unchecked();
+            state
+                .provider_breakers
+                .get_mut(&client.provider_key())
+                .is_some_and(CircuitBreaker::record_success)
+        };
+        if recovered {
+            self.notify_provider_health(
+                sub_id,
+                client,
+                ProviderHealthStatus::Recovered,
+                "requests succeed again".to_string(),
+            )
+            .await;
+        }
+    }
+
+    async fn record_provider_failure(&self, sub_id: &str, client: &ModelClient, error: &CodexErr) {
+        let opened = {
+            let mut state = self.state.lock_unchecked();
+            state
+                .provider_breakers
+                .entry(client.provider_key())
+                .or_insert_with(|| CircuitBreaker::new(client.get_provider_circuit_breaker()))
+                .record_failure(Instant::now())
+        };
+        if let Some(cooldown) = opened {
+            warn!(
+                "model provider {} is unhealthy: {error}",
+                client.provider_key()
+            );
+            self.notify_provider_health(
+                sub_id,
+                client,
+                ProviderHealthStatus::Unhealthy,
+                format!("{error}; not used for the next {}s", cooldown.as_secs()),
+            )
+            .await;
+        }
+    }
+
     async fn notify_stream_error(&self, sub_id: &str, message: impl Into<String>) {
         let event = Event {
             id: sub_id.to_string(),
@@ -881,11 +1712,45 @@ impl Session {
     }
 
     /// Build the full turn input by concatenating the current conversation
-    /// history with additional items for this turn.
+    /// history with additional items for this turn, with secrets masked.
     pub fn turn_input_with_history(&self, extra: Vec<ResponseItem>) -> Vec<ResponseItem> {
+        let extra = self.redactor.redact_items(&extra);
         [self.state.lock_unchecked().history.contents(), extra].concat()
     }
 
+    /// Apply `[images]` to the images in `items`, telling the user about the
+    /// ones that are left out.
+    async fn prepare_input(&self, sub_id: &str, items: Vec<InputItem>) -> Vec<InputItem> {
+        let (items, notes) = images::prepare_input(items, &self.images);
+        for note in notes {
+            self.notify_background_event(sub_id, note).await;
+        }
+        items
+    }
+
+    /// Save `response` as an artifact when its output is too large to send
+    /// to the model, telling the client where it went.
+    async fn offload_tool_output(
+        &self,
+        sub_id: &str,
+        response: ResponseInputItem,
+    ) -> ResponseInputItem {
+        let Some(artifacts) = &self.artifacts else {
+            return response;
+        };
+        let (response, event) = artifacts.offload(response);
+        if let Some(event) = event {
+            let _ = self
+                .tx_event
+                .send(Event {
+                    id: sub_id.to_string(),
+                    msg: EventMsg::ArtifactCreated(event),
+                })
+                .await;
+        }
+        response
+    }
+
     /// Returns the input if there was no task running to inject into
     pub fn inject_input(&self, input: Vec<InputItem>) -> Result<(), Vec<InputItem>> {
         let mut state = self.state.lock_unchecked();
@@ -920,6 +1785,16 @@ impl Session {
             .await
     }
 
+    pub(crate) async fn read_mcp_resource(
+        &self,
+        server: &str,
+        uri: &str,
+    ) -> anyhow::Result<ReadResourceResult> {
+        self.mcp_connection_manager
+            .read_resource(server, uri, None)
+            .await
+    }
+
     fn interrupt_task(&self) {
         info!("interrupt received: abort current task, if any");
         let mut state = self.state.lock_unchecked();
@@ -930,39 +1805,38 @@ impl Session {
         }
     }
 
-    /// Spawn the configured notifier (if any) with the given JSON payload as
-    /// the last argument. Failures are logged but otherwise ignored so that
-    /// notification issues do not interfere with the main workflow.
+    /// Deliver `notification` to wherever `[notifications]` routes it.
     fn maybe_notify(&self, notification: UserNotification) {
-        let Some(notify_command) = &self.notify else {
-            return;
-        };
-
-        if notify_command.is_empty() {
-            return;
-        }
-
-        let Ok(json) = serde_json::to_string(&notification) else {
-            error!("failed to serialise notification payload");
-            return;
-        };
+        self.notifier.notify(notification);
+    }
+}
 
-        let mut command = std::process::Command::new(&notify_command[0]);
-        if notify_command.len() > 1 {
-            command.args(&notify_command[1..]);
+/// What an approval request asks for, in one line, for notifications.
+fn approval_summary(msg: &EventMsg) -> Option<String> {
+    match msg {
+        EventMsg::ExecApprovalRequest(request) => {
+            let command = shlex::try_join(request.command.iter().map(String::as_str))
+                .unwrap_or_else(|_| request.command.join(" "));
+            Some(format!("Run `{command}`?"))
         }
-        command.arg(json);
-
-        // Fire-and-forget – we do not wait for completion.
-        if let Err(e) = command.spawn() {
-            warn!("failed to spawn notifier '{}': {e}", notify_command[0]);
+        EventMsg::ApplyPatchApprovalRequest(request) => {
+            let mut paths: Vec<String> = request
+                .changes
+                .keys()
+                .map(|path| path.display().to_string())
+                .collect();
+            paths.sort();
+            Some(format!("Change {}?", paths.join(", ")))
         }
+        EventMsg::BudgetApprovalRequest(exceeded) => Some(format!("Go over the {exceeded}?")),
+        _ => None,
     }
 }
 
 impl Drop for Session {
     fn drop(&mut self) {
         self.interrupt_task();
+        self.background_jobs.kill_all();
     }
 }
 
@@ -973,6 +1847,8 @@ pub(crate) struct ExecCommandContext {
     pub(crate) command_for_display: Vec<String>,
     pub(crate) cwd: PathBuf,
     pub(crate) apply_patch: Option<ApplyPatchCommandContext>,
+    /// Audit log entry for the command, recorded with each attempt to run it.
+    pub(crate) audit_action: AuditAction,
 }
 
 #[derive(Clone, Debug)]
@@ -985,9 +1861,14 @@ pub(crate) struct ApplyPatchCommandContext {
 pub(crate) struct AgentTask {
     sess: Arc<Session>,
     sub_id: String,
-    handle: AbortHandle,
+    handle: JoinHandle<()>,
+    cancel: CancellationToken,
 }
 
+/// How long an interrupted task may take to record its partial results
+/// before it is aborted.
+const INTERRUPT_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
 impl AgentTask {
     fn spawn(
         sess: Arc<Session>,
@@ -995,17 +1876,18 @@ impl AgentTask {
         sub_id: String,
         input: Vec<InputItem>,
     ) -> Self {
+        let cancel = Self::register_cancellation_token(&sess, &sub_id);
         let handle = {
             let sess = sess.clone();
             let sub_id = sub_id.clone();
             let tc = Arc::clone(&turn_context);
             tokio::spawn(async move { run_task(sess, tc.as_ref(), sub_id, input).await })
-                .abort_handle()
         };
         Self {
             sess,
             sub_id,
             handle,
+            cancel,
         }
     }
 
@@ -1016,6 +1898,7 @@ impl AgentTask {
         input: Vec<InputItem>,
         compact_instructions: String,
     ) -> Self {
+        let cancel = Self::register_cancellation_token(&sess, &sub_id);
         let handle = {
             let sess = sess.clone();
             let sub_id = sub_id.clone();
@@ -1023,29 +1906,161 @@ impl AgentTask {
             tokio::spawn(async move {
                 run_compact_task(sess, tc.as_ref(), sub_id, input, compact_instructions).await
             })
-            .abort_handle()
         };
         Self {
             sess,
             sub_id,
             handle,
+            cancel,
         }
     }
 
-    fn abort(self, reason: TurnAbortReason) {
-        // TOCTOU?
-        if !self.handle.is_finished() {
-            self.handle.abort();
-            let event = Event {
-                id: self.sub_id,
-                msg: EventMsg::TurnAborted(TurnAbortedEvent { reason }),
-            };
-            let tx_event = self.sess.tx_event.clone();
+    fn workflow(
+        sess: Arc<Session>,
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        name: String,
+        workflow: Workflow,
+        inputs: HashMap<String, String>,
+        log: StepLog,
+    ) -> Self {
+        let cancel = Self::register_cancellation_token(&sess, &sub_id);
+        let handle = {
+            let sess = sess.clone();
+            let sub_id = sub_id.clone();
+            let tc = Arc::clone(&turn_context);
             tokio::spawn(async move {
-                tx_event.send(event).await.ok();
-            });
-        }
-    }
+                run_workflow(sess, tc.as_ref(), sub_id, name, workflow, inputs, log).await
+            })
+        };
+        Self {
+            sess,
+            sub_id,
+            handle,
+            cancel,
+        }
+    }
+
+    fn tool_pipeline(
+        sess: Arc<Session>,
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        steps: Vec<ToolPipelineStep>,
+    ) -> Self {
+        let cancel = Self::register_cancellation_token(&sess, &sub_id);
+        let handle = {
+            let sess = sess.clone();
+            let sub_id = sub_id.clone();
+            let tc = Arc::clone(&turn_context);
+            tokio::spawn(async move { run_tool_pipeline(sess, tc.as_ref(), sub_id, steps).await })
+        };
+        Self {
+            sess,
+            sub_id,
+            handle,
+            cancel,
+        }
+    }
+
+    /// The token is registered before the task is spawned so the task always
+    /// finds it.
+    fn register_cancellation_token(sess: &Session, sub_id: &str) -> CancellationToken {
+        let cancel = CancellationToken::new();
+        let mut state = sess.state.lock_unchecked();
+        state
+            .cancellation_tokens
+            .insert(sub_id.to_string(), cancel.clone());
+        cancel
+    }
+
+    /// An interrupted task is cancelled and given [`INTERRUPT_GRACE_PERIOD`]
+    /// to stop its commands and tool calls and record what it has so far; a
+    /// replaced task is aborted right away.
+    fn abort(self, reason: TurnAbortReason) {
+        self.cancel.cancel();
+        if self.handle.is_finished() {
+            return;
+        }
+        let Self {
+            sess,
+            sub_id,
+            mut handle,
+            ..
+        } = self;
+        if matches!(reason, TurnAbortReason::Replaced) {
+            handle.abort();
+        }
+        tokio::spawn(async move {
+            if matches!(reason, TurnAbortReason::Interrupted) {
+                if tokio::time::timeout(INTERRUPT_GRACE_PERIOD, &mut handle)
+                    .await
+                    .is_err()
+                {
+                    warn!(
+                        "task {sub_id} did not stop within {INTERRUPT_GRACE_PERIOD:?}; aborting it"
+                    );
+                    handle.abort();
+                }
+                sess.remove_task(&sub_id);
+            }
+            let event = Event {
+                id: sub_id,
+                msg: EventMsg::TurnAborted(TurnAbortedEvent { reason }),
+            };
+            sess.tx_event.send(event).await.ok();
+        });
+    }
+}
+
+fn session_state_snapshot(config: &Config, turn_context: &TurnContext) -> SessionStateSnapshot {
+    SessionStateSnapshot {
+        cwd: Some(turn_context.cwd.clone()),
+        model: Some(turn_context.client.get_model()),
+        model_provider_id: Some(config.model_provider_id.clone()),
+        reasoning_effort: Some(turn_context.client.get_reasoning_effort()),
+        reasoning_summary: Some(turn_context.client.get_reasoning_summary()),
+        approval_policy: Some(turn_context.approval_policy),
+        sandbox_policy: Some(turn_context.sandbox_policy.clone()),
+    }
+}
+
+/// Overlay the settings recorded in a rollout onto `config`. The provider is
+/// only switched when the recorded id differs from the configured one and is
+/// still defined; otherwise the current provider definition is kept.
+fn apply_session_state(config: &mut Config, state: SessionStateSnapshot) {
+    if let Some(cwd) = state.cwd {
+        config.cwd = cwd;
+    }
+    if let Some(provider_id) = state.model_provider_id
+        && provider_id != config.model_provider_id
+        && let Some(provider) = config.model_providers.get(&provider_id)
+    {
+        config.model_provider = provider.clone();
+        config.model_provider_id = provider_id;
+    }
+    if let Some(model) = state.model
+        && model != config.model
+    {
+        config.model_family =
+            find_family_for_model(&model).unwrap_or_else(|| config.model_family.clone());
+        if let Some(model_info) = get_model_info(&config.model_family) {
+            config.model_context_window = Some(model_info.context_window);
+            config.model_max_output_tokens = Some(model_info.max_output_tokens);
+        }
+        config.model = model;
+    }
+    if let Some(effort) = state.reasoning_effort {
+        config.model_reasoning_effort = effort;
+    }
+    if let Some(summary) = state.reasoning_summary {
+        config.model_reasoning_summary = summary;
+    }
+    if let Some(approval_policy) = state.approval_policy {
+        config.approval_policy = approval_policy;
+    }
+    if let Some(sandbox_policy) = state.sandbox_policy {
+        config.sandbox_policy = sandbox_policy;
+    }
 }
 
 async fn submission_loop(
@@ -1070,6 +2085,7 @@ async fn submission_loop(
                 model,
                 effort,
                 summary,
+                dry_run,
             } => {
                 // Recalculate the persistent turn context with provided overrides.
                 let prev = Arc::clone(&turn_context);
@@ -1105,6 +2121,8 @@ async fn submission_loop(
                     effective_effort,
                     effective_summary,
                     sess.session_id,
+                    sess.azure_auth.clone(),
+                    sess.budget.clone(),
                 );
 
                 let new_approval_policy = approval_policy.unwrap_or(prev.approval_policy);
@@ -1122,6 +2140,10 @@ async fn submission_loop(
                     include_web_search_request: config.tools_web_search_request,
                     use_streamable_shell_tool: config.use_experimental_streamable_shell_tool,
                     include_view_image_tool: config.include_view_image_tool,
+                    include_background_jobs_tool: config.include_background_jobs_tool,
+                    include_git_tools: config.include_git_tools,
+                    include_edit_files_tool: config.include_edit_files_tool,
+                    include_refresh_environment_tool: config.environment_snapshot,
                 });
 
                 let new_turn_context = TurnContext {
@@ -1132,12 +2154,18 @@ async fn submission_loop(
                     approval_policy: new_approval_policy,
                     sandbox_policy: new_sandbox_policy.clone(),
                     shell_environment_policy: prev.shell_environment_policy.clone(),
+                    exec_limits: prev.exec_limits,
+                    sandbox_backend: prev.sandbox_backend,
                     cwd: new_cwd.clone(),
                     disable_response_storage: prev.disable_response_storage,
+                    response_format: None,
+                    dry_run: dry_run.unwrap_or(prev.dry_run),
                 };
 
                 // Install the new persistent context for subsequent tasks/turns.
                 turn_context = Arc::new(new_turn_context);
+                sess.state.lock_unchecked().rollout_state =
+                    session_state_snapshot(&config, &turn_context);
                 if cwd.is_some() || approval_policy.is_some() || sandbox_policy.is_some() {
                     sess.record_conversation_items(&[ResponseItem::from(EnvironmentContext::new(
                         cwd,
@@ -1150,6 +2178,7 @@ async fn submission_loop(
                 }
             }
             Op::UserInput { items } => {
+                let items = sess.prepare_input(&sub.id, items).await;
                 // attempt to inject input into current task
                 if let Err(items) = sess.inject_input(items) {
                     // no current task, spawn a new one
@@ -1166,7 +2195,9 @@ async fn submission_loop(
                 model,
                 effort,
                 summary,
+                response_format,
             } => {
+                let items = sess.prepare_input(&sub.id, items).await;
                 // attempt to inject input into current task
                 if let Err(items) = sess.inject_input(items) {
                     // Derive a fresh TurnContext for this turn using the provided overrides.
@@ -1194,6 +2225,8 @@ async fn submission_loop(
                         effort,
                         summary,
                         sess.session_id,
+                        sess.azure_auth.clone(),
+                        sess.budget.clone(),
                     );
 
                     let fresh_turn_context = TurnContext {
@@ -1208,14 +2241,22 @@ async fn submission_loop(
                             use_streamable_shell_tool: config
                                 .use_experimental_streamable_shell_tool,
                             include_view_image_tool: config.include_view_image_tool,
+                            include_background_jobs_tool: config.include_background_jobs_tool,
+                            include_git_tools: config.include_git_tools,
+                            include_edit_files_tool: config.include_edit_files_tool,
+                            include_refresh_environment_tool: config.environment_snapshot,
                         }),
                         user_instructions: turn_context.user_instructions.clone(),
                         base_instructions: turn_context.base_instructions.clone(),
                         approval_policy,
                         sandbox_policy,
                         shell_environment_policy: turn_context.shell_environment_policy.clone(),
+                        exec_limits: turn_context.exec_limits,
+                        sandbox_backend: turn_context.sandbox_backend,
                         cwd,
                         disable_response_storage: turn_context.disable_response_storage,
+                        response_format,
+                        dry_run: turn_context.dry_run,
                     };
                     // TODO: record the new environment context in the conversation history
                     // no current task, spawn a new one with the per‑turn context
@@ -1236,12 +2277,28 @@ async fn submission_loop(
                 }
                 other => sess.notify_approval(&id, other),
             },
+            Op::BudgetApproval { id, decision } => match decision {
+                ReviewDecision::Abort => {
+                    sess.interrupt_task();
+                }
+                other => sess.notify_approval(&id, other),
+            },
             Op::AddToHistory { text } => {
                 let id = sess.session_id;
                 let config = config.clone();
+                let recorder = sess.rollout.lock_unchecked().clone();
                 tokio::spawn(async move {
-                    if let Err(e) = crate::message_history::append_entry(&text, &id, &config).await
-                    {
+                    // The recorder writes the entry to `~/.codex/history.jsonl`
+                    // and to the stores configured in `[storage]`.
+                    let result = match recorder {
+                        Some(rec) => match crate::message_history::new_entry(&text, &id, &config) {
+                            Ok(Some(entry)) => rec.record_history(entry).await,
+                            Ok(None) => Ok(()),
+                            Err(e) => Err(e),
+                        },
+                        None => crate::message_history::append_entry(&text, &id, &config).await,
+                    };
+                    if let Err(e) = result {
                         warn!("failed to append to message history: {e}");
                     }
                 });
@@ -1298,6 +2355,97 @@ async fn submission_loop(
                     warn!("failed to send McpListToolsResponse event: {e}");
                 }
             }
+            Op::AddMcpServer {
+                name,
+                command,
+                args,
+                env,
+            } => {
+                let sess = sess.clone();
+                let sub_id = sub.id.clone();
+                tokio::spawn(async move {
+                    let config = McpServerConfig {
+                        command,
+                        args,
+                        env,
+                        ..Default::default()
+                    };
+                    let status = match sess
+                        .mcp_connection_manager
+                        .add_server(name.clone(), config)
+                        .await
+                    {
+                        Ok(tools) => McpServerStatusEvent {
+                            server: name,
+                            status: McpServerStatus::Running,
+                            tools: Some(tools),
+                            message: None,
+                        },
+                        Err(e) => McpServerStatusEvent {
+                            server: name,
+                            status: McpServerStatus::Failed,
+                            tools: None,
+                            message: Some(format!("{e:#}")),
+                        },
+                    };
+                    sess.send_event(Event {
+                        id: sub_id,
+                        msg: EventMsg::McpServerStatus(status),
+                    })
+                    .await;
+                });
+            }
+            Op::RemoveMcpServer { name } => {
+                let message = (!sess.mcp_connection_manager.remove_server(&name))
+                    .then(|| format!("unknown MCP server '{name}'"));
+                sess.send_event(Event {
+                    id: sub.id.clone(),
+                    msg: EventMsg::McpServerStatus(McpServerStatusEvent {
+                        server: name,
+                        status: McpServerStatus::Removed,
+                        tools: None,
+                        message,
+                    }),
+                })
+                .await;
+            }
+            Op::ListMcpPrompts => {
+                let prompts = sess
+                    .mcp_connection_manager
+                    .list_all_prompts()
+                    .into_iter()
+                    .map(|(server, prompt)| McpPromptInfo { server, prompt })
+                    .collect();
+                sess.send_event(Event {
+                    id: sub.id.clone(),
+                    msg: EventMsg::McpListPromptsResponse(McpListPromptsResponseEvent { prompts }),
+                })
+                .await;
+            }
+            Op::GetMcpPrompt {
+                server,
+                name,
+                arguments,
+            } => {
+                let sess = sess.clone();
+                let sub_id = sub.id.clone();
+                tokio::spawn(async move {
+                    let result = sess
+                        .mcp_connection_manager
+                        .get_prompt(&server, &name, arguments)
+                        .await
+                        .map_err(|e| format!("{e:#}"));
+                    sess.send_event(Event {
+                        id: sub_id,
+                        msg: EventMsg::McpGetPromptResponse(McpGetPromptResponseEvent {
+                            server,
+                            name,
+                            result,
+                        }),
+                    })
+                    .await;
+                });
+            }
             Op::ListCustomPrompts => {
                 let tx_event = sess.tx_event.clone();
                 let sub_id = sub.id.clone();
@@ -1319,10 +2467,52 @@ async fn submission_loop(
                     warn!("failed to send ListCustomPromptsResponse event: {e}");
                 }
             }
+            Op::RunToolPipeline { steps } => {
+                let task = AgentTask::tool_pipeline(
+                    sess.clone(),
+                    Arc::clone(&turn_context),
+                    sub.id,
+                    steps,
+                );
+                sess.set_task(task);
+            }
+            Op::RunWorkflow { name, inputs } => {
+                let dir = crate::workflow::workflows_dir(&config.codex_home);
+                match crate::workflow::load_workflow(&dir, &name) {
+                    Ok(workflow) => {
+                        let log = StepLog::new(&config.codex_home, &name, Utc::now());
+                        let task = AgentTask::workflow(
+                            sess.clone(),
+                            Arc::clone(&turn_context),
+                            sub.id,
+                            name,
+                            workflow,
+                            inputs,
+                            log,
+                        );
+                        sess.set_task(task);
+                    }
+                    Err(message) => {
+                        let event = Event {
+                            id: sub.id.clone(),
+                            msg: EventMsg::Error(ErrorEvent {
+                                message,
+                                ..Default::default()
+                            }),
+                        };
+                        sess.send_event(event).await;
+                        let event = Event {
+                            id: sub.id,
+                            msg: EventMsg::TaskComplete(TaskCompleteEvent {
+                                last_agent_message: None,
+                            }),
+                        };
+                        sess.send_event(event).await;
+                    }
+                }
+            }
             Op::Compact => {
                 // Create a summarization request as user input
-                const SUMMARIZATION_PROMPT: &str = include_str!("prompt_for_compact_command.md");
-
                 // Attempt to inject input into current task
                 if let Err(items) = sess.inject_input(vec![InputItem::Text {
                     text: "Start Summarization".to_string(),
@@ -1339,6 +2529,7 @@ async fn submission_loop(
             }
             Op::Shutdown => {
                 info!("Shutting down Codex instance");
+                sess.background_jobs.kill_all();
 
                 // Gracefully flush and shutdown rollout recorder on session end so tests
                 // that inspect the rollout file do not race with the background writer.
@@ -1351,6 +2542,7 @@ async fn submission_loop(
                         id: sub.id.clone(),
                         msg: EventMsg::Error(ErrorEvent {
                             message: "Failed to shutdown rollout recorder".to_string(),
+                            ..Default::default()
                         }),
                     };
                     if let Err(e) = sess.tx_event.send(event).await {
@@ -1367,6 +2559,65 @@ async fn submission_loop(
                 }
                 break;
             }
+            Op::Rewind { num_turns } => {
+                let msg = match sess.rewind(num_turns as usize).await {
+                    Ok(event) => EventMsg::ConversationBranch(event),
+                    Err(message) => EventMsg::Error(ErrorEvent {
+                        message,
+                        code: Some("validation.branch".to_string()),
+                        category: Some(ErrorCategory::Validation),
+                        retryable: false,
+                    }),
+                };
+                sess.send_event(Event {
+                    id: sub.id.clone(),
+                    msg,
+                })
+                .await;
+            }
+            Op::SwitchBranch { branch_id } => {
+                let msg = match sess.switch_branch(branch_id).await {
+                    Ok(event) => EventMsg::ConversationBranch(event),
+                    Err(message) => EventMsg::Error(ErrorEvent {
+                        message,
+                        code: Some("validation.branch".to_string()),
+                        category: Some(ErrorCategory::Validation),
+                        retryable: false,
+                    }),
+                };
+                sess.send_event(Event {
+                    id: sub.id.clone(),
+                    msg,
+                })
+                .await;
+            }
+            Op::ExportConversation { format, path } => {
+                let content = export_conversation(
+                    sess.session_id,
+                    &sess.state.lock_unchecked().history.contents(),
+                    format,
+                );
+                let path = path.map(|path| turn_context.cwd.join(path));
+                let msg = if let Some(path) = &path
+                    && let Err(e) = tokio::fs::write(path, &content).await
+                {
+                    EventMsg::Error(ErrorEvent {
+                        message: format!("failed to write transcript to {}: {e}", path.display()),
+                        ..Default::default()
+                    })
+                } else {
+                    EventMsg::ConversationExport(ConversationExportEvent {
+                        format,
+                        content,
+                        path,
+                    })
+                };
+                sess.send_event(Event {
+                    id: sub.id.clone(),
+                    msg,
+                })
+                .await;
+            }
             Op::GetHistory => {
                 let tx_event = sess.tx_event.clone();
                 let sub_id = sub.id.clone();
@@ -1412,6 +2663,11 @@ async fn run_task(
     if input.is_empty() {
         return;
     }
+    let cancel = sess.cancellation_token(&sub_id);
+    let Some(_task_gate) = sess.enter_task(&cancel).await else {
+        return;
+    };
+    sess.budget.start_turn();
     let event = Event {
         id: sub_id.clone(),
         msg: EventMsg::TaskStarted(TaskStartedEvent {
@@ -1422,14 +2678,63 @@ async fn run_task(
         return;
     }
 
+    let last_agent_message =
+        run_agent_turns(sess.clone(), turn_context, sub_id.clone(), input, &cancel).await;
+    sess.remove_task(&sub_id);
+    if cancel.is_cancelled() {
+        // `TurnAborted` is sent in place of `TaskComplete`.
+        return;
+    }
+    let event = Event {
+        id: sub_id,
+        msg: EventMsg::TaskComplete(TaskCompleteEvent { last_agent_message }),
+    };
+    sess.tx_event.send(event).await.ok();
+}
+
+/// Runs the model on `input`, and again on the outputs of the tools it calls,
+/// until it answers without calling tools. Returns its last message; errors
+/// are sent as events.
+async fn run_agent_turns(
+    sess: Arc<Session>,
+    turn_context: &TurnContext,
+    sub_id: String,
+    input: Vec<InputItem>,
+    cancel: &CancellationToken,
+) -> Option<String> {
+    let turn_start_hooks = sess
+        .run_hooks(
+            &sub_id,
+            &turn_hook_context(HookEvent::TurnStart, &sub_id, turn_context),
+        )
+        .await;
+    if let Some(blocked) = turn_start_hooks.iter().find(|run| run.blocks()) {
+        let event = Event {
+            id: sub_id.clone(),
+            msg: EventMsg::Error(ErrorEvent {
+                message: format!("turn blocked: {}", blocked.describe()),
+                code: Some("validation.blocked_by_hook".to_string()),
+                category: Some(ErrorCategory::Validation),
+                retryable: false,
+            }),
+        };
+        sess.tx_event.send(event).await.ok();
+        return None;
+    }
+
     let initial_input_for_turn: ResponseInputItem = ResponseInputItem::from(input);
     sess.record_conversation_items(&[initial_input_for_turn.clone().into()])
         .await;
+    if let Some(output) = hooks::attachment(&turn_start_hooks) {
+        sess.record_conversation_items(&[hook_output_message(output)])
+            .await;
+    }
 
     let mut last_agent_message: Option<String> = None;
+    let mut response_format_retries = 0;
     // Although from the perspective of codex.rs, TurnDiffTracker has the lifecycle of a Task which contains
     // many turns, from the perspective of the user, it is a single turn.
-    let mut turn_diff_tracker = TurnDiffTracker::new();
+    let turn_diff_tracker = SharedTurnDiffTracker::new(TurnDiffTracker::new());
 
     loop {
         // Note that pending_input would be something like a message the user
@@ -1442,6 +2747,8 @@ async fn run_task(
             .collect::<Vec<ResponseItem>>();
         sess.record_conversation_items(&pending_input).await;
 
+        maybe_auto_compact(&sess, turn_context, &sub_id).await;
+
         // Construct the input that we will send to the model. When using the
         // Chat completions API (or ZDR clients), the model needs the full
         // conversation history on each turn. The rollout file, however, should
@@ -1465,7 +2772,7 @@ async fn run_task(
         match run_turn(
             &sess,
             turn_context,
-            &mut turn_diff_tracker,
+            &turn_diff_tracker,
             sub_id.clone(),
             turn_input,
         )
@@ -1570,27 +2877,91 @@ async fn run_task(
                     sess.record_conversation_items(&items_to_record_in_conversation_history)
                         .await;
                 }
+                // An interrupted turn has recorded what it got; the task ends
+                // without asking the model again.
+                if cancel.is_cancelled() {
+                    break;
+                }
 
                 if responses.is_empty() {
                     debug!("Turn completed");
                     last_agent_message = get_last_assistant_message_from_turn(
                         &items_to_record_in_conversation_history,
                     );
+                    if let Some(format) = &turn_context.response_format
+                        && let Err(errors) = validate_response(
+                            format,
+                            last_agent_message.as_deref().unwrap_or_default(),
+                        )
+                    {
+                        if response_format_retries < MAX_RESPONSE_FORMAT_RETRIES {
+                            response_format_retries += 1;
+                            sess.notify_background_event(
+                                &sub_id,
+                                format!(
+                                    "response does not match the `{}` schema; asking again ({response_format_retries}/{MAX_RESPONSE_FORMAT_RETRIES})",
+                                    format.name
+                                ),
+                            )
+                            .await;
+                            let retry = ResponseInputItem::from(vec![InputItem::Text {
+                                text: response_format_retry_message(format, &errors),
+                            }]);
+                            sess.record_conversation_items(&[retry.into()]).await;
+                            continue;
+                        }
+                        let event = Event {
+                            id: sub_id.clone(),
+                            msg: EventMsg::Error(ErrorEvent {
+                                message: format!(
+                                    "response does not match the `{}` schema: {}",
+                                    format.name,
+                                    errors.join("; ")
+                                ),
+                                code: Some("validation.response_format".to_string()),
+                                category: Some(ErrorCategory::Validation),
+                                retryable: false,
+                            }),
+                        };
+                        sess.tx_event.send(event).await.ok();
+                    }
                     sess.maybe_notify(UserNotification::AgentTurnComplete {
                         turn_id: sub_id.clone(),
                         input_messages: turn_input_messages,
                         last_assistant_message: last_agent_message.clone(),
                     });
+                    // Output of turn-end hooks is seen by the model on the
+                    // next turn.
+                    let turn_end_hooks = sess
+                        .run_hooks(
+                            &sub_id,
+                            &turn_hook_context(HookEvent::TurnEnd, &sub_id, turn_context),
+                        )
+                        .await;
+                    if let Some(output) = hooks::attachment(&turn_end_hooks) {
+                        sess.record_conversation_items(&[hook_output_message(output)])
+                            .await;
+                    }
+                    let turn_diff = turn_diff_tracker.lock_unchecked().get_turn_diff();
+                    if let Some(turn_diff) = turn_diff {
+                        let message = ResponseInputItem::from(vec![InputItem::Text {
+                            text: turn_changes_message(&turn_diff.files),
+                        }]);
+                        sess.record_conversation_items(&[message.into()]).await;
+                    }
                     break;
                 }
             }
+            Err(_) if cancel.is_cancelled() => break,
             Err(e) => {
                 info!("Turn error: {e:#}");
+                sess.maybe_notify(UserNotification::Error {
+                    turn_id: sub_id.clone(),
+                    message: e.to_string(),
+                });
                 let event = Event {
                     id: sub_id.clone(),
-                    msg: EventMsg::Error(ErrorEvent {
-                        message: e.to_string(),
-                    }),
+                    msg: EventMsg::Error(e.to_error_event()),
                 };
                 sess.tx_event.send(event).await.ok();
                 // let the user continue the conversation
@@ -1598,45 +2969,187 @@ async fn run_task(
             }
         }
     }
-    sess.remove_task(&sub_id);
-    let event = Event {
-        id: sub_id,
-        msg: EventMsg::TaskComplete(TaskCompleteEvent { last_agent_message }),
-    };
-    sess.tx_event.send(event).await.ok();
+    last_agent_message
 }
 
 async fn run_turn(
     sess: &Session,
     turn_context: &TurnContext,
-    turn_diff_tracker: &mut TurnDiffTracker,
+    turn_diff_tracker: &SharedTurnDiffTracker,
     sub_id: String,
     input: Vec<ResponseItem>,
 ) -> CodexResult<Vec<ProcessedResponseItem>> {
-    let tools = get_openai_tools(
-        &turn_context.tools_config,
-        Some(sess.mcp_connection_manager.list_all_tools()),
-    );
+    let mut tools = get_openai_tools(&turn_context.tools_config, None);
+    let resources = sess.mcp_connection_manager.list_all_resources();
+    if !resources.is_empty() {
+        tools.push(create_read_resource_tool(&resources));
+    }
+    if sess.artifacts.is_some() {
+        tools.push(OpenAiTool::Function(create_read_artifact_tool()));
+    }
+    if sess.knowledge_base.is_some() {
+        tools.push(OpenAiTool::Function(create_kb_search_tool()));
+    }
+    if sess.azure_monitor.is_some() {
+        tools.extend(
+            create_azure_monitor_tools()
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+    }
+    if sess.key_vault.is_some() {
+        tools.extend(
+            create_key_vault_tools()
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+    }
+    if sess.azure_storage.is_some() {
+        tools.extend(
+            create_azure_storage_tools()
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+    }
+    if sess.arm_deployments.is_some() {
+        tools.extend(
+            create_arm_deployment_tools()
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+    }
+    if sess.ticketing.is_some() {
+        tools.extend(
+            create_ticketing_tools()
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+    }
+    if let Some(messaging) = &sess.messaging {
+        tools.push(OpenAiTool::Function(create_post_message_tool(
+            messaging.channels(),
+        )));
+    }
+    if let Some(sql) = &sess.sql {
+        tools.extend(
+            create_sql_tools(sql.profiles())
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+    }
+    if let Some(kubernetes) = &sess.kubernetes {
+        tools.extend(
+            create_kubernetes_tools(kubernetes.contexts())
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+    }
+    tools.extend_from_slice(sess.custom_tools.definitions());
+    tools.extend(mcp_tools_to_openai_tools(
+        sess.mcp_connection_manager.list_all_tools(),
+    ));
+    if let Some(max_tokens) = sess.tool_description_max_tokens {
+        trim_tool_descriptions(&mut tools, max_tokens);
+    }
 
     let prompt = Prompt {
         input,
         store: !turn_context.disable_response_storage,
         tools,
         base_instructions_override: turn_context.base_instructions.clone(),
+        response_format: turn_context.response_format.clone(),
     };
 
+    // The failover client is used at most once per turn: when the primary
+    // provider is unhealthy or the turn ran out of retries.
+    let failover_client = turn_context.client.failover_client();
+    let mut failover = failover_client.as_ref();
+    let mut client = &turn_context.client;
     let mut retries = 0;
+    let cancel = sess.cancellation_token(&sub_id);
     loop {
-        match try_run_turn(sess, turn_context, turn_diff_tracker, &sub_id, &prompt).await {
-            Ok(output) => return Ok(output),
+        if let Some(retry_in) = sess.provider_cooldown(client) {
+            let Some(next) = failover.take() else {
+                return Err(CodexErr::ProviderUnavailable {
+                    provider: client.provider_key(),
+                    retry_in_secs: retry_in.as_secs(),
+                });
+            };
+            sess.notify_provider_health(
+                &sub_id,
+                next,
+                ProviderHealthStatus::FailedOver,
+                format!("{} is unhealthy", client.provider_key()),
+            )
+            .await;
+            client = next;
+            retries = 0;
+            continue;
+        }
+        // A route to a provider that is cooling down falls back to `client`.
+        let routed = client
+            .routed_client(&prompt)
+            .filter(|(_, routed)| sess.provider_cooldown(routed).is_none());
+        let request_client = match &routed {
+            Some((route, routed)) => {
+                sess.send_event(Event {
+                    id: sub_id.clone(),
+                    msg: EventMsg::ModelRouted(ModelRoutedEvent {
+                        route: route.clone(),
+                        provider: routed.get_provider().name,
+                        model: routed.get_model(),
+                    }),
+                })
+                .await;
+                routed
+            }
+            None => client,
+        };
+        match try_run_turn(
+            sess,
+            turn_context,
+            request_client,
+            turn_diff_tracker,
+            &sub_id,
+            &prompt,
+        )
+        .await
+        {
+            Ok(output) => {
+                sess.record_provider_success(&sub_id, request_client).await;
+                return Ok(output);
+            }
             Err(CodexErr::Interrupted) => return Err(CodexErr::Interrupted),
             Err(CodexErr::EnvVar(var)) => return Err(CodexErr::EnvVar(var)),
             Err(e @ (CodexErr::UsageLimitReached(_) | CodexErr::UsageNotIncluded)) => {
                 return Err(e);
             }
+            Err(CodexErr::BudgetExceeded(exceeded)) => {
+                if turn_context.approval_policy == AskForApproval::Never {
+                    return Err(CodexErr::BudgetExceeded(exceeded));
+                }
+                let rx_approve = sess
+                    .request_approval(
+                        sub_id.clone(),
+                        EventMsg::BudgetApprovalRequest(exceeded.clone()),
+                    )
+                    .await;
+                match rx_approve.await.unwrap_or_default() {
+                    ReviewDecision::Approved => sess.budget.lift(&exceeded, false),
+                    ReviewDecision::ApprovedForSession => sess.budget.lift(&exceeded, true),
+                    ReviewDecision::Denied | ReviewDecision::Abort => {
+                        return Err(CodexErr::BudgetExceeded(exceeded));
+                    }
+                }
+            }
             Err(e) => {
+                sess.record_provider_failure(&sub_id, request_client, &e)
+                    .await;
+                if sess.provider_cooldown(request_client).is_some() {
+                    continue;
+                }
                 // Use the configured provider-specific stream retry budget.
-                let max_retries = turn_context.client.get_provider().stream_max_retries();
+                let max_retries = request_client.get_provider().stream_max_retries();
                 if retries < max_retries {
                     retries += 1;
                     let delay = match e {
@@ -1658,7 +3171,20 @@ async fn run_turn(
                     )
                     .await;
 
-                    tokio::time::sleep(delay).await;
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = cancel.cancelled() => return Err(CodexErr::Interrupted),
+                    }
+                } else if let Some(next) = failover.take() {
+                    sess.notify_provider_health(
+                        &sub_id,
+                        next,
+                        ProviderHealthStatus::FailedOver,
+                        format!("{} failed: {e}", request_client.provider_key()),
+                    )
+                    .await;
+                    client = next;
+                    retries = 0;
                 } else {
                     return Err(e);
                 }
@@ -1680,7 +3206,8 @@ struct ProcessedResponseItem {
 async fn try_run_turn(
     sess: &Session,
     turn_context: &TurnContext,
-    turn_diff_tracker: &mut TurnDiffTracker,
+    client: &ModelClient,
+    turn_diff_tracker: &SharedTurnDiffTracker,
     sub_id: &str,
     prompt: &Prompt,
 ) -> CodexResult<Vec<ProcessedResponseItem>> {
@@ -1739,18 +3266,63 @@ async fn try_run_turn(
         })
     };
 
-    let mut stream = turn_context.client.clone().stream(&prompt).await?;
+    let cancel = sess.cancellation_token(sub_id);
+    let client = client.clone();
+    let mut stream = tokio::select! {
+        stream = client.stream(&prompt) => stream?,
+        _ = cancel.cancelled() => return Ok(Vec::new()),
+    };
 
-    let mut output = Vec::new();
+    let mut output: Vec<ProcessedResponseItem> = Vec::new();
+    // Text of the assistant message that is still streaming, kept so an
+    // interrupted turn can record it.
+    let mut partial_message = String::new();
+    // Tool calls run while the rest of the response streams in; their
+    // responses are filled into `output` by index as they finish.
+    let mut scheduler = ToolCallScheduler::new(turn_context.client.get_max_parallel_tool_calls());
+    let mut running: FuturesUnordered<ToolCallFuture> = FuturesUnordered::new();
+    let run_tool_call = |index: usize, item: ResponseItem| -> ToolCallFuture {
+        async move {
+            // Reads of an artifact are never saved as another artifact.
+            let reads_artifact = matches!(
+                &item,
+                ResponseItem::FunctionCall { name, .. } if name == READ_ARTIFACT_TOOL_NAME
+            );
+            let response =
+                handle_response_item(sess, turn_context, turn_diff_tracker, sub_id, item).await;
+            let response = match response {
+                Ok(Some(response)) if !reads_artifact => {
+                    Ok(Some(sess.offload_tool_output(sub_id, response).await))
+                }
+                response => response,
+            };
+            (index, response)
+        }
+        .boxed()
+    };
 
     loop {
         // Poll the next item from the model stream. We must inspect *both* Ok and Err
         // cases so that transient stream failures (e.g., dropped SSE connection before
         // `response.completed`) bubble up and trigger the caller's retry logic.
-        let event = stream.next().await;
+        let event = tokio::select! {
+            event = stream.next() => event,
+            _ = cancel.cancelled() => {
+                return Ok(interrupted_turn_output(output, &mut running, partial_message).await);
+            }
+            Some((index, response)) = running.next(), if !running.is_empty() => {
+                record_tool_response(&mut output, &mut running, index, response).await?;
+                scheduler.finish(index);
+                for (index, item) in scheduler.start_ready() {
+                    running.push(run_tool_call(index, item));
+                }
+                continue;
+            }
+        };
         let Some(event) = event else {
             // Channel closed without yielding a final Completed event or explicit error.
             // Treat as a disconnected stream so the caller can retry.
+            finish_tool_calls(&mut running).await;
             return Err(CodexErr::Stream(
                 "stream closed before response.completed".into(),
                 None,
@@ -1762,6 +3334,7 @@ async fn try_run_turn(
             Err(e) => {
                 // Propagate the underlying stream error to the caller (run_turn), which
                 // will apply the configured `stream_max_retries` policy.
+                finish_tool_calls(&mut running).await;
                 return Err(e);
             }
         };
@@ -1769,19 +3342,41 @@ async fn try_run_turn(
         match event {
             ResponseEvent::Created => {}
             ResponseEvent::OutputItemDone(item) => {
-                let response = handle_response_item(
-                    sess,
-                    turn_context,
-                    turn_diff_tracker,
-                    sub_id,
-                    item.clone(),
-                )
-                .await?;
-                output.push(ProcessedResponseItem { item, response });
-            }
-            ResponseEvent::WebSearchCallBegin { call_id } => {
-                let _ = sess
-                    .tx_event
+                if is_tool_call(&item) {
+                    let access = tool_call_access(sess, turn_context, &item);
+                    scheduler.push(output.len(), access, item.clone());
+                    output.push(ProcessedResponseItem {
+                        item,
+                        response: None,
+                    });
+                    for (index, item) in scheduler.start_ready() {
+                        running.push(run_tool_call(index, item));
+                    }
+                } else {
+                    if matches!(&item, ResponseItem::Message { role, .. } if role == "assistant") {
+                        partial_message.clear();
+                    }
+                    let response = match handle_response_item(
+                        sess,
+                        turn_context,
+                        turn_diff_tracker,
+                        sub_id,
+                        item.clone(),
+                    )
+                    .await
+                    {
+                        Ok(response) => response,
+                        Err(e) => {
+                            finish_tool_calls(&mut running).await;
+                            return Err(e);
+                        }
+                    };
+                    output.push(ProcessedResponseItem { item, response });
+                }
+            }
+            ResponseEvent::WebSearchCallBegin { call_id } => {
+                let _ = sess
+                    .tx_event
                     .send(Event {
                         id: sub_id.to_string(),
                         msg: EventMsg::WebSearchBegin(WebSearchBeginEvent { call_id }),
@@ -1792,6 +3387,20 @@ async fn try_run_turn(
                 response_id: _,
                 token_usage,
             } => {
+                while let Some((index, response)) = running.next().await {
+                    if cancel.is_cancelled() {
+                        output[index].response = response.ok().flatten();
+                        return Ok(
+                            interrupted_turn_output(output, &mut running, partial_message).await,
+                        );
+                    }
+                    record_tool_response(&mut output, &mut running, index, response).await?;
+                    scheduler.finish(index);
+                    for (index, item) in scheduler.start_ready() {
+                        running.push(run_tool_call(index, item));
+                    }
+                }
+
                 if let Some(token_usage) = token_usage {
                     sess.tx_event
                         .send(Event {
@@ -1801,20 +3410,22 @@ async fn try_run_turn(
                         .await
                         .ok();
                 }
-
-                let unified_diff = turn_diff_tracker.get_unified_diff();
-                if let Ok(Some(unified_diff)) = unified_diff {
-                    let msg = EventMsg::TurnDiff(TurnDiffEvent { unified_diff });
-                    let event = Event {
-                        id: sub_id.to_string(),
-                        msg,
-                    };
-                    let _ = sess.tx_event.send(event).await;
+                for warning in sess.budget.take_warnings() {
+                    sess.tx_event
+                        .send(Event {
+                            id: sub_id.to_string(),
+                            msg: EventMsg::BudgetWarning(warning),
+                        })
+                        .await
+                        .ok();
                 }
 
+                sess.send_turn_diff(turn_diff_tracker, sub_id).await;
+
                 return Ok(output);
             }
             ResponseEvent::OutputTextDelta(delta) => {
+                partial_message.push_str(&delta);
                 let event = Event {
                     id: sub_id.to_string(),
                     msg: EventMsg::AgentMessageDelta(AgentMessageDeltaEvent { delta }),
@@ -1850,6 +3461,217 @@ async fn try_run_turn(
     }
 }
 
+/// A running tool call of [`try_run_turn`] and the index of its item in the
+/// turn's output.
+type ToolCallFuture<'a> = BoxFuture<'a, (usize, CodexResult<Option<ResponseInputItem>>)>;
+
+fn is_tool_call(item: &ResponseItem) -> bool {
+    matches!(
+        item,
+        ResponseItem::FunctionCall { .. }
+            | ResponseItem::LocalShellCall { .. }
+            | ResponseItem::CustomToolCall { .. }
+    )
+}
+
+/// What a tool call touches, see [`ToolCallScheduler`]. Calls to the same MCP
+/// server run one after the other.
+fn tool_call_access(
+    sess: &Session,
+    turn_context: &TurnContext,
+    item: &ResponseItem,
+) -> ToolCallAccess {
+    if let ResponseItem::FunctionCall { name, .. } = item
+        && let Some((server, _)) = sess.mcp_connection_manager.parse_tool_name(name)
+    {
+        return ToolCallAccess::writes(vec![Resource::McpServer(server)]);
+    }
+    tool_scheduler::tool_call_access(item, &turn_context.cwd)
+}
+
+/// The output of a turn that was interrupted: the tool calls that already
+/// started are cancelled through the task's token and awaited, the ones that
+/// had not started are answered with "aborted", and the text streamed so far
+/// is kept as the assistant message.
+async fn interrupted_turn_output(
+    mut output: Vec<ProcessedResponseItem>,
+    running: &mut FuturesUnordered<ToolCallFuture<'_>>,
+    partial_message: String,
+) -> Vec<ProcessedResponseItem> {
+    while let Some((index, response)) = running.next().await {
+        output[index].response = response.ok().flatten();
+    }
+    for processed in &mut output {
+        if processed.response.is_none() {
+            processed.response = aborted_tool_output(&processed.item);
+        }
+    }
+    if !partial_message.is_empty() {
+        output.push(ProcessedResponseItem {
+            item: ResponseItem::Message {
+                id: None,
+                role: "assistant".to_string(),
+                content: vec![ContentItem::OutputText {
+                    text: partial_message,
+                }],
+            },
+            response: None,
+        });
+    }
+    output
+}
+
+/// The response recorded for a tool call that was never run.
+fn aborted_tool_output(item: &ResponseItem) -> Option<ResponseInputItem> {
+    let output = FunctionCallOutputPayload {
+        content: "aborted".to_string(),
+        success: Some(false),
+    };
+    match item {
+        ResponseItem::FunctionCall { call_id, .. }
+        | ResponseItem::LocalShellCall {
+            call_id: Some(call_id),
+            ..
+        } => Some(ResponseInputItem::FunctionCallOutput {
+            call_id: call_id.clone(),
+            output,
+        }),
+        ResponseItem::CustomToolCall { call_id, .. } => {
+            Some(ResponseInputItem::CustomToolCallOutput {
+                call_id: call_id.clone(),
+                output: output.content,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Fill in the response of the tool call at `index`. When the call failed,
+/// the calls still running are let finish first, so that each of them
+/// reports its end before the error ends the turn.
+async fn record_tool_response(
+    output: &mut [ProcessedResponseItem],
+    running: &mut FuturesUnordered<ToolCallFuture<'_>>,
+    index: usize,
+    response: CodexResult<Option<ResponseInputItem>>,
+) -> CodexResult<()> {
+    match response {
+        Ok(response) => {
+            output[index].response = response;
+            Ok(())
+        }
+        Err(e) => {
+            finish_tool_calls(running).await;
+            Err(e)
+        }
+    }
+}
+
+/// Let the tool calls that already started finish before the turn is
+/// retried; the calls that have not started are dropped.
+async fn finish_tool_calls<F: Future>(running: &mut FuturesUnordered<F>) {
+    while running.next().await.is_some() {}
+}
+
+const SUMMARIZATION_PROMPT: &str = include_str!("prompt_for_compact_command.md");
+
+/// Share of the auto-compaction limit that recent turns may keep verbatim.
+const AUTO_COMPACT_KEEP_RECENT_PERCENT: u64 = 30;
+
+/// Tool outputs longer than this are shortened before being summarized.
+const AUTO_COMPACT_MAX_TOOL_OUTPUT_BYTES: usize = 4 * 1024;
+
+/// When the history is close to the model's context window, replace older
+/// turns with a model-written summary so the next request still fits.
+/// Failures are logged and the history is left untouched.
+async fn maybe_auto_compact(sess: &Session, turn_context: &TurnContext, sub_id: &str) {
+    let Some(limit) = turn_context.client.get_auto_compact_token_limit() else {
+        return;
+    };
+    let (split, items) = {
+        let state = sess.state.lock_unchecked();
+        if state.history.estimated_tokens() < limit {
+            return;
+        }
+        let Some(split) = state
+            .history
+            .compaction_split(limit * AUTO_COMPACT_KEEP_RECENT_PERCENT / 100)
+        else {
+            return;
+        };
+        (
+            split,
+            state
+                .history
+                .items_to_compact(split, AUTO_COMPACT_MAX_TOOL_OUTPUT_BYTES),
+        )
+    };
+
+    sess.notify_background_event(
+        sub_id,
+        "Conversation is nearing the context window; summarizing earlier turns.",
+    )
+    .await;
+    match summarize_items(turn_context, items).await {
+        Ok(summary) => {
+            let mut state = sess.state.lock_unchecked();
+            state.history.replace_with_summary(split, &summary);
+        }
+        Err(e) => warn!("automatic history compaction failed: {e}"),
+    }
+}
+
+/// Ask the model for a summary of `items` and return its text.
+async fn summarize_items(
+    turn_context: &TurnContext,
+    mut items: Vec<ResponseItem>,
+) -> CodexResult<String> {
+    items.push(ResponseItem::from(ResponseInputItem::from(vec![
+        InputItem::Text {
+            text: "Summarize the conversation so far.".to_string(),
+        },
+    ])));
+    let prompt = Prompt {
+        input: items,
+        store: !turn_context.disable_response_storage,
+        tools: Vec::new(),
+        base_instructions_override: Some(SUMMARIZATION_PROMPT.to_string()),
+        response_format: None,
+    };
+
+    let mut stream = turn_context.client.clone().stream(&prompt).await?;
+    let mut summary = String::new();
+    loop {
+        match stream.next().await {
+            Some(Ok(ResponseEvent::OutputItemDone(ResponseItem::Message {
+                role,
+                content,
+                ..
+            }))) if role == "assistant" => {
+                for c in content {
+                    if let ContentItem::OutputText { text } = c {
+                        summary.push_str(&text);
+                    }
+                }
+            }
+            Some(Ok(ResponseEvent::Completed { .. })) => break,
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e),
+            None => {
+                return Err(CodexErr::Stream(
+                    "stream closed before response.completed".into(),
+                    None,
+                ));
+            }
+        }
+    }
+
+    if summary.trim().is_empty() {
+        return Err(CodexErr::Stream("summary response was empty".into(), None));
+    }
+    Ok(summary)
+}
+
 async fn run_compact_task(
     sess: Arc<Session>,
     turn_context: &TurnContext,
@@ -1857,6 +3679,10 @@ async fn run_compact_task(
     input: Vec<InputItem>,
     compact_instructions: String,
 ) {
+    let cancel = sess.cancellation_token(&sub_id);
+    let Some(_task_gate) = sess.enter_task(&cancel).await else {
+        return;
+    };
     let model_context_window = turn_context.client.get_model_context_window();
     let start_event = Event {
         id: sub_id.clone(),
@@ -1877,6 +3703,7 @@ async fn run_compact_task(
         store: !turn_context.disable_response_storage,
         tools: Vec::new(),
         base_instructions_override: Some(compact_instructions.clone()),
+        response_format: None,
     };
 
     let max_retries = turn_context.client.get_provider().stream_max_retries();
@@ -1899,14 +3726,15 @@ async fn run_compact_task(
                         ),
                     )
                     .await;
-                    tokio::time::sleep(delay).await;
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = cancel.cancelled() => return,
+                    }
                     continue;
                 } else {
                     let event = Event {
                         id: sub_id.clone(),
-                        msg: EventMsg::Error(ErrorEvent {
-                            message: e.to_string(),
-                        }),
+                        msg: EventMsg::Error(e.to_error_event()),
                     };
                     sess.send_event(event).await;
                     return;
@@ -1938,14 +3766,351 @@ async fn run_compact_task(
     sess.send_event(event).await;
 }
 
+/// Runs the calls of an [`Op::RunToolPipeline`] in order, as if the model had
+/// made them, and stops at the first one that fails.
+async fn run_tool_pipeline(
+    sess: Arc<Session>,
+    turn_context: &TurnContext,
+    sub_id: String,
+    steps: Vec<ToolPipelineStep>,
+) {
+    let cancel = sess.cancellation_token(&sub_id);
+    let Some(_task_gate) = sess.enter_task(&cancel).await else {
+        return;
+    };
+    let event = Event {
+        id: sub_id.clone(),
+        msg: EventMsg::TaskStarted(TaskStartedEvent {
+            model_context_window: turn_context.client.get_model_context_window(),
+        }),
+    };
+    if sess.tx_event.send(event).await.is_err() {
+        return;
+    }
+
+    let turn_diff_tracker = SharedTurnDiffTracker::new(TurnDiffTracker::new());
+    let total = steps.len();
+    let mut outputs = Vec::with_capacity(total);
+    for (index, step) in steps.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            // `TurnAborted` is sent in place of `TaskComplete`.
+            sess.remove_task(&sub_id);
+            return;
+        }
+        let arguments = match step.arguments {
+            serde_json::Value::Null => "{}".to_string(),
+            arguments => arguments.to_string(),
+        };
+        let item = ResponseItem::FunctionCall {
+            id: None,
+            name: step.tool.clone(),
+            arguments,
+            call_id: format!("pipeline_{sub_id}_{index}"),
+        };
+        let response =
+            handle_response_item(&sess, turn_context, &turn_diff_tracker, &sub_id, item).await;
+        let (content, success) = match response {
+            Ok(Some(response)) => pipeline_step_output(response),
+            Ok(None) => (String::new(), None),
+            Err(e) => (e.to_string(), Some(false)),
+        };
+        outputs.push(format!("## {}\n\n{content}", step.tool));
+        if success == Some(false) {
+            let event = Event {
+                id: sub_id.clone(),
+                msg: EventMsg::Error(ErrorEvent {
+                    message: format!(
+                        "pipeline step {}/{total} `{}` failed: {content}",
+                        index + 1,
+                        step.tool
+                    ),
+                    ..Default::default()
+                }),
+            };
+            sess.tx_event.send(event).await.ok();
+            break;
+        }
+        sess.notify_background_event(
+            &sub_id,
+            format!("pipeline step {}/{total} `{}` done", index + 1, step.tool),
+        )
+        .await;
+    }
+
+    sess.remove_task(&sub_id);
+    let report = outputs.join("\n\n");
+    let event = Event {
+        id: sub_id.clone(),
+        msg: EventMsg::AgentMessage(AgentMessageEvent {
+            message: report.clone(),
+        }),
+    };
+    sess.send_event(event).await;
+    let event = Event {
+        id: sub_id,
+        msg: EventMsg::TaskComplete(TaskCompleteEvent {
+            last_agent_message: Some(report),
+        }),
+    };
+    sess.send_event(event).await;
+}
+
+/// Runs the steps of a workflow in order. A step whose `when` condition does
+/// not hold is skipped; a denied approval or a failed step ends the run,
+/// unless the step may fail.
+async fn run_workflow(
+    sess: Arc<Session>,
+    turn_context: &TurnContext,
+    sub_id: String,
+    name: String,
+    workflow: Workflow,
+    inputs: HashMap<String, String>,
+    log: StepLog,
+) {
+    let cancel = sess.cancellation_token(&sub_id);
+    let Some(_task_gate) = sess.enter_task(&cancel).await else {
+        return;
+    };
+    sess.budget.start_turn();
+    let event = Event {
+        id: sub_id.clone(),
+        msg: EventMsg::TaskStarted(TaskStartedEvent {
+            model_context_window: turn_context.client.get_model_context_window(),
+        }),
+    };
+    if sess.tx_event.send(event).await.is_err() {
+        return;
+    }
+
+    let turn_diff_tracker = SharedTurnDiffTracker::new(TurnDiffTracker::new());
+    let mut run = WorkflowRun::new(&workflow, inputs);
+    let total = workflow.steps.len();
+    let mut summary = Vec::with_capacity(total);
+    let mut error = None;
+    for (index, step) in workflow.steps.iter().enumerate() {
+        if cancel.is_cancelled() {
+            // `TurnAborted` is sent in place of `TaskComplete`.
+            sess.remove_task(&sub_id);
+            return;
+        }
+        let started_at = Utc::now();
+        let started = Instant::now();
+        let (status, output) = match run_workflow_step(
+            &sess,
+            turn_context,
+            &turn_diff_tracker,
+            &sub_id,
+            &name,
+            &run,
+            step,
+            &cancel,
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(message) => (StepStatus::Failed, message),
+        };
+        if cancel.is_cancelled() {
+            sess.remove_task(&sub_id);
+            return;
+        }
+
+        let entry = StepLogEntry {
+            workflow: &name,
+            step: &step.id,
+            status,
+            started_at,
+            duration_ms: started.elapsed().as_millis(),
+            output: output.clone(),
+        };
+        if let Err(e) = log.append(entry).await {
+            warn!("failed to write {}: {e}", log.path().display());
+        }
+        sess.notify_background_event(
+            &sub_id,
+            format!(
+                "workflow `{name}` step {}/{total} `{}` {}",
+                index + 1,
+                step.id,
+                status.as_str()
+            ),
+        )
+        .await;
+        summary.push(format!("- `{}`: {}", step.id, status.as_str()));
+
+        let stop = match status {
+            StepStatus::Succeeded | StepStatus::Skipped => false,
+            StepStatus::Failed => !step.continue_on_error,
+            StepStatus::Denied => true,
+        };
+        if stop {
+            error = Some(format!(
+                "workflow `{name}` stopped at step `{}` ({}): {output}",
+                step.id,
+                status.as_str()
+            ));
+            break;
+        }
+        run.record(&step.id, status, output);
+    }
+
+    sess.remove_task(&sub_id);
+    if let Some(message) = error {
+        let event = Event {
+            id: sub_id.clone(),
+            msg: EventMsg::Error(ErrorEvent {
+                message,
+                ..Default::default()
+            }),
+        };
+        sess.send_event(event).await;
+    }
+    let report = format!(
+        "Workflow `{name}`:\n\n{}\n\nStep log: {}",
+        summary.join("\n"),
+        log.path().display()
+    );
+    let event = Event {
+        id: sub_id.clone(),
+        msg: EventMsg::AgentMessage(AgentMessageEvent {
+            message: report.clone(),
+        }),
+    };
+    sess.send_event(event).await;
+    let event = Event {
+        id: sub_id,
+        msg: EventMsg::TaskComplete(TaskCompleteEvent {
+            last_agent_message: Some(report),
+        }),
+    };
+    sess.send_event(event).await;
+}
+
+/// Runs one workflow step and returns its status and output. `Err` carries
+/// the output of a step that could not be started, e.g. for a reference to
+/// a step that has not run.
+#[allow(clippy::too_many_arguments)]
+async fn run_workflow_step(
+    sess: &Arc<Session>,
+    turn_context: &TurnContext,
+    turn_diff_tracker: &SharedTurnDiffTracker,
+    sub_id: &str,
+    name: &str,
+    run: &WorkflowRun,
+    step: &WorkflowStep,
+    cancel: &CancellationToken,
+) -> Result<(StepStatus, String), String> {
+    if let Some(when) = &step.when
+        && !run.evaluate(when)?
+    {
+        return Ok((StepStatus::Skipped, format!("`{when}` does not hold")));
+    }
+
+    if let Some(approval) = &step.approval {
+        let reason = run.render(approval)?;
+        if turn_context.approval_policy == AskForApproval::Never {
+            return Ok((
+                StepStatus::Denied,
+                "the step requires approval but the approval policy is `never`".to_string(),
+            ));
+        }
+        // Approval requests show a command, so present the step as one.
+        let rx_approve = sess
+            .request_command_approval(
+                sub_id.to_string(),
+                format!("workflow_{sub_id}_{}", step.id),
+                vec!["workflow".to_string(), name.to_string(), step.id.clone()],
+                turn_context.cwd.clone(),
+                Some(reason),
+                None,
+            )
+            .await;
+        match rx_approve.await.unwrap_or_default() {
+            ReviewDecision::Approved | ReviewDecision::ApprovedForSession => {}
+            ReviewDecision::Denied | ReviewDecision::Abort => {
+                return Ok((StepStatus::Denied, "approval denied".to_string()));
+            }
+        }
+    }
+
+    if let Some(tool) = &step.tool {
+        let arguments = match run.render_value(&step.arguments)? {
+            serde_json::Value::Null => "{}".to_string(),
+            arguments => arguments.to_string(),
+        };
+        let item = ResponseItem::FunctionCall {
+            id: None,
+            name: tool.clone(),
+            arguments,
+            call_id: format!("workflow_{sub_id}_{}", step.id),
+        };
+        let response =
+            handle_response_item(sess, turn_context, turn_diff_tracker, sub_id, item).await;
+        let (output, success) = match response {
+            Ok(Some(response)) => pipeline_step_output(response),
+            Ok(None) => (String::new(), None),
+            Err(e) => (e.to_string(), Some(false)),
+        };
+        let status = if success == Some(false) {
+            StepStatus::Failed
+        } else {
+            StepStatus::Succeeded
+        };
+        return Ok((status, output));
+    }
+
+    let prompt = run.render(step.prompt.as_deref().unwrap_or_default())?;
+    let input = vec![InputItem::Text { text: prompt }];
+    match run_agent_turns(
+        sess.clone(),
+        turn_context,
+        sub_id.to_string(),
+        input,
+        cancel,
+    )
+    .await
+    {
+        Some(message) => Ok((StepStatus::Succeeded, message)),
+        None => Ok((StepStatus::Failed, "the model did not answer".to_string())),
+    }
+}
+
+/// The text and success flag of a pipeline step's output.
+fn pipeline_step_output(response: ResponseInputItem) -> (String, Option<bool>) {
+    let success = response_success(&response);
+    let content = match response {
+        ResponseInputItem::FunctionCallOutput { output, .. } => output.content,
+        ResponseInputItem::McpToolCallOutput { result, .. } => match result {
+            Ok(result) => convert_call_tool_result_to_function_call_output_payload(&result).content,
+            Err(e) => e,
+        },
+        ResponseInputItem::CustomToolCallOutput { output, .. } => output,
+        ResponseInputItem::Message { .. } => String::new(),
+    };
+    (content, success)
+}
+
 async fn handle_response_item(
     sess: &Session,
     turn_context: &TurnContext,
-    turn_diff_tracker: &mut TurnDiffTracker,
+    turn_diff_tracker: &SharedTurnDiffTracker,
     sub_id: &str,
     item: ResponseItem,
 ) -> CodexResult<Option<ResponseInputItem>> {
     debug!(?item, "Output item");
+    let tool_call = hook_tool_call(&item);
+    if let Some(call) = &tool_call {
+        let runs = sess
+            .run_hooks(
+                sub_id,
+                &call.context(HookEvent::BeforeTool, sub_id, turn_context, None),
+            )
+            .await;
+        if let Some(blocked) = runs.iter().find(|run| run.blocks()) {
+            let message = format!("tool call blocked: {}", blocked.describe());
+            return Ok(Some(call.output(message)));
+        }
+    }
     let output = match item {
         ResponseItem::Message { content, .. } => {
             for item in content {
@@ -2026,6 +4191,7 @@ async fn handle_response_item(
                 command: action.command,
                 workdir: action.working_directory,
                 timeout_ms: action.timeout_ms,
+                max_output_bytes: None,
                 with_escalated_permissions: None,
                 justification: None,
             };
@@ -2096,18 +4262,204 @@ async fn handle_response_item(
         }
         ResponseItem::Other => None,
     };
-    Ok(output)
-}
-
+    if let (Some(call), Some(response)) = (&tool_call, output.as_ref()) {
+        let success = response_success(response);
+        let runs = sess
+            .run_hooks(
+                sub_id,
+                &call.context(HookEvent::AfterTool, sub_id, turn_context, success),
+            )
+            .await;
+        if let Some(attachment) = hooks::attachment(&runs) {
+            return Ok(output.map(|response| attach_hook_output(response, &attachment)));
+        }
+    }
+    Ok(output)
+}
+
+/// A tool call as seen by hooks.
+struct HookToolCall {
+    name: String,
+    call_id: String,
+    arguments: String,
+    /// Custom tool calls are answered with custom tool call outputs.
+    custom: bool,
+}
+
+impl HookToolCall {
+    fn context<'a>(
+        &'a self,
+        event: HookEvent,
+        turn_id: &'a str,
+        turn_context: &'a TurnContext,
+        success: Option<bool>,
+    ) -> HookContext<'a> {
+        HookContext {
+            event,
+            turn_id,
+            cwd: &turn_context.cwd,
+            tool: Some(&self.name),
+            call_id: Some(&self.call_id),
+            arguments: Some(&self.arguments),
+            success,
+        }
+    }
+
+    fn output(&self, content: String) -> ResponseInputItem {
+        let call_id = self.call_id.clone();
+        if self.custom {
+            ResponseInputItem::CustomToolCallOutput {
+                call_id,
+                output: content,
+            }
+        } else {
+            ResponseInputItem::FunctionCallOutput {
+                call_id,
+                output: FunctionCallOutputPayload {
+                    content,
+                    success: Some(false),
+                },
+            }
+        }
+    }
+}
+
+fn hook_tool_call(item: &ResponseItem) -> Option<HookToolCall> {
+    match item {
+        ResponseItem::FunctionCall {
+            name,
+            arguments,
+            call_id,
+            ..
+        } => Some(HookToolCall {
+            name: name.clone(),
+            call_id: call_id.clone(),
+            arguments: arguments.clone(),
+            custom: false,
+        }),
+        ResponseItem::LocalShellCall {
+            id,
+            call_id,
+            action,
+            ..
+        } => Some(HookToolCall {
+            name: "local_shell".to_string(),
+            call_id: call_id.clone().or_else(|| id.clone())?,
+            arguments: serde_json::to_string(action).unwrap_or_default(),
+            custom: false,
+        }),
+        ResponseItem::CustomToolCall {
+            name,
+            input,
+            call_id,
+            ..
+        } => Some(HookToolCall {
+            name: name.clone(),
+            call_id: call_id.clone(),
+            arguments: input.clone(),
+            custom: true,
+        }),
+        _ => None,
+    }
+}
+
+fn response_success(response: &ResponseInputItem) -> Option<bool> {
+    match response {
+        ResponseInputItem::FunctionCallOutput { output, .. } => output.success,
+        ResponseInputItem::McpToolCallOutput { result, .. } => Some(
+            result
+                .as_ref()
+                .is_ok_and(|result| result.is_error != Some(true)),
+        ),
+        ResponseInputItem::CustomToolCallOutput { .. } | ResponseInputItem::Message { .. } => None,
+    }
+}
+
+/// Append the output of hooks to the output of a tool call.
+fn attach_hook_output(response: ResponseInputItem, attachment: &str) -> ResponseInputItem {
+    let append = |content: String| format!("{content}\n\n{attachment}");
+    match response {
+        ResponseInputItem::FunctionCallOutput { call_id, output } => {
+            ResponseInputItem::FunctionCallOutput {
+                call_id,
+                output: FunctionCallOutputPayload {
+                    content: append(output.content),
+                    success: output.success,
+                },
+            }
+        }
+        ResponseInputItem::McpToolCallOutput { call_id, result } => {
+            let output = match &result {
+                Ok(result) => convert_call_tool_result_to_function_call_output_payload(result),
+                Err(err) => FunctionCallOutputPayload {
+                    content: err.clone(),
+                    success: Some(false),
+                },
+            };
+            ResponseInputItem::FunctionCallOutput {
+                call_id,
+                output: FunctionCallOutputPayload {
+                    content: append(output.content),
+                    success: output.success,
+                },
+            }
+        }
+        ResponseInputItem::CustomToolCallOutput { call_id, output } => {
+            ResponseInputItem::CustomToolCallOutput {
+                call_id,
+                output: append(output),
+            }
+        }
+        ResponseInputItem::Message { .. } => response,
+    }
+}
+
+fn turn_hook_context<'a>(
+    event: HookEvent,
+    turn_id: &'a str,
+    turn_context: &'a TurnContext,
+) -> HookContext<'a> {
+    HookContext {
+        event,
+        turn_id,
+        cwd: &turn_context.cwd,
+        tool: None,
+        call_id: None,
+        arguments: None,
+        success: None,
+    }
+}
+
+/// Output of turn hooks, added to the conversation as a user message.
+fn hook_output_message(output: String) -> ResponseItem {
+    ResponseItem::Message {
+        id: None,
+        role: "user".to_string(),
+        content: vec![ContentItem::InputText { text: output }],
+    }
+}
+
 async fn handle_function_call(
     sess: &Session,
     turn_context: &TurnContext,
-    turn_diff_tracker: &mut TurnDiffTracker,
+    turn_diff_tracker: &SharedTurnDiffTracker,
     sub_id: String,
     name: String,
     arguments: String,
     call_id: String,
 ) -> ResponseInputItem {
+    if let Some(tool) = sess.custom_tools.get(&name) {
+        return handle_custom_tool(
+            sess,
+            turn_context,
+            turn_diff_tracker,
+            sub_id,
+            tool,
+            call_id,
+            &arguments,
+        )
+        .await;
+    }
     match name.as_str() {
         "container.exec" | "shell" => {
             let params = match parse_container_exec_arguments(arguments, turn_context, &call_id) {
@@ -2144,7 +4496,20 @@ async fn handle_function_call(
                 }
             };
             let abs = turn_context.resolve_path(Some(args.path));
-            let output = match sess.inject_input(vec![InputItem::LocalImage { path: abs }]) {
+            let image = sess
+                .prepare_input(&sub_id, vec![InputItem::LocalImage { path: abs }])
+                .await;
+            if image.is_empty() {
+                return ResponseInputItem::FunctionCallOutput {
+                    call_id,
+                    output: FunctionCallOutputPayload {
+                        content: "unable to attach image (it could not be read or is too large)"
+                            .to_string(),
+                        success: Some(false),
+                    },
+                };
+            }
+            let output = match sess.inject_input(image) {
                 Ok(()) => FunctionCallOutputPayload {
                     content: "attached local image path".to_string(),
                     success: Some(true),
@@ -2176,6 +4541,7 @@ async fn handle_function_call(
                 env: HashMap::new(),
                 with_escalated_permissions: None,
                 justification: None,
+                limits: turn_context.exec_limits,
             };
             handle_container_exec_with_params(
                 exec_params,
@@ -2188,6 +4554,172 @@ async fn handle_function_call(
             .await
         }
         "update_plan" => handle_update_plan(sess, arguments, sub_id, call_id).await,
+        BACKGROUND_START_TOOL_NAME => {
+            handle_background_start(sess, turn_context, sub_id, call_id, arguments).await
+        }
+        BACKGROUND_LIST_TOOL_NAME | BACKGROUND_OUTPUT_TOOL_NAME | BACKGROUND_KILL_TOOL_NAME => {
+            ResponseInputItem::FunctionCallOutput {
+                call_id,
+                output: sess.background_jobs.handle_tool_call(&name, &arguments),
+            }
+        }
+        READ_RESOURCE_TOOL_NAME => handle_read_resource(sess, call_id, &arguments).await,
+        READ_ARTIFACT_TOOL_NAME => {
+            let output = match &sess.artifacts {
+                Some(artifacts) => artifacts.handle_read_artifact(&arguments),
+                None => FunctionCallOutputPayload {
+                    content: "artifacts are not enabled".to_string(),
+                    success: Some(false),
+                },
+            };
+            ResponseInputItem::FunctionCallOutput { call_id, output }
+        }
+        KB_SEARCH_TOOL_NAME => {
+            let output = match &sess.knowledge_base {
+                Some(knowledge_base) => knowledge_base.handle_kb_search(&arguments).await,
+                None => FunctionCallOutputPayload {
+                    content: "no knowledge base is configured".to_string(),
+                    success: Some(false),
+                },
+            };
+            ResponseInputItem::FunctionCallOutput { call_id, output }
+        }
+        AZURE_MONITOR_METRICS_TOOL_NAME
+        | AZURE_MONITOR_ALERTS_TOOL_NAME
+        | AZURE_MONITOR_ACTIVITY_LOG_TOOL_NAME => {
+            let output = match &sess.azure_monitor {
+                Some(azure_monitor) => azure_monitor.handle_function_call(&name, &arguments).await,
+                None => FunctionCallOutputPayload {
+                    content: "Azure Monitor is not configured".to_string(),
+                    success: Some(false),
+                },
+            };
+            ResponseInputItem::FunctionCallOutput { call_id, output }
+        }
+        KEY_VAULT_LIST_VAULTS_TOOL_NAME
+        | KEY_VAULT_LIST_ITEMS_TOOL_NAME
+        | KEY_VAULT_CHECK_EXPIRY_TOOL_NAME => {
+            let output = match &sess.key_vault {
+                Some(key_vault) => key_vault.handle_function_call(&name, &arguments).await,
+                None => FunctionCallOutputPayload {
+                    content: "Key Vault is not configured".to_string(),
+                    success: Some(false),
+                },
+            };
+            ResponseInputItem::FunctionCallOutput { call_id, output }
+        }
+        KEY_VAULT_GET_SECRET_TOOL_NAME => {
+            handle_key_vault_get_secret(sess, turn_context, sub_id, call_id, &arguments).await
+        }
+        AZURE_STORAGE_LIST_CONTAINERS_TOOL_NAME
+        | AZURE_STORAGE_LIST_BLOBS_TOOL_NAME
+        | AZURE_STORAGE_READ_BLOB_TOOL_NAME
+        | AZURE_STORAGE_UPLOAD_BLOB_TOOL_NAME
+        | AZURE_STORAGE_GENERATE_SAS_TOOL_NAME => {
+            handle_azure_storage_call(sess, turn_context, sub_id, call_id, &name, &arguments).await
+        }
+        ARM_WHAT_IF_TOOL_NAME
+        | ARM_LIST_DEPLOYMENTS_TOOL_NAME
+        | ARM_DEPLOYMENT_ERRORS_TOOL_NAME => {
+            let output = match &sess.arm_deployments {
+                Some(arm_deployments) => {
+                    arm_deployments
+                        .handle_function_call(&name, &arguments, &turn_context.cwd)
+                        .await
+                }
+                None => FunctionCallOutputPayload {
+                    content: "ARM deployments are not configured".to_string(),
+                    success: Some(false),
+                },
+            };
+            ResponseInputItem::FunctionCallOutput { call_id, output }
+        }
+        ARM_DEPLOY_TOOL_NAME => {
+            handle_arm_deploy(sess, turn_context, sub_id, call_id, &arguments).await
+        }
+        SQL_LIST_DATABASES_TOOL_NAME
+        | SQL_LIST_TABLES_TOOL_NAME
+        | SQL_DESCRIBE_TABLE_TOOL_NAME
+        | SQL_QUERY_TOOL_NAME => {
+            let output = match &sess.sql {
+                Some(sql) => sql.handle_function_call(&name, &arguments).await,
+                None => FunctionCallOutputPayload {
+                    content: "SQL profiles are not configured".to_string(),
+                    success: Some(false),
+                },
+            };
+            ResponseInputItem::FunctionCallOutput { call_id, output }
+        }
+        K8S_GET_TOOL_NAME | K8S_DESCRIBE_TOOL_NAME | K8S_LOGS_TOOL_NAME | K8S_EVENTS_TOOL_NAME => {
+            let output = match &sess.kubernetes {
+                Some(kubernetes) => kubernetes.handle_function_call(&name, &arguments).await,
+                None => FunctionCallOutputPayload {
+                    content: "Kubernetes contexts are not configured".to_string(),
+                    success: Some(false),
+                },
+            };
+            ResponseInputItem::FunctionCallOutput { call_id, output }
+        }
+        POST_MESSAGE_TOOL_NAME => {
+            handle_post_message(sess, turn_context, sub_id, call_id, &arguments).await
+        }
+        TICKET_SEARCH_TOOL_NAME
+        | TICKET_GET_TOOL_NAME
+        | TICKET_CREATE_TOOL_NAME
+        | TICKET_COMMENT_TOOL_NAME => {
+            handle_ticketing_call(sess, turn_context, sub_id, call_id, &name, &arguments).await
+        }
+        REFRESH_ENVIRONMENT_TOOL_NAME => {
+            let env = create_env(&turn_context.shell_environment_policy);
+            let snapshot = EnvironmentSnapshot::collect(&turn_context.cwd, &env).await;
+            ResponseInputItem::FunctionCallOutput {
+                call_id,
+                output: FunctionCallOutputPayload {
+                    content: snapshot.serialize_to_xml(),
+                    success: Some(true),
+                },
+            }
+        }
+        EDIT_FILES_TOOL_NAME => {
+            crate::edit_files::handle_edit_files(
+                sess,
+                turn_context,
+                turn_diff_tracker,
+                &sub_id,
+                call_id,
+                &arguments,
+            )
+            .await
+        }
+        GIT_STATUS_TOOL_NAME | GIT_DIFF_TOOL_NAME | GIT_COMMIT_TOOL_NAME | GIT_STASH_TOOL_NAME => {
+            match crate::git_tools::handle_tool_call(&name, &arguments, &turn_context.cwd).await {
+                GitToolCall::Output(output) => {
+                    ResponseInputItem::FunctionCallOutput { call_id, output }
+                }
+                // Commits and stash changes go through the same approval and
+                // sandbox checks as the equivalent shell command.
+                GitToolCall::Exec(command) => {
+                    let exec_params = ExecParams {
+                        command,
+                        cwd: turn_context.cwd.clone(),
+                        timeout_ms: None,
+                        env: create_env(&turn_context.shell_environment_policy),
+                        with_escalated_permissions: None,
+                        justification: None,
+                        limits: turn_context.exec_limits,
+                    };
+                    handle_container_exec_with_params(
+                        exec_params,
+                        sess,
+                        turn_context,
+                        turn_diff_tracker,
+                        sub_id,
+                        call_id,
+                    )
+                    .await
+                }
+            }
+        }
         EXEC_COMMAND_TOOL_NAME => {
             // TODO(mbolin): Sandbox check.
             let exec_params = match serde_json::from_str::<ExecCommandParams>(&arguments) {
@@ -2238,11 +4770,34 @@ async fn handle_function_call(
         }
         _ => {
             match sess.mcp_connection_manager.parse_tool_name(&name) {
+                Some((server, tool_name))
+                    if turn_context.dry_run
+                        && !sess.mcp_connection_manager.is_read_only_tool(&name) =>
+                {
+                    let arguments = serde_json::from_str::<serde_json::Value>(&arguments)
+                        .unwrap_or(serde_json::Value::String(arguments));
+                    let request = serde_json::json!({
+                        "server": server,
+                        "method": "tools/call",
+                        "params": { "name": tool_name, "arguments": arguments },
+                    });
+                    ResponseInputItem::FunctionCallOutput {
+                        call_id,
+                        output: dry_run_output(&request),
+                    }
+                }
                 Some((server, tool_name)) => {
                     // TODO(mbolin): Determine appropriate timeout for tool call.
                     let timeout = None;
                     handle_mcp_tool_call(
-                        sess, &sub_id, call_id, server, tool_name, arguments, timeout,
+                        sess,
+                        turn_context,
+                        &sub_id,
+                        call_id,
+                        server,
+                        tool_name,
+                        arguments,
+                        timeout,
                     )
                     .await
                 }
@@ -2261,56 +4816,704 @@ async fn handle_function_call(
     }
 }
 
-async fn handle_custom_tool_call(
+/// Run a tool declared under `[custom_tools]` in config. Command tools run
+/// like `shell` calls: approved, sandboxed, audited and reported with
+/// `ExecCommandBegin`/`ExecCommandEnd` events.
+async fn handle_custom_tool(
+    sess: &Session,
+    turn_context: &TurnContext,
+    turn_diff_tracker: &SharedTurnDiffTracker,
+    sub_id: String,
+    tool: &CustomToolConfig,
+    call_id: String,
+    arguments: &str,
+) -> ResponseInputItem {
+    if turn_context.dry_run
+        && let Some(request) = custom_tools::dry_run_request(tool, arguments, &turn_context.cwd)
+    {
+        let output = match request {
+            Ok(request) => dry_run_output(&request),
+            Err(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(false),
+            },
+        };
+        return ResponseInputItem::FunctionCallOutput { call_id, output };
+    }
+    match custom_tools::exec_params_for_call(
+        tool,
+        arguments,
+        &turn_context.cwd,
+        create_env(&turn_context.shell_environment_policy),
+        turn_context.exec_limits,
+    ) {
+        Some(Ok(params)) => {
+            handle_container_exec_with_params(
+                params,
+                sess,
+                turn_context,
+                turn_diff_tracker,
+                sub_id,
+                call_id,
+            )
+            .await
+        }
+        Some(Err(content)) => ResponseInputItem::FunctionCallOutput {
+            call_id,
+            output: FunctionCallOutputPayload {
+                content,
+                success: Some(false),
+            },
+        },
+        None => ResponseInputItem::FunctionCallOutput {
+            call_id,
+            output: custom_tools::call(tool, arguments).await,
+        },
+    }
+}
+
+async fn handle_custom_tool_call(
+    sess: &Session,
+    turn_context: &TurnContext,
+    turn_diff_tracker: &SharedTurnDiffTracker,
+    sub_id: String,
+    name: String,
+    input: String,
+    call_id: String,
+) -> ResponseInputItem {
+    info!("CustomToolCall: {name} {input}");
+    match name.as_str() {
+        "apply_patch" => {
+            let exec_params = ExecParams {
+                command: vec!["apply_patch".to_string(), input.clone()],
+                cwd: turn_context.cwd.clone(),
+                timeout_ms: None,
+                env: HashMap::new(),
+                with_escalated_permissions: None,
+                justification: None,
+                limits: turn_context.exec_limits,
+            };
+            let resp = handle_container_exec_with_params(
+                exec_params,
+                sess,
+                turn_context,
+                turn_diff_tracker,
+                sub_id,
+                call_id,
+            )
+            .await;
+
+            // Convert function-call style output into a custom tool call output
+            match resp {
+                ResponseInputItem::FunctionCallOutput { call_id, output } => {
+                    ResponseInputItem::CustomToolCallOutput {
+                        call_id,
+                        output: output.content,
+                    }
+                }
+                // Pass through if already a custom tool output or other variant
+                other => other,
+            }
+        }
+        _ => {
+            debug!("unexpected CustomToolCall from stream");
+            ResponseInputItem::CustomToolCallOutput {
+                call_id,
+                output: format!("unsupported custom tool call: {name}"),
+            }
+        }
+    }
+}
+
+/// Read a Key Vault secret into the environment of later commands once the
+/// user approves. The value never reaches the model: the reply only names
+/// the variable, and the value is masked in everything recorded afterwards.
+async fn handle_key_vault_get_secret(
+    sess: &Session,
+    turn_context: &TurnContext,
+    sub_id: String,
+    call_id: String,
+    arguments: &str,
+) -> ResponseInputItem {
+    let reply = |content: String, success: bool| ResponseInputItem::FunctionCallOutput {
+        call_id: call_id.clone(),
+        output: FunctionCallOutputPayload {
+            content,
+            success: Some(success),
+        },
+    };
+    let Some(key_vault) = &sess.key_vault else {
+        return reply("Key Vault is not configured".to_string(), false);
+    };
+    let params = match parse_get_secret(arguments) {
+        Ok(params) => params,
+        Err(e) => return reply(e, false),
+    };
+    let audit_action = AuditAction::SecretRead {
+        vault: params.vault.clone(),
+        name: params.name.clone(),
+    };
+
+    if turn_context.approval_policy == AskForApproval::Never {
+        let reason = "reading a Key Vault secret requires approval but the approval policy is \
+            `never`"
+            .to_string();
+        sess.record_audit(
+            audit_action,
+            AuditApproval::Rejected {
+                reason: reason.clone(),
+            },
+            None,
+        )
+        .await;
+        return reply(format!("secret read rejected: {reason}"), false);
+    }
+    // Approval requests show a command, so present the read as one.
+    let rx_approve = sess
+        .request_command_approval(
+            sub_id,
+            call_id.clone(),
+            vec![
+                KEY_VAULT_GET_SECRET_TOOL_NAME.to_string(),
+                params.vault.clone(),
+                params.name.clone(),
+            ],
+            turn_context.cwd.clone(),
+            Some(format!(
+                "Read secret `{}` from Key Vault `{}` into ${} for later commands",
+                params.name, params.vault, params.env_var
+            )),
+            None,
+        )
+        .await;
+    let approval = match rx_approve.await.unwrap_or_default() {
+        ReviewDecision::Approved => AuditApproval::Approved,
+        // Not remembered: the next secret read asks again.
+        ReviewDecision::ApprovedForSession => AuditApproval::ApprovedForSession,
+        ReviewDecision::Denied | ReviewDecision::Abort => {
+            sess.record_audit(audit_action, AuditApproval::Denied, None)
+                .await;
+            return reply("secret read rejected by user".to_string(), false);
+        }
+    };
+
+    match key_vault.get_secret(&params).await {
+        Ok(value) => {
+            sess.add_secret_env(params.env_var.clone(), value);
+            sess.record_audit(
+                audit_action,
+                approval,
+                Some(AuditOutcome {
+                    success: true,
+                    exit_code: None,
+                    error: None,
+                }),
+            )
+            .await;
+            reply(
+                format!(
+                    "Secret `{}` from `{}` is set as ${} for later shell commands. Its value is \
+                     masked in command output.",
+                    params.name, params.vault, params.env_var
+                ),
+                true,
+            )
+        }
+        Err(e) => {
+            sess.record_audit(audit_action, approval, Some(AuditOutcome::error(&e)))
+                .await;
+            reply(e, false)
+        }
+    }
+}
+
+/// Run an Azure Storage tool. Uploads and SAS URLs granting more than read
+/// access ask for approval first; uploads are skipped in dry-run mode.
+async fn handle_azure_storage_call(
+    sess: &Session,
+    turn_context: &TurnContext,
+    sub_id: String,
+    call_id: String,
+    name: &str,
+    arguments: &str,
+) -> ResponseInputItem {
+    let reply = |content: String| ResponseInputItem::FunctionCallOutput {
+        call_id: call_id.clone(),
+        output: FunctionCallOutputPayload {
+            content,
+            success: Some(false),
+        },
+    };
+    let Some(azure_storage) = &sess.azure_storage else {
+        return reply("Azure Storage is not configured".to_string());
+    };
+    let mut audit = None;
+    if let Some(request) = azure_storage::write_request(name, arguments) {
+        if turn_context.dry_run && name == AZURE_STORAGE_UPLOAD_BLOB_TOOL_NAME {
+            return ResponseInputItem::FunctionCallOutput {
+                call_id,
+                output: dry_run_output(&request),
+            };
+        }
+        let audit_action = AuditAction::RemoteWrite {
+            tool: name.to_string(),
+            request: request.clone(),
+        };
+        if turn_context.approval_policy == AskForApproval::Never {
+            let reason = format!("`{name}` requires approval but the approval policy is `never`");
+            sess.record_audit(
+                audit_action,
+                AuditApproval::Rejected {
+                    reason: reason.clone(),
+                },
+                None,
+            )
+            .await;
+            return reply(reason);
+        }
+        // Approval requests show a command, so present the call as one.
+        let rx_approve = sess
+            .request_command_approval(
+                sub_id,
+                call_id.clone(),
+                vec![name.to_string(), request.to_string()],
+                turn_context.cwd.clone(),
+                Some(format!("Azure Storage tool `{name}` requires approval")),
+                None,
+            )
+            .await;
+        let approval = match rx_approve.await.unwrap_or_default() {
+            ReviewDecision::Approved => AuditApproval::Approved,
+            ReviewDecision::ApprovedForSession => AuditApproval::ApprovedForSession,
+            ReviewDecision::Denied | ReviewDecision::Abort => {
+                sess.record_audit(audit_action, AuditApproval::Denied, None)
+                    .await;
+                return reply(format!("`{name}` rejected by user"));
+            }
+        };
+        audit = Some((audit_action, approval));
+    }
+    let output = azure_storage
+        .handle_function_call(name, arguments, &turn_context.cwd)
+        .await;
+    if let Some((audit_action, approval)) = audit {
+        sess.record_audit(audit_action, approval, Some(audit_outcome_of(&output)))
+            .await;
+    }
+    ResponseInputItem::FunctionCallOutput { call_id, output }
+}
+
+/// Post a message to a Teams or Slack channel after the user approves it.
+/// In dry-run mode the rendered message is returned instead.
+async fn handle_post_message(
+    sess: &Session,
+    turn_context: &TurnContext,
+    sub_id: String,
+    call_id: String,
+    arguments: &str,
+) -> ResponseInputItem {
+    let reply = |content: String, success: bool| ResponseInputItem::FunctionCallOutput {
+        call_id: call_id.clone(),
+        output: FunctionCallOutputPayload {
+            content,
+            success: Some(success),
+        },
+    };
+    let Some(messaging) = &sess.messaging else {
+        return reply("Messaging is not configured".to_string(), false);
+    };
+    let message = match messaging.prepare(arguments) {
+        Ok(message) => message,
+        Err(e) => return reply(e, false),
+    };
+    if turn_context.dry_run {
+        return ResponseInputItem::FunctionCallOutput {
+            call_id,
+            output: dry_run_output(&serde_json::json!({
+                "channel": message.channel,
+                "payload": message.payload,
+            })),
+        };
+    }
+    let audit_action = AuditAction::RemoteWrite {
+        tool: POST_MESSAGE_TOOL_NAME.to_string(),
+        request: serde_json::json!({
+            "channel": message.channel,
+            "text": message.text,
+        }),
+    };
+    if turn_context.approval_policy == AskForApproval::Never {
+        let reason = format!(
+            "`{POST_MESSAGE_TOOL_NAME}` requires approval but the approval policy is `never`"
+        );
+        sess.record_audit(
+            audit_action,
+            AuditApproval::Rejected {
+                reason: reason.clone(),
+            },
+            None,
+        )
+        .await;
+        return reply(reason, false);
+    }
+    // Approval requests show a command, so present the post as one.
+    let rx_approve = sess
+        .request_command_approval(
+            sub_id,
+            call_id.clone(),
+            vec![POST_MESSAGE_TOOL_NAME.to_string(), message.channel.clone()],
+            turn_context.cwd.clone(),
+            Some(format!("Post to `{}`:\n{}", message.channel, message.text)),
+            None,
+        )
+        .await;
+    let approval = match rx_approve.await.unwrap_or_default() {
+        ReviewDecision::Approved => AuditApproval::Approved,
+        ReviewDecision::ApprovedForSession => AuditApproval::ApprovedForSession,
+        ReviewDecision::Denied | ReviewDecision::Abort => {
+            sess.record_audit(audit_action, AuditApproval::Denied, None)
+                .await;
+            return reply(
+                format!("post to `{}` rejected by user", message.channel),
+                false,
+            );
+        }
+    };
+    let result = messaging.post(&message).await;
+    sess.record_audit(
+        audit_action,
+        approval,
+        Some(audit_outcome_of_result(&result)),
+    )
+    .await;
+    match result {
+        Ok(content) => reply(content, true),
+        Err(e) => reply(e, false),
+    }
+}
+
+/// Run a ticketing tool. Creating a ticket or commenting on one asks for
+/// approval first and is skipped in dry-run mode.
+async fn handle_ticketing_call(
     sess: &Session,
     turn_context: &TurnContext,
-    turn_diff_tracker: &mut TurnDiffTracker,
     sub_id: String,
-    name: String,
-    input: String,
     call_id: String,
+    name: &str,
+    arguments: &str,
 ) -> ResponseInputItem {
-    info!("CustomToolCall: {name} {input}");
-    match name.as_str() {
-        "apply_patch" => {
-            let exec_params = ExecParams {
-                command: vec!["apply_patch".to_string(), input.clone()],
-                cwd: turn_context.cwd.clone(),
-                timeout_ms: None,
-                env: HashMap::new(),
-                with_escalated_permissions: None,
-                justification: None,
+    let reply = |content: String| ResponseInputItem::FunctionCallOutput {
+        call_id: call_id.clone(),
+        output: FunctionCallOutputPayload {
+            content,
+            success: Some(false),
+        },
+    };
+    let Some(ticketing) = &sess.ticketing else {
+        return reply("Ticketing is not configured".to_string());
+    };
+    let mut audit = None;
+    if let Some(request) = ticketing::write_request(name, arguments) {
+        if turn_context.dry_run {
+            return ResponseInputItem::FunctionCallOutput {
+                call_id,
+                output: dry_run_output(&request),
             };
-            let resp = handle_container_exec_with_params(
-                exec_params,
-                sess,
-                turn_context,
-                turn_diff_tracker,
+        }
+        let audit_action = AuditAction::RemoteWrite {
+            tool: name.to_string(),
+            request,
+        };
+        if turn_context.approval_policy == AskForApproval::Never {
+            let reason = format!("`{name}` requires approval but the approval policy is `never`");
+            sess.record_audit(
+                audit_action,
+                AuditApproval::Rejected {
+                    reason: reason.clone(),
+                },
+                None,
+            )
+            .await;
+            return reply(reason);
+        }
+        // Approval requests show a command, so present the call as one.
+        let rx_approve = sess
+            .request_command_approval(
                 sub_id,
-                call_id,
+                call_id.clone(),
+                vec![name.to_string(), arguments.to_string()],
+                turn_context.cwd.clone(),
+                Some(format!("Ticketing tool `{name}` requires approval")),
+                None,
             )
             .await;
+        let approval = match rx_approve.await.unwrap_or_default() {
+            ReviewDecision::Approved => AuditApproval::Approved,
+            ReviewDecision::ApprovedForSession => AuditApproval::ApprovedForSession,
+            ReviewDecision::Denied | ReviewDecision::Abort => {
+                sess.record_audit(audit_action, AuditApproval::Denied, None)
+                    .await;
+                return reply(format!("`{name}` rejected by user"));
+            }
+        };
+        audit = Some((audit_action, approval));
+    }
+    let output = ticketing.handle_function_call(name, arguments).await;
+    if let Some((audit_action, approval)) = audit {
+        sess.record_audit(audit_action, approval, Some(audit_outcome_of(&output)))
+            .await;
+    }
+    ResponseInputItem::FunctionCallOutput { call_id, output }
+}
 
-            // Convert function-call style output into a custom tool call output
-            match resp {
-                ResponseInputItem::FunctionCallOutput { call_id, output } => {
-                    ResponseInputItem::CustomToolCallOutput {
-                        call_id,
-                        output: output.content,
-                    }
+/// Deploy an ARM or Bicep template. The what-if preview is shown in the
+/// approval request; in dry-run mode it is returned instead of deploying.
+async fn handle_arm_deploy(
+    sess: &Session,
+    turn_context: &TurnContext,
+    sub_id: String,
+    call_id: String,
+    arguments: &str,
+) -> ResponseInputItem {
+    let reply = |content: String, success: bool| ResponseInputItem::FunctionCallOutput {
+        call_id: call_id.clone(),
+        output: FunctionCallOutputPayload {
+            content,
+            success: Some(success),
+        },
+    };
+    let Some(arm_deployments) = &sess.arm_deployments else {
+        return reply("ARM deployments are not configured".to_string(), false);
+    };
+    let deployment = match arm_deployments.prepare(arguments, &turn_context.cwd).await {
+        Ok(deployment) => deployment,
+        Err(e) => return reply(e, false),
+    };
+    if turn_context.dry_run {
+        return ResponseInputItem::FunctionCallOutput {
+            call_id,
+            output: dry_run_output(&serde_json::json!({
+                "resource_group": deployment.resource_group,
+                "deployment_name": deployment.name,
+                "template": deployment.template,
+                "what_if": deployment.what_if,
+            })),
+        };
+    }
+    let audit_action = AuditAction::RemoteWrite {
+        tool: ARM_DEPLOY_TOOL_NAME.to_string(),
+        request: serde_json::json!({
+            "resource_group": deployment.resource_group,
+            "deployment_name": deployment.name,
+            "template": deployment.template,
+        }),
+    };
+    if turn_context.approval_policy == AskForApproval::Never {
+        let reason = format!(
+            "`{ARM_DEPLOY_TOOL_NAME}` requires approval but the approval policy is `never`"
+        );
+        sess.record_audit(
+            audit_action,
+            AuditApproval::Rejected {
+                reason: reason.clone(),
+            },
+            None,
+        )
+        .await;
+        return reply(reason, false);
+    }
+    // Approval requests show a command, so present the deployment as one.
+    let rx_approve = sess
+        .request_command_approval(
+            sub_id,
+            call_id.clone(),
+            vec![
+                ARM_DEPLOY_TOOL_NAME.to_string(),
+                deployment.resource_group.clone(),
+                deployment.template.clone(),
+            ],
+            turn_context.cwd.clone(),
+            Some(deployment.what_if.clone()),
+            None,
+        )
+        .await;
+    let approval = match rx_approve.await.unwrap_or_default() {
+        ReviewDecision::Approved => AuditApproval::Approved,
+        ReviewDecision::ApprovedForSession => AuditApproval::ApprovedForSession,
+        ReviewDecision::Denied | ReviewDecision::Abort => {
+            sess.record_audit(audit_action, AuditApproval::Denied, None)
+                .await;
+            return reply(
+                format!("deployment `{}` rejected by user", deployment.name),
+                false,
+            );
+        }
+    };
+    let result = arm_deployments.deploy(&deployment).await;
+    sess.record_audit(
+        audit_action,
+        approval,
+        Some(audit_outcome_of_result(&result)),
+    )
+    .await;
+    match result {
+        Ok(content) => reply(content, true),
+        Err(e) => reply(e, false),
+    }
+}
+
+/// Audit outcome of a tool call that produced `output`; failures keep the
+/// first line of the output.
+fn audit_outcome_of(output: &FunctionCallOutputPayload) -> AuditOutcome {
+    if output.success == Some(true) {
+        AuditOutcome::success()
+    } else {
+        AuditOutcome::error(output.content.lines().next().unwrap_or_default())
+    }
+}
+
+fn audit_outcome_of_result(result: &Result<String, String>) -> AuditOutcome {
+    match result {
+        Ok(_) => AuditOutcome::success(),
+        Err(e) => AuditOutcome::error(e),
+    }
+}
+
+async fn handle_background_start(
+    sess: &Session,
+    turn_context: &TurnContext,
+    sub_id: String,
+    call_id: String,
+    arguments: String,
+) -> ResponseInputItem {
+    let reply = |content: String, success: bool| ResponseInputItem::FunctionCallOutput {
+        call_id: call_id.clone(),
+        output: FunctionCallOutputPayload {
+            content,
+            success: Some(success),
+        },
+    };
+    let params = match serde_json::from_str::<BackgroundStartParams>(&arguments) {
+        Ok(params) => params,
+        Err(e) => return reply(format!("failed to parse function arguments: {e}"), false),
+    };
+    let cwd = turn_context.resolve_path(params.workdir.clone());
+
+    // Background jobs go through the same approval flow as `shell` calls.
+    let rule = evaluate_command_rules(&sess.command_rules, &params.command, &cwd);
+    let safety = {
+        let state = sess.state.lock_unchecked();
+        assess_command_safety(
+            &params.command,
+            turn_context.approval_policy,
+            &turn_context.sandbox_policy,
+            &state.approved_commands,
+            params.with_escalated_permissions.unwrap_or(false),
+            get_sandbox_for_backend(turn_context.sandbox_backend),
+            rule.as_ref(),
+        )
+    };
+    let audit_action = AuditAction::Exec {
+        command: params.command.clone(),
+        cwd: cwd.clone(),
+        background: true,
+    };
+    let (sandbox_type, approval) = match safety {
+        SafetyCheck::AutoApprove { sandbox_type } => (
+            sandbox_type,
+            AuditApproval::Auto {
+                sandbox: sandbox_type,
+            },
+        ),
+        SafetyCheck::AskUser => {
+            let rx_approve = sess
+                .request_command_approval(
+                    sub_id.clone(),
+                    call_id.clone(),
+                    params.command.clone(),
+                    cwd.clone(),
+                    params.justification.clone(),
+                    rule.map(|rule| rule.rule),
+                )
+                .await;
+            let approval = match rx_approve.await.unwrap_or_default() {
+                ReviewDecision::Approved => AuditApproval::Approved,
+                ReviewDecision::ApprovedForSession => {
+                    sess.add_approved_command(params.command.clone());
+                    AuditApproval::ApprovedForSession
                 }
-                // Pass through if already a custom tool output or other variant
-                other => other,
-            }
+                ReviewDecision::Denied | ReviewDecision::Abort => {
+                    sess.record_audit(audit_action, AuditApproval::Denied, None)
+                        .await;
+                    return reply("exec command rejected by user".to_string(), false);
+                }
+            };
+            (SandboxType::None, approval)
         }
-        _ => {
-            debug!("unexpected CustomToolCall from stream");
-            ResponseInputItem::CustomToolCallOutput {
-                call_id,
-                output: format!("unsupported custom tool call: {name}"),
-            }
+        SafetyCheck::Reject { reason } => {
+            sess.record_audit(
+                audit_action,
+                AuditApproval::Rejected {
+                    reason: reason.clone(),
+                },
+                None,
+            )
+            .await;
+            return reply(format!("exec command rejected: {reason}"), false);
         }
-    }
+    };
+
+    let mut env = create_env(&turn_context.shell_environment_policy);
+    env.extend(sess.secret_env());
+    let started = match spawn_exec_child(
+        params.command.clone(),
+        cwd.clone(),
+        env,
+        sandbox_type,
+        &turn_context.sandbox_policy,
+        &sess.codex_linux_sandbox_exe,
+        &sess.docker_sandbox,
+    )
+    .await
+    {
+        Ok(child) => sess
+            .background_jobs
+            .start(
+                child,
+                params.command.clone(),
+                &turn_context.exec_limits,
+                sub_id.clone(),
+                sess.tx_event.clone(),
+            )
+            .map_err(CodexErr::from),
+        Err(e) => Err(e),
+    };
+    // The job is recorded when it starts; its exit status is not known yet.
+    let outcome = match &started {
+        Ok(_) => AuditOutcome {
+            success: true,
+            exit_code: None,
+            error: None,
+        },
+        Err(e) => AuditOutcome::error(e),
+    };
+    sess.record_audit(audit_action, approval, Some(outcome))
+        .await;
+    let job_id = match started {
+        Ok(job_id) => job_id,
+        Err(e) => return reply(format!("failed to start background job: {e}"), false),
+    };
+
+    sess.send_event(Event {
+        id: sub_id,
+        msg: EventMsg::BackgroundJobStarted(BackgroundJobStartedEvent {
+            job_id,
+            command: params.command,
+            cwd,
+        }),
+    })
+    .await;
+    reply(serde_json::json!({ "job_id": job_id }).to_string(), true)
 }
 
 fn to_exec_params(params: ShellToolCallParams, turn_context: &TurnContext) -> ExecParams {
@@ -2321,6 +5524,9 @@ fn to_exec_params(params: ShellToolCallParams, turn_context: &TurnContext) -> Ex
         env: create_env(&turn_context.shell_environment_policy),
         with_escalated_permissions: params.with_escalated_permissions,
         justification: params.justification,
+        limits: turn_context
+            .exec_limits
+            .with_call_limits(params.max_output_bytes),
     }
 }
 
@@ -2351,6 +5557,7 @@ pub struct ExecInvokeArgs<'a> {
     pub sandbox_type: SandboxType,
     pub sandbox_policy: &'a SandboxPolicy,
     pub codex_linux_sandbox_exe: &'a Option<PathBuf>,
+    pub docker_sandbox: &'a DockerSandbox,
     pub stdout_stream: Option<StdoutStream>,
 }
 
@@ -2373,13 +5580,25 @@ fn maybe_translate_shell_command(
 }
 
 async fn handle_container_exec_with_params(
-    params: ExecParams,
+    mut params: ExecParams,
     sess: &Session,
     turn_context: &TurnContext,
-    turn_diff_tracker: &mut TurnDiffTracker,
+    turn_diff_tracker: &SharedTurnDiffTracker,
     sub_id: String,
     call_id: String,
 ) -> ResponseInputItem {
+    if turn_context.dry_run && pushes_to_remote(&params.command) {
+        let request = serde_json::json!({
+            "command": params.command,
+            "cwd": params.cwd,
+        });
+        return ResponseInputItem::FunctionCallOutput {
+            call_id,
+            output: dry_run_output(&request),
+        };
+    }
+    params.env.extend(sess.secret_env());
+
     // check if this was a patch, and apply it if so
     let apply_patch_exec = match maybe_parse_apply_patch_verified(&params.command, &params.cwd) {
         MaybeApplyPatchVerified::Body(changes) => {
@@ -2409,7 +5628,25 @@ async fn handle_container_exec_with_params(
         MaybeApplyPatchVerified::NotApplyPatch => None,
     };
 
-    let (params, safety, command_for_display) = match &apply_patch_exec {
+    let (audit_action, user_approved_patch) = match &apply_patch_exec {
+        Some(ApplyPatchExec {
+            action,
+            user_explicitly_approved_this_action,
+        }) => (
+            audit_patch_action(action),
+            *user_explicitly_approved_this_action,
+        ),
+        None => (
+            AuditAction::Exec {
+                command: params.command.clone(),
+                cwd: params.cwd.clone(),
+                background: false,
+            },
+            false,
+        ),
+    };
+
+    let (params, safety, command_for_display, rule) = match &apply_patch_exec {
         Some(ApplyPatchExec {
             action: ApplyPatchAction { patch, cwd, .. },
             user_explicitly_approved_this_action,
@@ -2438,6 +5675,7 @@ async fn handle_container_exec_with_params(
                 env: HashMap::new(),
                 with_escalated_permissions: params.with_escalated_permissions,
                 justification: params.justification.clone(),
+                limits: params.limits,
             };
             let safety = if *user_explicitly_approved_this_action {
                 SafetyCheck::AutoApprove {
@@ -2448,15 +5686,18 @@ async fn handle_container_exec_with_params(
                     turn_context.approval_policy,
                     &turn_context.sandbox_policy,
                     params.with_escalated_permissions.unwrap_or(false),
+                    get_patch_sandbox_for_backend(turn_context.sandbox_backend),
                 )
             };
             (
                 params,
                 safety,
                 vec!["apply_patch".to_string(), patch.clone()],
+                None,
             )
         }
         None => {
+            let rule = evaluate_command_rules(&sess.command_rules, &params.command, &params.cwd);
             let safety = {
                 let state = sess.state.lock_unchecked();
                 assess_command_safety(
@@ -2465,15 +5706,26 @@ async fn handle_container_exec_with_params(
                     &turn_context.sandbox_policy,
                     &state.approved_commands,
                     params.with_escalated_permissions.unwrap_or(false),
+                    get_sandbox_for_backend(turn_context.sandbox_backend),
+                    rule.as_ref(),
                 )
             };
             let command_for_display = params.command.clone();
-            (params, safety, command_for_display)
+            (params, safety, command_for_display, rule)
         }
     };
 
-    let sandbox_type = match safety {
-        SafetyCheck::AutoApprove { sandbox_type } => sandbox_type,
+    let (sandbox_type, approval) = match safety {
+        SafetyCheck::AutoApprove { sandbox_type } => {
+            let approval = if user_approved_patch {
+                AuditApproval::Approved
+            } else {
+                AuditApproval::Auto {
+                    sandbox: sandbox_type,
+                }
+            };
+            (sandbox_type, approval)
+        }
         SafetyCheck::AskUser => {
             let rx_approve = sess
                 .request_command_approval(
@@ -2482,14 +5734,18 @@ async fn handle_container_exec_with_params(
                     params.command.clone(),
                     params.cwd.clone(),
                     params.justification.clone(),
+                    rule.map(|rule| rule.rule),
                 )
                 .await;
-            match rx_approve.await.unwrap_or_default() {
-                ReviewDecision::Approved => (),
+            let approval = match rx_approve.await.unwrap_or_default() {
+                ReviewDecision::Approved => AuditApproval::Approved,
                 ReviewDecision::ApprovedForSession => {
                     sess.add_approved_command(params.command.clone());
+                    AuditApproval::ApprovedForSession
                 }
                 ReviewDecision::Denied | ReviewDecision::Abort => {
+                    sess.record_audit(audit_action, AuditApproval::Denied, None)
+                        .await;
                     return ResponseInputItem::FunctionCallOutput {
                         call_id,
                         output: FunctionCallOutputPayload {
@@ -2498,14 +5754,22 @@ async fn handle_container_exec_with_params(
                         },
                     };
                 }
-            }
+            };
             // No sandboxing is applied because the user has given
             // explicit approval. Often, we end up in this case because
             // the command cannot be run in a sandbox, such as
             // installing a new dependency that requires network access.
-            SandboxType::None
+            (SandboxType::None, approval)
         }
         SafetyCheck::Reject { reason } => {
+            sess.record_audit(
+                audit_action,
+                AuditApproval::Rejected {
+                    reason: reason.clone(),
+                },
+                None,
+            )
+            .await;
             return ResponseInputItem::FunctionCallOutput {
                 call_id,
                 output: FunctionCallOutputPayload {
@@ -2530,6 +5794,7 @@ async fn handle_container_exec_with_params(
                 changes: convert_apply_patch_to_protocol(&action),
             },
         ),
+        audit_action,
     };
 
     let params = maybe_translate_shell_command(params, sess, turn_context);
@@ -2542,6 +5807,7 @@ async fn handle_container_exec_with_params(
                 sandbox_type,
                 sandbox_policy: &turn_context.sandbox_policy,
                 codex_linux_sandbox_exe: &sess.codex_linux_sandbox_exe,
+                docker_sandbox: &sess.docker_sandbox,
                 stdout_stream: if exec_command_context.apply_patch.is_some() {
                     None
                 } else {
@@ -2549,12 +5815,24 @@ async fn handle_container_exec_with_params(
                         sub_id: sub_id.clone(),
                         call_id: call_id.clone(),
                         tx_event: sess.tx_event.clone(),
+                        output: sess.exec_output,
                     })
                 },
             },
         )
         .await;
 
+    let outcome = match &output_result {
+        Ok(output) => AuditOutcome::exit_code(output.exit_code),
+        Err(e) => AuditOutcome::error(e),
+    };
+    sess.record_audit(
+        exec_command_context.audit_action.clone(),
+        approval,
+        Some(outcome),
+    )
+    .await;
+
     match output_result {
         Ok(output) => {
             let ExecToolCallOutput { exit_code, .. } = &output;
@@ -2584,7 +5862,7 @@ async fn handle_container_exec_with_params(
         Err(e) => ResponseInputItem::FunctionCallOutput {
             call_id: call_id.clone(),
             output: FunctionCallOutputPayload {
-                content: format!("execution error: {e}"),
+                content: format!("execution error: {}", e.describe_for_model()),
                 success: None,
             },
         },
@@ -2592,7 +5870,7 @@ async fn handle_container_exec_with_params(
 }
 
 async fn handle_sandbox_error(
-    turn_diff_tracker: &mut TurnDiffTracker,
+    turn_diff_tracker: &SharedTurnDiffTracker,
     params: ExecParams,
     exec_command_context: ExecCommandContext,
     error: SandboxErr,
@@ -2603,6 +5881,47 @@ async fn handle_sandbox_error(
     let call_id = exec_command_context.call_id.clone();
     let sub_id = exec_command_context.sub_id.clone();
     let cwd = exec_command_context.cwd.clone();
+    let audit_action = exec_command_context.audit_action.clone();
+
+    // If the command was killed for hitting one of its limits, return this
+    // failure to the model along with whatever output it produced before.
+    let killed = match &error {
+        SandboxErr::Timeout { output } => Some((
+            format!(
+                "command timed out after {} milliseconds; output before the timeout:",
+                params.timeout_duration().as_millis()
+            ),
+            output,
+        )),
+        SandboxErr::OutputLimit { output } => Some((
+            format!(
+                "command was killed after writing more than {} bytes of output; truncated output:",
+                params.limits.max_output_bytes.unwrap_or_default()
+            ),
+            output,
+        )),
+        SandboxErr::CpuTimeLimit { output } => Some((
+            format!(
+                "command was killed after using {} seconds of CPU time; output before it was killed:",
+                params.limits.cpu_time_secs.unwrap_or_default()
+            ),
+            output,
+        )),
+        SandboxErr::Cancelled { output } => Some((
+            "command was cancelled by the user; output before it was cancelled:".to_string(),
+            output,
+        )),
+        _ => None,
+    };
+    if let Some((reason, output)) = killed {
+        return ResponseInputItem::FunctionCallOutput {
+            call_id,
+            output: FunctionCallOutputPayload {
+                content: format!("{reason}\n{}", format_exec_output_str(output)),
+                success: Some(false),
+            },
+        };
+    }
 
     // Early out if either the user never wants to be asked for approval, or
     // we're letting the model manage escalation requests. Otherwise, continue
@@ -2621,20 +5940,6 @@ async fn handle_sandbox_error(
         AskForApproval::UnlessTrusted | AskForApproval::OnFailure => (),
     }
 
-    // similarly, if the command timed out, we can simply return this failure to the model
-    if matches!(error, SandboxErr::Timeout) {
-        return ResponseInputItem::FunctionCallOutput {
-            call_id,
-            output: FunctionCallOutputPayload {
-                content: format!(
-                    "command timed out after {} milliseconds",
-                    params.timeout_duration().as_millis()
-                ),
-                success: Some(false),
-            },
-        };
-    }
-
     // Note that when `error` is `SandboxErr::Denied`, it could be a false
     // positive. That is, it may have exited with a non-zero exit code, not
     // because the sandbox denied it, but because that is its expected behavior,
@@ -2654,11 +5959,17 @@ async fn handle_sandbox_error(
             params.command.clone(),
             cwd.clone(),
             Some("command failed; retry without sandbox?".to_string()),
+            None,
         )
         .await;
 
     match rx_approve.await.unwrap_or_default() {
-        ReviewDecision::Approved | ReviewDecision::ApprovedForSession => {
+        decision @ (ReviewDecision::Approved | ReviewDecision::ApprovedForSession) => {
+            let approval = if decision == ReviewDecision::Approved {
+                AuditApproval::Approved
+            } else {
+                AuditApproval::ApprovedForSession
+            };
             // Persist this command as pre‑approved for the
             // remainder of the session so future
             // executions skip the sandbox directly.
@@ -2679,6 +5990,7 @@ async fn handle_sandbox_error(
                         sandbox_type: SandboxType::None,
                         sandbox_policy: &turn_context.sandbox_policy,
                         codex_linux_sandbox_exe: &sess.codex_linux_sandbox_exe,
+                        docker_sandbox: &sess.docker_sandbox,
                         stdout_stream: if exec_command_context.apply_patch.is_some() {
                             None
                         } else {
@@ -2686,12 +5998,20 @@ async fn handle_sandbox_error(
                                 sub_id: sub_id.clone(),
                                 call_id: call_id.clone(),
                                 tx_event: sess.tx_event.clone(),
+                                output: sess.exec_output,
                             })
                         },
                     },
                 )
                 .await;
 
+            let outcome = match &retry_output_result {
+                Ok(output) => AuditOutcome::exit_code(output.exit_code),
+                Err(e) => AuditOutcome::error(e),
+            };
+            sess.record_audit(audit_action, approval, Some(outcome))
+                .await;
+
             match retry_output_result {
                 Ok(retry_output) => {
                     let ExecToolCallOutput { exit_code, .. } = &retry_output;
@@ -2717,6 +6037,8 @@ async fn handle_sandbox_error(
             }
         }
         ReviewDecision::Denied | ReviewDecision::Abort => {
+            sess.record_audit(audit_action, AuditApproval::Denied, None)
+                .await;
             // Fall through to original failure handling.
             ResponseInputItem::FunctionCallOutput {
                 call_id,
@@ -2893,9 +6215,13 @@ async fn drain_to_completed(
     sub_id: &str,
     prompt: &Prompt,
 ) -> CodexResult<()> {
+    let cancel = sess.cancellation_token(sub_id);
     let mut stream = turn_context.client.clone().stream(prompt).await?;
     loop {
-        let maybe_event = stream.next().await;
+        let maybe_event = tokio::select! {
+            event = stream.next() => event,
+            _ = cancel.cancelled() => return Err(CodexErr::Interrupted),
+        };
         let Some(event) = maybe_event else {
             return Err(CodexErr::Stream(
                 "stream closed before response.completed".into(),
@@ -2983,6 +6309,41 @@ mod tests {
         })
     }
 
+    #[tokio::test]
+    async fn failed_tool_call_lets_running_calls_finish() {
+        let finished = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let mut running: FuturesUnordered<ToolCallFuture> = FuturesUnordered::new();
+        running.push({
+            let finished = finished.clone();
+            async move {
+                tokio::time::sleep(StdDuration::from_millis(50)).await;
+                finished.store(true, std::sync::atomic::Ordering::SeqCst);
+                (1, Ok(None))
+            }
+            .boxed()
+        });
+        running.push(async { (0, Err(CodexErr::InternalAgentDied)) }.boxed());
+        let mut output: Vec<ProcessedResponseItem> = (0..2)
+            .map(|index| ProcessedResponseItem {
+                item: ResponseItem::FunctionCall {
+                    id: None,
+                    name: "shell".to_string(),
+                    arguments: "{}".to_string(),
+                    call_id: format!("call_{index}"),
+                },
+                response: None,
+            })
+            .collect();
+
+        let (index, response) = running.next().await.unwrap();
+        assert_eq!(index, 0);
+        let result = record_tool_response(&mut output, &mut running, index, response).await;
+
+        assert!(matches!(result, Err(CodexErr::InternalAgentDied)));
+        assert!(finished.load(std::sync::atomic::Ordering::SeqCst));
+        assert!(running.is_empty());
+    }
+
     #[test]
     fn prefers_structured_content_when_present() {
         let ctr = CallToolResult {
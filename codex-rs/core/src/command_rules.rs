@@ -0,0 +1,235 @@
+//! Evaluation of the `[[command_rules]]` configured in `config.toml`, which
+//! decide whether a command runs without approval, always needs approval, or
+//! is rejected. Commands no user rule covers fall back to the built-in list of
+//! known safe commands.
+
+use std::path::Path;
+
+use wildmatch::WildMatchPattern;
+
+use crate::bash::try_parse_bash;
+use crate::bash::try_parse_word_only_commands_sequence;
+use crate::config_types::CommandRule;
+use crate::config_types::CommandRuleDecision;
+use crate::is_safe_command::is_known_safe_command;
+
+/// Rule name reported for commands on the built-in safe list.
+pub(crate) const BUILTIN_SAFE_RULE: &str = "builtin-safe-commands";
+
+/// The rule that decided how a command is approved.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RuleMatch {
+    pub rule: String,
+    pub decision: CommandRuleDecision,
+}
+
+/// Find the rule deciding how `command`, run in `cwd`, is approved.
+///
+/// `bash -lc` scripts made of plain commands are split into those commands
+/// and each is matched separately: a denied command denies the script, one
+/// that needs approval makes the script need approval, and the script is only
+/// allowed when every command in it is. `None` means no rule covers the
+/// command (or some command in the script).
+pub(crate) fn evaluate_command_rules(
+    rules: &[CommandRule],
+    command: &[String],
+    cwd: &Path,
+) -> Option<RuleMatch> {
+    let commands = match command {
+        [bash, flag, script] if bash == "bash" && flag == "-lc" => try_parse_bash(script)
+            .and_then(|tree| try_parse_word_only_commands_sequence(&tree, script))
+            .filter(|commands| !commands.is_empty())
+            .unwrap_or_else(|| vec![command.to_vec()]),
+        _ => vec![command.to_vec()],
+    };
+
+    let matches: Vec<Option<RuleMatch>> = commands
+        .iter()
+        .map(|command| match_single_command(rules, command, cwd))
+        .collect();
+
+    for decision in [CommandRuleDecision::Deny, CommandRuleDecision::Ask] {
+        if let Some(found) = matches
+            .iter()
+            .flatten()
+            .find(|found| found.decision == decision)
+        {
+            return Some(found.clone());
+        }
+    }
+    let mut allowed = matches.into_iter().collect::<Option<Vec<_>>>()?;
+    // Report the user rule that allowed the command rather than the built-in
+    // list when both were involved.
+    let index = allowed
+        .iter()
+        .position(|found| found.rule != BUILTIN_SAFE_RULE)
+        .unwrap_or(0);
+    Some(allowed.swap_remove(index))
+}
+
+fn match_single_command(
+    rules: &[CommandRule],
+    command: &[String],
+    cwd: &Path,
+) -> Option<RuleMatch> {
+    if let Some(rule) = rules.iter().find(|rule| rule_matches(rule, command, cwd)) {
+        return Some(RuleMatch {
+            rule: rule_name(rule),
+            decision: rule.decision,
+        });
+    }
+    is_known_safe_command(command).then(|| RuleMatch {
+        rule: BUILTIN_SAFE_RULE.to_string(),
+        decision: CommandRuleDecision::Allow,
+    })
+}
+
+fn rule_matches(rule: &CommandRule, command: &[String], cwd: &Path) -> bool {
+    if rule.command.is_empty() || command.len() < rule.command.len() {
+        return false;
+    }
+    if let Some(rule_cwd) = &rule.cwd
+        && !cwd.starts_with(rule_cwd)
+    {
+        return false;
+    }
+
+    let (prefix, args) = command.split_at(rule.command.len());
+    let prefix_matches = rule
+        .command
+        .iter()
+        .zip(prefix)
+        .enumerate()
+        .all(|(i, (pattern, arg))| {
+            // Match the program by name so `git` also covers `/usr/bin/git`.
+            let arg = if i == 0 { program_name(arg) } else { arg };
+            WildMatchPattern::<'*', '?'>::new(pattern).matches(arg)
+        });
+
+    prefix_matches
+        && rule
+            .require_args
+            .iter()
+            .all(|required| args.iter().any(|arg| arg_matches(required, arg)))
+        && !rule
+            .forbid_args
+            .iter()
+            .any(|forbidden| args.iter().any(|arg| arg_matches(forbidden, arg)))
+}
+
+fn arg_matches(option: &str, arg: &str) -> bool {
+    arg == option
+        || arg
+            .strip_prefix(option)
+            .is_some_and(|rest| option.starts_with('-') && rest.starts_with('='))
+}
+
+fn program_name(program: &str) -> &str {
+    Path::new(program)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(program)
+}
+
+fn rule_name(rule: &CommandRule) -> String {
+    rule.name.clone().unwrap_or_else(|| rule.command.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    fn vec_str(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn rule(command: &[&str], decision: CommandRuleDecision) -> CommandRule {
+        CommandRule {
+            name: None,
+            command: vec_str(command),
+            require_args: Vec::new(),
+            forbid_args: Vec::new(),
+            cwd: None,
+            decision,
+        }
+    }
+
+    fn decision(
+        rules: &[CommandRule],
+        command: &[&str],
+        cwd: &str,
+    ) -> Option<(String, CommandRuleDecision)> {
+        evaluate_command_rules(rules, &vec_str(command), Path::new(cwd))
+            .map(|found| (found.rule, found.decision))
+    }
+
+    #[test]
+    fn argument_aware_rules() {
+        let rules = vec![
+            CommandRule {
+                name: Some("dry-run pushes".to_string()),
+                require_args: vec_str(&["--dry-run"]),
+                forbid_args: vec_str(&["--force"]),
+                ..rule(&["git", "push"], CommandRuleDecision::Allow)
+            },
+            rule(&["git", "push"], CommandRuleDecision::Ask),
+            rule(&["rm", "-rf", "/*"], CommandRuleDecision::Deny),
+        ];
+
+        assert_eq!(
+            decision(&rules, &["git", "push", "--dry-run", "origin"], "/"),
+            Some(("dry-run pushes".to_string(), CommandRuleDecision::Allow))
+        );
+        assert_eq!(
+            decision(
+                &rules,
+                &["/usr/bin/git", "push", "--dry-run", "--force=true"],
+                "/"
+            ),
+            Some(("git push".to_string(), CommandRuleDecision::Ask))
+        );
+        assert_eq!(
+            decision(&rules, &["rm", "-rf", "/home"], "/"),
+            Some(("rm -rf /*".to_string(), CommandRuleDecision::Deny))
+        );
+        assert_eq!(decision(&rules, &["rm", "-rf", "build"], "/"), None);
+        assert_eq!(
+            decision(&rules, &["ls"], "/"),
+            Some((BUILTIN_SAFE_RULE.to_string(), CommandRuleDecision::Allow))
+        );
+    }
+
+    #[test]
+    fn per_directory_rules() {
+        let rules = vec![CommandRule {
+            cwd: Some(PathBuf::from("/work/project")),
+            ..rule(&["cargo", "test"], CommandRuleDecision::Allow)
+        }];
+
+        assert!(decision(&rules, &["cargo", "test"], "/work/project/crate").is_some());
+        assert_eq!(decision(&rules, &["cargo", "test"], "/work/other"), None);
+    }
+
+    #[test]
+    fn scripts_are_matched_command_by_command() {
+        let rules = vec![
+            rule(&["cargo", "test"], CommandRuleDecision::Allow),
+            rule(&["curl"], CommandRuleDecision::Deny),
+        ];
+        let script = |script: &str| decision(&rules, &["bash", "-lc", script], "/");
+
+        assert_eq!(
+            script("ls && cargo test"),
+            Some(("cargo test".to_string(), CommandRuleDecision::Allow))
+        );
+        assert_eq!(
+            script("cargo test; curl example.com"),
+            Some(("curl".to_string(), CommandRuleDecision::Deny))
+        );
+        assert_eq!(script("cargo test && make"), None);
+        // Scripts that cannot be split are matched as a whole.
+        assert_eq!(script("cargo test > out.txt"), None);
+    }
+}
@@ -1,9 +1,44 @@
+use crate::config_interpolation::interpolate_config;
 use crate::config_profile::ConfigProfile;
+use crate::config_types::ApprovalHttpConfig;
+use crate::config_types::ArmDeploymentsConfig;
+use crate::config_types::ArtifactsConfig;
+use crate::config_types::AuditConfig;
+use crate::config_types::AzureAuthConfig;
+use crate::config_types::AzureMonitorConfig;
+use crate::config_types::AzureStorageConfig;
+use crate::config_types::BudgetConfig;
+use crate::config_types::CommandRule;
+use crate::config_types::CustomToolConfig;
+use crate::config_types::DockerSandbox;
+use crate::config_types::EmbeddingsToml;
+use crate::config_types::ExecLimits;
+use crate::config_types::ExecOutputConfig;
+use crate::config_types::ExecOutputToml;
 use crate::config_types::History;
+use crate::config_types::HookConfig;
+use crate::config_types::ImagesConfig;
+use crate::config_types::KeyVaultConfig;
+use crate::config_types::KnowledgeBaseToml;
+use crate::config_types::KubernetesConfig;
+use crate::config_types::McpHealthConfig;
+use crate::config_types::McpServerAuthConfig;
 use crate::config_types::McpServerConfig;
+use crate::config_types::McpServerMode;
+use crate::config_types::McpToolLimits;
+use crate::config_types::MessagingConfig;
+use crate::config_types::ModelRoute;
+use crate::config_types::NotificationsConfig;
+use crate::config_types::ProviderCircuitBreakerConfig;
+use crate::config_types::RedactionConfig;
+use crate::config_types::SandboxBackend;
 use crate::config_types::SandboxWorkspaceWrite;
+use crate::config_types::SchedulerConfig;
 use crate::config_types::ShellEnvironmentPolicy;
 use crate::config_types::ShellEnvironmentPolicyToml;
+use crate::config_types::SqlConfig;
+use crate::config_types::StorageConfig;
+use crate::config_types::TicketingConfig;
 use crate::config_types::Tui;
 use crate::config_types::UriBasedFileOpener;
 use crate::config_types::Verbosity;
@@ -13,8 +48,10 @@ use crate::model_family::find_family_for_model;
 use crate::model_provider_info::ModelProviderInfo;
 use crate::model_provider_info::built_in_model_providers;
 use crate::openai_model_info::get_model_info;
+use crate::project_config::apply_project_config;
 use crate::protocol::AskForApproval;
 use crate::protocol::SandboxPolicy;
+use crate::redaction::Redactor;
 use codex_login::AuthMode;
 use codex_protocol::config_types::ReasoningEffort;
 use codex_protocol::config_types::ReasoningSummary;
@@ -35,10 +72,47 @@ const OPENAI_DEFAULT_MODEL: &str = "gpt-5";
 /// the context window.
 pub(crate) const PROJECT_DOC_MAX_BYTES: usize = 32 * 1024; // 32 KiB
 
+/// Default for `mcp_server_max_result_bytes`, about 16k tokens.
+const DEFAULT_MCP_SERVER_MAX_RESULT_BYTES: usize = 64 * 1024; // 64 KiB
+
+const DEFAULT_KNOWLEDGE_BASE_CHUNK_BYTES: usize = 2 * 1024; // 2 KiB
+
+const DEFAULT_KNOWLEDGE_BASE_TOP_K: usize = 5;
+
 const CONFIG_TOML_FILE: &str = "config.toml";
 
 const DEFAULT_RESPONSES_ORIGINATOR_HEADER: &str = "codex_cli_rs";
 
+/// A secondary provider and model to send requests to while the primary ones
+/// fail.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderFailover {
+    /// Key into the model_providers map.
+    pub model_provider_id: String,
+    pub model_provider: ModelProviderInfo,
+    pub model: String,
+    pub model_family: ModelFamily,
+}
+
+/// The embedding model from `[embeddings]`, with its provider resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddingsConfig {
+    /// Key into the model_providers map.
+    pub model_provider_id: String,
+    pub model_provider: ModelProviderInfo,
+    pub model: String,
+    pub dimensions: Option<u32>,
+    pub rerank_model: Option<String>,
+}
+
+/// The `[knowledge_base]` table with its sources resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KnowledgeBaseConfig {
+    pub sources: Vec<PathBuf>,
+    pub chunk_bytes: usize,
+    pub top_k: usize,
+}
+
 /// Application configuration loaded from disk and merged with overrides.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Config {
@@ -53,12 +127,46 @@ pub struct Config {
     /// Maximum number of output tokens.
     pub model_max_output_tokens: Option<u64>,
 
+    /// Estimated history size, in tokens, at which older turns are
+    /// summarized automatically. Defaults to 80% of the context window; `0`
+    /// disables automatic compaction.
+    pub model_auto_compact_token_limit: Option<u64>,
+
+    /// Whether providers are asked to cache the stable start of every request
+    /// (system prompt, tool definitions and project doc).
+    pub prompt_caching: bool,
+
     /// Key into the model_providers map that specifies which provider to use.
     pub model_provider_id: String,
 
     /// Info needed to make an API request to the model.
     pub model_provider: ModelProviderInfo,
 
+    /// Provider and model used when `model_provider` keeps failing.
+    pub failover: Option<ProviderFailover>,
+
+    /// Routes choosing the model per request, checked in order.
+    pub model_routes: Vec<ModelRoute>,
+
+    /// When a failing provider is taken out of use.
+    pub provider_circuit_breaker: ProviderCircuitBreakerConfig,
+
+    /// Limits on the requests, tokens and cost of a session and of a day.
+    pub budget: BudgetConfig,
+
+    /// Model used to embed and rerank text, when one is configured.
+    pub embeddings: Option<EmbeddingsConfig>,
+
+    /// Documentation searched by the `kb_search` tool, when configured.
+    pub knowledge_base: Option<KnowledgeBaseConfig>,
+
+    /// Limits on the images attached to messages.
+    pub images: ImagesConfig,
+
+    /// Where large tool outputs are saved instead of being sent to the
+    /// model.
+    pub artifacts: ArtifactsConfig,
+
     /// Approval policy for executing commands.
     pub approval_policy: AskForApproval,
 
@@ -66,6 +174,37 @@ pub struct Config {
 
     pub shell_environment_policy: ShellEnvironmentPolicy,
 
+    /// Chunking and buffering of exec command output.
+    pub exec_output: ExecOutputConfig,
+
+    /// Timeout, output and resource limits for exec commands.
+    pub exec_limits: ExecLimits,
+
+    /// Most tool calls of one model response that run at the same time.
+    pub max_parallel_tool_calls: usize,
+
+    /// How commands that are auto-approved to run in a sandbox are sandboxed.
+    pub sandbox_backend: SandboxBackend,
+
+    /// Container settings for [`SandboxBackend::Docker`].
+    pub sandbox_docker: DockerSandbox,
+
+    /// Rules that decide how commands are approved, checked in order.
+    pub command_rules: Vec<CommandRule>,
+
+    /// Commands run around tool calls and turns, in order.
+    pub hooks: Vec<HookConfig>,
+
+    /// Function tools implemented by commands or HTTP endpoints, keyed by
+    /// tool name.
+    pub custom_tools: HashMap<String, CustomToolConfig>,
+
+    /// Masking of secrets before they are persisted or sent to the model.
+    pub redaction: RedactionConfig,
+
+    /// Tamper-evident log of commands, patches and MCP tool calls.
+    pub audit: AuditConfig,
+
     /// When `true`, `AgentReasoning` events emitted by the backend will be
     /// suppressed from the frontend output. This can reduce visual noise when
     /// users are only interested in the final agent responses.
@@ -108,6 +247,12 @@ pub struct Config {
     /// If unset the feature is disabled.
     pub notify: Option<Vec<String>>,
 
+    /// Which notifications go to `notify`, the desktop and webhooks.
+    pub notifications: NotificationsConfig,
+
+    /// Service that approval requests are sent to, for headless runs.
+    pub approval_http: Option<ApprovalHttpConfig>,
+
     /// The directory that should be treated as the current working directory
     /// for the session. All relative paths inside the business-logic layer are
     /// resolved against this path.
@@ -116,6 +261,22 @@ pub struct Config {
     /// Definition for MCP servers that Codex can reach out to for tool calls.
     pub mcp_servers: HashMap<String, McpServerConfig>,
 
+    /// Health checks and restarts of MCP servers.
+    pub mcp_health: McpHealthConfig,
+
+    /// Authentication and TLS of `codex mcp` when it listens on a port.
+    pub mcp_server_auth: McpServerAuthConfig,
+
+    /// Concurrency and rate limits of `codex mcp`, keyed by tool name.
+    pub mcp_server_limits: HashMap<String, McpToolLimits>,
+
+    /// Largest `codex` tool result `codex mcp` sends inline. Larger results
+    /// are saved as a resource and sent as an excerpt and a link to it.
+    pub mcp_server_max_result_bytes: usize,
+
+    /// What clients of `codex mcp` may do.
+    pub mcp_server_mode: McpServerMode,
+
     /// Combined provider map (defaults merged with user-defined overrides).
     pub model_providers: HashMap<String, ModelProviderInfo>,
 
@@ -129,6 +290,10 @@ pub struct Config {
     /// Settings that govern if and what will be written to `~/.codex/history.jsonl`.
     pub history: History,
 
+    /// Stores that sessions and history entries are written to in addition
+    /// to the files in `codex_home`.
+    pub storage: StorageConfig,
+
     /// Optional URI-based file opener. If set, citations to files in the model
     /// output will be hyperlinked using the specified URI scheme.
     pub file_opener: UriBasedFileOpener,
@@ -136,6 +301,36 @@ pub struct Config {
     /// Collection of settings that are specific to the TUI.
     pub tui: Tui,
 
+    /// Shared Azure (Entra ID) credential settings.
+    pub azure_auth: AzureAuthConfig,
+
+    /// Azure Monitor tools, when configured.
+    pub azure_monitor: Option<AzureMonitorConfig>,
+
+    /// Azure Key Vault tools, when configured.
+    pub key_vault: Option<KeyVaultConfig>,
+
+    /// Azure Storage blob tools, when configured.
+    pub azure_storage: Option<AzureStorageConfig>,
+
+    /// ARM deployment tools, when configured.
+    pub arm_deployments: Option<ArmDeploymentsConfig>,
+
+    /// Jira or ServiceNow ticket tools, when configured.
+    pub ticketing: Option<TicketingConfig>,
+
+    /// Teams and Slack channels the `post_message` tool posts to.
+    pub messaging: Option<MessagingConfig>,
+
+    /// SQL Server connection profiles for the read-only SQL tools.
+    pub sql: Option<SqlConfig>,
+
+    /// Kubeconfig contexts for the read-only Kubernetes tools.
+    pub kubernetes: Option<KubernetesConfig>,
+
+    /// Tasks `codex scheduler` runs on a cron schedule.
+    pub scheduler: Option<SchedulerConfig>,
+
     /// Path to the `codex-linux-sandbox` executable. This must be set if
     /// [`crate::exec::SandboxType::LinuxSeccomp`] is used. Note that this
     /// cannot be set in the config file: it must be set in code via
@@ -181,6 +376,24 @@ pub struct Config {
 
     /// Include the `view_image` tool that lets the agent attach a local image path to context.
     pub include_view_image_tool: bool,
+    /// Include the `background_*` tools that start and manage long-running
+    /// commands such as dev servers.
+    pub include_background_jobs_tool: bool,
+    /// Include the `git_*` tools for structured status, diffs, commits and
+    /// stashes.
+    pub include_git_tools: bool,
+    /// Include the `edit_files` tool that applies diffs and search/replace
+    /// edits to several files at once.
+    pub include_edit_files_tool: bool,
+    /// Add an environment snapshot to the conversation at session start and
+    /// include the `refresh_environment` tool.
+    pub environment_snapshot: bool,
+    /// Tools that change remote systems return the request they would send
+    /// instead of sending it.
+    pub dry_run: bool,
+    /// Descriptions of tools and their parameters are shortened to about
+    /// this many tokens each.
+    pub tool_description_max_tokens: Option<usize>,
     /// When true, disables burst-paste detection for typed input entirely.
     /// All characters are inserted as they are received, and no buffering
     /// or placeholder replacement will occur for fast keypress bursts.
@@ -206,6 +419,14 @@ impl Config {
         // Step 1: parse `config.toml` into a generic JSON value.
         let mut root_value = load_config_as_toml(&codex_home)?;
 
+        // Step 1b: overlay the project's `.codex/config.toml`, if any.
+        let cwd = match &overrides.cwd {
+            Some(cwd) if cwd.is_absolute() => cwd.clone(),
+            Some(cwd) => std::env::current_dir()?.join(cwd),
+            None => std::env::current_dir()?,
+        };
+        apply_project_config(&mut root_value, &cwd)?;
+
         // Step 2: apply the `-c` overrides.
         for (path, value) in cli_overrides.into_iter() {
             apply_toml_override(&mut root_value, &path, value);
@@ -241,13 +462,19 @@ pub fn load_config_as_toml_with_cli_overrides(
     Ok(cfg)
 }
 
-/// Read `CODEX_HOME/config.toml` and return it as a generic TOML value. Returns
-/// an empty TOML table when the file does not exist.
+/// Read `CODEX_HOME/config.toml` and return it as a generic TOML value, with
+/// `${env:VAR}` and `${file:path}` references expanded. Returns an empty TOML
+/// table when the file does not exist.
 pub fn load_config_as_toml(codex_home: &Path) -> std::io::Result<TomlValue> {
     let config_path = codex_home.join(CONFIG_TOML_FILE);
     match std::fs::read_to_string(&config_path) {
         Ok(contents) => match toml::from_str::<TomlValue>(&contents) {
-            Ok(val) => Ok(val),
+            Ok(mut val) => {
+                interpolate_config(&mut val, codex_home).inspect_err(|e| {
+                    tracing::error!("Failed to interpolate config.toml: {e}");
+                })?;
+                Ok(val)
+            }
             Err(e) => {
                 tracing::error!("Failed to parse config.toml: {e}");
                 Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
@@ -396,24 +623,105 @@ pub struct ConfigToml {
     /// Provider to use from the model_providers map.
     pub model_provider: Option<String>,
 
+    /// Model to use when the provider keeps failing.
+    pub failover_model: Option<String>,
+
+    /// Provider from the model_providers map to use when the primary
+    /// provider keeps failing.
+    pub failover_model_provider: Option<String>,
+
+    /// Routes that pick another model for matching requests.
+    #[serde(default)]
+    pub model_routes: Vec<ModelRoute>,
+
+    /// When a failing provider is taken out of use.
+    #[serde(default)]
+    pub provider_circuit_breaker: ProviderCircuitBreakerConfig,
+
+    /// Limits on the requests, tokens and cost of a session and of a day.
+    #[serde(default)]
+    pub budget: BudgetConfig,
+
+    /// Model used to embed and rerank text.
+    pub embeddings: Option<EmbeddingsToml>,
+
+    /// Documentation the agent can search.
+    pub knowledge_base: Option<KnowledgeBaseToml>,
+
+    /// Limits on the images attached to messages.
+    #[serde(default)]
+    pub images: ImagesConfig,
+
+    /// Saving large tool outputs to files.
+    #[serde(default)]
+    pub artifacts: ArtifactsConfig,
+
     /// Size of the context window for the model, in tokens.
     pub model_context_window: Option<u64>,
 
     /// Maximum number of output tokens.
     pub model_max_output_tokens: Option<u64>,
 
+    /// Estimated history size, in tokens, at which older turns are
+    /// summarized automatically. `0` disables automatic compaction.
+    pub model_auto_compact_token_limit: Option<u64>,
+
+    /// Ask providers to cache the stable start of every request. Defaults to
+    /// `true`.
+    pub prompt_caching: Option<bool>,
+
     /// Default approval policy for executing commands.
     pub approval_policy: Option<AskForApproval>,
 
     #[serde(default)]
     pub shell_environment_policy: ShellEnvironmentPolicyToml,
 
+    /// Chunking and buffering of exec command output.
+    #[serde(default)]
+    pub exec_output: ExecOutputToml,
+
+    /// Timeout, output and resource limits for exec commands.
+    #[serde(default)]
+    pub exec_limits: ExecLimits,
+
+    /// Most tool calls of one model response that run at the same time.
+    /// Defaults to 1, which runs them one after the other.
+    pub max_parallel_tool_calls: Option<usize>,
+
     /// Sandbox mode to use.
     pub sandbox_mode: Option<SandboxMode>,
 
     /// Sandbox configuration to apply if `sandbox` is `WorkspaceWrite`.
     pub sandbox_workspace_write: Option<SandboxWorkspaceWrite>,
 
+    /// Mechanism that enforces the sandbox, defaults to the platform sandbox.
+    #[serde(default)]
+    pub sandbox_backend: SandboxBackend,
+
+    /// Container settings used when `sandbox_backend` is `docker`.
+    #[serde(default)]
+    pub sandbox_docker: DockerSandbox,
+
+    /// Rules that allow, require approval for, or deny matching commands.
+    #[serde(default)]
+    pub command_rules: Vec<CommandRule>,
+
+    /// Commands run before or after tool calls and at turn boundaries.
+    #[serde(default)]
+    pub hooks: Vec<HookConfig>,
+
+    /// Function tools declared in config, keyed by tool name.
+    #[serde(default)]
+    pub custom_tools: HashMap<String, CustomToolConfig>,
+
+    /// Masking of secrets in the rollout and in requests to the model.
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+
+    /// Audit log of privileged actions in `~/.codex/audit/`.
+    #[serde(default)]
+    pub audit: AuditConfig,
+
     /// Disable server-side response storage (sends the full conversation
     /// context with every request). Currently necessary for OpenAI customers
     /// who have opted into Zero Data Retention (ZDR).
@@ -423,6 +731,13 @@ pub struct ConfigToml {
     #[serde(default)]
     pub notify: Option<Vec<String>>,
 
+    /// Routing of notifications to `notify`, the desktop and webhooks.
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+
+    /// HTTP service that approval requests are sent to, for headless runs.
+    pub approval_http: Option<ApprovalHttpConfig>,
+
     /// System instructions.
     pub instructions: Option<String>,
 
@@ -430,6 +745,26 @@ pub struct ConfigToml {
     #[serde(default)]
     pub mcp_servers: HashMap<String, McpServerConfig>,
 
+    /// Health checks and restarts of MCP servers.
+    #[serde(default)]
+    pub mcp_health: McpHealthConfig,
+
+    /// Authentication and TLS of `codex mcp` when it listens on a port.
+    #[serde(default)]
+    pub mcp_server_auth: McpServerAuthConfig,
+
+    /// Concurrency and rate limits of `codex mcp`, keyed by tool name.
+    #[serde(default)]
+    pub mcp_server_limits: HashMap<String, McpToolLimits>,
+
+    /// Largest `codex` tool result `codex mcp` sends inline; 0 sends every
+    /// result inline.
+    pub mcp_server_max_result_bytes: Option<usize>,
+
+    /// What clients of `codex mcp` may do.
+    #[serde(default)]
+    pub mcp_server_mode: McpServerMode,
+
     /// User-defined provider entries that extend/override the built-in list.
     #[serde(default)]
     pub model_providers: HashMap<String, ModelProviderInfo>,
@@ -448,6 +783,11 @@ pub struct ConfigToml {
     #[serde(default)]
     pub history: Option<History>,
 
+    /// SQLite database and HTTP service that sessions and history entries
+    /// are also written to.
+    #[serde(default)]
+    pub storage: StorageConfig,
+
     /// Optional URI-based file opener. If set, citations to files in the model
     /// output will be hyperlinked using the specified URI scheme.
     pub file_opener: Option<UriBasedFileOpener>,
@@ -455,6 +795,40 @@ pub struct ConfigToml {
     /// Collection of settings that are specific to the TUI.
     pub tui: Option<Tui>,
 
+    /// Shared Azure (Entra ID) credential settings.
+    pub azure_auth: Option<AzureAuthConfig>,
+
+    /// Tools that read Azure Monitor metrics, alerts and activity logs.
+    pub azure_monitor: Option<AzureMonitorConfig>,
+
+    /// Tools that list Azure Key Vault metadata and read secrets into the
+    /// environment of later commands.
+    pub key_vault: Option<KeyVaultConfig>,
+
+    /// Tools that list, read and upload Azure Storage blobs and create SAS
+    /// URLs.
+    pub azure_storage: Option<AzureStorageConfig>,
+
+    /// Tools that preview and deploy ARM and Bicep templates and inspect
+    /// past deployments.
+    pub arm_deployments: Option<ArmDeploymentsConfig>,
+
+    /// Tools that search, read, create and comment on Jira or ServiceNow
+    /// tickets.
+    pub ticketing: Option<TicketingConfig>,
+
+    /// Teams and Slack channels the `post_message` tool posts to.
+    pub messaging: Option<MessagingConfig>,
+
+    /// Connection profiles for read-only SQL Server and Azure SQL tools.
+    pub sql: Option<SqlConfig>,
+
+    /// Kubeconfig contexts for read-only Kubernetes tools.
+    pub kubernetes: Option<KubernetesConfig>,
+
+    /// Prompts and tool pipelines run headlessly on a cron schedule.
+    pub scheduler: Option<SchedulerConfig>,
+
     /// When set to `true`, `AgentReasoning` events will be hidden from the
     /// UI/output. Defaults to `false`.
     pub hide_agent_reasoning: Option<bool>,
@@ -493,6 +867,21 @@ pub struct ConfigToml {
     /// Nested tools section for feature toggles
     pub tools: Option<ToolsToml>,
 
+    /// Add a description of the OS, toolchains, Git status and container to
+    /// the conversation at session start, along with the
+    /// `refresh_environment` tool. Defaults to `true`; the probes run outside
+    /// the sandbox.
+    pub environment_snapshot: Option<bool>,
+
+    /// Rehearse instead of acting: MCP tools not marked read-only, custom
+    /// tools and `git push` return the request they would send without
+    /// sending it. Defaults to `false`.
+    pub dry_run: Option<bool>,
+
+    /// Shorten the description of each tool, and of each of its
+    /// parameters, to about this many tokens. Unset keeps them whole.
+    pub tool_description_max_tokens: Option<usize>,
+
     /// When true, disables burst-paste detection for typed input entirely.
     /// All characters are inserted as they are received, and no buffering
     /// or placeholder replacement will occur for fast keypress bursts.
@@ -512,6 +901,18 @@ pub struct ToolsToml {
     /// Enable the `view_image` tool that lets the agent attach local images.
     #[serde(default)]
     pub view_image: Option<bool>,
+
+    /// Enable the `background_*` tools for long-running commands.
+    #[serde(default)]
+    pub background_jobs: Option<bool>,
+
+    /// Enable the `git_status`, `git_diff`, `git_commit` and `git_stash` tools.
+    #[serde(default)]
+    pub git: Option<bool>,
+
+    /// Enable the `edit_files` tool.
+    #[serde(default)]
+    pub edit_files: Option<bool>,
 }
 
 impl ConfigToml {
@@ -573,16 +974,7 @@ impl ConfigToml {
         let profile = override_profile.or_else(|| self.profile.clone());
 
         match profile {
-            Some(key) => {
-                if let Some(profile) = self.profiles.get(key.as_str()) {
-                    return Ok(profile.clone());
-                }
-
-                Err(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    format!("config profile `{key}` not found"),
-                ))
-            }
+            Some(key) => ConfigProfile::resolve(&self.profiles, &key),
             None => Ok(ConfigProfile::default()),
         }
     }
@@ -636,16 +1028,7 @@ impl Config {
         } = overrides;
 
         let config_profile = match config_profile_key.as_ref().or(cfg.profile.as_ref()) {
-            Some(key) => cfg
-                .profiles
-                .get(key)
-                .ok_or_else(|| {
-                    std::io::Error::new(
-                        std::io::ErrorKind::NotFound,
-                        format!("config profile `{key}` not found"),
-                    )
-                })?
-                .clone(),
+            Some(key) => ConfigProfile::resolve(&cfg.profiles, key)?,
             None => ConfigProfile::default(),
         };
 
@@ -671,7 +1054,17 @@ impl Config {
             })?
             .clone();
 
+        // Reject invalid redaction patterns up front rather than leaving
+        // secrets unmasked.
+        Redactor::new(&cfg.redaction).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid redaction pattern: {e}"),
+            )
+        })?;
+
         let shell_environment_policy = cfg.shell_environment_policy.into();
+        let exec_output = cfg.exec_output.into();
 
         let resolved_cwd = {
             use std::env;
@@ -706,18 +1099,107 @@ impl Config {
             .or(config_profile.model)
             .or(cfg.model)
             .unwrap_or_else(default_model);
-        let model_family = find_family_for_model(&model).unwrap_or_else(|| {
-            let supports_reasoning_summaries =
-                cfg.model_supports_reasoning_summaries.unwrap_or(false);
-            ModelFamily {
-                slug: model.clone(),
-                family: model.clone(),
-                needs_special_apply_patch_instructions: false,
-                supports_reasoning_summaries,
-                uses_local_shell_tool: false,
-                apply_patch_tool_type: None,
+        let family_for_model = |model: &str| {
+            find_family_for_model(model).unwrap_or_else(|| {
+                let supports_reasoning_summaries =
+                    cfg.model_supports_reasoning_summaries.unwrap_or(false);
+                ModelFamily {
+                    slug: model.to_string(),
+                    family: model.to_string(),
+                    needs_special_apply_patch_instructions: false,
+                    supports_reasoning_summaries,
+                    uses_local_shell_tool: false,
+                    apply_patch_tool_type: None,
+                }
+            })
+        };
+        let model_family = family_for_model(&model);
+
+        let failover_model_provider_id = config_profile
+            .failover_model_provider
+            .or(cfg.failover_model_provider);
+        let failover_model = config_profile.failover_model.or(cfg.failover_model);
+        let failover = if failover_model_provider_id.is_some() || failover_model.is_some() {
+            let model_provider_id =
+                failover_model_provider_id.unwrap_or_else(|| model_provider_id.clone());
+            let model_provider = model_providers
+                .get(&model_provider_id)
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("Failover model provider `{model_provider_id}` not found"),
+                    )
+                })?
+                .clone();
+            let model = failover_model.unwrap_or_else(|| model.clone());
+            Some(ProviderFailover {
+                model_provider_id,
+                model_provider,
+                model_family: family_for_model(&model),
+                model,
+            })
+        } else {
+            None
+        };
+
+        if let Some(route) = cfg.model_routes.iter().find(|route| {
+            route
+                .model_provider
+                .as_ref()
+                .is_some_and(|id| !model_providers.contains_key(id))
+        }) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!(
+                    "Model route provider `{}` not found",
+                    route.model_provider.as_deref().unwrap_or_default()
+                ),
+            ));
+        }
+
+        let embeddings = match cfg.embeddings {
+            Some(embeddings) => {
+                let model_provider_id = embeddings
+                    .model_provider
+                    .unwrap_or_else(|| model_provider_id.clone());
+                let model_provider = model_providers
+                    .get(&model_provider_id)
+                    .ok_or_else(|| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::NotFound,
+                            format!("Embeddings model provider `{model_provider_id}` not found"),
+                        )
+                    })?
+                    .clone();
+                Some(EmbeddingsConfig {
+                    model_provider_id,
+                    model_provider,
+                    model: embeddings.model,
+                    dimensions: embeddings.dimensions,
+                    rerank_model: embeddings.rerank_model,
+                })
             }
-        });
+            None => None,
+        };
+
+        let knowledge_base = cfg
+            .knowledge_base
+            .filter(|knowledge_base| !knowledge_base.sources.is_empty())
+            .map(|knowledge_base| KnowledgeBaseConfig {
+                sources: knowledge_base
+                    .sources
+                    .into_iter()
+                    .map(|source| resolved_cwd.join(source))
+                    .collect(),
+                chunk_bytes: knowledge_base
+                    .chunk_bytes
+                    .filter(|&bytes| bytes > 0)
+                    .unwrap_or(DEFAULT_KNOWLEDGE_BASE_CHUNK_BYTES),
+                top_k: knowledge_base
+                    .top_k
+                    .filter(|&top_k| top_k > 0)
+                    .unwrap_or(DEFAULT_KNOWLEDGE_BASE_TOP_K),
+            });
 
         let openai_model_info = get_model_info(&model_family);
         let model_context_window = cfg
@@ -751,8 +1233,18 @@ impl Config {
             model_family,
             model_context_window,
             model_max_output_tokens,
+            model_auto_compact_token_limit: cfg.model_auto_compact_token_limit,
+            prompt_caching: cfg.prompt_caching.unwrap_or(true),
             model_provider_id,
             model_provider,
+            failover,
+            model_routes: cfg.model_routes,
+            provider_circuit_breaker: cfg.provider_circuit_breaker,
+            budget: cfg.budget,
+            embeddings,
+            knowledge_base,
+            images: cfg.images,
+            artifacts: cfg.artifacts,
             cwd: resolved_cwd,
             approval_policy: approval_policy
                 .or(config_profile.approval_policy)
@@ -760,21 +1252,51 @@ impl Config {
                 .unwrap_or_else(AskForApproval::default),
             sandbox_policy,
             shell_environment_policy,
+            exec_output,
+            exec_limits: cfg.exec_limits,
+            max_parallel_tool_calls: cfg.max_parallel_tool_calls.unwrap_or(1).max(1),
+            sandbox_backend: cfg.sandbox_backend,
+            sandbox_docker: cfg.sandbox_docker,
+            command_rules: cfg.command_rules,
+            hooks: cfg.hooks,
+            custom_tools: cfg.custom_tools,
+            redaction: cfg.redaction,
+            audit: cfg.audit,
             disable_response_storage: config_profile
                 .disable_response_storage
                 .or(cfg.disable_response_storage)
                 .or(disable_response_storage)
                 .unwrap_or(false),
             notify: cfg.notify,
+            notifications: cfg.notifications,
+            approval_http: cfg.approval_http,
             user_instructions,
             base_instructions,
             mcp_servers: cfg.mcp_servers,
+            mcp_health: cfg.mcp_health,
+            mcp_server_auth: cfg.mcp_server_auth,
+            mcp_server_limits: cfg.mcp_server_limits,
+            mcp_server_max_result_bytes: cfg
+                .mcp_server_max_result_bytes
+                .unwrap_or(DEFAULT_MCP_SERVER_MAX_RESULT_BYTES),
+            mcp_server_mode: cfg.mcp_server_mode,
             model_providers,
             project_doc_max_bytes: cfg.project_doc_max_bytes.unwrap_or(PROJECT_DOC_MAX_BYTES),
             codex_home,
             history,
+            storage: cfg.storage,
             file_opener: cfg.file_opener.unwrap_or(UriBasedFileOpener::VsCode),
             tui: cfg.tui.unwrap_or_default(),
+            azure_auth: cfg.azure_auth.unwrap_or_default(),
+            azure_monitor: cfg.azure_monitor,
+            key_vault: cfg.key_vault,
+            azure_storage: cfg.azure_storage,
+            arm_deployments: cfg.arm_deployments,
+            ticketing: cfg.ticketing,
+            messaging: cfg.messaging,
+            sql: cfg.sql,
+            kubernetes: cfg.kubernetes,
+            scheduler: cfg.scheduler,
             codex_linux_sandbox_exe,
 
             hide_agent_reasoning: cfg.hide_agent_reasoning.unwrap_or(false),
@@ -806,6 +1328,20 @@ impl Config {
                 .experimental_use_exec_command_tool
                 .unwrap_or(false),
             include_view_image_tool,
+            include_background_jobs_tool: cfg
+                .tools
+                .as_ref()
+                .and_then(|t| t.background_jobs)
+                .unwrap_or(false),
+            include_git_tools: cfg.tools.as_ref().and_then(|t| t.git).unwrap_or(false),
+            include_edit_files_tool: cfg
+                .tools
+                .as_ref()
+                .and_then(|t| t.edit_files)
+                .unwrap_or(false),
+            environment_snapshot: cfg.environment_snapshot.unwrap_or(true),
+            dry_run: cfg.dry_run.unwrap_or(false),
+            tool_description_max_tokens: cfg.tool_description_max_tokens,
             disable_paste_burst: cfg.disable_paste_burst.unwrap_or(false),
         };
         Ok(config)
@@ -1087,6 +1623,7 @@ disable_response_storage = true
             stream_max_retries: Some(10),
             stream_idle_timeout_ms: Some(300_000),
             requires_openai_auth: false,
+            azure: None,
         };
         let model_provider_map = {
             let mut model_provider_map = built_in_model_providers();
@@ -1144,22 +1681,60 @@ disable_response_storage = true
                 model_family: find_family_for_model("o3").expect("known model slug"),
                 model_context_window: Some(200_000),
                 model_max_output_tokens: Some(100_000),
+                model_auto_compact_token_limit: None,
+                prompt_caching: true,
                 model_provider_id: "openai".to_string(),
                 model_provider: fixture.openai_provider.clone(),
                 approval_policy: AskForApproval::Never,
                 sandbox_policy: SandboxPolicy::new_read_only_policy(),
                 shell_environment_policy: ShellEnvironmentPolicy::default(),
+                exec_output: ExecOutputConfig::default(),
+                exec_limits: ExecLimits::default(),
+                failover: None,
+                model_routes: Vec::new(),
+                provider_circuit_breaker: ProviderCircuitBreakerConfig::default(),
+                budget: BudgetConfig::default(),
+                embeddings: None,
+                knowledge_base: None,
+                images: ImagesConfig::default(),
+                artifacts: ArtifactsConfig::default(),
+                max_parallel_tool_calls: 1,
+                sandbox_backend: SandboxBackend::default(),
+                sandbox_docker: DockerSandbox::default(),
+                command_rules: Vec::new(),
+                hooks: Vec::new(),
+                custom_tools: HashMap::new(),
+                redaction: RedactionConfig::default(),
+                audit: AuditConfig::default(),
                 disable_response_storage: false,
                 user_instructions: None,
                 notify: None,
+                notifications: NotificationsConfig::default(),
+                approval_http: None,
                 cwd: fixture.cwd(),
                 mcp_servers: HashMap::new(),
+                mcp_health: McpHealthConfig::default(),
+                mcp_server_auth: McpServerAuthConfig::default(),
+                mcp_server_limits: HashMap::new(),
+                mcp_server_max_result_bytes: DEFAULT_MCP_SERVER_MAX_RESULT_BYTES,
+                mcp_server_mode: McpServerMode::default(),
                 model_providers: fixture.model_provider_map.clone(),
                 project_doc_max_bytes: PROJECT_DOC_MAX_BYTES,
                 codex_home: fixture.codex_home(),
                 history: History::default(),
+                storage: StorageConfig::default(),
                 file_opener: UriBasedFileOpener::VsCode,
                 tui: Tui::default(),
+                azure_auth: AzureAuthConfig::default(),
+                azure_monitor: None,
+                key_vault: None,
+                azure_storage: None,
+                arm_deployments: None,
+                ticketing: None,
+                messaging: None,
+                sql: None,
+                kubernetes: None,
+                scheduler: None,
                 codex_linux_sandbox_exe: None,
                 hide_agent_reasoning: false,
                 show_raw_agent_reasoning: false,
@@ -1176,6 +1751,12 @@ disable_response_storage = true
                 preferred_auth_method: AuthMode::ChatGPT,
                 use_experimental_streamable_shell_tool: false,
                 include_view_image_tool: true,
+                include_background_jobs_tool: false,
+                include_git_tools: false,
+                include_edit_files_tool: false,
+                environment_snapshot: true,
+                dry_run: false,
+                tool_description_max_tokens: None,
                 disable_paste_burst: false,
             },
             o3_profile_config
@@ -1202,22 +1783,60 @@ disable_response_storage = true
             model_family: find_family_for_model("gpt-3.5-turbo").expect("known model slug"),
             model_context_window: Some(16_385),
             model_max_output_tokens: Some(4_096),
+            model_auto_compact_token_limit: None,
+            prompt_caching: true,
             model_provider_id: "openai-chat-completions".to_string(),
             model_provider: fixture.openai_chat_completions_provider.clone(),
             approval_policy: AskForApproval::UnlessTrusted,
             sandbox_policy: SandboxPolicy::new_read_only_policy(),
             shell_environment_policy: ShellEnvironmentPolicy::default(),
+            exec_output: ExecOutputConfig::default(),
+            exec_limits: ExecLimits::default(),
+            failover: None,
+            model_routes: Vec::new(),
+            provider_circuit_breaker: ProviderCircuitBreakerConfig::default(),
+            budget: BudgetConfig::default(),
+            embeddings: None,
+            knowledge_base: None,
+            images: ImagesConfig::default(),
+            artifacts: ArtifactsConfig::default(),
+            max_parallel_tool_calls: 1,
+            sandbox_backend: SandboxBackend::default(),
+            sandbox_docker: DockerSandbox::default(),
+            command_rules: Vec::new(),
+            hooks: Vec::new(),
+            custom_tools: HashMap::new(),
+            redaction: RedactionConfig::default(),
+            audit: AuditConfig::default(),
             disable_response_storage: false,
             user_instructions: None,
             notify: None,
+            notifications: NotificationsConfig::default(),
+            approval_http: None,
             cwd: fixture.cwd(),
             mcp_servers: HashMap::new(),
+            mcp_health: McpHealthConfig::default(),
+            mcp_server_auth: McpServerAuthConfig::default(),
+            mcp_server_limits: HashMap::new(),
+            mcp_server_max_result_bytes: DEFAULT_MCP_SERVER_MAX_RESULT_BYTES,
+            mcp_server_mode: McpServerMode::default(),
             model_providers: fixture.model_provider_map.clone(),
             project_doc_max_bytes: PROJECT_DOC_MAX_BYTES,
             codex_home: fixture.codex_home(),
             history: History::default(),
+            storage: StorageConfig::default(),
             file_opener: UriBasedFileOpener::VsCode,
             tui: Tui::default(),
+            azure_auth: AzureAuthConfig::default(),
+            azure_monitor: None,
+            key_vault: None,
+            azure_storage: None,
+            arm_deployments: None,
+            ticketing: None,
+            messaging: None,
+            sql: None,
+            kubernetes: None,
+            scheduler: None,
             codex_linux_sandbox_exe: None,
             hide_agent_reasoning: false,
             show_raw_agent_reasoning: false,
@@ -1234,6 +1853,12 @@ disable_response_storage = true
             preferred_auth_method: AuthMode::ChatGPT,
             use_experimental_streamable_shell_tool: false,
             include_view_image_tool: true,
+            include_background_jobs_tool: false,
+            include_git_tools: false,
+            include_edit_files_tool: false,
+            environment_snapshot: true,
+            dry_run: false,
+            tool_description_max_tokens: None,
             disable_paste_burst: false,
         };
 
@@ -1275,22 +1900,60 @@ disable_response_storage = true
             model_family: find_family_for_model("o3").expect("known model slug"),
             model_context_window: Some(200_000),
             model_max_output_tokens: Some(100_000),
+            model_auto_compact_token_limit: None,
+            prompt_caching: true,
             model_provider_id: "openai".to_string(),
             model_provider: fixture.openai_provider.clone(),
             approval_policy: AskForApproval::OnFailure,
             sandbox_policy: SandboxPolicy::new_read_only_policy(),
             shell_environment_policy: ShellEnvironmentPolicy::default(),
+            exec_output: ExecOutputConfig::default(),
+            exec_limits: ExecLimits::default(),
+            failover: None,
+            model_routes: Vec::new(),
+            provider_circuit_breaker: ProviderCircuitBreakerConfig::default(),
+            budget: BudgetConfig::default(),
+            embeddings: None,
+            knowledge_base: None,
+            images: ImagesConfig::default(),
+            artifacts: ArtifactsConfig::default(),
+            max_parallel_tool_calls: 1,
+            sandbox_backend: SandboxBackend::default(),
+            sandbox_docker: DockerSandbox::default(),
+            command_rules: Vec::new(),
+            hooks: Vec::new(),
+            custom_tools: HashMap::new(),
+            redaction: RedactionConfig::default(),
+            audit: AuditConfig::default(),
             disable_response_storage: true,
             user_instructions: None,
             notify: None,
+            notifications: NotificationsConfig::default(),
+            approval_http: None,
             cwd: fixture.cwd(),
             mcp_servers: HashMap::new(),
+            mcp_health: McpHealthConfig::default(),
+            mcp_server_auth: McpServerAuthConfig::default(),
+            mcp_server_limits: HashMap::new(),
+            mcp_server_max_result_bytes: DEFAULT_MCP_SERVER_MAX_RESULT_BYTES,
+            mcp_server_mode: McpServerMode::default(),
             model_providers: fixture.model_provider_map.clone(),
             project_doc_max_bytes: PROJECT_DOC_MAX_BYTES,
             codex_home: fixture.codex_home(),
             history: History::default(),
+            storage: StorageConfig::default(),
             file_opener: UriBasedFileOpener::VsCode,
             tui: Tui::default(),
+            azure_auth: AzureAuthConfig::default(),
+            azure_monitor: None,
+            key_vault: None,
+            azure_storage: None,
+            arm_deployments: None,
+            ticketing: None,
+            messaging: None,
+            sql: None,
+            kubernetes: None,
+            scheduler: None,
             codex_linux_sandbox_exe: None,
             hide_agent_reasoning: false,
             show_raw_agent_reasoning: false,
@@ -1307,6 +1970,12 @@ disable_response_storage = true
             preferred_auth_method: AuthMode::ChatGPT,
             use_experimental_streamable_shell_tool: false,
             include_view_image_tool: true,
+            include_background_jobs_tool: false,
+            include_git_tools: false,
+            include_edit_files_tool: false,
+            environment_snapshot: true,
+            dry_run: false,
+            tool_description_max_tokens: None,
             disable_paste_burst: false,
         };
 
@@ -1315,6 +1984,51 @@ disable_response_storage = true
         Ok(())
     }
 
+    #[test]
+    fn test_failover_from_profile() -> std::io::Result<()> {
+        let fixture = create_test_fixture()?;
+        let mut cfg = fixture.cfg.clone();
+        if let Some(profile) = cfg.profiles.get_mut("o3") {
+            profile.failover_model_provider = Some("openai-chat-completions".to_string());
+        }
+
+        let config = Config::load_from_base_config_with_overrides(
+            cfg.clone(),
+            ConfigOverrides {
+                config_profile: Some("o3".to_string()),
+                cwd: Some(fixture.cwd()),
+                ..Default::default()
+            },
+            fixture.codex_home(),
+        )?;
+        assert_eq!(
+            config.failover,
+            Some(ProviderFailover {
+                model_provider_id: "openai-chat-completions".to_string(),
+                model_provider: fixture.openai_chat_completions_provider.clone(),
+                model: "o3".to_string(),
+                model_family: find_family_for_model("o3").expect("known model slug"),
+            })
+        );
+
+        cfg.failover_model_provider = Some("missing".to_string());
+        let err = Config::load_from_base_config_with_overrides(
+            cfg,
+            ConfigOverrides {
+                cwd: Some(fixture.cwd()),
+                ..Default::default()
+            },
+            fixture.codex_home(),
+        )
+        .expect_err("unknown failover provider");
+        assert_eq!(
+            err.to_string(),
+            "Failover model provider `missing` not found"
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_set_project_trusted_writes_explicit_tables() -> anyhow::Result<()> {
         let codex_home = TempDir::new().unwrap();
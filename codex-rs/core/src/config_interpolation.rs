@@ -0,0 +1,171 @@
+//! Expands `${env:VAR}` and `${file:path}` references in the string values of
+//! `config.toml`; `$${env:` and `$${file:` produce them literally. Any other
+//! `${...}`, such as a `${HOME}` meant for a shell, is left as it is. Relative
+//! file paths are resolved against `CODEX_HOME`, and a single trailing newline
+//! is dropped from file contents. Expanded text is not expanded again.
+
+use std::io::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use toml::Value as TomlValue;
+
+/// Expands the references in every string of `value`, in place.
+pub(crate) fn interpolate_config(value: &mut TomlValue, codex_home: &Path) -> std::io::Result<()> {
+    interpolate_value(value, "", &|name| std::env::var(name).ok(), codex_home)
+}
+
+fn interpolate_value(
+    value: &mut TomlValue,
+    path: &str,
+    env: &dyn Fn(&str) -> Option<String>,
+    codex_home: &Path,
+) -> std::io::Result<()> {
+    match value {
+        TomlValue::String(text) if text.contains('$') => {
+            *text = interpolate_str(text, env, codex_home)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, format!("`{path}`: {e}")))?;
+        }
+        TomlValue::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                interpolate_value(item, &format!("{path}[{index}]"), env, codex_home)?;
+            }
+        }
+        TomlValue::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                let item_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                interpolate_value(item, &item_path, env, codex_home)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn interpolate_str(
+    text: &str,
+    env: &dyn Fn(&str) -> Option<String>,
+    codex_home: &Path,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = find_reference(rest) {
+        if let Some(before) = rest[..start].strip_suffix('$') {
+            out.push_str(before);
+            out.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('}') else {
+            return Err(format!("unterminated `${{` in `{text}`"));
+        };
+        let reference = &after[..end];
+        rest = &after[end + 1..];
+        if let Some(name) = reference.strip_prefix("env:") {
+            match env(name) {
+                Some(value) => out.push_str(&value),
+                None => return Err(format!("environment variable `{name}` is not set")),
+            }
+        } else {
+            // `find_reference` only stops at `env:` and `file:` references.
+            let file = reference.strip_prefix("file:").unwrap_or(reference);
+            let file_path = codex_home.join(file);
+            let contents = std::fs::read_to_string(&file_path)
+                .map_err(|e| format!("cannot read `{}`: {e}", file_path.display()))?;
+            let contents = contents.strip_suffix('\n').unwrap_or(&contents);
+            out.push_str(contents.strip_suffix('\r').unwrap_or(contents));
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Offset of the first `${env:` or `${file:` in `text`.
+fn find_reference(text: &str) -> Option<usize> {
+    text.match_indices("${")
+        .map(|(start, _)| start)
+        .find(|start| {
+            let reference = &text[start + 2..];
+            reference.starts_with("env:") || reference.starts_with("file:")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    fn interpolate(toml: &str, codex_home: &Path) -> std::io::Result<TomlValue> {
+        let mut value: TomlValue = toml::from_str(toml).map_err(std::io::Error::other)?;
+        let env = |name: &str| (name == "CLUSTER").then(|| "stage".to_string());
+        interpolate_value(&mut value, "", &env, codex_home)?;
+        Ok(value)
+    }
+
+    #[test]
+    fn expands_env_and_file_references() -> std::io::Result<()> {
+        let codex_home = TempDir::new()?;
+        std::fs::write(codex_home.path().join("token"), "secret\n")?;
+
+        let value = interpolate(
+            r#"
+model = "o3"
+[model_providers.kusto.http_headers]
+Authorization = "Bearer ${file:token}"
+[profiles.dev]
+chatgpt_base_url = "https://${env:CLUSTER}.example.com/$${env:literal}/$5"
+"#,
+            codex_home.path(),
+        )?;
+        let expected: TomlValue = toml::from_str(
+            r#"
+model = "o3"
+[model_providers.kusto.http_headers]
+Authorization = "Bearer secret"
+[profiles.dev]
+chatgpt_base_url = "https://stage.example.com/${env:literal}/$5"
+"#,
+        )
+        .map_err(std::io::Error::other)?;
+        assert_eq!(value, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn errors_name_the_offending_key() -> std::io::Result<()> {
+        let codex_home = TempDir::new()?;
+        let error_for = |toml: &str| match interpolate(toml, codex_home.path()) {
+            Ok(_) => String::new(),
+            Err(e) => e.to_string(),
+        };
+
+        assert_eq!(
+            error_for("[profiles.prod]\nmodel = \"${env:MODEL}\""),
+            "`profiles.prod.model`: environment variable `MODEL` is not set"
+        );
+        assert!(error_for("model = \"${env:CLUSTER\"").contains("unterminated"));
+        assert!(error_for("model = \"${file:missing}\"").starts_with("`model`: cannot read"));
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_other_references_alone() -> std::io::Result<()> {
+        let codex_home = TempDir::new()?;
+        let toml = r#"
+notify = ["sh", "-c", "notify-send ${HOME} ${vault:x} $${HOME} ${"]
+[shell_environment_policy.set]
+PATH = "${HOME}/bin:${PATH}"
+"#;
+        let value = interpolate(toml, codex_home.path())?;
+        let expected: TomlValue = toml::from_str(toml).map_err(std::io::Error::other)?;
+        assert_eq!(value, expected);
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 use crate::config_types::Verbosity;
@@ -10,10 +11,18 @@ use codex_protocol::config_types::ReasoningSummary;
 /// in `config.toml`.
 #[derive(Debug, Clone, Default, PartialEq, Deserialize)]
 pub struct ConfigProfile {
+    /// Name of a profile whose values are used for any option this profile
+    /// does not set.
+    pub extends: Option<String>,
     pub model: Option<String>,
     /// The key in the `model_providers` map identifying the
     /// [`ModelProviderInfo`] to use.
     pub model_provider: Option<String>,
+    /// Model used when the provider keeps failing. Defaults to `model`.
+    pub failover_model: Option<String>,
+    /// The key in the `model_providers` map of the provider used when the
+    /// primary provider keeps failing. Defaults to `model_provider`.
+    pub failover_model_provider: Option<String>,
     pub approval_policy: Option<AskForApproval>,
     pub disable_response_storage: Option<bool>,
     pub model_reasoning_effort: Option<ReasoningEffort>,
@@ -22,3 +31,148 @@ pub struct ConfigProfile {
     pub chatgpt_base_url: Option<String>,
     pub experimental_instructions_file: Option<PathBuf>,
 }
+
+impl ConfigProfile {
+    /// Looks up the profile `key` and fills in the options it leaves unset
+    /// from the profiles it `extends`, nearest first.
+    pub fn resolve(
+        profiles: &HashMap<String, ConfigProfile>,
+        key: &str,
+    ) -> std::io::Result<ConfigProfile> {
+        let mut chain: Vec<&str> = Vec::new();
+        let mut resolved = ConfigProfile::default();
+        let mut next = Some(key);
+        while let Some(name) = next {
+            if chain.contains(&name) {
+                chain.push(name);
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "config profile `{key}` extends itself: {}",
+                        chain.join(" -> ")
+                    ),
+                ));
+            }
+            let profile = profiles.get(name).ok_or_else(|| {
+                let message = match chain.last() {
+                    Some(child) => {
+                        format!("config profile `{name}` (extended by `{child}`) not found")
+                    }
+                    None => format!("config profile `{name}` not found"),
+                };
+                std::io::Error::new(std::io::ErrorKind::NotFound, message)
+            })?;
+            chain.push(name);
+            resolved = resolved.or(profile.clone());
+            next = profile.extends.as_deref();
+        }
+        resolved.extends = None;
+        Ok(resolved)
+    }
+
+    /// Keeps the options set in `self` and takes the rest from `parent`.
+    fn or(self, parent: ConfigProfile) -> ConfigProfile {
+        // Destructure fully so that new options cannot be forgotten here.
+        let ConfigProfile {
+            extends,
+            model,
+            model_provider,
+            failover_model,
+            failover_model_provider,
+            approval_policy,
+            disable_response_storage,
+            model_reasoning_effort,
+            model_reasoning_summary,
+            model_verbosity,
+            chatgpt_base_url,
+            experimental_instructions_file,
+        } = self;
+        ConfigProfile {
+            extends: extends.or(parent.extends),
+            model: model.or(parent.model),
+            model_provider: model_provider.or(parent.model_provider),
+            failover_model: failover_model.or(parent.failover_model),
+            failover_model_provider: failover_model_provider.or(parent.failover_model_provider),
+            approval_policy: approval_policy.or(parent.approval_policy),
+            disable_response_storage: disable_response_storage.or(parent.disable_response_storage),
+            model_reasoning_effort: model_reasoning_effort.or(parent.model_reasoning_effort),
+            model_reasoning_summary: model_reasoning_summary.or(parent.model_reasoning_summary),
+            model_verbosity: model_verbosity.or(parent.model_verbosity),
+            chatgpt_base_url: chatgpt_base_url.or(parent.chatgpt_base_url),
+            experimental_instructions_file: experimental_instructions_file
+                .or(parent.experimental_instructions_file),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn profiles(toml: &str) -> HashMap<String, ConfigProfile> {
+        #[derive(Deserialize)]
+        struct Profiles {
+            profiles: HashMap<String, ConfigProfile>,
+        }
+        match toml::from_str::<Profiles>(toml) {
+            Ok(parsed) => parsed.profiles,
+            Err(e) => panic!("invalid profiles: {e}"),
+        }
+    }
+
+    #[test]
+    fn extended_profiles_fill_in_unset_options() {
+        let profiles = profiles(
+            r#"
+[profiles.base]
+model = "o3"
+model_provider = "openai"
+approval_policy = "never"
+
+[profiles.stage]
+extends = "base"
+model_provider = "azure"
+
+[profiles.prod]
+extends = "stage"
+approval_policy = "on-request"
+"#,
+        );
+
+        assert_eq!(
+            ConfigProfile::resolve(&profiles, "prod").ok(),
+            Some(ConfigProfile {
+                model: Some("o3".to_string()),
+                model_provider: Some("azure".to_string()),
+                approval_policy: Some(AskForApproval::OnRequest),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn cycles_and_missing_parents_are_reported() {
+        let profiles = profiles(
+            r#"
+[profiles.a]
+extends = "b"
+[profiles.b]
+extends = "a"
+[profiles.c]
+extends = "missing"
+"#,
+        );
+
+        let error = |key| match ConfigProfile::resolve(&profiles, key) {
+            Ok(_) => String::new(),
+            Err(e) => e.to_string(),
+        };
+        assert_eq!(error("a"), "config profile `a` extends itself: a -> b -> a");
+        assert_eq!(
+            error("c"),
+            "config profile `missing` (extended by `c`) not found"
+        );
+        assert_eq!(error("d"), "config profile `d` not found");
+    }
+}
@@ -7,11 +7,14 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 use wildmatch::WildMatchPattern;
 
+use codex_protocol::config_types::ReasoningEffort as ReasoningEffortConfig;
+use codex_protocol::protocol::ToolPipelineStep;
+
 use serde::Deserialize;
 use serde::Serialize;
 use strum_macros::Display;
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct McpServerConfig {
     pub command: String,
 
@@ -20,6 +23,329 @@ pub struct McpServerConfig {
 
     #[serde(default)]
     pub env: Option<HashMap<String, String>>,
+
+    /// Wildcard patterns on tool names. When not empty, only the matching
+    /// tools of the server are offered to the model.
+    #[serde(default)]
+    pub enabled_tools: Vec<String>,
+
+    /// Wildcard patterns on tool names that are never offered to the model,
+    /// even when they match `enabled_tools`.
+    #[serde(default)]
+    pub disabled_tools: Vec<String>,
+
+    /// Whether calls to the server's tools need approval, unless
+    /// `tool_approval` says otherwise.
+    #[serde(default)]
+    pub approval: McpToolApproval,
+
+    /// Approval for individual tools, keyed by tool name or wildcard pattern.
+    /// An exact name wins over patterns; among matching patterns, `prompt`
+    /// wins over `auto`.
+    #[serde(default)]
+    pub tool_approval: HashMap<String, McpToolApproval>,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum McpToolApproval {
+    /// Call the tool without asking.
+    #[default]
+    Auto,
+
+    /// Ask the user before every call, unless the tool was approved for the
+    /// rest of the session.
+    Prompt,
+}
+
+/// The `[mcp_health]` table: how MCP servers are checked and restarted.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct McpHealthConfig {
+    /// How often every server is pinged; `0` disables health checks and
+    /// restarts.
+    pub interval_ms: u64,
+
+    /// How long a server has to answer a ping.
+    pub ping_timeout_ms: u64,
+
+    /// Longest delay between attempts to restart a server. Delays start at
+    /// one second and double after every failed attempt.
+    pub max_restart_delay_ms: u64,
+}
+
+impl Default for McpHealthConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 30_000,
+            ping_timeout_ms: 5_000,
+            max_restart_delay_ms: 300_000,
+        }
+    }
+}
+
+/// The `[mcp_server_auth]` table: who may connect when `codex mcp` listens
+/// on a port, and whether connections are encrypted.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct McpServerAuthConfig {
+    /// Bearer tokens clients may present. When empty, WebSocket and HTTP
+    /// clients are not asked for a token.
+    pub tokens: Vec<McpClientToken>,
+
+    /// Serve TLS with this certificate instead of plain text.
+    pub tls: Option<McpServerTlsConfig>,
+}
+
+/// A bearer token and the client it identifies in the logs.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct McpClientToken {
+    /// Name of the client that presents the token.
+    pub client: String,
+
+    /// Environment variable holding the token, so it stays out of the config
+    /// file.
+    pub token_env: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct McpServerTlsConfig {
+    /// PEM file with the server certificate, followed by its chain.
+    pub cert: PathBuf,
+
+    /// PEM file with the private key of `cert`.
+    pub key: PathBuf,
+
+    /// PEM file with the CAs that sign client certificates. When set, every
+    /// client must present a certificate signed by one of them.
+    #[serde(default)]
+    pub client_ca: Option<PathBuf>,
+}
+
+/// Limits on calls to one tool of `codex mcp`, shared by all of its
+/// clients. Calls over a limit are refused with a throttling error rather
+/// than queued.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct McpToolLimits {
+    /// Calls that may run at the same time.
+    pub max_concurrent: Option<usize>,
+
+    /// Calls that may start within any 60 second window.
+    pub max_per_minute: Option<u32>,
+}
+
+/// What clients of `codex mcp` may do, so that a restricted server can be
+/// deployed for clients that are not trusted with everything.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum McpServerMode {
+    /// Conversations can only read: they run in the read-only sandbox and
+    /// never ask to escalate out of it.
+    ReadOnly,
+
+    /// Conversations can write within the sandbox, but clients cannot turn
+    /// it off, override the config or change the login.
+    Standard,
+
+    /// Clients can do everything.
+    #[default]
+    Admin,
+}
+
+/// Kinds of notifications that can be routed to the `notify` program, the
+/// desktop and webhooks.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotificationEvent {
+    /// A turn finished.
+    AgentTurnComplete,
+    /// The agent is waiting for the user to approve a command or change.
+    ApprovalRequested,
+    /// A turn ended with an error.
+    Error,
+}
+
+impl NotificationEvent {
+    pub const ALL: [NotificationEvent; 3] = [
+        NotificationEvent::AgentTurnComplete,
+        NotificationEvent::ApprovalRequested,
+        NotificationEvent::Error,
+    ];
+}
+
+/// The `[notifications]` table: where notifications about the session go.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// Events passed to the `notify` program.
+    pub notify_events: Vec<NotificationEvent>,
+
+    /// Events shown as desktop notifications.
+    pub desktop: Vec<NotificationEvent>,
+
+    /// HTTP endpoints, such as Slack or Teams incoming webhooks, that
+    /// notifications are posted to.
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            notify_events: vec![NotificationEvent::AgentTurnComplete],
+            desktop: Vec::new(),
+            webhooks: Vec::new(),
+        }
+    }
+}
+
+/// A webhook in `[[notifications.webhooks]]`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WebhookConfig {
+    pub url: String,
+
+    /// Events posted to the webhook. Defaults to all of them.
+    #[serde(default = "all_notification_events")]
+    pub events: Vec<NotificationEvent>,
+
+    /// Body of the request, in which `{{type}}`, `{{title}}`, `{{message}}`
+    /// and `{{turn_id}}` are replaced by the values of the notification,
+    /// JSON-escaped unless `headers` set a `Content-Type` other than JSON.
+    /// Defaults to the notification as JSON.
+    #[serde(default)]
+    pub template: Option<String>,
+
+    /// Extra HTTP headers sent with the request.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+fn all_notification_events() -> Vec<NotificationEvent> {
+    NotificationEvent::ALL.to_vec()
+}
+
+/// The `[approval_http]` table: approvals requested from an HTTP service, for
+/// runs without anyone at the terminal.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApprovalHttpConfig {
+    /// Approval requests are posted here as JSON. This can be a webhook that
+    /// forwards them to the approvers.
+    pub request_url: String,
+
+    /// Polled for the decision on a request; `{id}` is replaced by the id of
+    /// the request. Defaults to `<request_url>/{id}`.
+    #[serde(default)]
+    pub decision_url: Option<String>,
+
+    /// Extra HTTP headers sent with every request.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+
+    /// How often the decision is polled for.
+    #[serde(default = "default_approval_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+
+    /// How long to wait for a decision before `on_timeout` applies.
+    #[serde(default = "default_approval_timeout_ms")]
+    pub timeout_ms: u64,
+
+    #[serde(default)]
+    pub on_timeout: ApprovalTimeoutPolicy,
+}
+
+fn default_approval_poll_interval_ms() -> u64 {
+    2_000
+}
+
+fn default_approval_timeout_ms() -> u64 {
+    15 * 60 * 1_000
+}
+
+/// What happens to a request that nobody decides on in time.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApprovalTimeoutPolicy {
+    /// The action is denied and the agent may try something else.
+    #[default]
+    Deny,
+    /// The action is denied and the turn ends.
+    Abort,
+}
+
+/// The `[provider_circuit_breaker]` table: when a failing model provider is
+/// taken out of use.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ProviderCircuitBreakerConfig {
+    /// Consecutive failed requests after which the provider is considered
+    /// unhealthy; `0` disables the circuit breaker.
+    pub failure_threshold: u32,
+
+    /// How long an unhealthy provider is skipped before it is tried again.
+    pub cooldown_ms: u64,
+}
+
+impl Default for ProviderCircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown_ms: 60_000,
+        }
+    }
+}
+
+/// The `[budget]` table: how many requests, tokens and dollars a session, and
+/// all sessions of a day, may use.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct BudgetConfig {
+    pub session: BudgetLimits,
+    pub daily: BudgetLimits,
+
+    /// Share of a limit, in percent, at which a warning is sent.
+    pub warn_at_percent: u8,
+
+    /// Prices of models, by model slug, that costs are computed from. Models
+    /// without a price do not count towards `max_cost_usd`.
+    pub prices: HashMap<String, ModelPrice>,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            session: BudgetLimits::default(),
+            daily: BudgetLimits::default(),
+            warn_at_percent: 80,
+            prices: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Default)]
+pub struct BudgetLimits {
+    /// Completed requests to the model.
+    pub max_requests: Option<u64>,
+    /// Input and output tokens.
+    pub max_tokens: Option<u64>,
+    /// Cost in US dollars.
+    pub max_cost_usd: Option<f64>,
+}
+
+impl BudgetLimits {
+    pub fn is_empty(&self) -> bool {
+        self.max_requests.is_none() && self.max_tokens.is_none() && self.max_cost_usd.is_none()
+    }
+}
+
+/// US dollars per million tokens.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct ModelPrice {
+    pub input: f64,
+    /// Defaults to `input`.
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+    pub output: f64,
 }
 
 #[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
@@ -74,6 +400,34 @@ pub enum HistoryPersistence {
     None,
 }
 
+/// The `[storage]` table: stores that session transcripts and message
+/// history entries are written to in addition to the JSONL files in
+/// `~/.codex`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct StorageConfig {
+    /// SQLite database that every session is recorded in. Several sessions,
+    /// also from different processes, can share one database.
+    #[serde(default)]
+    pub sqlite_path: Option<PathBuf>,
+
+    /// HTTP service that every session is sent to, e.g. to keep the
+    /// transcripts of a team in one place.
+    #[serde(default)]
+    pub http: Option<HttpStorageConfig>,
+}
+
+/// The `[storage.http]` table.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HttpStorageConfig {
+    /// Records of a session are posted to `<url>/sessions/<session_id>/records`
+    /// and history entries to `<url>/history`.
+    pub url: String,
+
+    /// Extra HTTP headers sent with every request.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
 /// Collection of settings that are specific to the TUI.
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Tui {}
@@ -186,42 +540,1059 @@ impl From<ShellEnvironmentPolicyToml> for ShellEnvironmentPolicy {
     }
 }
 
-/// See https://platform.openai.com/docs/guides/reasoning?api-mode=responses#get-started-with-reasoning
-#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Display)]
-#[serde(rename_all = "lowercase")]
-#[strum(serialize_all = "lowercase")]
-pub enum ReasoningEffort {
-    Low,
-    #[default]
-    Medium,
-    High,
-    /// Option to disable reasoning.
-    None,
+/// Default size of each `ExecCommandOutputDelta` chunk.
+pub const DEFAULT_EXEC_OUTPUT_CHUNK_BYTES: usize = 8 * 1024; // 8 KiB
+
+/// Default amount of output retained per stream of an exec call.
+pub const DEFAULT_EXEC_OUTPUT_MAX_BUFFER_BYTES: usize = 1024 * 1024; // 1 MiB
+
+/// How output of `shell`/`local_shell` commands is streamed and buffered.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ExecOutputToml {
+    /// Maximum size of each streamed output chunk, in bytes.
+    pub chunk_bytes: Option<usize>,
+
+    /// Maximum output retained per stream, in bytes. Past this, the middle
+    /// of the output is dropped and only its start and end are kept.
+    pub max_buffer_bytes: Option<usize>,
 }
 
-/// A summary of the reasoning performed by the model. This can be useful for
-/// debugging and understanding the model's reasoning process.
-/// See https://platform.openai.com/docs/guides/reasoning?api-mode=responses#reasoning-summaries
-#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Display)]
-#[serde(rename_all = "lowercase")]
-#[strum(serialize_all = "lowercase")]
-pub enum ReasoningSummary {
-    #[default]
-    Auto,
-    Concise,
-    Detailed,
-    /// Option to disable reasoning summaries.
-    None,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExecOutputConfig {
+    pub chunk_bytes: usize,
+    pub max_buffer_bytes: usize,
 }
 
-/// Controls output length/detail on GPT-5 models via the Responses API.
-/// Serialized with lowercase values to match the OpenAI API.
-#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Display)]
-#[serde(rename_all = "lowercase")]
-#[strum(serialize_all = "lowercase")]
-pub enum Verbosity {
-    Low,
-    #[default]
-    Medium,
-    High,
+impl Default for ExecOutputConfig {
+    fn default() -> Self {
+        Self {
+            chunk_bytes: DEFAULT_EXEC_OUTPUT_CHUNK_BYTES,
+            max_buffer_bytes: DEFAULT_EXEC_OUTPUT_MAX_BUFFER_BYTES,
+        }
+    }
+}
+
+impl From<ExecOutputToml> for ExecOutputConfig {
+    fn from(toml: ExecOutputToml) -> Self {
+        Self {
+            chunk_bytes: toml
+                .chunk_bytes
+                .filter(|&bytes| bytes > 0)
+                .unwrap_or(DEFAULT_EXEC_OUTPUT_CHUNK_BYTES),
+            max_buffer_bytes: toml
+                .max_buffer_bytes
+                .unwrap_or(DEFAULT_EXEC_OUTPUT_MAX_BUFFER_BYTES),
+        }
+    }
+}
+
+/// Limits applied to `shell`/`local_shell` commands. Unset fields impose no
+/// limit, except `timeout_ms` which falls back to the built-in default.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct ExecLimits {
+    /// Wall-clock timeout used when the tool call does not specify one.
+    pub timeout_ms: Option<u64>,
+
+    /// Combined stdout and stderr a command may write before it is killed.
+    pub max_output_bytes: Option<usize>,
+
+    /// CPU time a command may use, in seconds. Enforced on Linux and Windows.
+    pub cpu_time_secs: Option<u64>,
+
+    /// Memory a command may use, in bytes. Enforced on Linux (address space)
+    /// and Windows (committed memory per process).
+    pub memory_bytes: Option<u64>,
+}
+
+impl ExecLimits {
+    /// Combine with the output limit requested by a single tool call, which
+    /// can lower the configured limit but not raise it.
+    pub fn with_call_limits(self, max_output_bytes: Option<usize>) -> Self {
+        let max_output_bytes = match (self.max_output_bytes, max_output_bytes) {
+            (Some(configured), Some(requested)) => Some(configured.min(requested)),
+            (configured, requested) => configured.or(requested),
+        };
+        Self {
+            max_output_bytes,
+            ..self
+        }
+    }
+}
+
+/// Masking of secrets in conversation items before they are recorded in the
+/// rollout or sent to the model provider.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RedactionConfig {
+    /// Mask secrets matching the built-in patterns and `patterns`.
+    pub enabled: bool,
+
+    /// Additional regular expressions matching secrets. Only the `secret`
+    /// capture group is masked when the pattern has one.
+    pub patterns: Vec<String>,
+
+    /// Also mask long random-looking tokens.
+    pub entropy: bool,
+
+    /// Shannon entropy, in bits per character, from which a token counts as
+    /// random.
+    pub entropy_threshold: f64,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            patterns: Vec::new(),
+            entropy: false,
+            entropy_threshold: 4.5,
+        }
+    }
+}
+
+/// The `[audit]` table: the tamper-evident log of privileged actions kept in
+/// `~/.codex/audit/audit.jsonl`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Record commands, patches and MCP tool calls in the audit log.
+    pub enabled: bool,
+
+    /// Wildcard patterns on `server/tool` selecting the MCP tool calls to
+    /// record.
+    pub mcp_tools: Vec<String>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mcp_tools: vec!["*".to_string()],
+        }
+    }
+}
+
+/// The `[images]` table: how large attached images may be before they are
+/// shrunk, or left out when `resize` is off.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(default)]
+pub struct ImagesConfig {
+    /// Longest side, in pixels.
+    pub max_dimension: u32,
+
+    /// Size of the encoded image, in bytes.
+    pub max_bytes: u64,
+
+    /// Shrink images over a limit instead of leaving them out.
+    pub resize: bool,
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        Self {
+            max_dimension: 2048,
+            max_bytes: 5 * 1024 * 1024,
+            resize: true,
+        }
+    }
+}
+
+/// The `[artifacts]` table: tool outputs over `inline_max_bytes` are saved
+/// under `$CODEX_HOME/artifacts/<session id>/` and the model gets the start
+/// and end of the output plus the `read_artifact` tool to read the rest.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(default)]
+pub struct ArtifactsConfig {
+    pub enabled: bool,
+
+    /// Largest output, in bytes, that is still sent to the model as is.
+    pub inline_max_bytes: usize,
+
+    /// Bytes from each of the start and the end of a saved output that are
+    /// sent to the model.
+    pub preview_bytes: usize,
+}
+
+impl Default for ArtifactsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            inline_max_bytes: 64 * 1024,
+            preview_bytes: 2 * 1024,
+        }
+    }
+}
+
+/// The `[knowledge_base]` table: documentation the agent searches with the
+/// `kb_search` tool.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct KnowledgeBaseToml {
+    /// Markdown files, or directories searched for them, such as a clone of
+    /// an Azure DevOps wiki. Relative paths are resolved against the working
+    /// directory.
+    pub sources: Vec<PathBuf>,
+
+    /// Largest piece a page is split into, in bytes.
+    #[serde(default)]
+    pub chunk_bytes: Option<usize>,
+
+    /// Number of results a search returns when the model does not say.
+    #[serde(default)]
+    pub top_k: Option<usize>,
+}
+
+/// The `[embeddings]` table: the model that embeds and reranks text for
+/// every part of Codex that needs it.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct EmbeddingsToml {
+    /// Embedding model, or the deployment name for Azure OpenAI providers.
+    pub model: String,
+
+    /// Provider from the model_providers map; defaults to `model_provider`.
+    #[serde(default)]
+    pub model_provider: Option<String>,
+
+    /// Length of the returned vectors, for models that can shorten them.
+    #[serde(default)]
+    pub dimensions: Option<u32>,
+
+    /// Model served at the provider's `/rerank` endpoint. Without one,
+    /// documents are ranked by the similarity of their embeddings.
+    #[serde(default)]
+    pub rerank_model: Option<String>,
+}
+
+/// A `[[model_routes]]` entry choosing the model for the requests it matches.
+/// Every condition that is set has to hold; a route without conditions
+/// matches every request.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelRoute {
+    /// Name reported when the route is taken; defaults to the model.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Model to use; defaults to the session's model.
+    #[serde(default)]
+    pub model: Option<String>,
+
+    /// Provider from the model_providers map; defaults to the session's.
+    #[serde(default)]
+    pub model_provider: Option<String>,
+
+    /// Reasoning effort to use; defaults to the session's.
+    #[serde(default)]
+    pub model_reasoning_effort: Option<ReasoningEffortConfig>,
+
+    /// Words or phrases, one of which the user's latest message has to
+    /// contain. Matched case-insensitively.
+    #[serde(default)]
+    pub prompt_contains: Vec<String>,
+
+    /// Tool calls the model has to have made since the user's latest message.
+    #[serde(default)]
+    pub min_tool_calls: Option<usize>,
+}
+
+/// What happens to a command matched by a [`CommandRule`].
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommandRuleDecision {
+    /// Run without approval and without a sandbox.
+    Allow,
+    /// Always ask for approval, even when a sandbox is available.
+    Ask,
+    /// Reject without asking.
+    Deny,
+}
+
+/// A `[[command_rules]]` entry deciding how commands it matches are approved.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CommandRule {
+    /// Name reported when the rule matches; defaults to the `command` pattern.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Program and leading arguments the command must start with. Each entry
+    /// is a pattern in which `*` matches any text and `?` one character.
+    pub command: Vec<String>,
+
+    /// Arguments that must all be present for the rule to match.
+    #[serde(default)]
+    pub require_args: Vec<String>,
+
+    /// Arguments that must all be absent for the rule to match. `--opt` also
+    /// covers `--opt=value`.
+    #[serde(default)]
+    pub forbid_args: Vec<String>,
+
+    /// Restrict the rule to commands run in this directory or below it.
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+
+    pub decision: CommandRuleDecision,
+}
+
+/// A `[custom_tools.<name>]` entry: a function tool offered to the model
+/// that is implemented by a command or an HTTP endpoint. Exactly one of
+/// `command` and `url` must be set.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CustomToolConfig {
+    pub description: String,
+
+    /// JSON schema of the arguments. Defaults to an object without
+    /// properties.
+    #[serde(default)]
+    pub parameters: Option<serde_json::Value>,
+
+    /// Program and arguments, in which `{name}` is replaced by the argument
+    /// `name` everywhere but in the program. The arguments are also passed as
+    /// JSON in `CODEX_TOOL_ARGUMENTS`, and the command runs like a `shell`
+    /// call.
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+
+    /// Endpoint, in which `{name}` is replaced by the URL-encoded argument
+    /// `name`. The response body is the tool's result.
+    #[serde(default)]
+    pub url: Option<String>,
+
+    /// HTTP method. `GET` and `DELETE` send the other arguments as query
+    /// parameters, the others as a JSON body.
+    #[serde(default)]
+    pub method: CustomToolHttpMethod,
+
+    /// Extra HTTP headers.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+
+    /// HTTP headers whose values are read from environment variables, e.g.
+    /// `{ "Authorization" = "MY_TOOL_TOKEN" }`.
+    #[serde(default)]
+    pub env_headers: HashMap<String, String>,
+
+    /// Defaults to 60 seconds.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+
+    /// The tool only reads, so it still runs in dry-run mode. Tools that
+    /// call a `GET` endpoint are taken to only read.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CustomToolHttpMethod {
+    Get,
+    #[default]
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+/// A `[[hooks]]` entry: a command run before or after tool calls, or when a
+/// turn starts or ends.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct HookConfig {
+    /// Name used in messages; defaults to the command line.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    pub event: HookEvent,
+
+    /// Program and arguments. A JSON description of the event is written to
+    /// its stdin.
+    pub command: Vec<String>,
+
+    /// Patterns on the tool name, in which `*` matches any text and `?` one
+    /// character, selecting the calls a tool hook runs for. Empty matches
+    /// every tool.
+    #[serde(default)]
+    pub tools: Vec<String>,
+
+    /// Kill the hook and treat it as failed after this long. Defaults to 60
+    /// seconds.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+
+    /// When a `before-tool` or `turn-start` hook fails, do not run the tool
+    /// call or the turn.
+    #[serde(default)]
+    pub block_on_failure: bool,
+
+    /// When the hook's output is added to the conversation.
+    #[serde(default)]
+    pub attach_output: HookAttachOutput,
+}
+
+/// Point in the agent loop at which a [`HookConfig`] runs.
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    BeforeTool,
+    AfterTool,
+    TurnStart,
+    TurnEnd,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookAttachOutput {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
+}
+
+/// Mechanism used to enforce the sandbox policy on commands that are
+/// auto-approved to run in a sandbox.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SandboxBackend {
+    /// Seatbelt on macOS, Landlock and seccomp on Linux. Not available on
+    /// other platforms.
+    #[default]
+    Platform,
+
+    /// Run commands in a throwaway Docker container, see [`DockerSandbox`].
+    Docker,
+
+    /// Never sandbox. Untrusted commands always need approval.
+    None,
+}
+
+/// Container used by [`SandboxBackend::Docker`].
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DockerSandbox {
+    /// Docker-compatible CLI used to start the container, e.g. `podman`.
+    pub program: String,
+
+    /// Image the commands run in. It must provide the programs the model is
+    /// expected to call, starting with `bash`.
+    pub image: String,
+
+    /// Additional arguments passed to `run`, before the image.
+    pub args: Vec<String>,
+}
+
+impl Default for DockerSandbox {
+    fn default() -> Self {
+        Self {
+            program: "docker".to_string(),
+            image: "ubuntu:24.04".to_string(),
+            args: Vec::new(),
+        }
+    }
+}
+
+/// See https://platform.openai.com/docs/guides/reasoning?api-mode=responses#get-started-with-reasoning
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    #[default]
+    Medium,
+    High,
+    /// Option to disable reasoning.
+    None,
+}
+
+/// A summary of the reasoning performed by the model. This can be useful for
+/// debugging and understanding the model's reasoning process.
+/// See https://platform.openai.com/docs/guides/reasoning?api-mode=responses#reasoning-summaries
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ReasoningSummary {
+    #[default]
+    Auto,
+    Concise,
+    Detailed,
+    /// Option to disable reasoning summaries.
+    None,
+}
+
+/// Controls output length/detail on GPT-5 models via the Responses API.
+/// Serialized with lowercase values to match the OpenAI API.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Verbosity {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+/// Settings for the shared Azure (Entra ID) credential used by every Azure
+/// integration. See [`crate::azure_auth::AzureAuth`].
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AzureAuthConfig {
+    /// Tenant to authenticate against when a caller does not name one.
+    #[serde(default = "default_azure_tenant_id")]
+    pub tenant_id: String,
+
+    /// Application (client) id used for all flows. Defaults to the public
+    /// Azure CLI client so device-code login works without an app
+    /// registration.
+    #[serde(default = "default_azure_client_id")]
+    pub client_id: String,
+
+    /// Which flow to use to obtain tokens.
+    #[serde(default)]
+    pub method: AzureAuthMethod,
+
+    /// Name of the environment variable holding the client secret when
+    /// `method = "client-secret"`.
+    #[serde(default = "default_azure_client_secret_env_var")]
+    pub client_secret_env_var: String,
+
+    /// Entra ID authority host. Override for sovereign clouds.
+    #[serde(default = "default_azure_authority_host")]
+    pub authority_host: String,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum AzureAuthMethod {
+    /// Interactive device-code login; refresh tokens are cached on disk.
+    #[default]
+    DeviceCode,
+    /// Confidential client credentials read from the environment.
+    ClientSecret,
+    /// Managed identity of the Azure host Codex is running on.
+    ManagedIdentity,
+}
+
+impl Default for AzureAuthConfig {
+    fn default() -> Self {
+        Self {
+            tenant_id: default_azure_tenant_id(),
+            client_id: default_azure_client_id(),
+            method: AzureAuthMethod::default(),
+            client_secret_env_var: default_azure_client_secret_env_var(),
+            authority_host: default_azure_authority_host(),
+        }
+    }
+}
+
+fn default_azure_tenant_id() -> String {
+    "organizations".to_string()
+}
+
+fn default_azure_client_id() -> String {
+    "04b07795-8ddb-461a-bbee-02f9e1bf7b46".to_string()
+}
+
+fn default_azure_client_secret_env_var() -> String {
+    "AZURE_CLIENT_SECRET".to_string()
+}
+
+fn default_azure_authority_host() -> String {
+    "https://login.microsoftonline.com".to_string()
+}
+
+/// The `[azure_monitor]` table: tools that read the metrics, fired alerts and
+/// activity log of Azure resources. The tools are offered to the model when
+/// the table is present.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AzureMonitorConfig {
+    /// Subscription whose alerts and activity log are read when the model
+    /// does not name one.
+    #[serde(default)]
+    pub subscription_id: Option<String>,
+
+    /// Azure Resource Manager endpoint. Override for sovereign clouds.
+    #[serde(default = "default_azure_resource_manager")]
+    pub resource_manager: String,
+
+    /// Most alerts or activity log events one call returns.
+    #[serde(default = "default_azure_monitor_max_results")]
+    pub max_results: usize,
+}
+
+fn default_azure_resource_manager() -> String {
+    "https://management.azure.com".to_string()
+}
+
+fn default_azure_monitor_max_results() -> usize {
+    50
+}
+
+/// The `[key_vault]` table: tools that list Azure Key Vaults and the metadata
+/// of their secrets, keys and certificates, and read a secret into the
+/// environment of later commands after approval. The tools are offered to
+/// the model when the table is present.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyVaultConfig {
+    /// Subscription whose vaults are listed when the model does not name one.
+    #[serde(default)]
+    pub subscription_id: Option<String>,
+
+    /// Azure Resource Manager endpoint. Override for sovereign clouds.
+    #[serde(default = "default_azure_resource_manager")]
+    pub resource_manager: String,
+
+    /// DNS suffix of vault URLs, also used for the token scope. Override for
+    /// sovereign clouds.
+    #[serde(default = "default_key_vault_dns_suffix")]
+    pub vault_dns_suffix: String,
+
+    /// Most vaults or items one call returns.
+    #[serde(default = "default_key_vault_max_results")]
+    pub max_results: usize,
+
+    /// Items expiring within this many days are reported by
+    /// `key_vault_check_expiry` when the model does not say.
+    #[serde(default = "default_key_vault_expiry_warning_days")]
+    pub expiry_warning_days: i64,
+}
+
+fn default_key_vault_dns_suffix() -> String {
+    "vault.azure.net".to_string()
+}
+
+fn default_key_vault_max_results() -> usize {
+    200
+}
+
+fn default_key_vault_expiry_warning_days() -> i64 {
+    30
+}
+
+/// The `[azure_storage]` table: tools that list, read and upload blobs and
+/// create SAS URLs with the shared Azure login. The tools are offered to the
+/// model when the table is present.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AzureStorageConfig {
+    /// Storage account used when the model does not name one.
+    #[serde(default)]
+    pub account: Option<String>,
+
+    /// DNS suffix of blob endpoints. Override for sovereign clouds.
+    #[serde(default = "default_azure_storage_blob_endpoint_suffix")]
+    pub blob_endpoint_suffix: String,
+
+    /// Most containers or blobs one call returns.
+    #[serde(default = "default_azure_storage_max_results")]
+    pub max_results: usize,
+
+    /// Largest part of a blob `azure_storage_read_blob` returns, in bytes.
+    #[serde(default = "default_azure_storage_max_read_bytes")]
+    pub max_read_bytes: u64,
+
+    /// Largest file `azure_storage_upload_blob` uploads, in bytes.
+    #[serde(default = "default_azure_storage_max_upload_bytes")]
+    pub max_upload_bytes: u64,
+
+    /// Longest validity of a SAS URL, in minutes.
+    #[serde(default = "default_azure_storage_max_sas_expiry_minutes")]
+    pub max_sas_expiry_minutes: i64,
+}
+
+fn default_azure_storage_blob_endpoint_suffix() -> String {
+    "blob.core.windows.net".to_string()
+}
+
+fn default_azure_storage_max_results() -> usize {
+    500
+}
+
+fn default_azure_storage_max_read_bytes() -> u64 {
+    256 * 1024
+}
+
+fn default_azure_storage_max_upload_bytes() -> u64 {
+    256 * 1024 * 1024
+}
+
+fn default_azure_storage_max_sas_expiry_minutes() -> i64 {
+    24 * 60
+}
+
+/// The `[arm_deployments]` table: tools that preview and deploy ARM and Bicep
+/// templates to resource groups and look into past deployments. The tools are
+/// offered to the model when the table is present.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ArmDeploymentsConfig {
+    /// Subscription deployed to when the model does not name one.
+    #[serde(default)]
+    pub subscription_id: Option<String>,
+
+    /// Azure Resource Manager endpoint. Override for sovereign clouds.
+    #[serde(default = "default_azure_resource_manager")]
+    pub resource_manager: String,
+
+    /// Bicep CLI used to build `.bicep` files into ARM templates.
+    #[serde(default = "default_arm_deployments_bicep_command")]
+    pub bicep_command: String,
+
+    /// How long a deployment or what-if operation is waited for, in seconds.
+    #[serde(default = "default_arm_deployments_deployment_timeout_secs")]
+    pub deployment_timeout_secs: u64,
+
+    /// Most deployments `arm_list_deployments` returns.
+    #[serde(default = "default_arm_deployments_max_results")]
+    pub max_results: usize,
+}
+
+fn default_arm_deployments_bicep_command() -> String {
+    "bicep".to_string()
+}
+
+fn default_arm_deployments_deployment_timeout_secs() -> u64 {
+    30 * 60
+}
+
+fn default_arm_deployments_max_results() -> usize {
+    50
+}
+
+/// Ticketing system behind the `[ticketing]` tools.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TicketingBackend {
+    Jira,
+    ServiceNow,
+}
+
+/// The `[ticketing]` table: tools that search, read, create and comment on
+/// Jira issues or ServiceNow records. The tools are offered to the model when
+/// the table is present.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct TicketingConfig {
+    pub backend: TicketingBackend,
+
+    /// Root URL of the instance, e.g. `https://contoso.atlassian.net` or
+    /// `https://contoso.service-now.com`.
+    pub base_url: String,
+
+    /// User for basic auth, with the token as the password. Without it the
+    /// token is sent as a bearer token, e.g. a Jira personal access token.
+    #[serde(default)]
+    pub username: Option<String>,
+
+    /// Environment variable holding the API token or password.
+    #[serde(default = "default_ticketing_token_env_var")]
+    pub token_env_var: String,
+
+    /// Jira project that tickets are created in when the model does not name
+    /// one.
+    #[serde(default)]
+    pub project: Option<String>,
+
+    /// Jira issue type of created tickets.
+    #[serde(default = "default_ticketing_issue_type")]
+    pub issue_type: String,
+
+    /// ServiceNow table that tickets live in.
+    #[serde(default = "default_ticketing_table")]
+    pub table: String,
+
+    /// Most tickets `ticket_search` returns.
+    #[serde(default = "default_ticketing_max_results")]
+    pub max_results: usize,
+}
+
+fn default_ticketing_token_env_var() -> String {
+    "CODEX_TICKETING_TOKEN".to_string()
+}
+
+fn default_ticketing_issue_type() -> String {
+    "Task".to_string()
+}
+
+fn default_ticketing_table() -> String {
+    "incident".to_string()
+}
+
+fn default_ticketing_max_results() -> usize {
+    20
+}
+
+/// Chat platform of a `[messaging.channels.<name>]` entry.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MessagingPlatform {
+    Teams,
+    Slack,
+}
+
+/// The `[messaging]` table: channels the `post_message` tool posts to. The
+/// tool is offered to the model when at least one channel is configured.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct MessagingConfig {
+    /// Channels by the name the model uses for them.
+    #[serde(default)]
+    pub channels: HashMap<String, MessagingChannelConfig>,
+
+    /// Slack Web API endpoint used for channels with a `slack_channel`.
+    #[serde(default = "default_messaging_slack_api_url")]
+    pub slack_api_url: String,
+
+    /// Longest message the model may post, in characters.
+    #[serde(default = "default_messaging_max_message_chars")]
+    pub max_message_chars: usize,
+}
+
+/// A `[messaging.channels.<name>]` entry. Messages go to the webhook when one
+/// is set; Slack channels can instead name a channel for `chat.postMessage`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct MessagingChannelConfig {
+    pub platform: MessagingPlatform,
+
+    /// Teams Workflows webhook or Slack incoming webhook URL.
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+
+    /// Environment variable holding the webhook URL, to keep it out of the
+    /// config file.
+    #[serde(default)]
+    pub webhook_url_env_var: Option<String>,
+
+    /// Slack channel ID or name, posted to with a bot token.
+    #[serde(default)]
+    pub slack_channel: Option<String>,
+
+    /// Environment variable holding the Slack bot token.
+    #[serde(default = "default_messaging_slack_token_env_var")]
+    pub slack_token_env_var: String,
+
+    /// What the channel is for, shown to the model.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+fn default_messaging_slack_api_url() -> String {
+    "https://slack.com/api".to_string()
+}
+
+fn default_messaging_max_message_chars() -> usize {
+    20_000
+}
+
+fn default_messaging_slack_token_env_var() -> String {
+    "SLACK_BOT_TOKEN".to_string()
+}
+
+/// How `sqlcmd` signs in to a `[sql.profiles.<name>]` server.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SqlAuthentication {
+    /// SQL login: `user` with the password in `password_env_var`.
+    Sql,
+    /// Windows integrated authentication.
+    Integrated,
+    /// Microsoft Entra ID through the default credential chain (Azure CLI
+    /// login, managed identity, environment). Needs the go-based `sqlcmd`.
+    #[default]
+    AzureAd,
+}
+
+/// The `[sql]` table: read-only tools for SQL Server and Azure SQL databases.
+/// The tools are offered to the model when at least one profile is
+/// configured.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SqlConfig {
+    /// Connection profiles by the name the model uses for them.
+    #[serde(default)]
+    pub profiles: HashMap<String, SqlProfileConfig>,
+
+    /// `sqlcmd` executable that runs the queries.
+    #[serde(default = "default_sql_sqlcmd_command")]
+    pub sqlcmd_command: String,
+
+    /// Most rows a query returns.
+    #[serde(default = "default_sql_max_rows")]
+    pub max_rows: usize,
+
+    /// How long a query may run, in seconds.
+    #[serde(default = "default_sql_query_timeout_secs")]
+    pub query_timeout_secs: u64,
+}
+
+/// A `[sql.profiles.<name>]` entry: a database and how to reach it.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SqlProfileConfig {
+    /// Server as `sqlcmd -S` takes it, e.g.
+    /// `tcp:contoso.database.windows.net,1433`.
+    pub server: String,
+
+    pub database: String,
+
+    #[serde(default)]
+    pub authentication: SqlAuthentication,
+
+    /// Login for SQL authentication.
+    #[serde(default)]
+    pub user: Option<String>,
+
+    /// Environment variable holding the password for SQL authentication.
+    #[serde(default = "default_sql_password_env_var")]
+    pub password_env_var: String,
+
+    /// Accept the server certificate without validating it.
+    #[serde(default)]
+    pub trust_server_certificate: bool,
+
+    /// What the database holds, shown to the model.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+fn default_sql_sqlcmd_command() -> String {
+    "sqlcmd".to_string()
+}
+
+fn default_sql_max_rows() -> usize {
+    100
+}
+
+fn default_sql_query_timeout_secs() -> u64 {
+    30
+}
+
+fn default_sql_password_env_var() -> String {
+    "CODEX_SQL_PASSWORD".to_string()
+}
+
+/// The `[kubernetes]` table: read-only tools that inspect clusters through
+/// `kubectl`. The tools are offered to the model when at least one context is
+/// configured.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct KubernetesConfig {
+    /// `kubectl` executable that runs the commands.
+    #[serde(default = "default_kubernetes_kubectl_command")]
+    pub kubectl_command: String,
+
+    /// Kubeconfig file; defaults to what `kubectl` uses (`KUBECONFIG` or
+    /// `~/.kube/config`).
+    #[serde(default)]
+    pub kubeconfig: Option<PathBuf>,
+
+    /// Kubeconfig contexts the tools may use. The first is the default.
+    #[serde(default)]
+    pub contexts: Vec<String>,
+
+    /// Namespace used when a call names none.
+    #[serde(default)]
+    pub default_namespace: Option<String>,
+
+    /// Most log lines `k8s_logs` returns.
+    #[serde(default = "default_kubernetes_max_log_lines")]
+    pub max_log_lines: usize,
+
+    /// Most bytes of output a call returns; longer output keeps its end.
+    #[serde(default = "default_kubernetes_max_output_bytes")]
+    pub max_output_bytes: usize,
+
+    /// Request timeout passed to `kubectl`, in seconds.
+    #[serde(default = "default_kubernetes_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_kubernetes_kubectl_command() -> String {
+    "kubectl".to_string()
+}
+
+fn default_kubernetes_max_log_lines() -> usize {
+    500
+}
+
+fn default_kubernetes_max_output_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_kubernetes_timeout_secs() -> u64 {
+    30
+}
+
+/// The `[scheduler]` table: tasks that `codex scheduler` runs headlessly on a
+/// cron schedule.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SchedulerConfig {
+    /// Tasks by name.
+    #[serde(default)]
+    pub tasks: HashMap<String, ScheduledTaskConfig>,
+
+    /// `[messaging]` channel told about failed runs of tasks that name no
+    /// channel of their own.
+    #[serde(default)]
+    pub notify_channel: Option<String>,
+
+    /// How long a run may take, in seconds, unless the task says otherwise.
+    #[serde(default = "default_scheduler_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// Most runs kept in the run history.
+    #[serde(default = "default_scheduler_max_history")]
+    pub max_history: usize,
+}
+
+/// A `[scheduler.tasks.<name>]` entry: what to run, when and with which
+/// settings. A task has either a `prompt` or a `pipeline`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduledTaskConfig {
+    /// Cron expression in local time: minute, hour, day of month, month and
+    /// day of week, e.g. `30 6 * * mon-fri`; or `@hourly`, `@daily`,
+    /// `@weekly`, `@monthly` or `@yearly`.
+    pub schedule: String,
+
+    /// Prompt sent to the model.
+    #[serde(default)]
+    pub prompt: Option<String>,
+
+    /// Tool calls run one after the other without the model.
+    #[serde(default)]
+    pub pipeline: Vec<ToolPipelineStep>,
+
+    /// Workflow from `$CODEX_HOME/workflows` that is run.
+    #[serde(default)]
+    pub workflow: Option<String>,
+
+    /// Inputs of the workflow.
+    #[serde(default)]
+    pub inputs: HashMap<String, String>,
+
+    /// `[profiles]` entry the run is configured with.
+    #[serde(default)]
+    pub profile: Option<String>,
+
+    /// Working directory of the run.
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+
+    /// `[messaging]` channel the output of successful runs is posted to.
+    #[serde(default)]
+    pub report_channel: Option<String>,
+
+    /// `[messaging]` channel told about failed runs; defaults to the
+    /// scheduler's `notify_channel`.
+    #[serde(default)]
+    pub notify_channel: Option<String>,
+
+    /// Approve the approval requests of a run instead of denying them. The
+    /// profile's approval policy must then allow requests, e.g.
+    /// `on-request`.
+    #[serde(default)]
+    pub auto_approve: bool,
+
+    /// How long a run may take, in seconds.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+
+    /// Keep the task configured without running it on its schedule.
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+fn default_scheduler_timeout_secs() -> u64 {
+    3600
+}
+
+fn default_scheduler_max_history() -> usize {
+    1000
 }
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use codex_protocol::models::ResponseItem;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::conversation_history::rewind_turns;
+
+/// Branch change recorded in the rollout. Replaying these records in order
+/// over the recorded items reconstructs every branch, so both the abandoned
+/// and the current branch survive in the rollout.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub(crate) enum BranchRecord {
+    /// The last `num_turns` turns were dropped and later items belong to the
+    /// new branch `branch_id`.
+    Rewind { branch_id: u32, num_turns: usize },
+    /// Later items continue the existing branch `branch_id`.
+    Switch { branch_id: u32 },
+}
+
+/// Tracks the branches of a conversation created by rewinding it. The
+/// history of the current branch lives in the caller; this keeps the items
+/// of the branches that were left behind.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConversationBranches {
+    current: u32,
+    last_id: u32,
+    saved: HashMap<u32, Vec<ResponseItem>>,
+}
+
+impl ConversationBranches {
+    pub(crate) fn current(&self) -> u32 {
+        self.current
+    }
+
+    /// Drop the last `num_turns` turns from `items` and continue on a new
+    /// branch, keeping the previous contents of `items` under the branch
+    /// being left. Returns the new branch id, or `None` when `items` has
+    /// fewer turns than requested.
+    pub(crate) fn rewind(
+        &mut self,
+        items: &mut Vec<ResponseItem>,
+        num_turns: usize,
+    ) -> Option<u32> {
+        let previous = items.clone();
+        if !rewind_turns(items, num_turns) {
+            return None;
+        }
+        self.saved.insert(self.current, previous);
+        self.last_id += 1;
+        self.current = self.last_id;
+        Some(self.current)
+    }
+
+    /// Continue on `branch_id`, swapping its saved items into `items`.
+    /// Returns `false` when no such branch exists.
+    pub(crate) fn switch(&mut self, items: &mut Vec<ResponseItem>, branch_id: u32) -> bool {
+        if branch_id == self.current {
+            return true;
+        }
+        let Some(restored) = self.saved.remove(&branch_id) else {
+            return false;
+        };
+        self.saved
+            .insert(self.current, std::mem::replace(items, restored));
+        self.current = branch_id;
+        true
+    }
+
+    /// Apply a [`BranchRecord`] read back from a rollout.
+    pub(crate) fn replay(&mut self, items: &mut Vec<ResponseItem>, record: &BranchRecord) -> bool {
+        match record {
+            BranchRecord::Rewind { num_turns, .. } => self.rewind(items, *num_turns).is_some(),
+            BranchRecord::Switch { branch_id } => self.switch(items, *branch_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codex_protocol::models::ContentItem;
+    use pretty_assertions::assert_eq;
+
+    fn msg(role: &str, text: &str) -> ResponseItem {
+        ResponseItem::Message {
+            id: None,
+            role: role.to_string(),
+            content: vec![ContentItem::InputText {
+                text: text.to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn rewind_forks_and_switch_restores() {
+        let mut branches = ConversationBranches::default();
+        let original = vec![
+            msg("user", "<environment_context>\n</environment_context>"),
+            msg("user", "u1"),
+            msg("assistant", "a1"),
+            msg("user", "u2"),
+            msg("assistant", "a2"),
+        ];
+        let mut items = original.clone();
+
+        assert_eq!(branches.rewind(&mut items, 3), None);
+        assert_eq!(items, original);
+
+        assert_eq!(branches.rewind(&mut items, 1), Some(1));
+        assert_eq!(items, original[..3].to_vec());
+
+        items.push(msg("user", "u2 alternative"));
+        let alternative = items.clone();
+
+        assert!(branches.switch(&mut items, 0));
+        assert_eq!(items, original);
+        assert!(!branches.switch(&mut items, 7));
+
+        // Replaying the records over the same items reaches the same state.
+        let mut replayed_branches = ConversationBranches::default();
+        let mut replayed = original.clone();
+        assert!(replayed_branches.replay(
+            &mut replayed,
+            &BranchRecord::Rewind {
+                branch_id: 1,
+                num_turns: 1
+            }
+        ));
+        replayed.push(msg("user", "u2 alternative"));
+        assert_eq!(replayed, alternative);
+        assert!(replayed_branches.replay(&mut replayed, &BranchRecord::Switch { branch_id: 0 }));
+        assert_eq!(replayed, original);
+        assert_eq!(replayed_branches.current(), 0);
+    }
+}
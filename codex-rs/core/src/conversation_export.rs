@@ -0,0 +1,516 @@
+//! Transcripts of a conversation, rendered for people rather than for the
+//! model.
+//!
+//! Markdown and HTML transcripts show the messages, the commands Codex ran
+//! and the tools it called, with tool outputs collapsed in `<details>` blocks
+//! and code blocks tagged with their language. Context Codex adds on its own
+//! (`AGENTS.md` instructions, the environment context and snapshot, the
+//! summary of the files changed in a turn) is left out. The JSON
+//! transcript is the conversation as sent to the model, unchanged.
+
+use std::path::Path;
+
+use codex_protocol::models::ContentItem;
+use codex_protocol::models::FunctionCallOutputPayload;
+use codex_protocol::models::LocalShellAction;
+use codex_protocol::models::ReasoningItemReasoningSummary;
+use codex_protocol::models::ResponseItem;
+use codex_protocol::models::ShellToolCallParams;
+use codex_protocol::models::WebSearchAction;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::client_common::USER_INSTRUCTIONS_START;
+use crate::environment_context::ENVIRONMENT_CONTEXT_START;
+use crate::environment_snapshot::ENVIRONMENT_SNAPSHOT_START;
+use crate::project_doc::SCOPED_INSTRUCTIONS_START;
+use crate::protocol::ExportFormat;
+use crate::rollout::read_saved_session;
+use crate::turn_diff_tracker::TURN_CHANGES_START;
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:960px;margin:2em auto;padding:0 1em;line-height:1.5;color:#1f2328}\
+h2{font-size:1.1em;margin-top:2em;border-bottom:1px solid #d0d7de}\
+pre{background:#f6f8fa;padding:.75em;overflow-x:auto;border-radius:6px}\
+details{margin:.5em 0}summary{cursor:pointer;color:#59636e}\
+.tool-name{font-weight:600}\
+.failed summary{color:#d1242f}";
+
+/// Renders `items`, the history of conversation `conversation_id`, in
+/// `format`.
+pub fn export_conversation(
+    conversation_id: Uuid,
+    items: &[ResponseItem],
+    format: ExportFormat,
+) -> String {
+    match format {
+        ExportFormat::Markdown => render_markdown(conversation_id, &entries(items)),
+        ExportFormat::Html => render_html(conversation_id, &entries(items)),
+        ExportFormat::Json => {
+            let transcript = json!({
+                "conversation_id": conversation_id,
+                "items": items,
+            });
+            // Serializing `ResponseItem`s cannot fail.
+            serde_json::to_string_pretty(&transcript).unwrap_or_default()
+        }
+    }
+}
+
+/// Renders the conversation recorded in the rollout file at `path`.
+pub async fn export_rollout(path: &Path, format: ExportFormat) -> std::io::Result<String> {
+    let session = read_saved_session(path).await?;
+    Ok(export_conversation(
+        session.session_id,
+        &session.items,
+        format,
+    ))
+}
+
+/// One block of a transcript.
+#[derive(Debug, PartialEq)]
+enum Entry {
+    UserMessage(String),
+    AssistantMessage(String),
+    Reasoning(String),
+    /// A command Codex ran or a tool it called, shown as a code block.
+    ToolCall {
+        title: String,
+        language: &'static str,
+        code: String,
+    },
+    ToolOutput {
+        output: String,
+        failed: bool,
+    },
+    WebSearch(String),
+}
+
+impl Entry {
+    fn is_user(&self) -> bool {
+        matches!(self, Entry::UserMessage(_))
+    }
+}
+
+fn entries(items: &[ResponseItem]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for item in items {
+        match item {
+            ResponseItem::Message { role, content, .. } => {
+                let text = content
+                    .iter()
+                    .map(|c| match c {
+                        ContentItem::InputText { text } | ContentItem::OutputText { text } => {
+                            text.clone()
+                        }
+                        // Images are usually inlined as data URLs, which
+                        // would only bloat the transcript.
+                        ContentItem::InputImage { .. } => "*[image]*".to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                if text.trim().is_empty() {
+                    continue;
+                }
+                if role == "user" {
+                    if !is_context_message(&text) {
+                        entries.push(Entry::UserMessage(text));
+                    }
+                } else {
+                    entries.push(Entry::AssistantMessage(text));
+                }
+            }
+            ResponseItem::Reasoning { summary, .. } => {
+                let text = summary
+                    .iter()
+                    .map(|ReasoningItemReasoningSummary::SummaryText { text }| text.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                if !text.trim().is_empty() {
+                    entries.push(Entry::Reasoning(text));
+                }
+            }
+            ResponseItem::LocalShellCall {
+                action: LocalShellAction::Exec(action),
+                ..
+            } => entries.push(command_entry(&action.command)),
+            ResponseItem::FunctionCall {
+                name, arguments, ..
+            } => entries.push(function_call_entry(name, arguments)),
+            ResponseItem::CustomToolCall { name, input, .. } => {
+                entries.push(Entry::ToolCall {
+                    title: name.clone(),
+                    language: if name == "apply_patch" {
+                        "diff"
+                    } else {
+                        "text"
+                    },
+                    code: input.clone(),
+                });
+            }
+            ResponseItem::FunctionCallOutput {
+                output: FunctionCallOutputPayload { content, success },
+                ..
+            } => entries.push(Entry::ToolOutput {
+                output: content.clone(),
+                failed: *success == Some(false),
+            }),
+            ResponseItem::CustomToolCallOutput { output, .. } => entries.push(Entry::ToolOutput {
+                output: output.clone(),
+                failed: false,
+            }),
+            ResponseItem::WebSearchCall {
+                action: WebSearchAction::Search { query },
+                ..
+            } => entries.push(Entry::WebSearch(query.clone())),
+            ResponseItem::WebSearchCall { .. } | ResponseItem::Other => {}
+        }
+    }
+    entries
+}
+
+/// Whether `text` is context Codex added to the conversation rather than
+/// something the user typed.
+fn is_context_message(text: &str) -> bool {
+    text.starts_with(USER_INSTRUCTIONS_START)
+        || text.starts_with(ENVIRONMENT_CONTEXT_START)
+        || text.starts_with(ENVIRONMENT_SNAPSHOT_START)
+        || text.starts_with(SCOPED_INSTRUCTIONS_START)
+        || text.starts_with(TURN_CHANGES_START)
+}
+
+fn command_entry(command: &[String]) -> Entry {
+    let code = match command {
+        [bash, flag, script] if bash == "bash" && flag == "-lc" => script.clone(),
+        _ => shlex::try_join(command.iter().map(String::as_str))
+            .unwrap_or_else(|_| command.join(" ")),
+    };
+    Entry::ToolCall {
+        title: "shell".to_string(),
+        language: "sh",
+        code,
+    }
+}
+
+fn function_call_entry(name: &str, arguments: &str) -> Entry {
+    if matches!(name, "shell" | "container.exec")
+        && let Ok(params) = serde_json::from_str::<ShellToolCallParams>(arguments)
+    {
+        return command_entry(&params.command);
+    }
+    let value = serde_json::from_str::<serde_json::Value>(arguments).ok();
+    if name == "apply_patch"
+        && let Some(input) = value.as_ref().and_then(|v| v["input"].as_str())
+    {
+        return Entry::ToolCall {
+            title: name.to_string(),
+            language: "diff",
+            code: input.to_string(),
+        };
+    }
+    Entry::ToolCall {
+        title: name.to_string(),
+        language: "json",
+        code: value
+            .and_then(|v| serde_json::to_string_pretty(&v).ok())
+            .unwrap_or_else(|| arguments.to_string()),
+    }
+}
+
+fn output_summary(output: &str, failed: bool) -> String {
+    let lines = output.lines().count();
+    let noun = if lines == 1 { "line" } else { "lines" };
+    if failed {
+        format!("Output, failed ({lines} {noun})")
+    } else {
+        format!("Output ({lines} {noun})")
+    }
+}
+
+fn render_markdown(conversation_id: Uuid, entries: &[Entry]) -> String {
+    let mut out = format!("# Codex transcript\n\nConversation `{conversation_id}`\n");
+    let mut speaker = None;
+    for entry in entries {
+        let is_user = entry.is_user();
+        if speaker != Some(is_user) {
+            out.push_str(if is_user {
+                "\n## User\n"
+            } else {
+                "\n## Codex\n"
+            });
+            speaker = Some(is_user);
+        }
+        out.push('\n');
+        match entry {
+            Entry::UserMessage(text) | Entry::AssistantMessage(text) => {
+                out.push_str(text.trim_end());
+                out.push('\n');
+            }
+            Entry::Reasoning(text) => {
+                out.push_str(&format!(
+                    "<details>\n<summary>Reasoning</summary>\n\n{}\n\n</details>\n",
+                    text.trim_end()
+                ));
+            }
+            Entry::ToolCall {
+                title,
+                language,
+                code,
+            } => {
+                out.push_str(&format!("**{title}**\n\n"));
+                out.push_str(&markdown_code_block(language, code));
+            }
+            Entry::ToolOutput { output, failed } => {
+                out.push_str(&format!(
+                    "<details>\n<summary>{}</summary>\n\n{}\n</details>\n",
+                    output_summary(output, *failed),
+                    markdown_code_block("text", output)
+                ));
+            }
+            Entry::WebSearch(query) => {
+                out.push_str(&format!("*Searched the web for* `{query}`\n"));
+            }
+        }
+    }
+    out
+}
+
+/// Fences `code`, using more backticks than any run inside it.
+fn markdown_code_block(language: &str, code: &str) -> String {
+    let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!(
+        "{fence}{language}\n{}\n{fence}\n",
+        code.trim_end_matches('\n')
+    )
+}
+
+fn render_html(conversation_id: Uuid, entries: &[Entry]) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Codex transcript {conversation_id}</title>\n<style>{HTML_STYLE}</style>\n</head>\n<body>\n<h1>Codex transcript</h1>\n<p>Conversation <code>{conversation_id}</code></p>\n"
+    );
+    let mut speaker = None;
+    for entry in entries {
+        let is_user = entry.is_user();
+        if speaker != Some(is_user) {
+            out.push_str(if is_user {
+                "<h2>User</h2>\n"
+            } else {
+                "<h2>Codex</h2>\n"
+            });
+            speaker = Some(is_user);
+        }
+        match entry {
+            Entry::UserMessage(text) | Entry::AssistantMessage(text) => {
+                out.push_str(&html_text(text));
+            }
+            Entry::Reasoning(text) => {
+                out.push_str(&format!(
+                    "<details>\n<summary>Reasoning</summary>\n{}</details>\n",
+                    html_text(text)
+                ));
+            }
+            Entry::ToolCall {
+                title,
+                language,
+                code,
+            } => {
+                out.push_str(&format!(
+                    "<p class=\"tool-name\">{}</p>\n{}",
+                    escape_html(title),
+                    html_code_block(language, code)
+                ));
+            }
+            Entry::ToolOutput { output, failed } => {
+                out.push_str(&format!(
+                    "<details{}>\n<summary>{}</summary>\n{}</details>\n",
+                    if *failed { " class=\"failed\"" } else { "" },
+                    output_summary(output, *failed),
+                    html_code_block("text", output)
+                ));
+            }
+            Entry::WebSearch(query) => {
+                out.push_str(&format!(
+                    "<p><em>Searched the web for</em> <code>{}</code></p>\n",
+                    escape_html(query)
+                ));
+            }
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Renders message text as paragraphs and code blocks. Fenced code blocks
+/// keep their language as a `language-*` class, which highlighters such as
+/// highlight.js and Prism pick up; everything else is shown as written.
+fn html_text(text: &str) -> String {
+    let mut out = String::new();
+    let mut paragraph = Vec::new();
+    let mut code: Option<(String, Vec<&str>)> = None;
+    let flush_paragraph = |out: &mut String, paragraph: &mut Vec<&str>| {
+        if !paragraph.is_empty() {
+            out.push_str(&format!("<p>{}</p>\n", escape_html(&paragraph.join("\n"))));
+            paragraph.clear();
+        }
+    };
+    for line in text.lines() {
+        let fence = line.trim_start().strip_prefix("```");
+        match (&mut code, fence) {
+            (Some((language, lines)), Some(_)) => {
+                out.push_str(&html_code_block(language, &lines.join("\n")));
+                code = None;
+            }
+            (Some((_, lines)), None) => lines.push(line),
+            (None, Some(language)) => {
+                flush_paragraph(&mut out, &mut paragraph);
+                code = Some((language.trim().to_string(), Vec::new()));
+            }
+            (None, None) if line.trim().is_empty() => flush_paragraph(&mut out, &mut paragraph),
+            (None, None) => paragraph.push(line),
+        }
+    }
+    // An unterminated code block runs to the end of the message.
+    if let Some((language, lines)) = code {
+        out.push_str(&html_code_block(&language, &lines.join("\n")));
+    }
+    flush_paragraph(&mut out, &mut paragraph);
+    out
+}
+
+fn html_code_block(language: &str, code: &str) -> String {
+    let class = if language.is_empty() {
+        String::new()
+    } else {
+        format!(" class=\"language-{}\"", escape_html(language))
+    };
+    format!(
+        "<pre><code{class}>{}</code></pre>\n",
+        escape_html(code.trim_end_matches('\n'))
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn message(role: &str, text: &str) -> ResponseItem {
+        let content = if role == "user" {
+            ContentItem::InputText {
+                text: text.to_string(),
+            }
+        } else {
+            ContentItem::OutputText {
+                text: text.to_string(),
+            }
+        };
+        ResponseItem::Message {
+            id: None,
+            role: role.to_string(),
+            content: vec![content],
+        }
+    }
+
+    fn history() -> Vec<ResponseItem> {
+        vec![
+            message("user", "<environment_context>\n</environment_context>"),
+            message("user", "What is in <src>?"),
+            ResponseItem::FunctionCall {
+                id: None,
+                name: "shell".to_string(),
+                arguments: r#"{"command":["bash","-lc","ls src"]}"#.to_string(),
+                call_id: "1".to_string(),
+            },
+            ResponseItem::FunctionCallOutput {
+                call_id: "1".to_string(),
+                output: FunctionCallOutputPayload {
+                    content: "lib.rs\nmain.rs\n```".to_string(),
+                    success: None,
+                },
+            },
+            message("assistant", "Two files:\n\n```rust\nfn main() {}\n```"),
+        ]
+    }
+
+    #[test]
+    fn markdown_collapses_tool_output_and_skips_context() {
+        let markdown = export_conversation(Uuid::nil(), &history(), ExportFormat::Markdown);
+
+        assert_eq!(
+            markdown,
+            r#"# Codex transcript
+
+Conversation `00000000-0000-0000-0000-000000000000`
+
+## User
+
+What is in <src>?
+
+## Codex
+
+**shell**
+
+```sh
+ls src
+```
+
+<details>
+<summary>Output (3 lines)</summary>
+
+````text
+lib.rs
+main.rs
+```
+````
+
+</details>
+
+Two files:
+
+```rust
+fn main() {}
+```
+"#
+        );
+    }
+
+    #[test]
+    fn html_escapes_text_and_tags_code_languages() {
+        let html = export_conversation(Uuid::nil(), &history(), ExportFormat::Html);
+
+        assert!(html.contains("<h2>User</h2>\n<p>What is in &lt;src&gt;?</p>\n<h2>Codex</h2>\n"));
+        assert!(html.contains("<pre><code class=\"language-sh\">ls src</code></pre>\n"));
+        assert!(html.contains(
+            "<details>\n<summary>Output (3 lines)</summary>\n<pre><code class=\"language-text\">lib.rs\nmain.rs\n```</code></pre>\n</details>\n"
+        ));
+        assert!(html.contains(
+            "<p>Two files:</p>\n<pre><code class=\"language-rust\">fn main() {}</code></pre>\n"
+        ));
+        assert!(!html.contains("environment_context"));
+    }
+
+    #[test]
+    fn json_keeps_every_item() {
+        let items = history();
+        let json = export_conversation(Uuid::nil(), &items, ExportFormat::Json);
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let parsed: Vec<ResponseItem> = serde_json::from_value(value["items"].clone()).unwrap();
+        assert_eq!(parsed, items);
+    }
+}
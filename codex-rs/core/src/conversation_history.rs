@@ -1,5 +1,19 @@
+use std::collections::HashSet;
+
+use codex_protocol::models::ContentItem;
+use codex_protocol::models::ReasoningItemContent;
+use codex_protocol::models::ReasoningItemReasoningSummary;
 use codex_protocol::models::ResponseItem;
 
+use crate::client_common::USER_INSTRUCTIONS_START;
+use crate::environment_context::ENVIRONMENT_CONTEXT_START;
+use crate::environment_snapshot::ENVIRONMENT_SNAPSHOT_START;
+use crate::project_doc::SCOPED_INSTRUCTIONS_START;
+use crate::turn_diff_tracker::TURN_CHANGES_START;
+
+/// Prefix of the message that replaces compacted turns.
+pub(crate) const COMPACTED_HISTORY_PREFIX: &str = "Summary of the earlier part of this conversation, which was compacted to fit the context window:";
+
 /// Transcript of conversation history
 #[derive(Debug, Clone, Default)]
 pub(crate) struct ConversationHistory {
@@ -59,6 +73,247 @@ impl ConversationHistory {
         kept.reverse();
         self.items = kept;
     }
+
+    /// Rough token count of the transcript (about four bytes per token),
+    /// used to decide when to compact before the context window fills up.
+    pub(crate) fn estimated_tokens(&self) -> u64 {
+        self.items.iter().map(estimate_item_tokens).sum()
+    }
+
+    /// Index that splits the history into older items to summarize and
+    /// recent items to keep verbatim. The split always falls on the start of
+    /// a user turn so every tool call stays next to its output, keeps at
+    /// least the latest turn, and otherwise keeps as many turns as fit in
+    /// `keep_recent_tokens`. Returns `None` when there is nothing older to
+    /// summarize.
+    pub(crate) fn compaction_split(&self, keep_recent_tokens: u64) -> Option<usize> {
+        let mut recent_tokens = 0;
+        let mut split = None;
+        for (idx, item) in self.items.iter().enumerate().rev() {
+            recent_tokens += estimate_item_tokens(item);
+            if idx == 0 || !is_turn_start(item) || self.splits_tool_call(idx) {
+                continue;
+            }
+            if split.is_some() && recent_tokens > keep_recent_tokens {
+                break;
+            }
+            split = Some(idx);
+        }
+        split.filter(|&idx| {
+            self.items[..idx]
+                .iter()
+                .any(|item| !is_session_context(item))
+        })
+    }
+
+    /// Items before `split` to hand to the summarizer, with session context
+    /// (user instructions, environment) left out and tool outputs longer
+    /// than `max_tool_output_bytes` shortened.
+    pub(crate) fn items_to_compact(
+        &self,
+        split: usize,
+        max_tool_output_bytes: usize,
+    ) -> Vec<ResponseItem> {
+        self.items[..split.min(self.items.len())]
+            .iter()
+            .filter(|item| !is_session_context(item))
+            .map(|item| match item {
+                ResponseItem::FunctionCallOutput { call_id, output } => {
+                    let mut output = output.clone();
+                    output.content = truncate_middle(&output.content, max_tool_output_bytes);
+                    ResponseItem::FunctionCallOutput {
+                        call_id: call_id.clone(),
+                        output,
+                    }
+                }
+                ResponseItem::CustomToolCallOutput { call_id, output } => {
+                    ResponseItem::CustomToolCallOutput {
+                        call_id: call_id.clone(),
+                        output: truncate_middle(output, max_tool_output_bytes),
+                    }
+                }
+                other => other.clone(),
+            })
+            .collect()
+    }
+
+    /// Replace the items before `split` with a single message carrying
+    /// `summary`. Session context messages are kept ahead of it.
+    pub(crate) fn replace_with_summary(&mut self, split: usize, summary: &str) {
+        let split = split.min(self.items.len());
+        let recent = self.items.split_off(split);
+        self.items.retain(is_session_context);
+        self.items.push(ResponseItem::Message {
+            id: None,
+            role: "user".to_string(),
+            content: vec![ContentItem::InputText {
+                text: format!("{COMPACTED_HISTORY_PREFIX}\n\n{summary}"),
+            }],
+        });
+        self.items.extend(recent);
+    }
+
+    /// Replace the whole transcript, e.g. when switching conversation
+    /// branches.
+    pub(crate) fn replace(&mut self, items: Vec<ResponseItem>) {
+        self.items = items;
+    }
+
+    /// Number of user turns in the transcript.
+    pub(crate) fn turn_count(&self) -> usize {
+        count_turns(&self.items)
+    }
+
+    /// Whether splitting before `idx` would separate a tool call from its
+    /// output.
+    fn splits_tool_call(&self, idx: usize) -> bool {
+        let older_calls: HashSet<&str> = self.items[..idx].iter().filter_map(call_id).collect();
+        self.items[idx..]
+            .iter()
+            .filter_map(output_call_id)
+            .any(|id| older_calls.contains(id))
+    }
+}
+
+/// Number of user turns in `items`, not counting session context.
+pub(crate) fn count_turns(items: &[ResponseItem]) -> usize {
+    items.iter().filter(|item| is_turn_start(item)).count()
+}
+
+/// Drop the last `num_turns` user turns and everything after them from
+/// `items`. Session context ahead of the first turn is always kept. Returns
+/// `false`, leaving `items` untouched, when there are fewer turns than that.
+pub(crate) fn rewind_turns(items: &mut Vec<ResponseItem>, num_turns: usize) -> bool {
+    if num_turns == 0 {
+        return true;
+    }
+    let cut = items
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, item)| is_turn_start(item))
+        .nth(num_turns - 1)
+        .map(|(idx, _)| idx);
+    match cut {
+        Some(idx) => {
+            items.truncate(idx);
+            true
+        }
+        None => false,
+    }
+}
+
+fn call_id(item: &ResponseItem) -> Option<&str> {
+    match item {
+        ResponseItem::FunctionCall { call_id, .. }
+        | ResponseItem::CustomToolCall { call_id, .. } => Some(call_id),
+        ResponseItem::LocalShellCall { call_id, id, .. } => call_id.as_deref().or(id.as_deref()),
+        _ => None,
+    }
+}
+
+fn output_call_id(item: &ResponseItem) -> Option<&str> {
+    match item {
+        ResponseItem::FunctionCallOutput { call_id, .. }
+        | ResponseItem::CustomToolCallOutput { call_id, .. } => Some(call_id),
+        _ => None,
+    }
+}
+
+/// A user message that is neither session context nor a summary Codex adds
+/// at the end of a turn starts a new turn.
+pub(crate) fn is_turn_start(item: &ResponseItem) -> bool {
+    let ResponseItem::Message { role, content, .. } = item else {
+        return false;
+    };
+    role == "user"
+        && !is_session_context(item)
+        && !content.iter().any(|c| {
+            matches!(c, ContentItem::InputText { text } if text.starts_with(TURN_CHANGES_START))
+        })
+}
+
+/// User instructions, nested `AGENTS.md` instructions, environment context
+/// and environment snapshot messages, which must survive compaction verbatim.
+fn is_session_context(item: &ResponseItem) -> bool {
+    let ResponseItem::Message { role, content, .. } = item else {
+        return false;
+    };
+    role == "user"
+        && content.iter().any(|c| match c {
+            ContentItem::InputText { text } => {
+                text.starts_with(USER_INSTRUCTIONS_START.trim_end())
+                    || text.starts_with(ENVIRONMENT_CONTEXT_START)
+                    || text.starts_with(ENVIRONMENT_SNAPSHOT_START)
+                    || text.starts_with(SCOPED_INSTRUCTIONS_START)
+            }
+            _ => false,
+        })
+}
+
+fn estimate_item_tokens(item: &ResponseItem) -> u64 {
+    let bytes = match item {
+        ResponseItem::Message { content, .. } => content
+            .iter()
+            .map(|c| match c {
+                ContentItem::InputText { text } | ContentItem::OutputText { text } => text.len(),
+                ContentItem::InputImage { image_url } => image_url.len(),
+            })
+            .sum(),
+        ResponseItem::Reasoning {
+            summary,
+            content,
+            encrypted_content,
+            ..
+        } => {
+            summary
+                .iter()
+                .map(|ReasoningItemReasoningSummary::SummaryText { text }| text.len())
+                .sum::<usize>()
+                + content
+                    .iter()
+                    .flatten()
+                    .map(
+                        |(ReasoningItemContent::ReasoningText { text }
+                         | ReasoningItemContent::Text { text })| text.len(),
+                    )
+                    .sum::<usize>()
+                + encrypted_content.as_ref().map_or(0, String::len)
+        }
+        ResponseItem::FunctionCall {
+            name, arguments, ..
+        } => name.len() + arguments.len(),
+        ResponseItem::FunctionCallOutput { output, .. } => output.content.len(),
+        ResponseItem::CustomToolCall { name, input, .. } => name.len() + input.len(),
+        ResponseItem::CustomToolCallOutput { output, .. } => output.len(),
+        ResponseItem::LocalShellCall { action, .. } => {
+            serde_json::to_string(action).map_or(0, |s| s.len())
+        }
+        ResponseItem::WebSearchCall { .. } | ResponseItem::Other => 0,
+    };
+    (bytes as u64).div_ceil(4)
+}
+
+/// Keep the head and tail of `text` within `max_bytes`, noting how much was
+/// dropped in between.
+fn truncate_middle(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    let mut head_end = max_bytes / 2;
+    while !text.is_char_boundary(head_end) {
+        head_end -= 1;
+    }
+    let mut tail_start = text.len() - max_bytes / 2;
+    while !text.is_char_boundary(tail_start) {
+        tail_start += 1;
+    }
+    let omitted = tail_start - head_end;
+    format!(
+        "{}\n[... {omitted} bytes omitted ...]\n{}",
+        &text[..head_end],
+        &text[tail_start..]
+    )
 }
 
 /// Anything that is not a system message or "reasoning" message is considered
@@ -79,7 +334,8 @@ fn is_api_message(message: &ResponseItem) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use codex_protocol::models::ContentItem;
+    use codex_protocol::models::FunctionCallOutputPayload;
+    use pretty_assertions::assert_eq;
 
     fn assistant_msg(text: &str) -> ResponseItem {
         ResponseItem::Message {
@@ -140,4 +396,128 @@ mod tests {
             ]
         );
     }
+
+    fn user_input(text: &str) -> ResponseItem {
+        ResponseItem::Message {
+            id: None,
+            role: "user".to_string(),
+            content: vec![ContentItem::InputText {
+                text: text.to_string(),
+            }],
+        }
+    }
+
+    fn call(call_id: &str) -> ResponseItem {
+        ResponseItem::FunctionCall {
+            id: None,
+            name: "shell".to_string(),
+            arguments: "{}".to_string(),
+            call_id: call_id.to_string(),
+        }
+    }
+
+    fn output(call_id: &str, content: &str) -> ResponseItem {
+        ResponseItem::FunctionCallOutput {
+            call_id: call_id.to_string(),
+            output: FunctionCallOutputPayload {
+                content: content.to_string(),
+                success: Some(true),
+            },
+        }
+    }
+
+    #[test]
+    fn compaction_keeps_recent_turns_and_session_context() {
+        let mut h = ConversationHistory::default();
+        let env = user_input("<environment_context>\n  <cwd>/repo</cwd>\n</environment_context>");
+        let turn1 = [
+            user_input("first"),
+            call("c1"),
+            output("c1", &"x".repeat(400)),
+        ];
+        let turn2 = [user_input("second"), assistant_msg("done")];
+        h.record_items(std::iter::once(&env).chain(&turn1).chain(&turn2));
+
+        // Only the latest turn fits in the recent budget.
+        let split = h.compaction_split(10).unwrap();
+        assert_eq!(split, 4);
+
+        let to_compact = h.items_to_compact(split, 64);
+        assert_eq!(to_compact.len(), 3, "session context is not summarized");
+        let ResponseItem::FunctionCallOutput { output, .. } = &to_compact[2] else {
+            panic!("expected tool output");
+        };
+        assert!(output.content.contains("bytes omitted"));
+        assert!(output.content.len() < 100);
+
+        h.replace_with_summary(split, "they said first");
+        let items = h.contents();
+        assert_eq!(items.len(), 4);
+        assert_eq!(items[0], env);
+        let ResponseItem::Message { content, .. } = &items[1] else {
+            panic!("expected summary message");
+        };
+        assert_eq!(
+            content,
+            &vec![ContentItem::InputText {
+                text: format!("{COMPACTED_HISTORY_PREFIX}\n\nthey said first"),
+            }]
+        );
+        assert_eq!(items[2..], turn2);
+    }
+
+    #[test]
+    fn turn_changes_summaries_do_not_start_turns() {
+        let mut items = vec![
+            user_input("first"),
+            assistant_msg("done"),
+            user_input(
+                "<turn_changes>\nFiles changed in this turn:\nM a.txt (+1 -0)\n</turn_changes>",
+            ),
+            user_input("second"),
+            assistant_msg("done"),
+        ];
+        assert_eq!(count_turns(&items), 2);
+
+        assert!(rewind_turns(&mut items, 1));
+        assert_eq!(items.len(), 3);
+    }
+
+    #[test]
+    fn scoped_instructions_are_session_context() {
+        let scoped = user_input(
+            "<scoped_instructions path=\"api/AGENTS.md\">\nuse tabs\n</scoped_instructions>",
+        );
+        let mut h = ConversationHistory::default();
+        h.record_items([user_input("first"), scoped.clone(), user_input("second")].iter());
+        assert_eq!(h.turn_count(), 2);
+
+        h.replace_with_summary(2, "they said first");
+        assert_eq!(h.contents()[0], scoped);
+    }
+
+    #[test]
+    fn compaction_never_separates_tool_calls_from_outputs() {
+        let mut h = ConversationHistory::default();
+        // A user message injected while a tool call is still outstanding.
+        let items = [
+            user_input("first"),
+            call("c1"),
+            user_input("injected"),
+            output("c1", "ok"),
+            assistant_msg("done"),
+        ];
+        h.record_items(items.iter());
+
+        assert_eq!(h.compaction_split(0), None);
+    }
+
+    #[test]
+    fn truncate_middle_respects_char_boundaries() {
+        let text = "é".repeat(100);
+        let truncated = truncate_middle(&text, 11);
+        assert!(truncated.starts_with("éé"));
+        assert!(truncated.ends_with("éé"));
+        assert_eq!(truncate_middle("short", 11), "short");
+    }
 }
@@ -52,6 +52,20 @@ impl ConversationManager {
             .await
     }
 
+    /// Resume a conversation that was recorded to disk, typically by an
+    /// earlier process. The resumed conversation keeps its original id.
+    pub async fn resume_conversation(
+        &self,
+        config: Config,
+        conversation_id: Uuid,
+    ) -> CodexResult<NewConversation> {
+        let CodexSpawnOk {
+            codex,
+            session_id: conversation_id,
+        } = Codex::resume(config, self.auth_manager.clone(), conversation_id).await?;
+        self.finalize_spawn(codex, conversation_id).await
+    }
+
     async fn spawn_conversation(
         &self,
         config: Config,
@@ -110,6 +124,11 @@ impl ConversationManager {
             .ok_or_else(|| CodexErr::ConversationNotFound(conversation_id))
     }
 
+    /// Ids of the conversations this manager holds.
+    pub async fn conversation_ids(&self) -> Vec<Uuid> {
+        self.conversations.read().await.keys().copied().collect()
+    }
+
     pub async fn remove_conversation(&self, conversation_id: Uuid) {
         self.conversations.write().await.remove(&conversation_id);
     }
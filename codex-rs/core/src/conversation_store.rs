@@ -0,0 +1,349 @@
+//! Stores that session transcripts and message history entries are written
+//! to.
+//!
+//! Every session is recorded in the JSONL files in `~/.codex`, which resume,
+//! `codex sessions` and transcript exports read. The `[storage]` table adds
+//! a SQLite database and an HTTP service that the same records are written
+//! to, e.g. so that a team can keep all of its transcripts in one place.
+//!
+//! A record is one line of a rollout file: the session metadata first, then
+//! response items, state snapshots and branch records, in the order they
+//! happened.
+
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use rusqlite::Connection;
+use rusqlite::params;
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::config_types::HttpStorageConfig;
+use crate::message_history;
+use crate::message_history::HistoryEntry;
+
+/// How long a single HTTP request to the storage service may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long SQLite waits for another process that holds the database lock.
+const SQLITE_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A place that session records and history entries are appended to.
+pub(crate) trait ConversationStore: Send + Sync {
+    /// Short name used in log messages.
+    fn name(&self) -> &'static str;
+
+    /// Appends `records` to the transcript of `session_id`.
+    fn append_records<'a>(
+        &'a self,
+        session_id: Uuid,
+        records: &'a [Value],
+    ) -> BoxFuture<'a, io::Result<()>>;
+
+    /// Appends an entry to the message history.
+    fn append_history<'a>(&'a self, entry: &'a HistoryEntry) -> BoxFuture<'a, io::Result<()>>;
+}
+
+/// Opens the stores configured in `[storage]`. A store that cannot be opened
+/// is reported and left out, so that sessions are still recorded locally.
+pub(crate) fn open_configured_stores(config: &Config) -> Vec<Arc<dyn ConversationStore>> {
+    let mut stores: Vec<Arc<dyn ConversationStore>> = Vec::new();
+    if let Some(path) = &config.storage.sqlite_path {
+        match SqliteStore::open(path) {
+            Ok(store) => stores.push(Arc::new(store)),
+            Err(e) => warn!("failed to open conversation database {path:?}: {e}"),
+        }
+    }
+    if let Some(http) = &config.storage.http {
+        stores.push(Arc::new(HttpStore::new(http.clone())));
+    }
+    stores
+}
+
+/// The rollout file of one session and `~/.codex/history.jsonl`.
+pub(crate) struct JsonlStore {
+    rollout: tokio::sync::Mutex<tokio::fs::File>,
+    history_path: PathBuf,
+}
+
+impl JsonlStore {
+    pub(crate) fn new(rollout: tokio::fs::File, history_path: PathBuf) -> Self {
+        Self {
+            rollout: tokio::sync::Mutex::new(rollout),
+            history_path,
+        }
+    }
+}
+
+impl ConversationStore for JsonlStore {
+    fn name(&self) -> &'static str {
+        "jsonl"
+    }
+
+    fn append_records<'a>(
+        &'a self,
+        _session_id: Uuid,
+        records: &'a [Value],
+    ) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            let mut file = self.rollout.lock().await;
+            for record in records {
+                let mut json = serde_json::to_string(record)?;
+                json.push('\n');
+                file.write_all(json.as_bytes()).await?;
+            }
+            file.flush().await
+        })
+    }
+
+    fn append_history<'a>(&'a self, entry: &'a HistoryEntry) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(message_history::write_entry(&self.history_path, entry))
+    }
+}
+
+/// A SQLite database with a `session_records` and a `history` table.
+pub(crate) struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path).map_err(io::Error::other)?;
+        conn.busy_timeout(SQLITE_BUSY_TIMEOUT)
+            .map_err(io::Error::other)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS session_records (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL,
+                record TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS session_records_session_id
+                ON session_records (session_id, id);
+            CREATE TABLE IF NOT EXISTS history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL,
+                ts INTEGER NOT NULL,
+                text TEXT NOT NULL
+            );",
+        )
+        .map_err(io::Error::other)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs `f` on the connection on a blocking thread.
+    async fn with_conn<T, F>(&self, f: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = Arc::clone(&self.conn);
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| io::Error::other("conversation database lock poisoned"))?;
+            f(&mut conn).map_err(io::Error::other)
+        })
+        .await?
+    }
+}
+
+impl ConversationStore for SqliteStore {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn append_records<'a>(
+        &'a self,
+        session_id: Uuid,
+        records: &'a [Value],
+    ) -> BoxFuture<'a, io::Result<()>> {
+        let records: Vec<String> = records.iter().map(Value::to_string).collect();
+        Box::pin(self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut insert = tx.prepare_cached(
+                    "INSERT INTO session_records (session_id, record) VALUES (?1, ?2)",
+                )?;
+                for record in &records {
+                    insert.execute(params![session_id.to_string(), record])?;
+                }
+            }
+            tx.commit()
+        }))
+    }
+
+    fn append_history<'a>(&'a self, entry: &'a HistoryEntry) -> BoxFuture<'a, io::Result<()>> {
+        let entry = entry.clone();
+        Box::pin(self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO history (session_id, ts, text) VALUES (?1, ?2, ?3)",
+                params![entry.session_id, entry.ts as i64, entry.text],
+            )
+            .map(|_| ())
+        }))
+    }
+}
+
+/// An HTTP service that records and history entries are posted to as JSON.
+pub(crate) struct HttpStore {
+    config: HttpStorageConfig,
+    client: reqwest::Client,
+}
+
+impl HttpStore {
+    pub(crate) fn new(config: HttpStorageConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn post(&self, path: &str, body: &impl serde::Serialize) -> io::Result<()> {
+        let url = format!("{}/{path}", self.config.url.trim_end_matches('/'));
+        let mut request = self.client.post(url).timeout(REQUEST_TIMEOUT).json(body);
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map(|_| ())
+            .map_err(io::Error::other)
+    }
+}
+
+impl ConversationStore for HttpStore {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn append_records<'a>(
+        &'a self,
+        session_id: Uuid,
+        records: &'a [Value],
+    ) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            self.post(&format!("sessions/{session_id}/records"), &records)
+                .await
+        })
+    }
+
+    fn append_history<'a>(&'a self, entry: &'a HistoryEntry) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(self.post("history", entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::body_json;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+
+    fn entry(text: &str) -> HistoryEntry {
+        HistoryEntry {
+            session_id: "s1".to_string(),
+            ts: 1,
+            text: text.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn sqlite_store_keeps_records_in_order() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("codex.sqlite");
+        let session_id = Uuid::new_v4();
+        let store = SqliteStore::open(&path).unwrap();
+        store
+            .append_records(session_id, &[json!({"id": session_id}), json!({"n": 1})])
+            .await
+            .unwrap();
+        store
+            .append_records(Uuid::new_v4(), &[json!({"other": true})])
+            .await
+            .unwrap();
+        // A second connection, as another session would open.
+        let store = SqliteStore::open(&path).unwrap();
+        store
+            .append_records(session_id, &[json!({"n": 2})])
+            .await
+            .unwrap();
+        store.append_history(&entry("fix the bug")).await.unwrap();
+
+        let conn = Connection::open(&path).unwrap();
+        let records: Vec<String> = conn
+            .prepare("SELECT record FROM session_records WHERE session_id = ?1 ORDER BY id")
+            .unwrap()
+            .query_map([session_id.to_string()], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            records,
+            vec![
+                json!({"id": session_id}).to_string(),
+                r#"{"n":1}"#.to_string(),
+                r#"{"n":2}"#.to_string(),
+            ]
+        );
+        let text: String = conn
+            .query_row("SELECT text FROM history", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(text, "fix the bug");
+    }
+
+    #[tokio::test]
+    async fn http_store_posts_records_and_history() {
+        let server = MockServer::start().await;
+        let session_id = Uuid::new_v4();
+        Mock::given(method("POST"))
+            .and(path(format!("/v1/sessions/{session_id}/records")))
+            .and(header("authorization", "Bearer secret"))
+            .and(body_json(json!([{"n": 1}])))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/history"))
+            .and(body_json(
+                json!({"session_id": "s1", "ts": 1, "text": "hi"}),
+            ))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let store = HttpStore::new(HttpStorageConfig {
+            url: format!("{}/v1/", server.uri()),
+            headers: [("authorization".to_string(), "Bearer secret".to_string())].into(),
+        });
+        store
+            .append_records(session_id, &[json!({"n": 1})])
+            .await
+            .unwrap();
+        assert!(store.append_history(&entry("hi")).await.is_err());
+    }
+}
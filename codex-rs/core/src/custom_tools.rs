@@ -0,0 +1,527 @@
+//! Function tools declared under `[custom_tools]` in config, implemented by
+//! running a command or calling an HTTP endpoint, see [`CustomToolConfig`].
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use codex_protocol::models::FunctionCallOutputPayload;
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::config_types::CustomToolConfig;
+use crate::config_types::CustomToolHttpMethod;
+use crate::config_types::ExecLimits;
+use crate::exec::ExecParams;
+use crate::openai_tools::OpenAiTool;
+use crate::openai_tools::custom_tool_to_openai_tool;
+
+const DEFAULT_CUSTOM_TOOL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Environment variable in which a command tool gets all of its arguments as
+/// a JSON object.
+pub(crate) const CUSTOM_TOOL_ARGUMENTS_ENV_VAR: &str = "CODEX_TOOL_ARGUMENTS";
+
+/// Results longer than this are cut, keeping the beginning.
+const MAX_CUSTOM_TOOL_OUTPUT_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CustomTools {
+    tools: HashMap<String, CustomToolConfig>,
+    definitions: Vec<OpenAiTool>,
+}
+
+impl CustomTools {
+    /// Validates `tools`, returning the usable ones along with an error for
+    /// each tool that was left out. Tools may not reuse the names of
+    /// `builtin_tools`.
+    pub(crate) fn new(
+        tools: &HashMap<String, CustomToolConfig>,
+        builtin_tools: &[OpenAiTool],
+    ) -> (Self, Vec<String>) {
+        let reserved_names: Vec<&str> = builtin_tools.iter().map(OpenAiTool::name).collect();
+        let mut custom_tools = Self::default();
+        let mut errors = Vec::new();
+        let mut names: Vec<&String> = tools.keys().collect();
+        names.sort();
+        for name in names {
+            let tool = &tools[name];
+            let definition = validate(name, tool, &reserved_names)
+                .and_then(|()| custom_tool_to_openai_tool(name, tool).map_err(|e| e.to_string()));
+            match definition {
+                Ok(definition) => {
+                    custom_tools
+                        .definitions
+                        .push(OpenAiTool::Function(definition));
+                    custom_tools.tools.insert(name.clone(), tool.clone());
+                }
+                Err(e) => errors.push(format!("custom tool `{name}` is disabled: {e}")),
+            }
+        }
+        (custom_tools, errors)
+    }
+
+    /// Tool definitions to send to the model, sorted by name.
+    pub(crate) fn definitions(&self) -> &[OpenAiTool] {
+        &self.definitions
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&CustomToolConfig> {
+        self.tools.get(name)
+    }
+}
+
+fn validate(name: &str, tool: &CustomToolConfig, reserved_names: &[&str]) -> Result<(), String> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("names may only contain letters, digits, `_` and `-`".to_string());
+    }
+    if reserved_names.contains(&name) || name.contains("__") {
+        return Err("the name is taken by a built-in or MCP tool".to_string());
+    }
+    match (&tool.command, &tool.url) {
+        (Some(command), None) if command.is_empty() => Err("`command` is empty".to_string()),
+        // The model picks the arguments, never the program.
+        (Some(command), None) if !placeholders(&command[0]).is_empty() => {
+            Err("the program in `command` may not contain `{...}` placeholders".to_string())
+        }
+        (Some(_), None) | (None, Some(_)) => Ok(()),
+        _ => Err("exactly one of `command` and `url` must be set".to_string()),
+    }
+}
+
+/// The command a call to a command tool runs, or `None` for HTTP tools. It
+/// goes through the same approval and sandbox as `shell` commands, with the
+/// arguments in [`CUSTOM_TOOL_ARGUMENTS_ENV_VAR`] on top of `env`.
+pub(crate) fn exec_params_for_call(
+    tool: &CustomToolConfig,
+    arguments: &str,
+    cwd: &Path,
+    mut env: HashMap<String, String>,
+    limits: ExecLimits,
+) -> Option<Result<ExecParams, String>> {
+    let command = tool.command.as_ref()?;
+    let arguments = match parse_arguments(arguments) {
+        Ok(arguments) => arguments,
+        Err(e) => return Some(Err(e)),
+    };
+    env.insert(
+        CUSTOM_TOOL_ARGUMENTS_ENV_VAR.to_string(),
+        Value::Object(arguments.clone()).to_string(),
+    );
+    Some(Ok(ExecParams {
+        command: command
+            .iter()
+            .map(|part| substitute(part, &arguments, str::to_string))
+            .collect(),
+        cwd: cwd.to_path_buf(),
+        timeout_ms: Some(
+            tool.timeout_ms
+                .unwrap_or(DEFAULT_CUSTOM_TOOL_TIMEOUT.as_millis() as u64),
+        ),
+        env,
+        with_escalated_permissions: None,
+        justification: None,
+        limits,
+    }))
+}
+
+/// The request a call to `tool` would make, for dry-run mode, or `None` when
+/// the tool only reads and runs anyway. Values of `env_headers` are masked.
+pub(crate) fn dry_run_request(
+    tool: &CustomToolConfig,
+    arguments: &str,
+    cwd: &Path,
+) -> Option<Result<Value, String>> {
+    if tool.read_only || (tool.url.is_some() && tool.method == CustomToolHttpMethod::Get) {
+        return None;
+    }
+    let arguments = match parse_arguments(arguments) {
+        Ok(arguments) => arguments,
+        Err(e) => return Some(Err(e)),
+    };
+    Some(match (&tool.command, &tool.url) {
+        (Some(command), _) => {
+            let argv: Vec<String> = command
+                .iter()
+                .map(|part| substitute(part, &arguments, str::to_string))
+                .collect();
+            Ok(serde_json::json!({
+                "command": argv,
+                "cwd": cwd,
+                "arguments": Value::Object(arguments),
+            }))
+        }
+        (None, Some(url)) => build_request(tool, url, &arguments).map(|request| {
+            let headers: Map<String, Value> = request
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    let value = if tool
+                        .env_headers
+                        .keys()
+                        .any(|header| header.eq_ignore_ascii_case(name.as_str()))
+                    {
+                        "***".to_string()
+                    } else {
+                        String::from_utf8_lossy(value.as_bytes()).into_owned()
+                    };
+                    (name.to_string(), Value::String(value))
+                })
+                .collect();
+            let body = request.body().and_then(|body| body.as_bytes()).map(|body| {
+                serde_json::from_slice(body)
+                    .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into()))
+            });
+            serde_json::json!({
+                "method": request.method().as_str(),
+                "url": request.url().as_str(),
+                "headers": headers,
+                "body": body,
+            })
+        }),
+        (None, None) => Err("the tool has no `command` or `url`".to_string()),
+    })
+}
+
+/// Call the endpoint of HTTP tool `tool` with the model's JSON `arguments`.
+/// Command tools are run with [`exec_params_for_call`].
+pub(crate) async fn call(tool: &CustomToolConfig, arguments: &str) -> FunctionCallOutputPayload {
+    let result = match (parse_arguments(arguments), &tool.url) {
+        (Ok(arguments), Some(url)) => {
+            let timeout = tool
+                .timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_CUSTOM_TOOL_TIMEOUT);
+            call_url(tool, url, &arguments, timeout).await
+        }
+        (Ok(_), None) => Err("the tool has no `url`".to_string()),
+        (Err(e), _) => Err(e),
+    };
+    let (content, success) = match result {
+        Ok(output) => (output, true),
+        Err(e) => (e, false),
+    };
+    FunctionCallOutputPayload {
+        content: truncate(content),
+        success: Some(success),
+    }
+}
+
+fn parse_arguments(arguments: &str) -> Result<Map<String, Value>, String> {
+    if arguments.trim().is_empty() {
+        return Ok(Map::new());
+    }
+    match serde_json::from_str(arguments) {
+        Ok(Value::Object(arguments)) => Ok(arguments),
+        Ok(_) => Err("arguments must be a JSON object".to_string()),
+        Err(e) => Err(format!("failed to parse function arguments: {e}")),
+    }
+}
+
+/// Replace every `{name}` in `template` with the argument `name`, encoded
+/// with `encode`. Strings are used as they are and other values as JSON;
+/// missing arguments become empty.
+fn substitute(
+    template: &str,
+    arguments: &Map<String, Value>,
+    encode: impl Fn(&str) -> String,
+) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) if is_placeholder_name(&after[..end]) => {
+                let value = match arguments.get(&after[..end]) {
+                    Some(Value::String(value)) => value.clone(),
+                    Some(Value::Null) | None => String::new(),
+                    Some(value) => value.to_string(),
+                };
+                out.push_str(&encode(&value));
+                rest = &after[end + 1..];
+            }
+            _ => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn is_placeholder_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn placeholders(template: &str) -> Vec<&str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+        .filter(|name| is_placeholder_name(name))
+        .collect()
+}
+
+async fn call_url(
+    tool: &CustomToolConfig,
+    url: &str,
+    arguments: &Map<String, Value>,
+    timeout: Duration,
+) -> Result<String, String> {
+    let mut request = build_request(tool, url, arguments)?;
+    *request.timeout_mut() = Some(timeout);
+    let response = reqwest::Client::new()
+        .execute(request)
+        .await
+        .map_err(|e| format!("request failed: {e}"))?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| format!("failed to read the response: {e}"))?;
+    if status.is_success() {
+        Ok(body)
+    } else {
+        Err(format!("HTTP {status}\n{body}"))
+    }
+}
+
+/// The HTTP request for a call to a URL tool. Arguments used in `url` are
+/// left out of the query or body.
+fn build_request(
+    tool: &CustomToolConfig,
+    url: &str,
+    arguments: &Map<String, Value>,
+) -> Result<reqwest::Request, String> {
+    let used = placeholders(url);
+    let url = substitute(url, arguments, percent_encode);
+    let rest: Map<String, Value> = arguments
+        .iter()
+        .filter(|(name, _)| !used.contains(&name.as_str()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+
+    let client = reqwest::Client::new();
+    let mut builder = match tool.method {
+        CustomToolHttpMethod::Get => client.get(&url),
+        CustomToolHttpMethod::Post => client.post(&url),
+        CustomToolHttpMethod::Put => client.put(&url),
+        CustomToolHttpMethod::Patch => client.patch(&url),
+        CustomToolHttpMethod::Delete => client.delete(&url),
+    };
+    builder = match tool.method {
+        CustomToolHttpMethod::Get | CustomToolHttpMethod::Delete => {
+            let query: Vec<(&String, String)> = rest
+                .iter()
+                .map(|(name, value)| match value {
+                    Value::String(value) => (name, value.clone()),
+                    value => (name, value.to_string()),
+                })
+                .collect();
+            builder.query(&query)
+        }
+        _ => builder.json(&rest),
+    };
+    for (header, value) in &tool.headers {
+        builder = builder.header(header, value);
+    }
+    for (header, env_var) in &tool.env_headers {
+        if let Ok(value) = std::env::var(env_var)
+            && !value.trim().is_empty()
+        {
+            builder = builder.header(header, value);
+        }
+    }
+    builder.build().map_err(|e| format!("invalid request: {e}"))
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            out.push(char::from(byte));
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    out
+}
+
+fn truncate(mut content: String) -> String {
+    if content.len() > MAX_CUSTOM_TOOL_OUTPUT_BYTES {
+        let mut end = MAX_CUSTOM_TOOL_OUTPUT_BYTES;
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
+        content.truncate(end);
+        content.push_str("\n[... output truncated ...]");
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openai_tools::ResponsesApiTool;
+    use pretty_assertions::assert_eq;
+
+    fn command_tool(command: &[&str]) -> CustomToolConfig {
+        CustomToolConfig {
+            description: "test tool".to_string(),
+            parameters: None,
+            command: Some(command.iter().map(|part| part.to_string()).collect()),
+            url: None,
+            method: CustomToolHttpMethod::default(),
+            headers: HashMap::new(),
+            env_headers: HashMap::new(),
+            timeout_ms: None,
+            read_only: false,
+        }
+    }
+
+    #[test]
+    fn invalid_tools_are_reported() {
+        let tools = HashMap::from([
+            ("lookup".to_string(), command_tool(&["echo", "{key}"])),
+            ("shell".to_string(), command_tool(&["sh"])),
+            ("runner".to_string(), command_tool(&["{program}", "--help"])),
+            (
+                "both".to_string(),
+                CustomToolConfig {
+                    url: Some("https://example.com".to_string()),
+                    ..command_tool(&["true"])
+                },
+            ),
+        ]);
+        let builtin_tools = [OpenAiTool::Function(ResponsesApiTool {
+            name: "shell".to_string(),
+            description: String::new(),
+            strict: false,
+            parameters: crate::openai_tools::JsonSchema::Boolean { description: None },
+        })];
+        let (custom_tools, errors) = CustomTools::new(&tools, &builtin_tools);
+        assert!(custom_tools.get("lookup").is_some());
+        assert_eq!(custom_tools.definitions().len(), 1);
+        assert_eq!(
+            errors,
+            vec![
+                "custom tool `both` is disabled: exactly one of `command` and `url` must be set"
+                    .to_string(),
+                "custom tool `runner` is disabled: the program in `command` may not contain \
+                 `{...}` placeholders"
+                    .to_string(),
+                "custom tool `shell` is disabled: the name is taken by a built-in or MCP tool"
+                    .to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn templates_substitute_arguments() {
+        let arguments = parse_arguments(r#"{"key": "a b/c", "n": 3, "flag": null}"#).unwrap();
+        assert_eq!(
+            substitute(
+                "https://x/{key}?n={n}&f={flag}{",
+                &arguments,
+                percent_encode
+            ),
+            "https://x/a%20b%2Fc?n=3&f={"
+        );
+        assert_eq!(placeholders("/{key}/{n}/{not a name}"), vec!["key", "n"]);
+    }
+
+    #[test]
+    fn command_tools_run_as_exec_calls() {
+        let tool = command_tool(&["jira", "view", "{key}"]);
+        let params = exec_params_for_call(
+            &tool,
+            r#"{"key":"AB-1"}"#,
+            Path::new("/repo"),
+            HashMap::from([("PATH".to_string(), "/bin".to_string())]),
+            ExecLimits::default(),
+        );
+        let Some(Ok(params)) = params else {
+            panic!("expected exec params");
+        };
+        assert_eq!(params.command, vec!["jira", "view", "AB-1"]);
+        assert_eq!(params.cwd, Path::new("/repo"));
+        assert_eq!(params.timeout_ms, Some(60_000));
+        assert_eq!(
+            params.env,
+            HashMap::from([
+                ("PATH".to_string(), "/bin".to_string()),
+                (
+                    CUSTOM_TOOL_ARGUMENTS_ENV_VAR.to_string(),
+                    r#"{"key":"AB-1"}"#.to_string()
+                ),
+            ])
+        );
+
+        assert!(matches!(
+            exec_params_for_call(
+                &tool,
+                "[1]",
+                Path::new("/"),
+                HashMap::new(),
+                ExecLimits::default()
+            ),
+            Some(Err(_))
+        ));
+        let http = CustomToolConfig {
+            command: None,
+            url: Some("https://example.com".to_string()),
+            ..tool
+        };
+        assert!(
+            exec_params_for_call(
+                &http,
+                "{}",
+                Path::new("/"),
+                HashMap::new(),
+                ExecLimits::default()
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn dry_run_describes_the_request() {
+        let tool = CustomToolConfig {
+            command: None,
+            url: Some("https://dev.azure.com/org/_apis/wit/workitems/{id}".to_string()),
+            method: CustomToolHttpMethod::Patch,
+            headers: HashMap::from([("X-Team".to_string(), "ops".to_string())]),
+            env_headers: HashMap::from([("Authorization".to_string(), "PATH".to_string())]),
+            ..command_tool(&[])
+        };
+        let request = dry_run_request(&tool, r#"{"id": 7, "title": "Fix"}"#, Path::new("/"));
+        assert_eq!(
+            request,
+            Some(Ok(serde_json::json!({
+                "method": "PATCH",
+                "url": "https://dev.azure.com/org/_apis/wit/workitems/7",
+                "headers": {
+                    "content-type": "application/json",
+                    "x-team": "ops",
+                    "authorization": "***",
+                },
+                "body": {"title": "Fix"},
+            })))
+        );
+
+        let get = CustomToolConfig {
+            method: CustomToolHttpMethod::Get,
+            ..tool
+        };
+        assert_eq!(dry_run_request(&get, "{}", Path::new("/")), None);
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use tokio::process::Child;
+
+use crate::config_types::DockerSandbox;
+use crate::protocol::SandboxPolicy;
+use crate::spawn::StdioPolicy;
+use crate::spawn::spawn_child_async;
+
+/// Environment variables that describe the host rather than the command and
+/// would break the container if forwarded.
+const HOST_ONLY_ENV_VARS: &[&str] = &["PATH", "HOME", "TMPDIR", "SHELL"];
+
+/// Spawn a shell tool command in a throwaway container.
+///
+/// The container only sees the working directory and the writable roots of
+/// `sandbox_policy`, mounted at the same paths as on the host. The writable
+/// roots are mounted read-write and everything else read-only, and the
+/// network is disabled unless the policy grants network access.
+pub async fn spawn_command_under_docker(
+    docker: &DockerSandbox,
+    command: Vec<String>,
+    sandbox_policy: &SandboxPolicy,
+    cwd: PathBuf,
+    stdio_policy: StdioPolicy,
+    env: HashMap<String, String>,
+) -> std::io::Result<Child> {
+    let args = create_docker_command_args(docker, command, sandbox_policy, &cwd, &env);
+    let arg0 = None;
+    // Values of the forwarded variables are read by the CLI from its own
+    // environment so they do not show up in the process list.
+    spawn_child_async(
+        PathBuf::from(&docker.program),
+        args,
+        arg0,
+        cwd,
+        sandbox_policy,
+        stdio_policy,
+        env,
+    )
+    .await
+}
+
+fn create_docker_command_args(
+    docker: &DockerSandbox,
+    command: Vec<String>,
+    sandbox_policy: &SandboxPolicy,
+    cwd: &Path,
+    env: &HashMap<String, String>,
+) -> Vec<String> {
+    let mut args: Vec<String> = vec![
+        "run".to_string(),
+        "--rm".to_string(),
+        "-i".to_string(),
+        // Reap the processes a command leaves behind when it exits.
+        "--init".to_string(),
+    ];
+
+    if !sandbox_policy.has_full_network_access() {
+        args.extend(["--network".to_string(), "none".to_string()]);
+    }
+
+    // Files the command creates should belong to the user, not to root.
+    #[cfg(unix)]
+    {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        args.extend(["--user".to_string(), format!("{uid}:{gid}")]);
+    }
+
+    let writable_roots = sandbox_policy.get_writable_roots_with_cwd(cwd);
+    let cwd_is_writable = sandbox_policy.has_full_disk_write_access()
+        || writable_roots.iter().any(|root| root.is_path_writable(cwd));
+    if !writable_roots
+        .iter()
+        .any(|root| cwd.starts_with(&root.root))
+    {
+        args.extend(volume_args(cwd, !cwd_is_writable));
+    }
+    for root in &writable_roots {
+        args.extend(volume_args(&root.root, false));
+        // Later mounts shadow earlier ones, so these stay read-only.
+        for subpath in root.read_only_subpaths.iter().filter(|p| p.exists()) {
+            args.extend(volume_args(subpath, true));
+        }
+    }
+
+    args.extend(["--workdir".to_string(), container_path(cwd)]);
+
+    let mut names: Vec<&String> = env
+        .keys()
+        .filter(|name| !HOST_ONLY_ENV_VARS.contains(&name.as_str()))
+        .collect();
+    names.sort();
+    for name in names {
+        args.extend(["--env".to_string(), name.clone()]);
+    }
+
+    args.extend(docker.args.iter().cloned());
+    args.push(docker.image.clone());
+    args.extend(command);
+    args
+}
+
+fn volume_args(path: &Path, read_only: bool) -> [String; 2] {
+    let mut spec = format!("{}:{}", path.display(), container_path(path));
+    if read_only {
+        spec.push_str(":ro");
+    }
+    ["--volume".to_string(), spec]
+}
+
+/// Location of the host `path` inside the container. Host paths are used as
+/// is, except that Windows paths such as `C:\src\app` become `/c/src/app`.
+fn container_path(path: &Path) -> String {
+    if cfg!(windows) {
+        let path = path.to_string_lossy().replace('\\', "/");
+        match path.split_once(":/") {
+            Some((drive, rest)) => format!("/{}/{rest}", drive.to_ascii_lowercase()),
+            None => path,
+        }
+    } else {
+        path.to_string_lossy().to_string()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn workspace_write_mounts_writable_roots_without_network() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cwd = tmp.path().join("project");
+        std::fs::create_dir_all(cwd.join(".git")).unwrap();
+        let policy = SandboxPolicy::WorkspaceWrite {
+            writable_roots: vec![],
+            network_access: false,
+            exclude_tmpdir_env_var: true,
+            exclude_slash_tmp: true,
+        };
+        let env = HashMap::from([
+            ("PATH".to_string(), "/usr/bin".to_string()),
+            ("RUST_LOG".to_string(), "info".to_string()),
+        ]);
+
+        let args = create_docker_command_args(
+            &DockerSandbox::default(),
+            vec!["bash".to_string(), "-lc".to_string(), "ls".to_string()],
+            &policy,
+            &cwd,
+            &env,
+        );
+
+        let cwd = cwd.display();
+        let user = unsafe { format!("{}:{}", libc::getuid(), libc::getgid()) };
+        let expected: Vec<String> = [
+            "run",
+            "--rm",
+            "-i",
+            "--init",
+            "--network",
+            "none",
+            "--user",
+            &user,
+            "--volume",
+            &format!("{cwd}:{cwd}"),
+            "--volume",
+            &format!("{cwd}/.git:{cwd}/.git:ro"),
+            "--workdir",
+            &cwd.to_string(),
+            "--env",
+            "RUST_LOG",
+            "ubuntu:24.04",
+            "bash",
+            "-lc",
+            "ls",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        assert_eq!(args, expected);
+    }
+
+    #[test]
+    fn read_only_mounts_cwd_read_only() {
+        let cwd = PathBuf::from("/work");
+        let args = create_docker_command_args(
+            &DockerSandbox::default(),
+            vec!["true".to_string()],
+            &SandboxPolicy::ReadOnly,
+            &cwd,
+            &HashMap::new(),
+        );
+        assert!(args.contains(&"/work:/work:ro".to_string()), "{args:?}");
+        assert!(args.contains(&"none".to_string()), "{args:?}");
+    }
+}
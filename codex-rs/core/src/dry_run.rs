@@ -0,0 +1,96 @@
+//! Dry-run mode: tools that change remote systems (MCP tools not marked
+//! read-only, custom tools and `git push`) answer with the request they would
+//! send instead of sending it, so risky automations can be rehearsed.
+
+use codex_protocol::models::FunctionCallOutputPayload;
+use serde_json::Value;
+
+use crate::bash::try_parse_bash;
+use crate::bash::try_parse_word_only_commands_sequence;
+
+/// The output of a call skipped in dry-run mode. It counts as a success so
+/// the model carries on with the rest of its plan.
+pub(crate) fn dry_run_output(request: &Value) -> FunctionCallOutputPayload {
+    let request = serde_json::to_string_pretty(request).unwrap_or_else(|_| request.to_string());
+    FunctionCallOutputPayload {
+        content: format!(
+            "Dry run: nothing was executed. This is the request that would have been sent:\n\
+             {request}"
+        ),
+        success: Some(true),
+    }
+}
+
+/// Whether `command` runs `git push`, directly or in a `bash -lc` script.
+pub(crate) fn pushes_to_remote(command: &[String]) -> bool {
+    let commands = match command {
+        [bash, flag, script] if bash == "bash" && flag == "-lc" => {
+            match try_parse_bash(script)
+                .and_then(|tree| try_parse_word_only_commands_sequence(&tree, script))
+            {
+                Some(commands) => commands,
+                // Scripts that cannot be taken apart are judged by their words.
+                None => {
+                    let mut words =
+                        script.split(|c: char| c.is_whitespace() || ";&|()`".contains(c));
+                    return words.clone().any(|word| word == "git")
+                        && words.any(|word| word == "push");
+                }
+            }
+        }
+        command => vec![command.to_vec()],
+    };
+    commands.iter().any(|command| is_git_push(command))
+}
+
+fn is_git_push(command: &[String]) -> bool {
+    let Some((program, args)) = command.split_first() else {
+        return false;
+    };
+    if !(program == "git" || program.ends_with("/git")) {
+        return false;
+    }
+    // Skip global options such as `-C <dir>` to find the subcommand.
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-C" | "-c" | "--git-dir" | "--work-tree" | "--namespace" => {
+                args.next();
+            }
+            arg if arg.starts_with('-') => {}
+            arg => return arg == "push",
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vec_str(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn detects_git_push() {
+        assert!(pushes_to_remote(&vec_str(&[
+            "git", "push", "origin", "main"
+        ])));
+        assert!(pushes_to_remote(&vec_str(&["git", "-C", "repo", "push"])));
+        assert!(pushes_to_remote(&vec_str(&[
+            "bash",
+            "-lc",
+            "git commit -m wip && git push --force"
+        ])));
+        assert!(pushes_to_remote(&vec_str(&[
+            "bash",
+            "-lc",
+            "for r in a b; do git -C $r push; done"
+        ])));
+
+        assert!(!pushes_to_remote(&vec_str(&["git", "status"])));
+        assert!(!pushes_to_remote(&vec_str(&["git", "-C", "push", "log"])));
+        assert!(!pushes_to_remote(&vec_str(&["bash", "-lc", "echo push"])));
+    }
+}
@@ -0,0 +1,890 @@
+//! The `edit_files` tool: edits one or more files with unified diffs or
+//! search/replace pairs. Diff context and search text are matched tolerantly,
+//! first ignoring trailing and then leading whitespace. Every edit is worked
+//! out in memory before any file is written, and if writing or re-reading
+//! any file fails, all of them are restored, so the edits are applied
+//! completely or not at all.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::PoisonError;
+
+use codex_protocol::models::FunctionCallOutputPayload;
+use codex_protocol::models::ResponseInputItem;
+use serde::Deserialize;
+use similar::TextDiff;
+
+use crate::audit_log::AuditAction;
+use crate::audit_log::AuditApproval;
+use crate::audit_log::AuditOutcome;
+use crate::codex::Session;
+use crate::codex::TurnContext;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+use crate::protocol::Event;
+use crate::protocol::EventMsg;
+use crate::protocol::FileChange;
+use crate::protocol::PatchApplyBeginEvent;
+use crate::protocol::PatchApplyEndEvent;
+use crate::protocol::ReviewDecision;
+use crate::safety::SafetyCheck;
+use crate::safety::assess_file_edit_safety;
+use crate::turn_diff_tracker::SharedTurnDiffTracker;
+
+pub(crate) const EDIT_FILES_TOOL_NAME: &str = "edit_files";
+
+/// Ways of comparing a line of the file with a line of the edit, from the
+/// strictest to the most lenient.
+const LINE_MATCHERS: [fn(&str) -> &str; 3] = [|line| line, str::trim_end, str::trim];
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct EditFilesParams {
+    pub(crate) edits: Vec<FileEdit>,
+}
+
+/// Edit of one file: a unified diff, search/replace pairs, or both, in which
+/// case the diff is applied first.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub(crate) struct FileEdit {
+    pub(crate) path: String,
+    /// `--- /dev/null` creates the file and `+++ /dev/null` deletes it.
+    #[serde(default)]
+    pub(crate) diff: Option<String>,
+    #[serde(default)]
+    pub(crate) replacements: Vec<Replacement>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct Replacement {
+    pub(crate) search: String,
+    pub(crate) replace: String,
+    /// Replace every occurrence rather than requiring a unique match.
+    #[serde(default)]
+    pub(crate) all: bool,
+}
+
+/// The change worked out for one file.
+#[derive(Debug, Clone, PartialEq)]
+struct PlannedEdit {
+    path: PathBuf,
+    /// `None` when the file is created.
+    old: Option<String>,
+    /// `None` when the file is deleted.
+    new: Option<String>,
+}
+
+/// A file's contents as lines, remembering how to write them back.
+struct Text {
+    lines: Vec<String>,
+    crlf: bool,
+    trailing_newline: bool,
+}
+
+impl Text {
+    fn parse(content: &str) -> Self {
+        let crlf = content.contains("\r\n");
+        let trailing_newline = content.is_empty() || content.ends_with('\n');
+        let lines = content
+            .lines()
+            .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+            .collect();
+        Self {
+            lines,
+            crlf,
+            trailing_newline,
+        }
+    }
+
+    fn render(&self) -> String {
+        let newline = if self.crlf { "\r\n" } else { "\n" };
+        let mut content = self.lines.join(newline);
+        if self.trailing_newline && !self.lines.is_empty() {
+            content.push_str(newline);
+        }
+        content
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum DiffLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Hunk {
+    /// 1-based line of the original file the hunk starts at, if given.
+    old_start: Option<usize>,
+    lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct ParsedDiff {
+    creates: bool,
+    deletes: bool,
+    hunks: Vec<Hunk>,
+    no_newline_at_end: bool,
+}
+
+pub(crate) async fn handle_edit_files(
+    sess: &Session,
+    turn_context: &TurnContext,
+    turn_diff_tracker: &SharedTurnDiffTracker,
+    sub_id: &str,
+    call_id: String,
+    arguments: &str,
+) -> ResponseInputItem {
+    let reply = |content: String, success: bool| ResponseInputItem::FunctionCallOutput {
+        call_id: call_id.clone(),
+        output: FunctionCallOutputPayload {
+            content,
+            success: Some(success),
+        },
+    };
+    let params = match serde_json::from_str::<EditFilesParams>(arguments) {
+        Ok(params) => params,
+        Err(e) => return reply(format!("failed to parse function arguments: {e}"), false),
+    };
+    let planned = match plan_edits(&params.edits, &turn_context.cwd) {
+        Ok(planned) => planned,
+        Err(e) => return reply(format!("error: {e}; no files were changed"), false),
+    };
+
+    let changes = to_protocol_changes(&planned);
+    let paths: Vec<PathBuf> = planned.iter().map(|edit| edit.path.clone()).collect();
+    let audit_action = AuditAction::Patch {
+        cwd: turn_context.cwd.clone(),
+        files: paths.clone(),
+    };
+    let approval = match assess_file_edit_safety(
+        &paths,
+        turn_context.approval_policy,
+        &turn_context.sandbox_policy,
+        &turn_context.cwd,
+    ) {
+        SafetyCheck::AutoApprove { sandbox_type } => AuditApproval::Auto {
+            sandbox: sandbox_type,
+        },
+        SafetyCheck::AskUser => {
+            let rx_approve = sess
+                .request_file_changes_approval(
+                    sub_id.to_string(),
+                    call_id.clone(),
+                    changes.clone(),
+                    None,
+                    None,
+                )
+                .await;
+            match rx_approve.await.unwrap_or_default() {
+                ReviewDecision::Approved => AuditApproval::Approved,
+                ReviewDecision::ApprovedForSession => AuditApproval::ApprovedForSession,
+                ReviewDecision::Denied | ReviewDecision::Abort => {
+                    sess.record_audit(audit_action, AuditApproval::Denied, None)
+                        .await;
+                    return reply("edits rejected by user".to_string(), false);
+                }
+            }
+        }
+        SafetyCheck::Reject { reason } => {
+            sess.record_audit(
+                audit_action,
+                AuditApproval::Rejected {
+                    reason: reason.clone(),
+                },
+                None,
+            )
+            .await;
+            return reply(format!("edits rejected: {reason}"), false);
+        }
+    };
+
+    turn_diff_tracker
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .on_patch_begin(&changes);
+    sess.send_event(Event {
+        id: sub_id.to_string(),
+        msg: EventMsg::PatchApplyBegin(PatchApplyBeginEvent {
+            call_id: call_id.clone(),
+            auto_approved: matches!(approval, AuditApproval::Auto { .. }),
+            changes,
+        }),
+    })
+    .await;
+
+    let summary = summarize(&planned);
+    let result = tokio::task::spawn_blocking(move || apply_planned_edits(&planned))
+        .await
+        .unwrap_or_else(|e| Err(format!("failed to apply edits: {e}")));
+
+    let outcome = match &result {
+        Ok(()) => AuditOutcome {
+            success: true,
+            exit_code: None,
+            error: None,
+        },
+        Err(e) => AuditOutcome::error(e),
+    };
+    sess.record_audit(audit_action, approval, Some(outcome))
+        .await;
+
+    let (stdout, stderr) = match &result {
+        Ok(()) => (summary.clone(), String::new()),
+        Err(e) => (String::new(), e.clone()),
+    };
+    sess.send_event(Event {
+        id: sub_id.to_string(),
+        msg: EventMsg::PatchApplyEnd(PatchApplyEndEvent {
+            call_id: call_id.clone(),
+            stdout,
+            stderr,
+            success: result.is_ok(),
+        }),
+    })
+    .await;
+    sess.send_turn_diff(turn_diff_tracker, sub_id).await;
+
+    match result {
+        Ok(()) => reply(summary, true),
+        Err(e) => reply(format!("error: {e}"), false),
+    }
+}
+
+/// Work out the new contents of every file without writing anything.
+/// Several edits of the same file are applied one after the other.
+fn plan_edits(edits: &[FileEdit], cwd: &Path) -> Result<Vec<PlannedEdit>, String> {
+    if edits.is_empty() {
+        return Err("no edits given".to_string());
+    }
+    let mut planned: Vec<PlannedEdit> = Vec::new();
+    for edit in edits {
+        let path = real_path(&cwd.join(&edit.path));
+        let index = match planned.iter().position(|p| p.path == path) {
+            Some(index) => index,
+            None => {
+                let old = match std::fs::read_to_string(&path) {
+                    Ok(content) => Some(content),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => return Err(format!("{}: {e}", path.display())),
+                };
+                planned.push(PlannedEdit {
+                    path: path.clone(),
+                    new: old.clone(),
+                    old,
+                });
+                planned.len() - 1
+            }
+        };
+        let current = planned[index].new.take();
+        planned[index].new =
+            apply_file_edit(current, edit).map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    planned.retain(|edit| edit.old != edit.new);
+    if planned.is_empty() {
+        return Err("the edits do not change any file".to_string());
+    }
+    Ok(planned)
+}
+
+fn apply_file_edit(current: Option<String>, edit: &FileEdit) -> Result<Option<String>, String> {
+    if edit.diff.is_none() && edit.replacements.is_empty() {
+        return Err("expected a diff or replacements".to_string());
+    }
+    let mut content = current;
+    if let Some(diff) = &edit.diff {
+        let diff = parse_unified_diff(diff)?;
+        content = match (content, diff.deletes, diff.creates) {
+            (Some(_), true, _) => None,
+            (None, true, _) => return Err("cannot delete a file that does not exist".to_string()),
+            (Some(_), false, true) => {
+                return Err("cannot create a file that already exists".to_string());
+            }
+            (current, false, creates) => {
+                let Some(current) = current.or_else(|| creates.then(String::new)) else {
+                    return Err("file does not exist".to_string());
+                };
+                let mut text = Text::parse(&current);
+                apply_hunks(&mut text, &diff.hunks)?;
+                if diff.no_newline_at_end {
+                    text.trailing_newline = false;
+                }
+                Some(text.render())
+            }
+        };
+    }
+    for (n, replacement) in edit.replacements.iter().enumerate() {
+        let Some(current) = content else {
+            return Err("cannot replace text in a file that does not exist".to_string());
+        };
+        content = Some(
+            apply_replacement(&current, replacement)
+                .map_err(|e| format!("replacement {}: {e}", n + 1))?,
+        );
+    }
+    Ok(content)
+}
+
+fn parse_unified_diff(diff: &str) -> Result<ParsedDiff, String> {
+    let mut parsed = ParsedDiff::default();
+    let lines: Vec<&str> = diff
+        .lines()
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+    let mut in_hunk = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        if let Some(old) = line.strip_prefix("--- ")
+            && let Some(new) = lines.get(i).and_then(|next| next.strip_prefix("+++ "))
+        {
+            i += 1;
+            in_hunk = false;
+            parsed.creates = old.trim() == "/dev/null";
+            parsed.deletes = new.trim() == "/dev/null";
+            continue;
+        }
+        if let Some(header) = line.strip_prefix("@@") {
+            in_hunk = true;
+            let old_start = header
+                .trim_start()
+                .strip_prefix('-')
+                .and_then(|range| range.split([',', ' ']).next())
+                .and_then(|start| start.parse().ok());
+            parsed.hunks.push(Hunk {
+                old_start,
+                lines: Vec::new(),
+            });
+            continue;
+        }
+        let Some(hunk) = parsed.hunks.last_mut().filter(|_| in_hunk) else {
+            // `diff --git`, `index` and similar header lines.
+            continue;
+        };
+        match line.chars().next() {
+            Some(' ') => hunk.lines.push(DiffLine::Context(line[1..].to_string())),
+            Some('-') => hunk.lines.push(DiffLine::Remove(line[1..].to_string())),
+            Some('+') => hunk.lines.push(DiffLine::Add(line[1..].to_string())),
+            // Editors often strip the space of empty context lines.
+            None => hunk.lines.push(DiffLine::Context(String::new())),
+            Some('\\') => {
+                parsed.no_newline_at_end = matches!(hunk.lines.last(), Some(DiffLine::Add(_)));
+            }
+            Some(_) => in_hunk = false,
+        }
+    }
+    if parsed.hunks.is_empty() && !parsed.deletes {
+        return Err("the diff has no hunks".to_string());
+    }
+    Ok(parsed)
+}
+
+fn apply_hunks(text: &mut Text, hunks: &[Hunk]) -> Result<(), String> {
+    let mut cursor = 0;
+    // Lines added minus lines removed by the hunks applied so far, to map
+    // the line numbers of later hunks onto the edited text.
+    let mut offset: isize = 0;
+    for (n, hunk) in hunks.iter().enumerate() {
+        let old: Vec<&str> = hunk
+            .lines
+            .iter()
+            .filter_map(|line| match line {
+                DiffLine::Context(line) | DiffLine::Remove(line) => Some(line.as_str()),
+                DiffLine::Add(_) => None,
+            })
+            .collect();
+        let hint = hunk
+            .old_start
+            .map(|start| start.saturating_sub(1).saturating_add_signed(offset))
+            .unwrap_or(cursor);
+        let at = if old.is_empty() {
+            hint.clamp(cursor, text.lines.len())
+        } else {
+            find_block(&text.lines, &old, cursor, hint)
+                .ok_or_else(|| format!("hunk {} does not match the file", n + 1))?
+        };
+
+        // Keep the file's own version of context lines, and re-indent added
+        // lines like the closest line before them (or the first line, for
+        // leading additions) when it was matched with different indentation.
+        let mut indent = old.first().map(|first| {
+            (
+                leading_whitespace(first).to_string(),
+                leading_whitespace(&text.lines[at]).to_string(),
+            )
+        });
+        let mut replacement = Vec::new();
+        let mut line_index = at;
+        for line in &hunk.lines {
+            match line {
+                DiffLine::Context(expected) | DiffLine::Remove(expected) => {
+                    let actual = &text.lines[line_index];
+                    if !expected.trim().is_empty() {
+                        indent = Some((
+                            leading_whitespace(expected).to_string(),
+                            leading_whitespace(actual).to_string(),
+                        ));
+                    }
+                    if matches!(line, DiffLine::Context(_)) {
+                        replacement.push(actual.clone());
+                    }
+                    line_index += 1;
+                }
+                DiffLine::Add(line) => replacement.push(match &indent {
+                    Some((from, to)) => reindent(line, from, to),
+                    None => line.clone(),
+                }),
+            }
+        }
+        let added = replacement.len();
+        text.lines.splice(at..at + old.len(), replacement);
+        offset += added as isize - old.len() as isize;
+        cursor = at + added;
+    }
+    Ok(())
+}
+
+fn apply_replacement(content: &str, replacement: &Replacement) -> Result<String, String> {
+    let Replacement {
+        search,
+        replace,
+        all,
+    } = replacement;
+    if search.is_empty() {
+        return Err("the search text is empty".to_string());
+    }
+    let ambiguous = |count: usize| {
+        format!(
+            "the search text matches {count} times; include more context to make it unique or \
+             set `all`"
+        )
+    };
+
+    match content.matches(search.as_str()).count() {
+        0 => {}
+        1 => return Ok(content.replacen(search.as_str(), replace, 1)),
+        _ if *all => return Ok(content.replace(search.as_str(), replace)),
+        count => return Err(ambiguous(count)),
+    }
+
+    // Fall back to matching whole lines with whitespace differences ignored.
+    let mut text = Text::parse(content);
+    let search: Vec<&str> = search.lines().collect();
+    let replace: Vec<String> = replace.lines().map(str::to_string).collect();
+    for matcher in &LINE_MATCHERS[1..] {
+        let mut positions = Vec::new();
+        let mut i = 0;
+        while i + search.len() <= text.lines.len() {
+            if lines_match(&text.lines[i..i + search.len()], &search, *matcher) {
+                positions.push(i);
+                i += search.len();
+            } else {
+                i += 1;
+            }
+        }
+        if positions.is_empty() {
+            continue;
+        }
+        if positions.len() > 1 && !all {
+            return Err(ambiguous(positions.len()));
+        }
+        for &at in positions.iter().rev() {
+            let (from, to) = (
+                leading_whitespace(search[0]),
+                leading_whitespace(&text.lines[at]),
+            );
+            let lines: Vec<String> = replace
+                .iter()
+                .map(|line| reindent(line, from, to))
+                .collect();
+            text.lines.splice(at..at + search.len(), lines);
+        }
+        return Ok(text.render());
+    }
+    Err("the search text was not found".to_string())
+}
+
+/// Start of the best match of `block` in `lines` at or after `from`: the
+/// strictest matcher that finds the block wins, and among its matches the one
+/// closest to `hint`.
+fn find_block(lines: &[String], block: &[&str], from: usize, hint: usize) -> Option<usize> {
+    if block.len() > lines.len() {
+        return None;
+    }
+    LINE_MATCHERS.iter().find_map(|matcher| {
+        (from..=lines.len() - block.len())
+            .filter(|&i| lines_match(&lines[i..i + block.len()], block, *matcher))
+            .min_by_key(|&i| i.abs_diff(hint))
+    })
+}
+
+fn lines_match(lines: &[String], block: &[&str], matcher: fn(&str) -> &str) -> bool {
+    lines
+        .iter()
+        .zip(block)
+        .all(|(line, expected)| matcher(line) == matcher(expected))
+}
+
+fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+fn reindent(line: &str, from: &str, to: &str) -> String {
+    match line.strip_prefix(from) {
+        Some(rest) if from != to => format!("{to}{rest}"),
+        _ => line.to_string(),
+    }
+}
+
+/// `path` with symlinks in its existing ancestors resolved, so that edits
+/// are checked against, and written to, the files they really change.
+fn real_path(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(real) = existing.canonicalize() {
+            return rest.iter().rev().fold(real, |path, name| path.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name);
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+fn to_protocol_changes(planned: &[PlannedEdit]) -> HashMap<PathBuf, FileChange> {
+    planned
+        .iter()
+        .map(|edit| {
+            let change = match (&edit.old, &edit.new) {
+                (None, Some(content)) => FileChange::Add {
+                    content: content.clone(),
+                },
+                (Some(old), Some(new)) => FileChange::Update {
+                    unified_diff: TextDiff::from_lines(old, new)
+                        .unified_diff()
+                        .context_radius(1)
+                        .to_string(),
+                    move_path: None,
+                },
+                (_, None) => FileChange::Delete,
+            };
+            (edit.path.clone(), change)
+        })
+        .collect()
+}
+
+fn summarize(planned: &[PlannedEdit]) -> String {
+    let mut summary = "Success. Updated the following files:\n".to_string();
+    for edit in planned {
+        let status = match (&edit.old, &edit.new) {
+            (None, _) => 'A',
+            (Some(_), Some(_)) => 'M',
+            (Some(_), None) => 'D',
+        };
+        summary.push_str(&format!("{status} {}\n", edit.path.display()));
+    }
+    summary
+}
+
+/// Write every planned edit and check that each file ended up as planned.
+/// On the first failure every file written so far is restored.
+fn apply_planned_edits(planned: &[PlannedEdit]) -> Result<(), String> {
+    for (i, edit) in planned.iter().enumerate() {
+        let result = write_file(&edit.path, edit.new.as_deref())
+            .map_err(|e| e.to_string())
+            .and_then(|()| verify_file(edit));
+        if let Err(e) = result {
+            let mut message = format!("{}: {e}", edit.path.display());
+            let failed: Vec<String> = planned[..=i]
+                .iter()
+                .rev()
+                .filter_map(|edit| {
+                    restore_file(edit)
+                        .err()
+                        .map(|e| format!("{}: {e}", edit.path.display()))
+                })
+                .collect();
+            if failed.is_empty() {
+                message.push_str("; all files were restored");
+            } else {
+                message.push_str(&format!("; failed to restore {}", failed.join(", ")));
+            }
+            return Err(message);
+        }
+    }
+    Ok(())
+}
+
+/// Replace `path` with `content`, or delete it when `content` is `None`. The
+/// new contents are written to a temporary file that is then renamed over
+/// `path`, so the file is never left half-written.
+fn write_file(path: &Path, content: Option<&str>) -> std::io::Result<()> {
+    let Some(content) = content else {
+        return std::fs::remove_file(path);
+    };
+    let parent = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(parent)?;
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp = parent.join(format!(".{name}.codex-edit.tmp"));
+    let result = std::fs::write(&tmp, content).and_then(|()| {
+        if let Ok(metadata) = std::fs::metadata(path) {
+            std::fs::set_permissions(&tmp, metadata.permissions())?;
+        }
+        std::fs::rename(&tmp, path)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn verify_file(edit: &PlannedEdit) -> Result<(), String> {
+    let actual = match std::fs::read_to_string(&edit.path) {
+        Ok(content) => Some(content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("failed to read back: {e}")),
+    };
+    if actual == edit.new {
+        Ok(())
+    } else {
+        Err("file contents differ from the edit after writing".to_string())
+    }
+}
+
+fn restore_file(edit: &PlannedEdit) -> std::io::Result<()> {
+    if edit.old.is_none() && std::fs::symlink_metadata(&edit.path).is_err() {
+        // The file was never created.
+        return Ok(());
+    }
+    write_file(&edit.path, edit.old.as_deref())
+}
+
+pub(crate) fn create_edit_files_tool() -> ResponsesApiTool {
+    let replacement_properties = std::collections::BTreeMap::from([
+        (
+            "search".to_string(),
+            JsonSchema::String {
+                description: Some(
+                    "Text to find. It must match once unless `all` is set; whitespace at the \
+                     start and end of lines is ignored if there is no exact match."
+                        .to_string(),
+                ),
+            },
+        ),
+        (
+            "replace".to_string(),
+            JsonSchema::String {
+                description: Some("Text to put in its place.".to_string()),
+            },
+        ),
+        (
+            "all".to_string(),
+            JsonSchema::Boolean {
+                description: Some("Replace every match. Defaults to false.".to_string()),
+            },
+        ),
+    ]);
+    let edit_properties = std::collections::BTreeMap::from([
+        (
+            "path".to_string(),
+            JsonSchema::String {
+                description: Some(
+                    "File to edit, relative to the working directory or absolute.".to_string(),
+                ),
+            },
+        ),
+        (
+            "diff".to_string(),
+            JsonSchema::String {
+                description: Some(
+                    "Unified diff of the file. Use `--- /dev/null` to create the file and \
+                     `+++ /dev/null` to delete it. Line numbers are hints; hunks are located \
+                     by their context."
+                        .to_string(),
+                ),
+            },
+        ),
+        (
+            "replacements".to_string(),
+            JsonSchema::Array {
+                items: Box::new(JsonSchema::Object {
+                    properties: replacement_properties,
+                    required: Some(vec!["search".to_string(), "replace".to_string()]),
+                    additional_properties: Some(false),
+                }),
+                description: Some(
+                    "Search/replace pairs, applied in order after the diff.".to_string(),
+                ),
+            },
+        ),
+    ]);
+    let properties = std::collections::BTreeMap::from([(
+        "edits".to_string(),
+        JsonSchema::Array {
+            items: Box::new(JsonSchema::Object {
+                properties: edit_properties,
+                required: Some(vec!["path".to_string()]),
+                additional_properties: Some(false),
+            }),
+            description: Some("Edits to apply, one or more per file.".to_string()),
+        },
+    )]);
+
+    ResponsesApiTool {
+        name: EDIT_FILES_TOOL_NAME.to_string(),
+        description: "Edits files with unified diffs or search/replace pairs. All edits are \
+            applied together: if any of them does not apply, no file is changed."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["edits".to_string()]),
+            additional_properties: Some(false),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    fn edit(path: &str, diff: Option<&str>, replacements: &[(&str, &str)]) -> FileEdit {
+        FileEdit {
+            path: path.to_string(),
+            diff: diff.map(str::to_string),
+            replacements: replacements
+                .iter()
+                .map(|(search, replace)| Replacement {
+                    search: search.to_string(),
+                    replace: replace.to_string(),
+                    all: false,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn diffs_match_with_whitespace_differences_and_stale_line_numbers() {
+        let text = "fn main() {\n    let x = 1;  \n    println!(\"{x}\");\n}\n";
+        // Wrong line numbers, trailing whitespace dropped and the context
+        // indented with a tab instead of spaces.
+        let diff = "--- a/main.rs\n+++ b/main.rs\n@@ -7,3 +7,3 @@\n fn main() {\n-\tlet x = 1;\n+\tlet x = 2;\n \tprintln!(\"{x}\");\n";
+        let new = apply_file_edit(Some(text.to_string()), &edit("main.rs", Some(diff), &[]));
+        assert_eq!(
+            new,
+            Ok(Some(
+                "fn main() {\n    let x = 2;\n    println!(\"{x}\");\n}\n".to_string()
+            ))
+        );
+
+        let crlf = "a\r\nb\r\nc\r\n";
+        let new = apply_file_edit(
+            Some(crlf.to_string()),
+            &edit("f", Some("@@ -2 +2 @@\n-b\n+B\n"), &[]),
+        );
+        assert_eq!(new, Ok(Some("a\r\nB\r\nc\r\n".to_string())));
+
+        let mismatch = apply_file_edit(
+            Some(text.to_string()),
+            &edit("f", Some("@@ -1 +1 @@\n-fn other() {\n+fn main() {\n"), &[]),
+        );
+        assert_eq!(mismatch, Err("hunk 1 does not match the file".to_string()));
+    }
+
+    #[test]
+    fn replacements_must_be_unique_unless_all() {
+        let text = "    if a {\n        run();\n    }\n";
+        assert_eq!(
+            apply_file_edit(
+                Some(text.to_string()),
+                &edit("f", None, &[("if a {\n    run();", "if b {\n    stop();")])
+            ),
+            Ok(Some("    if b {\n        stop();\n    }\n".to_string()))
+        );
+
+        let repeated = "x = 1\nx = 1\n";
+        assert!(
+            apply_file_edit(
+                Some(repeated.to_string()),
+                &edit("f", None, &[("x = 1", "y")])
+            )
+            .is_err()
+        );
+        let all = FileEdit {
+            replacements: vec![Replacement {
+                search: "x = 1".to_string(),
+                replace: "y".to_string(),
+                all: true,
+            }],
+            ..FileEdit::default()
+        };
+        assert_eq!(
+            apply_file_edit(Some(repeated.to_string()), &all),
+            Ok(Some("y\ny\n".to_string()))
+        );
+    }
+
+    #[test]
+    fn edits_are_all_or_nothing() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path();
+        std::fs::write(dir.join("a.txt"), "one\n").unwrap();
+        std::fs::write(dir.join("b.txt"), "two\n").unwrap();
+
+        // A failing hunk in the second file leaves the first one untouched.
+        let edits = vec![
+            edit("a.txt", None, &[("one", "uno")]),
+            edit("b.txt", Some("@@ -1 +1 @@\n-three\n+tres\n"), &[]),
+        ];
+        let err = plan_edits(&edits, dir).unwrap_err();
+        assert!(err.contains("b.txt: hunk 1 does not match"), "{err}");
+        assert_eq!(std::fs::read_to_string(dir.join("a.txt")).unwrap(), "one\n");
+
+        let edits = vec![
+            edit("a.txt", None, &[("one", "uno")]),
+            edit(
+                "new/c.txt",
+                Some("--- /dev/null\n+++ b/c.txt\n@@ -0,0 +1 @@\n+tres\n"),
+                &[],
+            ),
+            edit("b.txt", Some("--- a/b.txt\n+++ /dev/null\n"), &[]),
+        ];
+        let planned = plan_edits(&edits, dir).unwrap();
+        apply_planned_edits(&planned).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("a.txt")).unwrap(), "uno\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("new/c.txt")).unwrap(),
+            "tres\n"
+        );
+        assert!(!dir.join("b.txt").exists());
+
+        // Writing fails part way through: the earlier file is restored.
+        let dir = real_path(dir);
+        let planned = vec![
+            PlannedEdit {
+                path: dir.join("a.txt"),
+                old: Some("uno\n".to_string()),
+                new: Some("one\n".to_string()),
+            },
+            PlannedEdit {
+                path: dir.join("new/c.txt/oops"),
+                old: None,
+                new: Some("x".to_string()),
+            },
+        ];
+        let err = apply_planned_edits(&planned).unwrap_err();
+        assert!(err.ends_with("all files were restored"), "{err}");
+        assert_eq!(std::fs::read_to_string(dir.join("a.txt")).unwrap(), "uno\n");
+    }
+}
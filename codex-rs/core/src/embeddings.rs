@@ -0,0 +1,307 @@
+//! Embeddings and reranking with the model configured in `[embeddings]`, so
+//! every part of Codex that searches text by meaning uses the same model.
+
+use std::sync::Arc;
+
+use codex_login::AuthManager;
+use codex_login::AuthMode;
+use codex_login::CodexAuth;
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tracing::trace;
+
+use crate::azure_auth::AzureAuth;
+use crate::client_common::EmbeddingsApiRequest;
+use crate::client_common::EmbeddingsApiResponse;
+use crate::client_common::RankedDocument;
+use crate::client_common::RerankApiRequest;
+use crate::client_common::RerankApiResponse;
+use crate::client_common::rank_by_similarity;
+use crate::config::Config;
+use crate::config::EmbeddingsConfig;
+use crate::error::CodexErr;
+use crate::error::Result;
+use crate::model_provider_info::AZURE_OPENAI_SCOPE;
+use crate::model_provider_info::WireApi;
+use crate::util::backoff;
+use crate::util::retry_after;
+
+#[derive(Debug, Clone)]
+pub struct EmbeddingClient {
+    config: EmbeddingsConfig,
+    auth_manager: Option<Arc<AuthManager>>,
+    azure_auth: Arc<AzureAuth>,
+    client: reqwest::Client,
+}
+
+impl EmbeddingClient {
+    /// `None` when `[embeddings]` is not configured.
+    pub fn new(config: &Config, auth_manager: Option<Arc<AuthManager>>) -> Option<Self> {
+        let embeddings = config.embeddings.clone()?;
+        let azure_auth = Arc::new(AzureAuth::new(
+            config.azure_auth.clone(),
+            &config.codex_home,
+        ));
+        Some(Self::with_embeddings_config(
+            embeddings,
+            auth_manager,
+            azure_auth,
+        ))
+    }
+
+    fn with_embeddings_config(
+        config: EmbeddingsConfig,
+        auth_manager: Option<Arc<AuthManager>>,
+        azure_auth: Arc<AzureAuth>,
+    ) -> Self {
+        Self {
+            config,
+            auth_manager,
+            azure_auth,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    /// The embedding of each of `inputs`, in the same order.
+    pub async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let request = EmbeddingsApiRequest {
+            model: &self.config.model,
+            input: inputs,
+            dimensions: self.config.dimensions,
+            encoding_format: "float",
+        };
+        let mut response: EmbeddingsApiResponse = self
+            .post("embeddings", &self.config.model, &request)
+            .await?;
+        if response.data.len() != inputs.len() {
+            return Err(CodexErr::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "expected {} embeddings, got {}",
+                    inputs.len(),
+                    response.data.len()
+                ),
+            )));
+        }
+        response.data.sort_by_key(|data| data.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|data| data.embedding)
+            .collect())
+    }
+
+    /// `documents` ordered by relevance to `query`, most relevant first,
+    /// keeping at most `top_n`. Uses the `rerank_model` when one is
+    /// configured and the similarity of the embeddings otherwise.
+    pub async fn rerank(
+        &self,
+        query: &str,
+        documents: &[String],
+        top_n: Option<usize>,
+    ) -> Result<Vec<RankedDocument>> {
+        if documents.is_empty() {
+            return Ok(Vec::new());
+        }
+        let Some(rerank_model) = &self.config.rerank_model else {
+            let mut inputs = vec![query.to_string()];
+            inputs.extend_from_slice(documents);
+            let mut embeddings = self.embed(&inputs).await?;
+            let query = embeddings.remove(0);
+            return Ok(rank_by_similarity(&query, &embeddings, top_n));
+        };
+
+        let request = RerankApiRequest {
+            model: rerank_model,
+            query,
+            documents,
+            top_n,
+        };
+        let response: RerankApiResponse = self.post("rerank", rerank_model, &request).await?;
+        let mut ranked: Vec<RankedDocument> = response
+            .results
+            .into_iter()
+            .filter(|result| result.index < documents.len())
+            .map(|result| RankedDocument {
+                index: result.index,
+                score: result.relevance_score,
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+        ranked.truncate(top_n.unwrap_or(ranked.len()));
+        Ok(ranked)
+    }
+
+    /// POST `body` to the provider's `endpoint`, retrying on 429, 5xx and
+    /// transport errors up to the provider's retry budget.
+    async fn post<T: DeserializeOwned>(
+        &self,
+        endpoint: &'static str,
+        model: &str,
+        body: &impl Serialize,
+    ) -> Result<T> {
+        let provider = &self.config.model_provider;
+        if matches!(provider.wire_api, WireApi::Anthropic | WireApi::Gemini) {
+            return Err(CodexErr::UnsupportedEndpoint {
+                provider: self.config.model_provider_id.clone(),
+                endpoint,
+            });
+        }
+
+        let mut attempt = 0;
+        let max_retries = provider.request_max_retries();
+        loop {
+            attempt += 1;
+
+            let auth = self.auth().await?;
+            trace!(
+                "POST to {}",
+                provider.get_endpoint_url(&auth, model, endpoint)
+            );
+            let res = provider
+                .create_endpoint_request_builder(&self.client, &auth, model, endpoint)
+                .await?
+                .json(body)
+                .send()
+                .await;
+
+            match res {
+                Ok(resp) if resp.status().is_success() => return Ok(resp.json().await?),
+                Ok(res) => {
+                    let status = res.status();
+                    if !(status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()) {
+                        let body = (res.text().await).unwrap_or_default();
+                        return Err(CodexErr::UnexpectedStatus(status, body));
+                    }
+                    if attempt > max_retries {
+                        return Err(CodexErr::RetryLimit(status));
+                    }
+                    let delay = retry_after(res.headers()).unwrap_or_else(|| backoff(attempt));
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => {
+                    if attempt > max_retries {
+                        return Err(e.into());
+                    }
+                    tokio::time::sleep(backoff(attempt)).await;
+                }
+            }
+        }
+    }
+
+    /// An Entra ID token for Azure OpenAI providers that use one, otherwise
+    /// the API key from Codex login. Signing in with ChatGPT gives no access
+    /// to embeddings, so that login is not used.
+    async fn auth(&self) -> Result<Option<CodexAuth>> {
+        if self.config.model_provider.uses_azure_entra_auth() {
+            let token = self.azure_auth.get_token(AZURE_OPENAI_SCOPE).await?;
+            return Ok(Some(CodexAuth::from_api_key(&token.token)));
+        }
+        Ok(self
+            .auth_manager
+            .as_ref()
+            .and_then(|manager| manager.auth())
+            .filter(|auth| auth.mode == AuthMode::ApiKey))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::config_types::AzureAuthConfig;
+    use crate::model_provider_info::create_oss_provider_with_base_url;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::body_partial_json;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+
+    fn client(server: &MockServer, rerank_model: Option<&str>) -> EmbeddingClient {
+        let codex_home = std::env::temp_dir();
+        EmbeddingClient::with_embeddings_config(
+            EmbeddingsConfig {
+                model_provider_id: "oss".to_string(),
+                model_provider: create_oss_provider_with_base_url(&format!("{}/v1", server.uri())),
+                model: "nomic-embed-text".to_string(),
+                dimensions: Some(2),
+                rerank_model: rerank_model.map(str::to_string),
+            },
+            None,
+            Arc::new(AzureAuth::new(AzureAuthConfig::default(), &codex_home)),
+        )
+    }
+
+    #[tokio::test]
+    async fn embeds_inputs_in_order() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .and(body_partial_json(json!({
+                "model": "nomic-embed-text",
+                "input": ["a", "b"],
+                "dimensions": 2,
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": [
+                    {"index": 1, "embedding": [0.0, 1.0]},
+                    {"index": 0, "embedding": [1.0, 0.0]},
+                ],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let embeddings = client(&server, None)
+            .embed(&["a".to_string(), "b".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+    }
+
+    #[tokio::test]
+    async fn reranks_with_the_rerank_model() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/rerank"))
+            .and(body_partial_json(json!({
+                "model": "bge-reranker",
+                "query": "q",
+                "top_n": 1,
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "results": [
+                    {"index": 1, "relevance_score": 0.9},
+                ],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let ranked = client(&server, Some("bge-reranker"))
+            .rerank("q", &["x".to_string(), "y".to_string()], Some(1))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            ranked,
+            vec![RankedDocument {
+                index: 1,
+                score: 0.9
+            }]
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! A description of the machine Codex runs on: the OS, the toolchains on the
+//! `PATH`, the Git branch and whether Codex runs in a container. It is added
+//! to the conversation when a session starts, so that the model does not
+//! have to run `uname` or `python --version` to find out, and the
+//! `refresh_environment` tool collects it again.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use codex_protocol::models::ContentItem;
+use codex_protocol::models::ResponseItem;
+use tokio::process::Command;
+
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+
+/// wraps the environment snapshot in a tag for the model to parse more easily.
+pub(crate) const ENVIRONMENT_SNAPSHOT_START: &str = "<environment_snapshot>";
+pub(crate) const ENVIRONMENT_SNAPSHOT_END: &str = "</environment_snapshot>";
+
+pub(crate) const REFRESH_ENVIRONMENT_TOOL_NAME: &str = "refresh_environment";
+
+/// How long a single `--version` (or `git`) invocation may take.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Toolchains that are reported when they are on the `PATH`, with the
+/// arguments that make them print their version.
+const TOOLCHAINS: &[(&str, &[&str])] = &[
+    ("rustc", &["--version"]),
+    ("cargo", &["--version"]),
+    ("python3", &["--version"]),
+    ("node", &["--version"]),
+    ("npm", &["--version"]),
+    ("go", &["version"]),
+    ("java", &["-version"]),
+    ("dotnet", &["--version"]),
+    ("ruby", &["--version"]),
+    ("gcc", &["--version"]),
+    ("clang", &["--version"]),
+    ("docker", &["--version"]),
+];
+
+/// Version strings longer than this are cut off.
+const MAX_VERSION_CHARS: usize = 120;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EnvironmentSnapshot {
+    /// e.g. `Ubuntu 24.04 (x86_64)`.
+    os: String,
+    /// Kind of container or virtual machine Codex runs in, if it could tell.
+    container: Option<&'static str>,
+    /// First line of the version output of each toolchain found.
+    toolchains: Vec<(&'static str, String)>,
+    git: Option<GitSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct GitSnapshot {
+    branch: Option<String>,
+    changed_files: usize,
+    untracked_files: usize,
+}
+
+impl EnvironmentSnapshot {
+    /// Collects the snapshot for commands run in `cwd` with environment
+    /// `env`, which should be the environment commands of the session get.
+    pub(crate) async fn collect(cwd: &Path, env: &HashMap<String, String>) -> Self {
+        let info = os_info::get();
+        let os = format!(
+            "{} {} ({})",
+            info.os_type(),
+            info.version(),
+            std::env::consts::ARCH
+        );
+        let toolchains =
+            futures::future::join_all(TOOLCHAINS.iter().map(|(name, args)| async move {
+                let version = command_output(name, args, cwd, env).await?;
+                Some((*name, version_line(&version)?))
+            }))
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+        Self {
+            os,
+            container: detect_container(env),
+            toolchains,
+            git: collect_git(cwd, env).await,
+        }
+    }
+
+    /// Output looks like:
+    ///
+    /// ```xml
+    /// <environment_snapshot>
+    ///   <os>Ubuntu 24.04 (x86_64)</os>
+    ///   <container>docker</container>
+    ///   <toolchains>
+    ///     <rustc>rustc 1.89.0 (29483883e 2025-08-04)</rustc>
+    ///   </toolchains>
+    ///   <git branch="main" changed_files="2" untracked_files="1" />
+    /// </environment_snapshot>
+    /// ```
+    pub(crate) fn serialize_to_xml(&self) -> String {
+        let mut lines = vec![ENVIRONMENT_SNAPSHOT_START.to_string()];
+        lines.push(format!("  <os>{}</os>", self.os));
+        if let Some(container) = self.container {
+            lines.push(format!("  <container>{container}</container>"));
+        }
+        if !self.toolchains.is_empty() {
+            lines.push("  <toolchains>".to_string());
+            for (name, version) in &self.toolchains {
+                lines.push(format!("    <{name}>{version}</{name}>"));
+            }
+            lines.push("  </toolchains>".to_string());
+        }
+        if let Some(git) = &self.git {
+            let branch = match &git.branch {
+                Some(branch) => format!(" branch=\"{branch}\""),
+                None => String::new(),
+            };
+            lines.push(format!(
+                "  <git{branch} changed_files=\"{}\" untracked_files=\"{}\" />",
+                git.changed_files, git.untracked_files
+            ));
+        }
+        lines.push(ENVIRONMENT_SNAPSHOT_END.to_string());
+        lines.join("\n")
+    }
+}
+
+impl From<&EnvironmentSnapshot> for ResponseItem {
+    fn from(snapshot: &EnvironmentSnapshot) -> Self {
+        ResponseItem::Message {
+            id: None,
+            role: "user".to_string(),
+            content: vec![ContentItem::InputText {
+                text: format!(
+                    "{}\nThis snapshot was taken when the session started; call `{REFRESH_ENVIRONMENT_TOOL_NAME}` if it may be out of date.",
+                    snapshot.serialize_to_xml()
+                ),
+            }],
+        }
+    }
+}
+
+pub(crate) fn create_refresh_environment_tool() -> ResponsesApiTool {
+    ResponsesApiTool {
+        name: REFRESH_ENVIRONMENT_TOOL_NAME.to_string(),
+        description: "Describes the environment again: OS, toolchain versions, Git branch and status, and container. Use it after installing toolchains or switching branches instead of running version commands.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: Some(Vec::new()),
+            additional_properties: Some(false),
+        },
+    }
+}
+
+/// Runs `program` and returns what it printed, stdout first. Returns `None`
+/// if it cannot be started, fails or does not finish in time.
+async fn command_output(
+    program: &str,
+    args: &[&str],
+    cwd: &Path,
+    env: &HashMap<String, String>,
+) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(cwd)
+        .env_clear()
+        .envs(env)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(COMMAND_TIMEOUT, output)
+        .await
+        .ok()?
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // `java -version` prints to stderr.
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Some(text)
+}
+
+fn version_line(output: &str) -> Option<String> {
+    let line = output
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())?;
+    Some(line.chars().take(MAX_VERSION_CHARS).collect())
+}
+
+async fn collect_git(cwd: &Path, env: &HashMap<String, String>) -> Option<GitSnapshot> {
+    let status = command_output("git", &["status", "--porcelain=v1", "--branch"], cwd, env).await?;
+    Some(parse_git_status(&status))
+}
+
+/// Parses the output of `git status --porcelain=v1 --branch`.
+fn parse_git_status(status: &str) -> GitSnapshot {
+    let mut snapshot = GitSnapshot {
+        branch: None,
+        changed_files: 0,
+        untracked_files: 0,
+    };
+    for line in status.lines() {
+        if let Some(branch) = line.strip_prefix("## ") {
+            // `main...origin/main [ahead 1]`, or `HEAD (no branch)`.
+            let branch = branch.split("...").next().unwrap_or(branch).trim();
+            let branch = branch.strip_prefix("No commits yet on ").unwrap_or(branch);
+            if !branch.starts_with("HEAD (no branch)") {
+                snapshot.branch = Some(branch.to_string());
+            }
+        } else if line.starts_with("??") {
+            snapshot.untracked_files += 1;
+        } else if !line.is_empty() {
+            snapshot.changed_files += 1;
+        }
+    }
+    snapshot
+}
+
+fn detect_container(env: &HashMap<String, String>) -> Option<&'static str> {
+    if env.contains_key("KUBERNETES_SERVICE_HOST") {
+        Some("kubernetes")
+    } else if env.get("CODESPACES").is_some_and(|value| value == "true") {
+        Some("codespaces")
+    } else if Path::new("/.dockerenv").exists() {
+        Some("docker")
+    } else if Path::new("/run/.containerenv").exists() {
+        Some("podman")
+    } else if std::fs::read_to_string("/proc/version")
+        .is_ok_and(|version| version.to_lowercase().contains("microsoft"))
+    {
+        Some("wsl")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn serializes_to_xml() {
+        let snapshot = EnvironmentSnapshot {
+            os: "Ubuntu 24.04 (x86_64)".to_string(),
+            container: Some("docker"),
+            toolchains: vec![
+                ("rustc", "rustc 1.89.0".to_string()),
+                ("node", "v22.3.0".to_string()),
+            ],
+            git: Some(parse_git_status(
+                "## feature/x...origin/feature/x [ahead 1]\n M src/lib.rs\nA  src/new.rs\n?? notes.txt\n",
+            )),
+        };
+
+        assert_eq!(
+            snapshot.serialize_to_xml(),
+            r#"<environment_snapshot>
+  <os>Ubuntu 24.04 (x86_64)</os>
+  <container>docker</container>
+  <toolchains>
+    <rustc>rustc 1.89.0</rustc>
+    <node>v22.3.0</node>
+  </toolchains>
+  <git branch="feature/x" changed_files="2" untracked_files="1" />
+</environment_snapshot>"#
+        );
+    }
+
+    #[test]
+    fn detached_head_has_no_branch() {
+        assert_eq!(
+            parse_git_status("## HEAD (no branch)\n"),
+            GitSnapshot {
+                branch: None,
+                changed_files: 0,
+                untracked_files: 0,
+            }
+        );
+        assert_eq!(
+            parse_git_status("## No commits yet on main\n").branch,
+            Some("main".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_toolchains_are_skipped() {
+        let env = HashMap::from([("PATH".to_string(), String::new())]);
+        assert_eq!(
+            command_output("rustc", &["--version"], Path::new("."), &env).await,
+            None
+        );
+        assert_eq!(
+            version_line("\n  Python 3.12.3\nextra\n"),
+            Some("Python 3.12.3".to_string())
+        );
+    }
+}
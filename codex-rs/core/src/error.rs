@@ -6,6 +6,11 @@ use thiserror::Error;
 use tokio::task::JoinError;
 use uuid::Uuid;
 
+use crate::exec::ExecToolCallOutput;
+use crate::protocol::BudgetEvent;
+use crate::protocol::ErrorCategory;
+use crate::protocol::ErrorEvent;
+
 pub type Result<T> = std::result::Result<T, CodexErr>;
 
 #[derive(Error, Debug)]
@@ -24,9 +29,24 @@ pub enum SandboxErr {
     #[error("seccomp backend error")]
     SeccompBackend(#[from] seccompiler::BackendError),
 
-    /// Command timed out
+    /// Command timed out. Carries the output produced before it was killed.
     #[error("command timed out")]
-    Timeout,
+    Timeout { output: Box<ExecToolCallOutput> },
+
+    /// Command was killed for writing more than its `max_output_bytes`.
+    /// Carries the output kept up to that point.
+    #[error("command exceeded its output limit")]
+    OutputLimit { output: Box<ExecToolCallOutput> },
+
+    /// Command was killed for exceeding its CPU time limit. Carries the
+    /// output produced before it was killed.
+    #[error("command exceeded its CPU time limit")]
+    CpuTimeLimit { output: Box<ExecToolCallOutput> },
+
+    /// Command was killed because the turn was interrupted. Carries the
+    /// output produced before it was killed.
+    #[error("command was cancelled")]
+    Cancelled { output: Box<ExecToolCallOutput> },
 
     /// Command was killed by a signal
     #[error("command was killed by a signal")]
@@ -37,6 +57,20 @@ pub enum SandboxErr {
     LandlockRestrict,
 }
 
+impl SandboxErr {
+    /// Output of a command that was killed for hitting one of its limits or
+    /// because it was cancelled.
+    pub fn partial_output(&self) -> Option<&ExecToolCallOutput> {
+        match self {
+            SandboxErr::Timeout { output }
+            | SandboxErr::OutputLimit { output }
+            | SandboxErr::CpuTimeLimit { output }
+            | SandboxErr::Cancelled { output } => Some(output),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum CodexErr {
     /// Returned by ResponsesClient when the SSE stream disconnects or errors out **after** the HTTP
@@ -83,6 +117,28 @@ pub enum CodexErr {
     #[error("We're currently experiencing high demand, which may cause temporary errors.")]
     InternalServerError,
 
+    /// The provider failed repeatedly and no failover provider is left.
+    #[error(
+        "model provider {provider} is unavailable after repeated failures; try again in {retry_in_secs}s"
+    )]
+    ProviderUnavailable {
+        provider: String,
+        retry_in_secs: u64,
+    },
+
+    /// The provider's wire API has no endpoint for the request, e.g.
+    /// embeddings from an Anthropic provider.
+    #[error("model provider {provider} does not support {endpoint}")]
+    UnsupportedEndpoint {
+        provider: String,
+        endpoint: &'static str,
+    },
+
+    /// A budget from `[budget]` is used up and going over it was not
+    /// approved.
+    #[error("{0} exceeded")]
+    BudgetExceeded(BudgetEvent),
+
     /// Retry limit exceeded.
     #[error("exceeded retry limit, last status: {0}")]
     RetryLimit(StatusCode),
@@ -123,6 +179,9 @@ pub enum CodexErr {
 
     #[error("{0}")]
     EnvVar(EnvVarError),
+
+    #[error(transparent)]
+    AzureAuth(#[from] crate::azure_auth::AzureAuthError),
 }
 
 #[derive(Debug)]
@@ -221,11 +280,140 @@ impl CodexErr {
     }
 }
 
+impl CodexErr {
+    /// Broad kind of this error.
+    pub fn category(&self) -> ErrorCategory {
+        self.classify().0
+    }
+
+    /// Stable machine-readable code, `<category>.<reason>`. Codes are not
+    /// renamed once published, since clients match on them.
+    pub fn code(&self) -> &'static str {
+        self.classify().1
+    }
+
+    /// Whether trying the same thing again, unchanged, may succeed.
+    pub fn is_retryable(&self) -> bool {
+        self.classify().2
+    }
+
+    /// The error with its code, as reported to the model in tool results so
+    /// it can tell a failure worth retrying from one that needs a change.
+    pub fn describe_for_model(&self) -> String {
+        let retry = if self.is_retryable() {
+            ", retryable"
+        } else {
+            ""
+        };
+        format!("{self} [code: {}{retry}]", self.code())
+    }
+
+    pub fn to_error_event(&self) -> ErrorEvent {
+        ErrorEvent {
+            message: self.to_string(),
+            code: Some(self.code().to_string()),
+            category: Some(self.category()),
+            retryable: self.is_retryable(),
+        }
+    }
+
+    fn classify(&self) -> (ErrorCategory, &'static str, bool) {
+        use ErrorCategory::*;
+        match self {
+            CodexErr::Stream(..) => (Provider, "provider.stream_disconnected", true),
+            CodexErr::ConversationNotFound(_) => (NotFound, "not_found.conversation", false),
+            CodexErr::SessionConfiguredNotFirstEvent => {
+                (Internal, "internal.session_configured_not_first", false)
+            }
+            CodexErr::Timeout => (Timeout, "timeout.child_process", true),
+            CodexErr::Spawn => (Internal, "internal.spawn", true),
+            CodexErr::Interrupted => (Internal, "internal.interrupted", false),
+            CodexErr::UnexpectedStatus(status, _) => classify_status(*status),
+            CodexErr::UsageLimitReached(_) => (RateLimit, "rate_limit.usage_limit_reached", false),
+            CodexErr::UsageNotIncluded => (Auth, "auth.usage_not_included", false),
+            CodexErr::InternalServerError => (Provider, "provider.internal_server_error", true),
+            CodexErr::ProviderUnavailable { .. } => (Provider, "provider.unavailable", true),
+            CodexErr::UnsupportedEndpoint { .. } => {
+                (Validation, "validation.unsupported_endpoint", false)
+            }
+            CodexErr::BudgetExceeded(_) => (RateLimit, "rate_limit.budget_exceeded", false),
+            CodexErr::RetryLimit(status) if *status == StatusCode::TOO_MANY_REQUESTS => {
+                (RateLimit, "rate_limit.retry_limit", true)
+            }
+            CodexErr::RetryLimit(_) => (Provider, "provider.retry_limit", true),
+            CodexErr::InternalAgentDied => (Internal, "internal.agent_died", false),
+            CodexErr::Sandbox(err) => match err {
+                SandboxErr::Denied(..) => (Sandbox, "sandbox.denied", false),
+                SandboxErr::Timeout { .. } => (Timeout, "timeout.command", true),
+                SandboxErr::OutputLimit { .. } => (Sandbox, "sandbox.output_limit", false),
+                SandboxErr::CpuTimeLimit { .. } => (Sandbox, "sandbox.cpu_time_limit", false),
+                SandboxErr::Cancelled { .. } => (Sandbox, "sandbox.cancelled", false),
+                SandboxErr::Signal(_) => (Sandbox, "sandbox.signal", false),
+                #[cfg(target_os = "linux")]
+                SandboxErr::SeccompInstall(_) | SandboxErr::SeccompBackend(_) => {
+                    (Sandbox, "sandbox.setup", false)
+                }
+                SandboxErr::LandlockRestrict => (Sandbox, "sandbox.setup", false),
+            },
+            CodexErr::LandlockSandboxExecutableNotProvided => {
+                (Sandbox, "sandbox.executable_missing", false)
+            }
+            CodexErr::Io(err) => match err.kind() {
+                io::ErrorKind::NotFound => (NotFound, "not_found.io", false),
+                io::ErrorKind::TimedOut => (Timeout, "timeout.io", true),
+                io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => {
+                    (Validation, "validation.io", false)
+                }
+                _ => (Internal, "internal.io", false),
+            },
+            CodexErr::Reqwest(err) => {
+                if err.is_timeout() {
+                    (Timeout, "timeout.http", true)
+                } else if let Some(status) = err.status() {
+                    classify_status(status)
+                } else if err.is_connect() {
+                    (Provider, "provider.connection", true)
+                } else {
+                    (Provider, "provider.http", true)
+                }
+            }
+            CodexErr::Json(_) => (Validation, "validation.json", false),
+            #[cfg(target_os = "linux")]
+            CodexErr::LandlockRuleset(_) | CodexErr::LandlockPathFd(_) => {
+                (Sandbox, "sandbox.setup", false)
+            }
+            CodexErr::TokioJoin(_) => (Internal, "internal.join", false),
+            CodexErr::EnvVar(_) => (Auth, "auth.missing_env_var", false),
+            CodexErr::AzureAuth(_) => (Auth, "auth.azure", false),
+        }
+    }
+}
+
+/// Category, code and retryability of a failed HTTP request.
+fn classify_status(status: StatusCode) -> (ErrorCategory, &'static str, bool) {
+    use ErrorCategory::*;
+    match status {
+        StatusCode::UNAUTHORIZED => (Auth, "auth.unauthorized", false),
+        StatusCode::FORBIDDEN => (Auth, "auth.forbidden", false),
+        StatusCode::NOT_FOUND => (NotFound, "not_found.http", false),
+        StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => {
+            (Timeout, "timeout.http", true)
+        }
+        StatusCode::TOO_MANY_REQUESTS => (RateLimit, "rate_limit.too_many_requests", true),
+        status if status.is_client_error() => (Validation, "validation.bad_request", false),
+        status if status.is_server_error() => (Provider, "provider.server_error", true),
+        _ => (Provider, "provider.unexpected_status", false),
+    }
+}
+
 pub fn get_error_message_ui(e: &CodexErr) -> String {
     match e {
         CodexErr::Sandbox(SandboxErr::Denied(_, _, stderr)) => stderr.to_string(),
         // Timeouts are not sandbox errors from a UX perspective; present them plainly
-        CodexErr::Sandbox(SandboxErr::Timeout) => "error: command timed out".to_string(),
+        CodexErr::Sandbox(SandboxErr::Timeout { .. }) => "error: command timed out".to_string(),
+        CodexErr::Sandbox(
+            err @ (SandboxErr::OutputLimit { .. } | SandboxErr::CpuTimeLimit { .. }),
+        ) => format!("error: {err}"),
         _ => e.to_string(),
     }
 }
@@ -234,6 +422,32 @@ pub fn get_error_message_ui(e: &CodexErr) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn classifies_errors_with_stable_codes() {
+        let err = CodexErr::UnexpectedStatus(StatusCode::UNAUTHORIZED, String::new());
+        assert_eq!(err.category(), ErrorCategory::Auth);
+        assert_eq!(err.code(), "auth.unauthorized");
+        assert!(!err.is_retryable());
+
+        let err = CodexErr::RetryLimit(StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(err.code(), "rate_limit.retry_limit");
+        assert!(err.is_retryable());
+
+        let err = CodexErr::Io(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        assert_eq!(
+            err.describe_for_model(),
+            "no such file [code: not_found.io]"
+        );
+
+        let event = CodexErr::InternalServerError.to_error_event();
+        assert_eq!(event.category, Some(ErrorCategory::Provider));
+        assert_eq!(
+            event.code.as_deref(),
+            Some("provider.internal_server_error")
+        );
+        assert!(event.retryable);
+    }
+
     #[test]
     fn usage_limit_reached_error_formats_plus_plan() {
         let err = UsageLimitReachedError {
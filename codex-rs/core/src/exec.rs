@@ -2,9 +2,14 @@
 use std::os::unix::process::ExitStatusExt;
 
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::io;
 use std::path::PathBuf;
 use std::process::ExitStatus;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 use std::time::Instant;
 
@@ -13,10 +18,17 @@ use tokio::io::AsyncRead;
 use tokio::io::AsyncReadExt;
 use tokio::io::BufReader;
 use tokio::process::Child;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
 
+use crate::config_types::DockerSandbox;
+use crate::config_types::ExecLimits;
+use crate::config_types::ExecOutputConfig;
+use crate::docker::spawn_command_under_docker;
 use crate::error::CodexErr;
 use crate::error::Result;
 use crate::error::SandboxErr;
+use crate::exec_limits::ProcessLimits;
 use crate::landlock::spawn_command_under_linux_sandbox;
 use crate::protocol::Event;
 use crate::protocol::EventMsg;
@@ -26,20 +38,27 @@ use crate::protocol::SandboxPolicy;
 use crate::seatbelt::spawn_command_under_seatbelt;
 use crate::spawn::StdioPolicy;
 use crate::spawn::spawn_child_async;
+use serde::Deserialize;
+use serde::Serialize;
 use serde_bytes::ByteBuf;
 
 const DEFAULT_TIMEOUT_MS: u64 = 10_000;
 
 // Hardcode these since it does not seem worth including the libc crate just
 // for these.
-const SIGKILL_CODE: i32 = 9;
 const TIMEOUT_CODE: i32 = 64;
+const OUTPUT_LIMIT_CODE: i32 = 65;
+const CANCELLED_CODE: i32 = 66;
 const EXIT_CODE_SIGNAL_BASE: i32 = 128; // conventional shell: 128 + signal
 
 // I/O buffer sizing
-const READ_CHUNK_SIZE: usize = 8192; // bytes per read
 const AGGREGATE_BUFFER_INITIAL_CAPACITY: usize = 8 * 1024; // 8 KiB
 
+/// How long to keep reading output once the command has exited or been
+/// killed. Processes it left running in the background can otherwise hold the
+/// pipes open indefinitely.
+const IO_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// Limit the number of ExecCommandOutputDelta events emitted per exec call.
 /// Aggregation still collects full output; only the live event stream is capped.
 pub(crate) const MAX_EXEC_OUTPUT_DELTAS_PER_CALL: usize = 10_000;
@@ -52,15 +71,23 @@ pub struct ExecParams {
     pub env: HashMap<String, String>,
     pub with_escalated_permissions: Option<bool>,
     pub justification: Option<String>,
+    /// Output and resource limits; `limits.timeout_ms` applies when
+    /// `timeout_ms` is not set.
+    pub limits: ExecLimits,
 }
 
 impl ExecParams {
     pub fn timeout_duration(&self) -> Duration {
-        Duration::from_millis(self.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS))
+        Duration::from_millis(
+            self.timeout_ms
+                .or(self.limits.timeout_ms)
+                .unwrap_or(DEFAULT_TIMEOUT_MS),
+        )
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SandboxType {
     None,
 
@@ -69,6 +96,9 @@ pub enum SandboxType {
 
     /// Only available on Linux.
     LinuxSeccomp,
+
+    /// A Docker container, see [`crate::config_types::DockerSandbox`].
+    Docker,
 }
 
 #[derive(Clone)]
@@ -76,6 +106,8 @@ pub struct StdoutStream {
     pub sub_id: String,
     pub call_id: String,
     pub tx_event: Sender<Event>,
+    /// Chunk size of the emitted deltas and cap on the retained output.
+    pub output: ExecOutputConfig,
 }
 
 pub async fn process_exec_tool_call(
@@ -83,50 +115,59 @@ pub async fn process_exec_tool_call(
     sandbox_type: SandboxType,
     sandbox_policy: &SandboxPolicy,
     codex_linux_sandbox_exe: &Option<PathBuf>,
+    docker_sandbox: &DockerSandbox,
     stdout_stream: Option<StdoutStream>,
 ) -> Result<ExecToolCallOutput> {
-    let start = Instant::now();
-
-    let raw_output_result: std::result::Result<RawExecToolCallOutput, CodexErr> = match sandbox_type
-    {
-        SandboxType::None => exec(params, sandbox_policy, stdout_stream.clone()).await,
-        SandboxType::MacosSeatbelt => {
-            let timeout = params.timeout_duration();
-            let ExecParams {
-                command, cwd, env, ..
-            } = params;
-            let child = spawn_command_under_seatbelt(
-                command,
-                sandbox_policy,
-                cwd,
-                StdioPolicy::RedirectForShellTool,
-                env,
-            )
-            .await?;
-            consume_truncated_output(child, timeout, stdout_stream.clone()).await
-        }
-        SandboxType::LinuxSeccomp => {
-            let timeout = params.timeout_duration();
-            let ExecParams {
-                command, cwd, env, ..
-            } = params;
+    process_exec_tool_call_with_cancellation(
+        params,
+        sandbox_type,
+        sandbox_policy,
+        codex_linux_sandbox_exe,
+        docker_sandbox,
+        stdout_stream,
+        CancellationToken::new(),
+    )
+    .await
+}
 
-            let codex_linux_sandbox_exe = codex_linux_sandbox_exe
-                .as_ref()
-                .ok_or(CodexErr::LandlockSandboxExecutableNotProvided)?;
-            let child = spawn_command_under_linux_sandbox(
-                codex_linux_sandbox_exe,
-                command,
-                sandbox_policy,
-                cwd,
-                StdioPolicy::RedirectForShellTool,
-                env,
-            )
-            .await?;
+/// Like [`process_exec_tool_call`], but kills the command when `cancel` is
+/// cancelled and returns [`SandboxErr::Cancelled`] with its output so far.
+pub async fn process_exec_tool_call_with_cancellation(
+    params: ExecParams,
+    sandbox_type: SandboxType,
+    sandbox_policy: &SandboxPolicy,
+    codex_linux_sandbox_exe: &Option<PathBuf>,
+    docker_sandbox: &DockerSandbox,
+    stdout_stream: Option<StdoutStream>,
+    cancel: CancellationToken,
+) -> Result<ExecToolCallOutput> {
+    let start = Instant::now();
 
-            consume_truncated_output(child, timeout, stdout_stream).await
-        }
-    };
+    let timeout = params.timeout_duration();
+    let ExecParams {
+        command,
+        cwd,
+        env,
+        limits,
+        ..
+    } = params;
+    let raw_output_result: std::result::Result<RawExecToolCallOutput, CodexErr> =
+        match spawn_exec_child(
+            command,
+            cwd,
+            env,
+            sandbox_type,
+            sandbox_policy,
+            codex_linux_sandbox_exe,
+            docker_sandbox,
+        )
+        .await
+        {
+            Ok(child) => {
+                consume_truncated_output(child, timeout, limits, stdout_stream, cancel).await
+            }
+            Err(err) => Err(err),
+        };
     let duration = start.elapsed();
     match raw_output_result {
         Ok(raw_output) => {
@@ -134,12 +175,23 @@ pub async fn process_exec_tool_call(
             let stderr = raw_output.stderr.from_utf8_lossy();
 
             #[cfg(target_family = "unix")]
-            match raw_output.exit_status.signal() {
-                Some(TIMEOUT_CODE) => return Err(CodexErr::Sandbox(SandboxErr::Timeout)),
-                Some(signal) => {
-                    return Err(CodexErr::Sandbox(SandboxErr::Signal(signal)));
-                }
-                None => {}
+            if let Some(signal) = raw_output.exit_status.signal() {
+                // Hand back whatever the command printed before it was killed
+                // so the caller can still make use of it.
+                let output = Box::new(ExecToolCallOutput {
+                    exit_code: EXIT_CODE_SIGNAL_BASE + signal,
+                    stdout,
+                    stderr,
+                    aggregated_output: raw_output.aggregated_output.from_utf8_lossy(),
+                    duration,
+                });
+                return Err(CodexErr::Sandbox(match signal {
+                    TIMEOUT_CODE => SandboxErr::Timeout { output },
+                    OUTPUT_LIMIT_CODE => SandboxErr::OutputLimit { output },
+                    CANCELLED_CODE => SandboxErr::Cancelled { output },
+                    libc::SIGXCPU => SandboxErr::CpuTimeLimit { output },
+                    signal => SandboxErr::Signal(signal),
+                }));
             }
 
             let exit_code = raw_output.exit_status.code().unwrap_or(-1);
@@ -232,43 +284,88 @@ pub struct ExecToolCallOutput {
     pub duration: Duration,
 }
 
-async fn exec(
-    params: ExecParams,
+/// Spawns `command` under `sandbox_type` with its stdout and stderr piped, in
+/// its own process group so it can be killed together with its descendants.
+pub(crate) async fn spawn_exec_child(
+    command: Vec<String>,
+    cwd: PathBuf,
+    env: HashMap<String, String>,
+    sandbox_type: SandboxType,
     sandbox_policy: &SandboxPolicy,
-    stdout_stream: Option<StdoutStream>,
-) -> Result<RawExecToolCallOutput> {
-    let timeout = params.timeout_duration();
-    let ExecParams {
-        command, cwd, env, ..
-    } = params;
-
-    let (program, args) = command.split_first().ok_or_else(|| {
-        CodexErr::Io(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "command args are empty",
-        ))
-    })?;
-    let arg0 = None;
-    let child = spawn_child_async(
-        PathBuf::from(program),
-        args.into(),
-        arg0,
-        cwd,
-        sandbox_policy,
-        StdioPolicy::RedirectForShellTool,
-        env,
-    )
-    .await?;
-    consume_truncated_output(child, timeout, stdout_stream).await
+    codex_linux_sandbox_exe: &Option<PathBuf>,
+    docker_sandbox: &DockerSandbox,
+) -> Result<Child> {
+    let child = match sandbox_type {
+        SandboxType::None => {
+            let (program, args) = command.split_first().ok_or_else(|| {
+                CodexErr::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "command args are empty",
+                ))
+            })?;
+            let arg0 = None;
+            spawn_child_async(
+                PathBuf::from(program),
+                args.into(),
+                arg0,
+                cwd,
+                sandbox_policy,
+                StdioPolicy::RedirectForShellTool,
+                env,
+            )
+            .await?
+        }
+        SandboxType::MacosSeatbelt => {
+            spawn_command_under_seatbelt(
+                command,
+                sandbox_policy,
+                cwd,
+                StdioPolicy::RedirectForShellTool,
+                env,
+            )
+            .await?
+        }
+        SandboxType::LinuxSeccomp => {
+            let codex_linux_sandbox_exe = codex_linux_sandbox_exe
+                .as_ref()
+                .ok_or(CodexErr::LandlockSandboxExecutableNotProvided)?;
+            spawn_command_under_linux_sandbox(
+                codex_linux_sandbox_exe,
+                command,
+                sandbox_policy,
+                cwd,
+                StdioPolicy::RedirectForShellTool,
+                env,
+            )
+            .await?
+        }
+        SandboxType::Docker => {
+            spawn_command_under_docker(
+                docker_sandbox,
+                command,
+                sandbox_policy,
+                cwd,
+                StdioPolicy::RedirectForShellTool,
+                env,
+            )
+            .await?
+        }
+    };
+    Ok(child)
 }
 
 /// Consumes the output of a child process, truncating it so it is suitable for
-/// use as the output of a `shell` tool call. Also enforces specified timeout.
+/// use as the output of a `shell` tool call. Also enforces specified timeout
+/// and `limits`, and kills the child when `cancel` is cancelled.
 async fn consume_truncated_output(
     mut child: Child,
     timeout: Duration,
+    limits: ExecLimits,
     stdout_stream: Option<StdoutStream>,
+    cancel: CancellationToken,
 ) -> Result<RawExecToolCallOutput> {
+    let process_limits = ProcessLimits::apply(&child, &limits)?;
+
     // Both stdout and stderr were configured with `Stdio::piped()`
     // above, therefore `take()` should normally return `Some`.  If it doesn't
     // we treat it as an exceptional I/O error
@@ -284,51 +381,88 @@ async fn consume_truncated_output(
         ))
     })?;
 
+    let output_config = stdout_stream
+        .as_ref()
+        .map(|stream| stream.output)
+        .unwrap_or_default();
     let (agg_tx, agg_rx) = async_channel::unbounded::<Vec<u8>>();
+    let budget = limits
+        .max_output_bytes
+        .map(|max| Arc::new(OutputBudget::new(max)));
+    let stop_reading = CancellationToken::new();
 
     let stdout_handle = tokio::spawn(read_capped(
         BufReader::new(stdout_reader),
         stdout_stream.clone(),
         false,
         Some(agg_tx.clone()),
+        budget.clone(),
+        stop_reading.clone(),
     ));
     let stderr_handle = tokio::spawn(read_capped(
         BufReader::new(stderr_reader),
         stdout_stream.clone(),
         true,
         Some(agg_tx.clone()),
+        budget.clone(),
+        stop_reading.clone(),
     ));
 
-    let exit_status = tokio::select! {
+    let output_exceeded = async {
+        match &budget {
+            Some(budget) => budget.exceeded.notified().await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let mut exit_status = tokio::select! {
         result = tokio::time::timeout(timeout, child.wait()) => {
             match result {
                 Ok(Ok(exit_status)) => exit_status,
                 Ok(e) => e?,
                 Err(_) => {
                     // timeout
-                    child.start_kill()?;
+                    process_limits.kill(&mut child)?;
                     // Debatable whether `child.wait().await` should be called here.
                     synthetic_exit_status(EXIT_CODE_SIGNAL_BASE + TIMEOUT_CODE)
                 }
             }
         }
-        _ = tokio::signal::ctrl_c() => {
-            child.start_kill()?;
-            synthetic_exit_status(EXIT_CODE_SIGNAL_BASE + SIGKILL_CODE)
+        _ = output_exceeded => {
+            process_limits.kill(&mut child)?;
+            synthetic_exit_status(EXIT_CODE_SIGNAL_BASE + OUTPUT_LIMIT_CODE)
+        }
+        _ = cancel.cancelled() => {
+            process_limits.kill(&mut child)?;
+            synthetic_exit_status(EXIT_CODE_SIGNAL_BASE + CANCELLED_CODE)
         }
     };
+    // Output past the limit was dropped even if the command managed to exit
+    // on its own, so report it as truncated either way.
+    if budget.as_ref().is_some_and(|budget| budget.is_exceeded()) {
+        exit_status = synthetic_exit_status(EXIT_CODE_SIGNAL_BASE + OUTPUT_LIMIT_CODE);
+    }
 
-    let stdout = stdout_handle.await??;
-    let stderr = stderr_handle.await??;
+    let readers = async { (stdout_handle.await, stderr_handle.await) };
+    tokio::pin!(readers);
+    let (stdout, stderr) = match tokio::time::timeout(IO_DRAIN_TIMEOUT, &mut readers).await {
+        Ok(joined) => joined,
+        Err(_) => {
+            stop_reading.cancel();
+            readers.await
+        }
+    };
+    let stdout = stdout??;
+    let stderr = stderr??;
 
     drop(agg_tx);
 
-    let mut combined_buf = Vec::with_capacity(AGGREGATE_BUFFER_INITIAL_CAPACITY);
+    let mut combined_buf = CappedBuffer::new(output_config.max_buffer_bytes);
     while let Ok(chunk) = agg_rx.recv().await {
-        append_all(&mut combined_buf, &chunk);
+        combined_buf.push(&chunk);
     }
     let aggregated_output = StreamOutput {
-        text: combined_buf,
+        text: combined_buf.into_bytes(),
         truncated_after_lines: None,
     };
 
@@ -345,15 +479,26 @@ async fn read_capped<R: AsyncRead + Unpin + Send + 'static>(
     stream: Option<StdoutStream>,
     is_stderr: bool,
     aggregate_tx: Option<Sender<Vec<u8>>>,
+    budget: Option<Arc<OutputBudget>>,
+    stop: CancellationToken,
 ) -> io::Result<StreamOutput<Vec<u8>>> {
-    let mut buf = Vec::with_capacity(AGGREGATE_BUFFER_INITIAL_CAPACITY);
-    let mut tmp = [0u8; READ_CHUNK_SIZE];
+    let output_config = stream
+        .as_ref()
+        .map(|stream| stream.output)
+        .unwrap_or_default();
+    let mut buf = CappedBuffer::new(output_config.max_buffer_bytes);
+    let mut tmp = vec![0u8; output_config.chunk_bytes.max(1)];
     let mut emitted_deltas: usize = 0;
 
-    // No caps: append all bytes
-
     loop {
-        let n = reader.read(&mut tmp).await?;
+        let read = tokio::select! {
+            read = reader.read(&mut tmp) => read?,
+            _ = stop.cancelled() => break,
+        };
+        let n = match &budget {
+            Some(budget) => budget.take(read),
+            None => read,
+        };
         if n == 0 {
             break;
         }
@@ -384,16 +529,109 @@ async fn read_capped<R: AsyncRead + Unpin + Send + 'static>(
             let _ = tx.send(tmp[..n].to_vec()).await;
         }
 
-        append_all(&mut buf, &tmp[..n]);
+        buf.push(&tmp[..n]);
+        if n < read {
+            // Over the output limit; the command is about to be killed.
+            break;
+        }
         // Continue reading to EOF to avoid back-pressure
     }
 
     Ok(StreamOutput {
-        text: buf,
+        text: buf.into_bytes(),
         truncated_after_lines: None,
     })
 }
 
+/// Output a command may still write before it is killed for exceeding
+/// `max_output_bytes`, shared by its stdout and stderr readers.
+struct OutputBudget {
+    remaining: AtomicUsize,
+    exceeded_flag: AtomicBool,
+    exceeded: Notify,
+}
+
+impl OutputBudget {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            remaining: AtomicUsize::new(max_bytes),
+            exceeded_flag: AtomicBool::new(false),
+            exceeded: Notify::new(),
+        }
+    }
+
+    /// Claim `len` bytes of the budget, returning how many of them fit.
+    fn take(&self, len: usize) -> usize {
+        let (Ok(before) | Err(before)) =
+            self.remaining
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |remaining| {
+                    Some(remaining.saturating_sub(len))
+                });
+        let granted = before.min(len);
+        if granted < len {
+            self.exceeded_flag.store(true, Ordering::SeqCst);
+            self.exceeded.notify_one();
+        }
+        granted
+    }
+
+    fn is_exceeded(&self) -> bool {
+        self.exceeded_flag.load(Ordering::SeqCst)
+    }
+}
+
+/// Output retained for an exec stream, bounded to roughly `cap` bytes. The
+/// first half of the cap keeps the start of the output and the second half
+/// its most recent bytes; anything in between is dropped and replaced by a
+/// marker.
+struct CappedBuffer {
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    cap: usize,
+    omitted: usize,
+}
+
+impl CappedBuffer {
+    fn new(cap: usize) -> Self {
+        Self {
+            head: Vec::with_capacity(AGGREGATE_BUFFER_INITIAL_CAPACITY.min(cap)),
+            tail: VecDeque::new(),
+            cap,
+            omitted: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        let head_cap = self.cap / 2;
+        let tail_cap = self.cap - head_cap;
+        let to_head = head_cap.saturating_sub(self.head.len()).min(bytes.len());
+        append_all(&mut self.head, &bytes[..to_head]);
+
+        let mut rest = &bytes[to_head..];
+        if rest.len() > tail_cap {
+            self.omitted += self.tail.len() + rest.len() - tail_cap;
+            self.tail.clear();
+            rest = &rest[rest.len() - tail_cap..];
+        }
+        self.tail.extend(rest);
+        if self.tail.len() > tail_cap {
+            let excess = self.tail.len() - tail_cap;
+            self.tail.drain(..excess);
+            self.omitted += excess;
+        }
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        let mut out = self.head;
+        if self.omitted > 0 {
+            let marker = format!("\n[... {} bytes omitted ...]\n", self.omitted);
+            append_all(&mut out, marker.as_bytes());
+        }
+        out.extend(self.tail);
+        out
+    }
+}
+
 #[cfg(unix)]
 fn synthetic_exit_status(code: i32) -> ExitStatus {
     use std::os::unix::process::ExitStatusExt;
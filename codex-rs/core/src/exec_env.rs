@@ -25,12 +25,29 @@ where
         ShellEnvironmentPolicyInherit::All => vars.into_iter().collect(),
         ShellEnvironmentPolicyInherit::None => HashMap::new(),
         ShellEnvironmentPolicyInherit::Core => {
+            // `SYSTEMROOT` and the variables after it are needed by Windows programs.
             const CORE_VARS: &[&str] = &[
-                "HOME", "LOGNAME", "PATH", "SHELL", "USER", "USERNAME", "TMPDIR", "TEMP", "TMP",
+                "HOME",
+                "LOGNAME",
+                "PATH",
+                "SHELL",
+                "USER",
+                "USERNAME",
+                "TMPDIR",
+                "TEMP",
+                "TMP",
+                "SYSTEMROOT",
+                "WINDIR",
+                "COMSPEC",
+                "PATHEXT",
+                "USERPROFILE",
+                "APPDATA",
+                "LOCALAPPDATA",
+                "PROGRAMDATA",
             ];
-            let allow: HashSet<&str> = CORE_VARS.iter().copied().collect();
+            let allow: HashSet<String> = CORE_VARS.iter().map(|k| env_key(k)).collect();
             vars.into_iter()
-                .filter(|(k, _)| allow.contains(k.as_str()))
+                .filter(|(k, _)| allow.contains(&env_key(k)))
                 .collect()
         }
     };
@@ -57,6 +74,8 @@ where
 
     // Step 4 – Apply user-provided overrides.
     for (key, val) in &policy.r#set {
+        // `set.PATH` replaces the `Path` Windows reports.
+        env_map.retain(|k, _| env_key(k) != env_key(key));
         env_map.insert(key.clone(), val.clone());
     }
 
@@ -68,6 +87,15 @@ where
     env_map
 }
 
+/// `name` as the OS compares it: Windows ignores case in variable names.
+fn env_key(name: &str) -> String {
+    if cfg!(windows) {
+        name.to_ascii_uppercase()
+    } else {
+        name.to_string()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -101,6 +129,28 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_core_inherit_keeps_windows_vars() {
+        let vars = make_vars(&[
+            ("SYSTEMROOT", "C:\\Windows"),
+            ("PATHEXT", ".COM;.EXE"),
+            ("FOO", "bar"),
+        ]);
+
+        let policy = ShellEnvironmentPolicy {
+            inherit: ShellEnvironmentPolicyInherit::Core,
+            ..Default::default()
+        };
+        let result = populate_env(vars, &policy);
+
+        let expected: HashMap<String, String> = hashmap! {
+            "SYSTEMROOT".to_string() => "C:\\Windows".to_string(),
+            "PATHEXT".to_string() => ".COM;.EXE".to_string(),
+        };
+
+        assert_eq!(result, expected);
+    }
+
     #[test]
     fn test_include_only() {
         let vars = make_vars(&[("PATH", "/usr/bin"), ("FOO", "bar")]);
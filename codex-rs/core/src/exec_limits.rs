@@ -0,0 +1,196 @@
+//! OS-level enforcement of the CPU and memory limits in [`ExecLimits`], and
+//! termination of an exec command together with everything it started.
+//!
+//! On Linux the limits are installed as rlimits on the freshly spawned child;
+//! on Windows the child is placed in a Job Object. Other platforms only
+//! enforce the timeout and output limits, which are handled in `exec.rs`.
+
+use std::io;
+
+use tokio::process::Child;
+
+use crate::config_types::ExecLimits;
+
+/// Keeps whatever enforces the limits of one child alive for as long as the
+/// child is being supervised.
+pub(crate) struct ProcessLimits {
+    #[cfg(windows)]
+    job: Option<job::Job>,
+}
+
+impl ProcessLimits {
+    /// Install the CPU and memory limits of `limits` on `child`.
+    pub(crate) fn apply(
+        #[cfg_attr(not(any(target_os = "linux", windows)), allow(unused_variables))] child: &Child,
+        limits: &ExecLimits,
+    ) -> io::Result<Self> {
+        #[cfg(target_os = "linux")]
+        if let Some(pid) = child.id() {
+            set_rlimits(pid, limits)?;
+        }
+
+        #[cfg(not(any(target_os = "linux", windows)))]
+        if limits.cpu_time_secs.is_some() || limits.memory_bytes.is_some() {
+            tracing::warn!("exec CPU and memory limits are not supported on this platform");
+        }
+
+        Ok(Self {
+            #[cfg(windows)]
+            job: job::Job::for_child(child, limits)?,
+        })
+    }
+
+    /// Kill `child` along with the processes it started.
+    pub(crate) fn kill(&self, child: &mut Child) -> io::Result<()> {
+        // Shell tool commands run in their own process group (see
+        // `spawn_child_async`), so signalling the group also reaches anything
+        // they left running in the background.
+        #[cfg(unix)]
+        if let Some(pid) = child.id()
+            && let Ok(pgid) = libc::pid_t::try_from(pid)
+        {
+            unsafe {
+                libc::killpg(pgid, libc::SIGKILL);
+            }
+        }
+
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            return job.terminate();
+        }
+
+        child.start_kill()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_rlimits(pid: u32, limits: &ExecLimits) -> io::Result<()> {
+    let pid = libc::pid_t::try_from(pid).map_err(io::Error::other)?;
+    let check = |rc: libc::c_int| {
+        if rc == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    };
+
+    if let Some(secs) = limits.cpu_time_secs {
+        // The soft limit delivers SIGXCPU, which is reported as a CPU limit;
+        // the hard limit kills the command should it ignore that signal.
+        let limit = libc::rlimit {
+            rlim_cur: secs,
+            rlim_max: secs.saturating_add(1),
+        };
+        check(unsafe { libc::prlimit(pid, libc::RLIMIT_CPU, &limit, std::ptr::null_mut()) })?;
+    }
+    if let Some(bytes) = limits.memory_bytes {
+        let limit = libc::rlimit {
+            rlim_cur: bytes,
+            rlim_max: bytes,
+        };
+        check(unsafe { libc::prlimit(pid, libc::RLIMIT_AS, &limit, std::ptr::null_mut()) })?;
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+mod job {
+    use std::io;
+    use std::os::windows::io::RawHandle;
+
+    use tokio::process::Child;
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::System::JobObjects::AssignProcessToJobObject;
+    use windows_sys::Win32::System::JobObjects::CreateJobObjectW;
+    use windows_sys::Win32::System::JobObjects::JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+    use windows_sys::Win32::System::JobObjects::JOB_OBJECT_LIMIT_PROCESS_TIME;
+    use windows_sys::Win32::System::JobObjects::JOBOBJECT_EXTENDED_LIMIT_INFORMATION;
+    use windows_sys::Win32::System::JobObjects::JobObjectExtendedLimitInformation;
+    use windows_sys::Win32::System::JobObjects::SetInformationJobObject;
+    use windows_sys::Win32::System::JobObjects::TerminateJobObject;
+
+    use crate::config_types::ExecLimits;
+
+    /// Exit code of processes killed through [`Job::terminate`].
+    const KILLED_EXIT_CODE: u32 = 1;
+
+    /// Job Object owning an exec command and the processes it starts.
+    pub(super) struct Job(HANDLE);
+
+    // The handle is only used through thread-safe Win32 calls.
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        /// Place `child` in a new job. The job is needed to kill the whole
+        /// process tree, but failing to create one is only fatal when CPU or
+        /// memory limits were requested.
+        pub(super) fn for_child(child: &Child, limits: &ExecLimits) -> io::Result<Option<Self>> {
+            let Some(process) = child.raw_handle() else {
+                return Ok(None);
+            };
+            match Self::new(process, limits) {
+                Ok(job) => Ok(Some(job)),
+                Err(err) if limits.cpu_time_secs.is_none() && limits.memory_bytes.is_none() => {
+                    tracing::warn!("failed to place exec command in a job object: {err}");
+                    Ok(None)
+                }
+                Err(err) => Err(err),
+            }
+        }
+
+        fn new(process: RawHandle, limits: &ExecLimits) -> io::Result<Self> {
+            let handle = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+            if handle.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let job = Self(handle);
+
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+            if let Some(secs) = limits.cpu_time_secs {
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_TIME;
+                // Measured in 100-nanosecond ticks.
+                info.BasicLimitInformation.PerProcessUserTimeLimit =
+                    i64::try_from(secs.saturating_mul(10_000_000)).unwrap_or(i64::MAX);
+            }
+            if let Some(bytes) = limits.memory_bytes {
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+                info.ProcessMemoryLimit = usize::try_from(bytes).unwrap_or(usize::MAX);
+            }
+            if info.BasicLimitInformation.LimitFlags != 0 {
+                let ok = unsafe {
+                    SetInformationJobObject(
+                        job.0,
+                        JobObjectExtendedLimitInformation,
+                        (&raw const info).cast(),
+                        size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                    )
+                };
+                if ok == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            if unsafe { AssignProcessToJobObject(job.0, process) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(job)
+        }
+
+        pub(super) fn terminate(&self) -> io::Result<()> {
+            if unsafe { TerminateJobObject(self.0, KILLED_EXIT_CODE) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bytes::Bytes;
+use eventsource_stream::Eventsource;
+use futures::Stream;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tracing::trace;
+use uuid::Uuid;
+
+use crate::ModelProviderInfo;
+use crate::anthropic::split_data_url;
+use crate::chat_completions::post_sse_with_retries;
+use crate::client_common::Prompt;
+use crate::client_common::ResponseEvent;
+use crate::client_common::ResponseStream;
+use crate::error::CodexErr;
+use crate::error::Result;
+use crate::model_family::ModelFamily;
+use crate::openai_tools::create_tools_json_for_gemini_api;
+use crate::protocol::TokenUsage;
+use codex_protocol::models::ContentItem;
+use codex_protocol::models::ReasoningItemContent;
+use codex_protocol::models::ResponseItem;
+
+/// Implementation for the Gemini `streamGenerateContent` API.
+pub(crate) async fn stream_gemini(
+    prompt: &Prompt,
+    model_family: &ModelFamily,
+    max_output_tokens: Option<u64>,
+    client: &reqwest::Client,
+    provider: &ModelProviderInfo,
+) -> Result<ResponseStream> {
+    let contents = build_contents(&prompt.get_formatted_input());
+    let tools_json = create_tools_json_for_gemini_api(&prompt.tools)?;
+
+    let mut payload = json!({
+        "systemInstruction": {
+            "parts": [{"text": prompt.get_full_instructions_for_api(model_family, provider.wire_api)}],
+        },
+        "contents": contents,
+    });
+    if !tools_json.is_empty() {
+        payload["tools"] = json!(tools_json);
+    }
+    if let Some(max_output_tokens) = max_output_tokens {
+        payload["generationConfig"] = json!({"maxOutputTokens": max_output_tokens});
+    }
+
+    let resp = post_sse_with_retries(client, provider, &None, &model_family.slug, &payload).await?;
+    let (tx_event, rx_event) = mpsc::channel::<Result<ResponseEvent>>(1600);
+    let stream = resp.bytes_stream().map_err(CodexErr::Reqwest);
+    tokio::spawn(process_gemini_sse(
+        stream,
+        tx_event,
+        provider.stream_idle_timeout(),
+    ));
+    Ok(ResponseStream { rx_event })
+}
+
+/// Convert the conversation history into Gemini `contents`. Gemini uses the
+/// roles `user` and `model`, and a `functionResponse` is matched to its call
+/// by function name rather than by id, so call ids are resolved here.
+fn build_contents(input: &[ResponseItem]) -> Vec<serde_json::Value> {
+    let mut contents = Vec::<(&str, Vec<serde_json::Value>)>::new();
+    let mut push = |role: &'static str, part: serde_json::Value| match contents.last_mut() {
+        Some((last_role, parts)) if *last_role == role => parts.push(part),
+        _ => contents.push((role, vec![part])),
+    };
+    let mut call_names = HashMap::<&str, &str>::new();
+
+    for item in input {
+        match item {
+            ResponseItem::Message { role, content, .. } => {
+                let role = if role == "assistant" { "model" } else { "user" };
+                for c in content {
+                    match c {
+                        ContentItem::InputText { text } | ContentItem::OutputText { text } => {
+                            if !text.is_empty() {
+                                push(role, json!({"text": text}));
+                            }
+                        }
+                        ContentItem::InputImage { image_url } => {
+                            if let Some((mime_type, data)) = split_data_url(image_url) {
+                                push(
+                                    role,
+                                    json!({"inlineData": {"mimeType": mime_type, "data": data}}),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            ResponseItem::FunctionCall {
+                name,
+                arguments,
+                call_id,
+                ..
+            } => {
+                call_names.insert(call_id, name);
+                let args = serde_json::from_str::<serde_json::Value>(arguments)
+                    .unwrap_or_else(|_| json!({}));
+                push(
+                    "model",
+                    json!({"functionCall": {"name": name, "args": args}}),
+                );
+            }
+            ResponseItem::FunctionCallOutput { call_id, output } => {
+                let name = call_names
+                    .get(call_id.as_str())
+                    .copied()
+                    .unwrap_or_default();
+                push(
+                    "user",
+                    json!({"functionResponse": {
+                        "name": name,
+                        "response": {"content": output.content},
+                    }}),
+                );
+            }
+            ResponseItem::Reasoning { .. }
+            | ResponseItem::LocalShellCall { .. }
+            | ResponseItem::CustomToolCall { .. }
+            | ResponseItem::CustomToolCallOutput { .. }
+            | ResponseItem::WebSearchCall { .. }
+            | ResponseItem::Other => {
+                // Omit these items from the conversation history.
+                continue;
+            }
+        }
+    }
+
+    contents
+        .into_iter()
+        .map(|(role, parts)| json!({"role": role, "parts": parts}))
+        .collect()
+}
+
+/// SSE processor for Gemini. Every event carries a full
+/// `GenerateContentResponse` chunk and the stream simply ends after the last
+/// one, so the final items and `Completed` are emitted when it closes.
+async fn process_gemini_sse<S>(
+    stream: S,
+    tx_event: mpsc::Sender<Result<ResponseEvent>>,
+    idle_timeout: Duration,
+) where
+    S: Stream<Item = Result<Bytes>> + Unpin,
+{
+    let mut stream = stream.eventsource();
+    let mut assistant_text = String::new();
+    let mut reasoning_text = String::new();
+    let mut response_id = String::new();
+    let mut token_usage = None;
+    let mut created = false;
+
+    loop {
+        let sse = match timeout(idle_timeout, stream.next()).await {
+            Ok(Some(Ok(ev))) => ev,
+            Ok(Some(Err(e))) => {
+                let _ = tx_event
+                    .send(Err(CodexErr::Stream(e.to_string(), None)))
+                    .await;
+                return;
+            }
+            Ok(None) => {
+                if !reasoning_text.is_empty() {
+                    let item = ResponseItem::Reasoning {
+                        id: String::new(),
+                        summary: Vec::new(),
+                        content: Some(vec![ReasoningItemContent::ReasoningText {
+                            text: std::mem::take(&mut reasoning_text),
+                        }]),
+                        encrypted_content: None,
+                    };
+                    let _ = tx_event.send(Ok(ResponseEvent::OutputItemDone(item))).await;
+                }
+                if !assistant_text.is_empty() {
+                    let item = ResponseItem::Message {
+                        id: None,
+                        role: "assistant".to_string(),
+                        content: vec![ContentItem::OutputText {
+                            text: std::mem::take(&mut assistant_text),
+                        }],
+                    };
+                    let _ = tx_event.send(Ok(ResponseEvent::OutputItemDone(item))).await;
+                }
+                let _ = tx_event
+                    .send(Ok(ResponseEvent::Completed {
+                        response_id,
+                        token_usage,
+                    }))
+                    .await;
+                return;
+            }
+            Err(_) => {
+                let _ = tx_event
+                    .send(Err(CodexErr::Stream(
+                        "idle timeout waiting for SSE".into(),
+                        None,
+                    )))
+                    .await;
+                return;
+            }
+        };
+
+        let chunk: serde_json::Value = match serde_json::from_str(&sse.data) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        trace!("gemini received SSE chunk: {chunk:?}");
+
+        if let Some(message) = chunk["error"]["message"].as_str() {
+            let _ = tx_event
+                .send(Err(CodexErr::Stream(message.to_string(), None)))
+                .await;
+            return;
+        }
+
+        if !created {
+            created = true;
+            let _ = tx_event.send(Ok(ResponseEvent::Created)).await;
+        }
+        if let Some(id) = chunk["responseId"].as_str() {
+            response_id = id.to_string();
+        }
+        if let Some(usage) = chunk.get("usageMetadata") {
+            token_usage = Some(parse_usage(usage));
+        }
+
+        let parts = chunk["candidates"][0]["content"]["parts"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        for part in parts {
+            if let Some(call) = part.get("functionCall") {
+                let item = ResponseItem::FunctionCall {
+                    id: None,
+                    name: call["name"].as_str().unwrap_or_default().to_string(),
+                    arguments: call.get("args").unwrap_or(&json!({})).to_string(),
+                    // Gemini does not assign ids to function calls.
+                    call_id: format!("call_{}", Uuid::new_v4().simple()),
+                };
+                let _ = tx_event.send(Ok(ResponseEvent::OutputItemDone(item))).await;
+            } else if let Some(text) = part["text"].as_str() {
+                if part["thought"].as_bool() == Some(true) {
+                    reasoning_text.push_str(text);
+                    let _ = tx_event
+                        .send(Ok(ResponseEvent::ReasoningContentDelta(text.to_string())))
+                        .await;
+                } else {
+                    assistant_text.push_str(text);
+                    let _ = tx_event
+                        .send(Ok(ResponseEvent::OutputTextDelta(text.to_string())))
+                        .await;
+                }
+            }
+        }
+    }
+}
+
+fn parse_usage(usage: &serde_json::Value) -> TokenUsage {
+    let input_tokens = usage["promptTokenCount"].as_u64().unwrap_or_default();
+    let output_tokens = usage["candidatesTokenCount"].as_u64().unwrap_or_default();
+    let reasoning_output_tokens = usage["thoughtsTokenCount"].as_u64();
+    TokenUsage {
+        input_tokens,
+        cached_input_tokens: usage["cachedContentTokenCount"].as_u64(),
+        output_tokens,
+        reasoning_output_tokens,
+        total_tokens: usage["totalTokenCount"]
+            .as_u64()
+            .unwrap_or(input_tokens + output_tokens + reasoning_output_tokens.unwrap_or_default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use codex_protocol::models::FunctionCallOutputPayload;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn resolves_function_response_names() {
+        let input = vec![
+            ResponseItem::Message {
+                id: None,
+                role: "user".to_string(),
+                content: vec![ContentItem::InputText {
+                    text: "list files".to_string(),
+                }],
+            },
+            ResponseItem::FunctionCall {
+                id: None,
+                name: "shell".to_string(),
+                arguments: r#"{"command":["ls"]}"#.to_string(),
+                call_id: "call_1".to_string(),
+            },
+            ResponseItem::FunctionCallOutput {
+                call_id: "call_1".to_string(),
+                output: FunctionCallOutputPayload {
+                    content: "README.md".to_string(),
+                    success: Some(true),
+                },
+            },
+        ];
+
+        assert_eq!(
+            build_contents(&input),
+            vec![
+                json!({"role": "user", "parts": [{"text": "list files"}]}),
+                json!({"role": "model", "parts": [
+                    {"functionCall": {"name": "shell", "args": {"command": ["ls"]}}},
+                ]}),
+                json!({"role": "user", "parts": [
+                    {"functionResponse": {"name": "shell", "response": {"content": "README.md"}}},
+                ]}),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn maps_chunks_to_events() {
+        let body = [
+            r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"Hel"}]}}],"responseId":"r1"}"#,
+            r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"lo"},{"functionCall":{"name":"shell","args":{"command":["ls"]}}}]},"finishReason":"STOP"}],"usageMetadata":{"promptTokenCount":12,"candidatesTokenCount":5,"totalTokenCount":17}}"#,
+        ]
+        .iter()
+        .map(|data| format!("data: {data}\r\n\r\n"))
+        .collect::<String>();
+
+        let (tx, mut rx) = mpsc::channel(16);
+        let chunks = vec![Ok::<_, CodexErr>(Bytes::from(body))];
+        process_gemini_sse(futures::stream::iter(chunks), tx, Duration::from_secs(5)).await;
+        let mut events = Vec::new();
+        while let Some(ev) = rx.recv().await {
+            events.push(ev.unwrap());
+        }
+
+        assert_eq!(events.len(), 6);
+        assert!(matches!(events[0], ResponseEvent::Created));
+        assert!(matches!(&events[1], ResponseEvent::OutputTextDelta(d) if d == "Hel"));
+        assert!(matches!(&events[2], ResponseEvent::OutputTextDelta(d) if d == "lo"));
+        assert!(matches!(
+            &events[3],
+            ResponseEvent::OutputItemDone(ResponseItem::FunctionCall { name, arguments, .. })
+                if name == "shell" && arguments == r#"{"command":["ls"]}"#
+        ));
+        assert!(matches!(
+            &events[4],
+            ResponseEvent::OutputItemDone(ResponseItem::Message { content, .. })
+                if content == &vec![ContentItem::OutputText { text: "Hello".to_string() }]
+        ));
+        match &events[5] {
+            ResponseEvent::Completed {
+                response_id,
+                token_usage,
+            } => {
+                assert_eq!(response_id, "r1");
+                assert_eq!(token_usage.as_ref().unwrap().total_tokens, 17);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,759 @@
+//! Structured git tools: `git_status`, `git_diff`, `git_commit` and
+//! `git_stash`. Status and diffs are parsed from git's machine-readable
+//! output so the model gets JSON rather than free-form text. Commits and
+//! stash changes modify the repository, so they are turned into a fixed git
+//! command that goes through the same approval and sandbox flow as `shell`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use codex_protocol::models::FunctionCallOutputPayload;
+use serde::Deserialize;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+
+pub(crate) const GIT_STATUS_TOOL_NAME: &str = "git_status";
+pub(crate) const GIT_DIFF_TOOL_NAME: &str = "git_diff";
+pub(crate) const GIT_COMMIT_TOOL_NAME: &str = "git_commit";
+pub(crate) const GIT_STASH_TOOL_NAME: &str = "git_stash";
+
+const GIT_TOOL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Files returned per `git_diff` page unless the call asks for fewer.
+const DEFAULT_DIFF_PAGE_FILES: usize = 20;
+
+/// Patches longer than this are cut off; ask for the file on its own page or
+/// read it with `shell` to see the rest.
+const MAX_FILE_PATCH_BYTES: usize = 32 * 1024;
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct GitDiffParams {
+    /// Diff the index against `rev` (default `HEAD`) instead of the working
+    /// tree against the index.
+    #[serde(default)]
+    pub(crate) staged: bool,
+    /// Commit, or `a..b` range, to diff against.
+    #[serde(default)]
+    pub(crate) rev: Option<String>,
+    #[serde(default)]
+    pub(crate) paths: Vec<String>,
+    /// Index of the first changed file to return.
+    #[serde(default)]
+    pub(crate) offset: usize,
+    #[serde(default)]
+    pub(crate) limit: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct GitCommitParams {
+    pub(crate) message: String,
+    /// Stage every modified and deleted tracked file first.
+    #[serde(default)]
+    pub(crate) all: bool,
+    /// Commit only these paths.
+    #[serde(default)]
+    pub(crate) paths: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum GitStashAction {
+    Push,
+    Pop,
+    Apply,
+    Drop,
+    List,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct GitStashParams {
+    pub(crate) action: GitStashAction,
+    #[serde(default)]
+    pub(crate) message: Option<String>,
+    #[serde(default)]
+    pub(crate) include_untracked: bool,
+    /// Stash entry for `pop`, `apply` and `drop`; the latest when unset.
+    #[serde(default)]
+    pub(crate) index: Option<u32>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum GitChangeKind {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+    Copied,
+    TypeChanged,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct GitFileStatus {
+    pub(crate) path: String,
+    pub(crate) change: GitChangeKind,
+    /// Original path of a renamed or copied file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) from: Option<String>,
+}
+
+/// Response of `git_status`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct GitStatus {
+    /// `None` on a detached HEAD.
+    pub(crate) branch: Option<String>,
+    /// `None` before the first commit.
+    pub(crate) commit: Option<String>,
+    pub(crate) upstream: Option<String>,
+    pub(crate) ahead: u32,
+    pub(crate) behind: u32,
+    pub(crate) staged: Vec<GitFileStatus>,
+    pub(crate) unstaged: Vec<GitFileStatus>,
+    pub(crate) untracked: Vec<String>,
+    /// Files with merge conflicts.
+    pub(crate) conflicted: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct GitDiffFile {
+    pub(crate) path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) from: Option<String>,
+    /// `None` for binary files.
+    pub(crate) additions: Option<u64>,
+    pub(crate) deletions: Option<u64>,
+    pub(crate) patch: String,
+    pub(crate) truncated: bool,
+}
+
+/// Response of `git_diff`: one page of changed files.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct GitDiffPage {
+    pub(crate) total_files: usize,
+    pub(crate) files: Vec<GitDiffFile>,
+    /// `offset` of the next page, if there is one.
+    pub(crate) next_offset: Option<usize>,
+}
+
+/// What the session should do with a git tool call.
+#[derive(Debug, PartialEq)]
+pub(crate) enum GitToolCall {
+    /// The call was answered without changing the repository.
+    Output(FunctionCallOutputPayload),
+    /// The call changes the repository: run this command with approval.
+    Exec(Vec<String>),
+}
+
+/// Handle a call to one of the git tools in `cwd`.
+pub(crate) async fn handle_tool_call(name: &str, arguments: &str, cwd: &Path) -> GitToolCall {
+    let result = match name {
+        GIT_STATUS_TOOL_NAME => git_status(cwd).await.and_then(|status| to_json(&status)),
+        GIT_DIFF_TOOL_NAME => match parse_arguments::<GitDiffParams>(arguments) {
+            Ok(params) => git_diff(cwd, &params).await.and_then(|page| to_json(&page)),
+            Err(e) => Err(e),
+        },
+        GIT_COMMIT_TOOL_NAME => {
+            match parse_arguments::<GitCommitParams>(arguments).and_then(|p| commit_command(&p)) {
+                Ok(command) => return GitToolCall::Exec(command),
+                Err(e) => Err(e),
+            }
+        }
+        GIT_STASH_TOOL_NAME => match parse_arguments::<GitStashParams>(arguments) {
+            Ok(GitStashParams {
+                action: GitStashAction::List,
+                ..
+            }) => git_stash_list(cwd).await.and_then(|list| to_json(&list)),
+            Ok(params) => return GitToolCall::Exec(stash_command(&params)),
+            Err(e) => Err(e),
+        },
+        _ => Err(format!("unsupported call: {name}")),
+    };
+    GitToolCall::Output(match result {
+        Ok(content) => FunctionCallOutputPayload {
+            content,
+            success: Some(true),
+        },
+        Err(content) => FunctionCallOutputPayload {
+            content,
+            success: Some(false),
+        },
+    })
+}
+
+async fn git_status(cwd: &Path) -> Result<GitStatus, String> {
+    let output = run_git(
+        cwd,
+        &[
+            "status",
+            "--porcelain=v2",
+            "--branch",
+            "-z",
+            "--untracked-files=all",
+        ],
+    )
+    .await?;
+    Ok(parse_status(&String::from_utf8_lossy(&output)))
+}
+
+/// Parse the output of `git status --porcelain=v2 --branch -z`.
+fn parse_status(output: &str) -> GitStatus {
+    let mut status = GitStatus::default();
+    let mut fields = output.split('\0');
+    while let Some(entry) = fields.next() {
+        if let Some(header) = entry.strip_prefix("# ") {
+            let (key, value) = header.split_once(' ').unwrap_or((header, ""));
+            match key {
+                "branch.oid" if value != "(initial)" => status.commit = Some(value.to_string()),
+                "branch.head" if value != "(detached)" => status.branch = Some(value.to_string()),
+                "branch.upstream" => status.upstream = Some(value.to_string()),
+                "branch.ab" => {
+                    for count in value.split(' ') {
+                        if let Some(ahead) = count.strip_prefix('+') {
+                            status.ahead = ahead.parse().unwrap_or_default();
+                        } else if let Some(behind) = count.strip_prefix('-') {
+                            status.behind = behind.parse().unwrap_or_default();
+                        }
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        // Ordinary entries have 8 fields before the path, renames and copies
+        // 9 and are followed by the original path, unmerged entries 10.
+        let (kind, rest) = entry.split_at(entry.len().min(2));
+        let (fields_before_path, from) = match kind.trim_end() {
+            "1" => (7, None),
+            "2" => (8, fields.next().map(str::to_string)),
+            "u" => {
+                if let Some(path) = rest.splitn(10, ' ').nth(9) {
+                    status.conflicted.push(path.to_string());
+                }
+                continue;
+            }
+            "?" => {
+                status.untracked.push(rest.to_string());
+                continue;
+            }
+            _ => continue,
+        };
+        let parts: Vec<&str> = rest.splitn(fields_before_path + 1, ' ').collect();
+        let (Some(xy), Some(path)) = (parts.first(), parts.get(fields_before_path)) else {
+            continue;
+        };
+        let mut xy = xy.chars();
+        let (index, worktree) = (xy.next(), xy.next());
+        for (code, files) in [
+            (index, &mut status.staged),
+            (worktree, &mut status.unstaged),
+        ] {
+            if let Some(change) = code.and_then(change_kind) {
+                let from = matches!(change, GitChangeKind::Renamed | GitChangeKind::Copied)
+                    .then(|| from.clone())
+                    .flatten();
+                files.push(GitFileStatus {
+                    path: path.to_string(),
+                    change,
+                    from,
+                });
+            }
+        }
+    }
+    status
+}
+
+fn change_kind(code: char) -> Option<GitChangeKind> {
+    match code {
+        'A' => Some(GitChangeKind::Added),
+        'M' => Some(GitChangeKind::Modified),
+        'D' => Some(GitChangeKind::Deleted),
+        'R' => Some(GitChangeKind::Renamed),
+        'C' => Some(GitChangeKind::Copied),
+        'T' => Some(GitChangeKind::TypeChanged),
+        _ => None,
+    }
+}
+
+async fn git_diff(cwd: &Path, params: &GitDiffParams) -> Result<GitDiffPage, String> {
+    let mut base: Vec<String> = vec!["diff".to_string()];
+    if params.staged {
+        base.push("--cached".to_string());
+    }
+    if let Some(rev) = &params.rev {
+        // Anything starting with `-` would be taken as an option.
+        if rev.starts_with('-') {
+            return Err(format!("invalid revision: {rev}"));
+        }
+        base.push(rev.clone());
+    }
+
+    let mut numstat = base.clone();
+    numstat.extend(["--numstat", "-z", "--"].map(String::from));
+    numstat.extend(params.paths.iter().cloned());
+    let output = run_git(cwd, &numstat).await?;
+    let stats = parse_numstat(&String::from_utf8_lossy(&output));
+
+    let limit = params.limit.unwrap_or(DEFAULT_DIFF_PAGE_FILES).max(1);
+    let end = params.offset.saturating_add(limit).min(stats.len());
+    let mut files = Vec::new();
+    for stat in stats.iter().skip(params.offset).take(limit) {
+        let mut args = base.clone();
+        args.extend(["--no-color", "--no-ext-diff", "--"].map(String::from));
+        args.extend(stat.from.iter().cloned());
+        args.push(stat.path.clone());
+        let output = run_git(cwd, &args).await?;
+        let mut patch = String::from_utf8_lossy(&output).into_owned();
+        let truncated = patch.len() > MAX_FILE_PATCH_BYTES;
+        if truncated {
+            let mut cut = MAX_FILE_PATCH_BYTES;
+            while !patch.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            patch.truncate(cut);
+        }
+        files.push(GitDiffFile {
+            patch,
+            truncated,
+            ..stat.clone()
+        });
+    }
+
+    Ok(GitDiffPage {
+        total_files: stats.len(),
+        files,
+        next_offset: (end < stats.len()).then_some(end),
+    })
+}
+
+/// Parse the output of `git diff --numstat -z` into files without patches.
+fn parse_numstat(output: &str) -> Vec<GitDiffFile> {
+    let mut files = Vec::new();
+    let mut fields = output.split('\0');
+    while let Some(entry) = fields.next() {
+        let mut parts = entry.splitn(3, '\t');
+        let (Some(additions), Some(deletions), Some(path)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        // Renames have an empty path followed by the old and new paths.
+        let (path, from) = if path.is_empty() {
+            match (fields.next(), fields.next()) {
+                (Some(from), Some(to)) => (to.to_string(), Some(from.to_string())),
+                _ => continue,
+            }
+        } else {
+            (path.to_string(), None)
+        };
+        files.push(GitDiffFile {
+            path,
+            from,
+            additions: additions.parse().ok(),
+            deletions: deletions.parse().ok(),
+            patch: String::new(),
+            truncated: false,
+        });
+    }
+    files
+}
+
+/// Response of `git_stash` with `action: "list"`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct GitStashEntry {
+    pub(crate) index: u32,
+    pub(crate) message: String,
+}
+
+async fn git_stash_list(cwd: &Path) -> Result<Vec<GitStashEntry>, String> {
+    let output = run_git(cwd, &["stash", "list", "--format=%gs"]).await?;
+    Ok(String::from_utf8_lossy(&output)
+        .lines()
+        .zip(0..)
+        .map(|(message, index)| GitStashEntry {
+            index,
+            message: message.to_string(),
+        })
+        .collect())
+}
+
+fn commit_command(params: &GitCommitParams) -> Result<Vec<String>, String> {
+    if params.message.trim().is_empty() {
+        return Err("commit message must not be empty".to_string());
+    }
+    if params.all && !params.paths.is_empty() {
+        return Err("`all` and `paths` cannot be combined".to_string());
+    }
+    let mut command: Vec<String> = ["git", "commit", "-m"].map(String::from).to_vec();
+    command.push(params.message.clone());
+    if params.all {
+        command.push("--all".to_string());
+    }
+    if !params.paths.is_empty() {
+        command.push("--".to_string());
+        command.extend(params.paths.iter().cloned());
+    }
+    Ok(command)
+}
+
+fn stash_command(params: &GitStashParams) -> Vec<String> {
+    let action = match params.action {
+        GitStashAction::Push => "push",
+        GitStashAction::Pop => "pop",
+        GitStashAction::Apply => "apply",
+        GitStashAction::Drop => "drop",
+        GitStashAction::List => "list",
+    };
+    let mut command: Vec<String> = ["git", "stash", action].map(String::from).to_vec();
+    match params.action {
+        GitStashAction::Push => {
+            if params.include_untracked {
+                command.push("--include-untracked".to_string());
+            }
+            if let Some(message) = &params.message {
+                command.extend(["-m".to_string(), message.clone()]);
+            }
+        }
+        GitStashAction::Pop | GitStashAction::Apply | GitStashAction::Drop => {
+            if let Some(index) = params.index {
+                command.push(format!("stash@{{{index}}}"));
+            }
+        }
+        GitStashAction::List => {}
+    }
+    command
+}
+
+async fn run_git<S: AsRef<std::ffi::OsStr>>(cwd: &Path, args: &[S]) -> Result<Vec<u8>, String> {
+    let output = timeout(
+        GIT_TOOL_TIMEOUT,
+        Command::new("git")
+            .args(["-c", "core.quotepath=off"])
+            .args(args)
+            .current_dir(cwd)
+            // Reading the status must not take the index lock away from
+            // commands the user runs at the same time.
+            .env("GIT_OPTIONAL_LOCKS", "0")
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| "git timed out".to_string())?
+    .map_err(|e| format!("failed to run git: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+fn parse_arguments<T: DeserializeOwned>(arguments: &str) -> Result<T, String> {
+    // `git_status` takes no arguments and may be called with none at all.
+    let arguments = if arguments.trim().is_empty() {
+        "{}"
+    } else {
+        arguments
+    };
+    serde_json::from_str(arguments).map_err(|e| format!("failed to parse function arguments: {e}"))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| format!("failed to serialize response: {e}"))
+}
+
+pub(crate) fn create_git_tools() -> Vec<ResponsesApiTool> {
+    let paths = |description: &str| JsonSchema::Array {
+        items: Box::new(JsonSchema::String { description: None }),
+        description: Some(description.to_string()),
+    };
+
+    let diff_properties = BTreeMap::from([
+        (
+            "staged".to_string(),
+            JsonSchema::Boolean {
+                description: Some(
+                    "Show staged changes instead of unstaged ones. Defaults to false.".to_string(),
+                ),
+            },
+        ),
+        (
+            "rev".to_string(),
+            JsonSchema::String {
+                description: Some(
+                    "Commit or `a..b` range to diff against, e.g. `HEAD~1` or `main..HEAD`."
+                        .to_string(),
+                ),
+            },
+        ),
+        ("paths".to_string(), paths("Only diff these paths.")),
+        (
+            "offset".to_string(),
+            JsonSchema::Number {
+                description: Some(
+                    "Index of the first changed file to return; use next_offset from the \
+                     previous page."
+                        .to_string(),
+                ),
+            },
+        ),
+        (
+            "limit".to_string(),
+            JsonSchema::Number {
+                description: Some("Files per page. Defaults to 20.".to_string()),
+            },
+        ),
+    ]);
+
+    let commit_properties = BTreeMap::from([
+        (
+            "message".to_string(),
+            JsonSchema::String {
+                description: Some("The commit message.".to_string()),
+            },
+        ),
+        (
+            "all".to_string(),
+            JsonSchema::Boolean {
+                description: Some(
+                    "Stage all modified and deleted tracked files first (git commit --all)."
+                        .to_string(),
+                ),
+            },
+        ),
+        (
+            "paths".to_string(),
+            paths("Commit only these paths, whether staged or not."),
+        ),
+    ]);
+
+    let stash_properties = BTreeMap::from([
+        (
+            "action".to_string(),
+            JsonSchema::String {
+                description: Some("One of push, pop, apply, drop or list.".to_string()),
+            },
+        ),
+        (
+            "message".to_string(),
+            JsonSchema::String {
+                description: Some("Message for push.".to_string()),
+            },
+        ),
+        (
+            "include_untracked".to_string(),
+            JsonSchema::Boolean {
+                description: Some("Also stash untracked files on push.".to_string()),
+            },
+        ),
+        (
+            "index".to_string(),
+            JsonSchema::Number {
+                description: Some(
+                    "Stash entry for pop, apply and drop, as reported by list. Defaults to the \
+                     latest."
+                        .to_string(),
+                ),
+            },
+        ),
+    ]);
+
+    vec![
+        ResponsesApiTool {
+            name: GIT_STATUS_TOOL_NAME.to_string(),
+            description: "Returns the current branch, its upstream and ahead/behind counts, and \
+                the staged, unstaged, untracked and conflicted files of the repository as JSON."
+                .to_string(),
+            strict: false,
+            parameters: JsonSchema::Object {
+                properties: BTreeMap::new(),
+                required: None,
+                additional_properties: Some(false),
+            },
+        },
+        ResponsesApiTool {
+            name: GIT_DIFF_TOOL_NAME.to_string(),
+            description: "Returns the changed files with line counts and their patches, a page \
+                of files at a time. Untracked files are not included."
+                .to_string(),
+            strict: false,
+            parameters: JsonSchema::Object {
+                properties: diff_properties,
+                required: None,
+                additional_properties: Some(false),
+            },
+        },
+        ResponsesApiTool {
+            name: GIT_COMMIT_TOOL_NAME.to_string(),
+            description: "Commits the staged changes, or the given paths, with a message."
+                .to_string(),
+            strict: false,
+            parameters: JsonSchema::Object {
+                properties: commit_properties,
+                required: Some(vec!["message".to_string()]),
+                additional_properties: Some(false),
+            },
+        },
+        ResponsesApiTool {
+            name: GIT_STASH_TOOL_NAME.to_string(),
+            description: "Stashes the working tree changes, restores or drops a stash entry, or \
+                lists the stash."
+                .to_string(),
+            strict: false,
+            parameters: JsonSchema::Object {
+                properties: stash_properties,
+                required: Some(vec!["action".to_string()]),
+                additional_properties: Some(false),
+            },
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parses_porcelain_v2_status() {
+        let output = [
+            "# branch.oid 1234abcd",
+            "# branch.head main",
+            "# branch.upstream origin/main",
+            "# branch.ab +2 -1",
+            "1 M. N... 100644 100644 100644 aaaa bbbb src/lib.rs",
+            "1 .D N... 100644 100644 000000 aaaa aaaa old file.txt",
+            "2 R. N... 100644 100644 100644 aaaa aaaa R100 new.rs",
+            "old.rs",
+            "u UU N... 100644 100644 100644 100644 aaaa bbbb cccc conflict.rs",
+            "? notes.md",
+            "",
+        ]
+        .join("\0");
+
+        let file = |path: &str, change, from: Option<&str>| GitFileStatus {
+            path: path.to_string(),
+            change,
+            from: from.map(str::to_string),
+        };
+        assert_eq!(
+            parse_status(&output),
+            GitStatus {
+                branch: Some("main".to_string()),
+                commit: Some("1234abcd".to_string()),
+                upstream: Some("origin/main".to_string()),
+                ahead: 2,
+                behind: 1,
+                staged: vec![
+                    file("src/lib.rs", GitChangeKind::Modified, None),
+                    file("new.rs", GitChangeKind::Renamed, Some("old.rs")),
+                ],
+                unstaged: vec![file("old file.txt", GitChangeKind::Deleted, None)],
+                untracked: vec!["notes.md".to_string()],
+                conflicted: vec!["conflict.rs".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn mutating_calls_become_fixed_commands() {
+        let commit = |json: &str| {
+            parse_arguments::<GitCommitParams>(json).and_then(|params| commit_command(&params))
+        };
+        assert_eq!(
+            commit(r#"{"message": "Fix bug", "paths": ["a.rs"]}"#),
+            Ok(["git", "commit", "-m", "Fix bug", "--", "a.rs"]
+                .map(String::from)
+                .to_vec())
+        );
+        assert!(commit(r#"{"message": "x", "all": true, "paths": ["a.rs"]}"#).is_err());
+        assert!(commit(r#"{"message": " "}"#).is_err());
+
+        let stash = |json: &str| {
+            parse_arguments::<GitStashParams>(json).map(|params| stash_command(&params))
+        };
+        assert_eq!(
+            stash(r#"{"action": "push", "message": "wip", "include_untracked": true}"#),
+            Ok(["git", "stash", "push", "--include-untracked", "-m", "wip"]
+                .map(String::from)
+                .to_vec())
+        );
+        assert_eq!(
+            stash(r#"{"action": "pop", "index": 2}"#),
+            Ok(["git", "stash", "pop", "stash@{2}"]
+                .map(String::from)
+                .to_vec())
+        );
+    }
+
+    async fn git(cwd: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(cwd)
+            .status()
+            .await
+            .unwrap();
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    #[tokio::test]
+    async fn diff_is_paginated_per_file() {
+        let tmp = TempDir::new().unwrap();
+        let repo = tmp.path();
+        git(repo, &["init", "-q"]).await;
+        git(repo, &["config", "user.email", "test@example.com"]).await;
+        git(repo, &["config", "user.name", "Test"]).await;
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            std::fs::write(repo.join(name), "one\n").unwrap();
+        }
+        git(repo, &["add", "."]).await;
+        git(repo, &["commit", "-q", "-m", "init"]).await;
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            std::fs::write(repo.join(name), "one\ntwo\n").unwrap();
+        }
+
+        let params = GitDiffParams {
+            limit: Some(2),
+            ..GitDiffParams::default()
+        };
+        let page = git_diff(repo, &params).await.unwrap();
+        assert_eq!(page.total_files, 3);
+        assert_eq!(page.next_offset, Some(2));
+        let paths: Vec<&str> = page.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["a.txt", "b.txt"]);
+        assert_eq!(page.files[0].additions, Some(1));
+        assert!(
+            page.files[0].patch.contains("+two"),
+            "{}",
+            page.files[0].patch
+        );
+        assert!(!page.files[0].patch.contains("b.txt"));
+
+        let params = GitDiffParams {
+            offset: 2,
+            ..params
+        };
+        let page = git_diff(repo, &params).await.unwrap();
+        assert_eq!(page.files.len(), 1);
+        assert_eq!(page.next_offset, None);
+
+        let status = git_status(repo).await.unwrap();
+        assert_eq!(status.unstaged.len(), 3);
+        assert!(status.staged.is_empty());
+
+        let GitToolCall::Output(output) =
+            handle_tool_call(GIT_DIFF_TOOL_NAME, r#"{"rev": "--output=x"}"#, repo).await
+        else {
+            panic!("expected output");
+        };
+        assert_eq!(output.success, Some(false));
+    }
+}
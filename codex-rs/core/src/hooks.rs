@@ -0,0 +1,325 @@
+//! User-configured commands run before and after tool calls and when turns
+//! start and end, see [`HookConfig`].
+
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use wildmatch::WildMatchPattern;
+
+use crate::config_types::HookAttachOutput;
+use crate::config_types::HookConfig;
+use crate::config_types::HookEvent;
+
+const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Only the end of a hook's output is kept; that is where errors and
+/// summaries usually are.
+const MAX_HOOK_OUTPUT_BYTES: usize = 8 * 1024;
+
+/// Description of the event written to a hook's stdin as JSON.
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct HookContext<'a> {
+    pub(crate) event: HookEvent,
+    pub(crate) turn_id: &'a str,
+    pub(crate) cwd: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) tool: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) call_id: Option<&'a str>,
+    /// Raw arguments of the tool call.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) arguments: Option<&'a str>,
+    /// For `after-tool`, whether the call succeeded, when that is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) success: Option<bool>,
+}
+
+/// Result of running one hook.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HookRun {
+    pub(crate) name: String,
+    pub(crate) success: bool,
+    /// Exit code, or why the hook did not exit normally.
+    pub(crate) status: String,
+    pub(crate) output: String,
+    block_on_failure: bool,
+    attach_output: HookAttachOutput,
+}
+
+impl HookRun {
+    pub(crate) fn blocks(&self) -> bool {
+        self.block_on_failure && !self.success
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        let outcome = if self.success { "succeeded" } else { "failed" };
+        let mut description = format!("hook `{}` {outcome} ({})", self.name, self.status);
+        if !self.output.is_empty() {
+            description.push_str(":\n");
+            description.push_str(&self.output);
+        }
+        description
+    }
+
+    fn attached(&self) -> bool {
+        match self.attach_output {
+            HookAttachOutput::Never => false,
+            HookAttachOutput::OnFailure => !self.success,
+            HookAttachOutput::Always => true,
+        }
+    }
+}
+
+/// Output of `runs` that should be added to the conversation, if any.
+pub(crate) fn attachment(runs: &[HookRun]) -> Option<String> {
+    let attached: Vec<String> = runs
+        .iter()
+        .filter(|run| run.attached())
+        .map(HookRun::describe)
+        .collect();
+    (!attached.is_empty()).then(|| attached.join("\n\n"))
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Hooks {
+    hooks: Vec<HookConfig>,
+}
+
+impl Hooks {
+    pub(crate) fn new(hooks: Vec<HookConfig>) -> Self {
+        Self { hooks }
+    }
+
+    /// Run the hooks for `context` one after the other, in the order they
+    /// are configured. A failing hook with `block_on_failure` stops the
+    /// remaining `before-tool` and `turn-start` hooks.
+    pub(crate) async fn run(&self, context: &HookContext<'_>) -> Vec<HookRun> {
+        let mut runs = Vec::new();
+        for hook in self.hooks.iter().filter(|hook| applies(hook, context)) {
+            let run = run_hook(hook, context).await;
+            let blocks = run.blocks()
+                && matches!(context.event, HookEvent::BeforeTool | HookEvent::TurnStart);
+            runs.push(run);
+            if blocks {
+                break;
+            }
+        }
+        runs
+    }
+}
+
+fn applies(hook: &HookConfig, context: &HookContext<'_>) -> bool {
+    if hook.event != context.event || hook.command.is_empty() {
+        return false;
+    }
+    match context.tool {
+        Some(tool) if !hook.tools.is_empty() => hook
+            .tools
+            .iter()
+            .any(|pattern| WildMatchPattern::<'*', '?'>::new(pattern).matches(tool)),
+        _ => true,
+    }
+}
+
+async fn run_hook(hook: &HookConfig, context: &HookContext<'_>) -> HookRun {
+    let name = hook.name.clone().unwrap_or_else(|| hook.command.join(" "));
+    let timeout = hook
+        .timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_HOOK_TIMEOUT);
+    let (success, status, output) = match execute(&hook.command, context, timeout).await {
+        Ok((code, output)) => (code == Some(0), exit_status(code), output),
+        Err(e) => (false, e, String::new()),
+    };
+    HookRun {
+        name,
+        success,
+        status,
+        output: truncate_output(output.trim_end()),
+        block_on_failure: hook.block_on_failure,
+        attach_output: hook.attach_output,
+    }
+}
+
+/// Run `command` in the context's working directory, returning its exit code
+/// and combined stdout and stderr. The timeout covers writing the context to
+/// the hook's stdin, which blocks while a hook that ignores its input is still
+/// running; a hook that runs out of time is killed.
+async fn execute(
+    command: &[String],
+    context: &HookContext<'_>,
+    timeout: Duration,
+) -> Result<(Option<i32>, String), String> {
+    let payload = serde_json::to_vec(context).map_err(|e| e.to_string())?;
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .current_dir(context.cwd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to start: {e}"))?;
+    let stdin = child.stdin.take();
+    let write_input = async move {
+        if let Some(mut stdin) = stdin {
+            // Hooks are free to ignore their input.
+            let _ = stdin.write_all(&payload).await;
+        }
+    };
+    // Dropping `wait_with_output` on timeout drops the child, which kills it.
+    let run = async { tokio::join!(write_input, child.wait_with_output()).1 };
+    let output = match tokio::time::timeout(timeout, run).await {
+        Ok(output) => output.map_err(|e| e.to_string())?,
+        Err(_) => return Err(format!("timed out after {}ms", timeout.as_millis())),
+    };
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok((output.status.code(), text))
+}
+
+fn exit_status(code: Option<i32>) -> String {
+    match code {
+        Some(code) => format!("exit code {code}"),
+        None => "killed by a signal".to_string(),
+    }
+}
+
+fn truncate_output(output: &str) -> String {
+    if output.len() <= MAX_HOOK_OUTPUT_BYTES {
+        return output.to_string();
+    }
+    let mut start = output.len() - MAX_HOOK_OUTPUT_BYTES;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("[... output truncated ...]\n{}", &output[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn hook(event: HookEvent, tools: &[&str], script: &str) -> HookConfig {
+        HookConfig {
+            name: None,
+            event,
+            command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+            tools: tools.iter().map(|tool| tool.to_string()).collect(),
+            timeout_ms: None,
+            block_on_failure: true,
+            attach_output: HookAttachOutput::OnFailure,
+        }
+    }
+
+    fn tool_context<'a>(event: HookEvent, tool: &'a str, cwd: &'a Path) -> HookContext<'a> {
+        HookContext {
+            event,
+            turn_id: "1",
+            cwd,
+            tool: Some(tool),
+            call_id: Some("call"),
+            arguments: Some("{\"command\":[\"ls\"]}"),
+            success: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn tool_hooks_match_by_event_and_tool() {
+        let cwd = std::env::temp_dir();
+        let hooks = Hooks::new(vec![
+            hook(HookEvent::BeforeTool, &["shell"], "cat"),
+            hook(HookEvent::BeforeTool, &["apply_*"], "echo patch"),
+            hook(HookEvent::AfterTool, &[], "echo after"),
+        ]);
+
+        let runs = hooks
+            .run(&tool_context(HookEvent::BeforeTool, "shell", &cwd))
+            .await;
+        assert_eq!(runs.len(), 1);
+        // The event description arrives on stdin.
+        let payload: serde_json::Value = serde_json::from_str(&runs[0].output).unwrap();
+        assert_eq!(payload["event"], "before-tool");
+        assert_eq!(payload["tool"], "shell");
+        assert_eq!(payload["arguments"], "{\"command\":[\"ls\"]}");
+
+        let runs = hooks
+            .run(&tool_context(HookEvent::AfterTool, "mcp__tool", &cwd))
+            .await;
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].output, "after");
+    }
+
+    #[tokio::test]
+    async fn blocking_failures_stop_later_hooks_and_are_attached() {
+        let cwd = std::env::temp_dir();
+        let hooks = Hooks::new(vec![
+            hook(HookEvent::BeforeTool, &[], "echo lint failed; exit 3"),
+            hook(HookEvent::BeforeTool, &[], "echo unreachable"),
+        ]);
+        let runs = hooks
+            .run(&tool_context(HookEvent::BeforeTool, "shell", &cwd))
+            .await;
+        assert_eq!(runs.len(), 1);
+        assert!(runs[0].blocks());
+        assert_eq!(
+            attachment(&runs),
+            Some(
+                "hook `sh -c echo lint failed; exit 3` failed (exit code 3):\nlint failed"
+                    .to_string()
+            )
+        );
+
+        let timeout = HookConfig {
+            timeout_ms: Some(50),
+            attach_output: HookAttachOutput::Never,
+            ..hook(HookEvent::TurnEnd, &[], "sleep 5")
+        };
+        let runs = Hooks::new(vec![timeout])
+            .run(&HookContext {
+                event: HookEvent::TurnEnd,
+                turn_id: "1",
+                cwd: &cwd,
+                tool: None,
+                call_id: None,
+                arguments: None,
+                success: None,
+            })
+            .await;
+        assert_eq!(runs[0].status, "timed out after 50ms");
+        assert!(!runs[0].success);
+        assert_eq!(attachment(&runs), None);
+    }
+
+    #[tokio::test]
+    async fn hooks_that_ignore_their_input_still_time_out() {
+        let cwd = std::env::temp_dir();
+        // Far more than a pipe buffer holds, so writing it blocks until the
+        // hook reads it or exits.
+        let arguments = "x".repeat(1024 * 1024);
+        let context = HookContext {
+            arguments: Some(&arguments),
+            ..tool_context(HookEvent::BeforeTool, "shell", &cwd)
+        };
+
+        let runs = Hooks::new(vec![hook(HookEvent::BeforeTool, &[], "echo ignored")])
+            .run(&context)
+            .await;
+        assert!(runs[0].success, "{runs:?}");
+        assert_eq!(runs[0].output, "ignored");
+
+        let stuck = HookConfig {
+            timeout_ms: Some(100),
+            ..hook(HookEvent::BeforeTool, &[], "sleep 30")
+        };
+        let started = std::time::Instant::now();
+        let runs = Hooks::new(vec![stuck]).run(&context).await;
+        assert_eq!(runs[0].status, "timed out after 100ms");
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,248 @@
+//! Applies the `[images]` limits to the images attached to a message, so
+//! every provider gets images it accepts.
+
+use std::io::Cursor;
+
+use base64::Engine;
+use image::DynamicImage;
+use image::ImageFormat;
+use image::ImageReader;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use tracing::debug;
+
+use crate::config_types::ImagesConfig;
+use crate::protocol::InputItem;
+
+/// Images are not shrunk below this size to fit `max_bytes`.
+const MIN_DIMENSION: u32 = 256;
+
+const JPEG_QUALITY: u8 = 85;
+
+/// Turn the images of `items` into data URLs within `config`'s limits. Text
+/// and images given by a remote URL are kept as they are. Images that
+/// cannot be read, or are over a limit while `resize` is off, are left out;
+/// the returned notes say which and why.
+pub(crate) fn prepare_input(
+    items: Vec<InputItem>,
+    config: &ImagesConfig,
+) -> (Vec<InputItem>, Vec<String>) {
+    let mut prepared = Vec::with_capacity(items.len());
+    let mut notes = Vec::new();
+    for item in items {
+        let (source, image) = match item {
+            InputItem::LocalImage { path } => {
+                let mime = mime_guess::from_path(&path)
+                    .first()
+                    .map(|m| m.essence_str().to_owned())
+                    .unwrap_or_else(|| "application/octet-stream".to_string());
+                let image = std::fs::read(&path)
+                    .map(|bytes| (bytes, mime))
+                    .map_err(|e| format!("could not read file: {e}"));
+                (path.display().to_string(), image)
+            }
+            InputItem::Base64Image { data, mime_type } => (
+                "attachment".to_string(),
+                decode_base64(&data).map(|bytes| (bytes, mime_type)),
+            ),
+            InputItem::Image { image_url } if image_url.starts_with("data:") => {
+                ("attachment".to_string(), decode_data_url(&image_url))
+            }
+            item => {
+                prepared.push(item);
+                continue;
+            }
+        };
+        match image.and_then(|(bytes, mime)| fit_image(bytes, mime, config)) {
+            Ok((bytes, mime)) => {
+                let data = base64::engine::general_purpose::STANDARD.encode(bytes);
+                prepared.push(InputItem::Image {
+                    image_url: format!("data:{mime};base64,{data}"),
+                });
+            }
+            Err(reason) => notes.push(format!("Left out image {source}: {reason}")),
+        }
+    }
+    (prepared, notes)
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, String> {
+    base64::engine::general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|e| format!("invalid base64: {e}"))
+}
+
+/// The bytes and media type of a `data:<mime>;base64,<data>` URL.
+fn decode_data_url(url: &str) -> Result<(Vec<u8>, String), String> {
+    let (header, data) = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(','))
+        .ok_or_else(|| "malformed data URL".to_string())?;
+    let mime = header
+        .strip_suffix(";base64")
+        .ok_or_else(|| "data URL is not base64-encoded".to_string())?;
+    Ok((decode_base64(data)?, mime.to_string()))
+}
+
+/// `bytes` unchanged when they are within the limits, otherwise shrunk until
+/// they are. PNG and JPEG images are checked and resized; other formats are
+/// only held to `max_bytes`.
+fn fit_image(
+    bytes: Vec<u8>,
+    mime: String,
+    config: &ImagesConfig,
+) -> Result<(Vec<u8>, String), String> {
+    let too_large = |len: usize| len as u64 > config.max_bytes;
+    let Some(mut format) = image::guess_format(&bytes)
+        .ok()
+        .filter(|format| matches!(format, ImageFormat::Png | ImageFormat::Jpeg))
+    else {
+        if too_large(bytes.len()) {
+            return Err(format!(
+                "{} bytes is over the limit of {}",
+                bytes.len(),
+                config.max_bytes
+            ));
+        }
+        return Ok((bytes, mime));
+    };
+
+    let (width, height) = ImageReader::with_format(Cursor::new(&bytes), format)
+        .into_dimensions()
+        .map_err(|e| format!("could not read image: {e}"))?;
+    if width.max(height) <= config.max_dimension && !too_large(bytes.len()) {
+        return Ok((bytes, mime));
+    }
+    if !config.resize {
+        return Err(format!(
+            "{width}x{height} pixels and {} bytes is over the limits of {} pixels and {} bytes",
+            bytes.len(),
+            config.max_dimension,
+            config.max_bytes
+        ));
+    }
+
+    let mut image = image::load_from_memory_with_format(&bytes, format)
+        .map_err(|e| format!("could not read image: {e}"))?;
+    let mut max_dimension = config.max_dimension.min(width.max(height));
+    loop {
+        if image.width().max(image.height()) > max_dimension {
+            image = image.resize(max_dimension, max_dimension, FilterType::Triangle);
+        }
+        let encoded = encode(&image, format)?;
+        if !too_large(encoded.len()) {
+            debug!(
+                "resized image from {width}x{height} to {}x{}",
+                image.width(),
+                image.height()
+            );
+            return Ok((encoded, format.to_mime_type().to_string()));
+        }
+        // Photos shrink far more as JPEG than by halving them as PNG.
+        if format == ImageFormat::Png {
+            format = ImageFormat::Jpeg;
+        } else if max_dimension / 2 >= MIN_DIMENSION {
+            max_dimension /= 2;
+        } else {
+            return Err(format!(
+                "could not shrink it below the limit of {} bytes",
+                config.max_bytes
+            ));
+        }
+    }
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, String> {
+    let mut encoded = Vec::new();
+    let result = if format == ImageFormat::Jpeg {
+        // JPEG has no alpha channel.
+        DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITY))
+    } else {
+        image.write_to(&mut Cursor::new(&mut encoded), format)
+    };
+    result
+        .map(|()| encoded)
+        .map_err(|e| format!("could not encode image: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        encode(&DynamicImage::new_rgb8(width, height), ImageFormat::Png).unwrap()
+    }
+
+    fn base64_image(bytes: &[u8]) -> InputItem {
+        InputItem::Base64Image {
+            data: base64::engine::general_purpose::STANDARD.encode(bytes),
+            mime_type: "image/png".to_string(),
+        }
+    }
+
+    fn decoded_dimensions(item: &InputItem) -> (u32, u32) {
+        let InputItem::Image { image_url } = item else {
+            panic!("expected an image, got {item:?}");
+        };
+        let (bytes, _) = decode_data_url(image_url).unwrap();
+        let image = image::load_from_memory(&bytes).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn keeps_images_within_the_limits() {
+        let bytes = png(40, 20);
+        let (items, notes) = prepare_input(vec![base64_image(&bytes)], &ImagesConfig::default());
+
+        assert!(notes.is_empty());
+        let data = base64::engine::general_purpose::STANDARD.encode(&bytes);
+        assert_eq!(
+            items,
+            vec![InputItem::Image {
+                image_url: format!("data:image/png;base64,{data}"),
+            }]
+        );
+    }
+
+    #[test]
+    fn shrinks_images_over_the_max_dimension() {
+        let config = ImagesConfig {
+            max_dimension: 20,
+            ..Default::default()
+        };
+        let (items, notes) = prepare_input(vec![base64_image(&png(100, 50))], &config);
+
+        assert!(notes.is_empty());
+        assert_eq!(decoded_dimensions(&items[0]), (20, 10));
+    }
+
+    #[test]
+    fn leaves_out_large_images_when_resizing_is_off() {
+        let config = ImagesConfig {
+            max_dimension: 20,
+            resize: false,
+            ..Default::default()
+        };
+        let items = vec![
+            InputItem::Text {
+                text: "what is this?".to_string(),
+            },
+            base64_image(&png(100, 50)),
+        ];
+
+        let (items, notes) = prepare_input(items, &config);
+
+        assert_eq!(
+            items,
+            vec![InputItem::Text {
+                text: "what is this?".to_string(),
+            }]
+        );
+        assert_eq!(notes.len(), 1);
+        assert!(notes[0].contains("100x50 pixels"), "{}", notes[0]);
+    }
+}
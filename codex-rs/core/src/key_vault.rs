@@ -0,0 +1,774 @@
+//! Azure Key Vault tools for rotation and expiry audits: the vaults of a
+//! subscription, the metadata of their secrets, keys and certificates, and
+//! what is about to expire. Secret values are never returned to the model;
+//! `key_vault_get_secret` asks for approval and puts the value in an
+//! environment variable of later commands, masked in everything recorded.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+use codex_protocol::models::FunctionCallOutputPayload;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::azure_auth::AzureAuth;
+use crate::config_types::KeyVaultConfig;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+
+pub(crate) const KEY_VAULT_LIST_VAULTS_TOOL_NAME: &str = "key_vault_list_vaults";
+pub(crate) const KEY_VAULT_LIST_ITEMS_TOOL_NAME: &str = "key_vault_list_items";
+pub(crate) const KEY_VAULT_CHECK_EXPIRY_TOOL_NAME: &str = "key_vault_check_expiry";
+pub(crate) const KEY_VAULT_GET_SECRET_TOOL_NAME: &str = "key_vault_get_secret";
+
+const VAULTS_API_VERSION: &str = "2023-07-01";
+const DATA_PLANE_API_VERSION: &str = "7.4";
+
+/// The kinds of items a vault holds, as named in its URLs.
+const ITEM_KINDS: [&str; 3] = ["secrets", "keys", "certificates"];
+
+pub(crate) struct KeyVault {
+    config: KeyVaultConfig,
+    azure_auth: Arc<AzureAuth>,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListVaultsParams {
+    #[serde(default)]
+    subscription_id: Option<String>,
+    #[serde(default)]
+    resource_group: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListItemsParams {
+    vault: String,
+    #[serde(default)]
+    kind: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckExpiryParams {
+    vault: String,
+    #[serde(default)]
+    days: Option<i64>,
+}
+
+/// Arguments of `key_vault_get_secret`.
+#[derive(Debug, Deserialize)]
+pub(crate) struct GetSecretParams {
+    pub(crate) vault: String,
+    pub(crate) name: String,
+    pub(crate) env_var: String,
+    #[serde(default)]
+    pub(crate) version: Option<String>,
+}
+
+/// A page of an ARM or Key Vault list.
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    #[serde(default = "Vec::new")]
+    value: Vec<T>,
+    #[serde(default, rename = "nextLink")]
+    next_link: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Vault {
+    name: String,
+    #[serde(default)]
+    location: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    properties: VaultProperties,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VaultProperties {
+    vault_uri: Option<String>,
+    enable_rbac_authorization: Option<bool>,
+    enable_purge_protection: Option<bool>,
+}
+
+/// A secret, key or certificate as listed, without its value.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VaultItem {
+    #[serde(alias = "kid")]
+    id: String,
+    #[serde(default)]
+    attributes: ItemAttributes,
+    #[serde(default)]
+    content_type: Option<String>,
+    #[serde(default)]
+    managed: bool,
+}
+
+/// Item attributes; times are Unix seconds.
+#[derive(Debug, Default, Deserialize)]
+struct ItemAttributes {
+    enabled: Option<bool>,
+    exp: Option<i64>,
+    updated: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SecretBundle {
+    value: String,
+}
+
+/// The body of an ARM or Key Vault error response.
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetail {
+    code: String,
+    message: String,
+}
+
+impl KeyVault {
+    pub(crate) fn new(config: KeyVaultConfig, azure_auth: Arc<AzureAuth>) -> Self {
+        Self {
+            config,
+            azure_auth,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Answers a call to one of the metadata tools. `key_vault_get_secret`
+    /// needs approval and goes through [`KeyVault::get_secret`] instead.
+    pub(crate) async fn handle_function_call(
+        &self,
+        name: &str,
+        arguments: &str,
+    ) -> FunctionCallOutputPayload {
+        let result = match name {
+            KEY_VAULT_LIST_VAULTS_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.list_vaults(params).await,
+                Err(e) => Err(e),
+            },
+            KEY_VAULT_LIST_ITEMS_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.list_items(params).await,
+                Err(e) => Err(e),
+            },
+            KEY_VAULT_CHECK_EXPIRY_TOOL_NAME => match parse(arguments) {
+                Ok(params) => self.check_expiry(params).await,
+                Err(e) => Err(e),
+            },
+            _ => Err(format!("unknown Key Vault tool `{name}`")),
+        };
+        match result {
+            Ok(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(true),
+            },
+            Err(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(false),
+            },
+        }
+    }
+
+    async fn list_vaults(&self, params: ListVaultsParams) -> Result<String, String> {
+        let subscription_id = params
+            .subscription_id
+            .or_else(|| self.config.subscription_id.clone())
+            .ok_or_else(|| {
+                "no subscription: pass subscription_id or set key_vault.subscription_id".to_string()
+            })?;
+        let mut url = format!(
+            "{}/subscriptions/{subscription_id}",
+            self.resource_manager()
+        );
+        if let Some(resource_group) = &params.resource_group {
+            let _ = write!(url, "/resourceGroups/{resource_group}");
+        }
+        url.push_str("/providers/Microsoft.KeyVault/vaults");
+        let query = [("api-version", VAULTS_API_VERSION.to_string())];
+        let scope = format!("{}/.default", self.resource_manager());
+        let vaults: Vec<Vault> = self.list(&url, &query, &scope).await?;
+        Ok(format_vaults(&vaults))
+    }
+
+    async fn list_items(&self, params: ListItemsParams) -> Result<String, String> {
+        let kind = params.kind.unwrap_or_else(|| "secrets".to_string());
+        if !ITEM_KINDS.contains(&kind.as_str()) {
+            return Err(format!("kind must be one of {}", ITEM_KINDS.join(", ")));
+        }
+        let vault_url = self.vault_url(&params.vault)?;
+        let items = self.items(&vault_url, &kind).await?;
+        Ok(format_items(&params.vault, &kind, &items, Utc::now()))
+    }
+
+    async fn check_expiry(&self, params: CheckExpiryParams) -> Result<String, String> {
+        let days = params.days.unwrap_or(self.config.expiry_warning_days);
+        if days < 0 {
+            return Err("days must not be negative".to_string());
+        }
+        let vault_url = self.vault_url(&params.vault)?;
+        let mut items = Vec::new();
+        for kind in ITEM_KINDS {
+            for item in self.items(&vault_url, kind).await? {
+                items.push((kind, item));
+            }
+        }
+        Ok(format_expiry(&params.vault, &items, days, Utc::now()))
+    }
+
+    /// Reads the value of a secret. The caller asks for approval first and
+    /// keeps the value away from the model.
+    pub(crate) async fn get_secret(&self, params: &GetSecretParams) -> Result<String, String> {
+        let vault_url = self.vault_url(&params.vault)?;
+        let mut url = format!("{vault_url}/secrets/{}", params.name);
+        if let Some(version) = &params.version {
+            let _ = write!(url, "/{version}");
+        }
+        let query = [("api-version", DATA_PLANE_API_VERSION.to_string())];
+        let secret: SecretBundle = self.get(&url, &query, &self.vault_scope()).await?;
+        Ok(secret.value)
+    }
+
+    async fn items(&self, vault_url: &str, kind: &str) -> Result<Vec<VaultItem>, String> {
+        let url = format!("{vault_url}/{kind}");
+        let query = [("api-version", DATA_PLANE_API_VERSION.to_string())];
+        self.list(&url, &query, &self.vault_scope()).await
+    }
+
+    /// Every item of a list, following `nextLink` up to `max_results`.
+    async fn list<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &[(&str, String)],
+        scope: &str,
+    ) -> Result<Vec<T>, String> {
+        let mut page: Page<T> = self.get(url, query, scope).await?;
+        let mut items = Vec::new();
+        loop {
+            items.append(&mut page.value);
+            if items.len() >= self.config.max_results {
+                items.truncate(self.config.max_results);
+                break;
+            }
+            match page.next_link.take() {
+                Some(next_link) => page = self.get(&next_link, &[], scope).await?,
+                None => break,
+            }
+        }
+        Ok(items)
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &[(&str, String)],
+        scope: &str,
+    ) -> Result<T, String> {
+        let token = self
+            .azure_auth
+            .get_token(scope)
+            .await
+            .map_err(|e| format!("failed to get an Azure token: {e}"))?;
+        let response = self
+            .client
+            .get(url)
+            .query(query)
+            .bearer_auth(token.token)
+            .send()
+            .await
+            .map_err(|e| format!("failed to reach Azure Key Vault: {e}"))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let detail = match serde_json::from_str::<ErrorResponse>(&body) {
+                Ok(ErrorResponse { error }) => format!("{}: {}", error.code, error.message),
+                Err(_) => body,
+            };
+            return Err(format!("Key Vault request failed ({status}): {detail}"));
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| format!("failed to parse the Key Vault response: {e}"))
+    }
+
+    fn resource_manager(&self) -> &str {
+        self.config.resource_manager.trim_end_matches('/')
+    }
+
+    fn vault_scope(&self) -> String {
+        format!("https://{}/.default", self.config.vault_dns_suffix)
+    }
+
+    /// The URL of `vault`, given as a vault name or as its URL.
+    fn vault_url(&self, vault: &str) -> Result<String, String> {
+        let vault = vault.trim();
+        if vault.starts_with("https://") || vault.starts_with("http://") {
+            return Ok(vault.trim_end_matches('/').to_string());
+        }
+        if vault.is_empty() || !vault.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(format!(
+                "`{vault}` is not a vault name; pass a name such as contoso-kv or the vault URL"
+            ));
+        }
+        Ok(format!("https://{vault}.{}", self.config.vault_dns_suffix))
+    }
+}
+
+/// Parses the arguments of `key_vault_get_secret`.
+pub(crate) fn parse_get_secret(arguments: &str) -> Result<GetSecretParams, String> {
+    let params: GetSecretParams = parse(arguments)?;
+    let mut chars = params.env_var.chars();
+    let valid_env_var = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_env_var {
+        return Err(format!(
+            "`{}` is not an environment variable name",
+            params.env_var
+        ));
+    }
+    if params.name.is_empty() || params.name.contains('/') {
+        return Err(format!("`{}` is not a secret name", params.name));
+    }
+    Ok(params)
+}
+
+fn parse<T: DeserializeOwned>(arguments: &str) -> Result<T, String> {
+    serde_json::from_str(arguments).map_err(|e| format!("failed to parse function arguments: {e}"))
+}
+
+impl VaultItem {
+    /// The item name: the last segment of its id.
+    fn name(&self) -> &str {
+        self.id
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or("")
+    }
+
+    fn expires(&self) -> Option<DateTime<Utc>> {
+        self.attributes
+            .exp
+            .and_then(|exp| DateTime::from_timestamp(exp, 0))
+    }
+}
+
+fn format_date(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d").to_string()
+}
+
+/// `expires` relative to `now`, such as "expires 2024-06-01 (in 12 days)".
+fn format_expiry_date(expires: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let days = (expires - now).num_days();
+    let date = format_date(expires);
+    if expires <= now {
+        format!("expired {date} ({} days ago)", -days)
+    } else {
+        format!("expires {date} (in {days} days)")
+    }
+}
+
+fn format_vaults(vaults: &[Vault]) -> String {
+    if vaults.is_empty() {
+        return "No key vaults found.".to_string();
+    }
+    let mut out = format!("{} key vaults:\n", vaults.len());
+    for vault in vaults {
+        let _ = write!(out, "\n{}", vault.name);
+        if let Some(location) = &vault.location {
+            let _ = write!(out, " ({location})");
+        }
+        out.push('\n');
+        if let Some(uri) = &vault.properties.vault_uri {
+            let _ = writeln!(out, "  {uri}");
+        }
+        let access = match vault.properties.enable_rbac_authorization {
+            Some(true) => "Azure RBAC",
+            _ => "access policies",
+        };
+        let purge = match vault.properties.enable_purge_protection {
+            Some(true) => "on",
+            _ => "off",
+        };
+        let _ = writeln!(out, "  access: {access}, purge protection: {purge}");
+        if let Some(id) = &vault.id {
+            let _ = writeln!(out, "  {id}");
+        }
+    }
+    out
+}
+
+fn format_items(vault: &str, kind: &str, items: &[VaultItem], now: DateTime<Utc>) -> String {
+    if items.is_empty() {
+        return format!("No {kind} in {vault}.");
+    }
+    let mut out = format!("{} {kind} in {vault}:\n", items.len());
+    for item in items {
+        let _ = write!(out, "  {}", item.name());
+        if item.attributes.enabled == Some(false) {
+            out.push_str("  disabled");
+        }
+        match item.expires() {
+            Some(expires) => {
+                let _ = write!(out, "  {}", format_expiry_date(expires, now));
+            }
+            None => out.push_str("  no expiry"),
+        }
+        if let Some(updated) = item
+            .attributes
+            .updated
+            .and_then(|updated| DateTime::from_timestamp(updated, 0))
+        {
+            let _ = write!(out, "  updated {}", format_date(updated));
+        }
+        if let Some(content_type) = item.content_type.as_deref().filter(|c| !c.is_empty()) {
+            let _ = write!(out, "  {content_type}");
+        }
+        if item.managed {
+            out.push_str("  managed");
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn format_expiry(
+    vault: &str,
+    items: &[(&str, VaultItem)],
+    days: i64,
+    now: DateTime<Utc>,
+) -> String {
+    let horizon = now + Duration::days(days);
+    let mut expiring: Vec<(&str, &VaultItem, DateTime<Utc>)> = items
+        .iter()
+        .filter_map(|(kind, item)| item.expires().map(|expires| (*kind, item, expires)))
+        .filter(|(_, _, expires)| *expires <= horizon)
+        .collect();
+    expiring.sort_by_key(|(_, _, expires)| *expires);
+    let without_expiry = items
+        .iter()
+        .filter(|(_, item)| item.expires().is_none())
+        .count();
+
+    let mut out = if expiring.is_empty() {
+        format!("Nothing in {vault} expires within {days} days.\n")
+    } else {
+        format!(
+            "{} items in {vault} expired or expire within {days} days:\n",
+            expiring.len()
+        )
+    };
+    for (kind, item, expires) in &expiring {
+        let kind = kind.trim_end_matches('s');
+        let _ = write!(
+            out,
+            "  {kind} {}  {}",
+            item.name(),
+            format_expiry_date(*expires, now)
+        );
+        if item.attributes.enabled == Some(false) {
+            out.push_str("  disabled");
+        }
+        out.push('\n');
+    }
+    if without_expiry > 0 {
+        let _ = writeln!(
+            out,
+            "{without_expiry} of {} items have no expiry date.",
+            items.len()
+        );
+    }
+    out
+}
+
+pub(crate) fn create_key_vault_tools() -> Vec<ResponsesApiTool> {
+    let string = |description: &str| JsonSchema::String {
+        description: Some(description.to_string()),
+    };
+    let object = |properties: BTreeMap<String, JsonSchema>, required: &[&str]| JsonSchema::Object {
+        properties,
+        required: Some(required.iter().map(|r| r.to_string()).collect()),
+        additional_properties: Some(false),
+    };
+    let vault = || string("Vault name, such as contoso-kv, or its URL.");
+
+    let list_vaults = BTreeMap::from([
+        (
+            "subscription_id".to_string(),
+            string("Subscription id; defaults to the configured key_vault.subscription_id."),
+        ),
+        (
+            "resource_group".to_string(),
+            string("Only vaults in this resource group."),
+        ),
+    ]);
+    let list_items = BTreeMap::from([
+        ("vault".to_string(), vault()),
+        (
+            "kind".to_string(),
+            string("secrets, keys or certificates. Default secrets."),
+        ),
+    ]);
+    let check_expiry = BTreeMap::from([
+        ("vault".to_string(), vault()),
+        (
+            "days".to_string(),
+            JsonSchema::Number {
+                description: Some(
+                    "Report items expiring within this many days, besides expired ones. \
+                     Defaults to key_vault.expiry_warning_days."
+                        .to_string(),
+                ),
+            },
+        ),
+    ]);
+    let get_secret = BTreeMap::from([
+        ("vault".to_string(), vault()),
+        ("name".to_string(), string("Secret name.")),
+        (
+            "env_var".to_string(),
+            string(
+                "Environment variable that holds the value in later shell commands, such as DB_PASSWORD.",
+            ),
+        ),
+        (
+            "version".to_string(),
+            string("Secret version; defaults to the current one."),
+        ),
+    ]);
+
+    vec![
+        ResponsesApiTool {
+            name: KEY_VAULT_LIST_VAULTS_TOOL_NAME.to_string(),
+            description: "Lists the Azure Key Vaults of a subscription with their URL, location \
+                and access model."
+                .to_string(),
+            strict: false,
+            parameters: object(list_vaults, &[]),
+        },
+        ResponsesApiTool {
+            name: KEY_VAULT_LIST_ITEMS_TOOL_NAME.to_string(),
+            description: "Lists the secrets, keys or certificates of a Key Vault with their \
+                expiry, last update and whether they are enabled. Values are never shown."
+                .to_string(),
+            strict: false,
+            parameters: object(list_items, &["vault"]),
+        },
+        ResponsesApiTool {
+            name: KEY_VAULT_CHECK_EXPIRY_TOOL_NAME.to_string(),
+            description: "Finds the secrets, keys and certificates of a Key Vault that have \
+                expired or expire soon, soonest first. Use it for rotation audits."
+                .to_string(),
+            strict: false,
+            parameters: object(check_expiry, &["vault"]),
+        },
+        ResponsesApiTool {
+            name: KEY_VAULT_GET_SECRET_TOOL_NAME.to_string(),
+            description: "Reads a Key Vault secret into an environment variable of later shell \
+                commands, after the user approves. The value is not returned and is masked in \
+                command output; refer to the variable instead."
+                .to_string(),
+            strict: false,
+            parameters: object(get_secret, &["vault", "name", "env_var"]),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::config_types::AzureAuthConfig;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::TempDir;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+
+    fn key_vault(server: &MockServer, codex_home: &TempDir) -> KeyVault {
+        let azure_auth = AzureAuth::new(AzureAuthConfig::default(), codex_home.path())
+            .with_token_for_tests(&format!("{}/.default", server.uri()), "arm-token")
+            .with_token_for_tests("https://vault.azure.net/.default", "vault-token");
+        KeyVault::new(
+            KeyVaultConfig {
+                subscription_id: Some("sub-1".to_string()),
+                resource_manager: server.uri(),
+                vault_dns_suffix: "vault.azure.net".to_string(),
+                max_results: 100,
+                expiry_warning_days: 30,
+            },
+            Arc::new(azure_auth),
+        )
+    }
+
+    fn timestamp(time: &str) -> i64 {
+        DateTime::parse_from_rfc3339(time).unwrap().timestamp()
+    }
+
+    #[tokio::test]
+    async fn lists_vaults_of_a_resource_group() {
+        let server = MockServer::start().await;
+        let codex_home = TempDir::new().unwrap();
+        Mock::given(method("GET"))
+            .and(path(
+                "/subscriptions/sub-1/resourceGroups/rg/providers/Microsoft.KeyVault/vaults",
+            ))
+            .and(header("authorization", "Bearer arm-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "value": [{
+                    "id": "/subscriptions/sub-1/resourceGroups/rg/providers/Microsoft.KeyVault/vaults/contoso-kv",
+                    "name": "contoso-kv",
+                    "location": "westeurope",
+                    "properties": {
+                        "vaultUri": "https://contoso-kv.vault.azure.net/",
+                        "enableRbacAuthorization": true,
+                    },
+                }],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let output = key_vault(&server, &codex_home)
+            .handle_function_call(
+                KEY_VAULT_LIST_VAULTS_TOOL_NAME,
+                &json!({ "resource_group": "rg" }).to_string(),
+            )
+            .await;
+
+        assert_eq!(output.success, Some(true), "{}", output.content);
+        assert_eq!(
+            output.content,
+            "1 key vaults:\n\
+             \n\
+             contoso-kv (westeurope)\n  \
+             https://contoso-kv.vault.azure.net/\n  \
+             access: Azure RBAC, purge protection: off\n  \
+             /subscriptions/sub-1/resourceGroups/rg/providers/Microsoft.KeyVault/vaults/contoso-kv\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_secret_values_only_through_get_secret() {
+        let server = MockServer::start().await;
+        let codex_home = TempDir::new().unwrap();
+        Mock::given(method("GET"))
+            .and(path("/secrets"))
+            .and(header("authorization", "Bearer vault-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "value": [{
+                    "id": format!("{}/secrets/db-password", server.uri()),
+                    "attributes": { "enabled": true },
+                    "contentType": "text/plain",
+                }],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/secrets/db-password"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "value": "hunter2",
+                "id": format!("{}/secrets/db-password/abc", server.uri()),
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let key_vault = key_vault(&server, &codex_home);
+
+        let output = key_vault
+            .handle_function_call(
+                KEY_VAULT_LIST_ITEMS_TOOL_NAME,
+                &json!({ "vault": server.uri() }).to_string(),
+            )
+            .await;
+        assert_eq!(output.success, Some(true), "{}", output.content);
+        assert!(
+            output
+                .content
+                .contains("db-password  no expiry  text/plain")
+        );
+        assert!(!output.content.contains("hunter2"));
+
+        let params = parse_get_secret(
+            &json!({ "vault": server.uri(), "name": "db-password", "env_var": "DB_PASSWORD" })
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!(key_vault.get_secret(&params).await.unwrap(), "hunter2");
+    }
+
+    #[test]
+    fn rejects_bad_secret_arguments() {
+        let err = parse_get_secret(
+            &json!({ "vault": "kv", "name": "db-password", "env_var": "1PASSWORD" }).to_string(),
+        )
+        .unwrap_err();
+        assert_eq!(err, "`1PASSWORD` is not an environment variable name");
+        let err = parse_get_secret(
+            &json!({ "vault": "kv", "name": "a/b", "env_var": "PASSWORD" }).to_string(),
+        )
+        .unwrap_err();
+        assert_eq!(err, "`a/b` is not a secret name");
+    }
+
+    #[test]
+    fn reports_expired_and_expiring_items_soonest_first() {
+        let now = DateTime::parse_from_rfc3339("2024-05-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let item = |id: &str, exp: Option<&str>| VaultItem {
+            id: format!("https://kv.vault.azure.net/{id}"),
+            attributes: ItemAttributes {
+                enabled: Some(true),
+                exp: exp.map(timestamp),
+                updated: None,
+            },
+            content_type: None,
+            managed: false,
+        };
+        let items = vec![
+            (
+                "secrets",
+                item("secrets/api-key", Some("2024-05-20T00:00:00Z")),
+            ),
+            (
+                "secrets",
+                item("secrets/later", Some("2024-09-01T00:00:00Z")),
+            ),
+            ("secrets", item("secrets/forever", None)),
+            (
+                "certificates",
+                item("certificates/tls", Some("2024-04-29T00:00:00Z")),
+            ),
+        ];
+
+        assert_eq!(
+            format_expiry("kv", &items, 30, now),
+            "2 items in kv expired or expire within 30 days:\n  \
+             certificate tls  expired 2024-04-29 (2 days ago)\n  \
+             secret api-key  expires 2024-05-20 (in 19 days)\n\
+             1 of 4 items have no expiry date.\n"
+        );
+        assert_eq!(
+            format_expiry("kv", &items[2..3], 30, now),
+            "Nothing in kv expires within 30 days.\n1 of 1 items have no expiry date.\n"
+        );
+    }
+}
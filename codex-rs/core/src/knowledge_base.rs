@@ -0,0 +1,635 @@
+//! The `kb_search` tool: searches the organization's documentation, such as
+//! runbooks in Markdown or a clone of an Azure DevOps wiki, so the agent can
+//! ground its answers in it and cite where they came from.
+//!
+//! Pages are split into chunks along their headings and kept in an index
+//! under `$CODEX_HOME/knowledge_base/`, with the embedding of each chunk when
+//! `[embeddings]` is configured. The index is brought up to date with the
+//! sources before every search, so only pages that changed are embedded
+//! again. Without an embedding model, chunks are ranked by the query's words.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use codex_login::AuthManager;
+use codex_protocol::models::FunctionCallOutputPayload;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tracing::warn;
+
+use crate::client_common::RankedDocument;
+use crate::client_common::rank_by_similarity;
+use crate::config::Config;
+use crate::config::KnowledgeBaseConfig;
+use crate::embeddings::EmbeddingClient;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+
+pub(crate) const KB_SEARCH_TOOL_NAME: &str = "kb_search";
+
+/// Chunks embedded per request.
+const EMBED_BATCH_SIZE: usize = 64;
+
+pub(crate) struct KnowledgeBase {
+    config: KnowledgeBaseConfig,
+    index_path: PathBuf,
+    embeddings: Option<EmbeddingClient>,
+    /// Loaded on the first search.
+    index: tokio::sync::Mutex<Option<Index>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    /// Model the chunks were embedded with, `None` when they were not.
+    model: Option<String>,
+    files: BTreeMap<PathBuf, IndexedFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedFile {
+    modified: SystemTime,
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Chunk {
+    /// The page's first top-level heading, or its file name.
+    title: String,
+    /// Headings the chunk is under, outermost first.
+    headings: Vec<String>,
+    start_line: usize,
+    end_line: usize,
+    text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    embedding: Option<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct KbSearchParams {
+    query: String,
+    #[serde(default)]
+    top_k: Option<usize>,
+}
+
+impl KnowledgeBase {
+    /// `None` when `[knowledge_base]` is not configured.
+    pub(crate) fn new(config: &Config, auth_manager: Option<Arc<AuthManager>>) -> Option<Self> {
+        let knowledge_base = config.knowledge_base.clone()?;
+        // Each set of sources has its own index.
+        let mut hasher = Sha256::new();
+        for source in &knowledge_base.sources {
+            hasher.update(source.to_string_lossy().as_bytes());
+            hasher.update([0]);
+        }
+        let index_name = format!("{:x}.json", hasher.finalize());
+        Some(Self {
+            config: knowledge_base,
+            index_path: config.codex_home.join("knowledge_base").join(index_name),
+            embeddings: EmbeddingClient::new(config, auth_manager),
+            index: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    pub(crate) async fn handle_kb_search(&self, arguments: &str) -> FunctionCallOutputPayload {
+        let result = match serde_json::from_str::<KbSearchParams>(arguments) {
+            Ok(params) => self
+                .search(&params.query, params.top_k.unwrap_or(self.config.top_k))
+                .await
+                .map(|results| format_results(&params.query, &results)),
+            Err(e) => Err(format!("failed to parse function arguments: {e}")),
+        };
+        match result {
+            Ok(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(true),
+            },
+            Err(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(false),
+            },
+        }
+    }
+
+    /// The `top_k` chunks most relevant to `query`, with the page each came
+    /// from.
+    async fn search(&self, query: &str, top_k: usize) -> Result<Vec<(PathBuf, Chunk)>, String> {
+        let mut index = self.index.lock().await;
+        let index = index.get_or_insert_with(|| self.load());
+        self.sync(index).await?;
+
+        let chunks: Vec<(&PathBuf, &Chunk)> = index
+            .files
+            .iter()
+            .flat_map(|(path, file)| file.chunks.iter().map(move |chunk| (path, chunk)))
+            .collect();
+        let ranked = match &self.embeddings {
+            Some(embeddings) => {
+                let query = embeddings
+                    .embed(&[query.to_string()])
+                    .await
+                    .map_err(|e| format!("failed to embed the query: {e}"))?
+                    .remove(0);
+                let documents: Vec<Vec<f32>> = chunks
+                    .iter()
+                    .map(|(_, chunk)| chunk.embedding.clone().unwrap_or_default())
+                    .collect();
+                rank_by_similarity(&query, &documents, Some(top_k))
+            }
+            None => rank_by_keywords(
+                query,
+                &chunks.iter().map(|(_, chunk)| *chunk).collect::<Vec<_>>(),
+                top_k,
+            ),
+        };
+        Ok(ranked
+            .into_iter()
+            .map(|ranked| {
+                let (path, chunk) = chunks[ranked.index];
+                (path.clone(), chunk.clone())
+            })
+            .collect())
+    }
+
+    fn load(&self) -> Index {
+        std::fs::read_to_string(&self.index_path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    /// Index the pages added or changed since the last search and drop the
+    /// ones that are gone.
+    async fn sync(&self, index: &mut Index) -> Result<(), String> {
+        let model = self.embeddings.as_ref().map(|e| e.model().to_string());
+        let mut changed = false;
+        if index.model != model {
+            index.model = model;
+            index.files.clear();
+            changed = true;
+        }
+
+        let pages = find_pages(&self.config.sources);
+        let before = index.files.len();
+        index.files.retain(|path, _| pages.contains(path));
+        changed |= index.files.len() != before;
+
+        let mut updated = Vec::new();
+        for path in pages {
+            let Ok(modified) = std::fs::metadata(&path).and_then(|m| m.modified()) else {
+                continue;
+            };
+            if index
+                .files
+                .get(&path)
+                .is_some_and(|file| file.modified == modified)
+            {
+                continue;
+            }
+            match std::fs::read_to_string(&path) {
+                Ok(text) => {
+                    let chunks = chunk_page(&path, &text, self.config.chunk_bytes);
+                    updated.push((path, IndexedFile { modified, chunks }));
+                }
+                Err(e) => warn!("failed to read {}: {e}", path.display()),
+            }
+        }
+
+        if let Some(embeddings) = &self.embeddings {
+            let mut chunks: Vec<&mut Chunk> = updated
+                .iter_mut()
+                .flat_map(|(_, file)| file.chunks.iter_mut())
+                .collect();
+            for batch in chunks.chunks_mut(EMBED_BATCH_SIZE) {
+                let inputs: Vec<String> =
+                    batch.iter().map(|chunk| embedding_input(chunk)).collect();
+                let vectors = embeddings
+                    .embed(&inputs)
+                    .await
+                    .map_err(|e| format!("failed to embed the knowledge base: {e}"))?;
+                for (chunk, vector) in batch.iter_mut().zip(vectors) {
+                    chunk.embedding = Some(vector);
+                }
+            }
+        }
+
+        changed |= !updated.is_empty();
+        index.files.extend(updated);
+        if changed {
+            self.save(index);
+        }
+        Ok(())
+    }
+
+    fn save(&self, index: &Index) {
+        let result = serde_json::to_string(index)
+            .map_err(std::io::Error::other)
+            .and_then(|json| {
+                if let Some(dir) = self.index_path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::fs::write(&self.index_path, json)
+            });
+        if let Err(e) = result {
+            warn!(
+                "failed to save knowledge base index {}: {e}",
+                self.index_path.display()
+            );
+        }
+    }
+}
+
+/// The Markdown files among `sources` and in the directories among them.
+/// Hidden files and directories, such as `.git` and the `.attachments` of
+/// an Azure DevOps wiki, are skipped.
+fn find_pages(sources: &[PathBuf]) -> HashSet<PathBuf> {
+    fn visit(path: &Path, pages: &mut HashSet<PathBuf>) {
+        if path.is_dir() {
+            let Ok(entries) = std::fs::read_dir(path) else {
+                return;
+            };
+            for entry in entries.flatten() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    visit(&entry.path(), pages);
+                }
+            }
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == "md" || ext == "markdown")
+        {
+            pages.insert(path.to_path_buf());
+        }
+    }
+
+    let mut pages = HashSet::new();
+    for source in sources {
+        visit(source, &mut pages);
+    }
+    pages
+}
+
+/// Split a Markdown page into chunks of at most `chunk_bytes` along its
+/// headings, then its paragraphs, then its lines.
+fn chunk_page(path: &Path, text: &str, chunk_bytes: usize) -> Vec<Chunk> {
+    struct Section<'a> {
+        headings: Vec<String>,
+        start_line: usize,
+        lines: Vec<&'a str>,
+    }
+
+    let mut sections = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut section = Section {
+        headings: Vec::new(),
+        start_line: 1,
+        lines: Vec::new(),
+    };
+    let mut in_fence = false;
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if let Some((level, heading)) = parse_heading(line).filter(|_| !in_fence) {
+            headings.retain(|(l, _)| *l < level);
+            headings.push((level, heading));
+            sections.push(std::mem::replace(
+                &mut section,
+                Section {
+                    headings: headings.iter().map(|(_, h)| h.clone()).collect(),
+                    start_line: number + 1,
+                    lines: Vec::new(),
+                },
+            ));
+        }
+        section.lines.push(line);
+    }
+    sections.push(section);
+
+    let title = text
+        .lines()
+        .filter_map(parse_heading)
+        .find(|(level, _)| *level == 1)
+        .map(|(_, heading)| heading)
+        .unwrap_or_else(|| page_name(path));
+
+    let mut chunks = Vec::new();
+    for section in sections {
+        // Pieces of at most `chunk_bytes`, broken at blank lines when possible.
+        let mut start = 0;
+        while start < section.lines.len() {
+            let mut end = start;
+            let mut len = 0;
+            let mut last_break = None;
+            while end < section.lines.len()
+                && (end == start || len + section.lines[end].len() < chunk_bytes)
+            {
+                len += section.lines[end].len() + 1;
+                if section.lines[end].trim().is_empty() && end > start {
+                    last_break = Some(end);
+                }
+                end += 1;
+            }
+            if end < section.lines.len()
+                && let Some(last_break) = last_break
+            {
+                end = last_break + 1;
+            }
+            let lines = &section.lines[start..end];
+            if let Some(first) = lines.iter().position(|line| !line.trim().is_empty()) {
+                let last = lines
+                    .iter()
+                    .rposition(|line| !line.trim().is_empty())
+                    .unwrap_or(first);
+                chunks.push(Chunk {
+                    title: title.clone(),
+                    headings: section.headings.clone(),
+                    start_line: section.start_line + start + first,
+                    end_line: section.start_line + start + last,
+                    text: lines[first..=last].join("\n"),
+                    embedding: None,
+                });
+            }
+            start = end;
+        }
+    }
+    chunks
+}
+
+/// The level and text of an ATX heading such as `## Restore a vault`.
+fn parse_heading(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim().to_string()))
+}
+
+/// The page name of a file, decoded the way Azure DevOps wikis encode page
+/// names in file names: `-` for a space and `%2D` for a hyphen.
+fn page_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem = stem.replace('-', " ");
+    let mut name = Vec::with_capacity(stem.len());
+    let bytes = stem.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = (bytes[i] == b'%')
+            .then(|| stem.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match decoded {
+            Some(byte) => {
+                name.push(byte);
+                i += 3;
+            }
+            None => {
+                name.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&name).into_owned()
+}
+
+fn embedding_input(chunk: &Chunk) -> String {
+    format!(
+        "{}\n{}\n{}",
+        chunk.title,
+        chunk.headings.join(" > "),
+        chunk.text
+    )
+}
+
+/// Rank `chunks` by how often they use the words of `query`, weighting rare
+/// words more.
+fn rank_by_keywords(query: &str, chunks: &[&Chunk], top_k: usize) -> Vec<RankedDocument> {
+    fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| word.len() > 1)
+            .map(str::to_lowercase)
+    }
+
+    let terms: HashSet<String> = words(query).collect();
+    let counts: Vec<HashMap<String, usize>> = chunks
+        .iter()
+        .map(|chunk| {
+            let mut counts = HashMap::new();
+            for word in words(&chunk.headings.join(" ")).chain(words(&chunk.text)) {
+                if terms.contains(&word) {
+                    *counts.entry(word).or_insert(0) += 1;
+                }
+            }
+            counts
+        })
+        .collect();
+
+    let mut ranked: Vec<RankedDocument> = counts
+        .iter()
+        .enumerate()
+        .map(|(index, chunk_counts)| {
+            let score = chunk_counts
+                .iter()
+                .map(|(term, &count)| {
+                    let containing = counts.iter().filter(|c| c.contains_key(term)).count();
+                    let idf = (1.0 + chunks.len() as f32 / containing as f32).ln();
+                    idf * count as f32 / (count as f32 + 1.2)
+                })
+                .sum();
+            RankedDocument { index, score }
+        })
+        .filter(|ranked| ranked.score > 0.0)
+        .collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+    ranked.truncate(top_k);
+    ranked
+}
+
+fn format_results(query: &str, results: &[(PathBuf, Chunk)]) -> String {
+    if results.is_empty() {
+        return format!("no results for `{query}`");
+    }
+    let mut content = String::from(
+        "Cite the sources you use by their path and lines, for example \
+         `runbooks/restore.md:10-42`.\n",
+    );
+    for (number, (path, chunk)) in results.iter().enumerate() {
+        let mut location = vec![chunk.title.clone()];
+        location.extend(
+            chunk
+                .headings
+                .iter()
+                .filter(|heading| **heading != chunk.title)
+                .cloned(),
+        );
+        content.push_str(&format!(
+            "\n[{}] {} ({}:{}-{})\n{}\n",
+            number + 1,
+            location.join(" > "),
+            path.display(),
+            chunk.start_line,
+            chunk.end_line,
+            chunk.text
+        ));
+    }
+    content
+}
+
+pub(crate) fn create_kb_search_tool() -> ResponsesApiTool {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "query".to_string(),
+        JsonSchema::String {
+            description: Some("What to look for, in natural language.".to_string()),
+        },
+    );
+    properties.insert(
+        "top_k".to_string(),
+        JsonSchema::Number {
+            description: Some("How many passages to return.".to_string()),
+        },
+    );
+
+    ResponsesApiTool {
+        name: KB_SEARCH_TOOL_NAME.to_string(),
+        description: "Searches the organization's documentation, such as runbooks and wiki \
+            pages, and returns the most relevant passages with the file and lines they come \
+            from. Use it before answering questions about internal systems and procedures, and \
+            cite the passages you rely on."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["query".to_string()]),
+            additional_properties: Some(false),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    const RUNBOOK: &str = "\
+# Restore a vault
+
+Restores take about an hour.
+
+## Steps
+
+1. Stop the writers.
+
+2. Run the restore.
+
+```sh
+# not a heading
+restore --vault main
+```
+
+## Rollback
+
+Start the writers again.
+";
+
+    #[test]
+    fn chunks_pages_along_their_headings() {
+        let chunks = chunk_page(Path::new("restore.md"), RUNBOOK, 1024);
+
+        let outline: Vec<(Vec<String>, usize, usize)> = chunks
+            .iter()
+            .map(|chunk| (chunk.headings.clone(), chunk.start_line, chunk.end_line))
+            .collect();
+        assert_eq!(
+            outline,
+            vec![
+                (vec!["Restore a vault".to_string()], 1, 3),
+                (
+                    vec!["Restore a vault".to_string(), "Steps".to_string()],
+                    5,
+                    14
+                ),
+                (
+                    vec!["Restore a vault".to_string(), "Rollback".to_string()],
+                    16,
+                    18
+                ),
+            ]
+        );
+        assert!(chunks.iter().all(|chunk| chunk.title == "Restore a vault"));
+        assert!(chunks[1].text.contains("# not a heading"));
+    }
+
+    #[test]
+    fn splits_long_sections_at_blank_lines() {
+        let chunks = chunk_page(Path::new("restore.md"), RUNBOOK, 60);
+
+        let steps: Vec<&str> = chunks
+            .iter()
+            .filter(|chunk| chunk.headings.last().is_some_and(|h| h == "Steps"))
+            .map(|chunk| chunk.text.as_str())
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                "## Steps\n\n1. Stop the writers.\n\n2. Run the restore.",
+                "```sh\n# not a heading\nrestore --vault main\n```",
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_azure_devops_wiki_page_names() {
+        assert_eq!(
+            page_name(Path::new("wiki/Fail%2Dover-the-primary.md")),
+            "Fail-over the primary"
+        );
+    }
+
+    #[tokio::test]
+    async fn searches_by_keywords_without_an_embedding_model() {
+        let codex_home = TempDir::new().unwrap();
+        let docs = TempDir::new().unwrap();
+        std::fs::write(docs.path().join("restore.md"), RUNBOOK).unwrap();
+        std::fs::create_dir(docs.path().join(".git")).unwrap();
+        std::fs::write(docs.path().join(".git").join("notes.md"), "rollback").unwrap();
+        let kb = KnowledgeBase {
+            config: KnowledgeBaseConfig {
+                sources: vec![docs.path().to_path_buf()],
+                chunk_bytes: 1024,
+                top_k: 5,
+            },
+            index_path: codex_home.path().join("index.json"),
+            embeddings: None,
+            index: tokio::sync::Mutex::new(None),
+        };
+
+        let output = kb
+            .handle_kb_search(r#"{"query":"rollback","top_k":1}"#)
+            .await;
+        let path = docs.path().join("restore.md");
+        assert_eq!(
+            output.content,
+            format!(
+                "Cite the sources you use by their path and lines, for example \
+                 `runbooks/restore.md:10-42`.\n\n[1] Restore a vault > Rollback ({}:16-18)\n\
+                 ## Rollback\n\nStart the writers again.\n",
+                path.display()
+            )
+        );
+        assert!(codex_home.path().join("index.json").exists());
+    }
+}
@@ -0,0 +1,577 @@
+//! Read-only Kubernetes tools: list resources, describe one, read the recent
+//! logs of a pod and list events, against the kubeconfig contexts of the
+//! `[kubernetes]` table, e.g. to look into an unhealthy AKS service.
+//!
+//! The tools run `kubectl` with arguments built from a fixed set of verbs.
+//! Resource kinds are limited to an allowlist that leaves out secrets, and
+//! names are checked so that no model-supplied value can become a flag.
+
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::time::Duration;
+
+use codex_protocol::models::FunctionCallOutputPayload;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use tokio::process::Command;
+
+use crate::config_types::KubernetesConfig;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+
+pub(crate) const K8S_GET_TOOL_NAME: &str = "k8s_get";
+pub(crate) const K8S_DESCRIBE_TOOL_NAME: &str = "k8s_describe";
+pub(crate) const K8S_LOGS_TOOL_NAME: &str = "k8s_logs";
+pub(crate) const K8S_EVENTS_TOOL_NAME: &str = "k8s_events";
+
+/// Kinds the tools may read. Secrets are left out on purpose.
+const KINDS: &[&str] = &[
+    "configmaps",
+    "cronjobs",
+    "daemonsets",
+    "deployments",
+    "endpoints",
+    "horizontalpodautoscalers",
+    "ingresses",
+    "jobs",
+    "namespaces",
+    "nodes",
+    "persistentvolumeclaims",
+    "persistentvolumes",
+    "pods",
+    "replicasets",
+    "services",
+    "statefulsets",
+];
+
+/// Namespace value that lists resources of all namespaces.
+const ALL_NAMESPACES: &str = "all";
+
+#[derive(Debug, Deserialize)]
+struct GetParams {
+    #[serde(default)]
+    context: Option<String>,
+    #[serde(default)]
+    namespace: Option<String>,
+    kind: String,
+    #[serde(default)]
+    selector: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DescribeParams {
+    #[serde(default)]
+    context: Option<String>,
+    #[serde(default)]
+    namespace: Option<String>,
+    kind: String,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogsParams {
+    #[serde(default)]
+    context: Option<String>,
+    #[serde(default)]
+    namespace: Option<String>,
+    pod: String,
+    #[serde(default)]
+    container: Option<String>,
+    #[serde(default)]
+    tail_lines: Option<usize>,
+    #[serde(default)]
+    since: Option<String>,
+    #[serde(default)]
+    previous: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventsParams {
+    #[serde(default)]
+    context: Option<String>,
+    #[serde(default)]
+    namespace: Option<String>,
+    /// Only events about the object with this name.
+    #[serde(default)]
+    name: Option<String>,
+    /// Only warnings.
+    #[serde(default)]
+    warnings_only: bool,
+}
+
+pub(crate) struct Kubernetes {
+    config: KubernetesConfig,
+}
+
+impl Kubernetes {
+    pub(crate) fn new(config: KubernetesConfig) -> Self {
+        Self { config }
+    }
+
+    pub(crate) fn contexts(&self) -> &[String] {
+        &self.config.contexts
+    }
+
+    pub(crate) async fn handle_function_call(
+        &self,
+        name: &str,
+        arguments: &str,
+    ) -> FunctionCallOutputPayload {
+        let result = match self.kubectl_args(name, arguments) {
+            Ok(args) => self.run(&args).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(true),
+            },
+            Err(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(false),
+            },
+        }
+    }
+
+    /// The `kubectl` arguments of a tool call.
+    fn kubectl_args(&self, name: &str, arguments: &str) -> Result<Vec<String>, String> {
+        match name {
+            K8S_GET_TOOL_NAME => {
+                let params: GetParams = parse(arguments)?;
+                let mut args = self.scope(params.context, params.namespace)?;
+                args.extend([
+                    "get".to_string(),
+                    kind(&params.kind)?,
+                    "-o".into(),
+                    "wide".into(),
+                ]);
+                if let Some(selector) = params.selector {
+                    args.push(format!("--selector={selector}"));
+                }
+                Ok(args)
+            }
+            K8S_DESCRIBE_TOOL_NAME => {
+                let params: DescribeParams = parse(arguments)?;
+                let mut args = self.scope(params.context, params.namespace)?;
+                args.extend([
+                    "describe".to_string(),
+                    kind(&params.kind)?,
+                    object_name(&params.name)?,
+                ]);
+                Ok(args)
+            }
+            K8S_LOGS_TOOL_NAME => {
+                let params: LogsParams = parse(arguments)?;
+                let mut args = self.scope(params.context, params.namespace)?;
+                let tail_lines = params
+                    .tail_lines
+                    .unwrap_or(self.config.max_log_lines)
+                    .min(self.config.max_log_lines)
+                    .max(1);
+                args.extend([
+                    "logs".to_string(),
+                    object_name(&params.pod)?,
+                    format!("--tail={tail_lines}"),
+                    "--timestamps".into(),
+                ]);
+                if let Some(container) = params.container {
+                    args.push(format!("--container={}", object_name(&container)?));
+                }
+                if let Some(since) = params.since {
+                    if !is_duration(&since) {
+                        return Err(format!("`{since}` is not a duration such as 30m or 2h"));
+                    }
+                    args.push(format!("--since={since}"));
+                }
+                if params.previous {
+                    args.push("--previous".into());
+                }
+                Ok(args)
+            }
+            K8S_EVENTS_TOOL_NAME => {
+                let params: EventsParams = parse(arguments)?;
+                let mut args = self.scope(params.context, params.namespace)?;
+                args.extend([
+                    "get".to_string(),
+                    "events".into(),
+                    "--sort-by=.lastTimestamp".into(),
+                ]);
+                let mut selectors = Vec::new();
+                if let Some(name) = params.name {
+                    selectors.push(format!("involvedObject.name={}", object_name(&name)?));
+                }
+                if params.warnings_only {
+                    selectors.push("type=Warning".to_string());
+                }
+                if !selectors.is_empty() {
+                    args.push(format!("--field-selector={}", selectors.join(",")));
+                }
+                Ok(args)
+            }
+            _ => Err(format!("unknown Kubernetes tool `{name}`")),
+        }
+    }
+
+    /// The kubeconfig, context and namespace arguments of a call.
+    fn scope(
+        &self,
+        context: Option<String>,
+        namespace: Option<String>,
+    ) -> Result<Vec<String>, String> {
+        let context = match context {
+            Some(context) if self.config.contexts.contains(&context) => context,
+            Some(context) => {
+                return Err(format!(
+                    "context `{context}` is not configured; use one of: {}",
+                    self.config.contexts.join(", ")
+                ));
+            }
+            None => self
+                .config
+                .contexts
+                .first()
+                .cloned()
+                .ok_or_else(|| "no Kubernetes contexts are configured".to_string())?,
+        };
+        let mut args = Vec::new();
+        if let Some(kubeconfig) = &self.config.kubeconfig {
+            args.push(format!("--kubeconfig={}", kubeconfig.display()));
+        }
+        args.push(format!("--context={context}"));
+        args.push(format!("--request-timeout={}s", self.config.timeout_secs));
+        match namespace.or_else(|| self.config.default_namespace.clone()) {
+            Some(namespace) if namespace == ALL_NAMESPACES => {
+                args.push("--all-namespaces".into());
+            }
+            Some(namespace) => args.push(format!("--namespace={}", object_name(&namespace)?)),
+            None => {}
+        }
+        Ok(args)
+    }
+
+    async fn run(&self, args: &[String]) -> Result<String, String> {
+        let child = Command::new(&self.config.kubectl_command)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("failed to start `{}`: {e}", self.config.kubectl_command))?;
+        // kubectl enforces --request-timeout per request; this also covers
+        // a hung process.
+        let timeout = Duration::from_secs(self.config.timeout_secs * 2);
+        let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(output) => output.map_err(|e| e.to_string())?,
+            Err(_) => return Err(format!("kubectl timed out after {}s", timeout.as_secs())),
+        };
+        if !output.status.success() {
+            return Err(format!(
+                "kubectl failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.trim().is_empty() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Ok(match stderr.trim() {
+                "" => "No output.".to_string(),
+                message => message.to_string(),
+            });
+        }
+        Ok(truncate_tail(&stdout, self.config.max_output_bytes))
+    }
+}
+
+fn parse<T: DeserializeOwned>(arguments: &str) -> Result<T, String> {
+    serde_json::from_str(arguments).map_err(|e| format!("failed to parse function arguments: {e}"))
+}
+
+/// The plural kind `kubectl` is given, from a kind in the allowlist. Singular
+/// kinds are accepted too.
+fn kind(kind: &str) -> Result<String, String> {
+    let lower = kind.to_ascii_lowercase();
+    KINDS
+        .iter()
+        .find(|allowed| {
+            **allowed == lower
+                || allowed.strip_suffix('s') == Some(lower.as_str())
+                || allowed.strip_suffix("es") == Some(lower.as_str())
+        })
+        .map(|allowed| allowed.to_string())
+        .ok_or_else(|| format!("kind `{kind}` is not one of: {}", KINDS.join(", ")))
+}
+
+/// `name` if it is a valid Kubernetes object name.
+fn object_name(name: &str) -> Result<String, String> {
+    let valid = !name.is_empty()
+        && name.len() <= 253
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.');
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(format!("`{name}` is not a valid Kubernetes name"))
+    }
+}
+
+/// Whether `since` is a duration `kubectl logs --since` takes, e.g. `1h30m`.
+fn is_duration(since: &str) -> bool {
+    let mut digits = 0;
+    let mut units = 0;
+    for c in since.chars() {
+        match c {
+            '0'..='9' => digits += 1,
+            'h' | 'm' | 's' if digits > 0 => {
+                digits = 0;
+                units += 1;
+            }
+            _ => return false,
+        }
+    }
+    digits == 0 && units > 0
+}
+
+/// The end of `output`, cut to at most `max_bytes` at a line boundary, since
+/// the most recent logs and events come last.
+fn truncate_tail(output: &str, max_bytes: usize) -> String {
+    if output.len() <= max_bytes {
+        return output.to_string();
+    }
+    let mut start = output.len() - max_bytes;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    let tail = &output[start..];
+    let tail = tail.find('\n').map_or(tail, |newline| &tail[newline + 1..]);
+    format!("[output cut to the last {} bytes]\n{tail}", tail.len())
+}
+
+pub(crate) fn create_kubernetes_tools(contexts: &[String]) -> Vec<ResponsesApiTool> {
+    let string = |description: &str| JsonSchema::String {
+        description: Some(description.to_string()),
+    };
+    let boolean = |description: &str| JsonSchema::Boolean {
+        description: Some(description.to_string()),
+    };
+    let object = |properties: BTreeMap<String, JsonSchema>, required: &[&str]| JsonSchema::Object {
+        properties,
+        required: Some(required.iter().map(|r| r.to_string()).collect()),
+        additional_properties: Some(false),
+    };
+    let context = || {
+        string(&format!(
+            "Kubeconfig context; one of: {}. Defaults to the first.",
+            contexts.join(", ")
+        ))
+    };
+    let namespace =
+        || string("Namespace; defaults to the configured namespace. `all` lists every namespace.");
+    let kind_schema = || string(&format!("Resource kind, one of: {}.", KINDS.join(", ")));
+    let scoped = |extra: Vec<(&str, JsonSchema)>| {
+        let mut properties = BTreeMap::from([
+            ("context".to_string(), context()),
+            ("namespace".to_string(), namespace()),
+        ]);
+        properties.extend(
+            extra
+                .into_iter()
+                .map(|(name, schema)| (name.to_string(), schema)),
+        );
+        properties
+    };
+
+    vec![
+        ResponsesApiTool {
+            name: K8S_GET_TOOL_NAME.to_string(),
+            description: "Lists Kubernetes resources of a kind with `kubectl get -o wide`, \
+                e.g. pods with their status, restarts and nodes."
+                .to_string(),
+            strict: false,
+            parameters: object(
+                scoped(vec![
+                    ("kind", kind_schema()),
+                    (
+                        "selector",
+                        string("Label selector, e.g. `app=checkout,tier!=canary`."),
+                    ),
+                ]),
+                &["kind"],
+            ),
+        },
+        ResponsesApiTool {
+            name: K8S_DESCRIBE_TOOL_NAME.to_string(),
+            description: "Describes one Kubernetes resource with `kubectl describe`: its \
+                spec, status, conditions and recent events."
+                .to_string(),
+            strict: false,
+            parameters: object(
+                scoped(vec![
+                    ("kind", kind_schema()),
+                    ("name", string("Name of the resource.")),
+                ]),
+                &["kind", "name"],
+            ),
+        },
+        ResponsesApiTool {
+            name: K8S_LOGS_TOOL_NAME.to_string(),
+            description: "Returns the most recent log lines of a pod, with timestamps.".to_string(),
+            strict: false,
+            parameters: object(
+                scoped(vec![
+                    ("pod", string("Name of the pod.")),
+                    (
+                        "container",
+                        string("Container, for pods with more than one."),
+                    ),
+                    (
+                        "tail_lines",
+                        JsonSchema::Number {
+                            description: Some(
+                                "Lines to return from the end; capped by the configuration."
+                                    .to_string(),
+                            ),
+                        },
+                    ),
+                    (
+                        "since",
+                        string("Only logs newer than this duration, e.g. `15m` or `2h`."),
+                    ),
+                    (
+                        "previous",
+                        boolean("Logs of the previous, crashed instance of the container."),
+                    ),
+                ]),
+                &["pod"],
+            ),
+        },
+        ResponsesApiTool {
+            name: K8S_EVENTS_TOOL_NAME.to_string(),
+            description: "Lists Kubernetes events, oldest first, such as failed scheduling, \
+                image pull errors and probe failures."
+                .to_string(),
+            strict: false,
+            parameters: object(
+                scoped(vec![
+                    (
+                        "name",
+                        string("Only events about the object with this name."),
+                    ),
+                    ("warnings_only", boolean("Only events of type Warning.")),
+                ]),
+                &[],
+            ),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    fn kubernetes() -> Kubernetes {
+        Kubernetes::new(KubernetesConfig {
+            kubectl_command: "kubectl".to_string(),
+            kubeconfig: Some(PathBuf::from("/home/me/.kube/aks")),
+            contexts: vec!["aks-prod".to_string(), "aks-staging".to_string()],
+            default_namespace: Some("checkout".to_string()),
+            max_log_lines: 200,
+            max_output_bytes: 64 * 1024,
+            timeout_secs: 20,
+        })
+    }
+
+    #[test]
+    fn builds_kubectl_arguments() {
+        let kubernetes = kubernetes();
+        assert_eq!(
+            kubernetes
+                .kubectl_args(
+                    K8S_LOGS_TOOL_NAME,
+                    r#"{"pod": "api-7d9f", "tail_lines": 5000, "since": "1h30m", "previous": true}"#
+                )
+                .unwrap(),
+            vec![
+                "--kubeconfig=/home/me/.kube/aks",
+                "--context=aks-prod",
+                "--request-timeout=20s",
+                "--namespace=checkout",
+                "logs",
+                "api-7d9f",
+                "--tail=200",
+                "--timestamps",
+                "--since=1h30m",
+                "--previous",
+            ]
+        );
+        assert_eq!(
+            kubernetes
+                .kubectl_args(
+                    K8S_EVENTS_TOOL_NAME,
+                    r#"{"context": "aks-staging", "namespace": "all", "name": "api-7d9f", "warnings_only": true}"#
+                )
+                .unwrap(),
+            vec![
+                "--kubeconfig=/home/me/.kube/aks",
+                "--context=aks-staging",
+                "--request-timeout=20s",
+                "--all-namespaces",
+                "get",
+                "events",
+                "--sort-by=.lastTimestamp",
+                "--field-selector=involvedObject.name=api-7d9f,type=Warning",
+            ]
+        );
+        assert_eq!(
+            kubernetes
+                .kubectl_args(K8S_GET_TOOL_NAME, r#"{"kind": "Deployment"}"#)
+                .unwrap()[4..],
+            ["get", "deployments", "-o", "wide"]
+        );
+    }
+
+    #[test]
+    fn rejects_unsafe_arguments() {
+        let kubernetes = kubernetes();
+        for (name, arguments, error) in [
+            (
+                K8S_GET_TOOL_NAME,
+                r#"{"kind": "secrets"}"#,
+                format!("kind `secrets` is not one of: {}", KINDS.join(", ")),
+            ),
+            (
+                K8S_DESCRIBE_TOOL_NAME,
+                r#"{"kind": "pod", "name": "--raw=/api"}"#,
+                "`--raw=/api` is not a valid Kubernetes name".to_string(),
+            ),
+            (
+                K8S_GET_TOOL_NAME,
+                r#"{"kind": "pods", "context": "minikube"}"#,
+                "context `minikube` is not configured; use one of: aks-prod, aks-staging"
+                    .to_string(),
+            ),
+            (
+                K8S_LOGS_TOOL_NAME,
+                r#"{"pod": "api", "since": "yesterday"}"#,
+                "`yesterday` is not a duration such as 30m or 2h".to_string(),
+            ),
+        ] {
+            assert_eq!(kubernetes.kubectl_args(name, arguments), Err(error));
+        }
+    }
+
+    #[test]
+    fn keeps_the_end_of_long_output() {
+        assert_eq!(truncate_tail("one\ntwo\n", 100), "one\ntwo\n");
+        assert_eq!(
+            truncate_tail("first line\nsecond\nthird\n", 12),
+            "[output cut to the last 6 bytes]\nthird\n"
+        );
+    }
+}
@@ -5,31 +5,66 @@
 // the TUI or the tracing stack).
 #![deny(clippy::print_stdout, clippy::print_stderr)]
 
+mod anthropic;
 mod apply_patch;
+mod approval_http;
+mod arm_deployments;
+mod artifacts;
+pub mod audit_log;
+pub mod azure_auth;
+mod azure_monitor;
+mod azure_storage;
+mod background_jobs;
 mod bash;
+mod budget;
 mod chat_completions;
+mod circuit_breaker;
 mod client;
 mod client_common;
 pub mod codex;
 mod codex_conversation;
+mod command_rules;
+mod conversation_branches;
+mod conversation_export;
+mod conversation_store;
 pub use codex_conversation::CodexConversation;
+pub use conversation_export::export_conversation;
+pub use conversation_export::export_rollout;
 pub mod config;
+mod config_interpolation;
 pub mod config_profile;
 pub mod config_types;
 mod conversation_history;
 pub mod custom_prompts;
+mod custom_tools;
+pub mod docker;
+mod dry_run;
+mod edit_files;
+mod embeddings;
+pub use client_common::RankedDocument;
+pub use embeddings::EmbeddingClient;
 mod environment_context;
+mod environment_snapshot;
 pub mod error;
 pub mod exec;
 mod exec_command;
 pub mod exec_env;
+mod exec_limits;
 mod flags;
+mod gemini;
 pub mod git_info;
+mod git_tools;
+mod hooks;
+mod images;
 mod is_safe_command;
+mod key_vault;
+mod knowledge_base;
+mod kubernetes;
 pub mod landlock;
 mod mcp_connection_manager;
 mod mcp_tool_call;
 mod message_history;
+mod messaging;
 mod model_provider_info;
 pub mod parse_command;
 pub use model_provider_info::BUILT_IN_OSS_MODEL_PROVIDER_ID;
@@ -41,21 +76,35 @@ mod conversation_manager;
 pub use conversation_manager::ConversationManager;
 pub use conversation_manager::NewConversation;
 pub mod model_family;
+mod ollama_chat;
 mod openai_model_info;
 mod openai_tools;
 pub mod plan_tool;
+mod project_config;
 pub mod project_doc;
+mod redaction;
+pub use redaction::Redactor;
+mod response_format;
 mod rollout;
+pub use rollout::SessionSummary;
+pub use rollout::list_sessions;
 pub(crate) mod safety;
+pub mod scheduler;
 pub mod seatbelt;
 pub mod shell;
 pub mod spawn;
+mod sql;
 pub mod terminal;
+mod ticketing;
+mod token_refresh;
 mod tool_apply_patch;
+mod tool_scheduler;
 pub mod turn_diff_tracker;
 pub mod user_agent;
 mod user_notification;
 pub mod util;
+mod windows_path;
+pub mod workflow;
 pub use apply_patch::CODEX_APPLY_PATCH_ARG1;
 pub use safety::get_platform_sandbox;
 // Re-export the protocol types from the standalone `codex-protocol` crate so existing
@@ -5,18 +5,39 @@
 //! helpers to query the available tools across *all* servers and returns them
 //! in a single aggregated map using the fully-qualified tool name
 //! `"<server><MCP_TOOL_NAME_DELIMITER><tool>"` as the key.
+//!
+//! Servers are pinged periodically and restarted, with increasing delays
+//! between attempts, when they stop answering; their tools are listed again
+//! once they are back. Servers can also be added and removed while the
+//! session runs.
+//!
+//! Resources and prompts of servers that offer them are listed alongside the
+//! tools: resources are read by the model through the `read_resource` tool,
+//! prompts are offered to clients as templates.
 
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::ffi::OsString;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
 use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Context;
 use anyhow::Result;
 use anyhow::anyhow;
 use codex_mcp_client::McpClient;
 use mcp_types::ClientCapabilities;
+use mcp_types::GetPromptResult;
 use mcp_types::Implementation;
+use mcp_types::ListPromptsRequestParams;
+use mcp_types::ListResourcesRequestParams;
+use mcp_types::PingRequest;
+use mcp_types::Prompt;
+use mcp_types::ReadResourceResult;
+use mcp_types::Resource;
 use mcp_types::Tool;
 
 use serde_json::json;
@@ -25,8 +46,13 @@ use sha1::Sha1;
 use tokio::task::JoinSet;
 use tracing::info;
 use tracing::warn;
+use wildmatch::WildMatchPattern;
 
+use crate::config_types::McpHealthConfig;
 use crate::config_types::McpServerConfig;
+use crate::config_types::McpToolApproval;
+use crate::protocol::McpServerStatus;
+use crate::protocol::McpServerStatusEvent;
 
 /// Delimiter used to separate the server name from the tool name in a fully
 /// qualified tool name.
@@ -36,14 +62,23 @@ use crate::config_types::McpServerConfig;
 const MCP_TOOL_NAME_DELIMITER: &str = "__";
 const MAX_TOOL_NAME_LENGTH: usize = 64;
 
-/// Timeout for the `tools/list` request.
+/// Timeout for the `tools/list`, `resources/list` and `prompts/list`
+/// requests.
 const LIST_TOOLS_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Most pages of resources or prompts fetched from one server.
+const MAX_LIST_PAGES: usize = 10;
+
 /// Map that holds a startup error for every MCP server that could **not** be
 /// spawned successfully.
 pub type ClientStartErrors = HashMap<String, anyhow::Error>;
 
-fn qualify_tools(tools: Vec<ToolInfo>) -> HashMap<String, ToolInfo> {
+/// Names every tool `<server>__<tool>`. When two tools end up with the same
+/// name, such as tool `c` of server `a__b` and tool `b__c` of server `a`, the
+/// one whose server and tool names sort first is kept, so the same tool wins
+/// every time.
+fn qualify_tools(mut tools: Vec<ToolInfo>) -> HashMap<String, ToolInfo> {
+    tools.sort_by(|a, b| (&a.server_name, &a.tool_name).cmp(&(&b.server_name, &b.tool_name)));
     let mut used_names = HashSet::new();
     let mut qualified_tools = HashMap::new();
     for tool in tools {
@@ -64,7 +99,10 @@ fn qualify_tools(tools: Vec<ToolInfo>) -> HashMap<String, ToolInfo> {
         }
 
         if used_names.contains(&qualified_name) {
-            warn!("skipping duplicated tool {}", qualified_name);
+            warn!(
+                "skipping tool {} of server {}: its name {qualified_name} is taken",
+                tool.tool_name, tool.server_name
+            );
             continue;
         }
 
@@ -81,17 +119,49 @@ struct ToolInfo {
     tool: Tool,
 }
 
-/// A thin wrapper around a set of running [`McpClient`] instances.
-#[derive(Default)]
+/// A thin wrapper around a set of running [`McpClient`] instances. Clones
+/// share the same servers.
+#[derive(Default, Clone)]
 pub(crate) struct McpConnectionManager {
-    /// Server-name -> client instance.
+    state: Arc<Mutex<ManagerState>>,
+}
+
+#[derive(Default)]
+struct ManagerState {
+    /// Server name -> server.
     ///
     /// The server name originates from the keys of the `mcp_servers` map in
-    /// the user configuration.
-    clients: HashMap<String, std::sync::Arc<McpClient>>,
+    /// the user configuration, or from [`McpConnectionManager::add_server`].
+    servers: HashMap<String, ServerState>,
 
     /// Fully qualified tool name -> tool instance.
     tools: HashMap<String, ToolInfo>,
+
+    /// Incremented for every server added, so that a restart finishing after
+    /// its server was replaced or removed is discarded.
+    next_generation: u64,
+}
+
+struct ServerState {
+    config: McpServerConfig,
+    generation: u64,
+    /// `None` while the server is down.
+    client: Option<Arc<McpClient>>,
+    catalog: Catalog,
+    /// Failed restarts since the server was last up.
+    failures: u32,
+    /// When the next restart may be attempted.
+    retry_at: Instant,
+}
+
+/// What a server offers, as listed when it last started.
+#[derive(Default)]
+struct Catalog {
+    tools: Vec<Tool>,
+    /// Empty unless the server has the `resources` capability.
+    resources: Vec<Resource>,
+    /// Empty unless the server has the `prompts` capability.
+    prompts: Vec<Prompt>,
 }
 
 impl McpConnectionManager {
@@ -102,13 +172,15 @@ impl McpConnectionManager {
     ///   instructions.
     ///
     /// Servers that fail to start are reported in `ClientStartErrors`: the
-    /// user should be informed about these errors.
+    /// user should be informed about these errors. Health checks keep trying
+    /// to start them.
     pub async fn new(
         mcp_servers: HashMap<String, McpServerConfig>,
     ) -> Result<(Self, ClientStartErrors)> {
+        let manager = Self::default();
         // Early exit if no servers are configured.
         if mcp_servers.is_empty() {
-            return Ok((Self::default(), ClientStartErrors::default()));
+            return Ok((manager, ClientStartErrors::default()));
         }
 
         // Launch all configured servers concurrently.
@@ -127,74 +199,54 @@ impl McpConnectionManager {
             }
 
             join_set.spawn(async move {
-                let McpServerConfig { command, args, env } = cfg;
-                let client_res = McpClient::new_stdio_client(
-                    command.into(),
-                    args.into_iter().map(OsString::from).collect(),
-                    env,
-                )
-                .await;
-                match client_res {
-                    Ok(client) => {
-                        // Initialize the client.
-                        let params = mcp_types::InitializeRequestParams {
-                            capabilities: ClientCapabilities {
-                                experimental: None,
-                                roots: None,
-                                sampling: None,
-                                // https://modelcontextprotocol.io/specification/2025-06-18/client/elicitation#capabilities
-                                // indicates this should be an empty object.
-                                elicitation: Some(json!({})),
-                            },
-                            client_info: Implementation {
-                                name: "codex-mcp-client".to_owned(),
-                                version: env!("CARGO_PKG_VERSION").to_owned(),
-                                title: Some("Codex".into()),
-                            },
-                            protocol_version: mcp_types::MCP_SCHEMA_VERSION.to_owned(),
-                        };
-                        let initialize_notification_params = None;
-                        let timeout = Some(Duration::from_secs(10));
-                        match client
-                            .initialize(params, initialize_notification_params, timeout)
-                            .await
-                        {
-                            Ok(_response) => (server_name, Ok(client)),
-                            Err(e) => (server_name, Err(e)),
-                        }
-                    }
-                    Err(e) => (server_name, Err(e.into())),
-                }
+                let started = start_server(&cfg).await;
+                (server_name, cfg, started)
             });
         }
 
-        let mut clients: HashMap<String, std::sync::Arc<McpClient>> =
-            HashMap::with_capacity(join_set.len());
-
+        let mut results = Vec::new();
         while let Some(res) = join_set.join_next().await {
-            let (server_name, client_res) = res?; // JoinError propagation
+            results.push(res?); // JoinError propagation
+        }
 
-            match client_res {
-                Ok(client) => {
-                    clients.insert(server_name, std::sync::Arc::new(client));
-                }
+        let now = Instant::now();
+        let mut state = lock(&manager.state);
+        for (server_name, config, started) in results {
+            let generation = state.take_generation();
+            let server = match started {
+                Ok((client, catalog)) => ServerState {
+                    config,
+                    generation,
+                    client: Some(client),
+                    catalog,
+                    failures: 0,
+                    retry_at: now,
+                },
                 Err(e) => {
-                    errors.insert(server_name, e);
+                    errors.insert(server_name.clone(), e);
+                    ServerState {
+                        config,
+                        generation,
+                        client: None,
+                        catalog: Catalog::default(),
+                        failures: 1,
+                        retry_at: now,
+                    }
                 }
-            }
+            };
+            state.servers.insert(server_name, server);
         }
+        state.rebuild_tools();
+        drop(state);
 
-        let all_tools = list_all_tools(&clients).await?;
-
-        let tools = qualify_tools(all_tools);
-
-        Ok((Self { clients, tools }, errors))
+        Ok((manager, errors))
     }
 
     /// Returns a single map that contains **all** tools. Each key is the
     /// fully-qualified name for the tool.
     pub fn list_all_tools(&self) -> HashMap<String, Tool> {
-        self.tools
+        lock(&self.state)
+            .tools
             .iter()
             .map(|(name, tool)| (name.clone(), tool.tool.clone()))
             .collect()
@@ -208,11 +260,15 @@ impl McpConnectionManager {
         arguments: Option<serde_json::Value>,
         timeout: Option<Duration>,
     ) -> Result<mcp_types::CallToolResult> {
-        let client = self
-            .clients
-            .get(server)
-            .ok_or_else(|| anyhow!("unknown MCP server '{server}'"))?
-            .clone();
+        let client = self.client(server, |config| {
+            if offers_tool(config, tool) {
+                Ok(())
+            } else {
+                Err(anyhow!(
+                    "tool '{tool}' of MCP server '{server}' is disabled"
+                ))
+            }
+        })?;
 
         client
             .call_tool(tool.to_string(), arguments, timeout)
@@ -220,57 +276,480 @@ impl McpConnectionManager {
             .with_context(|| format!("tool call failed for `{server}/{tool}`"))
     }
 
+    /// Resources of all running servers, as (server name, resource) pairs
+    /// ordered by server name.
+    pub(crate) fn list_all_resources(&self) -> Vec<(String, Resource)> {
+        self.collect_catalog(|catalog| &catalog.resources)
+    }
+
+    /// Prompts of all running servers, as (server name, prompt) pairs
+    /// ordered by server name.
+    pub(crate) fn list_all_prompts(&self) -> Vec<(String, Prompt)> {
+        self.collect_catalog(|catalog| &catalog.prompts)
+    }
+
+    pub(crate) async fn read_resource(
+        &self,
+        server: &str,
+        uri: &str,
+        timeout: Option<Duration>,
+    ) -> Result<ReadResourceResult> {
+        self.client(server, |_| Ok(()))?
+            .read_resource(uri.to_string(), timeout)
+            .await
+            .with_context(|| format!("failed to read `{uri}` from `{server}`"))
+    }
+
+    /// Render a prompt of `server` with the given arguments.
+    pub(crate) async fn get_prompt(
+        &self,
+        server: &str,
+        prompt: &str,
+        arguments: HashMap<String, String>,
+    ) -> Result<GetPromptResult> {
+        let arguments = (!arguments.is_empty()).then(|| json!(arguments));
+        self.client(server, |_| Ok(()))?
+            .get_prompt(prompt.to_string(), arguments, Some(LIST_TOOLS_TIMEOUT))
+            .await
+            .with_context(|| format!("failed to get prompt `{prompt}` from `{server}`"))
+    }
+
+    /// The client of a running server whose config passes `check`.
+    fn client(
+        &self,
+        server: &str,
+        check: impl FnOnce(&McpServerConfig) -> Result<()>,
+    ) -> Result<Arc<McpClient>> {
+        let state = lock(&self.state);
+        let entry = state
+            .servers
+            .get(server)
+            .ok_or_else(|| anyhow!("unknown MCP server '{server}'"))?;
+        check(&entry.config)?;
+        entry
+            .client
+            .clone()
+            .ok_or_else(|| anyhow!("MCP server '{server}' is not running"))
+    }
+
+    fn collect_catalog<T: Clone>(&self, items: impl Fn(&Catalog) -> &Vec<T>) -> Vec<(String, T)> {
+        let state = lock(&self.state);
+        let mut server_names: Vec<&String> = state.servers.keys().collect();
+        server_names.sort();
+        server_names
+            .into_iter()
+            .flat_map(|server_name| {
+                items(&state.servers[server_name].catalog)
+                    .iter()
+                    .map(|item| (server_name.clone(), item.clone()))
+            })
+            .collect()
+    }
+
     pub fn parse_tool_name(&self, tool_name: &str) -> Option<(String, String)> {
-        self.tools
+        lock(&self.state)
+            .tools
             .get(tool_name)
             .map(|tool| (tool.server_name.clone(), tool.tool_name.clone()))
     }
-}
 
-/// Query every server for its available tools and return a single map that
-/// contains **all** tools. Each key is the fully-qualified name for the tool.
-async fn list_all_tools(
-    clients: &HashMap<String, std::sync::Arc<McpClient>>,
-) -> Result<Vec<ToolInfo>> {
-    let mut join_set = JoinSet::new();
-
-    // Spawn one task per server so we can query them concurrently. This
-    // keeps the overall latency roughly at the slowest server instead of
-    // the cumulative latency.
-    for (server_name, client) in clients {
-        let server_name_cloned = server_name.clone();
-        let client_clone = client.clone();
-        join_set.spawn(async move {
-            let res = client_clone
-                .list_tools(None, Some(LIST_TOOLS_TIMEOUT))
-                .await;
-            (server_name_cloned, res)
-        });
+    /// Whether the server marks the tool with the fully-qualified name
+    /// `tool_name` as read-only.
+    pub(crate) fn is_read_only_tool(&self, tool_name: &str) -> bool {
+        lock(&self.state).tools.get(tool_name).is_some_and(|tool| {
+            tool.tool
+                .annotations
+                .as_ref()
+                .and_then(|annotations| annotations.read_only_hint)
+                == Some(true)
+        })
+    }
+
+    /// Whether calls to `tool` of `server` need the user's approval.
+    pub(crate) fn tool_approval(&self, server: &str, tool: &str) -> McpToolApproval {
+        lock(&self.state)
+            .servers
+            .get(server)
+            .map_or(McpToolApproval::Auto, |entry| {
+                tool_approval(&entry.config, tool)
+            })
     }
 
-    let mut aggregated: Vec<ToolInfo> = Vec::with_capacity(join_set.len());
+    /// Start a server, replacing the one with the same name if there is one,
+    /// and return the number of tools it offers.
+    pub async fn add_server(&self, server_name: String, config: McpServerConfig) -> Result<usize> {
+        if !is_valid_mcp_server_name(&server_name) {
+            return Err(anyhow!(
+                "invalid server name '{server_name}': must match pattern ^[a-zA-Z0-9_-]+$"
+            ));
+        }
+        let (client, catalog) = start_server(&config).await?;
+        let tool_count = catalog.tools.len();
+        let mut state = lock(&self.state);
+        let generation = state.take_generation();
+        state.servers.insert(
+            server_name,
+            ServerState {
+                config,
+                generation,
+                client: Some(client),
+                catalog,
+                failures: 0,
+                retry_at: Instant::now(),
+            },
+        );
+        state.rebuild_tools();
+        Ok(tool_count)
+    }
+
+    /// Stop a server and withdraw its tools. Returns whether it existed.
+    pub fn remove_server(&self, server_name: &str) -> bool {
+        let mut state = lock(&self.state);
+        let removed = state.servers.remove(server_name).is_some();
+        if removed {
+            state.rebuild_tools();
+        }
+        removed
+    }
+
+    /// Try to restart the servers that are down and due for another attempt
+    /// and, with `ping`, check that the running ones still answer. Returns
+    /// the servers whose state changed.
+    pub(crate) async fn check_health(
+        &self,
+        health: &McpHealthConfig,
+        ping: bool,
+    ) -> Vec<McpServerStatusEvent> {
+        let ping_timeout = Duration::from_millis(health.ping_timeout_ms);
+        let now = Instant::now();
+        let servers: Vec<(String, u64, McpServerConfig, Option<Arc<McpClient>>)> = {
+            let state = lock(&self.state);
+            state
+                .servers
+                .iter()
+                .filter(|(_, server)| match server.client {
+                    Some(_) => ping,
+                    None => server.retry_at <= now,
+                })
+                .map(|(name, server)| {
+                    (
+                        name.clone(),
+                        server.generation,
+                        server.config.clone(),
+                        server.client.clone(),
+                    )
+                })
+                .collect()
+        };
 
-    while let Some(join_res) = join_set.join_next().await {
-        let (server_name, list_result) = join_res?;
-        let list_result = list_result?;
+        let mut join_set = JoinSet::new();
+        for (server_name, generation, config, client) in servers {
+            join_set.spawn(async move {
+                let ping_error = match &client {
+                    Some(client) => match client
+                        .send_request::<PingRequest>(None, Some(ping_timeout))
+                        .await
+                    {
+                        Ok(_) => return (server_name, generation, None, None),
+                        Err(e) => Some(e),
+                    },
+                    None => None,
+                };
+                let started = start_server(&config).await;
+                (server_name, generation, ping_error, Some(started))
+            });
+        }
 
-        for tool in list_result.tools {
-            let tool_info = ToolInfo {
-                server_name: server_name.clone(),
-                tool_name: tool.name.clone(),
-                tool,
+        let mut changes = Vec::new();
+        while let Some(res) = join_set.join_next().await {
+            let Ok((server_name, generation, ping_error, started)) = res else {
+                continue;
+            };
+            let Some(started) = started else {
+                continue;
             };
-            aggregated.push(tool_info);
+            let mut state = lock(&self.state);
+            let Some(server) = state
+                .servers
+                .get_mut(&server_name)
+                .filter(|server| server.generation == generation)
+            else {
+                continue;
+            };
+            if let Some(e) = &ping_error {
+                warn!("MCP server `{server_name}` did not answer a ping: {e:#}");
+                changes.push(McpServerStatusEvent {
+                    server: server_name.clone(),
+                    status: McpServerStatus::Restarting,
+                    tools: None,
+                    message: Some(format!("{e:#}")),
+                });
+            }
+            match started {
+                Ok((client, catalog)) => {
+                    info!("MCP server `{server_name}` restarted");
+                    changes.push(McpServerStatusEvent {
+                        server: server_name,
+                        status: McpServerStatus::Running,
+                        tools: Some(catalog.tools.len()),
+                        message: None,
+                    });
+                    server.client = Some(client);
+                    server.catalog = catalog;
+                    server.failures = 0;
+                }
+                Err(e) => {
+                    server.client = None;
+                    server.catalog = Catalog::default();
+                    server.failures += 1;
+                    let delay = restart_delay(server.failures, health);
+                    server.retry_at = Instant::now() + delay;
+                    changes.push(McpServerStatusEvent {
+                        server: server_name,
+                        status: McpServerStatus::Failed,
+                        tools: None,
+                        message: Some(format!("{e:#}; retrying in {}s", delay.as_secs().max(1))),
+                    });
+                }
+            }
+            state.rebuild_tools();
+        }
+        changes.sort_by(|a, b| a.server.cmp(&b.server));
+        changes
+    }
+
+    /// When the earliest restart of a server that is down is due.
+    fn next_restart(&self) -> Option<Instant> {
+        lock(&self.state)
+            .servers
+            .values()
+            .filter(|server| server.client.is_none())
+            .map(|server| server.retry_at)
+            .min()
+    }
+
+    /// Ping the servers every `health.interval_ms` and restart the ones that
+    /// are down as soon as they are due, for as long as the manager is alive,
+    /// passing the changes to `on_change`.
+    pub(crate) fn spawn_health_checks<F, Fut>(&self, health: McpHealthConfig, on_change: F)
+    where
+        F: Fn(McpServerStatusEvent) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send,
+    {
+        if health.interval_ms == 0 {
+            return;
         }
+        let interval = Duration::from_millis(health.interval_ms);
+        let state = Arc::downgrade(&self.state);
+        tokio::spawn(async move {
+            let mut next_ping = Instant::now() + interval;
+            loop {
+                let wake_at = match state.upgrade() {
+                    Some(state) => Self { state }
+                        .next_restart()
+                        .map_or(next_ping, |restart| restart.min(next_ping)),
+                    None => break,
+                };
+                tokio::time::sleep_until(wake_at.into()).await;
+                let Some(state) = state.upgrade() else {
+                    break;
+                };
+                let manager = Self { state };
+                let ping = Instant::now() >= next_ping;
+                if ping {
+                    next_ping = Instant::now() + interval;
+                }
+                for change in manager.check_health(&health, ping).await {
+                    on_change(change).await;
+                }
+            }
+        });
+    }
+}
+
+impl ManagerState {
+    fn take_generation(&mut self) -> u64 {
+        self.next_generation += 1;
+        self.next_generation
     }
 
-    info!(
-        "aggregated {} tools from {} servers",
-        aggregated.len(),
-        clients.len()
-    );
+    fn rebuild_tools(&mut self) {
+        let mut server_names: Vec<&String> = self.servers.keys().collect();
+        server_names.sort();
+        let all_tools: Vec<ToolInfo> = server_names
+            .into_iter()
+            .flat_map(|server_name| {
+                let server = &self.servers[server_name];
+                server
+                    .catalog
+                    .tools
+                    .iter()
+                    .filter(|tool| offers_tool(&server.config, &tool.name))
+                    .map(move |tool| ToolInfo {
+                        server_name: server_name.clone(),
+                        tool_name: tool.name.clone(),
+                        tool: tool.clone(),
+                    })
+            })
+            .collect();
+        info!(
+            "aggregated {} tools from {} servers",
+            all_tools.len(),
+            self.servers.len()
+        );
+        self.tools = qualify_tools(all_tools);
+    }
+}
+
+/// Whether the server's `enabled_tools` and `disabled_tools` let the model
+/// see `tool`.
+fn offers_tool(config: &McpServerConfig, tool: &str) -> bool {
+    (config.enabled_tools.is_empty() || matches_any(&config.enabled_tools, tool))
+        && !matches_any(&config.disabled_tools, tool)
+}
+
+fn tool_approval(config: &McpServerConfig, tool: &str) -> McpToolApproval {
+    if let Some(approval) = config.tool_approval.get(tool) {
+        return *approval;
+    }
+    let matching: Vec<McpToolApproval> = config
+        .tool_approval
+        .iter()
+        .filter(|(pattern, _)| WildMatchPattern::<'*', '?'>::new(pattern).matches(tool))
+        .map(|(_, approval)| *approval)
+        .collect();
+    if matching.contains(&McpToolApproval::Prompt) {
+        McpToolApproval::Prompt
+    } else if matching.is_empty() {
+        config.approval
+    } else {
+        McpToolApproval::Auto
+    }
+}
 
-    Ok(aggregated)
+fn matches_any(patterns: &[String], tool: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| WildMatchPattern::<'*', '?'>::new(pattern).matches(tool))
+}
+
+fn lock(state: &Mutex<ManagerState>) -> MutexGuard<'_, ManagerState> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn restart_delay(failures: u32, health: &McpHealthConfig) -> Duration {
+    let delay_ms = 1_000u64.saturating_mul(1 << failures.saturating_sub(1).min(20));
+    Duration::from_millis(delay_ms.min(health.max_restart_delay_ms))
+}
+
+/// Spawn and initialize a server, then list what it offers.
+async fn start_server(config: &McpServerConfig) -> Result<(Arc<McpClient>, Catalog)> {
+    let McpServerConfig {
+        command, args, env, ..
+    } = config.clone();
+    let client = McpClient::new_stdio_client(
+        command.clone().into(),
+        args.into_iter().map(OsString::from).collect(),
+        env,
+    )
+    .await?;
+
+    // Initialize the client.
+    let params = mcp_types::InitializeRequestParams {
+        capabilities: ClientCapabilities {
+            experimental: None,
+            roots: None,
+            sampling: None,
+            // https://modelcontextprotocol.io/specification/2025-06-18/client/elicitation#capabilities
+            // indicates this should be an empty object.
+            elicitation: Some(json!({})),
+        },
+        client_info: Implementation {
+            name: "codex-mcp-client".to_owned(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            title: Some("Codex".into()),
+        },
+        protocol_version: mcp_types::MCP_SCHEMA_VERSION.to_owned(),
+    };
+    let initialize_notification_params = None;
+    let timeout = Some(Duration::from_secs(10));
+    let initialized = client
+        .initialize(params, initialize_notification_params, timeout)
+        .await?;
+
+    let tools = client
+        .list_tools(None, Some(LIST_TOOLS_TIMEOUT))
+        .await?
+        .tools;
+    // Resources and prompts are extras: a server that fails to list them
+    // still offers its tools.
+    let capabilities = initialized.capabilities;
+    let resources = if capabilities.resources.is_some() {
+        list_resources(&client).await.unwrap_or_else(|e| {
+            warn!("failed to list resources of MCP server `{command}`: {e:#}");
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    let prompts = if capabilities.prompts.is_some() {
+        list_prompts(&client).await.unwrap_or_else(|e| {
+            warn!("failed to list prompts of MCP server `{command}`: {e:#}");
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    Ok((
+        Arc::new(client),
+        Catalog {
+            tools,
+            resources,
+            prompts,
+        },
+    ))
+}
+
+async fn list_resources(client: &McpClient) -> Result<Vec<Resource>> {
+    let mut resources = Vec::new();
+    let mut cursor = None;
+    for _ in 0..MAX_LIST_PAGES {
+        let page = client
+            .list_resources(
+                cursor.map(|cursor| ListResourcesRequestParams {
+                    cursor: Some(cursor),
+                }),
+                Some(LIST_TOOLS_TIMEOUT),
+            )
+            .await?;
+        resources.extend(page.resources);
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    Ok(resources)
+}
+
+async fn list_prompts(client: &McpClient) -> Result<Vec<Prompt>> {
+    let mut prompts = Vec::new();
+    let mut cursor = None;
+    for _ in 0..MAX_LIST_PAGES {
+        let page = client
+            .list_prompts(
+                cursor.map(|cursor| ListPromptsRequestParams {
+                    cursor: Some(cursor),
+                }),
+                Some(LIST_TOOLS_TIMEOUT),
+            )
+            .await?;
+        prompts.extend(page.prompts);
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    Ok(prompts)
 }
 
 fn is_valid_mcp_server_name(server_name: &str) -> bool {
@@ -285,6 +764,99 @@ mod tests {
     use super::*;
     use mcp_types::ToolInputSchema;
 
+    #[test]
+    fn tool_filters_and_approval_overrides() {
+        let config = McpServerConfig {
+            command: "server".to_string(),
+            enabled_tools: vec!["get_*".to_string(), "create_*".to_string()],
+            disabled_tools: vec!["*_secret".to_string()],
+            tool_approval: HashMap::from([
+                ("create_*".to_string(), McpToolApproval::Prompt),
+                ("*_issue".to_string(), McpToolApproval::Auto),
+                ("create_comment".to_string(), McpToolApproval::Auto),
+            ]),
+            ..Default::default()
+        };
+        assert!(offers_tool(&config, "get_issue"));
+        assert!(offers_tool(&config, "create_issue"));
+        assert!(!offers_tool(&config, "get_secret"));
+        assert!(!offers_tool(&config, "delete_issue"));
+
+        assert_eq!(tool_approval(&config, "get_issue"), McpToolApproval::Auto);
+        // `prompt` wins among matching patterns, an exact name wins over both.
+        assert_eq!(
+            tool_approval(&config, "create_issue"),
+            McpToolApproval::Prompt
+        );
+        assert_eq!(
+            tool_approval(&config, "create_comment"),
+            McpToolApproval::Auto
+        );
+
+        let config = McpServerConfig {
+            approval: McpToolApproval::Prompt,
+            ..config
+        };
+        assert_eq!(tool_approval(&config, "get_repo"), McpToolApproval::Prompt);
+        assert_eq!(tool_approval(&config, "get_issue"), McpToolApproval::Auto);
+    }
+
+    #[test]
+    fn restart_delay_doubles_up_to_the_maximum() {
+        let health = McpHealthConfig {
+            max_restart_delay_ms: 10_000,
+            ..McpHealthConfig::default()
+        };
+        let delays: Vec<u64> = (1..=6)
+            .map(|failures| restart_delay(failures, &health).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![1_000, 2_000, 4_000, 8_000, 10_000, 10_000]);
+    }
+
+    #[tokio::test]
+    async fn failed_servers_are_kept_and_retried() {
+        let config = McpServerConfig {
+            command: "codex-test-no-such-mcp-server".to_string(),
+            ..Default::default()
+        };
+        let (manager, errors) =
+            McpConnectionManager::new(HashMap::from([("broken".to_string(), config)]))
+                .await
+                .unwrap();
+        assert!(errors.contains_key("broken"));
+        assert!(manager.next_restart().is_some());
+        assert!(
+            manager
+                .call_tool("broken", "tool", None, None)
+                .await
+                .is_err()
+        );
+
+        let changes = manager
+            .check_health(&McpHealthConfig::default(), true)
+            .await;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].server, "broken");
+        assert_eq!(changes[0].status, McpServerStatus::Failed);
+        assert!(
+            changes[0]
+                .message
+                .as_deref()
+                .is_some_and(|message| message.ends_with("retrying in 2s"))
+        );
+        // The next attempt is not due yet.
+        assert!(
+            manager
+                .check_health(&McpHealthConfig::default(), true)
+                .await
+                .is_empty()
+        );
+
+        assert!(manager.remove_server("broken"));
+        assert!(!manager.remove_server("broken"));
+        assert_eq!(manager.next_restart(), None);
+    }
+
     fn create_test_tool(server_name: &str, tool_name: &str) -> ToolInfo {
         ToolInfo {
             server_name: server_name.to_string(),
@@ -332,6 +904,26 @@ mod tests {
         assert!(qualified_tools.contains_key("server1__duplicate_tool"));
     }
 
+    #[test]
+    fn test_qualify_tools_collision_across_servers() {
+        let tools = vec![
+            create_test_tool("a__b", "c"),
+            create_test_tool("a", "b__c"),
+            create_test_tool("a", "d"),
+        ];
+
+        let qualified_tools = qualify_tools(tools);
+
+        let mut keys: Vec<_> = qualified_tools.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["a__b__c", "a__d"]);
+        let kept = &qualified_tools["a__b__c"];
+        assert_eq!(
+            (kept.server_name.as_str(), kept.tool_name.as_str()),
+            ("a", "b__c")
+        );
+    }
+
     #[test]
     fn test_qualify_tools_long_names_same_server() {
         let server_name = "my_server";
@@ -1,21 +1,50 @@
+use std::collections::BTreeMap;
 use std::time::Duration;
 use std::time::Instant;
 
+use mcp_types::ReadResourceResultContents;
+use mcp_types::Resource;
+use serde::Deserialize;
 use tracing::error;
 
+use crate::audit_log::AuditAction;
+use crate::audit_log::AuditApproval;
+use crate::audit_log::AuditOutcome;
 use crate::codex::Session;
+use crate::codex::TurnContext;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::OpenAiTool;
+use crate::openai_tools::ResponsesApiTool;
+use crate::protocol::AskForApproval;
 use crate::protocol::Event;
 use crate::protocol::EventMsg;
 use crate::protocol::McpInvocation;
 use crate::protocol::McpToolCallBeginEvent;
 use crate::protocol::McpToolCallEndEvent;
+use crate::protocol::ReviewDecision;
 use codex_protocol::models::FunctionCallOutputPayload;
 use codex_protocol::models::ResponseInputItem;
 
+pub(crate) const READ_RESOURCE_TOOL_NAME: &str = "read_resource";
+
+/// Resources listed in the description of the `read_resource` tool; the
+/// model can still read the others by URI.
+const MAX_LISTED_RESOURCES: usize = 50;
+
+const MAX_RESOURCE_OUTPUT_BYTES: usize = 64 * 1024;
+
+#[derive(Deserialize)]
+struct ReadResourceArgs {
+    server: String,
+    uri: String,
+}
+
 /// Handles the specified tool call dispatches the appropriate
 /// `McpToolCallBegin` and `McpToolCallEnd` events to the `Session`.
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn handle_mcp_tool_call(
     sess: &Session,
+    turn_context: &TurnContext,
     sub_id: &str,
     call_id: String,
     server: String,
@@ -43,6 +72,45 @@ pub(crate) async fn handle_mcp_tool_call(
         }
     };
 
+    let approval = match request_approval(
+        sess,
+        turn_context,
+        sub_id,
+        &call_id,
+        &server,
+        &tool_name,
+        &arguments,
+    )
+    .await
+    {
+        Ok(approval) => approval,
+        Err(approval) => {
+            let content = match &approval {
+                AuditApproval::Rejected { reason } => format!("MCP tool call rejected: {reason}"),
+                _ => "MCP tool call rejected by user".to_string(),
+            };
+            if sess.audits_mcp_tool(&server, &tool_name) {
+                sess.record_audit(
+                    AuditAction::McpToolCall {
+                        server,
+                        tool: tool_name,
+                        arguments: arguments_value,
+                    },
+                    approval,
+                    None,
+                )
+                .await;
+            }
+            return ResponseInputItem::FunctionCallOutput {
+                call_id,
+                output: FunctionCallOutputPayload {
+                    content,
+                    success: Some(false),
+                },
+            };
+        }
+    };
+
     let invocation = McpInvocation {
         server: server.clone(),
         tool: tool_name.clone(),
@@ -57,10 +125,15 @@ pub(crate) async fn handle_mcp_tool_call(
 
     let start = Instant::now();
     // Perform the tool call.
-    let result = sess
-        .call_tool(&server, &tool_name, arguments_value.clone(), timeout)
-        .await
-        .map_err(|e| format!("tool call error: {e}"));
+    // Dropping the call on cancellation sends `notifications/cancelled` to
+    // the server.
+    let cancel = sess.cancellation_token(sub_id);
+    let result = tokio::select! {
+        result = sess.call_tool(&server, &tool_name, arguments_value.clone(), timeout) => {
+            result.map_err(|e| format!("tool call error: {e}"))
+        }
+        _ = cancel.cancelled() => Err("tool call cancelled by the user".to_string()),
+    };
     let tool_call_end_event = EventMsg::McpToolCallEnd(McpToolCallEndEvent {
         call_id: call_id.clone(),
         invocation,
@@ -70,9 +143,176 @@ pub(crate) async fn handle_mcp_tool_call(
 
     notify_mcp_tool_call_event(sess, sub_id, tool_call_end_event.clone()).await;
 
+    if sess.audits_mcp_tool(&server, &tool_name) {
+        let outcome = match &result {
+            Ok(result) => AuditOutcome {
+                success: !result.is_error.unwrap_or(false),
+                exit_code: None,
+                error: None,
+            },
+            Err(e) => AuditOutcome::error(e),
+        };
+        sess.record_audit(
+            AuditAction::McpToolCall {
+                server,
+                tool: tool_name,
+                arguments: arguments_value,
+            },
+            approval,
+            Some(outcome),
+        )
+        .await;
+    }
+
     ResponseInputItem::McpToolCallOutput { call_id, result }
 }
 
+/// Ask the user before calling a tool configured with `prompt` approval.
+/// Returns how the call was approved, or why it must not be made.
+async fn request_approval(
+    sess: &Session,
+    turn_context: &TurnContext,
+    sub_id: &str,
+    call_id: &str,
+    server: &str,
+    tool_name: &str,
+    arguments: &str,
+) -> Result<AuditApproval, AuditApproval> {
+    if !sess.mcp_tool_needs_approval(server, tool_name) {
+        return Ok(AuditApproval::NotRequired);
+    }
+    if turn_context.approval_policy == AskForApproval::Never {
+        return Err(AuditApproval::Rejected {
+            reason: format!(
+                "`{server}/{tool_name}` requires approval but the approval policy is `never`"
+            ),
+        });
+    }
+
+    // Approval requests show a command, so present the call as one.
+    let mut command = vec![format!("{server}/{tool_name}")];
+    if !arguments.trim().is_empty() {
+        command.push(arguments.to_string());
+    }
+    let rx_approve = sess
+        .request_command_approval(
+            sub_id.to_string(),
+            call_id.to_string(),
+            command,
+            turn_context.cwd.clone(),
+            Some(format!("MCP tool `{server}/{tool_name}` requires approval")),
+            None,
+        )
+        .await;
+    match rx_approve.await.unwrap_or_default() {
+        ReviewDecision::Approved => Ok(AuditApproval::Approved),
+        ReviewDecision::ApprovedForSession => {
+            sess.add_approved_mcp_tool(server, tool_name);
+            Ok(AuditApproval::ApprovedForSession)
+        }
+        ReviewDecision::Denied | ReviewDecision::Abort => Err(AuditApproval::Denied),
+    }
+}
+
+/// The `read_resource` tool, describing the resources offered by the MCP
+/// servers as `(server, resource)` pairs.
+pub(crate) fn create_read_resource_tool(resources: &[(String, Resource)]) -> OpenAiTool {
+    let mut description = "Reads a resource offered by an MCP server and returns its contents. \
+        Available resources:"
+        .to_string();
+    for (server, resource) in resources.iter().take(MAX_LISTED_RESOURCES) {
+        description.push_str(&format!("\n- server `{server}`, uri `{}`", resource.uri));
+        let title = resource.title.as_deref().unwrap_or(&resource.name);
+        match &resource.description {
+            Some(summary) => description.push_str(&format!(": {title} - {summary}")),
+            None => description.push_str(&format!(": {title}")),
+        }
+    }
+    if resources.len() > MAX_LISTED_RESOURCES {
+        description.push_str(&format!(
+            "\n- ... and {} more",
+            resources.len() - MAX_LISTED_RESOURCES
+        ));
+    }
+
+    let properties = BTreeMap::from([
+        (
+            "server".to_string(),
+            JsonSchema::String {
+                description: Some("Name of the MCP server offering the resource.".to_string()),
+            },
+        ),
+        (
+            "uri".to_string(),
+            JsonSchema::String {
+                description: Some("URI of the resource.".to_string()),
+            },
+        ),
+    ]);
+    OpenAiTool::Function(ResponsesApiTool {
+        name: READ_RESOURCE_TOOL_NAME.to_string(),
+        description,
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["server".to_string(), "uri".to_string()]),
+            additional_properties: Some(false),
+        },
+    })
+}
+
+/// Reads an MCP resource for the model. Text contents are returned as is,
+/// binary contents are only described.
+pub(crate) async fn handle_read_resource(
+    sess: &Session,
+    call_id: String,
+    arguments: &str,
+) -> ResponseInputItem {
+    let reply = |content: String, success: bool| ResponseInputItem::FunctionCallOutput {
+        call_id: call_id.clone(),
+        output: FunctionCallOutputPayload {
+            content,
+            success: Some(success),
+        },
+    };
+    let args = match serde_json::from_str::<ReadResourceArgs>(arguments) {
+        Ok(args) => args,
+        Err(e) => return reply(format!("failed to parse function arguments: {e}"), false),
+    };
+    match sess.read_mcp_resource(&args.server, &args.uri).await {
+        Ok(result) => {
+            let content = result
+                .contents
+                .into_iter()
+                .map(|contents| match contents {
+                    ReadResourceResultContents::TextResourceContents(text) => text.text,
+                    ReadResourceResultContents::BlobResourceContents(blob) => format!(
+                        "[binary resource {} ({}), {} bytes base64-encoded]",
+                        blob.uri,
+                        blob.mime_type.as_deref().unwrap_or("unknown type"),
+                        blob.blob.len()
+                    ),
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            reply(truncate_resource(content), true)
+        }
+        Err(e) => reply(format!("{e:#}"), false),
+    }
+}
+
+fn truncate_resource(mut content: String) -> String {
+    if content.len() > MAX_RESOURCE_OUTPUT_BYTES {
+        let mut end = MAX_RESOURCE_OUTPUT_BYTES;
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
+        content.truncate(end);
+        content.push_str("\n[... resource truncated ...]");
+    }
+    content
+}
+
 async fn notify_mcp_tool_call_event(sess: &Session, sub_id: &str, event: EventMsg) {
     sess.send_event(Event {
         id: sub_id.to_string(),
@@ -80,3 +320,51 @@ async fn notify_mcp_tool_call_event(sess: &Session, sub_id: &str, event: EventMs
     })
     .await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn resource(uri: &str, description: Option<&str>) -> Resource {
+        Resource {
+            annotations: None,
+            description: description.map(str::to_string),
+            mime_type: None,
+            name: uri.rsplit('/').next().unwrap_or(uri).to_string(),
+            size: None,
+            title: None,
+            uri: uri.to_string(),
+        }
+    }
+
+    #[test]
+    fn read_resource_tool_lists_resources() {
+        let mut resources = vec![
+            (
+                "docs".to_string(),
+                resource("file:///guide.md", Some("User guide")),
+            ),
+            ("kb".to_string(), resource("kb://articles/42", None)),
+        ];
+        let OpenAiTool::Function(tool) = create_read_resource_tool(&resources) else {
+            panic!("expected a function tool");
+        };
+        assert_eq!(
+            tool.description,
+            "Reads a resource offered by an MCP server and returns its contents. \
+             Available resources:\n\
+             - server `docs`, uri `file:///guide.md`: guide.md - User guide\n\
+             - server `kb`, uri `kb://articles/42`: 42"
+        );
+
+        resources.extend(
+            (0..MAX_LISTED_RESOURCES)
+                .map(|i| ("kb".to_string(), resource(&format!("kb://{i}"), None))),
+        );
+        let OpenAiTool::Function(tool) = create_read_resource_tool(&resources) else {
+            panic!("expected a function tool");
+        };
+        assert!(tool.description.ends_with("\n- ... and 2 more"));
+    }
+}
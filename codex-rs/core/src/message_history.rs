@@ -18,6 +18,7 @@ use std::fs::File;
 use std::fs::OpenOptions;
 use std::io::Result;
 use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
 
 use serde::Deserialize;
@@ -48,47 +49,63 @@ pub struct HistoryEntry {
     pub text: String,
 }
 
-fn history_filepath(config: &Config) -> PathBuf {
+pub(crate) fn history_filepath(config: &Config) -> PathBuf {
     let mut path = config.codex_home.clone();
     path.push(HISTORY_FILENAME);
     path
 }
 
-/// Append a `text` entry associated with `session_id` to the history file. Uses
-/// advisory file locking to ensure that concurrent writes do not interleave,
-/// which entails a small amount of blocking I/O internally.
-pub(crate) async fn append_entry(text: &str, session_id: &Uuid, config: &Config) -> Result<()> {
+/// Build the history entry for `text` typed in session `session_id`, or
+/// `None` if history persistence is turned off.
+pub(crate) fn new_entry(
+    text: &str,
+    session_id: &Uuid,
+    config: &Config,
+) -> Result<Option<HistoryEntry>> {
     match config.history.persistence {
         HistoryPersistence::SaveAll => {
             // Save everything: proceed.
         }
         HistoryPersistence::None => {
             // No history persistence requested.
-            return Ok(());
+            return Ok(None);
         }
     }
 
     // TODO: check `text` for sensitive patterns
 
-    // Resolve `~/.codex/history.jsonl` and ensure the parent directory exists.
-    let path = history_filepath(config);
-    if let Some(parent) = path.parent() {
-        tokio::fs::create_dir_all(parent).await?;
-    }
-
     // Compute timestamp (seconds since the Unix epoch).
     let ts = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .map_err(|e| std::io::Error::other(format!("system clock before Unix epoch: {e}")))?
         .as_secs();
 
-    // Construct the JSON line first so we can write it in a single syscall.
-    let entry = HistoryEntry {
+    Ok(Some(HistoryEntry {
         session_id: session_id.to_string(),
         ts,
         text: text.to_string(),
-    };
-    let mut line = serde_json::to_string(&entry)
+    }))
+}
+
+/// Append a `text` entry associated with `session_id` to the history file. Uses
+/// advisory file locking to ensure that concurrent writes do not interleave,
+/// which entails a small amount of blocking I/O internally.
+pub(crate) async fn append_entry(text: &str, session_id: &Uuid, config: &Config) -> Result<()> {
+    match new_entry(text, session_id, config)? {
+        Some(entry) => write_entry(&history_filepath(config), &entry).await,
+        None => Ok(()),
+    }
+}
+
+/// Append `entry` to the history file at `path`.
+pub(crate) async fn write_entry(path: &Path, entry: &HistoryEntry) -> Result<()> {
+    // Ensure the parent directory of `~/.codex/history.jsonl` exists.
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    // Construct the JSON line first so we can write it in a single syscall.
+    let mut line = serde_json::to_string(entry)
         .map_err(|e| std::io::Error::other(format!("failed to serialise history entry: {e}")))?;
     line.push('\n');
 
@@ -100,7 +117,7 @@ pub(crate) async fn append_entry(text: &str, session_id: &Uuid, config: &Config)
         options.mode(0o600);
     }
 
-    let mut history_file = options.open(&path)?;
+    let mut history_file = options.open(path)?;
 
     // Ensure permissions.
     ensure_owner_only_permissions(&history_file).await?;
@@ -0,0 +1,648 @@
+//! The `post_message` tool: posts a markdown message, optionally with a
+//! title and facts laid out as a card, to a Teams or Slack channel from the
+//! `[messaging]` table, e.g. to publish an investigation summary or release
+//! notes at the end of a task.
+//!
+//! Teams channels get an Adaptive Card through a Workflows webhook. Slack
+//! channels get Block Kit blocks through an incoming webhook or, with a bot
+//! token, `chat.postMessage`. Markdown is rewritten into the dialect each
+//! platform understands.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+use serde_json::json;
+
+use crate::config_types::MessagingChannelConfig;
+use crate::config_types::MessagingConfig;
+use crate::config_types::MessagingPlatform;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+
+pub(crate) const POST_MESSAGE_TOOL_NAME: &str = "post_message";
+
+/// Longest text of one Slack section block.
+const SLACK_SECTION_CHARS: usize = 3000;
+
+/// Most fields of one Slack section block.
+const SLACK_SECTION_FIELDS: usize = 10;
+
+/// Longest text of a Slack header block.
+const SLACK_HEADER_CHARS: usize = 150;
+
+#[derive(Debug, Deserialize)]
+struct PostMessageParams {
+    channel: String,
+    text: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    facts: Vec<Fact>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Fact {
+    title: String,
+    value: String,
+}
+
+/// A message rendered for its channel, ready to be approved and posted.
+pub(crate) struct PreparedMessage {
+    pub(crate) channel: String,
+    /// The markdown the model wrote, shown for approval.
+    pub(crate) text: String,
+    pub(crate) payload: Value,
+}
+
+/// Where a channel's messages are sent.
+enum Endpoint {
+    Webhook(String),
+    SlackChannel { channel: String, token: String },
+}
+
+pub(crate) struct Messaging {
+    config: MessagingConfig,
+    client: reqwest::Client,
+}
+
+impl Messaging {
+    pub(crate) fn new(config: MessagingConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub(crate) fn channels(&self) -> &HashMap<String, MessagingChannelConfig> {
+        &self.config.channels
+    }
+
+    /// Parses a `post_message` call and renders the message for its channel.
+    pub(crate) fn prepare(&self, arguments: &str) -> Result<PreparedMessage, String> {
+        let params: PostMessageParams = serde_json::from_str(arguments)
+            .map_err(|e| format!("failed to parse function arguments: {e}"))?;
+        let channel = self.channel(&params.channel)?;
+        let length = params.text.chars().count();
+        if length > self.config.max_message_chars {
+            return Err(format!(
+                "the message has {length} characters; the limit is {}",
+                self.config.max_message_chars
+            ));
+        }
+        let payload = match channel.platform {
+            MessagingPlatform::Teams => teams_payload(&params),
+            MessagingPlatform::Slack => slack_payload(&params),
+        };
+        Ok(PreparedMessage {
+            channel: params.channel,
+            text: params.text,
+            payload,
+        })
+    }
+
+    pub(crate) async fn post(&self, message: &PreparedMessage) -> Result<String, String> {
+        let channel = self.channel(&message.channel)?;
+        let endpoint =
+            endpoint(channel).map_err(|e| format!("cannot post to `{}`: {e}", message.channel))?;
+        let response = match endpoint {
+            Endpoint::Webhook(url) => self.client.post(url).json(&message.payload).send().await,
+            Endpoint::SlackChannel {
+                channel: slack_channel,
+                token,
+            } => {
+                let mut payload = message.payload.clone();
+                payload["channel"] = json!(slack_channel);
+                self.client
+                    .post(format!(
+                        "{}/chat.postMessage",
+                        self.config.slack_api_url.trim_end_matches('/')
+                    ))
+                    .bearer_auth(token)
+                    .json(&payload)
+                    .send()
+                    .await
+            }
+        };
+        let response =
+            response.map_err(|e| format!("failed to post to `{}`: {e}", message.channel))?;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            return Err(format!(
+                "`{}` rejected the message ({status}): {}",
+                message.channel,
+                body.trim()
+            ));
+        }
+        // The Slack Web API reports errors in the body of a 200 response.
+        if let Ok(reply) = serde_json::from_str::<Value>(&body)
+            && reply.get("ok") == Some(&Value::Bool(false))
+        {
+            let error = reply
+                .get("error")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error");
+            return Err(format!(
+                "`{}` rejected the message: {error}",
+                message.channel
+            ));
+        }
+        Ok(format!("Posted to `{}`.", message.channel))
+    }
+
+    fn channel(&self, name: &str) -> Result<&MessagingChannelConfig, String> {
+        self.config.channels.get(name).ok_or_else(|| {
+            let mut names: Vec<&str> = self.config.channels.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            format!(
+                "unknown channel `{name}`; configured channels: {}",
+                names.join(", ")
+            )
+        })
+    }
+}
+
+fn endpoint(channel: &MessagingChannelConfig) -> Result<Endpoint, String> {
+    if let Some(url) = &channel.webhook_url {
+        return Ok(Endpoint::Webhook(url.clone()));
+    }
+    if let Some(var) = &channel.webhook_url_env_var {
+        return env_value(var).map(Endpoint::Webhook);
+    }
+    if let Some(slack_channel) = &channel.slack_channel
+        && channel.platform == MessagingPlatform::Slack
+    {
+        return Ok(Endpoint::SlackChannel {
+            channel: slack_channel.clone(),
+            token: env_value(&channel.slack_token_env_var)?,
+        });
+    }
+    Err("no webhook_url, webhook_url_env_var or slack_channel is configured".to_string())
+}
+
+fn env_value(var: &str) -> Result<String, String> {
+    std::env::var(var)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| format!("the {var} environment variable is not set"))
+}
+
+/// An Adaptive Card, as Teams Workflows webhooks accept it.
+fn teams_payload(params: &PostMessageParams) -> Value {
+    let mut body = Vec::new();
+    if let Some(title) = &params.title {
+        body.push(json!({
+            "type": "TextBlock",
+            "text": title,
+            "size": "Large",
+            "weight": "Bolder",
+            "wrap": true,
+        }));
+    }
+    body.push(json!({
+        "type": "TextBlock",
+        "text": teams_markdown(&params.text),
+        "wrap": true,
+    }));
+    if !params.facts.is_empty() {
+        let facts: Vec<Value> = params
+            .facts
+            .iter()
+            .map(|fact| json!({ "title": fact.title, "value": fact.value }))
+            .collect();
+        body.push(json!({ "type": "FactSet", "facts": facts }));
+    }
+    json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": body,
+                "msteams": { "width": "Full" },
+            },
+        }],
+    })
+}
+
+/// Plain mrkdwn text, or Block Kit blocks when the message has a title or
+/// facts. The text is kept as the notification fallback.
+fn slack_payload(params: &PostMessageParams) -> Value {
+    let text = slack_mrkdwn(&params.text);
+    if params.title.is_none() && params.facts.is_empty() {
+        return json!({ "text": text });
+    }
+    let mut blocks = Vec::new();
+    if let Some(title) = &params.title {
+        let title: String = title.chars().take(SLACK_HEADER_CHARS).collect();
+        blocks.push(json!({
+            "type": "header",
+            "text": { "type": "plain_text", "text": title },
+        }));
+    }
+    for chunk in split_text(&text, SLACK_SECTION_CHARS) {
+        blocks.push(json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": chunk },
+        }));
+    }
+    for facts in params.facts.chunks(SLACK_SECTION_FIELDS) {
+        let fields: Vec<Value> = facts
+            .iter()
+            .map(|fact| {
+                json!({
+                    "type": "mrkdwn",
+                    "text": format!("*{}*\n{}", slack_escape(&fact.title), slack_escape(&fact.value)),
+                })
+            })
+            .collect();
+        blocks.push(json!({ "type": "section", "fields": fields }));
+    }
+    let fallback = match &params.title {
+        Some(title) => format!("{}\n{text}", slack_escape(title)),
+        None => text,
+    };
+    json!({ "text": fallback, "blocks": blocks })
+}
+
+/// Splits `text` into pieces of at most `max_chars` characters, at line
+/// breaks where possible.
+fn split_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for line in text.split_inclusive('\n') {
+        let mut line = line;
+        while line.chars().count() > max_chars {
+            if !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+            }
+            let split = line
+                .char_indices()
+                .nth(max_chars)
+                .map_or(line.len(), |(index, _)| index);
+            chunks.push(line[..split].to_string());
+            line = &line[split..];
+        }
+        if current.chars().count() + line.chars().count() > max_chars {
+            chunks.push(std::mem::take(&mut current));
+        }
+        current.push_str(line);
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Markdown that Adaptive Card text blocks render. They have no headings, so
+/// headings become bold lines.
+fn teams_markdown(markdown: &str) -> String {
+    map_lines(markdown, |line| match heading(line) {
+        Some(text) => format!("**{text}**"),
+        None => line.to_string(),
+    })
+}
+
+/// Markdown rewritten as Slack mrkdwn: single-asterisk bold, underscore
+/// italics, `<url|text>` links, bold headings and bullet characters.
+fn slack_mrkdwn(markdown: &str) -> String {
+    map_lines(markdown, |line| {
+        if let Some(text) = heading(line) {
+            return format!("*{}*", slack_inline(text).replace('*', ""));
+        }
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let trimmed = line.trim_start();
+        if let Some(item) = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "))
+        {
+            return format!("{indent}• {}", slack_inline(item));
+        }
+        if let Some(quote) = trimmed.strip_prefix("> ") {
+            return format!("{indent}> {}", slack_inline(quote));
+        }
+        slack_inline(line)
+    })
+}
+
+/// Applies `f` to each line outside fenced code blocks.
+fn map_lines(markdown: &str, f: impl Fn(&str) -> String) -> String {
+    let mut in_code = false;
+    let lines: Vec<String> = markdown
+        .lines()
+        .map(|line| {
+            if line.trim_start().starts_with("```") {
+                in_code = !in_code;
+                return line.to_string();
+            }
+            if in_code { line.to_string() } else { f(line) }
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// The text of a `#` heading line.
+fn heading(line: &str) -> Option<&str> {
+    let rest = line.trim_start().trim_start_matches('#');
+    let hashes = line.trim_start().len() - rest.len();
+    if !(1..=6).contains(&hashes) {
+        return None;
+    }
+    rest.strip_prefix(' ').map(str::trim)
+}
+
+fn slack_inline(line: &str) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if c == '`'
+            && let Some(end) = rest[1..].find('`')
+        {
+            out.push_str(&rest[..end + 2]);
+            rest = &rest[end + 2..];
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("**").or_else(|| rest.strip_prefix("__")) {
+            out.push('*');
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("~~") {
+            out.push('~');
+            rest = after;
+            continue;
+        }
+        if c == '*'
+            && rest[1..].starts_with(|next: char| !next.is_whitespace())
+            && let Some(end) = rest[1..].find('*')
+        {
+            out.push('_');
+            out.push_str(&slack_inline(&rest[1..end + 1]));
+            out.push('_');
+            rest = &rest[end + 2..];
+            continue;
+        }
+        if c == '['
+            && let Some((text, url, after)) = markdown_link(rest)
+        {
+            out.push_str(&format!("<{url}|{}>", slack_escape(text)));
+            rest = after;
+            continue;
+        }
+        out.push_str(&slack_escape(&c.to_string()));
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// The text, URL and remainder of a `[text](url)` link at the start of `s`.
+fn markdown_link(s: &str) -> Option<(&str, &str, &str)> {
+    let close = s.find("](")?;
+    let text = &s[1..close];
+    let target = &s[close + 2..];
+    let end = target.find(')')?;
+    Some((text, &target[..end], &target[end + 1..]))
+}
+
+/// Escapes the characters Slack treats as control sequences.
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// The `post_message` tool, listing `channels` so the model can pick one.
+pub(crate) fn create_post_message_tool(
+    channels: &HashMap<String, MessagingChannelConfig>,
+) -> ResponsesApiTool {
+    let mut names: Vec<&String> = channels.keys().collect();
+    names.sort_unstable();
+    let mut description = "Posts a markdown message to a Teams or Slack channel after the user \
+        approves it. A title or facts turn the message into a card. Configured channels:"
+        .to_string();
+    for name in names {
+        let channel = &channels[name];
+        let platform = match channel.platform {
+            MessagingPlatform::Teams => "Teams",
+            MessagingPlatform::Slack => "Slack",
+        };
+        description.push_str(&format!("\n- `{name}` ({platform})"));
+        if let Some(summary) = &channel.description {
+            description.push_str(&format!(": {summary}"));
+        }
+    }
+
+    let string = |description: &str| JsonSchema::String {
+        description: Some(description.to_string()),
+    };
+    let fact = JsonSchema::Object {
+        properties: BTreeMap::from([
+            ("title".to_string(), string("Label of the fact.")),
+            ("value".to_string(), string("Value of the fact.")),
+        ]),
+        required: Some(vec!["title".to_string(), "value".to_string()]),
+        additional_properties: Some(false),
+    };
+    let properties = BTreeMap::from([
+        (
+            "channel".to_string(),
+            string("Name of a configured channel."),
+        ),
+        (
+            "text".to_string(),
+            string("Body of the message, in markdown."),
+        ),
+        (
+            "title".to_string(),
+            string("Title shown at the top of the card."),
+        ),
+        (
+            "facts".to_string(),
+            JsonSchema::Array {
+                items: Box::new(fact),
+                description: Some(
+                    "Label and value pairs shown as a table, e.g. severity or build number."
+                        .to_string(),
+                ),
+            },
+        ),
+    ]);
+    ResponsesApiTool {
+        name: POST_MESSAGE_TOOL_NAME.to_string(),
+        description,
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["channel".to_string(), "text".to_string()]),
+            additional_properties: Some(false),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::body_json;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+
+    fn messaging(channels: Vec<(&str, MessagingPlatform, String)>) -> Messaging {
+        Messaging::new(MessagingConfig {
+            channels: channels
+                .into_iter()
+                .map(|(name, platform, webhook_url)| {
+                    (
+                        name.to_string(),
+                        MessagingChannelConfig {
+                            platform,
+                            webhook_url: Some(webhook_url),
+                            webhook_url_env_var: None,
+                            slack_channel: None,
+                            slack_token_env_var: "SLACK_BOT_TOKEN".to_string(),
+                            description: None,
+                        },
+                    )
+                })
+                .collect(),
+            slack_api_url: "https://slack.com/api".to_string(),
+            max_message_chars: 20_000,
+        })
+    }
+
+    #[tokio::test]
+    async fn posts_cards_to_teams() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/workflows/oncall"))
+            .and(body_json(json!({
+                "type": "message",
+                "attachments": [{
+                    "contentType": "application/vnd.microsoft.card.adaptive",
+                    "content": {
+                        "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                        "type": "AdaptiveCard",
+                        "version": "1.4",
+                        "body": [
+                            {
+                                "type": "TextBlock",
+                                "text": "Checkout latency resolved",
+                                "size": "Large",
+                                "weight": "Bolder",
+                                "wrap": true,
+                            },
+                            {
+                                "type": "TextBlock",
+                                "text": "**Cause**\nA slow query after the **2.4** deploy.",
+                                "wrap": true,
+                            },
+                            {
+                                "type": "FactSet",
+                                "facts": [{ "title": "Severity", "value": "2" }],
+                            },
+                        ],
+                        "msteams": { "width": "Full" },
+                    },
+                }],
+            })))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let messaging = messaging(vec![(
+            "oncall",
+            MessagingPlatform::Teams,
+            format!("{}/workflows/oncall", server.uri()),
+        )]);
+
+        let message = messaging
+            .prepare(
+                &json!({
+                    "channel": "oncall",
+                    "title": "Checkout latency resolved",
+                    "text": "## Cause\nA slow query after the **2.4** deploy.",
+                    "facts": [{ "title": "Severity", "value": "2" }],
+                })
+                .to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            messaging.post(&message).await,
+            Ok("Posted to `oncall`.".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_rejected_slack_messages() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/services/T0/B0/x"))
+            .and(body_json(
+                json!({ "text": "Release *1.2* is out: <https://example.com/notes|notes>" }),
+            ))
+            .respond_with(ResponseTemplate::new(404).set_body_string("no_service"))
+            .mount(&server)
+            .await;
+        let messaging = messaging(vec![(
+            "releases",
+            MessagingPlatform::Slack,
+            format!("{}/services/T0/B0/x", server.uri()),
+        )]);
+
+        let message = messaging
+            .prepare(
+                r#"{"channel": "releases", "text": "Release **1.2** is out: [notes](https://example.com/notes)"}"#,
+            )
+            .unwrap();
+
+        assert_eq!(
+            messaging.post(&message).await,
+            Err("`releases` rejected the message (404 Not Found): no_service".to_string())
+        );
+        assert_eq!(
+            messaging
+                .prepare(r#"{"channel": "general", "text": "hi"}"#)
+                .err(),
+            Some("unknown channel `general`; configured channels: releases".to_string())
+        );
+    }
+
+    #[test]
+    fn rewrites_markdown_as_slack_mrkdwn() {
+        let markdown = "# Summary\n\
+                        - **Root cause**: *stale* cache & <retries>\n\
+                        - ~~rollback~~ see `a*b*c`\n\
+                        ```\n**kept**\n```\n\
+                        2 * 3";
+        assert_eq!(
+            slack_mrkdwn(markdown),
+            "*Summary*\n\
+             • *Root cause*: _stale_ cache &amp; &lt;retries&gt;\n\
+             • ~rollback~ see `a*b*c`\n\
+             ```\n**kept**\n```\n\
+             2 * 3"
+        );
+    }
+
+    #[test]
+    fn splits_long_text_at_line_breaks() {
+        assert_eq!(
+            split_text("aaaa\nbbbb\ncc", 6),
+            vec!["aaaa\n".to_string(), "bbbb\n".to_string(), "cc".to_string()]
+        );
+        assert_eq!(
+            split_text("abcdefgh", 3),
+            vec!["abc".to_string(), "def".to_string(), "gh".to_string()]
+        );
+    }
+}
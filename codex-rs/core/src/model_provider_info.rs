@@ -21,6 +21,9 @@ const DEFAULT_REQUEST_MAX_RETRIES: u64 = 4;
 const MAX_STREAM_MAX_RETRIES: u64 = 100;
 /// Hard cap for user-configured `request_max_retries`.
 const MAX_REQUEST_MAX_RETRIES: u64 = 100;
+const DEFAULT_AZURE_OPENAI_API_VERSION: &str = "2025-04-01-preview";
+/// Entra ID scope that grants access to Azure OpenAI.
+pub(crate) const AZURE_OPENAI_SCOPE: &str = "https://cognitiveservices.azure.com/.default";
 
 /// Wire protocol that the provider speaks. Most third-party services only
 /// implement the classic OpenAI Chat Completions JSON schema, whereas OpenAI
@@ -37,6 +40,26 @@ pub enum WireApi {
     /// Regular Chat Completions compatible with `/v1/chat/completions`.
     #[default]
     Chat,
+
+    /// Anthropic Messages API at `/v1/messages`.
+    Anthropic,
+
+    /// Google Gemini `streamGenerateContent` API.
+    Gemini,
+
+    /// Ollama's native `/api/chat` API.
+    Ollama,
+}
+
+impl WireApi {
+    /// Whether requests can carry a JSON schema the response must follow.
+    /// For the other APIs the schema is described in the instructions.
+    pub(crate) fn supports_response_format(self) -> bool {
+        match self {
+            WireApi::Responses | WireApi::Chat | WireApi::Ollama => true,
+            WireApi::Anthropic | WireApi::Gemini => false,
+        }
+    }
 }
 
 /// Serializable representation of a provider definition.
@@ -83,6 +106,41 @@ pub struct ModelProviderInfo {
     /// Whether this provider requires some form of standard authentication (API key, ChatGPT token).
     #[serde(default)]
     pub requires_openai_auth: bool,
+
+    /// Azure OpenAI settings. When present, requests are routed to the
+    /// deployment with the `api-version` Azure expects and authenticated
+    /// with an `api-key` header or an Entra ID token.
+    pub azure: Option<AzureOpenAiInfo>,
+}
+
+/// Azure OpenAI specific provider settings.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct AzureOpenAiInfo {
+    /// Name of the Azure OpenAI resource. Used to derive the base URL
+    /// (`https://<resource>.openai.azure.com/openai`) when `base_url` is not
+    /// set.
+    pub resource: Option<String>,
+
+    /// Deployment that requests are routed to. Defaults to the model name.
+    pub deployment: Option<String>,
+
+    /// Value of the `api-version` query parameter.
+    pub api_version: Option<String>,
+
+    /// How requests are authenticated.
+    #[serde(default)]
+    pub auth: AzureOpenAiAuth,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AzureOpenAiAuth {
+    /// Key from `env_key`, sent in the `api-key` header.
+    #[default]
+    ApiKey,
+
+    /// Entra ID token obtained through the shared `azure_auth` login.
+    Entra,
 }
 
 impl ModelProviderInfo {
@@ -98,6 +156,33 @@ impl ModelProviderInfo {
         &'a self,
         client: &'a reqwest::Client,
         auth: &Option<CodexAuth>,
+        model: &str,
+    ) -> crate::error::Result<reqwest::RequestBuilder> {
+        self.create_request_builder_for(client, auth, |auth| self.get_full_url(auth, model))
+            .await
+    }
+
+    /// Like [`ModelProviderInfo::create_request_builder`], for one of the
+    /// provider's other OpenAI-compatible endpoints, see
+    /// [`ModelProviderInfo::get_endpoint_url`].
+    pub(crate) async fn create_endpoint_request_builder<'a>(
+        &'a self,
+        client: &'a reqwest::Client,
+        auth: &Option<CodexAuth>,
+        model: &str,
+        endpoint: &str,
+    ) -> crate::error::Result<reqwest::RequestBuilder> {
+        self.create_request_builder_for(client, auth, |auth| {
+            self.get_endpoint_url(auth, model, endpoint)
+        })
+        .await
+    }
+
+    async fn create_request_builder_for<'a>(
+        &'a self,
+        client: &'a reqwest::Client,
+        auth: &Option<CodexAuth>,
+        url_for: impl FnOnce(&Option<CodexAuth>) -> String,
     ) -> crate::error::Result<reqwest::RequestBuilder> {
         let effective_auth = match self.api_key() {
             Ok(Some(key)) => Some(CodexAuth::from_api_key(&key)),
@@ -111,54 +196,194 @@ impl ModelProviderInfo {
             }
         };
 
-        let url = self.get_full_url(&effective_auth);
+        let url = url_for(&effective_auth);
 
         let mut builder = client.post(url);
 
         if let Some(auth) = effective_auth.as_ref() {
-            builder = builder.bearer_auth(auth.get_token().await?);
+            let token = auth.get_token().await?;
+            builder = match self.wire_api {
+                WireApi::Responses | WireApi::Chat
+                    if self
+                        .azure
+                        .as_ref()
+                        .is_some_and(|azure| azure.auth == AzureOpenAiAuth::ApiKey) =>
+                {
+                    builder.header("api-key", token)
+                }
+                WireApi::Responses | WireApi::Chat | WireApi::Ollama => builder.bearer_auth(token),
+                WireApi::Anthropic => builder.header("x-api-key", token),
+                WireApi::Gemini => builder.header("x-goog-api-key", token),
+            };
+        }
+
+        if self.wire_api == WireApi::Anthropic
+            && !self
+                .http_headers
+                .as_ref()
+                .is_some_and(|h| h.contains_key(ANTHROPIC_VERSION_HEADER))
+        {
+            builder = builder.header(ANTHROPIC_VERSION_HEADER, DEFAULT_ANTHROPIC_VERSION);
         }
 
         Ok(self.apply_http_headers(builder))
     }
 
     fn get_query_string(&self) -> String {
-        self.query_params
-            .as_ref()
-            .map_or_else(String::new, |params| {
-                let full_params = params
-                    .iter()
-                    .map(|(k, v)| format!("{k}={v}"))
-                    .collect::<Vec<_>>()
-                    .join("&");
-                format!("?{full_params}")
+        // Gemini only streams SSE when asked to explicitly.
+        let sse_param = (self.wire_api == WireApi::Gemini).then(|| "alt=sse".to_string());
+        // An `api-version` in `query_params` takes precedence over the Azure
+        // setting.
+        let api_version_param = self
+            .azure_api_version()
+            .filter(|_| {
+                !self
+                    .query_params
+                    .as_ref()
+                    .is_some_and(|params| params.contains_key("api-version"))
             })
+            .map(|version| format!("api-version={version}"));
+        let params = sse_param
+            .into_iter()
+            .chain(api_version_param)
+            .chain(
+                self.query_params
+                    .iter()
+                    .flatten()
+                    .map(|(k, v)| format!("{k}={v}")),
+            )
+            .collect::<Vec<_>>();
+        if params.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", params.join("&"))
+        }
     }
 
-    pub(crate) fn get_full_url(&self, auth: &Option<CodexAuth>) -> String {
-        let default_base_url = if matches!(
-            auth,
-            Some(CodexAuth {
-                mode: AuthMode::ChatGPT,
-                ..
-            })
-        ) {
-            "https://chatgpt.com/backend-api/codex"
-        } else {
-            "https://api.openai.com/v1"
+    pub fn get_full_url(&self, auth: &Option<CodexAuth>, model: &str) -> String {
+        let default_base_url = match self.wire_api {
+            WireApi::Anthropic => "https://api.anthropic.com/v1",
+            WireApi::Gemini => "https://generativelanguage.googleapis.com/v1beta",
+            WireApi::Ollama => "http://localhost:11434",
+            WireApi::Responses | WireApi::Chat
+                if matches!(
+                    auth,
+                    Some(CodexAuth {
+                        mode: AuthMode::ChatGPT,
+                        ..
+                    })
+                ) =>
+            {
+                "https://chatgpt.com/backend-api/codex"
+            }
+            WireApi::Responses | WireApi::Chat => "https://api.openai.com/v1",
         };
         let query_string = self.get_query_string();
-        let base_url = self
-            .base_url
-            .clone()
-            .unwrap_or(default_base_url.to_string());
+        let base_url = self.base_url_or(default_base_url);
 
         match self.wire_api {
             WireApi::Responses => format!("{base_url}/responses{query_string}"),
+            // Azure routes Chat Completions by deployment in the path; the
+            // Responses API takes the deployment as the request's `model`.
+            WireApi::Chat if self.azure.is_some() => {
+                let deployment = self.request_model(model);
+                format!("{base_url}/deployments/{deployment}/chat/completions{query_string}")
+            }
             WireApi::Chat => format!("{base_url}/chat/completions{query_string}"),
+            WireApi::Anthropic => format!("{base_url}/messages{query_string}"),
+            WireApi::Gemini => {
+                format!("{base_url}/models/{model}:streamGenerateContent{query_string}")
+            }
+            // Accept the OpenAI-compatible `/v1` root the `oss` provider uses.
+            WireApi::Ollama => {
+                let host_root = base_url.trim_end_matches('/').trim_end_matches("/v1");
+                format!("{host_root}/api/chat{query_string}")
+            }
         }
     }
 
+    /// URL of an OpenAI-compatible endpoint other than the one used for
+    /// responses, such as `embeddings` or `rerank`. Azure routes it by
+    /// deployment, with `model` as the deployment name, and Ollama serves it
+    /// under `/v1`.
+    pub(crate) fn get_endpoint_url(
+        &self,
+        auth: &Option<CodexAuth>,
+        model: &str,
+        endpoint: &str,
+    ) -> String {
+        let query_string = self.get_query_string();
+        match self.wire_api {
+            WireApi::Ollama => {
+                let base_url = self.base_url_or("http://localhost:11434");
+                let host_root = base_url.trim_end_matches('/').trim_end_matches("/v1");
+                format!("{host_root}/v1/{endpoint}{query_string}")
+            }
+            _ if self.azure.is_some() => {
+                let base_url = self.base_url_or("https://api.openai.com/v1");
+                // The provider's `deployment` serves the chat model; other
+                // endpoints name their own deployment as the model.
+                format!("{base_url}/deployments/{model}/{endpoint}{query_string}")
+            }
+            _ => {
+                let default_base_url = match auth {
+                    Some(CodexAuth {
+                        mode: AuthMode::ChatGPT,
+                        ..
+                    }) => "https://chatgpt.com/backend-api/codex",
+                    _ => "https://api.openai.com/v1",
+                };
+                let base_url = self.base_url_or(default_base_url);
+                format!("{base_url}/{endpoint}{query_string}")
+            }
+        }
+    }
+
+    /// The configured base URL, the one derived from the Azure resource, or
+    /// `default_base_url`.
+    fn base_url_or(&self, default_base_url: &str) -> String {
+        self.base_url
+            .clone()
+            .or_else(|| {
+                self.azure
+                    .as_ref()
+                    .and_then(|azure| azure.resource.as_ref())
+                    .map(|resource| format!("https://{resource}.openai.azure.com/openai"))
+            })
+            .unwrap_or(default_base_url.to_string())
+    }
+
+    /// Model name to send to the provider: the Azure deployment when one is
+    /// configured, otherwise `model` unchanged.
+    pub(crate) fn request_model<'a>(&'a self, model: &'a str) -> &'a str {
+        self.azure
+            .as_ref()
+            .and_then(|azure| azure.deployment.as_deref())
+            .unwrap_or(model)
+    }
+
+    /// Whether requests must carry an Entra ID token from the shared Azure
+    /// login rather than a key or Codex login.
+    pub(crate) fn uses_azure_entra_auth(&self) -> bool {
+        self.azure
+            .as_ref()
+            .is_some_and(|azure| azure.auth == AzureOpenAiAuth::Entra)
+    }
+
+    fn azure_api_version(&self) -> Option<&str> {
+        // Anthropic and Gemini deployments on Azure are addressed through
+        // `base_url`/`query_params` as usual.
+        if !matches!(self.wire_api, WireApi::Responses | WireApi::Chat) {
+            return None;
+        }
+        self.azure.as_ref().map(|azure| {
+            azure
+                .api_version
+                .as_deref()
+                .unwrap_or(DEFAULT_AZURE_OPENAI_API_VERSION)
+        })
+    }
+
     /// Apply provider-specific HTTP headers (both static and environment-based)
     /// onto an existing `reqwest::RequestBuilder` and return the updated
     /// builder.
@@ -229,6 +454,9 @@ impl ModelProviderInfo {
     }
 }
 
+const ANTHROPIC_VERSION_HEADER: &str = "anthropic-version";
+const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+
 const DEFAULT_OLLAMA_PORT: u32 = 11434;
 
 pub const BUILT_IN_OSS_MODEL_PROVIDER_ID: &str = "oss";
@@ -279,6 +507,7 @@ pub fn built_in_model_providers() -> HashMap<String, ModelProviderInfo> {
                 stream_max_retries: None,
                 stream_idle_timeout_ms: None,
                 requires_openai_auth: true,
+                azure: None,
             },
         ),
         (BUILT_IN_OSS_MODEL_PROVIDER_ID, create_oss_provider()),
@@ -323,6 +552,7 @@ pub fn create_oss_provider_with_base_url(base_url: &str) -> ModelProviderInfo {
         stream_max_retries: None,
         stream_idle_timeout_ms: None,
         requires_openai_auth: false,
+        azure: None,
     }
 }
 
@@ -350,6 +580,7 @@ base_url = "http://localhost:11434/v1"
             stream_max_retries: None,
             stream_idle_timeout_ms: None,
             requires_openai_auth: false,
+            azure: None,
         };
 
         let provider: ModelProviderInfo = toml::from_str(azure_provider_toml).unwrap();
@@ -379,6 +610,7 @@ query_params = { api-version = "2025-04-01-preview" }
             stream_max_retries: None,
             stream_idle_timeout_ms: None,
             requires_openai_auth: false,
+            azure: None,
         };
 
         let provider: ModelProviderInfo = toml::from_str(azure_provider_toml).unwrap();
@@ -411,9 +643,101 @@ env_http_headers = { "X-Example-Env-Header" = "EXAMPLE_ENV_VAR" }
             stream_max_retries: None,
             stream_idle_timeout_ms: None,
             requires_openai_auth: false,
+            azure: None,
         };
 
         let provider: ModelProviderInfo = toml::from_str(azure_provider_toml).unwrap();
         assert_eq!(expected_provider, provider);
     }
+
+    #[test]
+    fn test_deserialize_anthropic_and_gemini_providers() {
+        let anthropic: ModelProviderInfo = toml::from_str(
+            r#"
+name = "Anthropic"
+env_key = "ANTHROPIC_API_KEY"
+wire_api = "anthropic"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(anthropic.wire_api, WireApi::Anthropic);
+        assert_eq!(
+            anthropic.get_full_url(&None, "claude-sonnet-4-0"),
+            "https://api.anthropic.com/v1/messages"
+        );
+
+        let gemini: ModelProviderInfo = toml::from_str(
+            r#"
+name = "Gemini"
+env_key = "GEMINI_API_KEY"
+wire_api = "gemini"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(gemini.wire_api, WireApi::Gemini);
+        assert_eq!(
+            gemini.get_full_url(&None, "gemini-2.5-pro"),
+            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-pro:streamGenerateContent?alt=sse"
+        );
+    }
+
+    #[test]
+    fn test_ollama_native_url() {
+        let mut provider = create_oss_provider_with_base_url("http://localhost:11434/v1");
+        provider.wire_api = WireApi::Ollama;
+        assert_eq!(
+            provider.get_full_url(&None, "llama3.1"),
+            "http://localhost:11434/api/chat"
+        );
+
+        provider.base_url = None;
+        assert_eq!(
+            provider.get_full_url(&None, "llama3.1"),
+            "http://localhost:11434/api/chat"
+        );
+    }
+
+    #[test]
+    fn test_azure_openai_routing() {
+        let provider: ModelProviderInfo = toml::from_str(
+            r#"
+name = "Azure OpenAI"
+env_key = "AZURE_OPENAI_API_KEY"
+azure = { resource = "contoso", deployment = "prod-gpt-4o" }
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            provider.azure,
+            Some(AzureOpenAiInfo {
+                resource: Some("contoso".into()),
+                deployment: Some("prod-gpt-4o".into()),
+                api_version: None,
+                auth: AzureOpenAiAuth::ApiKey,
+            })
+        );
+        assert!(!provider.uses_azure_entra_auth());
+        assert_eq!(provider.request_model("gpt-4o"), "prod-gpt-4o");
+        assert_eq!(
+            provider.get_full_url(&None, "gpt-4o"),
+            "https://contoso.openai.azure.com/openai/deployments/prod-gpt-4o/chat/completions?api-version=2025-04-01-preview"
+        );
+
+        let responses: ModelProviderInfo = toml::from_str(
+            r#"
+name = "Azure OpenAI"
+base_url = "https://contoso.openai.azure.com/openai"
+wire_api = "responses"
+query_params = { api-version = "preview" }
+azure = { api_version = "2025-03-01-preview", auth = "entra" }
+        "#,
+        )
+        .unwrap();
+        assert!(responses.uses_azure_entra_auth());
+        assert_eq!(responses.request_model("gpt-5"), "gpt-5");
+        assert_eq!(
+            responses.get_full_url(&None, "gpt-5"),
+            "https://contoso.openai.azure.com/openai/responses?api-version=preview"
+        );
+    }
 }
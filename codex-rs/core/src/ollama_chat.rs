@@ -0,0 +1,589 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bytes::Bytes;
+use bytes::BytesMut;
+use futures::Stream;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use reqwest::StatusCode;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tracing::debug;
+use tracing::trace;
+use uuid::Uuid;
+
+use crate::ModelProviderInfo;
+use crate::chat_completions::post_sse_with_retries;
+use crate::client_common::Prompt;
+use crate::client_common::ResponseEvent;
+use crate::client_common::ResponseStream;
+use crate::error::CodexErr;
+use crate::error::Result;
+use crate::model_family::ModelFamily;
+use crate::openai_tools::create_tools_json_for_chat_completions_api;
+use crate::protocol::TokenUsage;
+use codex_protocol::models::ContentItem;
+use codex_protocol::models::ReasoningItemContent;
+use codex_protocol::models::ResponseItem;
+
+const TOOL_EMULATION_TEMPLATE: &str = include_str!("ollama_tool_emulation.md");
+const TOOL_CALL_OPEN: &str = "<tool_call>";
+const TOOL_CALL_CLOSE: &str = "</tool_call>";
+
+/// Models that rejected native `tools` during this process. Requests for
+/// them go straight to prompt-based emulation instead of failing first.
+static MODELS_WITHOUT_TOOLS: LazyLock<Mutex<HashSet<String>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Implementation for Ollama's native `/api/chat` API. When the model does
+/// not support function calling, tools are described in the system prompt
+/// and calls are parsed back out of the reply.
+pub(crate) async fn stream_ollama_chat(
+    prompt: &Prompt,
+    model_family: &ModelFamily,
+    max_output_tokens: Option<u64>,
+    context_window: Option<u64>,
+    client: &reqwest::Client,
+    provider: &ModelProviderInfo,
+) -> Result<ResponseStream> {
+    let model = model_family.slug.as_str();
+    let tools_json = create_tools_json_for_chat_completions_api(&prompt.tools)?;
+
+    let mut options = serde_json::Map::new();
+    if let Some(max_output_tokens) = max_output_tokens {
+        options.insert("num_predict".to_string(), json!(max_output_tokens));
+    }
+    // Ollama's default context is far smaller than what Codex sends.
+    if let Some(context_window) = context_window {
+        options.insert("num_ctx".to_string(), json!(context_window));
+    }
+
+    let mut emulate_tools = !tools_json.is_empty() && model_lacks_tools(model);
+    let resp = loop {
+        let mut payload = json!({
+            "model": model,
+            "messages": build_messages(prompt, model_family, &tools_json, emulate_tools),
+            "stream": true,
+            "tools": if emulate_tools { Vec::new() } else { tools_json.clone() },
+            "options": options,
+        });
+        if let Some(format) = &prompt.response_format {
+            payload["format"] = format.schema.clone();
+        }
+        match post_sse_with_retries(client, provider, &None, model, &payload).await {
+            Ok(resp) => break resp,
+            Err(CodexErr::UnexpectedStatus(StatusCode::BAD_REQUEST, body))
+                if !emulate_tools && body.contains("does not support tools") =>
+            {
+                debug!("{model} does not support native tools; emulating them in the prompt");
+                if let Ok(mut models) = MODELS_WITHOUT_TOOLS.lock() {
+                    models.insert(model.to_string());
+                }
+                emulate_tools = true;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    let (tx_event, rx_event) = mpsc::channel::<Result<ResponseEvent>>(1600);
+    let stream = resp.bytes_stream().map_err(CodexErr::Reqwest);
+    tokio::spawn(process_ollama_stream(
+        stream,
+        tx_event,
+        provider.stream_idle_timeout(),
+        emulate_tools,
+    ));
+    Ok(ResponseStream { rx_event })
+}
+
+fn model_lacks_tools(model: &str) -> bool {
+    MODELS_WITHOUT_TOOLS
+        .lock()
+        .is_ok_and(|models| models.contains(model))
+}
+
+fn build_messages(
+    prompt: &Prompt,
+    model_family: &ModelFamily,
+    tools_json: &[serde_json::Value],
+    emulate_tools: bool,
+) -> Vec<serde_json::Value> {
+    let mut system = prompt.get_full_instructions(model_family).into_owned();
+    if emulate_tools {
+        system.push('\n');
+        system.push_str(&TOOL_EMULATION_TEMPLATE.replace("{tools}", &describe_tools(tools_json)));
+    }
+
+    let mut messages = vec![json!({"role": "system", "content": system})];
+    let mut call_names = HashMap::<&str, &str>::new();
+    let input = prompt.get_formatted_input();
+
+    for item in &input {
+        match item {
+            ResponseItem::Message { role, content, .. } => {
+                let mut text = String::new();
+                let mut images = Vec::new();
+                for c in content {
+                    match c {
+                        ContentItem::InputText { text: t }
+                        | ContentItem::OutputText { text: t } => text.push_str(t),
+                        ContentItem::InputImage { image_url } => {
+                            // Ollama takes bare base64 image data.
+                            if let Some((_, data)) = image_url
+                                .strip_prefix("data:")
+                                .and_then(|rest| rest.split_once(";base64,"))
+                            {
+                                images.push(data);
+                            }
+                        }
+                    }
+                }
+                let mut message = json!({"role": role, "content": text});
+                if !images.is_empty() {
+                    message["images"] = json!(images);
+                }
+                messages.push(message);
+            }
+            ResponseItem::FunctionCall {
+                name,
+                arguments,
+                call_id,
+                ..
+            } => {
+                call_names.insert(call_id, name);
+                let arguments = serde_json::from_str::<serde_json::Value>(arguments)
+                    .unwrap_or_else(|_| json!({}));
+                if emulate_tools {
+                    let call = json!({"name": name, "arguments": arguments});
+                    messages.push(json!({
+                        "role": "assistant",
+                        "content": format!("{TOOL_CALL_OPEN}\n{call}\n{TOOL_CALL_CLOSE}"),
+                    }));
+                } else {
+                    messages.push(json!({
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [{
+                            "function": {"name": name, "arguments": arguments},
+                        }],
+                    }));
+                }
+            }
+            ResponseItem::FunctionCallOutput { call_id, output } => {
+                let name = call_names
+                    .get(call_id.as_str())
+                    .copied()
+                    .unwrap_or_default();
+                if emulate_tools {
+                    messages.push(json!({
+                        "role": "user",
+                        "content": format!(
+                            "<tool_result name=\"{name}\">\n{}\n</tool_result>",
+                            output.content
+                        ),
+                    }));
+                } else {
+                    messages.push(json!({
+                        "role": "tool",
+                        "tool_name": name,
+                        "content": output.content,
+                    }));
+                }
+            }
+            ResponseItem::Reasoning { .. }
+            | ResponseItem::LocalShellCall { .. }
+            | ResponseItem::CustomToolCall { .. }
+            | ResponseItem::CustomToolCallOutput { .. }
+            | ResponseItem::WebSearchCall { .. }
+            | ResponseItem::Other => {
+                // Omit these items from the conversation history.
+                continue;
+            }
+        }
+    }
+
+    messages
+}
+
+/// Render Chat Completions style tool definitions for the emulation prompt.
+fn describe_tools(tools_json: &[serde_json::Value]) -> String {
+    tools_json
+        .iter()
+        .map(|tool| {
+            let function = &tool["function"];
+            format!(
+                "## {}\n{}\nParameters (JSON Schema): {}\n",
+                function["name"].as_str().unwrap_or_default(),
+                function["description"].as_str().unwrap_or_default(),
+                function["parameters"],
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Split an emulated reply into the text before the first `<tool_call>` and
+/// the calls it contains. Blocks that do not parse are kept as text.
+fn parse_emulated_tool_calls(text: &str) -> (String, Vec<(String, String)>) {
+    let Some(start) = text.find(TOOL_CALL_OPEN) else {
+        return (text.to_string(), Vec::new());
+    };
+
+    let mut calls = Vec::new();
+    let mut rest = &text[start..];
+    while let Some(open) = rest.find(TOOL_CALL_OPEN) {
+        let body_start = open + TOOL_CALL_OPEN.len();
+        let (body, next) = match rest[body_start..].find(TOOL_CALL_CLOSE) {
+            Some(close) => (
+                &rest[body_start..body_start + close],
+                &rest[body_start + close + TOOL_CALL_CLOSE.len()..],
+            ),
+            None => (&rest[body_start..], ""),
+        };
+        let body = body
+            .trim()
+            .trim_start_matches("```json")
+            .trim_matches('`')
+            .trim();
+        if let Ok(call) = serde_json::from_str::<serde_json::Value>(body)
+            && let Some(name) = call["name"].as_str()
+        {
+            let arguments = match &call["arguments"] {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Null => "{}".to_string(),
+                other => other.to_string(),
+            };
+            calls.push((name.to_string(), arguments));
+        }
+        rest = next;
+    }
+
+    if calls.is_empty() {
+        return (text.to_string(), calls);
+    }
+    (text[..start].trim_end().to_string(), calls)
+}
+
+/// Length of the longest suffix of `text` that could be the beginning of a
+/// `<tool_call>` marker, i.e. text that must be held back from streaming.
+fn partial_marker_len(text: &str) -> usize {
+    (1..TOOL_CALL_OPEN.len())
+        .rev()
+        .find(|&n| text.ends_with(&TOOL_CALL_OPEN[..n]))
+        .unwrap_or(0)
+}
+
+#[derive(Default)]
+struct OllamaStreamState {
+    emulate_tools: bool,
+    assistant_text: String,
+    reasoning_text: String,
+    /// Bytes of `assistant_text` already forwarded as deltas.
+    streamed_len: usize,
+    created: bool,
+}
+
+impl OllamaStreamState {
+    /// Handle one NDJSON chunk. Returns `true` once the final chunk has been
+    /// processed.
+    async fn handle_chunk(
+        &mut self,
+        chunk: serde_json::Value,
+        tx_event: &mpsc::Sender<Result<ResponseEvent>>,
+    ) -> bool {
+        trace!("ollama received chunk: {chunk:?}");
+
+        if let Some(error) = chunk["error"].as_str() {
+            let _ = tx_event
+                .send(Err(CodexErr::Stream(error.to_string(), None)))
+                .await;
+            return true;
+        }
+
+        if !self.created {
+            self.created = true;
+            let _ = tx_event.send(Ok(ResponseEvent::Created)).await;
+        }
+
+        let message = &chunk["message"];
+        if let Some(thinking) = message["thinking"].as_str()
+            && !thinking.is_empty()
+        {
+            self.reasoning_text.push_str(thinking);
+            let _ = tx_event
+                .send(Ok(ResponseEvent::ReasoningContentDelta(
+                    thinking.to_string(),
+                )))
+                .await;
+        }
+        if let Some(content) = message["content"].as_str()
+            && !content.is_empty()
+        {
+            self.assistant_text.push_str(content);
+            self.stream_text(tx_event).await;
+        }
+        for call in message["tool_calls"].as_array().into_iter().flatten() {
+            let function = &call["function"];
+            let item = ResponseItem::FunctionCall {
+                id: None,
+                name: function["name"].as_str().unwrap_or_default().to_string(),
+                arguments: function.get("arguments").unwrap_or(&json!({})).to_string(),
+                // Ollama does not assign ids to tool calls.
+                call_id: format!("call_{}", Uuid::new_v4().simple()),
+            };
+            let _ = tx_event.send(Ok(ResponseEvent::OutputItemDone(item))).await;
+        }
+
+        if chunk["done"].as_bool() != Some(true) {
+            return false;
+        }
+
+        self.finish(&chunk, tx_event).await;
+        true
+    }
+
+    /// Forward newly received text. In emulation mode anything from a
+    /// `<tool_call>` marker onwards is held back.
+    async fn stream_text(&mut self, tx_event: &mpsc::Sender<Result<ResponseEvent>>) {
+        let end = if !self.emulate_tools {
+            self.assistant_text.len()
+        } else if let Some(start) = self.assistant_text.find(TOOL_CALL_OPEN) {
+            start
+        } else {
+            self.assistant_text.len() - partial_marker_len(&self.assistant_text)
+        };
+        if end > self.streamed_len {
+            let delta = self.assistant_text[self.streamed_len..end].to_string();
+            self.streamed_len = end;
+            let _ = tx_event
+                .send(Ok(ResponseEvent::OutputTextDelta(delta)))
+                .await;
+        }
+    }
+
+    async fn finish(
+        &mut self,
+        chunk: &serde_json::Value,
+        tx_event: &mpsc::Sender<Result<ResponseEvent>>,
+    ) {
+        if !self.reasoning_text.is_empty() {
+            let item = ResponseItem::Reasoning {
+                id: String::new(),
+                summary: Vec::new(),
+                content: Some(vec![ReasoningItemContent::ReasoningText {
+                    text: std::mem::take(&mut self.reasoning_text),
+                }]),
+                encrypted_content: None,
+            };
+            let _ = tx_event.send(Ok(ResponseEvent::OutputItemDone(item))).await;
+        }
+
+        let (text, calls) = if self.emulate_tools {
+            parse_emulated_tool_calls(&self.assistant_text)
+        } else {
+            (std::mem::take(&mut self.assistant_text), Vec::new())
+        };
+        if !text.is_empty() {
+            let item = ResponseItem::Message {
+                id: None,
+                role: "assistant".to_string(),
+                content: vec![ContentItem::OutputText { text }],
+            };
+            let _ = tx_event.send(Ok(ResponseEvent::OutputItemDone(item))).await;
+        }
+        for (name, arguments) in calls {
+            let item = ResponseItem::FunctionCall {
+                id: None,
+                name,
+                arguments,
+                call_id: format!("call_{}", Uuid::new_v4().simple()),
+            };
+            let _ = tx_event.send(Ok(ResponseEvent::OutputItemDone(item))).await;
+        }
+
+        let input_tokens = chunk["prompt_eval_count"].as_u64().unwrap_or_default();
+        let output_tokens = chunk["eval_count"].as_u64().unwrap_or_default();
+        let _ = tx_event
+            .send(Ok(ResponseEvent::Completed {
+                response_id: String::new(),
+                token_usage: Some(TokenUsage {
+                    input_tokens,
+                    cached_input_tokens: None,
+                    output_tokens,
+                    reasoning_output_tokens: None,
+                    total_tokens: input_tokens + output_tokens,
+                }),
+            }))
+            .await;
+    }
+}
+
+/// Processor for Ollama's newline-delimited JSON stream, mapped onto
+/// [`ResponseEvent`] like the SSE based wire APIs.
+async fn process_ollama_stream<S>(
+    mut stream: S,
+    tx_event: mpsc::Sender<Result<ResponseEvent>>,
+    idle_timeout: Duration,
+    emulate_tools: bool,
+) where
+    S: Stream<Item = Result<Bytes>> + Unpin,
+{
+    let mut state = OllamaStreamState {
+        emulate_tools,
+        ..Default::default()
+    };
+    let mut buffer = BytesMut::new();
+
+    loop {
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line = buffer.split_to(pos + 1);
+            let Ok(chunk) = serde_json::from_slice::<serde_json::Value>(&line) else {
+                continue;
+            };
+            if state.handle_chunk(chunk, &tx_event).await {
+                return;
+            }
+        }
+
+        match timeout(idle_timeout, stream.next()).await {
+            Ok(Some(Ok(bytes))) => buffer.extend_from_slice(&bytes),
+            Ok(Some(Err(e))) => {
+                let _ = tx_event.send(Err(e)).await;
+                return;
+            }
+            Ok(None) => {
+                // The final line may lack a trailing newline.
+                if let Ok(chunk) = serde_json::from_slice::<serde_json::Value>(&buffer)
+                    && state.handle_chunk(chunk, &tx_event).await
+                {
+                    return;
+                }
+                let _ = tx_event
+                    .send(Err(CodexErr::Stream(
+                        "stream closed before the final chunk".into(),
+                        None,
+                    )))
+                    .await;
+                return;
+            }
+            Err(_) => {
+                let _ = tx_event
+                    .send(Err(CodexErr::Stream(
+                        "idle timeout waiting for response chunk".into(),
+                        None,
+                    )))
+                    .await;
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    async fn collect_events(lines: &[&str], emulate_tools: bool) -> Vec<ResponseEvent> {
+        let (tx, mut rx) = mpsc::channel(32);
+        // Split mid-line to exercise buffering across network chunks.
+        let body = lines.join("\n");
+        let (a, b) = body.split_at(body.len() / 2);
+        let chunks = vec![
+            Ok::<_, CodexErr>(Bytes::from(a.to_string())),
+            Ok(Bytes::from(b.to_string())),
+        ];
+        process_ollama_stream(
+            futures::stream::iter(chunks),
+            tx,
+            Duration::from_secs(5),
+            emulate_tools,
+        )
+        .await;
+        let mut events = Vec::new();
+        while let Some(ev) = rx.recv().await {
+            events.push(ev.unwrap());
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn maps_native_tool_calls() {
+        let events = collect_events(
+            &[
+                r#"{"message":{"role":"assistant","content":"Listing"},"done":false}"#,
+                r#"{"message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"shell","arguments":{"command":["ls"]}}}]},"done":false}"#,
+                r#"{"message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":20,"eval_count":4}"#,
+            ],
+            false,
+        )
+        .await;
+
+        assert_eq!(events.len(), 5);
+        assert!(matches!(events[0], ResponseEvent::Created));
+        assert!(matches!(&events[1], ResponseEvent::OutputTextDelta(d) if d == "Listing"));
+        assert!(matches!(
+            &events[2],
+            ResponseEvent::OutputItemDone(ResponseItem::FunctionCall { name, arguments, .. })
+                if name == "shell" && arguments == r#"{"command":["ls"]}"#
+        ));
+        assert!(matches!(
+            &events[3],
+            ResponseEvent::OutputItemDone(ResponseItem::Message { .. })
+        ));
+        match &events[4] {
+            ResponseEvent::Completed { token_usage, .. } => {
+                assert_eq!(token_usage.as_ref().unwrap().total_tokens, 24);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn parses_emulated_tool_calls_without_streaming_them() {
+        let events = collect_events(
+            &[
+                r#"{"message":{"role":"assistant","content":"Let me look. <tool"},"done":false}"#,
+                r#"{"message":{"role":"assistant","content":"_call>\n{\"name\": \"shell\", \"arguments\": {\"command\": [\"ls\"]}}\n</tool_call>"},"done":false}"#,
+                r#"{"message":{"role":"assistant","content":""},"done":true}"#,
+            ],
+            true,
+        )
+        .await;
+
+        let deltas: String = events
+            .iter()
+            .filter_map(|ev| match ev {
+                ResponseEvent::OutputTextDelta(d) => Some(d.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(deltas, "Let me look. ");
+        assert!(events.iter().any(|ev| matches!(
+            ev,
+            ResponseEvent::OutputItemDone(ResponseItem::Message { content, .. })
+                if content == &vec![ContentItem::OutputText { text: "Let me look.".to_string() }]
+        )));
+        assert!(events.iter().any(|ev| matches!(
+            ev,
+            ResponseEvent::OutputItemDone(ResponseItem::FunctionCall { name, arguments, .. })
+                if name == "shell" && arguments == r#"{"command":["ls"]}"#
+        )));
+    }
+
+    #[test]
+    fn leaves_malformed_tool_calls_as_text() {
+        let text = "<tool_call>not json</tool_call>";
+        assert_eq!(
+            parse_emulated_tool_calls(text),
+            (text.to_string(), Vec::new())
+        );
+    }
+}
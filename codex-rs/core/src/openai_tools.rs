@@ -5,6 +5,7 @@ use serde_json::json;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 
+use crate::config_types::CustomToolConfig;
 use crate::model_family::ModelFamily;
 use crate::plan_tool::PLAN_TOOL;
 use crate::protocol::AskForApproval;
@@ -55,6 +56,18 @@ pub(crate) enum OpenAiTool {
     Freeform(FreeformTool),
 }
 
+impl OpenAiTool {
+    /// The name the model calls the tool by.
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            OpenAiTool::Function(ResponsesApiTool { name, .. })
+            | OpenAiTool::Freeform(FreeformTool { name, .. }) => name,
+            OpenAiTool::LocalShell {} => "local_shell",
+            OpenAiTool::WebSearch {} => "web_search",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum ConfigShellToolType {
     DefaultShell,
@@ -70,6 +83,10 @@ pub(crate) struct ToolsConfig {
     pub apply_patch_tool_type: Option<ApplyPatchToolType>,
     pub web_search_request: bool,
     pub include_view_image_tool: bool,
+    pub background_jobs: bool,
+    pub git_tools: bool,
+    pub edit_files: bool,
+    pub refresh_environment: bool,
 }
 
 pub(crate) struct ToolsConfigParams<'a> {
@@ -81,6 +98,10 @@ pub(crate) struct ToolsConfigParams<'a> {
     pub(crate) include_web_search_request: bool,
     pub(crate) use_streamable_shell_tool: bool,
     pub(crate) include_view_image_tool: bool,
+    pub(crate) include_background_jobs_tool: bool,
+    pub(crate) include_git_tools: bool,
+    pub(crate) include_edit_files_tool: bool,
+    pub(crate) include_refresh_environment_tool: bool,
 }
 
 impl ToolsConfig {
@@ -94,6 +115,10 @@ impl ToolsConfig {
             include_web_search_request,
             use_streamable_shell_tool,
             include_view_image_tool,
+            include_background_jobs_tool,
+            include_git_tools,
+            include_edit_files_tool,
+            include_refresh_environment_tool,
         } = params;
         let mut shell_type = if *use_streamable_shell_tool {
             ConfigShellToolType::StreamableShell
@@ -126,6 +151,10 @@ impl ToolsConfig {
             apply_patch_tool_type,
             web_search_request: *include_web_search_request,
             include_view_image_tool: *include_view_image_tool,
+            background_jobs: *include_background_jobs_tool,
+            git_tools: *include_git_tools,
+            edit_files: *include_edit_files_tool,
+            refresh_environment: *include_refresh_environment_tool,
         }
     }
 }
@@ -187,6 +216,14 @@ fn create_shell_tool() -> OpenAiTool {
             description: Some("The timeout for the command in milliseconds".to_string()),
         },
     );
+    properties.insert(
+        "max_output_bytes".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Stop the command once it has written this many bytes of output".to_string(),
+            ),
+        },
+    );
 
     OpenAiTool::Function(ResponsesApiTool {
         name: "shell".to_string(),
@@ -221,6 +258,14 @@ fn create_shell_tool_for_sandbox(sandbox_policy: &SandboxPolicy) -> OpenAiTool {
             description: Some("The timeout for the command in milliseconds".to_string()),
         },
     );
+    properties.insert(
+        "max_output_bytes".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Stop the command once it has written this many bytes of output".to_string(),
+            ),
+        },
+    );
 
     if matches!(sandbox_policy, SandboxPolicy::WorkspaceWrite { .. }) {
         properties.insert(
@@ -374,6 +419,69 @@ pub(crate) fn create_tools_json_for_chat_completions_api(
     Ok(tools_json)
 }
 
+/// Translate the tool list into Anthropic Messages `tools` entries. Only
+/// function tools are supported; built-in OpenAI tools are dropped.
+pub(crate) fn create_tools_json_for_anthropic_api(
+    tools: &Vec<OpenAiTool>,
+) -> crate::error::Result<Vec<serde_json::Value>> {
+    let tools_json = create_tools_json_for_responses_api(tools)?
+        .into_iter()
+        .filter(|tool| tool.get("type").and_then(|t| t.as_str()) == Some("function"))
+        .map(|tool| {
+            json!({
+                "name": tool.get("name"),
+                "description": tool.get("description"),
+                "input_schema": tool.get("parameters"),
+            })
+        })
+        .collect();
+    Ok(tools_json)
+}
+
+/// Translate the tool list into a Gemini `tools` array holding a single
+/// `functionDeclarations` entry (or nothing when there are no function
+/// tools).
+pub(crate) fn create_tools_json_for_gemini_api(
+    tools: &Vec<OpenAiTool>,
+) -> crate::error::Result<Vec<serde_json::Value>> {
+    let declarations: Vec<serde_json::Value> = create_tools_json_for_responses_api(tools)?
+        .into_iter()
+        .filter(|tool| tool.get("type").and_then(|t| t.as_str()) == Some("function"))
+        .map(|tool| {
+            let mut parameters = tool.get("parameters").cloned().unwrap_or_default();
+            strip_unsupported_gemini_schema_keys(&mut parameters);
+            json!({
+                "name": tool.get("name"),
+                "description": tool.get("description"),
+                "parameters": parameters,
+            })
+        })
+        .collect();
+    if declarations.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(vec![json!({ "functionDeclarations": declarations })])
+}
+
+/// Gemini accepts an OpenAPI subset of JSON Schema and rejects requests that
+/// contain `additionalProperties`.
+fn strip_unsupported_gemini_schema_keys(schema: &mut serde_json::Value) {
+    match schema {
+        serde_json::Value::Object(map) => {
+            map.remove("additionalProperties");
+            for value in map.values_mut() {
+                strip_unsupported_gemini_schema_keys(value);
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                strip_unsupported_gemini_schema_keys(value);
+            }
+        }
+        _ => {}
+    }
+}
+
 pub(crate) fn mcp_tool_to_openai_tool(
     fully_qualified_name: String,
     tool: mcp_types::Tool,
@@ -409,6 +517,24 @@ pub(crate) fn mcp_tool_to_openai_tool(
     })
 }
 
+/// Converts a tool declared under `[custom_tools]` in config.
+pub(crate) fn custom_tool_to_openai_tool(
+    name: &str,
+    tool: &CustomToolConfig,
+) -> Result<ResponsesApiTool, serde_json::Error> {
+    let mut parameters = tool
+        .parameters
+        .clone()
+        .unwrap_or_else(|| json!({ "type": "object" }));
+    sanitize_json_schema(&mut parameters);
+    Ok(ResponsesApiTool {
+        name: name.to_string(),
+        description: tool.description.clone(),
+        strict: false,
+        parameters: serde_json::from_value(parameters)?,
+    })
+}
+
 /// Sanitize a JSON Schema (as serde_json::Value) so it can fit our limited
 /// JsonSchema enum. This function:
 /// - Ensures every schema object has a "type". If missing, infers it from
@@ -523,6 +649,99 @@ fn sanitize_json_schema(value: &mut JsonValue) {
     }
 }
 
+/// Converts the MCP tools, keyed by fully qualified name. See
+/// [`McpConnectionManager`] for how names that collide are resolved.
+pub(crate) fn mcp_tools_to_openai_tools(
+    mcp_tools: HashMap<String, mcp_types::Tool>,
+) -> Vec<OpenAiTool> {
+    // Ensure deterministic ordering to maximize prompt cache hits.
+    // HashMap iteration order is non-deterministic, so sort by fully-qualified tool name.
+    let mut entries: Vec<(String, mcp_types::Tool)> = mcp_tools.into_iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut tools = Vec::with_capacity(entries.len());
+    for (name, tool) in entries.into_iter() {
+        match mcp_tool_to_openai_tool(name.clone(), tool) {
+            Ok(converted_tool) => tools.push(OpenAiTool::Function(converted_tool)),
+            Err(e) => {
+                tracing::error!("Failed to convert {name:?} MCP tool to OpenAI tool: {e:?}");
+            }
+        }
+    }
+    tools
+}
+
+/// Shortens the descriptions of `tools` and of their parameters to about
+/// `max_tokens` tokens each (four bytes per token), ending at a sentence or
+/// word where one is close.
+pub(crate) fn trim_tool_descriptions(tools: &mut [OpenAiTool], max_tokens: usize) {
+    let max_bytes = max_tokens.saturating_mul(4);
+    for tool in tools {
+        match tool {
+            OpenAiTool::Function(ResponsesApiTool {
+                description,
+                parameters,
+                ..
+            }) => {
+                trim_description(description, max_bytes);
+                trim_schema_descriptions(parameters, max_bytes);
+            }
+            OpenAiTool::Freeform(FreeformTool { description, .. }) => {
+                trim_description(description, max_bytes);
+            }
+            OpenAiTool::LocalShell {} | OpenAiTool::WebSearch {} => {}
+        }
+    }
+}
+
+fn trim_schema_descriptions(schema: &mut JsonSchema, max_bytes: usize) {
+    match schema {
+        JsonSchema::Boolean { description }
+        | JsonSchema::String { description }
+        | JsonSchema::Number { description } => {
+            if let Some(description) = description {
+                trim_description(description, max_bytes);
+            }
+        }
+        JsonSchema::Array { items, description } => {
+            if let Some(description) = description {
+                trim_description(description, max_bytes);
+            }
+            trim_schema_descriptions(items, max_bytes);
+        }
+        JsonSchema::Object { properties, .. } => {
+            for property in properties.values_mut() {
+                trim_schema_descriptions(property, max_bytes);
+            }
+        }
+    }
+}
+
+fn trim_description(description: &mut String, max_bytes: usize) {
+    if description.len() <= max_bytes {
+        return;
+    }
+    let mut end = max_bytes;
+    while !description.is_char_boundary(end) {
+        end -= 1;
+    }
+    let head = &description[..end];
+    // Prefer ending on a whole sentence, then on a whole word, as long as
+    // that keeps most of the budget.
+    let sentence_end = head.rfind(". ").map(|i| i + 1).or_else(|| head.rfind('\n'));
+    let end = match sentence_end {
+        Some(i) if i >= end / 2 => i,
+        _ => head
+            .rfind(char::is_whitespace)
+            .filter(|&i| i >= end / 2)
+            .unwrap_or(end),
+    };
+    description.truncate(end);
+    let trimmed_len = description.trim_end().len();
+    description.truncate(trimmed_len);
+    description.push('…');
+}
+
 /// Returns a list of OpenAiTools based on the provided config and MCP tools.
 /// Note that the keys of mcp_tools should be fully qualified names. See
 /// [`McpConnectionManager`] for more details.
@@ -552,6 +771,34 @@ pub(crate) fn get_openai_tools(
         }
     }
 
+    if config.background_jobs {
+        tools.extend(
+            crate::background_jobs::create_background_tools()
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+    }
+
+    if config.git_tools {
+        tools.extend(
+            crate::git_tools::create_git_tools()
+                .into_iter()
+                .map(OpenAiTool::Function),
+        );
+    }
+
+    if config.edit_files {
+        tools.push(OpenAiTool::Function(
+            crate::edit_files::create_edit_files_tool(),
+        ));
+    }
+
+    if config.refresh_environment {
+        tools.push(OpenAiTool::Function(
+            crate::environment_snapshot::create_refresh_environment_tool(),
+        ));
+    }
+
     if config.plan_tool {
         tools.push(PLAN_TOOL.clone());
     }
@@ -577,19 +824,7 @@ pub(crate) fn get_openai_tools(
     }
 
     if let Some(mcp_tools) = mcp_tools {
-        // Ensure deterministic ordering to maximize prompt cache hits.
-        // HashMap iteration order is non-deterministic, so sort by fully-qualified tool name.
-        let mut entries: Vec<(String, mcp_types::Tool)> = mcp_tools.into_iter().collect();
-        entries.sort_by(|a, b| a.0.cmp(&b.0));
-
-        for (name, tool) in entries.into_iter() {
-            match mcp_tool_to_openai_tool(name.clone(), tool.clone()) {
-                Ok(converted_tool) => tools.push(OpenAiTool::Function(converted_tool)),
-                Err(e) => {
-                    tracing::error!("Failed to convert {name:?} MCP tool to OpenAI tool: {e:?}");
-                }
-            }
-        }
+        tools.extend(mcp_tools_to_openai_tools(mcp_tools));
     }
 
     tools
@@ -640,6 +875,10 @@ mod tests {
             include_web_search_request: true,
             use_streamable_shell_tool: false,
             include_view_image_tool: true,
+            include_background_jobs_tool: false,
+            include_git_tools: false,
+            include_edit_files_tool: false,
+            include_refresh_environment_tool: false,
         });
         let tools = get_openai_tools(&config, Some(HashMap::new()));
 
@@ -661,6 +900,10 @@ mod tests {
             include_web_search_request: true,
             use_streamable_shell_tool: false,
             include_view_image_tool: true,
+            include_background_jobs_tool: false,
+            include_git_tools: false,
+            include_edit_files_tool: false,
+            include_refresh_environment_tool: false,
         });
         let tools = get_openai_tools(&config, Some(HashMap::new()));
 
@@ -682,6 +925,10 @@ mod tests {
             include_web_search_request: true,
             use_streamable_shell_tool: false,
             include_view_image_tool: true,
+            include_background_jobs_tool: false,
+            include_git_tools: false,
+            include_edit_files_tool: false,
+            include_refresh_environment_tool: false,
         });
         let tools = get_openai_tools(
             &config,
@@ -787,6 +1034,10 @@ mod tests {
             include_web_search_request: false,
             use_streamable_shell_tool: false,
             include_view_image_tool: true,
+            include_background_jobs_tool: false,
+            include_git_tools: false,
+            include_edit_files_tool: false,
+            include_refresh_environment_tool: false,
         });
 
         // Intentionally construct a map with keys that would sort alphabetically.
@@ -864,6 +1115,10 @@ mod tests {
             include_web_search_request: true,
             use_streamable_shell_tool: false,
             include_view_image_tool: true,
+            include_background_jobs_tool: false,
+            include_git_tools: false,
+            include_edit_files_tool: false,
+            include_refresh_environment_tool: false,
         });
 
         let tools = get_openai_tools(
@@ -926,6 +1181,10 @@ mod tests {
             include_web_search_request: true,
             use_streamable_shell_tool: false,
             include_view_image_tool: true,
+            include_background_jobs_tool: false,
+            include_git_tools: false,
+            include_edit_files_tool: false,
+            include_refresh_environment_tool: false,
         });
 
         let tools = get_openai_tools(
@@ -983,6 +1242,10 @@ mod tests {
             include_web_search_request: true,
             use_streamable_shell_tool: false,
             include_view_image_tool: true,
+            include_background_jobs_tool: false,
+            include_git_tools: false,
+            include_edit_files_tool: false,
+            include_refresh_environment_tool: false,
         });
 
         let tools = get_openai_tools(
@@ -1040,6 +1303,10 @@ mod tests {
             include_web_search_request: true,
             use_streamable_shell_tool: false,
             include_view_image_tool: true,
+            include_background_jobs_tool: false,
+            include_git_tools: false,
+            include_edit_files_tool: false,
+            include_refresh_environment_tool: false,
         });
 
         let tools = get_openai_tools(
@@ -1081,4 +1348,78 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn test_custom_tool_schema_from_toml() {
+        let tool: CustomToolConfig = toml::from_str(
+            r#"
+description = "Look up an issue"
+command = ["jira", "view", "{key}"]
+parameters = { type = "object", properties = { key = { type = "string" }, limit = { type = "integer" } }, required = ["key"] }
+"#,
+        )
+        .expect("parse custom tool");
+
+        let tool = custom_tool_to_openai_tool("jira_issue", &tool).expect("convert tool");
+        assert_eq!(
+            tool,
+            ResponsesApiTool {
+                name: "jira_issue".to_string(),
+                description: "Look up an issue".to_string(),
+                strict: false,
+                parameters: JsonSchema::Object {
+                    properties: BTreeMap::from([
+                        ("key".to_string(), JsonSchema::String { description: None }),
+                        (
+                            "limit".to_string(),
+                            JsonSchema::Number { description: None }
+                        ),
+                    ]),
+                    required: Some(vec!["key".to_string()]),
+                    additional_properties: None,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_trim_tool_descriptions() {
+        let mut tools = vec![OpenAiTool::Function(ResponsesApiTool {
+            name: "search".to_string(),
+            description: "Searches the index. Results are ranked by relevance and include \
+                          the title, the path and a short excerpt of each page."
+                .to_string(),
+            strict: false,
+            parameters: JsonSchema::Object {
+                properties: BTreeMap::from([(
+                    "query".to_string(),
+                    JsonSchema::String {
+                        description: Some(
+                            "Words to look for; quoted phrases must match exactly".to_string(),
+                        ),
+                    },
+                )]),
+                required: None,
+                additional_properties: None,
+            },
+        })];
+
+        trim_tool_descriptions(&mut tools, 8);
+
+        let OpenAiTool::Function(ResponsesApiTool {
+            description,
+            parameters: JsonSchema::Object { properties, .. },
+            ..
+        }) = &tools[0]
+        else {
+            panic!("expected a function tool");
+        };
+        assert_eq!(description, "Searches the index.…");
+        assert_eq!(
+            properties["query"],
+            JsonSchema::String {
+                description: Some("Words to look for; quoted…".to_string()),
+            }
+        );
+    }
 }
@@ -11,8 +11,17 @@
 //!     current working directory (inclusive) and concatenate their contents in
 //!     that order.
 //! 3.  We do **not** walk past the Git root.
+//!
+//! Inside a Git repository, `AGENTS.md` files in directories *below* the
+//! working directory, such as the packages of a monorepo, only apply to the
+//! files in their directory. They are collected by [`NestedProjectDocs`] and
+//! added to the conversation the first time the agent works on a file they
+//! apply to, more specific (deeper) docs after, and taking precedence over,
+//! more general ones. All docs share the `project_doc_max_bytes` budget; docs
+//! that do not fit are truncated or skipped and reported to the user.
 
 use crate::config::Config;
+use std::path::Path;
 use std::path::PathBuf;
 use tokio::io::AsyncReadExt;
 use tracing::error;
@@ -24,24 +33,52 @@ const CANDIDATE_FILENAMES: &[&str] = &["AGENTS.md"];
 /// be concatenated with the following separator.
 const PROJECT_DOC_SEPARATOR: &str = "\n\n--- project-doc ---\n\n";
 
-/// Combines `Config::instructions` and `AGENTS.md` (if present) into a single
-/// string of instructions.
-pub(crate) async fn get_user_instructions(config: &Config) -> Option<String> {
-    match read_project_docs(config).await {
-        Ok(Some(project_doc)) => match &config.user_instructions {
-            Some(original_instructions) => Some(format!(
-                "{original_instructions}{PROJECT_DOC_SEPARATOR}{project_doc}"
-            )),
-            None => Some(project_doc),
-        },
-        Ok(None) => config.user_instructions.clone(),
+/// Project docs loaded when a session starts.
+pub(crate) struct ProjectDocs {
+    /// `Config::instructions` combined with the `AGENTS.md` files from the
+    /// repository root down to the working directory.
+    pub(crate) user_instructions: Option<String>,
+    /// `AGENTS.md` files below the working directory.
+    pub(crate) nested: NestedProjectDocs,
+    /// Docs that were truncated or skipped to stay within the budget.
+    pub(crate) truncated: Vec<String>,
+}
+
+/// Loads the project docs for a new session. Failures are logged and treated
+/// as if there were no docs.
+pub(crate) async fn load_project_docs(config: &Config) -> ProjectDocs {
+    let (project_doc, truncated, remaining) = match read_docs(config).await {
+        Ok(docs) => (docs.text, docs.truncated, docs.remaining),
         Err(e) => {
             error!("error trying to find project doc: {e:#}");
-            config.user_instructions.clone()
+            (None, Vec::new(), 0)
         }
+    };
+    let user_instructions = match (project_doc, &config.user_instructions) {
+        (Some(project_doc), Some(original_instructions)) => Some(format!(
+            "{original_instructions}{PROJECT_DOC_SEPARATOR}{project_doc}"
+        )),
+        (Some(project_doc), None) => Some(project_doc),
+        (None, _) => config.user_instructions.clone(),
+    };
+    let nested = NestedProjectDocs::discover(config, remaining).unwrap_or_else(|e| {
+        error!("error trying to find nested project docs: {e:#}");
+        NestedProjectDocs::default()
+    });
+    ProjectDocs {
+        user_instructions,
+        nested,
+        truncated,
     }
 }
 
+/// Combines `Config::instructions` and `AGENTS.md` (if present) into a single
+/// string of instructions.
+#[cfg(test)]
+pub(crate) async fn get_user_instructions(config: &Config) -> Option<String> {
+    load_project_docs(config).await.user_instructions
+}
+
 /// Attempt to locate and load the project documentation.
 ///
 /// On success returns `Ok(Some(contents))` where `contents` is the
@@ -49,15 +86,32 @@ pub(crate) async fn get_user_instructions(config: &Config) -> Option<String> {
 /// function returns `Ok(None)`. Unexpected I/O failures bubble up as `Err` so
 /// callers can decide how to handle them.
 pub async fn read_project_docs(config: &Config) -> std::io::Result<Option<String>> {
+    Ok(read_docs(config).await?.text)
+}
+
+/// The project docs along the path to the working directory.
+struct Docs {
+    text: Option<String>,
+    truncated: Vec<String>,
+    /// Bytes left of `project_doc_max_bytes`.
+    remaining: usize,
+}
+
+async fn read_docs(config: &Config) -> std::io::Result<Docs> {
     let max_total = config.project_doc_max_bytes;
+    let mut docs = Docs {
+        text: None,
+        truncated: Vec::new(),
+        remaining: max_total,
+    };
 
     if max_total == 0 {
-        return Ok(None);
+        return Ok(docs);
     }
 
     let paths = discover_project_doc_paths(config)?;
     if paths.is_empty() {
-        return Ok(None);
+        return Ok(docs);
     }
 
     let mut remaining: u64 = max_total as u64;
@@ -65,7 +119,8 @@ pub async fn read_project_docs(config: &Config) -> std::io::Result<Option<String
 
     for p in paths {
         if remaining == 0 {
-            break;
+            docs.truncated.push(skipped_report(&p));
+            continue;
         }
 
         let file = match tokio::fs::File::open(&p).await {
@@ -85,6 +140,7 @@ pub async fn read_project_docs(config: &Config) -> std::io::Result<Option<String
                 p.display(),
                 remaining,
             );
+            docs.truncated.push(truncated_report(&p, remaining, size));
         }
 
         let text = String::from_utf8_lossy(&data).to_string();
@@ -94,11 +150,44 @@ pub async fn read_project_docs(config: &Config) -> std::io::Result<Option<String
         }
     }
 
-    if parts.is_empty() {
-        Ok(None)
-    } else {
-        Ok(Some(parts.join("\n\n")))
+    docs.remaining = remaining as usize;
+    if !parts.is_empty() {
+        docs.text = Some(parts.join("\n\n"));
+    }
+    Ok(docs)
+}
+
+/// Tells the user which project docs did not fit into the budget.
+pub(crate) fn truncation_report(truncated: &[String]) -> String {
+    format!(
+        "Project docs exceed `project_doc_max_bytes`: {}.",
+        truncated.join("; ")
+    )
+}
+
+fn truncated_report(path: &Path, kept: u64, size: u64) -> String {
+    format!(
+        "`{}` was truncated to {kept} of {size} bytes",
+        path.display()
+    )
+}
+
+fn skipped_report(path: &Path) -> String {
+    format!("`{}` was skipped", path.display())
+}
+
+/// Walks upwards from `dir` until a directory containing `.git` (a directory
+/// or, for worktrees and submodules, a file) is found. The filesystem root is
+/// not considered.
+pub(crate) fn find_git_root(dir: &Path) -> std::io::Result<Option<PathBuf>> {
+    for cursor in dir.ancestors().filter(|p| p.parent().is_some()) {
+        match std::fs::metadata(cursor.join(".git")) {
+            Ok(_) => return Ok(Some(cursor.to_path_buf())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
     }
+    Ok(None)
 }
 
 /// Discover the list of AGENTS.md files using the same search rules as
@@ -112,43 +201,18 @@ pub fn discover_project_doc_paths(config: &Config) -> std::io::Result<Vec<PathBu
         dir = canon;
     }
 
-    // Build chain from cwd upwards and detect git root.
-    let mut chain: Vec<PathBuf> = vec![dir.clone()];
-    let mut git_root: Option<PathBuf> = None;
-    let mut cursor = dir.clone();
-    while let Some(parent) = cursor.parent() {
-        let git_marker = cursor.join(".git");
-        let git_exists = match std::fs::metadata(&git_marker) {
-            Ok(_) => true,
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
-            Err(e) => return Err(e),
-        };
-
-        if git_exists {
-            git_root = Some(cursor.clone());
-            break;
-        }
-
-        chain.push(parent.to_path_buf());
-        cursor = parent.to_path_buf();
-    }
-
-    let search_dirs: Vec<PathBuf> = if let Some(root) = git_root {
-        let mut dirs: Vec<PathBuf> = Vec::new();
-        let mut saw_root = false;
-        for p in chain.iter().rev() {
-            if !saw_root {
-                if p == &root {
-                    saw_root = true;
-                } else {
-                    continue;
-                }
-            }
-            dirs.push(p.clone());
+    let search_dirs: Vec<PathBuf> = match find_git_root(&dir)? {
+        Some(root) => {
+            let mut dirs: Vec<PathBuf> = dir
+                .ancestors()
+                .take_while(|p| *p != root)
+                .map(Path::to_path_buf)
+                .collect();
+            dirs.push(root);
+            dirs.reverse();
+            dirs
         }
-        dirs
-    } else {
-        vec![config.cwd.clone()]
+        None => vec![config.cwd.clone()],
     };
 
     let mut found: Vec<PathBuf> = Vec::new();
@@ -173,6 +237,178 @@ pub fn discover_project_doc_paths(config: &Config) -> std::io::Result<Vec<PathBu
     Ok(found)
 }
 
+/// How deep below the working directory nested docs are looked for.
+const MAX_NESTED_DOC_DEPTH: usize = 8;
+
+/// Most nested docs that are tracked for a session.
+const MAX_NESTED_DOCS: usize = 128;
+
+/// Directories that never hold docs for the project itself.
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target", "vendor"];
+
+/// Start of the message that adds a nested `AGENTS.md` to the conversation.
+pub(crate) const SCOPED_INSTRUCTIONS_START: &str = "<scoped_instructions";
+
+/// An `AGENTS.md` below the working directory, ready to be added to the
+/// conversation.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ScopedProjectDoc {
+    /// The instructions, wrapped in a `<scoped_instructions>` block that says
+    /// which files they apply to. `None` when the budget was used up.
+    pub(crate) message: Option<String>,
+    /// Set when the doc did not fit into the remaining budget.
+    pub(crate) truncated: Option<String>,
+}
+
+/// The `AGENTS.md` files below the working directory that have not been
+/// added to the conversation yet.
+#[derive(Debug, Default)]
+pub(crate) struct NestedProjectDocs {
+    cwd: PathBuf,
+    /// Directories holding a pending doc, shallowest first.
+    pending: Vec<PathBuf>,
+    /// Bytes left of `project_doc_max_bytes`.
+    remaining: usize,
+}
+
+/// Adds the subdirectories of `dir` that hold an `AGENTS.md` to `found`,
+/// skipping hidden and dependency directories.
+fn collect_nested_doc_dirs(
+    dir: &Path,
+    depth: usize,
+    found: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    if depth > MAX_NESTED_DOC_DEPTH {
+        return Ok(());
+    }
+    let mut subdirs = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir()
+            && !name.starts_with('.')
+            && !SKIPPED_DIRS.contains(&name.as_str())
+        {
+            subdirs.push(entry.path());
+        }
+    }
+    subdirs.sort();
+    for subdir in subdirs {
+        if found.len() == MAX_NESTED_DOCS {
+            return Ok(());
+        }
+        if CANDIDATE_FILENAMES
+            .iter()
+            .any(|name| subdir.join(name).is_file())
+        {
+            found.push(subdir.clone());
+        }
+        collect_nested_doc_dirs(&subdir, depth + 1, found)?;
+    }
+    Ok(())
+}
+
+impl NestedProjectDocs {
+    fn discover(config: &Config, remaining: usize) -> std::io::Result<Self> {
+        let cwd = config
+            .cwd
+            .canonicalize()
+            .unwrap_or_else(|_| config.cwd.clone());
+        // Outside of a repository the working directory may be anything,
+        // e.g. the home directory, which is not worth scanning.
+        if config.project_doc_max_bytes == 0 || find_git_root(&cwd)?.is_none() {
+            return Ok(Self {
+                cwd,
+                ..Default::default()
+            });
+        }
+
+        let mut pending = Vec::new();
+        collect_nested_doc_dirs(&cwd, 1, &mut pending)?;
+        if pending.len() == MAX_NESTED_DOCS {
+            tracing::warn!("Only the first {MAX_NESTED_DOCS} nested project docs are used.");
+        }
+        pending.sort_by_key(|dir| dir.components().count());
+        Ok(Self {
+            cwd,
+            pending,
+            remaining,
+        })
+    }
+
+    /// Removes and reads the pending docs that apply to any of `paths`,
+    /// general ones first. Relative paths are resolved against the working
+    /// directory.
+    pub(crate) fn take_for_paths(&mut self, paths: &[PathBuf]) -> Vec<ScopedProjectDoc> {
+        if self.pending.is_empty() {
+            return Vec::new();
+        }
+        let paths: Vec<PathBuf> = paths
+            .iter()
+            .map(|path| {
+                let path = self.cwd.join(path);
+                path.canonicalize().unwrap_or(path)
+            })
+            .collect();
+        let (matching, pending): (Vec<PathBuf>, Vec<PathBuf>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|dir| paths.iter().any(|path| path.starts_with(dir)));
+        self.pending = pending;
+        matching
+            .into_iter()
+            .filter_map(|dir| self.read_scoped_doc(&dir))
+            .collect()
+    }
+
+    fn read_scoped_doc(&mut self, dir: &Path) -> Option<ScopedProjectDoc> {
+        use std::io::Read;
+
+        let path = CANDIDATE_FILENAMES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())?;
+        let relative = path.strip_prefix(&self.cwd).unwrap_or(&path);
+        if self.remaining == 0 {
+            return Some(ScopedProjectDoc {
+                message: None,
+                truncated: Some(skipped_report(relative)),
+            });
+        }
+
+        let file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                error!("error reading project doc `{}`: {e:#}", path.display());
+                return None;
+            }
+        };
+        let size = file.metadata().map(|m| m.len()).unwrap_or_default();
+        let mut data = Vec::new();
+        if let Err(e) = file.take(self.remaining as u64).read_to_end(&mut data) {
+            error!("error reading project doc `{}`: {e:#}", path.display());
+            return None;
+        }
+        let truncated =
+            (size > data.len() as u64).then(|| truncated_report(relative, data.len() as u64, size));
+        let text = String::from_utf8_lossy(&data);
+        if text.trim().is_empty() {
+            return None;
+        }
+        self.remaining = self.remaining.saturating_sub(data.len());
+
+        let scope = dir.strip_prefix(&self.cwd).unwrap_or(dir);
+        Some(ScopedProjectDoc {
+            message: Some(format!(
+                "{SCOPED_INSTRUCTIONS_START} path=\"{}\">\nThese instructions apply to files under `{}/`. Where they conflict with more general AGENTS.md instructions, these take precedence.\n\n{}\n</scoped_instructions>",
+                relative.display(),
+                scope.display(),
+                text.trim_end()
+            )),
+            truncated,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -347,4 +583,93 @@ mod tests {
         let res = get_user_instructions(&cfg).await.expect("doc expected");
         assert_eq!(res, "root doc\n\ncrate doc");
     }
+
+    /// Docs below the working directory are not part of the initial
+    /// instructions; they are handed out, general ones first, once a file
+    /// they apply to is touched, and only once.
+    #[tokio::test]
+    async fn nested_docs_are_added_for_touched_files() {
+        let repo = tempfile::tempdir().expect("tempdir");
+        fs::write(
+            repo.path().join(".git"),
+            "gitdir: /path/to/actual/git/dir\n",
+        )
+        .unwrap();
+        fs::write(repo.path().join("AGENTS.md"), "root doc").unwrap();
+        let api = repo.path().join("packages/api");
+        fs::create_dir_all(api.join("v2")).unwrap();
+        fs::create_dir_all(repo.path().join("packages/web")).unwrap();
+        fs::create_dir_all(repo.path().join("node_modules/dep")).unwrap();
+        fs::write(api.join("AGENTS.md"), "api doc").unwrap();
+        fs::write(api.join("v2/AGENTS.md"), "v2 doc").unwrap();
+        fs::write(repo.path().join("packages/web/AGENTS.md"), "web doc").unwrap();
+        fs::write(repo.path().join("node_modules/dep/AGENTS.md"), "dep doc").unwrap();
+
+        let mut docs = load_project_docs(&make_config(&repo, 4096, None)).await;
+        assert_eq!(docs.user_instructions.as_deref(), Some("root doc"));
+        assert_eq!(docs.truncated, Vec::<String>::new());
+
+        let messages: Vec<String> = docs
+            .nested
+            .take_for_paths(&[PathBuf::from("packages/api/v2/handler.rs")])
+            .into_iter()
+            .filter_map(|doc| doc.message)
+            .collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("<scoped_instructions path=\"packages/api/AGENTS.md\">"));
+        assert!(messages[0].contains("apply to files under `packages/api/`"));
+        assert!(messages[0].ends_with("api doc\n</scoped_instructions>"));
+        assert!(messages[1].contains("v2 doc"));
+
+        assert_eq!(
+            docs.nested
+                .take_for_paths(&[api.join("v2/other.rs"), PathBuf::from("node_modules/dep")]),
+            Vec::new()
+        );
+        assert_eq!(
+            docs.nested
+                .take_for_paths(&[repo.path().join("packages/web")])
+                .len(),
+            1
+        );
+    }
+
+    /// Nested docs share the budget with the docs read at startup, and what
+    /// does not fit is reported.
+    #[tokio::test]
+    async fn nested_docs_are_truncated_to_the_remaining_budget() {
+        let repo = tempfile::tempdir().expect("tempdir");
+        fs::write(
+            repo.path().join(".git"),
+            "gitdir: /path/to/actual/git/dir\n",
+        )
+        .unwrap();
+        fs::write(repo.path().join("AGENTS.md"), "A".repeat(10)).unwrap();
+        fs::create_dir_all(repo.path().join("a")).unwrap();
+        fs::create_dir_all(repo.path().join("b")).unwrap();
+        fs::write(repo.path().join("a/AGENTS.md"), "B".repeat(10)).unwrap();
+        fs::write(repo.path().join("b/AGENTS.md"), "C".repeat(10)).unwrap();
+
+        let mut docs = load_project_docs(&make_config(&repo, 15, None)).await;
+        let a = docs.nested.take_for_paths(&[PathBuf::from("a/x")]);
+        assert_eq!(a.len(), 1);
+        assert!(
+            a[0].message
+                .as_deref()
+                .is_some_and(|m| m.contains("\nBBBBB\n"))
+        );
+        assert_eq!(
+            a[0].truncated.as_deref(),
+            Some("`a/AGENTS.md` was truncated to 5 of 10 bytes")
+        );
+
+        let b = docs.nested.take_for_paths(&[PathBuf::from("b/x")]);
+        assert_eq!(
+            b,
+            vec![ScopedProjectDoc {
+                message: None,
+                truncated: Some("`b/AGENTS.md` was skipped".to_string()),
+            }]
+        );
+    }
 }
@@ -0,0 +1,369 @@
+//! Checks the final message of a turn against the JSON schema requested with
+//! a [`ResponseFormat`]. Only the commonly used part of JSON Schema is
+//! understood; unknown keywords are ignored.
+
+use codex_protocol::protocol::ResponseFormat;
+use serde_json::Value;
+
+/// How often the model is asked again for a message matching the schema.
+pub(crate) const MAX_RESPONSE_FORMAT_RETRIES: u32 = 2;
+
+/// Deepest nesting of schemas (including `$ref`s) that is followed.
+const MAX_DEPTH: usize = 64;
+
+/// Parses `message` as JSON and checks it against the schema of `format`. A
+/// message wrapped in a Markdown code fence is accepted.
+pub(crate) fn validate_response(format: &ResponseFormat, message: &str) -> Result<(), Vec<String>> {
+    let value: Value = serde_json::from_str(strip_code_fence(message.trim()))
+        .map_err(|e| vec![format!("the message is not valid JSON: {e}")])?;
+    let mut validator = Validator {
+        root: &format.schema,
+        errors: Vec::new(),
+    };
+    validator.validate(&format.schema, &value, "$", 0);
+    if validator.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(validator.errors)
+    }
+}
+
+/// Describes the schema in the instructions, for providers that cannot
+/// enforce it themselves.
+pub(crate) fn response_format_instructions(format: &ResponseFormat) -> String {
+    format!(
+        "Your final message must be a single JSON value, without any other text, that matches this JSON schema named `{}`:\n{}",
+        format.name, format.schema
+    )
+}
+
+/// Asks the model to fix a final message that did not match the schema.
+pub(crate) fn response_format_retry_message(format: &ResponseFormat, errors: &[String]) -> String {
+    format!(
+        "Your last message does not match the JSON schema `{}`:\n- {}\nReply again with only JSON that matches the schema.",
+        format.name,
+        errors.join("\n- ")
+    )
+}
+
+fn strip_code_fence(message: &str) -> &str {
+    let Some(rest) = message.strip_prefix("```") else {
+        return message;
+    };
+    let Some((_language, body)) = rest.split_once('\n') else {
+        return message;
+    };
+    body.trim_end().strip_suffix("```").unwrap_or(body).trim()
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+    errors: Vec<String>,
+}
+
+impl<'a> Validator<'a> {
+    fn validate(&mut self, schema: &'a Value, value: &Value, path: &str, depth: usize) {
+        if depth > MAX_DEPTH {
+            self.errors
+                .push(format!("{path}: the schema is nested too deeply"));
+            return;
+        }
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => {
+                self.errors
+                    .push(format!("{path}: no value is allowed here"));
+                return;
+            }
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match reference
+                .strip_prefix('#')
+                .and_then(|pointer| self.root.pointer(pointer))
+            {
+                Some(target) => self.validate(target, value, path, depth + 1),
+                None => self
+                    .errors
+                    .push(format!("{path}: cannot resolve `$ref` {reference}")),
+            }
+        }
+
+        if let Some(expected) = schema.get("type") {
+            let types: Vec<&str> = match expected {
+                Value::String(name) => vec![name.as_str()],
+                Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+                self.errors.push(format!(
+                    "{path}: expected {}, got {}",
+                    types.join(" or "),
+                    type_name(value)
+                ));
+                return;
+            }
+        }
+
+        if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+            && !allowed.contains(value)
+        {
+            self.errors.push(format!(
+                "{path}: {value} is not one of {}",
+                Value::from(allowed.clone())
+            ));
+        }
+        if let Some(expected) = schema.get("const")
+            && expected != value
+        {
+            self.errors
+                .push(format!("{path}: expected {expected}, got {value}"));
+        }
+
+        match value {
+            Value::Object(object) => {
+                let properties = schema.get("properties").and_then(Value::as_object);
+                if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                    for name in required.iter().filter_map(Value::as_str) {
+                        if !object.contains_key(name) {
+                            self.errors
+                                .push(format!("{path}: missing required property `{name}`"));
+                        }
+                    }
+                }
+                // Sorted, so errors come out in the same order whatever the
+                // map's iteration order.
+                let mut names: Vec<&String> = object.keys().collect();
+                names.sort();
+                for name in names {
+                    let property = &object[name];
+                    let property_path = format!("{path}.{name}");
+                    match properties.and_then(|properties| properties.get(name)) {
+                        Some(property_schema) => {
+                            self.validate(property_schema, property, &property_path, depth + 1)
+                        }
+                        None => match schema.get("additionalProperties") {
+                            Some(Value::Bool(false)) => self
+                                .errors
+                                .push(format!("{path}: unexpected property `{name}`")),
+                            Some(additional) => {
+                                self.validate(additional, property, &property_path, depth + 1)
+                            }
+                            None => {}
+                        },
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if let Some(min) = schema.get("minItems").and_then(Value::as_u64)
+                    && (items.len() as u64) < min
+                {
+                    self.errors
+                        .push(format!("{path}: expected at least {min} items"));
+                }
+                if let Some(max) = schema.get("maxItems").and_then(Value::as_u64)
+                    && (items.len() as u64) > max
+                {
+                    self.errors
+                        .push(format!("{path}: expected at most {max} items"));
+                }
+                if let Some(item_schema) = schema.get("items") {
+                    for (index, item) in items.iter().enumerate() {
+                        self.validate(item_schema, item, &format!("{path}[{index}]"), depth + 1);
+                    }
+                }
+            }
+            Value::String(text) => {
+                let length = text.chars().count() as u64;
+                if let Some(min) = schema.get("minLength").and_then(Value::as_u64)
+                    && length < min
+                {
+                    self.errors
+                        .push(format!("{path}: expected at least {min} characters"));
+                }
+                if let Some(max) = schema.get("maxLength").and_then(Value::as_u64)
+                    && length > max
+                {
+                    self.errors
+                        .push(format!("{path}: expected at most {max} characters"));
+                }
+                if let Some(pattern) = schema.get("pattern").and_then(Value::as_str)
+                    && let Ok(regex) = regex_lite::Regex::new(pattern)
+                    && !regex.is_match(text)
+                {
+                    self.errors
+                        .push(format!("{path}: does not match the pattern `{pattern}`"));
+                }
+            }
+            Value::Number(number) => {
+                let number = number.as_f64().unwrap_or_default();
+                let bound = |keyword| schema.get(keyword).and_then(Value::as_f64);
+                if let Some(min) = bound("minimum")
+                    && number < min
+                {
+                    self.errors.push(format!("{path}: must be at least {min}"));
+                }
+                if let Some(max) = bound("maximum")
+                    && number > max
+                {
+                    self.errors.push(format!("{path}: must be at most {max}"));
+                }
+                if let Some(min) = bound("exclusiveMinimum")
+                    && number <= min
+                {
+                    self.errors
+                        .push(format!("{path}: must be greater than {min}"));
+                }
+                if let Some(max) = bound("exclusiveMaximum")
+                    && number >= max
+                {
+                    self.errors.push(format!("{path}: must be less than {max}"));
+                }
+            }
+            Value::Null | Value::Bool(_) => {}
+        }
+
+        if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+            for sub_schema in all {
+                self.validate(sub_schema, value, path, depth + 1);
+            }
+        }
+        if let Some(any) = schema.get("anyOf").and_then(Value::as_array)
+            && !any
+                .iter()
+                .any(|sub_schema| self.matches(sub_schema, value, depth))
+        {
+            self.errors
+                .push(format!("{path}: does not match any of the allowed schemas"));
+        }
+        if let Some(one) = schema.get("oneOf").and_then(Value::as_array) {
+            let matching = one
+                .iter()
+                .filter(|sub_schema| self.matches(sub_schema, value, depth))
+                .count();
+            if matching != 1 {
+                self.errors.push(format!(
+                    "{path}: must match exactly one of the allowed schemas, matches {matching}"
+                ));
+            }
+        }
+        if let Some(not) = schema.get("not")
+            && self.matches(not, value, depth)
+        {
+            self.errors
+                .push(format!("{path}: matches a schema it must not match"));
+        }
+    }
+
+    fn matches(&self, schema: &'a Value, value: &Value, depth: usize) -> bool {
+        let mut validator = Validator {
+            root: self.root,
+            errors: Vec::new(),
+        };
+        validator.validate(schema, value, "$", depth + 1);
+        validator.errors.is_empty()
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => value.as_f64().is_some_and(|number| number.fract() == 0.0),
+        "number" => value.is_number(),
+        _ => type_name(value) == name,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn plan_format() -> ResponseFormat {
+        ResponseFormat {
+            name: "migration_plan".to_string(),
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "steps": {
+                        "type": "array",
+                        "minItems": 1,
+                        "items": {"$ref": "#/$defs/step"},
+                    },
+                    "risk": {"enum": ["low", "medium", "high"]},
+                },
+                "required": ["steps", "risk"],
+                "additionalProperties": false,
+                "$defs": {
+                    "step": {
+                        "type": "object",
+                        "properties": {
+                            "title": {"type": "string", "minLength": 1},
+                            "minutes": {"type": "integer", "minimum": 0},
+                        },
+                        "required": ["title"],
+                    },
+                },
+            }),
+            strict: false,
+        }
+    }
+
+    #[test]
+    fn accepts_matching_json_even_in_a_code_fence() {
+        let message = r#"{"steps": [{"title": "Back up", "minutes": 5}], "risk": "low"}"#;
+        assert_eq!(validate_response(&plan_format(), message), Ok(()));
+        assert_eq!(
+            validate_response(&plan_format(), &format!("```json\n{message}\n```")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn reports_every_mismatch_with_its_path() {
+        let message =
+            r#"{"steps": [{"minutes": 1.5}, {"title": ""}], "risk": "extreme", "notes": 1}"#;
+        assert_eq!(
+            validate_response(&plan_format(), message),
+            Err(vec![
+                "$: unexpected property `notes`".to_string(),
+                r#"$.risk: "extreme" is not one of ["low","medium","high"]"#.to_string(),
+                "$.steps[0]: missing required property `title`".to_string(),
+                "$.steps[0].minutes: expected integer, got number".to_string(),
+                "$.steps[1].title: expected at least 1 characters".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_text_that_is_not_json() {
+        let Err(errors) = validate_response(&plan_format(), "Here is the plan: ...") else {
+            panic!("expected an error");
+        };
+        assert!(errors[0].starts_with("the message is not valid JSON"));
+    }
+
+    #[test]
+    fn combinators_and_recursive_refs_terminate() {
+        let format = ResponseFormat {
+            name: "loop".to_string(),
+            schema: json!({"anyOf": [{"type": "string"}, {"$ref": "#"}]}),
+            strict: false,
+        };
+        assert_eq!(validate_response(&format, r#""ok""#), Ok(()));
+        assert!(validate_response(&format, "1").is_err());
+    }
+}
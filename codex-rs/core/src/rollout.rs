@@ -1,9 +1,12 @@
 //! Persist Codex session rollouts (.jsonl) so sessions can be replayed or inspected later.
+//! Rollouts are also written to the stores configured in `[storage]`.
 
 use std::fs::File;
 use std::fs::{self};
 use std::io::Error as IoError;
 use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use serde::Deserialize;
 use serde::Serialize;
@@ -11,7 +14,6 @@ use serde_json::Value;
 use time::OffsetDateTime;
 use time::format_description::FormatItem;
 use time::macros::format_description;
-use tokio::io::AsyncWriteExt;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::mpsc::{self};
 use tokio::sync::oneshot;
@@ -19,10 +21,25 @@ use tracing::info;
 use tracing::warn;
 use uuid::Uuid;
 
+use crate::client_common::USER_INSTRUCTIONS_START;
 use crate::config::Config;
+use crate::conversation_branches::BranchRecord;
+use crate::conversation_branches::ConversationBranches;
+use crate::conversation_store::ConversationStore;
+use crate::conversation_store::JsonlStore;
+use crate::conversation_store::open_configured_stores;
+use crate::environment_context::ENVIRONMENT_CONTEXT_START;
+use crate::environment_snapshot::ENVIRONMENT_SNAPSHOT_START;
 use crate::git_info::GitInfo;
 use crate::git_info::collect_git_info;
+use crate::message_history::HistoryEntry;
+use crate::message_history::history_filepath;
+use codex_protocol::config_types::ReasoningEffort as ReasoningEffortConfig;
+use codex_protocol::config_types::ReasoningSummary as ReasoningSummaryConfig;
+use codex_protocol::models::ContentItem;
 use codex_protocol::models::ResponseItem;
+use codex_protocol::protocol::AskForApproval;
+use codex_protocol::protocol::SandboxPolicy;
 
 const SESSIONS_SUBDIR: &str = "sessions";
 
@@ -41,8 +58,25 @@ struct SessionMetaWithGit {
     git: Option<GitInfo>,
 }
 
-#[derive(Serialize, Deserialize, Default, Clone)]
-pub struct SessionStateSnapshot {}
+/// Configuration in effect for a session. A new snapshot is written whenever
+/// the session records items so that resuming picks up the latest settings.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct SessionStateSnapshot {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_provider_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<ReasoningEffortConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_summary: Option<ReasoningSummaryConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval_policy: Option<AskForApproval>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox_policy: Option<SandboxPolicy>,
+}
 
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct SavedSession {
@@ -52,10 +86,26 @@ pub struct SavedSession {
     #[serde(default)]
     pub state: SessionStateSnapshot,
     pub session_id: Uuid,
+    /// Branches left behind by rewinding; `items` holds the current one.
+    #[serde(skip)]
+    pub(crate) branches: ConversationBranches,
+}
+
+/// Summary of a recorded session, used to list sessions that can be resumed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSummary {
+    pub id: Uuid,
+    pub path: PathBuf,
+    pub timestamp: String,
+    pub cwd: Option<PathBuf>,
+    pub model: Option<String>,
+    /// First message the user typed in the session, if any.
+    pub preview: Option<String>,
 }
 
 /// Records all [`ResponseItem`]s for a session and flushes them to disk after
-/// every update.
+/// every update. The message history entries typed in the session go through
+/// the recorder as well, so that they reach the same stores.
 ///
 /// Rollouts are recorded as JSONL and can be inspected with tools such as:
 ///
@@ -71,6 +121,8 @@ pub(crate) struct RolloutRecorder {
 enum RolloutCmd {
     AddItems(Vec<ResponseItem>),
     UpdateState(SessionStateSnapshot),
+    Branch(BranchRecord),
+    History(HistoryEntry),
     Shutdown { ack: oneshot::Sender<()> },
 }
 
@@ -108,7 +160,8 @@ impl RolloutRecorder {
         // writes. Using `tokio::fs::File` keeps everything on the async I/O
         // driver instead of blocking the runtime.
         tokio::task::spawn(rollout_writer(
-            tokio::fs::File::from_std(file),
+            session_stores(config, file),
+            session_id,
             rx,
             Some(SessionMeta {
                 timestamp,
@@ -157,62 +210,27 @@ impl RolloutRecorder {
             .map_err(|e| IoError::other(format!("failed to queue rollout state: {e}")))
     }
 
+    pub(crate) async fn record_branch(&self, record: BranchRecord) -> std::io::Result<()> {
+        self.tx
+            .send(RolloutCmd::Branch(record))
+            .await
+            .map_err(|e| IoError::other(format!("failed to queue rollout branch: {e}")))
+    }
+
+    pub(crate) async fn record_history(&self, entry: HistoryEntry) -> std::io::Result<()> {
+        self.tx
+            .send(RolloutCmd::History(entry))
+            .await
+            .map_err(|e| IoError::other(format!("failed to queue history entry: {e}")))
+    }
+
     pub async fn resume(
+        config: &Config,
         path: &Path,
         cwd: std::path::PathBuf,
     ) -> std::io::Result<(Self, SavedSession)> {
         info!("Resuming rollout from {path:?}");
-        let text = tokio::fs::read_to_string(path).await?;
-        let mut lines = text.lines();
-        let meta_line = lines
-            .next()
-            .ok_or_else(|| IoError::other("empty session file"))?;
-        let session: SessionMeta = serde_json::from_str(meta_line)
-            .map_err(|e| IoError::other(format!("failed to parse session meta: {e}")))?;
-        let mut items = Vec::new();
-        let mut state = SessionStateSnapshot::default();
-
-        for line in lines {
-            if line.trim().is_empty() {
-                continue;
-            }
-            let v: Value = match serde_json::from_str(line) {
-                Ok(v) => v,
-                Err(_) => continue,
-            };
-            if v.get("record_type")
-                .and_then(|rt| rt.as_str())
-                .map(|s| s == "state")
-                .unwrap_or(false)
-            {
-                if let Ok(s) = serde_json::from_value::<SessionStateSnapshot>(v.clone()) {
-                    state = s
-                }
-                continue;
-            }
-            match serde_json::from_value::<ResponseItem>(v.clone()) {
-                Ok(item) => match item {
-                    ResponseItem::Message { .. }
-                    | ResponseItem::LocalShellCall { .. }
-                    | ResponseItem::FunctionCall { .. }
-                    | ResponseItem::FunctionCallOutput { .. }
-                    | ResponseItem::CustomToolCall { .. }
-                    | ResponseItem::CustomToolCallOutput { .. }
-                    | ResponseItem::Reasoning { .. } => items.push(item),
-                    ResponseItem::WebSearchCall { .. } | ResponseItem::Other => {}
-                },
-                Err(e) => {
-                    warn!("failed to parse item: {v:?}, error: {e}");
-                }
-            }
-        }
-
-        let saved = SavedSession {
-            session: session.clone(),
-            items: items.clone(),
-            state: state.clone(),
-            session_id: session.id,
-        };
+        let saved = read_saved_session(path).await?;
 
         let file = std::fs::OpenOptions::new()
             .append(true)
@@ -221,7 +239,8 @@ impl RolloutRecorder {
 
         let (tx, rx) = mpsc::channel::<RolloutCmd>(256);
         tokio::task::spawn(rollout_writer(
-            tokio::fs::File::from_std(file),
+            session_stores(config, file),
+            saved.session_id,
             rx,
             None,
             cwd,
@@ -246,6 +265,157 @@ impl RolloutRecorder {
     }
 }
 
+/// Read and parse the rollout file at `path` without reopening it for writing.
+pub(crate) async fn read_saved_session(path: &Path) -> std::io::Result<SavedSession> {
+    let text = tokio::fs::read_to_string(path).await?;
+    parse_rollout(&text)
+}
+
+fn parse_rollout(text: &str) -> std::io::Result<SavedSession> {
+    let mut lines = text.lines();
+    let meta_line = lines
+        .next()
+        .ok_or_else(|| IoError::other("empty session file"))?;
+    let session: SessionMeta = serde_json::from_str(meta_line)
+        .map_err(|e| IoError::other(format!("failed to parse session meta: {e}")))?;
+    let mut items = Vec::new();
+    let mut state = SessionStateSnapshot::default();
+    let mut branches = ConversationBranches::default();
+
+    for line in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let v: Value = match serde_json::from_str(line) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        match v.get("record_type").and_then(|rt| rt.as_str()) {
+            Some("state") => {
+                if let Ok(s) = serde_json::from_value::<SessionStateSnapshot>(v.clone()) {
+                    state = s
+                }
+                continue;
+            }
+            Some("branch") => {
+                match serde_json::from_value::<BranchRecord>(v.clone()) {
+                    Ok(record) => {
+                        if !branches.replay(&mut items, &record) {
+                            warn!("failed to replay branch record: {v:?}");
+                        }
+                    }
+                    Err(e) => warn!("failed to parse branch record: {v:?}, error: {e}"),
+                }
+                continue;
+            }
+            _ => {}
+        }
+        match serde_json::from_value::<ResponseItem>(v.clone()) {
+            Ok(item) => match item {
+                ResponseItem::Message { .. }
+                | ResponseItem::LocalShellCall { .. }
+                | ResponseItem::FunctionCall { .. }
+                | ResponseItem::FunctionCallOutput { .. }
+                | ResponseItem::CustomToolCall { .. }
+                | ResponseItem::CustomToolCallOutput { .. }
+                | ResponseItem::Reasoning { .. } => items.push(item),
+                ResponseItem::WebSearchCall { .. } | ResponseItem::Other => {}
+            },
+            Err(e) => {
+                warn!("failed to parse item: {v:?}, error: {e}");
+            }
+        }
+    }
+
+    Ok(SavedSession {
+        session_id: session.id,
+        session,
+        items,
+        state,
+        branches,
+    })
+}
+
+/// Locate the rollout file recorded for `session_id` under
+/// `codex_home/sessions`.
+pub fn find_rollout_path(codex_home: &Path, session_id: Uuid) -> std::io::Result<Option<PathBuf>> {
+    let suffix = format!("-{session_id}.jsonl");
+    Ok(rollout_files(&codex_home.join(SESSIONS_SUBDIR))?
+        .into_iter()
+        .find(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(&suffix))
+        }))
+}
+
+/// List the sessions recorded under `codex_home/sessions`, most recent first.
+/// Files that cannot be parsed are skipped.
+pub async fn list_sessions(codex_home: &Path) -> std::io::Result<Vec<SessionSummary>> {
+    let mut summaries = Vec::new();
+    for path in rollout_files(&codex_home.join(SESSIONS_SUBDIR))? {
+        let saved = match read_saved_session(&path).await {
+            Ok(saved) => saved,
+            Err(e) => {
+                warn!("skipping unreadable rollout {path:?}: {e}");
+                continue;
+            }
+        };
+        summaries.push(SessionSummary {
+            id: saved.session_id,
+            timestamp: saved.session.timestamp,
+            cwd: saved.state.cwd,
+            model: saved.state.model,
+            preview: first_user_message(&saved.items),
+            path,
+        });
+    }
+    summaries.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    Ok(summaries)
+}
+
+/// Collect every `rollout-*.jsonl` file below `dir`. A missing directory
+/// simply means no sessions have been recorded yet.
+fn rollout_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(rollout_files(&path)?);
+        } else if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("rollout-") && name.ends_with(".jsonl"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn first_user_message(items: &[ResponseItem]) -> Option<String> {
+    items.iter().find_map(|item| match item {
+        ResponseItem::Message { role, content, .. } if role == "user" => {
+            content.iter().find_map(|c| match c {
+                ContentItem::InputText { text }
+                    if !text.starts_with(USER_INSTRUCTIONS_START)
+                        && !text.starts_with(ENVIRONMENT_CONTEXT_START)
+                        && !text.starts_with(ENVIRONMENT_SNAPSHOT_START) =>
+                {
+                    Some(text.clone())
+                }
+                _ => None,
+            })
+        }
+        _ => None,
+    })
+}
+
 struct LogFileInfo {
     /// Opened file handle to the rollout file.
     file: File,
@@ -291,14 +461,23 @@ fn create_log_file(config: &Config, session_id: Uuid) -> std::io::Result<LogFile
     })
 }
 
+/// The local JSONL files followed by the stores configured in `[storage]`.
+fn session_stores(config: &Config, rollout: File) -> Vec<Arc<dyn ConversationStore>> {
+    let mut stores: Vec<Arc<dyn ConversationStore>> = vec![Arc::new(JsonlStore::new(
+        tokio::fs::File::from_std(rollout),
+        history_filepath(config),
+    ))];
+    stores.extend(open_configured_stores(config));
+    stores
+}
+
 async fn rollout_writer(
-    file: tokio::fs::File,
+    stores: Vec<Arc<dyn ConversationStore>>,
+    session_id: Uuid,
     mut rx: mpsc::Receiver<RolloutCmd>,
     mut meta: Option<SessionMeta>,
     cwd: std::path::PathBuf,
-) -> std::io::Result<()> {
-    let mut writer = JsonlWriter { file };
-
+) {
     // If we have a meta, collect git info asynchronously and write meta first
     if let Some(session_meta) = meta.take() {
         let git_info = collect_git_info(&cwd).await;
@@ -307,28 +486,29 @@ async fn rollout_writer(
             git: git_info,
         };
 
-        // Write the SessionMeta as the first item in the file
-        writer.write_line(&session_meta_with_git).await?;
+        // Write the SessionMeta as the first record of the session
+        append_records(&stores, session_id, vec![to_record(&session_meta_with_git)]).await;
     }
 
     // Process rollout commands
     while let Some(cmd) = rx.recv().await {
         match cmd {
             RolloutCmd::AddItems(items) => {
-                for item in items {
-                    match item {
+                let records = items
+                    .iter()
+                    .filter(|item| match item {
                         ResponseItem::Message { .. }
                         | ResponseItem::LocalShellCall { .. }
                         | ResponseItem::FunctionCall { .. }
                         | ResponseItem::FunctionCallOutput { .. }
                         | ResponseItem::CustomToolCall { .. }
                         | ResponseItem::CustomToolCallOutput { .. }
-                        | ResponseItem::Reasoning { .. } => {
-                            writer.write_line(&item).await?;
-                        }
-                        ResponseItem::WebSearchCall { .. } | ResponseItem::Other => {}
-                    }
-                }
+                        | ResponseItem::Reasoning { .. } => true,
+                        ResponseItem::WebSearchCall { .. } | ResponseItem::Other => false,
+                    })
+                    .map(to_record)
+                    .collect();
+                append_records(&stores, session_id, records).await;
             }
             RolloutCmd::UpdateState(state) => {
                 #[derive(Serialize)]
@@ -337,32 +517,131 @@ async fn rollout_writer(
                     #[serde(flatten)]
                     state: &'a SessionStateSnapshot,
                 }
-                writer
-                    .write_line(&StateLine {
-                        record_type: "state",
-                        state: &state,
-                    })
-                    .await?;
+                let record = to_record(&StateLine {
+                    record_type: "state",
+                    state: &state,
+                });
+                append_records(&stores, session_id, vec![record]).await;
+            }
+            RolloutCmd::Branch(record) => {
+                #[derive(Serialize)]
+                struct BranchLine<'a> {
+                    record_type: &'static str,
+                    #[serde(flatten)]
+                    record: &'a BranchRecord,
+                }
+                let record = to_record(&BranchLine {
+                    record_type: "branch",
+                    record: &record,
+                });
+                append_records(&stores, session_id, vec![record]).await;
+            }
+            RolloutCmd::History(entry) => {
+                let results = futures::future::join_all(
+                    stores.iter().map(|store| store.append_history(&entry)),
+                )
+                .await;
+                for (store, result) in stores.iter().zip(results) {
+                    if let Err(e) = result {
+                        warn!(
+                            "failed to write history entry to {} store: {e}",
+                            store.name()
+                        );
+                    }
+                }
             }
             RolloutCmd::Shutdown { ack } => {
                 let _ = ack.send(());
             }
         }
     }
+}
 
-    Ok(())
+fn to_record(item: &impl Serialize) -> Value {
+    serde_json::to_value(item).unwrap_or_else(|e| {
+        warn!("failed to serialize rollout record: {e}");
+        Value::Null
+    })
 }
 
-struct JsonlWriter {
-    file: tokio::fs::File,
+/// Writes `records` to every store at once. A store that fails is reported
+/// and does not keep the others from recording the session.
+async fn append_records(
+    stores: &[Arc<dyn ConversationStore>],
+    session_id: Uuid,
+    records: Vec<Value>,
+) {
+    let records: Vec<Value> = records.into_iter().filter(|r| !r.is_null()).collect();
+    if records.is_empty() {
+        return;
+    }
+    let results = futures::future::join_all(
+        stores
+            .iter()
+            .map(|store| store.append_records(session_id, &records)),
+    )
+    .await;
+    for (store, result) in stores.iter().zip(results) {
+        if let Err(e) = result {
+            warn!(
+                "failed to write session {session_id} to {} store: {e}",
+                store.name()
+            );
+        }
+    }
 }
 
-impl JsonlWriter {
-    async fn write_line(&mut self, item: &impl serde::Serialize) -> std::io::Result<()> {
-        let mut json = serde_json::to_string(item)?;
-        json.push('\n');
-        let _ = self.file.write_all(json.as_bytes()).await;
-        self.file.flush().await?;
-        Ok(())
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_rollout_keeps_latest_state_snapshot() {
+        let id = Uuid::new_v4();
+        let text = [
+            serde_json::json!({"id": id, "timestamp": "2025-01-01T00:00:00.000Z"}),
+            serde_json::json!({"record_type": "state", "model": "o3"}),
+            serde_json::json!({"type": "message", "role": "user", "content": [{"type": "input_text", "text": "<environment_context>\n</environment_context>"}]}),
+            serde_json::json!({"type": "message", "role": "user", "content": [{"type": "input_text", "text": "fix the bug"}]}),
+            serde_json::json!({"record_type": "state", "model": "gpt-5", "cwd": "/repo"}),
+        ]
+        .iter()
+        .map(|v| format!("{v}\n"))
+        .collect::<String>();
+
+        let saved = parse_rollout(&text).unwrap();
+        assert_eq!(saved.session_id, id);
+        assert_eq!(saved.items.len(), 2);
+        assert_eq!(
+            saved.state,
+            SessionStateSnapshot {
+                cwd: Some(PathBuf::from("/repo")),
+                model: Some("gpt-5".to_string()),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            first_user_message(&saved.items).as_deref(),
+            Some("fix the bug")
+        );
+    }
+
+    #[test]
+    fn find_rollout_path_searches_dated_directories() {
+        let home = tempfile::TempDir::new().unwrap();
+        let id = Uuid::new_v4();
+        let dir = home.path().join(SESSIONS_SUBDIR).join("2025/01/02");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("rollout-2025-01-02T03-04-05-{id}.jsonl"));
+        fs::write(&path, "").unwrap();
+
+        assert_eq!(find_rollout_path(home.path(), id).unwrap(), Some(path));
+        assert_eq!(
+            find_rollout_path(home.path(), Uuid::new_v4()).unwrap(),
+            None
+        );
     }
 }
@@ -6,8 +6,10 @@ use std::path::PathBuf;
 use codex_apply_patch::ApplyPatchAction;
 use codex_apply_patch::ApplyPatchFileChange;
 
+use crate::command_rules::RuleMatch;
+use crate::config_types::CommandRuleDecision;
+use crate::config_types::SandboxBackend;
 use crate::exec::SandboxType;
-use crate::is_safe_command::is_known_safe_command;
 use crate::protocol::AskForApproval;
 use crate::protocol::SandboxPolicy;
 
@@ -23,6 +25,7 @@ pub fn assess_patch_safety(
     policy: AskForApproval,
     sandbox_policy: &SandboxPolicy,
     cwd: &Path,
+    sandbox: Option<SandboxType>,
 ) -> SafetyCheck {
     if action.is_empty() {
         return SafetyCheck::Reject {
@@ -51,7 +54,7 @@ pub fn assess_patch_safety(
         // Only auto‑approve when we can actually enforce a sandbox. Otherwise
         // fall back to asking the user because the patch may touch arbitrary
         // paths outside the project.
-        match get_platform_sandbox() {
+        match sandbox {
             Some(sandbox_type) => SafetyCheck::AutoApprove { sandbox_type },
             None => SafetyCheck::AskUser,
         }
@@ -69,41 +72,63 @@ pub fn assess_patch_safety(
 /// true:
 ///
 /// - the user has explicitly approved the command
-/// - the command is on the "known safe" list
+/// - `rule`, the command rule matching the command, allows it (this includes
+///   the built-in list of known safe commands)
 /// - `DangerFullAccess` was specified and `UnlessTrusted` was not
+///
+/// Otherwise it runs in `sandbox`, the sandbox available for untrusted
+/// commands, or needs approval when there is none. Rules can also require
+/// approval for, or reject, commands that would otherwise be sandboxed.
 pub fn assess_command_safety(
     command: &[String],
     approval_policy: AskForApproval,
     sandbox_policy: &SandboxPolicy,
     approved: &HashSet<Vec<String>>,
     with_escalated_permissions: bool,
+    sandbox: Option<SandboxType>,
+    rule: Option<&RuleMatch>,
 ) -> SafetyCheck {
-    // A command is "trusted" because either:
-    // - it belongs to a set of commands we consider "safe" by default, or
-    // - the user has explicitly approved the command for this session
-    //
-    // Currently, whether a command is "trusted" is a simple boolean, but we
-    // should include more metadata on this command test to indicate whether it
-    // should be run inside a sandbox or not. (This could be something the user
-    // defines as part of `execpolicy`.)
-    //
-    // For example, when `is_known_safe_command(command)` returns `true`, it
-    // would probably be fine to run the command in a sandbox, but when
-    // `approved.contains(command)` is `true`, the user may have approved it for
-    // the session _because_ they know it needs to run outside a sandbox.
-    if is_known_safe_command(command) || approved.contains(command) {
+    // Denied commands are never run, not even after approval.
+    if let Some(rule) = rule
+        && rule.decision == CommandRuleDecision::Deny
+    {
+        return SafetyCheck::Reject {
+            reason: format!("denied by command rule `{}`", rule.rule),
+        };
+    }
+
+    // When the user has approved a command for the session, they may have
+    // done so _because_ they know it needs to run outside a sandbox.
+    if approved.contains(command) {
         return SafetyCheck::AutoApprove {
             sandbox_type: SandboxType::None,
         };
     }
 
-    assess_safety_for_untrusted_command(approval_policy, sandbox_policy, with_escalated_permissions)
+    match rule.map(|rule| rule.decision) {
+        Some(CommandRuleDecision::Allow) => SafetyCheck::AutoApprove {
+            sandbox_type: SandboxType::None,
+        },
+        Some(CommandRuleDecision::Ask) if approval_policy == AskForApproval::Never => {
+            SafetyCheck::Reject {
+                reason: "command rule requires approval, but approvals are disabled".to_string(),
+            }
+        }
+        Some(CommandRuleDecision::Ask) => SafetyCheck::AskUser,
+        Some(CommandRuleDecision::Deny) | None => assess_safety_for_untrusted_command(
+            approval_policy,
+            sandbox_policy,
+            with_escalated_permissions,
+            sandbox,
+        ),
+    }
 }
 
 pub(crate) fn assess_safety_for_untrusted_command(
     approval_policy: AskForApproval,
     sandbox_policy: &SandboxPolicy,
     with_escalated_permissions: bool,
+    sandbox: Option<SandboxType>,
 ) -> SafetyCheck {
     use AskForApproval::*;
     use SandboxPolicy::*;
@@ -124,7 +149,7 @@ pub(crate) fn assess_safety_for_untrusted_command(
             if with_escalated_permissions {
                 SafetyCheck::AskUser
             } else {
-                match get_platform_sandbox() {
+                match sandbox {
                     Some(sandbox_type) => SafetyCheck::AutoApprove { sandbox_type },
                     // Fall back to asking since the command is untrusted and
                     // we do not have a sandbox available
@@ -136,7 +161,7 @@ pub(crate) fn assess_safety_for_untrusted_command(
         | (Never, WorkspaceWrite { .. })
         | (OnFailure, ReadOnly)
         | (OnFailure, WorkspaceWrite { .. }) => {
-            match get_platform_sandbox() {
+            match sandbox {
                 Some(sandbox_type) => SafetyCheck::AutoApprove { sandbox_type },
                 None => {
                     if matches!(approval_policy, OnFailure) {
@@ -169,10 +194,77 @@ pub fn get_platform_sandbox() -> Option<SandboxType> {
     }
 }
 
+/// The sandbox untrusted commands run in under `backend`, if any.
+pub fn get_sandbox_for_backend(backend: SandboxBackend) -> Option<SandboxType> {
+    match backend {
+        SandboxBackend::Platform => get_platform_sandbox(),
+        SandboxBackend::Docker => Some(SandboxType::Docker),
+        SandboxBackend::None => None,
+    }
+}
+
+/// The sandbox patches are applied in under `backend`. Patches are applied by
+/// the Codex executable itself, which is not available inside a container, so
+/// the Docker backend falls back to the platform sandbox for them.
+pub(crate) fn get_patch_sandbox_for_backend(backend: SandboxBackend) -> Option<SandboxType> {
+    match backend {
+        SandboxBackend::Docker => get_platform_sandbox(),
+        backend => get_sandbox_for_backend(backend),
+    }
+}
+
+/// Safety of edits that Codex writes to the files itself instead of running
+/// `apply_patch` in a sandbox. Without a sandbox to fall back on, the edits
+/// only go ahead without approval when every path is writable under
+/// `sandbox_policy`.
+pub(crate) fn assess_file_edit_safety(
+    paths: &[PathBuf],
+    policy: AskForApproval,
+    sandbox_policy: &SandboxPolicy,
+    cwd: &Path,
+) -> SafetyCheck {
+    if paths.is_empty() {
+        return SafetyCheck::Reject {
+            reason: "no files to edit".to_string(),
+        };
+    }
+    if policy == AskForApproval::UnlessTrusted {
+        return SafetyCheck::AskUser;
+    }
+
+    if are_paths_writable(paths.iter().map(PathBuf::as_path), sandbox_policy, cwd) {
+        SafetyCheck::AutoApprove {
+            sandbox_type: SandboxType::None,
+        }
+    } else if policy == AskForApproval::Never {
+        SafetyCheck::Reject {
+            reason: "writing outside of the project; rejected by user approval settings"
+                .to_string(),
+        }
+    } else {
+        SafetyCheck::AskUser
+    }
+}
+
 fn is_write_patch_constrained_to_writable_paths(
     action: &ApplyPatchAction,
     sandbox_policy: &SandboxPolicy,
     cwd: &Path,
+) -> bool {
+    let paths = action.changes().iter().flat_map(|(path, change)| {
+        let dest = match change {
+            ApplyPatchFileChange::Update { move_path, .. } => move_path.as_deref(),
+            ApplyPatchFileChange::Add { .. } | ApplyPatchFileChange::Delete => None,
+        };
+        std::iter::once(path.as_path()).chain(dest)
+    });
+    are_paths_writable(paths, sandbox_policy, cwd)
+}
+
+fn are_paths_writable<'a>(
+    paths: impl IntoIterator<Item = &'a Path>,
+    sandbox_policy: &SandboxPolicy,
+    cwd: &Path,
 ) -> bool {
     // Early‑exit if there are no declared writable roots.
     let writable_roots = match sandbox_policy {
@@ -204,9 +296,9 @@ fn is_write_patch_constrained_to_writable_paths(
     // Determine whether `path` is inside **any** writable root. Both `path`
     // and roots are converted to absolute, normalized forms before the
     // prefix check.
-    let is_path_writable = |p: &PathBuf| {
+    let is_path_writable = |p: &Path| {
         let abs = if p.is_absolute() {
-            p.clone()
+            p.to_path_buf()
         } else {
             cwd.join(p)
         };
@@ -220,27 +312,7 @@ fn is_write_patch_constrained_to_writable_paths(
             .any(|writable_root| writable_root.is_path_writable(&abs))
     };
 
-    for (path, change) in action.changes() {
-        match change {
-            ApplyPatchFileChange::Add { .. } | ApplyPatchFileChange::Delete => {
-                if !is_path_writable(path) {
-                    return false;
-                }
-            }
-            ApplyPatchFileChange::Update { move_path, .. } => {
-                if !is_path_writable(path) {
-                    return false;
-                }
-                if let Some(dest) = move_path
-                    && !is_path_writable(dest)
-                {
-                    return false;
-                }
-            }
-        }
-    }
-
-    true
+    paths.into_iter().all(is_path_writable)
 }
 
 #[cfg(test)]
@@ -313,6 +385,8 @@ mod tests {
             &sandbox_policy,
             &approved,
             request_escalated_privileges,
+            get_platform_sandbox(),
+            None,
         );
 
         assert_eq!(safety_check, SafetyCheck::AskUser);
@@ -332,6 +406,8 @@ mod tests {
             &sandbox_policy,
             &approved,
             request_escalated_privileges,
+            get_platform_sandbox(),
+            None,
         );
 
         let expected = match get_platform_sandbox() {
@@ -340,4 +416,104 @@ mod tests {
         };
         assert_eq!(safety_check, expected);
     }
+
+    #[test]
+    fn untrusted_commands_use_the_configured_backend() {
+        let command = vec!["cargo".to_string(), "build".to_string()];
+        let sandbox_policy = SandboxPolicy::new_workspace_write_policy();
+        let approved: HashSet<Vec<String>> = HashSet::new();
+        let assess = |approval_policy, backend| {
+            assess_command_safety(
+                &command,
+                approval_policy,
+                &sandbox_policy,
+                &approved,
+                false,
+                get_sandbox_for_backend(backend),
+                None,
+            )
+        };
+
+        assert_eq!(
+            assess(AskForApproval::OnRequest, SandboxBackend::Docker),
+            SafetyCheck::AutoApprove {
+                sandbox_type: SandboxType::Docker
+            }
+        );
+        assert_eq!(
+            assess(AskForApproval::OnRequest, SandboxBackend::None),
+            SafetyCheck::AskUser
+        );
+        assert!(matches!(
+            assess(AskForApproval::Never, SandboxBackend::None),
+            SafetyCheck::Reject { .. }
+        ));
+        assert_eq!(
+            assess(AskForApproval::UnlessTrusted, SandboxBackend::Docker),
+            SafetyCheck::AskUser
+        );
+        assert_eq!(
+            get_patch_sandbox_for_backend(SandboxBackend::Docker),
+            get_platform_sandbox()
+        );
+    }
+
+    #[test]
+    fn command_rules_override_sandboxing() {
+        let command = vec!["git".to_string(), "push".to_string()];
+        let sandbox_policy = SandboxPolicy::new_workspace_write_policy();
+        let approved = HashSet::from([command.clone()]);
+        let rule = |decision| RuleMatch {
+            rule: "git push".to_string(),
+            decision,
+        };
+        let assess = |approval_policy, approved: &HashSet<Vec<String>>, rule: &RuleMatch| {
+            assess_command_safety(
+                &command,
+                approval_policy,
+                &sandbox_policy,
+                approved,
+                false,
+                Some(SandboxType::Docker),
+                Some(rule),
+            )
+        };
+
+        assert_eq!(
+            assess(
+                AskForApproval::OnRequest,
+                &approved,
+                &rule(CommandRuleDecision::Deny)
+            ),
+            SafetyCheck::Reject {
+                reason: "denied by command rule `git push`".to_string()
+            }
+        );
+        assert_eq!(
+            assess(
+                AskForApproval::OnRequest,
+                &HashSet::new(),
+                &rule(CommandRuleDecision::Ask)
+            ),
+            SafetyCheck::AskUser
+        );
+        assert!(matches!(
+            assess(
+                AskForApproval::Never,
+                &HashSet::new(),
+                &rule(CommandRuleDecision::Ask)
+            ),
+            SafetyCheck::Reject { .. }
+        ));
+        assert_eq!(
+            assess(
+                AskForApproval::UnlessTrusted,
+                &HashSet::new(),
+                &rule(CommandRuleDecision::Allow)
+            ),
+            SafetyCheck::AutoApprove {
+                sandbox_type: SandboxType::None
+            }
+        );
+    }
 }
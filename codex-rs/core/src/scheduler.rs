@@ -0,0 +1,740 @@
+//! Runs the tasks of the `[scheduler]` table headlessly on their cron
+//! schedules: each run is a fresh conversation, configured with the task's
+//! profile, that is sent the task's prompt, tool pipeline or workflow.
+//!
+//! Every run is appended to a run history in `$CODEX_HOME/scheduler`. The
+//! output of successful runs can be posted to a `[messaging]` channel, and
+//! failed runs are reported to one.
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::bail;
+use chrono::DateTime;
+use chrono::Datelike;
+use chrono::Local;
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
+use chrono::TimeZone;
+use chrono::Timelike;
+use chrono::Utc;
+use codex_login::AuthManager;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+use toml::Value as TomlValue;
+use tracing::info;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::ConversationManager;
+use crate::NewConversation;
+use crate::codex_conversation::CodexConversation;
+use crate::config::Config;
+use crate::config::ConfigOverrides;
+use crate::config_types::ScheduledTaskConfig;
+use crate::config_types::SchedulerConfig;
+use crate::messaging::Messaging;
+use crate::protocol::AskForApproval;
+use crate::protocol::EventMsg;
+use crate::protocol::InputItem;
+use crate::protocol::Op;
+use crate::protocol::ReviewDecision;
+use crate::protocol::TaskCompleteEvent;
+use crate::workflow::load_workflow;
+use crate::workflow::workflows_dir;
+
+/// Most characters of a run's output or error kept in the history and
+/// posted to a channel.
+const MAX_RECORDED_CHARS: usize = 4000;
+
+/// When a cron schedule fires, in local time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    /// Bit 0 is Sunday.
+    days_of_week: u64,
+    /// Both the day of month and the day of week are restricted, so a day
+    /// matching either fires, as in cron.
+    either_day: bool,
+}
+
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAYS_OF_WEEK: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(format!(
+                "`{expression}` does not have the five fields minute, hour, day of month, month and day of week"
+            ));
+        };
+        let mut days_of_week = parse_field(day_of_week, 0, 7, DAYS_OF_WEEK, 0)?;
+        // 7 is Sunday as well.
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59, &[], 0)?,
+            hours: parse_field(hour, 0, 23, &[], 0)?,
+            days_of_month: parse_field(day_of_month, 1, 31, &[], 0)?,
+            months: parse_field(month, 1, 12, MONTHS, 1)?,
+            days_of_week,
+            either_day: !day_of_month.starts_with('*') && !day_of_week.starts_with('*'),
+        })
+    }
+}
+
+/// The values of one field as a bit set: `*`, numbers, names, ranges, steps
+/// and comma-separated lists of those. `names[i]` stands for `i + first_name`.
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    first_name: u32,
+) -> Result<u64, String> {
+    let value = |text: &str| -> Result<u32, String> {
+        let lower = text.to_ascii_lowercase();
+        let value = match names.iter().position(|name| *name == lower) {
+            Some(index) => index as u32 + first_name,
+            None => text
+                .parse()
+                .map_err(|_| format!("`{text}` in `{field}` is not a number"))?,
+        };
+        if (min..=max).contains(&value) {
+            Ok(value)
+        } else {
+            Err(format!(
+                "{value} in `{field}` is not between {min} and {max}"
+            ))
+        }
+    };
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("`{step}` in `{field}` is not a step")),
+            },
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `5/15` means from 5 to the end.
+            None if step > 1 => (value(range)?, max),
+            None => {
+                let value = value(range)?;
+                (value, value)
+            }
+        };
+        if start > end {
+            return Err(format!("`{range}` in `{field}` is an empty range"));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl CronSchedule {
+    /// The first minute after `after` the schedule fires at, if any in the
+    /// next few years.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + chrono::Duration::minutes(1);
+        let limit = time + chrono::Duration::days(5 * 366);
+        while time < limit {
+            let date = time.date();
+            if self.months & (1 << date.month()) == 0 {
+                let (year, month) = match date.month() {
+                    12 => (date.year() + 1, 1),
+                    month => (date.year(), month + 1),
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.fires_on(date) {
+                time = date.succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = time.with_minute(0)? + chrono::Duration::hours(1);
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time += chrono::Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    /// The first local time after `after` the schedule fires at. Times that
+    /// do not exist because of a daylight saving change are skipped.
+    pub fn next_local_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut naive = after.naive_local();
+        loop {
+            naive = self.next_after(naive)?;
+            if let Some(time) = Local.from_local_datetime(&naive).earliest() {
+                return Some(time);
+            }
+        }
+    }
+
+    fn fires_on(&self, date: NaiveDate) -> bool {
+        let day_of_month = self.days_of_month & (1 << date.day()) != 0;
+        let day_of_week = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+        if self.either_day {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Succeeded,
+    Failed,
+    TimedOut,
+}
+
+/// One run of a scheduled task, as kept in the run history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskRun {
+    pub task: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub status: RunStatus,
+    /// The conversation of the run; its rollout has the full transcript.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversation_id: Option<Uuid>,
+    /// The final message of a successful run, shortened.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The run history file of the scheduler.
+pub fn history_path(codex_home: &Path) -> PathBuf {
+    codex_home.join("scheduler").join("history.jsonl")
+}
+
+/// The last `limit` runs in the history at `path`, oldest first.
+pub fn read_history(path: &Path, limit: usize) -> std::io::Result<Vec<TaskRun>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let runs: Vec<TaskRun> = contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    Ok(runs[runs.len().saturating_sub(limit)..].to_vec())
+}
+
+struct ScheduledTask {
+    config: ScheduledTaskConfig,
+    schedule: CronSchedule,
+}
+
+pub struct Scheduler {
+    config: SchedulerConfig,
+    tasks: BTreeMap<String, ScheduledTask>,
+    codex_home: PathBuf,
+    /// `-c` overrides every run's configuration is loaded with.
+    cli_overrides: Vec<(String, TomlValue)>,
+    codex_linux_sandbox_exe: Option<PathBuf>,
+    conversation_manager: ConversationManager,
+    messaging: Option<Messaging>,
+    /// Tasks with a run in progress; a task is not started twice at once.
+    running: Mutex<HashSet<String>>,
+    history_lock: tokio::sync::Mutex<()>,
+}
+
+impl Scheduler {
+    /// Checks the tasks of `config.scheduler`. The configuration of each run
+    /// is loaded again, with `cli_overrides` and the task's profile, so edits
+    /// to `config.toml` apply from the next run.
+    pub fn new(
+        config: &Config,
+        cli_overrides: Vec<(String, TomlValue)>,
+        auth_manager: Arc<AuthManager>,
+    ) -> anyhow::Result<Self> {
+        let scheduler = config
+            .scheduler
+            .clone()
+            .context("no tasks are configured; add them to the [scheduler] table")?;
+        let channels = config
+            .messaging
+            .as_ref()
+            .map(|messaging| &messaging.channels);
+        let mut tasks = BTreeMap::new();
+        for (name, task) in &scheduler.tasks {
+            let schedule = task
+                .schedule
+                .parse::<CronSchedule>()
+                .map_err(|e| anyhow::anyhow!("task `{name}`: {e}"))?;
+            let kinds = [
+                task.prompt.is_some(),
+                !task.pipeline.is_empty(),
+                task.workflow.is_some(),
+            ];
+            if kinds.into_iter().filter(|kind| *kind).count() != 1 {
+                bail!("task `{name}` must have one of a prompt, a pipeline or a workflow");
+            }
+            if let Some(workflow) = &task.workflow {
+                load_workflow(&workflows_dir(&config.codex_home), workflow)
+                    .map_err(|e| anyhow::anyhow!("task `{name}`: {e}"))?;
+            }
+            let notify_channel = task
+                .notify_channel
+                .as_ref()
+                .or(scheduler.notify_channel.as_ref());
+            for channel in [task.report_channel.as_ref(), notify_channel]
+                .into_iter()
+                .flatten()
+            {
+                if !channels.is_some_and(|channels| channels.contains_key(channel)) {
+                    bail!("task `{name}` posts to `{channel}`, which is not a [messaging] channel");
+                }
+            }
+            tasks.insert(
+                name.clone(),
+                ScheduledTask {
+                    config: task.clone(),
+                    schedule,
+                },
+            );
+        }
+        Ok(Self {
+            config: scheduler,
+            tasks,
+            codex_home: config.codex_home.clone(),
+            cli_overrides,
+            codex_linux_sandbox_exe: config.codex_linux_sandbox_exe.clone(),
+            conversation_manager: ConversationManager::new(auth_manager),
+            messaging: config.messaging.clone().map(Messaging::new),
+            running: Mutex::new(HashSet::new()),
+            history_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// The tasks with when they run next; `None` for disabled tasks.
+    pub fn next_runs(&self) -> Vec<(String, Option<DateTime<Local>>)> {
+        let after = Local::now();
+        self.tasks
+            .iter()
+            .map(|(name, task)| {
+                let next = (!task.config.disabled)
+                    .then(|| task.schedule.next_local_after(after))
+                    .flatten();
+                (name.clone(), next)
+            })
+            .collect()
+    }
+
+    pub fn history_path(&self) -> PathBuf {
+        history_path(&self.codex_home)
+    }
+
+    /// Runs the enabled tasks on their schedules until the future is dropped.
+    /// A task still running when it is due again is skipped that time.
+    pub async fn run(self: Arc<Self>) {
+        let mut next: BTreeMap<String, DateTime<Local>> = self
+            .next_runs()
+            .into_iter()
+            .filter_map(|(name, next)| Some((name, next?)))
+            .collect();
+        loop {
+            let Some(due) = next.values().min().copied() else {
+                info!("no scheduled task has a future run");
+                return;
+            };
+            let wait = (due - Local::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+
+            let now = Local::now();
+            let due: Vec<String> = next
+                .iter()
+                .filter(|(_, at)| **at <= now)
+                .map(|(name, _)| name.clone())
+                .collect();
+            for name in due {
+                match self
+                    .tasks
+                    .get(&name)
+                    .and_then(|task| task.schedule.next_local_after(now))
+                {
+                    Some(at) => next.insert(name.clone(), at),
+                    None => next.remove(&name),
+                };
+                let scheduler = Arc::clone(&self);
+                tokio::spawn(async move {
+                    if let Err(e) = scheduler.run_task(&name).await {
+                        warn!("scheduled task `{name}` was not run: {e:#}");
+                    }
+                });
+            }
+        }
+    }
+
+    /// Runs `name` now, records the run and posts its report or failure
+    /// notice. Fails when the task does not exist or is already running.
+    pub async fn run_task(&self, name: &str) -> anyhow::Result<TaskRun> {
+        let task = self
+            .tasks
+            .get(name)
+            .with_context(|| format!("no task is named `{name}`"))?;
+        if !self.running_tasks().insert(name.to_string()) {
+            bail!("task `{name}` is still running");
+        }
+        info!("running scheduled task `{name}`");
+        let started_at = Utc::now();
+        let (conversation_id, result) = self.execute(&task.config).await;
+        self.running_tasks().remove(name);
+
+        let (status, output, error) = match result {
+            Ok(output) => (RunStatus::Succeeded, Some(shorten(&output)), None),
+            Err((status, error)) => (status, None, Some(shorten(&error))),
+        };
+        let run = TaskRun {
+            task: name.to_string(),
+            started_at,
+            finished_at: Utc::now(),
+            status,
+            conversation_id,
+            output,
+            error,
+        };
+        if let Err(e) = self.record(&run).await {
+            warn!("failed to record the run of `{name}`: {e}");
+        }
+        self.notify(&task.config, &run).await;
+        Ok(run)
+    }
+
+    fn running_tasks(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.running
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Runs the task in a new conversation and returns the final message, or
+    /// why the run failed.
+    async fn execute(
+        &self,
+        task: &ScheduledTaskConfig,
+    ) -> (Option<Uuid>, Result<String, (RunStatus, String)>) {
+        let failed = |e: String| (None, Err((RunStatus::Failed, e)));
+        let overrides = ConfigOverrides {
+            config_profile: task.profile.clone(),
+            cwd: task.cwd.clone(),
+            codex_linux_sandbox_exe: self.codex_linux_sandbox_exe.clone(),
+            ..Default::default()
+        };
+        let mut config =
+            match Config::load_with_cli_overrides(self.cli_overrides.clone(), overrides) {
+                Ok(config) => config,
+                Err(e) => return failed(format!("failed to load the configuration: {e}")),
+            };
+        // Nobody is there to answer approval requests, so they are rejected
+        // up front unless the task approves them or they go over HTTP.
+        if !task.auto_approve && config.approval_http.is_none() {
+            config.approval_policy = AskForApproval::Never;
+        }
+        let NewConversation {
+            conversation_id,
+            conversation,
+            ..
+        } = match self.conversation_manager.new_conversation(config).await {
+            Ok(conversation) => conversation,
+            Err(e) => return failed(format!("failed to start a conversation: {e}")),
+        };
+
+        let op = match (&task.prompt, &task.workflow) {
+            (Some(prompt), _) => Op::UserInput {
+                items: vec![InputItem::Text {
+                    text: prompt.clone(),
+                }],
+            },
+            (None, Some(workflow)) => Op::RunWorkflow {
+                name: workflow.clone(),
+                inputs: task.inputs.clone(),
+            },
+            (None, None) => Op::RunToolPipeline {
+                steps: task.pipeline.clone(),
+            },
+        };
+        let timeout = Duration::from_secs(task.timeout_secs.unwrap_or(self.config.timeout_secs));
+        let result = match tokio::time::timeout(
+            timeout,
+            drive(&conversation, op, task.auto_approve),
+        )
+        .await
+        {
+            Ok(Ok(result)) => result.map_err(|e| (RunStatus::Failed, e)),
+            Ok(Err(e)) => Err((RunStatus::Failed, e.to_string())),
+            Err(_) => {
+                conversation.submit(Op::Interrupt).await.ok();
+                Err((
+                    RunStatus::TimedOut,
+                    format!("the run did not finish within {}s", timeout.as_secs()),
+                ))
+            }
+        };
+        conversation.submit(Op::Shutdown).await.ok();
+        self.conversation_manager
+            .remove_conversation(conversation_id)
+            .await;
+        (Some(conversation_id), result)
+    }
+
+    /// Appends `run` to the history, dropping the oldest runs beyond
+    /// `max_history`.
+    async fn record(&self, run: &TaskRun) -> std::io::Result<()> {
+        let _guard = self.history_lock.lock().await;
+        let path = self.history_path();
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut lines: Vec<String> = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => contents.lines().map(str::to_string).collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        lines.push(serde_json::to_string(run)?);
+        let keep = lines.len().saturating_sub(self.config.max_history.max(1));
+        let mut contents = lines[keep..].join("\n");
+        contents.push('\n');
+        tokio::fs::write(&path, contents).await
+    }
+
+    /// Posts the output of a successful run to the task's report channel, or
+    /// the error of a failed one to its notify channel.
+    async fn notify(&self, task: &ScheduledTaskConfig, run: &TaskRun) {
+        let (channel, title, text) = match (&run.status, &run.output) {
+            (RunStatus::Succeeded, output) => (
+                task.report_channel.as_ref(),
+                format!("Scheduled task `{}`", run.task),
+                output.clone().unwrap_or_default(),
+            ),
+            (status, _) => (
+                task.notify_channel
+                    .as_ref()
+                    .or(self.config.notify_channel.as_ref()),
+                format!(
+                    "Scheduled task `{}` {}",
+                    run.task,
+                    match status {
+                        RunStatus::TimedOut => "timed out",
+                        _ => "failed",
+                    }
+                ),
+                run.error.clone().unwrap_or_default(),
+            ),
+        };
+        let (Some(channel), Some(messaging)) = (channel, &self.messaging) else {
+            return;
+        };
+        let mut facts = vec![json!({
+            "title": "Started",
+            "value": run.started_at.with_timezone(&Local).format("%Y-%m-%d %H:%M %Z").to_string(),
+        })];
+        if let Some(conversation_id) = run.conversation_id {
+            facts.push(json!({ "title": "Conversation", "value": conversation_id.to_string() }));
+        }
+        let arguments = json!({
+            "channel": channel,
+            "title": title,
+            "text": if text.is_empty() { "No output." } else { text.as_str() },
+            "facts": facts,
+        });
+        let posted = match messaging.prepare(&arguments.to_string()) {
+            Ok(message) => messaging.post(&message).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = posted {
+            warn!(
+                "failed to post the run of `{}` to `{channel}`: {e}",
+                run.task
+            );
+        }
+    }
+}
+
+/// Sends `op` and answers the approval requests of the run until it
+/// completes. Returns the final message, or the errors the run reported.
+async fn drive(
+    conversation: &CodexConversation,
+    op: Op,
+    auto_approve: bool,
+) -> crate::error::Result<Result<String, String>> {
+    let sub_id = conversation.submit(op).await?;
+    let decision = if auto_approve {
+        ReviewDecision::Approved
+    } else {
+        ReviewDecision::Denied
+    };
+    let mut errors = Vec::new();
+    loop {
+        let event = conversation.next_event().await?;
+        match event.msg {
+            EventMsg::ExecApprovalRequest(_) => {
+                conversation
+                    .submit(Op::ExecApproval {
+                        id: event.id,
+                        decision,
+                    })
+                    .await?;
+            }
+            EventMsg::ApplyPatchApprovalRequest(_) => {
+                conversation
+                    .submit(Op::PatchApproval {
+                        id: event.id,
+                        decision,
+                    })
+                    .await?;
+            }
+            // Going over a budget is never approved headlessly.
+            EventMsg::BudgetApprovalRequest(_) => {
+                conversation
+                    .submit(Op::BudgetApproval {
+                        id: event.id,
+                        decision: ReviewDecision::Denied,
+                    })
+                    .await?;
+            }
+            EventMsg::Error(error) if event.id == sub_id => errors.push(error.message),
+            EventMsg::TurnAborted(_) if event.id == sub_id => {
+                errors.push("the run was aborted".to_string());
+                return Ok(Err(errors.join("\n")));
+            }
+            EventMsg::TaskComplete(TaskCompleteEvent { last_agent_message })
+                if event.id == sub_id =>
+            {
+                return Ok(if errors.is_empty() {
+                    Ok(last_agent_message.unwrap_or_default())
+                } else {
+                    Err(errors.join("\n"))
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+fn shorten(text: &str) -> String {
+    match text.char_indices().nth(MAX_RECORDED_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn at(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn next(expression: &str, after: &str) -> String {
+        let schedule: CronSchedule = expression.parse().unwrap();
+        schedule
+            .next_after(at(after))
+            .unwrap()
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    }
+
+    #[test]
+    fn finds_the_next_run() {
+        assert_eq!(next("*/15 * * * *", "2025-03-01 10:07"), "2025-03-01 10:15");
+        assert_eq!(next("0 2 * * *", "2025-03-01 02:00"), "2025-03-02 02:00");
+        // 2025-03-01 is a Saturday.
+        assert_eq!(
+            next("30 6 * * mon-fri", "2025-03-01 10:00"),
+            "2025-03-03 06:30"
+        );
+        assert_eq!(next("0 0 31 * *", "2025-04-01 00:00"), "2025-05-31 00:00");
+        assert_eq!(next("0 9 29 feb *", "2025-01-01 00:00"), "2028-02-29 09:00");
+        // Day of month or day of week when both are restricted.
+        assert_eq!(next("0 0 15 * 7", "2025-03-01 00:00"), "2025-03-02 00:00");
+        assert_eq!(next("@monthly", "2025-12-15 08:00"), "2026-01-01 00:00");
+        assert_eq!(
+            next("5/20 8-9 * * *", "2025-03-01 08:50"),
+            "2025-03-01 09:05"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        for (expression, error) in [
+            (
+                "0 2 * *",
+                "`0 2 * *` does not have the five fields minute, hour, day of month, month and day of week",
+            ),
+            ("60 * * * *", "60 in `60` is not between 0 and 59"),
+            ("0 * * foo *", "`foo` in `foo` is not a number"),
+            ("*/0 * * * *", "`0` in `*/0` is not a step"),
+            ("0 5-1 * * *", "`5-1` in `5-1` is an empty range"),
+        ] {
+            assert_eq!(
+                expression.parse::<CronSchedule>(),
+                Err(error.to_string()),
+                "{expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn reads_the_last_runs_of_the_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = history_path(dir.path());
+        assert_eq!(read_history(&path, 10).unwrap(), Vec::new());
+
+        let run = |task: &str| TaskRun {
+            task: task.to_string(),
+            started_at: DateTime::from_timestamp(1_740_000_000, 0).unwrap(),
+            finished_at: DateTime::from_timestamp(1_740_000_060, 0).unwrap(),
+            status: RunStatus::Failed,
+            conversation_id: None,
+            output: None,
+            error: Some("kusto_query failed".to_string()),
+        };
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let lines: Vec<String> = ["a", "b", "c"]
+            .into_iter()
+            .map(|task| serde_json::to_string(&run(task)).unwrap())
+            .collect();
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        assert_eq!(read_history(&path, 2).unwrap(), vec![run("b"), run("c")]);
+    }
+}
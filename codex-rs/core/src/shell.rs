@@ -65,28 +65,27 @@ impl Shell {
                     };
                 }
 
-                // Not a bash command. If model did not generate a PowerShell command,
-                // turn it into a PowerShell command.
-                let first = command.first().map(String::as_str);
-                if first != Some(ps.exe.as_str()) {
-                    // TODO (CODEX_2900): Handle escaping newlines.
-                    if command.iter().any(|a| a.contains('\n') || a.contains('\r')) {
-                        return Some(command);
-                    }
+                // Model generated a PowerShell or cmd.exe command. Run it with
+                // the shell it was written for.
+                if command
+                    .first()
+                    .is_some_and(|program| is_windows_shell(program))
+                {
+                    return Some(command);
+                }
 
-                    let joined = shlex::try_join(command.iter().map(|s| s.as_str())).ok();
-                    return joined.map(|arg| {
-                        vec![
-                            ps.exe.clone(),
-                            "-NoProfile".to_string(),
-                            "-Command".to_string(),
-                            arg,
-                        ]
-                    });
+                // TODO (CODEX_2900): Handle escaping newlines.
+                if command.iter().any(|a| a.contains('\n') || a.contains('\r')) {
+                    return Some(command);
                 }
 
-                // Model generated a PowerShell command. Run it.
-                Some(command)
+                // Otherwise turn it into a PowerShell command.
+                Some(vec![
+                    ps.exe.clone(),
+                    "-NoProfile".to_string(),
+                    "-Command".to_string(),
+                    join_powershell(&command),
+                ])
             }
             Shell::Unknown => None,
         }
@@ -116,6 +115,50 @@ fn strip_bash_lc(command: &Vec<String>) -> Option<String> {
     }
 }
 
+/// Whether `program` is PowerShell or cmd.exe, by name or by path.
+fn is_windows_shell(program: &str) -> bool {
+    let name = program
+        .rsplit(['\\', '/'])
+        .next()
+        .unwrap_or(program)
+        .to_ascii_lowercase();
+    let name = name.strip_suffix(".exe").unwrap_or(&name);
+    matches!(name, "pwsh" | "powershell" | "cmd")
+}
+
+/// `command` as a PowerShell command line. Arguments are single-quoted when
+/// they need to be, and a quoted program is run with the `&` call operator
+/// since PowerShell would otherwise read it as a string.
+fn join_powershell(command: &[String]) -> String {
+    let mut joined = command
+        .iter()
+        .map(|arg| quote_powershell(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if joined.starts_with('\'') {
+        joined.insert_str(0, "& ");
+    }
+    joined
+}
+
+fn quote_powershell(arg: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_./\\:=+".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_plain) {
+        return arg.to_string();
+    }
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('\'');
+    for c in arg.chars() {
+        // PowerShell takes typographic single quotes for `'` too.
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
 #[cfg(target_os = "macos")]
 pub async fn default_user_shell() -> Shell {
     use tokio::process::Command;
@@ -317,10 +360,12 @@ mod tests {
                     )]),
                     with_escalated_permissions: None,
                     justification: None,
+                    limits: Default::default(),
                 },
                 SandboxType::None,
                 &SandboxPolicy::DangerFullAccess,
                 &None,
+                &Default::default(),
                 None,
             )
             .await
@@ -430,3 +475,59 @@ mod tests_windows {
         }
     }
 }
+
+#[cfg(test)]
+mod tests_powershell_quoting {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn invocation(command: &[&str]) -> Option<Vec<String>> {
+        Shell::PowerShell(PowerShellConfig {
+            exe: "pwsh.exe".to_string(),
+            bash_exe_fallback: None,
+        })
+        .format_default_shell_invocation(command.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn quotes_arguments_for_powershell() {
+        let cases = [
+            (vec!["echo", "hello"], "echo hello"),
+            (
+                vec!["git", "commit", "-m", "it's done"],
+                "git commit -m 'it''s done'",
+            ),
+            (vec!["rg", "--glob", "*.rs", "$x"], "rg --glob '*.rs' '$x'"),
+            (
+                vec![r"C:\Program Files\Git\bin\git.exe", "status"],
+                r"& 'C:\Program Files\Git\bin\git.exe' status",
+            ),
+            (vec!["echo", ""], "echo ''"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                invocation(&input),
+                Some(vec![
+                    "pwsh.exe".to_string(),
+                    "-NoProfile".to_string(),
+                    "-Command".to_string(),
+                    expected.to_string(),
+                ]),
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn runs_commands_for_other_windows_shells_as_they_are() {
+        for input in [
+            vec!["cmd", "/c", "dir /b"],
+            vec!["CMD.EXE", "/c", "ver"],
+            vec!["powershell.exe", "-Command", "Get-ChildItem"],
+            vec![r"C:\Program Files\PowerShell\7\pwsh.exe", "-c", "ls"],
+        ] {
+            let expected = input.iter().map(|s| s.to_string()).collect();
+            assert_eq!(invocation(&input), Some(expected), "input: {input:?}");
+        }
+    }
+}
@@ -52,7 +52,7 @@ pub(crate) async fn spawn_child_async(
     #[cfg(unix)]
     cmd.arg0(arg0.map_or_else(|| program.to_string_lossy().to_string(), String::from));
     cmd.args(args);
-    cmd.current_dir(cwd);
+    cmd.current_dir(crate::windows_path::normalize_path(&cwd));
     cmd.env_clear();
     cmd.envs(env);
 
@@ -85,6 +85,21 @@ pub(crate) async fn spawn_child_async(
         });
     }
 
+    // Run shell tool commands in their own process group so that a timeout or
+    // an exceeded limit can kill everything the command started, not just the
+    // shell itself.
+    #[cfg(unix)]
+    if matches!(stdio_policy, StdioPolicy::RedirectForShellTool) {
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setpgid(0, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
     match stdio_policy {
         StdioPolicy::RedirectForShellTool => {
             // Do not create a file descriptor for stdin because otherwise some
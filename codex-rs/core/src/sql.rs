@@ -0,0 +1,767 @@
+//! Read-only tools for SQL Server and Azure SQL databases: list databases
+//! and tables, describe a table and run a parameterized query, e.g. to
+//! check a database after a restore.
+//!
+//! Queries run through `sqlcmd`, which talks TDS to the server, against the
+//! connection profiles of the `[sql]` table. A query must be a single
+//! `SELECT` (optionally after `WITH`) with no statement that changes data or
+//! settings. It is passed to `sp_executesql` with its parameters as typed
+//! literals, under `SET ROWCOUNT`, inside a transaction that is rolled back.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::process::Stdio;
+use std::time::Duration;
+
+use codex_protocol::models::FunctionCallOutputPayload;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::process::Command;
+
+use crate::config_types::SqlAuthentication;
+use crate::config_types::SqlConfig;
+use crate::config_types::SqlProfileConfig;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+
+pub(crate) const SQL_LIST_DATABASES_TOOL_NAME: &str = "sql_list_databases";
+pub(crate) const SQL_LIST_TABLES_TOOL_NAME: &str = "sql_list_tables";
+pub(crate) const SQL_DESCRIBE_TABLE_TOOL_NAME: &str = "sql_describe_table";
+pub(crate) const SQL_QUERY_TOOL_NAME: &str = "sql_query";
+
+/// Column separator `sqlcmd` is asked to print.
+const SEPARATOR: char = '\t';
+
+/// Longest cell value shown, in characters.
+const MAX_CELL_CHARS: usize = 1000;
+
+/// Extra time `sqlcmd` gets on top of the query timeout to log in and exit.
+const PROCESS_GRACE: Duration = Duration::from_secs(30);
+
+/// Words that change data, schema, settings or the session, or reach outside
+/// the database. A read-only query may not contain any of them.
+const FORBIDDEN_WORDS: &[&str] = &[
+    "ALTER",
+    "BACKUP",
+    "BEGIN",
+    "BULK",
+    "COMMIT",
+    "CREATE",
+    "DBCC",
+    "DELETE",
+    "DENY",
+    "DROP",
+    "EXEC",
+    "EXECUTE",
+    "GO",
+    "GRANT",
+    "INSERT",
+    "INTO",
+    "KILL",
+    "MERGE",
+    "OPENDATASOURCE",
+    "OPENQUERY",
+    "OPENROWSET",
+    "RECONFIGURE",
+    "RESTORE",
+    "REVOKE",
+    "ROLLBACK",
+    "SAVE",
+    "SET",
+    "SHUTDOWN",
+    "TRUNCATE",
+    "UPDATE",
+    "UPDATETEXT",
+    "USE",
+    "WAITFOR",
+    "WRITETEXT",
+];
+
+#[derive(Debug, Deserialize)]
+struct ProfileParams {
+    profile: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListTablesParams {
+    profile: String,
+    #[serde(default)]
+    schema: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DescribeTableParams {
+    profile: String,
+    table: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryParams {
+    profile: String,
+    query: String,
+    #[serde(default)]
+    parameters: BTreeMap<String, Value>,
+    #[serde(default)]
+    max_rows: Option<usize>,
+}
+
+/// One result set printed by `sqlcmd`.
+#[derive(Debug, PartialEq)]
+struct ResultSet {
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+pub(crate) struct Sql {
+    config: SqlConfig,
+}
+
+impl Sql {
+    pub(crate) fn new(config: SqlConfig) -> Self {
+        Self { config }
+    }
+
+    pub(crate) fn profiles(&self) -> &HashMap<String, SqlProfileConfig> {
+        &self.config.profiles
+    }
+
+    pub(crate) async fn handle_function_call(
+        &self,
+        name: &str,
+        arguments: &str,
+    ) -> FunctionCallOutputPayload {
+        let result = match name {
+            SQL_LIST_DATABASES_TOOL_NAME => match parse::<ProfileParams>(arguments) {
+                Ok(params) => {
+                    self.run_internal(
+                        &params.profile,
+                        "SELECT name, state_desc, recovery_model_desc, create_date \
+                         FROM sys.databases ORDER BY name",
+                        &BTreeMap::new(),
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            },
+            SQL_LIST_TABLES_TOOL_NAME => match parse::<ListTablesParams>(arguments) {
+                Ok(params) => {
+                    let mut parameters = BTreeMap::new();
+                    let mut query = "SELECT TABLE_SCHEMA, TABLE_NAME, TABLE_TYPE \
+                                     FROM INFORMATION_SCHEMA.TABLES"
+                        .to_string();
+                    if let Some(schema) = params.schema {
+                        query.push_str(" WHERE TABLE_SCHEMA = @schema");
+                        parameters.insert("schema".to_string(), Value::String(schema));
+                    }
+                    query.push_str(" ORDER BY TABLE_SCHEMA, TABLE_NAME");
+                    self.run_internal(&params.profile, &query, &parameters)
+                        .await
+                }
+                Err(e) => Err(e),
+            },
+            SQL_DESCRIBE_TABLE_TOOL_NAME => match parse::<DescribeTableParams>(arguments) {
+                Ok(params) => {
+                    let (schema, table) = match params.table.split_once('.') {
+                        Some((schema, table)) => (schema, table),
+                        None => ("dbo", params.table.as_str()),
+                    };
+                    let parameters = BTreeMap::from([
+                        ("schema".to_string(), Value::String(unquote(schema))),
+                        ("table".to_string(), Value::String(unquote(table))),
+                    ]);
+                    self.run_internal(
+                        &params.profile,
+                        "SELECT c.COLUMN_NAME, c.DATA_TYPE, c.CHARACTER_MAXIMUM_LENGTH, \
+                         c.IS_NULLABLE, c.COLUMN_DEFAULT, \
+                         CASE WHEN k.COLUMN_NAME IS NULL THEN '' ELSE 'PK' END AS [KEY] \
+                         FROM INFORMATION_SCHEMA.COLUMNS c \
+                         LEFT JOIN (SELECT u.TABLE_SCHEMA, u.TABLE_NAME, u.COLUMN_NAME \
+                         FROM INFORMATION_SCHEMA.KEY_COLUMN_USAGE u \
+                         JOIN INFORMATION_SCHEMA.TABLE_CONSTRAINTS t \
+                         ON t.CONSTRAINT_NAME = u.CONSTRAINT_NAME \
+                         AND t.TABLE_SCHEMA = u.TABLE_SCHEMA \
+                         AND t.CONSTRAINT_TYPE = 'PRIMARY KEY') k \
+                         ON k.TABLE_SCHEMA = c.TABLE_SCHEMA AND k.TABLE_NAME = c.TABLE_NAME \
+                         AND k.COLUMN_NAME = c.COLUMN_NAME \
+                         WHERE c.TABLE_SCHEMA = @schema AND c.TABLE_NAME = @table \
+                         ORDER BY c.ORDINAL_POSITION",
+                        &parameters,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            },
+            SQL_QUERY_TOOL_NAME => match parse::<QueryParams>(arguments) {
+                Ok(params) => self.query(params).await,
+                Err(e) => Err(e),
+            },
+            _ => Err(format!("unknown SQL tool `{name}`")),
+        };
+        match result {
+            Ok(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(true),
+            },
+            Err(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(false),
+            },
+        }
+    }
+
+    async fn query(&self, params: QueryParams) -> Result<String, String> {
+        check_read_only(&params.query)?;
+        let max_rows = params
+            .max_rows
+            .unwrap_or(self.config.max_rows)
+            .clamp(1, self.config.max_rows);
+        let batch = build_batch(&params.query, &params.parameters, max_rows)?;
+        let output = self.run(&params.profile, &batch).await?;
+        Ok(format_result_sets(&parse_output(&output), max_rows))
+    }
+
+    /// Runs one of the tools' own queries, with the full row limit.
+    async fn run_internal(
+        &self,
+        profile: &str,
+        query: &str,
+        parameters: &BTreeMap<String, Value>,
+    ) -> Result<String, String> {
+        let batch = build_batch(query, parameters, self.config.max_rows)?;
+        let output = self.run(profile, &batch).await?;
+        Ok(format_result_sets(
+            &parse_output(&output),
+            self.config.max_rows,
+        ))
+    }
+
+    /// Runs `batch` with `sqlcmd` against `profile` and returns its output.
+    async fn run(&self, profile_name: &str, batch: &str) -> Result<String, String> {
+        let profile = self.config.profiles.get(profile_name).ok_or_else(|| {
+            let mut names: Vec<&str> = self.config.profiles.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            format!(
+                "unknown profile `{profile_name}`; configured profiles: {}",
+                names.join(", ")
+            )
+        })?;
+        let mut command = Command::new(&self.config.sqlcmd_command);
+        command.args(sqlcmd_args(profile, self.config.query_timeout_secs));
+        if profile.authentication == SqlAuthentication::Sql {
+            if profile.user.is_none() {
+                return Err(format!(
+                    "profile `{profile_name}` uses SQL authentication but sets no user"
+                ));
+            }
+            let var = &profile.password_env_var;
+            let password = std::env::var(var)
+                .ok()
+                .filter(|password| !password.is_empty())
+                .ok_or_else(|| format!("the {var} environment variable is not set"))?;
+            command.env("SQLCMDPASSWORD", password);
+        }
+        let child = command
+            .arg("-Q")
+            .arg(batch)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("failed to start `{}`: {e}", self.config.sqlcmd_command))?;
+        let timeout = Duration::from_secs(self.config.query_timeout_secs) + PROCESS_GRACE;
+        let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(output) => output.map_err(|e| e.to_string())?,
+            Err(_) => return Err(format!("sqlcmd timed out after {}s", timeout.as_secs())),
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        if output.status.success() {
+            return Ok(stdout);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(format!(
+            "query failed on `{profile_name}`:\n{}",
+            format!("{}\n{}", stdout.trim(), stderr.trim()).trim()
+        ))
+    }
+}
+
+fn parse<T: DeserializeOwned>(arguments: &str) -> Result<T, String> {
+    serde_json::from_str(arguments).map_err(|e| format!("failed to parse function arguments: {e}"))
+}
+
+/// Arguments that connect `sqlcmd` to `profile`, print tab-separated output
+/// without padding and disable `sqlcmd` commands and variables.
+fn sqlcmd_args(profile: &SqlProfileConfig, query_timeout_secs: u64) -> Vec<String> {
+    let mut args: Vec<String> = vec![
+        "-S".into(),
+        profile.server.clone(),
+        "-d".into(),
+        profile.database.clone(),
+        // Exit with an error code when the query fails.
+        "-b".into(),
+        // Trim padding, do not wrap lines, separate columns with tabs.
+        "-W".into(),
+        "-w".into(),
+        "65535".into(),
+        "-s".into(),
+        SEPARATOR.to_string(),
+        // No `:!!` shell commands or `$(var)` substitution.
+        "-X".into(),
+        "-x".into(),
+        "-t".into(),
+        query_timeout_secs.to_string(),
+        "-K".into(),
+        "ReadOnly".into(),
+    ];
+    match profile.authentication {
+        SqlAuthentication::Sql => {
+            args.push("-U".into());
+            args.push(profile.user.clone().unwrap_or_default());
+        }
+        SqlAuthentication::Integrated => args.push("-E".into()),
+        SqlAuthentication::AzureAd => {
+            args.push("--authentication-method".into());
+            args.push("ActiveDirectoryDefault".into());
+        }
+    }
+    if profile.trust_server_certificate {
+        args.push("-C".into());
+    }
+    args
+}
+
+/// Rejects anything but a query that only reads.
+fn check_read_only(query: &str) -> Result<(), String> {
+    for line in query.lines() {
+        let line = line.trim_start();
+        if line.starts_with(':') || line.starts_with("!!") {
+            return Err("sqlcmd commands are not allowed".to_string());
+        }
+    }
+    let words = sql_words(query)?;
+    match words.first().map(String::as_str) {
+        Some("SELECT" | "WITH") => {}
+        _ => {
+            return Err(
+                "only SELECT queries, optionally starting with WITH, are allowed".to_string(),
+            );
+        }
+    }
+    if let Some(word) = words
+        .iter()
+        .find(|word| FORBIDDEN_WORDS.contains(&word.as_str()))
+    {
+        return Err(format!("`{word}` is not allowed in a read-only query"));
+    }
+    if let Some(word) = words
+        .iter()
+        .find(|word| word.starts_with("SP_") || word.starts_with("XP_"))
+    {
+        return Err(format!(
+            "stored procedures such as `{word}` are not allowed in a read-only query"
+        ));
+    }
+    Ok(())
+}
+
+/// The upper-cased keywords and identifiers of `query`, leaving out comments,
+/// string literals and quoted identifiers.
+fn sql_words(query: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = query.chars().peekable();
+    let mut word = String::new();
+    while let Some(c) = chars.next() {
+        if c.is_alphanumeric() || matches!(c, '_' | '@' | '#' | '$') {
+            word.extend(c.to_uppercase());
+            continue;
+        }
+        if !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        match c {
+            '-' if chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if previous == '*' => break,
+                        Some(c) => previous = c,
+                        None => return Err("unterminated comment".to_string()),
+                    }
+                }
+            }
+            '\'' | '"' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                loop {
+                    match chars.next() {
+                        // A doubled closing character escapes itself.
+                        Some(c) if c == close && chars.peek() == Some(&close) => {
+                            chars.next();
+                        }
+                        Some(c) if c == close => break,
+                        Some(_) => {}
+                        None => return Err("unterminated string or identifier".to_string()),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// The batch `sqlcmd` runs: `query` through `sp_executesql` with
+/// `parameters`, returning at most one row more than `max_rows` so that cut
+/// results can be told apart, in a transaction that is rolled back.
+fn build_batch(
+    query: &str,
+    parameters: &BTreeMap<String, Value>,
+    max_rows: usize,
+) -> Result<String, String> {
+    let mut declarations = Vec::new();
+    let mut values = Vec::new();
+    for (name, value) in parameters {
+        let name = name.strip_prefix('@').unwrap_or(name);
+        if name.is_empty()
+            || name.starts_with(|c: char| c.is_ascii_digit())
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(format!("`{name}` is not a valid parameter name"));
+        }
+        let (sql_type, literal) = sql_literal(value).ok_or_else(|| {
+            format!("parameter `{name}` must be a string, number, boolean or null")
+        })?;
+        declarations.push(format!("@{name} {sql_type}"));
+        values.push(format!("@{name} = {literal}"));
+    }
+    let mut call = format!("EXEC sp_executesql {}", string_literal(query));
+    if !declarations.is_empty() {
+        let _ = write!(
+            call,
+            ", {}, {}",
+            string_literal(&declarations.join(", ")),
+            values.join(", ")
+        );
+    }
+    Ok(format!(
+        "SET NOCOUNT ON;\n\
+         SET XACT_ABORT ON;\n\
+         SET ROWCOUNT {};\n\
+         BEGIN TRANSACTION;\n\
+         {call};\n\
+         IF @@TRANCOUNT > 0 ROLLBACK TRANSACTION;",
+        max_rows + 1
+    ))
+}
+
+/// The type and T-SQL literal of a JSON parameter value.
+fn sql_literal(value: &Value) -> Option<(&'static str, String)> {
+    Some(match value {
+        Value::Null => ("nvarchar(max)", "NULL".to_string()),
+        Value::Bool(b) => ("bit", (if *b { "1" } else { "0" }).to_string()),
+        Value::Number(n) if n.is_i64() || n.is_u64() => ("bigint", n.to_string()),
+        Value::Number(n) => ("float", n.to_string()),
+        Value::String(s) => ("nvarchar(max)", string_literal(s)),
+        Value::Array(_) | Value::Object(_) => return None,
+    })
+}
+
+fn string_literal(s: &str) -> String {
+    format!("N'{}'", s.replace('\'', "''"))
+}
+
+/// `name` without `[...]` or `"..."` quoting.
+fn unquote(name: &str) -> String {
+    let name = name.trim();
+    name.strip_prefix('[')
+        .and_then(|name| name.strip_suffix(']'))
+        .or_else(|| {
+            name.strip_prefix('"')
+                .and_then(|name| name.strip_suffix('"'))
+        })
+        .unwrap_or(name)
+        .to_string()
+}
+
+/// The result sets of `sqlcmd -W -s <tab>` output: a header line, a line of
+/// dashes and the rows, with a blank line after each set.
+fn parse_output(output: &str) -> Vec<ResultSet> {
+    let lines: Vec<&str> = output.lines().collect();
+    let mut sets = Vec::new();
+    let mut i = 0;
+    while i + 1 < lines.len() {
+        let underline = lines[i + 1];
+        let is_underline = !underline.is_empty()
+            && underline.contains('-')
+            && underline.chars().all(|c| c == '-' || c == SEPARATOR);
+        if !is_underline {
+            i += 1;
+            continue;
+        }
+        let columns: Vec<String> = lines[i].split(SEPARATOR).map(str::to_string).collect();
+        let mut rows = Vec::new();
+        i += 2;
+        while i < lines.len() && !lines[i].is_empty() {
+            rows.push(lines[i].split(SEPARATOR).map(str::to_string).collect());
+            i += 1;
+        }
+        sets.push(ResultSet { columns, rows });
+    }
+    sets
+}
+
+/// Result sets as markdown tables. A set with more than `max_rows` rows is
+/// cut, and says so.
+fn format_result_sets(sets: &[ResultSet], max_rows: usize) -> String {
+    if sets.is_empty() {
+        return "The query returned no result set.".to_string();
+    }
+    let mut out = String::new();
+    for (index, set) in sets.iter().enumerate() {
+        if index > 0 {
+            out.push('\n');
+        }
+        let cell = |value: &str| {
+            let value = value.replace('|', "\\|");
+            match value.char_indices().nth(MAX_CELL_CHARS) {
+                Some((end, _)) => format!("{}…", &value[..end]),
+                None => value,
+            }
+        };
+        let header: Vec<String> = set.columns.iter().map(|c| cell(c)).collect();
+        let _ = writeln!(out, "| {} |", header.join(" | "));
+        let _ = writeln!(out, "|{}", " --- |".repeat(set.columns.len()));
+        for row in set.rows.iter().take(max_rows) {
+            let row: Vec<String> = row.iter().map(|c| cell(c)).collect();
+            let _ = writeln!(out, "| {} |", row.join(" | "));
+        }
+        if set.rows.len() > max_rows {
+            let _ = writeln!(out, "(first {max_rows} rows; more were cut)");
+        } else {
+            let _ = writeln!(out, "({} rows)", set.rows.len());
+        }
+    }
+    out
+}
+
+pub(crate) fn create_sql_tools(
+    profiles: &HashMap<String, SqlProfileConfig>,
+) -> Vec<ResponsesApiTool> {
+    let mut names: Vec<&String> = profiles.keys().collect();
+    names.sort_unstable();
+    let mut listing = "Configured profiles:".to_string();
+    for name in names {
+        let profile = &profiles[name];
+        let _ = write!(
+            listing,
+            "\n- `{name}`: {} on {}",
+            profile.database, profile.server
+        );
+        if let Some(description) = &profile.description {
+            let _ = write!(listing, " ({description})");
+        }
+    }
+
+    let string = |description: &str| JsonSchema::String {
+        description: Some(description.to_string()),
+    };
+    let object = |properties: BTreeMap<String, JsonSchema>, required: &[&str]| JsonSchema::Object {
+        properties,
+        required: Some(required.iter().map(|r| r.to_string()).collect()),
+        additional_properties: Some(false),
+    };
+    let profile = || string("Name of a configured connection profile.");
+    let list_tables = BTreeMap::from([
+        ("profile".to_string(), profile()),
+        (
+            "schema".to_string(),
+            string("Only list tables of this schema."),
+        ),
+    ]);
+    let describe_table = BTreeMap::from([
+        ("profile".to_string(), profile()),
+        (
+            "table".to_string(),
+            string(
+                "Table name, optionally with its schema: `sales.orders`. The schema defaults to dbo.",
+            ),
+        ),
+    ]);
+    let query = BTreeMap::from([
+        ("profile".to_string(), profile()),
+        (
+            "query".to_string(),
+            string("A T-SQL SELECT, optionally starting with WITH. Refer to parameters as @name."),
+        ),
+        (
+            "parameters".to_string(),
+            JsonSchema::Object {
+                properties: BTreeMap::new(),
+                required: None,
+                additional_properties: Some(true),
+            },
+        ),
+        (
+            "max_rows".to_string(),
+            JsonSchema::Number {
+                description: Some("Most rows to return; capped by the configuration.".to_string()),
+            },
+        ),
+    ]);
+
+    vec![
+        ResponsesApiTool {
+            name: SQL_LIST_DATABASES_TOOL_NAME.to_string(),
+            description: format!(
+                "Lists the databases of a SQL Server or Azure SQL server with their state and \
+                 recovery model. {listing}"
+            ),
+            strict: false,
+            parameters: object(
+                BTreeMap::from([("profile".to_string(), profile())]),
+                &["profile"],
+            ),
+        },
+        ResponsesApiTool {
+            name: SQL_LIST_TABLES_TOOL_NAME.to_string(),
+            description: "Lists the tables and views of a profile's database.".to_string(),
+            strict: false,
+            parameters: object(list_tables, &["profile"]),
+        },
+        ResponsesApiTool {
+            name: SQL_DESCRIBE_TABLE_TOOL_NAME.to_string(),
+            description: "Lists the columns of a table with their types, nullability, defaults \
+                and primary key."
+                .to_string(),
+            strict: false,
+            parameters: object(describe_table, &["profile", "table"]),
+        },
+        ResponsesApiTool {
+            name: SQL_QUERY_TOOL_NAME.to_string(),
+            description: "Runs a read-only SELECT query and returns its rows as a table. \
+                Statements that change data, schema or settings are rejected. Pass values \
+                as `parameters`, e.g. {\"since\": \"2024-05-01\"} for @since, rather than in \
+                the query text."
+                .to_string(),
+            strict: false,
+            parameters: object(query, &["profile", "query"]),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn allows_only_read_only_queries() {
+        for query in [
+            "SELECT name FROM sys.databases",
+            "with recent as (select * from orders) select count(*) from recent",
+            "SELECT 'DROP TABLE x; --' AS [delete], \"update\" FROM t -- INSERT\n",
+            "/* EXEC */ SELECT 1",
+        ] {
+            assert_eq!(check_read_only(query), Ok(()), "{query}");
+        }
+        for (query, error) in [
+            (
+                "DELETE FROM orders",
+                "only SELECT queries, optionally starting with WITH, are allowed",
+            ),
+            (
+                "SELECT 1; DROP TABLE orders",
+                "`DROP` is not allowed in a read-only query",
+            ),
+            (
+                "SELECT * INTO backup FROM orders",
+                "`INTO` is not allowed in a read-only query",
+            ),
+            (
+                "SELECT 1; SET ROWCOUNT 0",
+                "`SET` is not allowed in a read-only query",
+            ),
+            (
+                "SELECT * FROM OPENROWSET('SQLNCLI', 'x', 'y')",
+                "`OPENROWSET` is not allowed in a read-only query",
+            ),
+            ("SELECT 1\n:!! rm -rf /", "sqlcmd commands are not allowed"),
+            ("SELECT 'unterminated", "unterminated string or identifier"),
+        ] {
+            assert_eq!(check_read_only(query), Err(error.to_string()), "{query}");
+        }
+    }
+
+    #[test]
+    fn passes_parameters_to_sp_executesql() {
+        let parameters = BTreeMap::from([
+            ("@name".to_string(), json!("O'Brien")),
+            ("since".to_string(), json!(20240501)),
+            ("active".to_string(), json!(true)),
+        ]);
+        assert_eq!(
+            build_batch("SELECT * FROM t WHERE name = @name", &parameters, 10).unwrap(),
+            "SET NOCOUNT ON;\n\
+             SET XACT_ABORT ON;\n\
+             SET ROWCOUNT 11;\n\
+             BEGIN TRANSACTION;\n\
+             EXEC sp_executesql N'SELECT * FROM t WHERE name = @name', \
+             N'@name nvarchar(max), @active bit, @since bigint', \
+             @name = N'O''Brien', @active = 1, @since = 20240501;\n\
+             IF @@TRANCOUNT > 0 ROLLBACK TRANSACTION;"
+        );
+        assert_eq!(
+            build_batch(
+                "SELECT 1",
+                &BTreeMap::from([("x; --".to_string(), json!(1))]),
+                10
+            ),
+            Err("`x; --` is not a valid parameter name".to_string())
+        );
+    }
+
+    #[test]
+    fn formats_sqlcmd_output() {
+        let output = "name\tstate_desc\n----\t----------\nmaster\tONLINE\norders\tRESTORING\n\n\
+                      n\n-\n1\n";
+        let sets = parse_output(output);
+        assert_eq!(
+            sets,
+            vec![
+                ResultSet {
+                    columns: vec!["name".to_string(), "state_desc".to_string()],
+                    rows: vec![
+                        vec!["master".to_string(), "ONLINE".to_string()],
+                        vec!["orders".to_string(), "RESTORING".to_string()],
+                    ],
+                },
+                ResultSet {
+                    columns: vec!["n".to_string()],
+                    rows: vec![vec!["1".to_string()]],
+                },
+            ]
+        );
+        assert_eq!(
+            format_result_sets(&sets, 1),
+            "| name | state_desc |\n| --- | --- |\n| master | ONLINE |\n\
+             (first 1 rows; more were cut)\n\
+             \n| n |\n| --- |\n| 1 |\n(1 rows)\n"
+        );
+    }
+}
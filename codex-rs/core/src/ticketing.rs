@@ -0,0 +1,950 @@
+//! Tools that search, read, create and comment on tickets in Jira or
+//! ServiceNow, so that an incident investigation can update the ticket that
+//! tracks it.
+//!
+//! The `[ticketing]` table picks the backend. Each backend implements
+//! [`TicketBackend`] over its REST API and maps its records to [`Ticket`], so
+//! the tools and their output look the same for both.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+use codex_protocol::models::FunctionCallOutputPayload;
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use serde_json::json;
+
+use crate::config_types::TicketingBackend;
+use crate::config_types::TicketingConfig;
+use crate::openai_tools::JsonSchema;
+use crate::openai_tools::ResponsesApiTool;
+
+pub(crate) const TICKET_SEARCH_TOOL_NAME: &str = "ticket_search";
+pub(crate) const TICKET_GET_TOOL_NAME: &str = "ticket_get";
+pub(crate) const TICKET_CREATE_TOOL_NAME: &str = "ticket_create";
+pub(crate) const TICKET_COMMENT_TOOL_NAME: &str = "ticket_comment";
+
+/// Fields of a Jira issue shown by the tools.
+const JIRA_FIELDS: &str = "summary,status,priority,assignee,updated,description,comment";
+
+/// Fields of a ServiceNow record shown by the tools.
+const SERVICENOW_FIELDS: &str = "sys_id,number,short_description,description,state,priority,assigned_to,sys_updated_on,comments";
+
+/// A ticket as the tools show it, whichever backend it came from.
+#[derive(Debug, Default)]
+struct Ticket {
+    id: String,
+    title: String,
+    status: Option<String>,
+    priority: Option<String>,
+    assignee: Option<String>,
+    updated: Option<String>,
+    url: String,
+    description: Option<String>,
+    comments: Vec<String>,
+}
+
+/// A ticket to create.
+#[derive(Debug, Deserialize)]
+struct NewTicket {
+    summary: String,
+    #[serde(default)]
+    description: String,
+    /// Jira project key; defaults to `ticketing.project`.
+    #[serde(default)]
+    project: Option<String>,
+    /// Backend fields set on the ticket as they are, e.g. `priority` in Jira
+    /// or `urgency` in ServiceNow.
+    #[serde(default)]
+    fields: serde_json::Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchParams {
+    query: String,
+    #[serde(default)]
+    max_results: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetParams {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommentParams {
+    id: String,
+    comment: String,
+}
+
+/// A ticketing system the tools talk to.
+trait TicketBackend: Send + Sync {
+    /// Tickets matching `query`, in the backend's query language.
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        max_results: usize,
+    ) -> BoxFuture<'a, Result<Vec<Ticket>, String>>;
+
+    /// The ticket `id`, with its description and comments.
+    fn get<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Ticket, String>>;
+
+    /// Creates a ticket and returns its id and URL.
+    fn create<'a>(&'a self, ticket: &'a NewTicket) -> BoxFuture<'a, Result<Ticket, String>>;
+
+    /// Adds a comment to the ticket `id`.
+    fn comment<'a>(&'a self, id: &'a str, comment: &'a str) -> BoxFuture<'a, Result<(), String>>;
+}
+
+pub(crate) struct Ticketing {
+    backend: Arc<dyn TicketBackend>,
+    max_results: usize,
+}
+
+impl Ticketing {
+    pub(crate) fn new(config: TicketingConfig) -> Self {
+        let connection = Connection::new(&config);
+        Self::with_connection(config, connection)
+    }
+
+    /// Uses `token` instead of reading `token_env_var`, for tests.
+    #[cfg(test)]
+    fn new_with_token(config: TicketingConfig, token: &str) -> Self {
+        let mut connection = Connection::new(&config);
+        connection.token = Some(token.to_string());
+        Self::with_connection(config, connection)
+    }
+
+    fn with_connection(config: TicketingConfig, connection: Connection) -> Self {
+        let backend: Arc<dyn TicketBackend> = match config.backend {
+            TicketingBackend::Jira => Arc::new(Jira {
+                connection,
+                project: config.project,
+                issue_type: config.issue_type,
+            }),
+            TicketingBackend::ServiceNow => Arc::new(ServiceNow {
+                connection,
+                table: config.table,
+            }),
+        };
+        Self {
+            backend,
+            max_results: config.max_results,
+        }
+    }
+
+    pub(crate) async fn handle_function_call(
+        &self,
+        name: &str,
+        arguments: &str,
+    ) -> FunctionCallOutputPayload {
+        let result = match name {
+            TICKET_SEARCH_TOOL_NAME => match parse::<SearchParams>(arguments) {
+                Ok(params) => {
+                    let max_results = params
+                        .max_results
+                        .unwrap_or(self.max_results)
+                        .min(self.max_results);
+                    self.backend
+                        .search(&params.query, max_results)
+                        .await
+                        .map(|tickets| format_tickets(&tickets))
+                }
+                Err(e) => Err(e),
+            },
+            TICKET_GET_TOOL_NAME => match parse::<GetParams>(arguments) {
+                Ok(params) => self
+                    .backend
+                    .get(&params.id)
+                    .await
+                    .map(|t| format_ticket(&t)),
+                Err(e) => Err(e),
+            },
+            TICKET_CREATE_TOOL_NAME => match parse::<NewTicket>(arguments) {
+                Ok(ticket) => self
+                    .backend
+                    .create(&ticket)
+                    .await
+                    .map(|created| format!("Created {}: {}", created.id, created.url)),
+                Err(e) => Err(e),
+            },
+            TICKET_COMMENT_TOOL_NAME => match parse::<CommentParams>(arguments) {
+                Ok(params) => self
+                    .backend
+                    .comment(&params.id, &params.comment)
+                    .await
+                    .map(|()| format!("Commented on {}.", params.id)),
+                Err(e) => Err(e),
+            },
+            _ => Err(format!("unknown ticketing tool `{name}`")),
+        };
+        match result {
+            Ok(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(true),
+            },
+            Err(content) => FunctionCallOutputPayload {
+                content,
+                success: Some(false),
+            },
+        }
+    }
+}
+
+/// The change a ticketing call would make, for approval and dry runs, or
+/// `None` when the call only reads.
+pub(crate) fn write_request(name: &str, arguments: &str) -> Option<Value> {
+    if name != TICKET_CREATE_TOOL_NAME && name != TICKET_COMMENT_TOOL_NAME {
+        return None;
+    }
+    let arguments = serde_json::from_str::<Value>(arguments).unwrap_or(Value::Null);
+    Some(json!({ "tool": name, "arguments": arguments }))
+}
+
+fn parse<T: DeserializeOwned>(arguments: &str) -> Result<T, String> {
+    serde_json::from_str(arguments).map_err(|e| format!("failed to parse function arguments: {e}"))
+}
+
+/// The base URL and credentials of a ticketing system.
+struct Connection {
+    client: reqwest::Client,
+    base_url: String,
+    username: Option<String>,
+    token_env_var: String,
+    token: Option<String>,
+}
+
+impl Connection {
+    fn new(config: &TicketingConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            username: config.username.clone(),
+            token_env_var: config.token_env_var.clone(),
+            token: None,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    /// Sends `request` with basic auth when a username is configured and a
+    /// bearer token otherwise.
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let token = match &self.token {
+            Some(token) => token.clone(),
+            None => std::env::var(&self.token_env_var)
+                .ok()
+                .filter(|token| !token.is_empty())
+                .ok_or_else(|| {
+                    format!(
+                        "the ticketing token is missing: set the {} environment variable",
+                        self.token_env_var
+                    )
+                })?,
+        };
+        let request = match &self.username {
+            Some(username) => request.basic_auth(username, Some(token)),
+            None => request.bearer_auth(token),
+        };
+        let response = request
+            .header("accept", "application/json")
+            .send()
+            .await
+            .map_err(|e| format!("failed to reach {}: {e}", self.base_url))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!(
+                "ticketing request failed ({status}): {}",
+                error_detail(&body)
+            ));
+        }
+        Ok(response)
+    }
+
+    async fn json<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T, String> {
+        self.send(request)
+            .await?
+            .json()
+            .await
+            .map_err(|e| format!("failed to parse the ticketing response: {e}"))
+    }
+}
+
+/// The messages of a Jira or ServiceNow error body, or the body itself.
+fn error_detail(body: &str) -> String {
+    let Ok(value) = serde_json::from_str::<Value>(body) else {
+        return body.trim().to_string();
+    };
+    let mut messages: Vec<String> = Vec::new();
+    if let Some(list) = value.get("errorMessages").and_then(Value::as_array) {
+        messages.extend(list.iter().filter_map(Value::as_str).map(str::to_string));
+    }
+    if let Some(errors) = value.get("errors").and_then(Value::as_object) {
+        for (field, message) in errors {
+            messages.push(format!("{field}: {}", message.as_str().unwrap_or_default()));
+        }
+    }
+    if let Some(error) = value.get("error") {
+        for key in ["message", "detail"] {
+            if let Some(text) = error
+                .get(key)
+                .and_then(Value::as_str)
+                .filter(|text| !text.is_empty())
+            {
+                messages.push(text.to_string());
+            }
+        }
+    }
+    if messages.is_empty() {
+        body.trim().to_string()
+    } else {
+        messages.join("; ")
+    }
+}
+
+struct Jira {
+    connection: Connection,
+    project: Option<String>,
+    issue_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JiraSearch {
+    #[serde(default)]
+    issues: Vec<JiraIssue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JiraIssue {
+    key: String,
+    #[serde(default)]
+    fields: JiraFields,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct JiraFields {
+    summary: Option<String>,
+    status: Option<JiraNamed>,
+    priority: Option<JiraNamed>,
+    assignee: Option<JiraUser>,
+    updated: Option<String>,
+    description: Option<String>,
+    comment: Option<JiraComments>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JiraNamed {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JiraUser {
+    display_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JiraComments {
+    #[serde(default)]
+    comments: Vec<JiraComment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JiraComment {
+    author: Option<JiraUser>,
+    created: Option<String>,
+    #[serde(default)]
+    body: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JiraCreated {
+    key: String,
+}
+
+impl Jira {
+    fn ticket(&self, issue: JiraIssue) -> Ticket {
+        let fields = issue.fields;
+        Ticket {
+            url: self.connection.url(&format!("/browse/{}", issue.key)),
+            id: issue.key,
+            title: fields.summary.unwrap_or_default(),
+            status: fields.status.map(|status| status.name),
+            priority: fields.priority.map(|priority| priority.name),
+            assignee: fields.assignee.map(|assignee| assignee.display_name),
+            updated: fields.updated,
+            description: fields.description.filter(|d| !d.trim().is_empty()),
+            comments: fields
+                .comment
+                .map(|comments| comments.comments)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|comment| {
+                    format!(
+                        "{} {}:\n{}",
+                        comment.created.as_deref().unwrap_or("?"),
+                        comment
+                            .author
+                            .map_or_else(|| "unknown".to_string(), |a| a.display_name),
+                        comment.body.trim()
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TicketBackend for Jira {
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        max_results: usize,
+    ) -> BoxFuture<'a, Result<Vec<Ticket>, String>> {
+        Box::pin(async move {
+            let request = self
+                .connection
+                .client
+                .get(self.connection.url("/rest/api/2/search"))
+                .query(&[
+                    ("jql", query),
+                    ("maxResults", max_results.to_string().as_str()),
+                    ("fields", "summary,status,priority,assignee,updated"),
+                ]);
+            let search: JiraSearch = self.connection.json(request).await?;
+            Ok(search
+                .issues
+                .into_iter()
+                .map(|issue| self.ticket(issue))
+                .collect())
+        })
+    }
+
+    fn get<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Ticket, String>> {
+        Box::pin(async move {
+            let request = self
+                .connection
+                .client
+                .get(self.connection.url(&format!("/rest/api/2/issue/{id}")))
+                .query(&[("fields", JIRA_FIELDS)]);
+            let issue: JiraIssue = self.connection.json(request).await?;
+            Ok(self.ticket(issue))
+        })
+    }
+
+    fn create<'a>(&'a self, ticket: &'a NewTicket) -> BoxFuture<'a, Result<Ticket, String>> {
+        Box::pin(async move {
+            let project = ticket
+                .project
+                .clone()
+                .or_else(|| self.project.clone())
+                .ok_or_else(|| {
+                    "no Jira project: pass project or set ticketing.project".to_string()
+                })?;
+            let mut fields = ticket.fields.clone();
+            fields.insert("project".to_string(), json!({ "key": project }));
+            fields.insert("summary".to_string(), json!(ticket.summary));
+            fields.insert("description".to_string(), json!(ticket.description));
+            fields
+                .entry("issuetype")
+                .or_insert_with(|| json!({ "name": self.issue_type }));
+            let request = self
+                .connection
+                .client
+                .post(self.connection.url("/rest/api/2/issue"))
+                .json(&json!({ "fields": fields }));
+            let created: JiraCreated = self.connection.json(request).await?;
+            Ok(Ticket {
+                url: self.connection.url(&format!("/browse/{}", created.key)),
+                id: created.key,
+                title: ticket.summary.clone(),
+                ..Default::default()
+            })
+        })
+    }
+
+    fn comment<'a>(&'a self, id: &'a str, comment: &'a str) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let request = self
+                .connection
+                .client
+                .post(
+                    self.connection
+                        .url(&format!("/rest/api/2/issue/{id}/comment")),
+                )
+                .json(&json!({ "body": comment }));
+            self.connection.send(request).await?;
+            Ok(())
+        })
+    }
+}
+
+struct ServiceNow {
+    connection: Connection,
+    table: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServiceNowResult<T> {
+    result: T,
+}
+
+impl ServiceNow {
+    fn table_url(&self) -> String {
+        self.connection
+            .url(&format!("/api/now/table/{}", self.table))
+    }
+
+    /// Records of the table matching `query`, with display values rather
+    /// than sys_ids for reference fields.
+    async fn records(
+        &self,
+        query: &str,
+        fields: &str,
+        limit: usize,
+    ) -> Result<Vec<serde_json::Map<String, Value>>, String> {
+        let request = self.connection.client.get(self.table_url()).query(&[
+            ("sysparm_query", query),
+            ("sysparm_fields", fields),
+            ("sysparm_limit", limit.to_string().as_str()),
+            ("sysparm_display_value", "true"),
+        ]);
+        let records: ServiceNowResult<Vec<serde_json::Map<String, Value>>> =
+            self.connection.json(request).await?;
+        Ok(records.result)
+    }
+
+    /// The record whose number is `id`.
+    async fn record(
+        &self,
+        id: &str,
+        fields: &str,
+    ) -> Result<serde_json::Map<String, Value>, String> {
+        if id.contains('^') {
+            return Err(format!("`{id}` is not a ticket number"));
+        }
+        self.records(&format!("number={id}"), fields, 1)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("no {} record numbered {id}", self.table))
+    }
+
+    fn ticket(&self, record: &serde_json::Map<String, Value>) -> Ticket {
+        let field = |name: &str| record.get(name).and_then(display_value);
+        let sys_id = field("sys_id").unwrap_or_default();
+        Ticket {
+            id: field("number").unwrap_or_else(|| sys_id.clone()),
+            title: field("short_description").unwrap_or_default(),
+            status: field("state"),
+            priority: field("priority"),
+            assignee: field("assigned_to"),
+            updated: field("sys_updated_on"),
+            url: self
+                .connection
+                .url(&format!("/nav_to.do?uri={}.do?sys_id={sys_id}", self.table)),
+            description: field("description"),
+            comments: field("comments").into_iter().collect(),
+        }
+    }
+}
+
+/// The text of a ServiceNow field: the string itself, or the display value
+/// of a reference.
+fn display_value(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::String(text) => text.as_str(),
+        Value::Object(reference) => reference.get("display_value")?.as_str()?,
+        _ => return None,
+    };
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+impl TicketBackend for ServiceNow {
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        max_results: usize,
+    ) -> BoxFuture<'a, Result<Vec<Ticket>, String>> {
+        Box::pin(async move {
+            let records = self
+                .records(
+                    query,
+                    "sys_id,number,short_description,state,priority,assigned_to,sys_updated_on",
+                    max_results,
+                )
+                .await?;
+            Ok(records.iter().map(|record| self.ticket(record)).collect())
+        })
+    }
+
+    fn get<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Ticket, String>> {
+        Box::pin(async move {
+            let record = self.record(id, SERVICENOW_FIELDS).await?;
+            Ok(self.ticket(&record))
+        })
+    }
+
+    fn create<'a>(&'a self, ticket: &'a NewTicket) -> BoxFuture<'a, Result<Ticket, String>> {
+        Box::pin(async move {
+            let mut fields = ticket.fields.clone();
+            fields.insert("short_description".to_string(), json!(ticket.summary));
+            fields.insert("description".to_string(), json!(ticket.description));
+            let request = self
+                .connection
+                .client
+                .post(self.table_url())
+                .query(&[("sysparm_display_value", "true")])
+                .json(&fields);
+            let created: ServiceNowResult<serde_json::Map<String, Value>> =
+                self.connection.json(request).await?;
+            Ok(self.ticket(&created.result))
+        })
+    }
+
+    fn comment<'a>(&'a self, id: &'a str, comment: &'a str) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let record = self.record(id, "sys_id").await?;
+            let sys_id = record
+                .get("sys_id")
+                .and_then(display_value)
+                .ok_or_else(|| format!("{id} has no sys_id"))?;
+            let request = self
+                .connection
+                .client
+                .patch(format!("{}/{sys_id}", self.table_url()))
+                .json(&json!({ "comments": comment }));
+            self.connection.send(request).await?;
+            Ok(())
+        })
+    }
+}
+
+fn format_tickets(tickets: &[Ticket]) -> String {
+    if tickets.is_empty() {
+        return "No tickets found.".to_string();
+    }
+    let mut out = format!("{} tickets:\n", tickets.len());
+    for ticket in tickets {
+        let _ = write!(out, "- {}", ticket.id);
+        for label in [&ticket.status, &ticket.priority].into_iter().flatten() {
+            let _ = write!(out, " [{label}]");
+        }
+        let _ = write!(out, " {}", ticket.title);
+        match (&ticket.assignee, &ticket.updated) {
+            (Some(assignee), Some(updated)) => {
+                let _ = write!(out, " ({assignee}, updated {updated})");
+            }
+            (Some(assignee), None) => {
+                let _ = write!(out, " ({assignee})");
+            }
+            (None, Some(updated)) => {
+                let _ = write!(out, " (updated {updated})");
+            }
+            (None, None) => {}
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn format_ticket(ticket: &Ticket) -> String {
+    let mut out = format!("{}: {}\n", ticket.id, ticket.title);
+    for (label, value) in [
+        ("Status", &ticket.status),
+        ("Priority", &ticket.priority),
+        ("Assignee", &ticket.assignee),
+        ("Updated", &ticket.updated),
+    ] {
+        if let Some(value) = value {
+            let _ = writeln!(out, "{label}: {value}");
+        }
+    }
+    let _ = writeln!(out, "URL: {}", ticket.url);
+    if let Some(description) = &ticket.description {
+        let _ = write!(out, "\nDescription:\n{}\n", description.trim());
+    }
+    if !ticket.comments.is_empty() {
+        out.push_str("\nComments:\n");
+        for comment in &ticket.comments {
+            let _ = writeln!(out, "{comment}\n");
+        }
+    }
+    out
+}
+
+pub(crate) fn create_ticketing_tools() -> Vec<ResponsesApiTool> {
+    let string = |description: &str| JsonSchema::String {
+        description: Some(description.to_string()),
+    };
+    let object = |properties: BTreeMap<String, JsonSchema>, required: &[&str]| JsonSchema::Object {
+        properties,
+        required: Some(required.iter().map(|r| r.to_string()).collect()),
+        additional_properties: Some(false),
+    };
+    let id = || string("Ticket key or number, e.g. OPS-123 or INC0012345.");
+    let search = BTreeMap::from([
+        (
+            "query".to_string(),
+            string(
+                "Query in the backend's language: JQL for Jira, an encoded query for \
+                 ServiceNow (e.g. `active=true^short_descriptionLIKEdisk`).",
+            ),
+        ),
+        (
+            "max_results".to_string(),
+            JsonSchema::Number {
+                description: Some("Most tickets to return.".to_string()),
+            },
+        ),
+    ]);
+    let create = BTreeMap::from([
+        (
+            "summary".to_string(),
+            string("One-line title of the ticket."),
+        ),
+        ("description".to_string(), string("Body of the ticket.")),
+        (
+            "project".to_string(),
+            string("Jira project key; defaults to the configured project."),
+        ),
+        (
+            "fields".to_string(),
+            JsonSchema::Object {
+                properties: BTreeMap::new(),
+                required: None,
+                additional_properties: Some(true),
+            },
+        ),
+    ]);
+    let comment = BTreeMap::from([
+        ("id".to_string(), id()),
+        ("comment".to_string(), string("Text of the comment.")),
+    ]);
+
+    vec![
+        ResponsesApiTool {
+            name: TICKET_SEARCH_TOOL_NAME.to_string(),
+            description: "Searches the configured Jira or ServiceNow instance for tickets."
+                .to_string(),
+            strict: false,
+            parameters: object(search, &["query"]),
+        },
+        ResponsesApiTool {
+            name: TICKET_GET_TOOL_NAME.to_string(),
+            description: "Reads a ticket with its description and comments.".to_string(),
+            strict: false,
+            parameters: object(BTreeMap::from([("id".to_string(), id())]), &["id"]),
+        },
+        ResponsesApiTool {
+            name: TICKET_CREATE_TOOL_NAME.to_string(),
+            description: "Creates a ticket after the user approves it. `fields` sets further \
+                backend fields as they are, e.g. {\"priority\": {\"name\": \"High\"}} in Jira \
+                or {\"urgency\": \"1\"} in ServiceNow."
+                .to_string(),
+            strict: false,
+            parameters: object(create, &["summary"]),
+        },
+        ResponsesApiTool {
+            name: TICKET_COMMENT_TOOL_NAME.to_string(),
+            description: "Adds a comment to a ticket after the user approves it.".to_string(),
+            strict: false,
+            parameters: object(comment, &["id", "comment"]),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::body_json;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+    use wiremock::matchers::query_param;
+
+    fn config(backend: TicketingBackend, server: &MockServer) -> TicketingConfig {
+        TicketingConfig {
+            backend,
+            base_url: server.uri(),
+            username: Some("bot@contoso.com".to_string()),
+            token_env_var: "CODEX_TEST_TICKETING_TOKEN_UNSET".to_string(),
+            project: Some("OPS".to_string()),
+            issue_type: "Task".to_string(),
+            table: "incident".to_string(),
+            max_results: 20,
+        }
+    }
+
+    #[tokio::test]
+    async fn searches_and_reads_jira_issues() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/api/2/search"))
+            .and(query_param("jql", "project = OPS AND status != Done"))
+            .and(query_param("maxResults", "5"))
+            // Basic auth of bot@contoso.com:secret.
+            .and(header(
+                "authorization",
+                "Basic Ym90QGNvbnRvc28uY29tOnNlY3JldA==",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "issues": [{
+                    "key": "OPS-12",
+                    "fields": {
+                        "summary": "Checkout latency above SLO",
+                        "status": { "name": "In Progress" },
+                        "priority": { "name": "High" },
+                        "assignee": { "displayName": "Ada Lovelace" },
+                        "updated": "2024-05-01T08:00:00.000+0000",
+                    },
+                }],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/api/2/issue/OPS-12"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "key": "OPS-12",
+                "fields": {
+                    "summary": "Checkout latency above SLO",
+                    "status": { "name": "In Progress" },
+                    "description": "p95 is 2.3s.",
+                    "comment": { "comments": [{
+                        "author": { "displayName": "Ada Lovelace" },
+                        "created": "2024-05-01T08:05:00.000+0000",
+                        "body": "Looking at the database.",
+                    }]},
+                },
+            })))
+            .mount(&server)
+            .await;
+        let ticketing =
+            Ticketing::new_with_token(config(TicketingBackend::Jira, &server), "secret");
+
+        let output = ticketing
+            .handle_function_call(
+                TICKET_SEARCH_TOOL_NAME,
+                r#"{"query": "project = OPS AND status != Done", "max_results": 5}"#,
+            )
+            .await;
+        assert_eq!(
+            output.content,
+            "1 tickets:\n- OPS-12 [In Progress] [High] Checkout latency above SLO \
+             (Ada Lovelace, updated 2024-05-01T08:00:00.000+0000)\n"
+        );
+
+        let output = ticketing
+            .handle_function_call(TICKET_GET_TOOL_NAME, r#"{"id": "OPS-12"}"#)
+            .await;
+        assert_eq!(
+            output.content,
+            format!(
+                "OPS-12: Checkout latency above SLO\n\
+                 Status: In Progress\n\
+                 URL: {}/browse/OPS-12\n\
+                 \nDescription:\np95 is 2.3s.\n\
+                 \nComments:\n\
+                 2024-05-01T08:05:00.000+0000 Ada Lovelace:\nLooking at the database.\n\n",
+                server.uri()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn comments_on_servicenow_incidents_by_number() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/now/table/incident"))
+            .and(query_param("sysparm_query", "number=INC0012345"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "result": [{ "sys_id": "9d385017c611228701d22104cc95c371" }],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(
+                "/api/now/table/incident/9d385017c611228701d22104cc95c371",
+            ))
+            .and(body_json(
+                json!({ "comments": "Mitigated by scaling out." }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "result": {} })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let ticketing =
+            Ticketing::new_with_token(config(TicketingBackend::ServiceNow, &server), "secret");
+
+        let output = ticketing
+            .handle_function_call(
+                TICKET_COMMENT_TOOL_NAME,
+                r#"{"id": "INC0012345", "comment": "Mitigated by scaling out."}"#,
+            )
+            .await;
+
+        assert_eq!(output.success, Some(true), "{}", output.content);
+        assert_eq!(output.content, "Commented on INC0012345.");
+    }
+
+    #[tokio::test]
+    async fn reports_jira_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/rest/api/2/issue"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "errorMessages": [],
+                "errors": { "priority": "Priority name 'P0' is not valid" },
+            })))
+            .mount(&server)
+            .await;
+        let ticketing =
+            Ticketing::new_with_token(config(TicketingBackend::Jira, &server), "secret");
+
+        let output = ticketing
+            .handle_function_call(
+                TICKET_CREATE_TOOL_NAME,
+                r#"{"summary": "Outage", "fields": {"priority": {"name": "P0"}}}"#,
+            )
+            .await;
+
+        assert_eq!(output.success, Some(false));
+        assert_eq!(
+            output.content,
+            "ticketing request failed (400 Bad Request): priority: Priority name 'P0' is not valid"
+        );
+    }
+
+    #[test]
+    fn only_creates_and_comments_are_writes() {
+        assert_eq!(
+            write_request(TICKET_GET_TOOL_NAME, r#"{"id": "OPS-1"}"#),
+            None
+        );
+        assert_eq!(
+            write_request(
+                TICKET_COMMENT_TOOL_NAME,
+                r#"{"id": "OPS-1", "comment": "hi"}"#
+            ),
+            Some(json!({
+                "tool": "ticket_comment",
+                "arguments": { "id": "OPS-1", "comment": "hi" },
+            }))
+        );
+    }
+}
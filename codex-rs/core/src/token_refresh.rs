@@ -0,0 +1,170 @@
+//! Background renewal of OAuth access tokens.
+//!
+//! Long-running sessions would otherwise only notice an expired token when a
+//! request fails with 401 in the middle of a tool call. The refresher sleeps
+//! until shortly before the earliest known expiry (ChatGPT access token and
+//! every cached Azure token) and renews them ahead of time.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::DateTime;
+use chrono::Utc;
+use codex_login::AuthManager;
+use tokio::task::JoinHandle;
+use tracing::debug;
+use tracing::warn;
+
+use crate::azure_auth::AzureAuth;
+
+/// How long before expiry a token is renewed.
+const REFRESH_LEAD: Duration = Duration::from_secs(10 * 60);
+
+/// Upper bound on how long the refresher sleeps, so credentials acquired
+/// after it last looked (e.g. a new Azure login) are picked up.
+const MAX_SLEEP: Duration = Duration::from_secs(5 * 60);
+
+/// Minimum pause between two refresh passes, which keeps a provider that
+/// hands out very short-lived tokens from turning this into a busy loop.
+const MIN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Pause after the first failed pass; it doubles with every consecutive
+/// failure up to [`MAX_RETRY_DELAY`].
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30 * 60);
+
+/// Handle to the background refresh task; the task stops when this is
+/// dropped.
+pub(crate) struct TokenRefresher {
+    handle: JoinHandle<()>,
+}
+
+impl TokenRefresher {
+    pub(crate) fn spawn(auth_manager: Arc<AuthManager>, azure_auth: Arc<AzureAuth>) -> Self {
+        let handle = tokio::spawn(run(auth_manager, azure_auth));
+        Self { handle }
+    }
+}
+
+impl Drop for TokenRefresher {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn run(auth_manager: Arc<AuthManager>, azure_auth: Arc<AzureAuth>) {
+    let lead = chrono::Duration::from_std(REFRESH_LEAD).unwrap_or_default();
+    let mut failures = 0;
+    // Expiry of the token whose renewal was rejected for good (e.g. a revoked
+    // refresh token). It is left alone until a new login replaces it, which
+    // also changes its expiry.
+    let mut chatgpt_rejected = None;
+    let mut azure_rejected = None;
+    loop {
+        let chatgpt_expiration = auth_manager
+            .auth()
+            .and_then(|auth| auth.get_access_token_expiration())
+            .filter(|expires_at| chatgpt_rejected != Some(*expires_at));
+        let azure_expiration = azure_auth
+            .next_expiration()
+            .await
+            .filter(|expires_at| azure_rejected != Some(*expires_at));
+        tokio::time::sleep(time_until_due(
+            [chatgpt_expiration, azure_expiration]
+                .into_iter()
+                .flatten()
+                .min(),
+            lead,
+        ))
+        .await;
+
+        let mut failed = false;
+        if chatgpt_expiration.is_some() {
+            match auth_manager.refresh_token_if_expiring(lead).await {
+                Ok(true) => debug!("refreshed ChatGPT access token ahead of expiry"),
+                Ok(false) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                    warn!("ChatGPT refresh token was rejected; log in again to renew it: {e}");
+                    chatgpt_rejected = chatgpt_expiration;
+                }
+                Err(e) => {
+                    warn!("failed to refresh ChatGPT access token: {e}");
+                    failed = true;
+                }
+            }
+        }
+        if azure_expiration.is_some() {
+            match azure_auth.refresh_expiring(Utc::now() + lead).await {
+                Ok(0) => {}
+                Ok(n) => debug!("refreshed {n} Azure token(s) ahead of expiry"),
+                Err(e) if !e.is_retryable() => {
+                    warn!("Azure token renewal was rejected; log in again to renew it: {e}");
+                    azure_rejected = azure_expiration;
+                }
+                Err(e) => {
+                    warn!("failed to refresh Azure token: {e}");
+                    failed = true;
+                }
+            }
+        }
+
+        if failed {
+            failures += 1;
+            tokio::time::sleep(retry_delay(failures)).await;
+        } else {
+            failures = 0;
+            tokio::time::sleep(MIN_INTERVAL).await;
+        }
+    }
+}
+
+/// Backoff after `failures` consecutive failed passes.
+fn retry_delay(failures: u32) -> Duration {
+    RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY)
+}
+
+fn time_until_due(expiration: Option<DateTime<Utc>>, lead: chrono::Duration) -> Duration {
+    match expiration {
+        Some(expires_at) => (expires_at - lead - Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO)
+            .min(MAX_SLEEP),
+        None => MAX_SLEEP,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn sleeps_until_lead_before_expiry() {
+        let lead = chrono::Duration::minutes(10);
+
+        assert_eq!(time_until_due(None, lead), MAX_SLEEP);
+        assert_eq!(
+            time_until_due(Some(Utc::now() + chrono::Duration::minutes(5)), lead),
+            Duration::ZERO
+        );
+        assert_eq!(
+            time_until_due(Some(Utc::now() + chrono::Duration::hours(2)), lead),
+            MAX_SLEEP
+        );
+
+        let due = time_until_due(Some(Utc::now() + chrono::Duration::minutes(12)), lead);
+        assert!(due > Duration::from_secs(100) && due <= Duration::from_secs(120));
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_cap() {
+        assert_eq!(retry_delay(1), RETRY_DELAY);
+        assert_eq!(retry_delay(2), RETRY_DELAY * 2);
+        assert_eq!(retry_delay(4), RETRY_DELAY * 8);
+        assert_eq!(retry_delay(6), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+}
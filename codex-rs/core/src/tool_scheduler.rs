@@ -0,0 +1,395 @@
+//! Runs the tool calls of a turn concurrently, up to a limit, while calls that
+//! touch the same resource run one after the other, in the order the model
+//! made them.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use codex_apply_patch::Hunk;
+use codex_protocol::models::LocalShellAction;
+use codex_protocol::models::ResponseItem;
+use codex_protocol::models::ShellToolCallParams;
+use serde::Deserialize;
+
+use crate::arm_deployments::ARM_DEPLOYMENT_ERRORS_TOOL_NAME;
+use crate::arm_deployments::ARM_LIST_DEPLOYMENTS_TOOL_NAME;
+use crate::arm_deployments::ARM_WHAT_IF_TOOL_NAME;
+use crate::artifacts::READ_ARTIFACT_TOOL_NAME;
+use crate::azure_monitor::AZURE_MONITOR_ACTIVITY_LOG_TOOL_NAME;
+use crate::azure_monitor::AZURE_MONITOR_ALERTS_TOOL_NAME;
+use crate::azure_monitor::AZURE_MONITOR_METRICS_TOOL_NAME;
+use crate::azure_storage::AZURE_STORAGE_LIST_BLOBS_TOOL_NAME;
+use crate::azure_storage::AZURE_STORAGE_LIST_CONTAINERS_TOOL_NAME;
+use crate::azure_storage::AZURE_STORAGE_READ_BLOB_TOOL_NAME;
+use crate::edit_files::EDIT_FILES_TOOL_NAME;
+use crate::environment_snapshot::REFRESH_ENVIRONMENT_TOOL_NAME;
+use crate::git_tools::GIT_DIFF_TOOL_NAME;
+use crate::git_tools::GIT_STATUS_TOOL_NAME;
+use crate::is_safe_command::is_known_safe_command;
+use crate::key_vault::KEY_VAULT_CHECK_EXPIRY_TOOL_NAME;
+use crate::key_vault::KEY_VAULT_LIST_ITEMS_TOOL_NAME;
+use crate::key_vault::KEY_VAULT_LIST_VAULTS_TOOL_NAME;
+use crate::knowledge_base::KB_SEARCH_TOOL_NAME;
+use crate::kubernetes::K8S_DESCRIBE_TOOL_NAME;
+use crate::kubernetes::K8S_EVENTS_TOOL_NAME;
+use crate::kubernetes::K8S_GET_TOOL_NAME;
+use crate::kubernetes::K8S_LOGS_TOOL_NAME;
+use crate::mcp_tool_call::READ_RESOURCE_TOOL_NAME;
+use crate::sql::SQL_DESCRIBE_TABLE_TOOL_NAME;
+use crate::sql::SQL_LIST_DATABASES_TOOL_NAME;
+use crate::sql::SQL_LIST_TABLES_TOOL_NAME;
+use crate::sql::SQL_QUERY_TOOL_NAME;
+use crate::ticketing::TICKET_GET_TOOL_NAME;
+use crate::ticketing::TICKET_SEARCH_TOOL_NAME;
+
+/// Something tool calls can read or change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Resource {
+    Path(PathBuf),
+    McpServer(String),
+    Plan,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ToolCallAccess {
+    /// Reads and changes only the given resources.
+    Resources {
+        reads: Vec<Resource>,
+        writes: Vec<Resource>,
+    },
+    /// May read any file, e.g. a read-only shell command.
+    ReadsAnything,
+    /// May change anything, e.g. an arbitrary shell command.
+    Exclusive,
+}
+
+impl ToolCallAccess {
+    pub(crate) fn writes(resources: Vec<Resource>) -> Self {
+        Self::Resources {
+            reads: Vec::new(),
+            writes: resources,
+        }
+    }
+
+    pub(crate) fn reads(resources: Vec<Resource>) -> Self {
+        Self::Resources {
+            reads: resources,
+            writes: Vec::new(),
+        }
+    }
+
+    /// Whether two calls must not run at the same time.
+    fn conflicts(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Exclusive, _) | (_, Self::Exclusive) => true,
+            (Self::ReadsAnything, Self::ReadsAnything) => false,
+            (Self::ReadsAnything, Self::Resources { writes, .. })
+            | (Self::Resources { writes, .. }, Self::ReadsAnything) => writes
+                .iter()
+                .any(|resource| matches!(resource, Resource::Path(_))),
+            (
+                Self::Resources { reads, writes },
+                Self::Resources {
+                    reads: other_reads,
+                    writes: other_writes,
+                },
+            ) => {
+                writes.iter().any(|resource| {
+                    other_reads.contains(resource) || other_writes.contains(resource)
+                }) || other_writes.iter().any(|resource| reads.contains(resource))
+            }
+        }
+    }
+}
+
+/// What a call to one of the built-in tools touches. Unknown tools are
+/// assumed to touch anything.
+pub(crate) fn tool_call_access(item: &ResponseItem, cwd: &Path) -> ToolCallAccess {
+    match item {
+        ResponseItem::FunctionCall {
+            name, arguments, ..
+        } => function_call_access(name, arguments, cwd),
+        ResponseItem::LocalShellCall {
+            action: LocalShellAction::Exec(action),
+            ..
+        } => command_access(&action.command),
+        ResponseItem::CustomToolCall { name, input, .. } if name == "apply_patch" => {
+            patch_access(input, cwd)
+        }
+        _ => ToolCallAccess::Exclusive,
+    }
+}
+
+fn function_call_access(name: &str, arguments: &str, cwd: &Path) -> ToolCallAccess {
+    #[derive(Deserialize)]
+    struct PatchArgs {
+        input: String,
+    }
+    #[derive(Deserialize)]
+    struct PathArgs {
+        path: String,
+    }
+    #[derive(Deserialize)]
+    struct EditFilesArgs {
+        edits: Vec<PathArgs>,
+    }
+    #[derive(Deserialize)]
+    struct ReadResourceArgs {
+        server: String,
+    }
+
+    match name {
+        "container.exec" | "shell" => serde_json::from_str::<ShellToolCallParams>(arguments)
+            .map_or(ToolCallAccess::Exclusive, |params| {
+                command_access(&params.command)
+            }),
+        "apply_patch" => serde_json::from_str::<PatchArgs>(arguments)
+            .map_or(ToolCallAccess::Exclusive, |args| {
+                patch_access(&args.input, cwd)
+            }),
+        EDIT_FILES_TOOL_NAME => serde_json::from_str::<EditFilesArgs>(arguments).map_or(
+            ToolCallAccess::Exclusive,
+            |args| {
+                ToolCallAccess::writes(
+                    args.edits
+                        .into_iter()
+                        .map(|edit| Resource::Path(cwd.join(edit.path)))
+                        .collect(),
+                )
+            },
+        ),
+        "view_image" => serde_json::from_str::<PathArgs>(arguments)
+            .map_or(ToolCallAccess::ReadsAnything, |args| {
+                ToolCallAccess::reads(vec![Resource::Path(cwd.join(args.path))])
+            }),
+        "update_plan" => ToolCallAccess::writes(vec![Resource::Plan]),
+        READ_RESOURCE_TOOL_NAME => serde_json::from_str::<ReadResourceArgs>(arguments)
+            .map_or(ToolCallAccess::Exclusive, |args| {
+                ToolCallAccess::reads(vec![Resource::McpServer(args.server)])
+            }),
+        GIT_STATUS_TOOL_NAME
+        | GIT_DIFF_TOOL_NAME
+        | REFRESH_ENVIRONMENT_TOOL_NAME
+        | READ_ARTIFACT_TOOL_NAME
+        | KB_SEARCH_TOOL_NAME
+        | AZURE_MONITOR_METRICS_TOOL_NAME
+        | AZURE_MONITOR_ALERTS_TOOL_NAME
+        | AZURE_MONITOR_ACTIVITY_LOG_TOOL_NAME
+        | KEY_VAULT_LIST_VAULTS_TOOL_NAME
+        | KEY_VAULT_LIST_ITEMS_TOOL_NAME
+        | KEY_VAULT_CHECK_EXPIRY_TOOL_NAME
+        | AZURE_STORAGE_LIST_CONTAINERS_TOOL_NAME
+        | AZURE_STORAGE_LIST_BLOBS_TOOL_NAME
+        | AZURE_STORAGE_READ_BLOB_TOOL_NAME
+        | ARM_WHAT_IF_TOOL_NAME
+        | ARM_LIST_DEPLOYMENTS_TOOL_NAME
+        | ARM_DEPLOYMENT_ERRORS_TOOL_NAME
+        | TICKET_SEARCH_TOOL_NAME
+        | TICKET_GET_TOOL_NAME
+        | SQL_LIST_DATABASES_TOOL_NAME
+        | SQL_LIST_TABLES_TOOL_NAME
+        | SQL_DESCRIBE_TABLE_TOOL_NAME
+        | SQL_QUERY_TOOL_NAME
+        | K8S_GET_TOOL_NAME
+        | K8S_DESCRIBE_TOOL_NAME
+        | K8S_LOGS_TOOL_NAME
+        | K8S_EVENTS_TOOL_NAME => ToolCallAccess::ReadsAnything,
+        _ => ToolCallAccess::Exclusive,
+    }
+}
+
+fn command_access(command: &[String]) -> ToolCallAccess {
+    if is_known_safe_command(command) {
+        ToolCallAccess::ReadsAnything
+    } else {
+        ToolCallAccess::Exclusive
+    }
+}
+
+fn patch_access(patch: &str, cwd: &Path) -> ToolCallAccess {
+    let Ok(args) = codex_apply_patch::parse_patch(patch) else {
+        return ToolCallAccess::Exclusive;
+    };
+    let mut paths = Vec::new();
+    for hunk in args.hunks {
+        match hunk {
+            Hunk::AddFile { path, .. } | Hunk::DeleteFile { path } => paths.push(path),
+            Hunk::UpdateFile {
+                path, move_path, ..
+            } => {
+                paths.push(path);
+                paths.extend(move_path);
+            }
+        }
+    }
+    ToolCallAccess::writes(
+        paths
+            .into_iter()
+            .map(|path| Resource::Path(cwd.join(path)))
+            .collect(),
+    )
+}
+
+/// Decides when the tool calls of a turn may start. `T` is whatever is
+/// needed to run a call.
+pub(crate) struct ToolCallScheduler<T> {
+    limit: usize,
+    /// Calls that have not finished, in the order the model made them.
+    calls: Vec<ScheduledCall<T>>,
+}
+
+struct ScheduledCall<T> {
+    id: usize,
+    access: ToolCallAccess,
+    /// `None` once the call has started.
+    payload: Option<T>,
+}
+
+impl<T> ToolCallScheduler<T> {
+    /// A scheduler running at most `limit` calls at once.
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            calls: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, id: usize, access: ToolCallAccess, payload: T) {
+        self.calls.push(ScheduledCall {
+            id,
+            access,
+            payload: Some(payload),
+        });
+    }
+
+    /// The calls that can start now, in order. A call starts once no earlier
+    /// unfinished call conflicts with it and fewer than `limit` calls run.
+    pub(crate) fn start_ready(&mut self) -> Vec<(usize, T)> {
+        let mut running = self
+            .calls
+            .iter()
+            .filter(|call| call.payload.is_none())
+            .count();
+        let mut ready = Vec::new();
+        for index in 0..self.calls.len() {
+            if running >= self.limit {
+                break;
+            }
+            let (earlier, rest) = self.calls.split_at_mut(index);
+            let call = &mut rest[0];
+            if call.payload.is_none()
+                || earlier
+                    .iter()
+                    .any(|earlier| earlier.access.conflicts(&call.access))
+            {
+                continue;
+            }
+            if let Some(payload) = call.payload.take() {
+                ready.push((call.id, payload));
+                running += 1;
+            }
+        }
+        ready
+    }
+
+    pub(crate) fn finish(&mut self, id: usize) {
+        self.calls.retain(|call| call.id != id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn function_call(name: &str, arguments: serde_json::Value) -> ResponseItem {
+        ResponseItem::FunctionCall {
+            id: None,
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+            call_id: "call".to_string(),
+        }
+    }
+
+    fn patch(path: &str) -> ResponseItem {
+        function_call(
+            "apply_patch",
+            serde_json::json!({
+                "input": format!("*** Begin Patch\n*** Add File: {path}\n+hello\n*** End Patch"),
+            }),
+        )
+    }
+
+    #[test]
+    fn classifies_builtin_tool_calls() {
+        let cwd = Path::new("/repo");
+        assert_eq!(
+            tool_call_access(&patch("a.txt"), cwd),
+            ToolCallAccess::writes(vec![Resource::Path(PathBuf::from("/repo/a.txt"))])
+        );
+        assert_eq!(
+            tool_call_access(
+                &function_call("shell", serde_json::json!({"command": ["ls", "-l"]})),
+                cwd
+            ),
+            ToolCallAccess::ReadsAnything
+        );
+        assert_eq!(
+            tool_call_access(
+                &function_call("shell", serde_json::json!({"command": ["rm", "a.txt"]})),
+                cwd
+            ),
+            ToolCallAccess::Exclusive
+        );
+        assert_eq!(
+            tool_call_access(&function_call("unknown", serde_json::json!({})), cwd),
+            ToolCallAccess::Exclusive
+        );
+    }
+
+    #[test]
+    fn conflicting_calls_run_in_order() {
+        let cwd = Path::new("/repo");
+        let access = |item: &ResponseItem| tool_call_access(item, cwd);
+        let mut scheduler = ToolCallScheduler::new(3);
+        scheduler.push(0, access(&patch("a.txt")), "patch a");
+        scheduler.push(1, access(&patch("b.txt")), "patch b");
+        scheduler.push(2, access(&patch("a.txt")), "patch a again");
+        scheduler.push(
+            3,
+            ToolCallAccess::writes(vec![Resource::McpServer("docs".to_string())]),
+            "mcp",
+        );
+        scheduler.push(4, ToolCallAccess::ReadsAnything, "ls");
+
+        // The second patch to a.txt waits for the first, and `ls` waits for
+        // both patches.
+        assert_eq!(
+            scheduler.start_ready(),
+            vec![(0, "patch a"), (1, "patch b"), (3, "mcp")]
+        );
+        assert_eq!(scheduler.start_ready(), vec![]);
+
+        // Three calls are running already.
+        scheduler.finish(3);
+        assert_eq!(scheduler.start_ready(), vec![]);
+        scheduler.finish(0);
+        assert_eq!(scheduler.start_ready(), vec![(2, "patch a again")]);
+        scheduler.finish(1);
+        scheduler.finish(2);
+        assert_eq!(scheduler.start_ready(), vec![(4, "ls")]);
+    }
+
+    #[test]
+    fn exclusive_calls_wait_for_everything_before_them() {
+        let mut scheduler = ToolCallScheduler::new(4);
+        scheduler.push(0, ToolCallAccess::ReadsAnything, "cat");
+        scheduler.push(1, ToolCallAccess::Exclusive, "make");
+        scheduler.push(2, ToolCallAccess::ReadsAnything, "ls");
+        assert_eq!(scheduler.start_ready(), vec![(0, "cat")]);
+        scheduler.finish(0);
+        assert_eq!(scheduler.start_ready(), vec![(1, "make")]);
+        scheduler.finish(1);
+        assert_eq!(scheduler.start_ready(), vec![(2, "ls")]);
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
@@ -8,13 +9,27 @@ use anyhow::Context;
 use anyhow::Result;
 use anyhow::anyhow;
 use sha1::digest::Output;
+use similar::ChangeTag;
 use uuid::Uuid;
 
 use crate::protocol::FileChange;
+use crate::protocol::TurnDiffEvent;
+use crate::protocol::TurnDiffFile;
+use crate::protocol::TurnDiffFileChange;
 
 const ZERO_OID: &str = "0000000000000000000000000000000000000000";
 const DEV_NULL: &str = "/dev/null";
 
+/// Start of the message that reminds the model of the files it changed.
+pub(crate) const TURN_CHANGES_START: &str = "<turn_changes>";
+
+/// Worktrees with more files that differ from `HEAD` are not tracked for
+/// changes made by commands.
+const MAX_SNAPSHOT_FILES: usize = 1000;
+/// Files larger than this that differ from `HEAD` are not tracked for changes
+/// made by commands.
+const MAX_SNAPSHOT_FILE_BYTES: u64 = 1024 * 1024;
+
 struct BaselineFileInfo {
     path: PathBuf,
     content: Vec<u8>,
@@ -22,6 +37,10 @@ struct BaselineFileInfo {
     oid: String,
 }
 
+/// A [`TurnDiffTracker`] shared by the tool calls of a turn, which may run
+/// concurrently.
+pub type SharedTurnDiffTracker = std::sync::Mutex<TurnDiffTracker>;
+
 /// Tracks sets of changes to files and exposes the overall unified diff.
 /// Internally, the way this works is now:
 /// 1. Maintain an in-memory baseline snapshot of files when they are first seen.
@@ -223,7 +242,14 @@ impl TurnDiffTracker {
     /// collected before the first time they were touched by apply_patch during this turn with
     /// the current repo state.
     pub fn get_unified_diff(&mut self) -> Result<Option<String>> {
+        Ok(self.get_turn_diff().map(|diff| diff.unified_diff))
+    }
+
+    /// Like [`Self::get_unified_diff`], along with a summary of each changed
+    /// file.
+    pub fn get_turn_diff(&mut self) -> Option<TurnDiffEvent> {
         let mut aggregated = String::new();
+        let mut files = Vec::new();
 
         // Compute diffs per tracked internal file in a stable order by external path.
         let mut baseline_file_names: Vec<String> =
@@ -236,20 +262,43 @@ impl TurnDiffTracker {
         });
 
         for internal in baseline_file_names {
-            aggregated.push_str(self.get_file_diff(&internal).as_str());
+            let Some((diff, file)) = self.get_file_diff(&internal) else {
+                continue;
+            };
+            aggregated.push_str(&diff);
             if !aggregated.ends_with('\n') {
                 aggregated.push('\n');
             }
+            files.push(file);
         }
 
         if aggregated.trim().is_empty() {
-            Ok(None)
+            None
         } else {
-            Ok(Some(aggregated))
+            Some(TurnDiffEvent {
+                unified_diff: aggregated,
+                files,
+            })
         }
     }
 
-    fn get_file_diff(&mut self, internal_file_name: &str) -> String {
+    /// Starts tracking the files a command changed, unless they are tracked
+    /// already.
+    pub fn on_exec_end(&mut self, changes: WorktreeChanges) {
+        for info in changes.0 {
+            if self.external_to_temp_name.contains_key(&info.path) {
+                continue;
+            }
+            let internal = Uuid::new_v4().to_string();
+            self.external_to_temp_name
+                .insert(info.path.clone(), internal.clone());
+            self.temp_name_to_current_path
+                .insert(internal.clone(), info.path.clone());
+            self.baseline_file_info.insert(internal, info);
+        }
+    }
+
+    fn get_file_diff(&mut self, internal_file_name: &str) -> Option<(String, TurnDiffFile)> {
         let mut aggregated = String::new();
 
         // Snapshot lightweight fields only.
@@ -260,10 +309,7 @@ impl TurnDiffTracker {
                 (PathBuf::new(), FileMode::Regular, ZERO_OID.to_string())
             }
         };
-        let current_external_path = match self.get_path_for_internal(internal_file_name) {
-            Some(p) => p,
-            None => return aggregated,
-        };
+        let current_external_path = self.get_path_for_internal(internal_file_name)?;
 
         let current_mode = file_mode_for_path(&current_external_path).unwrap_or(FileMode::Regular);
         let right_bytes = blob_bytes(&current_external_path, &current_mode);
@@ -296,7 +342,7 @@ impl TurnDiffTracker {
 
         // Fast path: identical bytes or both missing.
         if left_bytes == right_bytes.as_deref() {
-            return aggregated;
+            return None;
         }
 
         aggregated.push_str(&format!("diff --git a/{left_display} b/{right_display}\n"));
@@ -313,6 +359,26 @@ impl TurnDiffTracker {
             aggregated.push_str(&format!("new mode {current_mode}\n"));
         }
 
+        let mut file = TurnDiffFile {
+            path: PathBuf::from(if is_delete {
+                &left_display
+            } else {
+                &right_display
+            }),
+            moved_from: (!is_add && !is_delete && left_display != right_display)
+                .then(|| PathBuf::from(&left_display)),
+            change: if is_add {
+                TurnDiffFileChange::Added
+            } else if is_delete {
+                TurnDiffFileChange::Deleted
+            } else {
+                TurnDiffFileChange::Modified
+            },
+            hunks: 0,
+            added_lines: 0,
+            removed_lines: 0,
+        };
+
         let left_text = left_bytes.and_then(|b| std::str::from_utf8(b).ok());
         let right_text = right_bytes
             .as_deref()
@@ -341,13 +407,18 @@ impl TurnDiffTracker {
             };
 
             let diff = similar::TextDiff::from_lines(l, r);
-            let unified = diff
-                .unified_diff()
-                .context_radius(3)
-                .header(&old_header, &new_header)
-                .to_string();
+            let mut unified = diff.unified_diff();
+            unified.context_radius(3).header(&old_header, &new_header);
+            file.hunks = unified.iter_hunks().count();
+            for change in diff.iter_all_changes() {
+                match change.tag() {
+                    ChangeTag::Insert => file.added_lines += 1,
+                    ChangeTag::Delete => file.removed_lines += 1,
+                    ChangeTag::Equal => {}
+                }
+            }
 
-            aggregated.push_str(&unified);
+            aggregated.push_str(&unified.to_string());
         } else {
             aggregated.push_str(&format!("index {left_oid}..{right_oid}\n"));
             let old_header = if left_present {
@@ -364,10 +435,202 @@ impl TurnDiffTracker {
             aggregated.push_str(&format!("+++ {new_header}\n"));
             aggregated.push_str("Binary files differ\n");
         }
-        aggregated
+        Some((aggregated, file))
+    }
+}
+
+/// The files of a Git worktree that differ from `HEAD`, captured before a
+/// command runs so that the files it changes can be added to the turn diff.
+/// Commands run outside of a Git repository are not tracked, and neither
+/// are changes to ignored files.
+pub struct WorktreeSnapshot {
+    root: PathBuf,
+    /// Baselines of the files that differed from `HEAD`, by absolute path.
+    dirty: HashMap<PathBuf, BaselineFileInfo>,
+    /// Files that differed from `HEAD` but were too large to capture.
+    skipped: HashSet<PathBuf>,
+}
+
+/// Baselines of the files a command changed; see [`WorktreeSnapshot`].
+pub struct WorktreeChanges(Vec<BaselineFileInfo>);
+
+impl WorktreeChanges {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 }
 
+impl WorktreeSnapshot {
+    /// Captures the worktree containing `cwd`. Runs `git`, so call it off
+    /// the async runtime.
+    pub fn capture(cwd: &Path) -> Option<Self> {
+        let root = git_output(cwd, &["rev-parse", "--show-toplevel"])?;
+        let root = PathBuf::from(String::from_utf8_lossy(&root).trim());
+        let dirty_paths = git_dirty_paths(&root)?;
+        if dirty_paths.len() > MAX_SNAPSHOT_FILES {
+            return None;
+        }
+        let mut dirty = HashMap::new();
+        let mut skipped = HashSet::new();
+        for path in dirty_paths {
+            let too_large = fs::symlink_metadata(&path)
+                .is_ok_and(|metadata| metadata.len() > MAX_SNAPSHOT_FILE_BYTES);
+            if too_large {
+                skipped.insert(path);
+                continue;
+            }
+            let info = baseline_from_disk(&path);
+            dirty.insert(path, info);
+        }
+        Some(Self {
+            root,
+            dirty,
+            skipped,
+        })
+    }
+
+    /// Returns the baselines of the files that changed since the snapshot was
+    /// captured. Runs `git`, so call it off the async runtime.
+    pub fn changes(mut self) -> WorktreeChanges {
+        let mut paths = git_dirty_paths(&self.root).unwrap_or_default();
+        // A file restored to its state in `HEAD` changed, too.
+        paths.extend(self.dirty.keys().cloned());
+        paths.sort();
+        paths.dedup();
+
+        let mut changes = Vec::new();
+        for path in paths {
+            if self.skipped.contains(&path) {
+                continue;
+            }
+            let baseline = match self.dirty.remove(&path) {
+                Some(before) => before,
+                None => baseline_from_head(&self.root, &path),
+            };
+            let current = baseline_from_disk(&path);
+            if baseline.oid != current.oid || baseline.mode != current.mode {
+                changes.push(baseline);
+            }
+        }
+        WorktreeChanges(changes)
+    }
+}
+
+/// Runs `git` in `dir` and returns its stdout if it succeeds.
+fn git_output(dir: &Path, args: &[&str]) -> Option<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .ok()?;
+    output.status.success().then_some(output.stdout)
+}
+
+/// Absolute paths of the files in the worktree at `root` that differ from
+/// `HEAD`, including untracked files that are not ignored.
+fn git_dirty_paths(root: &Path) -> Option<Vec<PathBuf>> {
+    let output = git_output(
+        root,
+        &[
+            "status",
+            "--porcelain=v1",
+            "-z",
+            "--untracked-files=all",
+            "--no-renames",
+        ],
+    )?;
+    Some(
+        output
+            .split(|b| *b == 0)
+            .filter(|entry| entry.len() > 3)
+            .map(|entry| root.join(String::from_utf8_lossy(&entry[3..]).as_ref()))
+            .collect(),
+    )
+}
+
+/// The current state of the file at `path`, as a baseline.
+fn baseline_from_disk(path: &Path) -> BaselineFileInfo {
+    let mode = file_mode_for_path(path).unwrap_or(FileMode::Regular);
+    match blob_bytes(path, &mode) {
+        Some(content) => BaselineFileInfo {
+            path: path.to_path_buf(),
+            oid: format!("{:x}", git_blob_sha1_hex_bytes(&content)),
+            content,
+            mode,
+        },
+        None => BaselineFileInfo {
+            path: path.to_path_buf(),
+            content: vec![],
+            mode: FileMode::Regular,
+            oid: ZERO_OID.to_string(),
+        },
+    }
+}
+
+/// The state of the file at `path` in `HEAD`, as a baseline.
+fn baseline_from_head(root: &Path, path: &Path) -> BaselineFileInfo {
+    let missing = BaselineFileInfo {
+        path: path.to_path_buf(),
+        content: vec![],
+        mode: FileMode::Regular,
+        oid: ZERO_OID.to_string(),
+    };
+    let Ok(rel) = path.strip_prefix(root) else {
+        return missing;
+    };
+    let Some(tree_entry) = git_output(
+        root,
+        &["ls-tree", "-z", "HEAD", "--", &rel.to_string_lossy()],
+    ) else {
+        return missing;
+    };
+    // `<mode> blob <oid>\t<path>`
+    let tree_entry = String::from_utf8_lossy(&tree_entry);
+    let mut fields = tree_entry.split(['\t', ' ']);
+    let (Some(mode), Some("blob"), Some(oid)) = (fields.next(), fields.next(), fields.next())
+    else {
+        return missing;
+    };
+    let mode = match mode {
+        "100755" => FileMode::Executable,
+        "120000" => FileMode::Symlink,
+        _ => FileMode::Regular,
+    };
+    let Some(content) = git_output(root, &["cat-file", "blob", oid]) else {
+        return missing;
+    };
+    BaselineFileInfo {
+        path: path.to_path_buf(),
+        content,
+        mode,
+        oid: oid.to_string(),
+    }
+}
+
+/// Tells the model which files it changed in a turn, so that it can take
+/// them into account later in the conversation.
+pub(crate) fn turn_changes_message(files: &[TurnDiffFile]) -> String {
+    let mut message = format!("{TURN_CHANGES_START}\nFiles changed in this turn:\n");
+    for file in files {
+        let marker = match file.change {
+            TurnDiffFileChange::Added => "A",
+            TurnDiffFileChange::Modified => "M",
+            TurnDiffFileChange::Deleted => "D",
+        };
+        let path = match &file.moved_from {
+            Some(from) => format!("{} -> {}", from.display(), file.path.display()),
+            None => file.path.display().to_string(),
+        };
+        message.push_str(&format!(
+            "{marker} {path} (+{} -{})\n",
+            file.added_lines, file.removed_lines
+        ));
+    }
+    message.push_str("</turn_changes>");
+    message
+}
+
 /// Compute the Git SHA-1 blob object ID for the given content (bytes).
 fn git_blob_sha1_hex_bytes(data: &[u8]) -> Output<sha1::Sha1> {
     // Git blob hash is sha1 of: "blob <len>\0<data>"
@@ -883,4 +1146,57 @@ index {ZERO_OID}..{right_oid}
         };
         assert_eq!(combined, expected_combined);
     }
+
+    #[test]
+    fn changes_made_by_commands_are_tracked() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().canonicalize().unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(args)
+                .current_dir(&repo)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?} failed");
+        };
+        git(&["init", "-q"]);
+        git(&["config", "user.email", "test@example.com"]);
+        git(&["config", "user.name", "Test"]);
+        fs::write(repo.join("a.txt"), "one\n").unwrap();
+        fs::write(repo.join("b.txt"), "keep\n").unwrap();
+        fs::write(repo.join("c.txt"), "drop\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "init"]);
+        // Changed before the command ran, and left alone by it.
+        fs::write(repo.join("b.txt"), "keep\nlocal\n").unwrap();
+
+        let snapshot = WorktreeSnapshot::capture(&repo).unwrap();
+        fs::write(repo.join("a.txt"), "one\ntwo\n").unwrap();
+        fs::remove_file(repo.join("c.txt")).unwrap();
+        fs::write(repo.join("d.txt"), "new\n").unwrap();
+        let mut acc = TurnDiffTracker::new();
+        acc.on_exec_end(snapshot.changes());
+
+        let files = acc.get_turn_diff().unwrap().files;
+        let file = |path: &str, change, added_lines, removed_lines| TurnDiffFile {
+            path: PathBuf::from(path),
+            moved_from: None,
+            change,
+            hunks: 1,
+            added_lines,
+            removed_lines,
+        };
+        assert_eq!(
+            files,
+            vec![
+                file("a.txt", TurnDiffFileChange::Modified, 1, 0),
+                file("c.txt", TurnDiffFileChange::Deleted, 0, 1),
+                file("d.txt", TurnDiffFileChange::Added, 1, 0),
+            ]
+        );
+        assert_eq!(
+            turn_changes_message(&files),
+            "<turn_changes>\nFiles changed in this turn:\nM a.txt (+1 -0)\nD c.txt (+0 -1)\nA d.txt (+1 -0)\n</turn_changes>"
+        );
+    }
 }
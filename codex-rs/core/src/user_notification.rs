@@ -1,4 +1,15 @@
+use std::time::Duration;
+
 use serde::Serialize;
+use tracing::error;
+use tracing::warn;
+
+use crate::config_types::NotificationEvent;
+use crate::config_types::NotificationsConfig;
+use crate::config_types::WebhookConfig;
+
+/// How long a webhook may take to accept a notification.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// User can configure a program that will receive notifications. Each
 /// notification is serialized as JSON and passed as an argument to the
@@ -16,11 +27,257 @@ pub(crate) enum UserNotification {
         /// The last message sent by the assistant in the turn.
         last_assistant_message: Option<String>,
     },
+
+    #[serde(rename_all = "kebab-case")]
+    ApprovalRequested {
+        turn_id: String,
+
+        /// What needs approval, e.g. the command to run.
+        summary: String,
+    },
+
+    #[serde(rename_all = "kebab-case")]
+    Error { turn_id: String, message: String },
+}
+
+impl UserNotification {
+    fn event(&self) -> NotificationEvent {
+        match self {
+            UserNotification::AgentTurnComplete { .. } => NotificationEvent::AgentTurnComplete,
+            UserNotification::ApprovalRequested { .. } => NotificationEvent::ApprovalRequested,
+            UserNotification::Error { .. } => NotificationEvent::Error,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            UserNotification::AgentTurnComplete { .. } => "agent-turn-complete",
+            UserNotification::ApprovalRequested { .. } => "approval-requested",
+            UserNotification::Error { .. } => "error",
+        }
+    }
+
+    fn turn_id(&self) -> &str {
+        match self {
+            UserNotification::AgentTurnComplete { turn_id, .. }
+            | UserNotification::ApprovalRequested { turn_id, .. }
+            | UserNotification::Error { turn_id, .. } => turn_id,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            UserNotification::AgentTurnComplete { .. } => "Codex: turn complete",
+            UserNotification::ApprovalRequested { .. } => "Codex: approval needed",
+            UserNotification::Error { .. } => "Codex: error",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            UserNotification::AgentTurnComplete {
+                input_messages,
+                last_assistant_message,
+                ..
+            } => last_assistant_message
+                .clone()
+                .unwrap_or_else(|| input_messages.join(" ")),
+            UserNotification::ApprovalRequested { summary, .. } => summary.clone(),
+            UserNotification::Error { message, .. } => message.clone(),
+        }
+    }
+}
+
+/// Delivers notifications to the `notify` program, the desktop and webhooks,
+/// according to `[notifications]`. Delivery happens in the background and
+/// failures are logged but otherwise ignored so that notification issues do
+/// not interfere with the main workflow.
+pub(crate) struct Notifier {
+    notify: Option<Vec<String>>,
+    config: NotificationsConfig,
+    client: reqwest::Client,
+}
+
+impl Notifier {
+    pub(crate) fn new(notify: Option<Vec<String>>, config: NotificationsConfig) -> Self {
+        Self {
+            notify,
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub(crate) fn notify(&self, notification: UserNotification) {
+        let event = notification.event();
+        if self.config.notify_events.contains(&event) {
+            self.run_notify_program(&notification);
+        }
+        if self.config.desktop.contains(&event) {
+            show_desktop_notification(notification.title(), &notification.message());
+        }
+        for webhook in &self.config.webhooks {
+            if webhook.events.contains(&event) {
+                self.post_webhook(webhook, &notification);
+            }
+        }
+    }
+
+    /// Spawn the configured notifier (if any) with the given JSON payload as
+    /// the last argument.
+    fn run_notify_program(&self, notification: &UserNotification) {
+        let Some(notify_command) = &self.notify else {
+            return;
+        };
+
+        if notify_command.is_empty() {
+            return;
+        }
+
+        let Ok(json) = serde_json::to_string(&notification) else {
+            error!("failed to serialise notification payload");
+            return;
+        };
+
+        let mut command = std::process::Command::new(&notify_command[0]);
+        if notify_command.len() > 1 {
+            command.args(&notify_command[1..]);
+        }
+        command.arg(json);
+
+        // Fire-and-forget – we do not wait for completion.
+        if let Err(e) = command.spawn() {
+            warn!("failed to spawn notifier '{}': {e}", notify_command[0]);
+        }
+    }
+
+    fn post_webhook(&self, webhook: &WebhookConfig, notification: &UserNotification) {
+        let content_type = webhook
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| value.as_str());
+        let json = content_type.is_none_or(|value| value.contains("json"));
+        let body = match render_webhook_body(webhook.template.as_deref(), notification, json) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("failed to serialise notification payload: {e}");
+                return;
+            }
+        };
+        let mut request = self
+            .client
+            .post(&webhook.url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .body(body);
+        if content_type.is_none() {
+            request = request.header("content-type", "application/json");
+        }
+        for (name, value) in &webhook.headers {
+            request = request.header(name, value);
+        }
+        let url = webhook.url.clone();
+        tokio::spawn(async move {
+            match request.send().await {
+                Ok(response) if !response.status().is_success() => {
+                    warn!("webhook {url} rejected notification: {}", response.status());
+                }
+                Ok(_) => {}
+                Err(e) => warn!("failed to post notification to webhook {url}: {e}"),
+            }
+        });
+    }
+}
+
+/// Fills in the placeholders of `template`, or serializes the notification
+/// when there is no template. Placeholders are replaced in a single pass, so
+/// a value that itself contains `{{...}}` is inserted as it is. With `json`,
+/// values are JSON-escaped so that they can be used inside JSON strings.
+fn render_webhook_body(
+    template: Option<&str>,
+    notification: &UserNotification,
+    json: bool,
+) -> serde_json::Result<String> {
+    let Some(template) = template else {
+        return serde_json::to_string(notification);
+    };
+    let escape = |value: &str| -> serde_json::Result<String> {
+        if !json {
+            return Ok(value.to_string());
+        }
+        let quoted = serde_json::to_string(value)?;
+        Ok(quoted[1..quoted.len() - 1].to_string())
+    };
+    let mut body = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        body.push_str(&rest[..start]);
+        let placeholder = rest[start + 2..]
+            .find("}}")
+            .map(|end| &rest[start + 2..start + 2 + end]);
+        let value = match placeholder {
+            Some("type") => Some(escape(notification.type_name())?),
+            Some("title") => Some(escape(notification.title())?),
+            Some("message") => Some(escape(&notification.message())?),
+            Some("turn_id") => Some(escape(notification.turn_id())?),
+            _ => None,
+        };
+        match (value, placeholder) {
+            (Some(value), Some(placeholder)) => {
+                body.push_str(&value);
+                rest = &rest[start + placeholder.len() + 4..];
+            }
+            _ => {
+                body.push_str("{{");
+                rest = &rest[start + 2..];
+            }
+        }
+    }
+    body.push_str(rest);
+    Ok(body)
+}
+
+/// Shows a notification with the notification service of the platform. The
+/// text is passed through the environment so that it needs no quoting.
+fn show_desktop_notification(title: &str, message: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = std::process::Command::new("osascript");
+        command.args([
+            "-e",
+            "display notification (system attribute \"CODEX_NOTIFICATION_MESSAGE\") with title (system attribute \"CODEX_NOTIFICATION_TITLE\")",
+        ]);
+        command
+    } else if cfg!(windows) {
+        let mut command = std::process::Command::new("powershell");
+        command.args([
+            "-NoProfile",
+            "-Command",
+            "[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
+             $xml = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+             $text = $xml.GetElementsByTagName('text'); \
+             $text.Item(0).AppendChild($xml.CreateTextNode($env:CODEX_NOTIFICATION_TITLE)) > $null; \
+             $text.Item(1).AppendChild($xml.CreateTextNode($env:CODEX_NOTIFICATION_MESSAGE)) > $null; \
+             [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('Codex').Show([Windows.UI.Notifications.ToastNotification]::new($xml))",
+        ]);
+        command
+    } else {
+        let mut command = std::process::Command::new("notify-send");
+        command.args(["--app-name=Codex", "--", title, message]);
+        command
+    };
+    command
+        .env("CODEX_NOTIFICATION_TITLE", title)
+        .env("CODEX_NOTIFICATION_MESSAGE", message)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    if let Err(e) = command.spawn() {
+        warn!("failed to show desktop notification: {e}");
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use pretty_assertions::assert_eq;
 
     #[test]
     fn test_user_notification() {
@@ -37,4 +294,53 @@ mod tests {
             r#"{"type":"agent-turn-complete","turn-id":"12345","input-messages":["Rename `foo` to `bar` and update the callsites."],"last-assistant-message":"Rename complete and verified `cargo build` succeeds."}"#
         );
     }
+
+    #[test]
+    fn webhook_templates_are_filled_in_with_escaped_values() {
+        let notification = UserNotification::ApprovalRequested {
+            turn_id: "7".to_string(),
+            summary: "rm -rf \"build\"".to_string(),
+        };
+
+        let body = render_webhook_body(
+            Some(r#"{"text": "*{{title}}* ({{type}}, turn {{turn_id}}): {{message}}"}"#),
+            &notification,
+            true,
+        )
+        .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            body["text"],
+            r#"*Codex: approval needed* (approval-requested, turn 7): rm -rf "build""#
+        );
+
+        assert_eq!(
+            render_webhook_body(None, &notification, true).unwrap(),
+            r#"{"type":"approval-requested","turn-id":"7","summary":"rm -rf \"build\""}"#
+        );
+    }
+
+    #[test]
+    fn placeholders_in_values_are_not_expanded() {
+        let notification = UserNotification::AgentTurnComplete {
+            turn_id: "12".to_string(),
+            input_messages: Vec::new(),
+            last_assistant_message: Some("use {{turn_id}} and {{title}}\", {{".to_string()),
+        };
+        let template = "{{message}} in turn {{turn_id}} {{unknown}}";
+
+        assert_eq!(
+            render_webhook_body(Some(template), &notification, false).unwrap(),
+            "use {{turn_id}} and {{title}}\", {{ in turn 12 {{unknown}}"
+        );
+        let body = render_webhook_body(
+            Some(r#"{"text": "{{message}}", "turn": "{{turn_id}}"}"#),
+            &notification,
+            true,
+        )
+        .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["text"], "use {{turn_id}} and {{title}}\", {{");
+        assert_eq!(body["turn"], "12");
+    }
 }
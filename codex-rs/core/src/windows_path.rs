@@ -0,0 +1,170 @@
+//! Normalization of Windows paths, so a path built from a cwd and a path the
+//! model wrote with `/` names the same file as the one Windows reports, and
+//! paths past `MAX_PATH` or on a network share can still be opened.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Longest path the Win32 APIs accept without the `\\?\` prefix.
+const MAX_PATH: usize = 260;
+
+const VERBATIM_PREFIX: &str = r"\\?\";
+const VERBATIM_UNC_PREFIX: &str = r"\\?\UNC\";
+const DEVICE_PREFIX: &str = r"\\.\";
+
+/// `path` normalized with [`normalize`] on Windows and unchanged elsewhere.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(path) if cfg!(windows) => PathBuf::from(normalize(path)),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// `path` with `\` separators and `.` and `..` resolved. Absolute paths
+/// carry the `\\?\` prefix only when they are too long to go without it,
+/// so tools that cannot handle it (`cmd.exe` among them) keep working.
+/// Device paths, and verbatim paths that name neither a drive nor a share,
+/// are returned unchanged since `/` is an ordinary character in them.
+pub(crate) fn normalize(path: &str) -> String {
+    if path.starts_with(DEVICE_PREFIX) {
+        return path.to_string();
+    }
+    let (root, rest) = if let Some(rest) = path.strip_prefix(VERBATIM_UNC_PREFIX) {
+        match split_share(rest) {
+            Some((share, rest)) => (Root::Share(share), rest),
+            None => return path.to_string(),
+        }
+    } else if let Some(rest) = path.strip_prefix(VERBATIM_PREFIX) {
+        match split_drive(rest) {
+            Some((drive, rest)) => (Root::Drive(drive), rest),
+            None => return path.to_string(),
+        }
+    } else {
+        let path = path.replace('/', "\\");
+        if let Some(rest) = path.strip_prefix(r"\\") {
+            match split_share(rest) {
+                Some((share, rest)) => (Root::Share(share), rest.to_string()),
+                None => return path,
+            }
+        } else if let Some((drive, rest)) = split_drive(&path) {
+            (Root::Drive(drive), rest)
+        } else {
+            return collapse_separators(&path);
+        }
+    };
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in rest.split('\\') {
+        match component {
+            "" | "." => {}
+            // Windows resolves `..` by name too, before following links.
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    let rest = components.join("\\");
+    let normalized = match &root {
+        Root::Drive(drive) => format!(r"{drive}\{rest}"),
+        Root::Share(share) if rest.is_empty() => format!(r"\\{share}"),
+        Root::Share(share) => format!(r"\\{share}\{rest}"),
+    };
+    if normalized.len() < MAX_PATH {
+        return normalized;
+    }
+    match root {
+        Root::Drive(_) => format!("{VERBATIM_PREFIX}{normalized}"),
+        Root::Share(_) => format!("{VERBATIM_UNC_PREFIX}{}", &normalized[2..]),
+    }
+}
+
+enum Root {
+    /// `C:`
+    Drive(String),
+    /// `server\share`
+    Share(String),
+}
+
+/// The drive of an absolute path such as `C:\dir`, and the rest of it.
+fn split_drive(path: &str) -> Option<(String, String)> {
+    let mut chars = path.chars();
+    let letter = chars.next().filter(char::is_ascii_alphabetic)?;
+    let rest = chars.as_str().strip_prefix(':')?;
+    if !(rest.is_empty() || rest.starts_with('\\')) {
+        // `C:dir` is relative to the cwd of drive C.
+        return None;
+    }
+    Some((
+        format!("{}:", letter.to_ascii_uppercase()),
+        rest.to_string(),
+    ))
+}
+
+/// The `server\share` of a UNC path without its leading `\\`, and the rest.
+fn split_share(path: &str) -> Option<(String, String)> {
+    let mut parts = path.splitn(3, '\\');
+    let server = parts.next().filter(|server| !server.is_empty())?;
+    let share = parts.next().filter(|share| !share.is_empty())?;
+    let rest = parts.next().unwrap_or_default();
+    Some((format!(r"{server}\{share}"), rest.to_string()))
+}
+
+fn collapse_separators(path: &str) -> String {
+    let mut collapsed = String::with_capacity(path.len());
+    for c in path.chars() {
+        if !(c == '\\' && collapsed.ends_with('\\')) {
+            collapsed.push(c);
+        }
+    }
+    collapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn normalizes_separators_and_dots() {
+        assert_eq!(normalize(r"C:\repo\src/main.rs"), r"C:\repo\src\main.rs");
+        assert_eq!(
+            normalize(r"c:/repo//./src/../Cargo.toml"),
+            r"C:\repo\Cargo.toml"
+        );
+        assert_eq!(normalize(r"C:\.."), r"C:\");
+        assert_eq!(normalize("src//lib.rs"), r"src\lib.rs");
+        assert_eq!(normalize("C:dir/file"), r"C:dir\file");
+    }
+
+    #[test]
+    fn handles_unc_and_verbatim_paths() {
+        assert_eq!(
+            normalize("//server/share/dir/file.txt"),
+            r"\\server\share\dir\file.txt"
+        );
+        assert_eq!(normalize(r"\\?\C:\repo\src"), r"C:\repo\src");
+        assert_eq!(
+            normalize(r"\\?\UNC\server\share\dir"),
+            r"\\server\share\dir"
+        );
+        assert_eq!(normalize(r"\\.\pipe\codex"), r"\\.\pipe\codex");
+        assert_eq!(
+            normalize(r"\\?\Volume{1234}\dir/file"),
+            r"\\?\Volume{1234}\dir/file"
+        );
+    }
+
+    #[test]
+    fn keeps_the_verbatim_prefix_on_long_paths() {
+        let long = "d".repeat(MAX_PATH);
+        assert_eq!(
+            normalize(&format!("C:/repo/{long}")),
+            format!(r"\\?\C:\repo\{long}")
+        );
+        assert_eq!(
+            normalize(&format!(r"\\server\share\{long}")),
+            format!(r"\\?\UNC\server\share\{long}")
+        );
+    }
+}
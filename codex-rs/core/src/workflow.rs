@@ -0,0 +1,677 @@
+//! Declarative workflows: TOML files in `$CODEX_HOME/workflows` that chain
+//! tool calls and model prompts, with conditions on the results of earlier
+//! steps and approvals before steps that should not run unattended. A
+//! workflow runs in a session through [`crate::protocol::Op::RunWorkflow`].
+//!
+//! Strings in a step's `arguments`, `prompt` and `approval` may reference
+//! `{{inputs.NAME}}` and `{{steps.ID.output}}`, `{{steps.ID.status}}` or
+//! `{{steps.ID.json.PATH}}`, where `PATH` is a dotted path into the step's
+//! output parsed as JSON. `when` conditions compare such references with
+//! `==`, `!=`, `<`, `<=`, `>`, `>=` and `contains`, joined by `and`, `or`
+//! and `not`.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use chrono::DateTime;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Most characters of a step's output kept in the step log.
+const MAX_LOGGED_CHARS: usize = 4000;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workflow {
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// Inputs with their default values; a run can override them.
+    #[serde(default)]
+    pub inputs: BTreeMap<String, Value>,
+
+    pub steps: Vec<WorkflowStep>,
+}
+
+/// One step of a [`Workflow`]: either a `tool` call or a `prompt` for the
+/// model.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkflowStep {
+    /// Name later steps refer to the step by.
+    pub id: String,
+
+    /// Tool to call, as the model would call it.
+    #[serde(default)]
+    pub tool: Option<String>,
+
+    /// Arguments of the tool call.
+    #[serde(default)]
+    pub arguments: Value,
+
+    /// Prompt the model answers, using tools as it sees fit.
+    #[serde(default)]
+    pub prompt: Option<String>,
+
+    /// Condition the step runs under; it is skipped otherwise.
+    #[serde(default)]
+    pub when: Option<String>,
+
+    /// Ask for approval with this message before running the step.
+    #[serde(default)]
+    pub approval: Option<String>,
+
+    /// Go on with the next step when this one fails.
+    #[serde(default)]
+    pub continue_on_error: bool,
+}
+
+/// What a step of a run did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Succeeded,
+    Failed,
+    /// Its `when` condition did not hold.
+    Skipped,
+    /// Its approval was denied.
+    Denied,
+}
+
+impl StepStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StepStatus::Succeeded => "succeeded",
+            StepStatus::Failed => "failed",
+            StepStatus::Skipped => "skipped",
+            StepStatus::Denied => "denied",
+        }
+    }
+}
+
+/// The directory workflows are loaded from.
+pub fn workflows_dir(codex_home: &Path) -> PathBuf {
+    codex_home.join("workflows")
+}
+
+/// Loads and checks the workflow `name` from `dir`.
+pub fn load_workflow(dir: &Path, name: &str) -> Result<Workflow, String> {
+    if !is_identifier(name) {
+        return Err(format!("`{name}` is not a workflow name"));
+    }
+    let path = dir.join(format!("{name}.toml"));
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| format!("failed to read workflow {}: {e}", path.display()))?;
+    let workflow: Workflow = toml::from_str(&contents)
+        .map_err(|e| format!("workflow {} is not valid: {e}", path.display()))?;
+    workflow.validate()?;
+    Ok(workflow)
+}
+
+impl Workflow {
+    fn validate(&self) -> Result<(), String> {
+        if self.steps.is_empty() {
+            return Err("the workflow has no steps".to_string());
+        }
+        let mut ids = HashSet::new();
+        for step in &self.steps {
+            let id = &step.id;
+            if !is_identifier(id) {
+                return Err(format!(
+                    "step id `{id}` may only have letters, digits, `-` and `_`"
+                ));
+            }
+            if !ids.insert(id.as_str()) {
+                return Err(format!("step id `{id}` is used twice"));
+            }
+            if step.tool.is_some() == step.prompt.is_some() {
+                return Err(format!("step `{id}` must have either a tool or a prompt"));
+            }
+            if let Some(when) = &step.when {
+                parse_condition(when).map_err(|e| format!("step `{id}`: {e}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+struct StepResult {
+    status: StepStatus,
+    output: String,
+}
+
+/// The inputs of a run and the results of the steps that ran so far.
+pub(crate) struct WorkflowRun {
+    inputs: BTreeMap<String, String>,
+    steps: HashMap<String, StepResult>,
+}
+
+impl WorkflowRun {
+    /// The workflow's input defaults with `inputs` applied over them.
+    pub(crate) fn new(workflow: &Workflow, inputs: HashMap<String, String>) -> Self {
+        let mut merged: BTreeMap<String, String> = workflow
+            .inputs
+            .iter()
+            .map(|(name, value)| (name.clone(), value_text(value)))
+            .collect();
+        merged.extend(inputs);
+        Self {
+            inputs: merged,
+            steps: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, id: &str, status: StepStatus, output: String) {
+        self.steps
+            .insert(id.to_string(), StepResult { status, output });
+    }
+
+    /// `template` with its `{{...}}` references replaced.
+    pub(crate) fn render(&self, template: &str) -> Result<String, String> {
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            rendered.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or_else(|| format!("`{{{{` without `}}}}` in `{template}`"))?;
+            rendered.push_str(&self.resolve(after[..end].trim())?);
+            rest = &after[end + 2..];
+        }
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+
+    /// `value` with the references in all of its strings replaced.
+    pub(crate) fn render_value(&self, value: &Value) -> Result<Value, String> {
+        Ok(match value {
+            Value::String(text) => Value::String(self.render(text)?),
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.render_value(item))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(name, field)| Ok((name.clone(), self.render_value(field)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            other => other.clone(),
+        })
+    }
+
+    /// Whether the condition `when` holds.
+    pub(crate) fn evaluate(&self, when: &str) -> Result<bool, String> {
+        self.eval(&parse_condition(when)?)
+    }
+
+    fn resolve(&self, reference: &str) -> Result<String, String> {
+        let parts: Vec<&str> = reference.split('.').collect();
+        match parts[..] {
+            ["inputs", name] => self
+                .inputs
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("there is no input `{name}`")),
+            ["steps", id, field, ref path @ ..] => {
+                let step = self
+                    .steps
+                    .get(id)
+                    .ok_or_else(|| format!("step `{id}` has not run"))?;
+                match (field, path) {
+                    ("output", []) => Ok(step.output.clone()),
+                    ("status", []) => Ok(step.status.as_str().to_string()),
+                    ("json", path) => {
+                        let mut value: Value = serde_json::from_str(&step.output)
+                            .map_err(|_| format!("the output of step `{id}` is not JSON"))?;
+                        for key in path {
+                            value = match value {
+                                Value::Object(mut fields) => fields.remove(*key),
+                                Value::Array(mut items) => key
+                                    .parse::<usize>()
+                                    .ok()
+                                    .filter(|index| *index < items.len())
+                                    .map(|index| items.swap_remove(index)),
+                                _ => None,
+                            }
+                            .ok_or_else(|| format!("`{reference}` is not in the output"))?;
+                        }
+                        Ok(value_text(&value))
+                    }
+                    _ => Err(format!("`{reference}` is not a reference")),
+                }
+            }
+            _ => Err(format!("`{reference}` is not a reference")),
+        }
+    }
+
+    fn eval(&self, condition: &Condition) -> Result<bool, String> {
+        Ok(match condition {
+            Condition::Or(left, right) => self.eval(left)? || self.eval(right)?,
+            Condition::And(left, right) => self.eval(left)? && self.eval(right)?,
+            Condition::Not(inner) => !self.eval(inner)?,
+            Condition::Truthy(operand) => {
+                let value = self.operand(operand)?;
+                !matches!(value.trim(), "" | "0" | "false")
+            }
+            Condition::Compare(left, op, right) => {
+                let left = self.operand(left)?;
+                let right = self.operand(right)?;
+                let numbers = left
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .zip(right.trim().parse::<f64>().ok());
+                match (op, numbers) {
+                    (CompareOp::Contains, _) => left.contains(&right),
+                    (CompareOp::Eq, Some((l, r))) => l == r,
+                    (CompareOp::Ne, Some((l, r))) => l != r,
+                    (CompareOp::Eq, None) => left == right,
+                    (CompareOp::Ne, None) => left != right,
+                    (CompareOp::Lt, Some((l, r))) => l < r,
+                    (CompareOp::Le, Some((l, r))) => l <= r,
+                    (CompareOp::Gt, Some((l, r))) => l > r,
+                    (CompareOp::Ge, Some((l, r))) => l >= r,
+                    (_, None) => {
+                        return Err(format!("cannot compare `{left}` and `{right}` as numbers"));
+                    }
+                }
+            }
+        })
+    }
+
+    fn operand(&self, operand: &Operand) -> Result<String, String> {
+        match operand {
+            Operand::Literal(text) => Ok(text.clone()),
+            Operand::Reference(reference) => self.resolve(reference),
+        }
+    }
+}
+
+/// The text a JSON value stands for in templates and conditions.
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Condition {
+    Or(Box<Condition>, Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+    Compare(Operand, CompareOp, Operand),
+    Truthy(Operand),
+}
+
+#[derive(Debug, PartialEq)]
+enum Operand {
+    Literal(String),
+    Reference(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(CompareOp),
+}
+
+fn tokenize(condition: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = condition.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some(next) if next == c => break,
+                    Some(next) => text.push(next),
+                    None => return Err(format!("unterminated string in `{condition}`")),
+                }
+            }
+            tokens.push(Token::Quoted(text));
+        } else if matches!(c, '=' | '!' | '<' | '>') {
+            chars.next();
+            let equals = chars.next_if_eq(&'=').is_some();
+            tokens.push(Token::Op(match (c, equals) {
+                ('=', true) => CompareOp::Eq,
+                ('!', true) => CompareOp::Ne,
+                ('<', false) => CompareOp::Lt,
+                ('<', true) => CompareOp::Le,
+                ('>', false) => CompareOp::Gt,
+                ('>', true) => CompareOp::Ge,
+                _ => return Err(format!("`{c}` is not an operator in `{condition}`")),
+            }));
+        } else {
+            let mut word = String::new();
+            while let Some(&next) = chars.peek() {
+                if next.is_whitespace() || matches!(next, '=' | '!' | '<' | '>' | '"' | '\'') {
+                    break;
+                }
+                word.push(next);
+                chars.next();
+            }
+            tokens.push(match word.as_str() {
+                "contains" => Token::Op(CompareOp::Contains),
+                _ => Token::Word(word),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_condition(condition: &str) -> Result<Condition, String> {
+    let tokens = tokenize(condition)?;
+    let mut parser = ConditionParser {
+        tokens: &tokens,
+        position: 0,
+    };
+    let parsed = parser.or()?;
+    if parser.position < tokens.len() {
+        return Err(format!(
+            "unexpected `{:?}` in `{condition}`",
+            tokens[parser.position]
+        ));
+    }
+    Ok(parsed)
+}
+
+/// Recursive descent over `or` > `and` > `not` > comparison.
+struct ConditionParser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl ConditionParser<'_> {
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found =
+            matches!(self.tokens.get(self.position), Some(Token::Word(word)) if word == keyword);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.keyword("or") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.not()?;
+        while self.keyword("and") {
+            condition = Condition::And(Box::new(condition), Box::new(self.not()?));
+        }
+        Ok(condition)
+    }
+
+    fn not(&mut self) -> Result<Condition, String> {
+        if self.keyword("not") {
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        let left = self.operand()?;
+        match self.tokens.get(self.position) {
+            Some(Token::Op(op)) => {
+                self.position += 1;
+                Ok(Condition::Compare(left, *op, self.operand()?))
+            }
+            _ => Ok(Condition::Truthy(left)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| "the condition ends too early".to_string())?;
+        self.position += 1;
+        match token {
+            Token::Quoted(text) => Ok(Operand::Literal(text.clone())),
+            Token::Word(word) if word.starts_with("inputs.") || word.starts_with("steps.") => {
+                Ok(Operand::Reference(word.clone()))
+            }
+            Token::Word(word)
+                if word.parse::<f64>().is_ok() || word == "true" || word == "false" =>
+            {
+                Ok(Operand::Literal(word.clone()))
+            }
+            Token::Word(word) => Err(format!(
+                "`{word}` is not a reference, number or quoted string"
+            )),
+            Token::Op(op) => Err(format!("expected a value, found `{op:?}`")),
+        }
+    }
+}
+
+/// One line of a run's step log.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct StepLogEntry<'a> {
+    pub(crate) workflow: &'a str,
+    pub(crate) step: &'a str,
+    pub(crate) status: StepStatus,
+    pub(crate) started_at: DateTime<Utc>,
+    pub(crate) duration_ms: u128,
+    pub(crate) output: String,
+}
+
+/// The step log of one run: a JSON line per step in
+/// `$CODEX_HOME/workflows/logs`.
+pub(crate) struct StepLog {
+    path: PathBuf,
+}
+
+impl StepLog {
+    pub(crate) fn new(codex_home: &Path, workflow: &str, started_at: DateTime<Utc>) -> Self {
+        let file = format!(
+            "{workflow}-{}.jsonl",
+            started_at.format("%Y%m%dT%H%M%S%.3fZ")
+        );
+        Self {
+            path: workflows_dir(codex_home).join("logs").join(file),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) async fn append(&self, mut entry: StepLogEntry<'_>) -> std::io::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        if let Some((end, _)) = entry.output.char_indices().nth(MAX_LOGGED_CHARS) {
+            entry.output.truncate(end);
+            entry.output.push('…');
+        }
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(line.as_bytes()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const WORKFLOW: &str = r#"
+description = "Open a bug when checkout errors spike"
+
+[inputs]
+threshold = 5
+
+[[steps]]
+id = "errors"
+tool = "kusto__query"
+arguments = { query = "Errors | where Service == 'checkout'" }
+
+[[steps]]
+id = "bug"
+when = "steps.errors.json.rate > inputs.threshold"
+approval = "Open a bug for an error rate of {{steps.errors.json.rate}}%?"
+tool = "ticket_create"
+arguments = { summary = "Checkout error rate at {{ steps.errors.json.rate }}%", labels = ["{{inputs.team}}"] }
+
+[[steps]]
+id = "notify"
+when = "steps.bug.status == 'succeeded' and not steps.bug.output contains 'DRY RUN'"
+prompt = "Tell #ops about {{steps.bug.output}}."
+"#;
+
+    fn workflow() -> Workflow {
+        let workflow: Workflow = toml::from_str(WORKFLOW).unwrap();
+        workflow.validate().unwrap();
+        workflow
+    }
+
+    #[test]
+    fn renders_references_and_evaluates_conditions() {
+        let workflow = workflow();
+        let mut run = WorkflowRun::new(
+            &workflow,
+            HashMap::from([("team".to_string(), "payments".to_string())]),
+        );
+        run.record(
+            "errors",
+            StepStatus::Succeeded,
+            r#"{"rate": 7.5, "samples": [{"code": 502}]}"#.to_string(),
+        );
+
+        let bug = &workflow.steps[1];
+        assert!(run.evaluate(bug.when.as_deref().unwrap()).unwrap());
+        assert_eq!(
+            run.render(bug.approval.as_deref().unwrap()).unwrap(),
+            "Open a bug for an error rate of 7.5%?"
+        );
+        assert_eq!(
+            run.render_value(&bug.arguments).unwrap(),
+            serde_json::json!({
+                "summary": "Checkout error rate at 7.5%",
+                "labels": ["payments"],
+            })
+        );
+        assert_eq!(
+            run.render("first code {{steps.errors.json.samples.0.code}}")
+                .unwrap(),
+            "first code 502"
+        );
+
+        let notify = workflow.steps[2].when.as_deref().unwrap();
+        assert_eq!(
+            run.evaluate(notify),
+            Err("step `bug` has not run".to_string())
+        );
+        run.record("bug", StepStatus::Succeeded, "Created PAY-12".to_string());
+        assert!(run.evaluate(notify).unwrap());
+        run.record(
+            "bug",
+            StepStatus::Succeeded,
+            "DRY RUN: would create".to_string(),
+        );
+        assert!(!run.evaluate(notify).unwrap());
+    }
+
+    #[test]
+    fn parses_conditions_with_precedence() {
+        assert_eq!(
+            parse_condition("not steps.a.output or inputs.x>=2 and 'a'!=\"b\"").unwrap(),
+            Condition::Or(
+                Box::new(Condition::Not(Box::new(Condition::Truthy(
+                    Operand::Reference("steps.a.output".to_string())
+                )))),
+                Box::new(Condition::And(
+                    Box::new(Condition::Compare(
+                        Operand::Reference("inputs.x".to_string()),
+                        CompareOp::Ge,
+                        Operand::Literal("2".to_string()),
+                    )),
+                    Box::new(Condition::Compare(
+                        Operand::Literal("a".to_string()),
+                        CompareOp::Ne,
+                        Operand::Literal("b".to_string()),
+                    )),
+                )),
+            )
+        );
+        assert_eq!(
+            parse_condition("error_rate > 5"),
+            Err("`error_rate` is not a reference, number or quoted string".to_string())
+        );
+        assert_eq!(
+            parse_condition("steps.a.output ="),
+            Err("`=` is not an operator in `steps.a.output =`".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_workflows() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("twice.toml"),
+            "[[steps]]\nid = \"a\"\ntool = \"git_status\"\n\n[[steps]]\nid = \"a\"\nprompt = \"hi\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("both.toml"),
+            "[[steps]]\nid = \"a\"\ntool = \"git_status\"\nprompt = \"hi\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            load_workflow(dir.path(), "twice"),
+            Err("step id `a` is used twice".to_string())
+        );
+        assert_eq!(
+            load_workflow(dir.path(), "both"),
+            Err("step `a` must have either a tool or a prompt".to_string())
+        );
+        assert_eq!(
+            load_workflow(dir.path(), "../secrets"),
+            Err("`../secrets` is not a workflow name".to_string())
+        );
+    }
+}
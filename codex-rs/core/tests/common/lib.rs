@@ -9,10 +9,15 @@ use codex_core::config::ConfigToml;
 
 /// Returns a default `Config` whose on-disk state is confined to the provided
 /// temporary directory. Using a per-test directory keeps tests hermetic and
-/// avoids clobbering a developer’s real `~/.codex`.
+/// avoids clobbering a developer’s real `~/.codex`. The environment snapshot
+/// is turned off so that requests do not depend on the machine running the
+/// tests.
 pub fn load_default_config_for_test(codex_home: &TempDir) -> Config {
     Config::load_from_base_config_with_overrides(
-        ConfigToml::default(),
+        ConfigToml {
+            environment_snapshot: Some(false),
+            ..Default::default()
+        },
         ConfigOverrides::default(),
         codex_home.path().to_path_buf(),
     )
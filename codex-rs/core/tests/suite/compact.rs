@@ -249,3 +249,95 @@ async fn summarize_context_three_requests_and_instructions() {
         "third request should not include the summarize trigger"
     );
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn auto_compacts_older_turns_when_history_exceeds_limit() {
+    if std::env::var(CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR).is_ok() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    let server = MockServer::start().await;
+
+    let sse1 = sse(vec![
+        ev_assistant_message("m1", FIRST_REPLY),
+        ev_completed("r1"),
+    ]);
+    let sse2 = sse(vec![
+        ev_assistant_message("m2", SUMMARY_TEXT),
+        ev_completed("r2"),
+    ]);
+    let sse3 = sse(vec![ev_completed("r3")]);
+
+    let is_summary_request = |body: &str| body.contains("You are a summarization assistant");
+    mount_sse_once(
+        &server,
+        move |req: &wiremock::Request| {
+            let body = std::str::from_utf8(&req.body).unwrap_or("");
+            body.contains("\"text\":\"hello world\"") && !is_summary_request(body)
+        },
+        sse1,
+    )
+    .await;
+    mount_sse_once(
+        &server,
+        move |req: &wiremock::Request| {
+            is_summary_request(std::str::from_utf8(&req.body).unwrap_or(""))
+        },
+        sse2,
+    )
+    .await;
+    mount_sse_once(
+        &server,
+        move |req: &wiremock::Request| {
+            let body = std::str::from_utf8(&req.body).unwrap_or("");
+            body.contains(&format!("\"text\":\"{THIRD_USER_MSG}\"")) && !is_summary_request(body)
+        },
+        sse3,
+    )
+    .await;
+
+    let model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = model_provider;
+    // Any history with an earlier turn is over the limit.
+    config.model_auto_compact_token_limit = Some(1);
+    let conversation_manager = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"));
+    let codex = conversation_manager
+        .new_conversation(config)
+        .await
+        .unwrap()
+        .conversation;
+
+    for text in ["hello world", THIRD_USER_MSG] {
+        codex
+            .submit(Op::UserInput {
+                items: vec![InputItem::Text { text: text.into() }],
+            })
+            .await
+            .unwrap();
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+    }
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 3, "expected turn, summary, turn");
+
+    // The summary request covers the first turn but not the new input.
+    let body2 = std::str::from_utf8(&requests[1].body).unwrap();
+    assert!(body2.contains("hello world"));
+    assert!(body2.contains(FIRST_REPLY));
+    assert!(!body2.contains(THIRD_USER_MSG));
+
+    // The next turn sees the summary in place of the first turn.
+    let body3 = std::str::from_utf8(&requests[2].body).unwrap();
+    assert!(body3.contains(SUMMARY_TEXT));
+    assert!(body3.contains("<environment_context>"));
+    assert!(!body3.contains("hello world"));
+    assert!(!body3.contains(FIRST_REPLY));
+}
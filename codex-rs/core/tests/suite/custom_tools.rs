@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use codex_core::ConversationManager;
+use codex_core::ModelProviderInfo;
+use codex_core::built_in_model_providers;
+use codex_core::config_types::CustomToolConfig;
+use codex_core::config_types::CustomToolHttpMethod;
+use codex_core::protocol::AskForApproval;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+use codex_core::protocol::ReviewDecision;
+use codex_core::spawn::CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR;
+use codex_login::CodexAuth;
+use core_test_support::load_default_config_for_test;
+use core_test_support::load_sse_fixture_with_id_from_str;
+use core_test_support::wait_for_event;
+use tempfile::TempDir;
+use wiremock::Mock;
+use wiremock::MockServer;
+use wiremock::ResponseTemplate;
+use wiremock::matchers::method;
+use wiremock::matchers::path;
+
+fn sse_response(raw: &str, id: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200)
+        .insert_header("content-type", "text/event-stream")
+        .set_body_raw(
+            load_sse_fixture_with_id_from_str(raw, id),
+            "text/event-stream",
+        )
+}
+
+const TICKET_CALL: &str = r#"[
+  {"type": "response.output_item.done", "item": {
+    "type": "function_call",
+    "name": "ticket",
+    "arguments": "{\"key\":\"AB-1\"}",
+    "call_id": "call1"
+  }},
+  {"type": "response.completed", "response": {"id": "__ID__"}}
+]"#;
+
+const COMPLETED: &str = r#"[
+  {"type": "response.completed", "response": {"id": "__ID__"}}
+]"#;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn command_tools_are_approved_and_reported_like_shell_calls() {
+    if std::env::var(CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR).is_ok() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(sse_response(TICKET_CALL, "resp1"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(sse_response(COMPLETED, "resp2"))
+        .mount(&server)
+        .await;
+
+    let home = TempDir::new().unwrap();
+    let cwd = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.cwd = cwd.path().to_path_buf();
+    config.approval_policy = AskForApproval::UnlessTrusted;
+    config.model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    let command = vec![
+        "sh".to_string(),
+        "-c".to_string(),
+        "echo \"$0 $CODEX_TOOL_ARGUMENTS\"".to_string(),
+        "{key}".to_string(),
+    ];
+    config.custom_tools = HashMap::from([(
+        "ticket".to_string(),
+        CustomToolConfig {
+            description: "Show a ticket.".to_string(),
+            parameters: None,
+            command: Some(command.clone()),
+            url: None,
+            method: CustomToolHttpMethod::default(),
+            headers: HashMap::new(),
+            env_headers: HashMap::new(),
+            timeout_ms: None,
+            read_only: false,
+        },
+    )]);
+    let conversation_manager = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"));
+    let codex = conversation_manager
+        .new_conversation(config)
+        .await
+        .unwrap()
+        .conversation;
+
+    codex
+        .submit(Op::UserInput {
+            items: vec![InputItem::Text {
+                text: "show AB-1".to_string(),
+            }],
+        })
+        .await
+        .unwrap();
+
+    let expected_command = vec![
+        command[0].clone(),
+        command[1].clone(),
+        command[2].clone(),
+        "AB-1".to_string(),
+    ];
+    let approval = loop {
+        let event = codex.next_event().await.unwrap();
+        if let EventMsg::ExecApprovalRequest(request) = event.msg {
+            assert_eq!(request.command, expected_command);
+            break event.id;
+        }
+    };
+    codex
+        .submit(Op::ExecApproval {
+            id: approval,
+            decision: ReviewDecision::Approved,
+        })
+        .await
+        .unwrap();
+    let EventMsg::ExecCommandBegin(begin) =
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::ExecCommandBegin(_))).await
+    else {
+        panic!("expected ExecCommandBegin");
+    };
+    assert_eq!(begin.call_id, "call1");
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::ExecCommandEnd(_))).await;
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    let body = requests[1].body_json::<serde_json::Value>().unwrap();
+    let output = body["input"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["type"] == "function_call_output")
+        .unwrap();
+    let content: serde_json::Value =
+        serde_json::from_str(output["output"].as_str().unwrap()).unwrap();
+    assert_eq!(content["output"], "AB-1 {\"key\":\"AB-1\"}\n");
+}
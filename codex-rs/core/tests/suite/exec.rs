@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 
+use codex_core::config_types::ExecLimits;
 use codex_core::exec::ExecParams;
 use codex_core::exec::ExecToolCallOutput;
 use codex_core::exec::SandboxType;
@@ -35,11 +36,20 @@ async fn run_test_cmd(tmp: TempDir, cmd: Vec<&str>) -> Result<ExecToolCallOutput
         env: HashMap::new(),
         with_escalated_permissions: None,
         justification: None,
+        limits: ExecLimits::default(),
     };
 
     let policy = SandboxPolicy::new_read_only_policy();
 
-    process_exec_tool_call(params, sandbox_type, &policy, &None, None).await
+    process_exec_tool_call(
+        params,
+        sandbox_type,
+        &policy,
+        &None,
+        &Default::default(),
+        None,
+    )
+    .await
 }
 
 /// Command succeeds with exit code 0 normally
@@ -2,17 +2,25 @@
 
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
 
 use async_channel::Receiver;
+use codex_core::config_types::ExecLimits;
+use codex_core::config_types::ExecOutputConfig;
+use codex_core::error::CodexErr;
+use codex_core::error::SandboxErr;
 use codex_core::exec::ExecParams;
 use codex_core::exec::SandboxType;
 use codex_core::exec::StdoutStream;
 use codex_core::exec::process_exec_tool_call;
+use codex_core::exec::process_exec_tool_call_with_cancellation;
 use codex_core::protocol::Event;
 use codex_core::protocol::EventMsg;
 use codex_core::protocol::ExecCommandOutputDeltaEvent;
 use codex_core::protocol::ExecOutputStream;
 use codex_core::protocol::SandboxPolicy;
+use tokio_util::sync::CancellationToken;
 
 fn collect_stdout_events(rx: Receiver<Event>) -> Vec<u8> {
     let mut out = Vec::new();
@@ -37,6 +45,7 @@ async fn test_exec_stdout_stream_events_echo() {
         sub_id: "test-sub".to_string(),
         call_id: "call-1".to_string(),
         tx_event: tx,
+        output: ExecOutputConfig::default(),
     };
 
     let cmd = vec![
@@ -53,6 +62,7 @@ async fn test_exec_stdout_stream_events_echo() {
         env: HashMap::new(),
         with_escalated_permissions: None,
         justification: None,
+        limits: ExecLimits::default(),
     };
 
     let policy = SandboxPolicy::new_read_only_policy();
@@ -62,6 +72,7 @@ async fn test_exec_stdout_stream_events_echo() {
         SandboxType::None,
         &policy,
         &None,
+        &Default::default(),
         Some(stdout_stream),
     )
     .await;
@@ -87,6 +98,7 @@ async fn test_exec_stderr_stream_events_echo() {
         sub_id: "test-sub".to_string(),
         call_id: "call-2".to_string(),
         tx_event: tx,
+        output: ExecOutputConfig::default(),
     };
 
     let cmd = vec![
@@ -103,6 +115,7 @@ async fn test_exec_stderr_stream_events_echo() {
         env: HashMap::new(),
         with_escalated_permissions: None,
         justification: None,
+        limits: ExecLimits::default(),
     };
 
     let policy = SandboxPolicy::new_read_only_policy();
@@ -112,6 +125,7 @@ async fn test_exec_stderr_stream_events_echo() {
         SandboxType::None,
         &policy,
         &None,
+        &Default::default(),
         Some(stdout_stream),
     )
     .await;
@@ -156,13 +170,21 @@ async fn test_aggregated_output_interleaves_in_order() {
         env: HashMap::new(),
         with_escalated_permissions: None,
         justification: None,
+        limits: ExecLimits::default(),
     };
 
     let policy = SandboxPolicy::new_read_only_policy();
 
-    let result = process_exec_tool_call(params, SandboxType::None, &policy, &None, None)
-        .await
-        .expect("process_exec_tool_call");
+    let result = process_exec_tool_call(
+        params,
+        SandboxType::None,
+        &policy,
+        &None,
+        &Default::default(),
+        None,
+    )
+    .await
+    .expect("process_exec_tool_call");
 
     assert_eq!(result.exit_code, 0);
     assert_eq!(result.stdout.text, "O1\nO2\n");
@@ -170,3 +192,248 @@ async fn test_aggregated_output_interleaves_in_order() {
     assert_eq!(result.aggregated_output.text, "O1\nE1\nO2\nE2\n");
     assert_eq!(result.aggregated_output.truncated_after_lines, None);
 }
+
+#[tokio::test]
+async fn test_exec_output_is_chunked_and_capped() {
+    let (tx, rx) = async_channel::unbounded::<Event>();
+
+    let stdout_stream = StdoutStream {
+        sub_id: "test-sub".to_string(),
+        call_id: "call-3".to_string(),
+        tx_event: tx,
+        output: ExecOutputConfig {
+            chunk_bytes: 4,
+            max_buffer_bytes: 8,
+        },
+    };
+
+    let params = ExecParams {
+        command: vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            "printf '0123456789abcdef'".to_string(),
+        ],
+        cwd: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+        timeout_ms: Some(5_000),
+        env: HashMap::new(),
+        with_escalated_permissions: None,
+        justification: None,
+        limits: ExecLimits::default(),
+    };
+
+    let policy = SandboxPolicy::new_read_only_policy();
+
+    let result = process_exec_tool_call(
+        params,
+        SandboxType::None,
+        &policy,
+        &None,
+        &Default::default(),
+        Some(stdout_stream),
+    )
+    .await
+    .expect("process_exec_tool_call");
+
+    // Only the start and end of the output are retained...
+    assert_eq!(result.stdout.text, "0123\n[... 8 bytes omitted ...]\ncdef");
+
+    // ...while every byte is still streamed, in chunks of at most 4 bytes.
+    let mut chunks = Vec::new();
+    while let Ok(ev) = rx.try_recv() {
+        if let EventMsg::ExecCommandOutputDelta(ExecCommandOutputDeltaEvent { chunk, .. }) = ev.msg
+        {
+            chunks.push(chunk.into_vec());
+        }
+    }
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 4));
+    assert_eq!(chunks.concat(), b"0123456789abcdef");
+}
+
+#[tokio::test]
+async fn test_timeout_returns_partial_output() {
+    let params = ExecParams {
+        command: vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            "printf 'partial\n'; exec sleep 5".to_string(),
+        ],
+        cwd: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+        timeout_ms: Some(500),
+        env: HashMap::new(),
+        with_escalated_permissions: None,
+        justification: None,
+        limits: ExecLimits::default(),
+    };
+
+    let policy = SandboxPolicy::new_read_only_policy();
+
+    let result = process_exec_tool_call(
+        params,
+        SandboxType::None,
+        &policy,
+        &None,
+        &Default::default(),
+        None,
+    )
+    .await;
+
+    let Err(CodexErr::Sandbox(SandboxErr::Timeout { output })) = result else {
+        panic!("expected a timeout, got {result:?}");
+    };
+    assert_eq!(output.stdout.text, "partial\n");
+    assert_eq!(output.aggregated_output.text, "partial\n");
+}
+
+fn sh_params(script: &str, timeout_ms: u64, limits: ExecLimits) -> ExecParams {
+    ExecParams {
+        command: vec!["/bin/sh".to_string(), "-c".to_string(), script.to_string()],
+        cwd: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+        timeout_ms: Some(timeout_ms),
+        env: HashMap::new(),
+        with_escalated_permissions: None,
+        justification: None,
+        limits,
+    }
+}
+
+#[tokio::test]
+async fn test_output_limit_kills_command() {
+    let limits = ExecLimits {
+        max_output_bytes: Some(1_000),
+        ..Default::default()
+    };
+    let params = sh_params("yes", 10_000, limits);
+
+    let policy = SandboxPolicy::new_read_only_policy();
+    let start = Instant::now();
+    let result = process_exec_tool_call(
+        params,
+        SandboxType::None,
+        &policy,
+        &None,
+        &Default::default(),
+        None,
+    )
+    .await;
+
+    let Err(CodexErr::Sandbox(SandboxErr::OutputLimit { output })) = result else {
+        panic!("expected the output limit to be hit, got {result:?}");
+    };
+    assert_eq!(output.aggregated_output.text.len(), 1_000);
+    assert!(output.aggregated_output.text.starts_with("y\ny\n"));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_timeout_kills_background_processes() {
+    // The backgrounded sleep keeps the output pipes open; without killing the
+    // whole process group this would wait for it to finish.
+    let params = sh_params(
+        "sleep 30 & printf 'started\n'; wait",
+        500,
+        ExecLimits::default(),
+    );
+
+    let policy = SandboxPolicy::new_read_only_policy();
+    let start = Instant::now();
+    let result = process_exec_tool_call(
+        params,
+        SandboxType::None,
+        &policy,
+        &None,
+        &Default::default(),
+        None,
+    )
+    .await;
+
+    let Err(CodexErr::Sandbox(SandboxErr::Timeout { output })) = result else {
+        panic!("expected a timeout, got {result:?}");
+    };
+    assert_eq!(output.stdout.text, "started\n");
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_exit_does_not_wait_for_background_processes() {
+    let params = sh_params("sleep 30 & printf 'done\n'", 10_000, ExecLimits::default());
+
+    let policy = SandboxPolicy::new_read_only_policy();
+    let start = Instant::now();
+    let result = process_exec_tool_call(
+        params,
+        SandboxType::None,
+        &policy,
+        &None,
+        &Default::default(),
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.exit_code, 0);
+    assert_eq!(result.stdout.text, "done\n");
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_cpu_time_limit_kills_command() {
+    let limits = ExecLimits {
+        cpu_time_secs: Some(1),
+        ..Default::default()
+    };
+    let params = sh_params("while :; do :; done", 20_000, limits);
+
+    let policy = SandboxPolicy::new_read_only_policy();
+    let result = process_exec_tool_call(
+        params,
+        SandboxType::None,
+        &policy,
+        &None,
+        &Default::default(),
+        None,
+    )
+    .await;
+
+    assert!(
+        matches!(
+            result,
+            Err(CodexErr::Sandbox(SandboxErr::CpuTimeLimit { .. }))
+        ),
+        "expected the CPU time limit to be hit, got {result:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_cancellation_kills_command_and_keeps_output() {
+    let params = sh_params(
+        "printf 'partial\n'; exec sleep 5",
+        10_000,
+        ExecLimits::default(),
+    );
+
+    let policy = SandboxPolicy::new_read_only_policy();
+    let cancel = CancellationToken::new();
+    let canceller = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        canceller.cancel();
+    });
+    let start = Instant::now();
+    let result = process_exec_tool_call_with_cancellation(
+        params,
+        SandboxType::None,
+        &policy,
+        &None,
+        &Default::default(),
+        None,
+        cancel,
+    )
+    .await;
+
+    let Err(CodexErr::Sandbox(SandboxErr::Cancelled { output })) = result else {
+        panic!("expected the command to be cancelled, got {result:?}");
+    };
+    assert_eq!(output.stdout.text, "partial\n");
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
@@ -0,0 +1,119 @@
+use codex_core::ConversationManager;
+use codex_core::ModelProviderInfo;
+use codex_core::built_in_model_providers;
+use codex_core::config_types::HookAttachOutput;
+use codex_core::config_types::HookConfig;
+use codex_core::config_types::HookEvent;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+use codex_core::spawn::CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR;
+use codex_login::CodexAuth;
+use core_test_support::load_default_config_for_test;
+use core_test_support::load_sse_fixture_with_id_from_str;
+use core_test_support::wait_for_event;
+use tempfile::TempDir;
+use wiremock::Mock;
+use wiremock::MockServer;
+use wiremock::ResponseTemplate;
+use wiremock::matchers::method;
+use wiremock::matchers::path;
+
+fn sse_response(raw: &str, id: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200)
+        .insert_header("content-type", "text/event-stream")
+        .set_body_raw(
+            load_sse_fixture_with_id_from_str(raw, id),
+            "text/event-stream",
+        )
+}
+
+const SHELL_CALL: &str = r#"[
+  {"type": "response.output_item.done", "item": {
+    "type": "function_call",
+    "name": "shell",
+    "arguments": "{\"command\":[\"touch\",\"should-not-exist\"]}",
+    "call_id": "call1"
+  }},
+  {"type": "response.completed", "response": {"id": "__ID__"}}
+]"#;
+
+const COMPLETED: &str = r#"[
+  {"type": "response.completed", "response": {"id": "__ID__"}}
+]"#;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn failing_before_tool_hook_blocks_the_call() {
+    if std::env::var(CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR).is_ok() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(sse_response(SHELL_CALL, "resp1"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(sse_response(COMPLETED, "resp2"))
+        .mount(&server)
+        .await;
+
+    let home = TempDir::new().unwrap();
+    let cwd = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.cwd = cwd.path().to_path_buf();
+    config.model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    config.hooks = vec![HookConfig {
+        name: Some("lint".to_string()),
+        event: HookEvent::BeforeTool,
+        command: vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "echo lint failed; exit 1".to_string(),
+        ],
+        tools: vec!["shell".to_string()],
+        timeout_ms: None,
+        block_on_failure: true,
+        attach_output: HookAttachOutput::OnFailure,
+    }];
+    let conversation_manager = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"));
+    let codex = conversation_manager
+        .new_conversation(config)
+        .await
+        .unwrap()
+        .conversation;
+
+    codex
+        .submit(Op::UserInput {
+            items: vec![InputItem::Text {
+                text: "create a file".to_string(),
+            }],
+        })
+        .await
+        .unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    assert!(!cwd.path().join("should-not-exist").exists());
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    let body = requests[1].body_json::<serde_json::Value>().unwrap();
+    let output = body["input"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["type"] == "function_call_output")
+        .unwrap();
+    assert_eq!(
+        output["output"],
+        "tool call blocked: hook `lint` failed (exit code 1):\nlint failed"
+    );
+}
@@ -3,10 +3,18 @@
 mod cli_stream;
 mod client;
 mod compact;
+mod custom_tools;
 mod exec;
 mod exec_stream_events;
+mod hooks;
 mod live_cli;
+mod notifications;
+mod parallel_tool_calls;
 mod prompt_caching;
+mod provider_failover;
+mod redaction;
+mod response_format;
+mod resume;
 mod seatbelt;
 mod stream_error_allows_next_turn;
 mod stream_no_completed;
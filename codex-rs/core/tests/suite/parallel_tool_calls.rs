@@ -0,0 +1,143 @@
+use codex_core::ConversationManager;
+use codex_core::ModelProviderInfo;
+use codex_core::built_in_model_providers;
+use codex_core::config_types::HookAttachOutput;
+use codex_core::config_types::HookConfig;
+use codex_core::config_types::HookEvent;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+use codex_core::spawn::CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR;
+use codex_login::CodexAuth;
+use core_test_support::load_default_config_for_test;
+use core_test_support::load_sse_fixture_with_id_from_str;
+use core_test_support::wait_for_event;
+use tempfile::TempDir;
+use wiremock::Mock;
+use wiremock::MockServer;
+use wiremock::ResponseTemplate;
+use wiremock::matchers::method;
+use wiremock::matchers::path;
+
+fn sse_response(raw: &str, id: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200)
+        .insert_header("content-type", "text/event-stream")
+        .set_body_raw(
+            load_sse_fixture_with_id_from_str(raw, id),
+            "text/event-stream",
+        )
+}
+
+const TWO_SHELL_CALLS: &str = r#"[
+  {"type": "response.output_item.done", "item": {
+    "type": "function_call",
+    "name": "shell",
+    "arguments": "{\"command\":[\"echo\",\"first\"]}",
+    "call_id": "call1"
+  }},
+  {"type": "response.output_item.done", "item": {
+    "type": "function_call",
+    "name": "shell",
+    "arguments": "{\"command\":[\"echo\",\"second\"]}",
+    "call_id": "call2"
+  }},
+  {"type": "response.completed", "response": {"id": "__ID__"}}
+]"#;
+
+const COMPLETED: &str = r#"[
+  {"type": "response.completed", "response": {"id": "__ID__"}}
+]"#;
+
+/// Each call's before-tool hook waits until the other call's hook has
+/// started, so the calls only succeed when they run at the same time.
+const RENDEZVOUS_HOOK: &str = r#"
+ctx=$(cat)
+case "$ctx" in
+  *call1*) me=1; other=2 ;;
+  *) me=2; other=1 ;;
+esac
+touch "started$me"
+while [ ! -f "started$other" ]; do sleep 0.05; done
+"#;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn independent_tool_calls_run_concurrently_and_keep_their_order() {
+    if std::env::var(CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR).is_ok() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(sse_response(TWO_SHELL_CALLS, "resp1"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(sse_response(COMPLETED, "resp2"))
+        .mount(&server)
+        .await;
+
+    let home = TempDir::new().unwrap();
+    let cwd = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.cwd = cwd.path().to_path_buf();
+    config.model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    config.max_parallel_tool_calls = 2;
+    config.hooks = vec![HookConfig {
+        name: Some("rendezvous".to_string()),
+        event: HookEvent::BeforeTool,
+        command: vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            RENDEZVOUS_HOOK.to_string(),
+        ],
+        tools: vec!["shell".to_string()],
+        timeout_ms: Some(5_000),
+        block_on_failure: true,
+        attach_output: HookAttachOutput::OnFailure,
+    }];
+    let conversation_manager = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"));
+    let codex = conversation_manager
+        .new_conversation(config)
+        .await
+        .unwrap()
+        .conversation;
+
+    codex
+        .submit(Op::UserInput {
+            items: vec![InputItem::Text {
+                text: "echo twice".to_string(),
+            }],
+        })
+        .await
+        .unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    let first = requests[0].body_json::<serde_json::Value>().unwrap();
+    assert_eq!(first["parallel_tool_calls"], true);
+
+    let body = requests[1].body_json::<serde_json::Value>().unwrap();
+    let outputs: Vec<&serde_json::Value> = body["input"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|item| item["type"] == "function_call_output")
+        .collect();
+    assert_eq!(outputs.len(), 2);
+    assert_eq!(outputs[0]["call_id"], "call1");
+    assert_eq!(outputs[1]["call_id"], "call2");
+    for (output, expected) in outputs.iter().zip(["first", "second"]) {
+        let content = output["output"].as_str().unwrap();
+        assert!(content.contains(expected), "unexpected output: {content}");
+    }
+}
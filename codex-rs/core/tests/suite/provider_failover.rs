@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use codex_core::ConversationManager;
+use codex_core::ModelProviderInfo;
+use codex_core::WireApi;
+use codex_core::config::ProviderFailover;
+use codex_core::config_types::ProviderCircuitBreakerConfig;
+use codex_core::model_family::find_family_for_model;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+use codex_core::protocol::ProviderHealthStatus;
+use codex_core::spawn::CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR;
+use codex_login::CodexAuth;
+use core_test_support::load_default_config_for_test;
+use core_test_support::load_sse_fixture_with_id;
+use core_test_support::wait_for_event_with_timeout;
+use tempfile::TempDir;
+use wiremock::Mock;
+use wiremock::MockServer;
+use wiremock::ResponseTemplate;
+use wiremock::matchers::method;
+use wiremock::matchers::path;
+
+fn provider(name: &str, server: &MockServer) -> ModelProviderInfo {
+    ModelProviderInfo {
+        name: name.into(),
+        base_url: Some(format!("{}/v1", server.uri())),
+        env_key: Some("PATH".into()),
+        env_key_instructions: None,
+        wire_api: WireApi::Responses,
+        query_params: None,
+        http_headers: None,
+        env_http_headers: None,
+        request_max_retries: Some(0),
+        stream_max_retries: Some(0),
+        stream_idle_timeout_ms: Some(2_000),
+        requires_openai_auth: false,
+        azure: None,
+    }
+}
+
+async fn submit(
+    codex: &codex_core::CodexConversation,
+    text: &str,
+) -> codex_core::error::Result<String> {
+    codex
+        .submit(Op::UserInput {
+            items: vec![InputItem::Text { text: text.into() }],
+        })
+        .await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn unhealthy_provider_fails_over_and_is_skipped() {
+    if std::env::var(CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR).is_ok() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    // The primary provider is down. Once its circuit is open it must not see
+    // the second turn at all.
+    let primary = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&primary)
+        .await;
+
+    let secondary = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .set_body_raw(
+                    load_sse_fixture_with_id("tests/fixtures/completed_template.json", "resp"),
+                    "text/event-stream",
+                ),
+        )
+        .expect(2)
+        .mount(&secondary)
+        .await;
+
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = provider("primary", &primary);
+    config.failover = Some(ProviderFailover {
+        model_provider_id: "secondary".to_string(),
+        model_provider: provider("secondary", &secondary),
+        model: "o3".to_string(),
+        model_family: find_family_for_model("o3").unwrap(),
+    });
+    config.provider_circuit_breaker = ProviderCircuitBreakerConfig {
+        failure_threshold: 1,
+        cooldown_ms: 60_000,
+    };
+
+    let conversation_manager =
+        ConversationManager::with_auth(CodexAuth::from_api_key("Test API Key"));
+    let codex = conversation_manager
+        .new_conversation(config)
+        .await
+        .unwrap()
+        .conversation;
+
+    submit(&codex, "first").await.unwrap();
+    let EventMsg::ProviderHealth(unhealthy) = wait_for_event_with_timeout(
+        &codex,
+        |ev| matches!(ev, EventMsg::ProviderHealth(_)),
+        Duration::from_secs(5),
+    )
+    .await
+    else {
+        unreachable!();
+    };
+    assert_eq!(unhealthy.provider, "primary");
+    assert_eq!(unhealthy.status, ProviderHealthStatus::Unhealthy);
+
+    let EventMsg::ProviderHealth(failed_over) = wait_for_event_with_timeout(
+        &codex,
+        |ev| matches!(ev, EventMsg::ProviderHealth(_)),
+        Duration::from_secs(5),
+    )
+    .await
+    else {
+        unreachable!();
+    };
+    assert_eq!(failed_over.provider, "secondary");
+    assert_eq!(failed_over.model, "o3");
+    assert_eq!(failed_over.status, ProviderHealthStatus::FailedOver);
+    wait_for_event_with_timeout(
+        &codex,
+        |ev| matches!(ev, EventMsg::TaskComplete(_)),
+        Duration::from_secs(5),
+    )
+    .await;
+
+    submit(&codex, "second").await.unwrap();
+    wait_for_event_with_timeout(
+        &codex,
+        |ev| matches!(ev, EventMsg::TaskComplete(_)),
+        Duration::from_secs(5),
+    )
+    .await;
+
+    let requests = secondary.received_requests().await.unwrap();
+    let body = requests[0].body_json::<serde_json::Value>().unwrap();
+    assert_eq!(body["model"], "o3");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn unhealthy_provider_without_failover_fails_fast() {
+    if std::env::var(CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR).is_ok() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = provider("primary", &server);
+    config.provider_circuit_breaker = ProviderCircuitBreakerConfig {
+        failure_threshold: 1,
+        cooldown_ms: 60_000,
+    };
+
+    let conversation_manager =
+        ConversationManager::with_auth(CodexAuth::from_api_key("Test API Key"));
+    let codex = conversation_manager
+        .new_conversation(config)
+        .await
+        .unwrap()
+        .conversation;
+
+    for text in ["first", "second"] {
+        submit(&codex, text).await.unwrap();
+        let EventMsg::Error(error) = wait_for_event_with_timeout(
+            &codex,
+            |ev| matches!(ev, EventMsg::Error(_)),
+            Duration::from_secs(5),
+        )
+        .await
+        else {
+            unreachable!();
+        };
+        assert!(
+            error
+                .message
+                .contains("unavailable after repeated failures"),
+            "{}",
+            error.message
+        );
+        wait_for_event_with_timeout(
+            &codex,
+            |ev| matches!(ev, EventMsg::TaskComplete(_)),
+            Duration::from_secs(5),
+        )
+        .await;
+    }
+}
@@ -0,0 +1,98 @@
+use codex_core::ConversationManager;
+use codex_core::ModelProviderInfo;
+use codex_core::built_in_model_providers;
+use codex_core::list_sessions;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+use codex_core::spawn::CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR;
+use codex_login::CodexAuth;
+use core_test_support::load_default_config_for_test;
+use core_test_support::load_sse_fixture_with_id_from_str;
+use core_test_support::wait_for_event;
+use tempfile::TempDir;
+use wiremock::Mock;
+use wiremock::MockServer;
+use wiremock::ResponseTemplate;
+use wiremock::matchers::method;
+use wiremock::matchers::path;
+
+const SECRET: &str = "sk-proj-abcdefghijklmnopqrstuvwxyz012345";
+
+/// The model answers by echoing the secret back.
+const ECHO: &str = r#"[
+  {"type": "response.output_item.done", "item": {
+    "type": "message",
+    "role": "assistant",
+    "id": "msg1",
+    "content": [{"type": "output_text", "text": "Using sk-proj-abcdefghijklmnopqrstuvwxyz012345 now."}]
+  }},
+  {"type": "response.completed", "response": {"id": "__ID__"}}
+]"#;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn secrets_are_masked_in_requests_and_rollout() {
+    if std::env::var(CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR).is_ok() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .set_body_raw(
+                    load_sse_fixture_with_id_from_str(ECHO, "resp1"),
+                    "text/event-stream",
+                ),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    let conversation_manager = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"));
+    let codex = conversation_manager
+        .new_conversation(config.clone())
+        .await
+        .unwrap()
+        .conversation;
+
+    codex
+        .submit(Op::UserInput {
+            items: vec![InputItem::Text {
+                text: format!("use the key {SECRET}"),
+            }],
+        })
+        .await
+        .unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+    codex.submit(Op::Shutdown).await.unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::ShutdownComplete)).await;
+
+    let requests = server.received_requests().await.unwrap();
+    let body = String::from_utf8(requests[0].body.clone()).unwrap();
+    assert!(!body.contains(SECRET), "secret sent to the model: {body}");
+    assert!(body.contains("use the key [REDACTED]"), "{body}");
+
+    let sessions = list_sessions(&config.codex_home).await.unwrap();
+    assert_eq!(
+        sessions[0].preview.as_deref(),
+        Some("use the key [REDACTED]")
+    );
+    let rollout = std::fs::read_to_string(&sessions[0].path).unwrap();
+    assert!(
+        !rollout.contains(SECRET),
+        "secret in the rollout: {rollout}"
+    );
+    assert!(rollout.contains("Using [REDACTED] now."), "{rollout}");
+}
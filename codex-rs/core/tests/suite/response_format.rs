@@ -0,0 +1,154 @@
+#![expect(clippy::unwrap_used)]
+
+use codex_core::ConversationManager;
+use codex_core::ModelProviderInfo;
+use codex_core::built_in_model_providers;
+use codex_core::protocol::AskForApproval;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+use codex_core::protocol::ResponseFormat;
+use codex_core::protocol::SandboxPolicy;
+use codex_core::protocol_config_types::ReasoningEffort;
+use codex_core::protocol_config_types::ReasoningSummary;
+use codex_core::spawn::CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR;
+use codex_login::CodexAuth;
+use core_test_support::load_default_config_for_test;
+use core_test_support::wait_for_event;
+use pretty_assertions::assert_eq;
+use serde_json::Value;
+use serde_json::json;
+use tempfile::TempDir;
+use wiremock::Mock;
+use wiremock::MockServer;
+use wiremock::ResponseTemplate;
+use wiremock::matchers::method;
+use wiremock::matchers::path;
+
+fn sse_message(id: &str, text: &str) -> ResponseTemplate {
+    let events = [
+        json!({
+            "type": "response.output_item.done",
+            "item": {
+                "type": "message",
+                "role": "assistant",
+                "id": id,
+                "content": [{"type": "output_text", "text": text}],
+            },
+        }),
+        json!({
+            "type": "response.completed",
+            "response": {"id": id},
+        }),
+    ];
+    let body: String = events
+        .iter()
+        .map(|event| {
+            format!(
+                "event: {}\ndata: {event}\n\n",
+                event["type"].as_str().unwrap()
+            )
+        })
+        .collect();
+    ResponseTemplate::new(200)
+        .insert_header("content-type", "text/event-stream")
+        .set_body_raw(body, "text/event-stream")
+}
+
+fn is_retry(req: &wiremock::Request) -> bool {
+    std::str::from_utf8(&req.body)
+        .unwrap_or_default()
+        .contains("does not match the JSON schema")
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn invalid_structured_response_is_asked_for_again() {
+    if std::env::var(CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR).is_ok() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .and(|req: &wiremock::Request| !is_retry(req))
+        .respond_with(sse_message("m1", "Step one: back up the database."))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .and(is_retry)
+        .respond_with(sse_message("m2", r#"{"steps": ["back up the database"]}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    let cwd = config.cwd.clone();
+    let conversation_manager =
+        ConversationManager::with_auth(CodexAuth::from_api_key("Test API Key"));
+    let codex = conversation_manager
+        .new_conversation(config)
+        .await
+        .unwrap()
+        .conversation;
+
+    let schema = json!({
+        "type": "object",
+        "properties": {"steps": {"type": "array", "items": {"type": "string"}}},
+        "required": ["steps"],
+        "additionalProperties": false,
+    });
+    codex
+        .submit(Op::UserTurn {
+            items: vec![InputItem::Text {
+                text: "produce a migration plan".into(),
+            }],
+            cwd,
+            approval_policy: AskForApproval::Never,
+            sandbox_policy: SandboxPolicy::ReadOnly,
+            model: "o3".to_string(),
+            effort: ReasoningEffort::Medium,
+            summary: ReasoningSummary::Auto,
+            response_format: Some(ResponseFormat {
+                name: "migration_plan".to_string(),
+                schema: schema.clone(),
+                strict: true,
+            }),
+        })
+        .await
+        .unwrap();
+
+    let EventMsg::TaskComplete(complete) =
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await
+    else {
+        unreachable!();
+    };
+    assert_eq!(
+        complete.last_agent_message.as_deref(),
+        Some(r#"{"steps": ["back up the database"]}"#)
+    );
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    let first = requests[0].body_json::<Value>().unwrap();
+    assert_eq!(
+        first["text"]["format"],
+        json!({
+            "type": "json_schema",
+            "name": "migration_plan",
+            "schema": schema,
+            "strict": true,
+        })
+    );
+    let retry = requests[1].body_json::<Value>().unwrap().to_string();
+    assert!(retry.contains("the message is not valid JSON"), "{retry}");
+}
@@ -0,0 +1,236 @@
+use codex_core::ConversationManager;
+use codex_core::ModelProviderInfo;
+use codex_core::built_in_model_providers;
+use codex_core::list_sessions;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+use codex_core::spawn::CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR;
+use codex_login::CodexAuth;
+use core_test_support::load_default_config_for_test;
+use core_test_support::load_sse_fixture_with_id;
+use core_test_support::wait_for_event;
+use tempfile::TempDir;
+use wiremock::Mock;
+use wiremock::MockServer;
+use wiremock::ResponseTemplate;
+use wiremock::matchers::method;
+use wiremock::matchers::path;
+
+use pretty_assertions::assert_eq;
+
+fn sse_completed(id: &str) -> String {
+    load_sse_fixture_with_id("tests/fixtures/completed_template.json", id)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn resume_restores_history_and_settings_by_id() {
+    if std::env::var(CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR).is_ok() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .set_body_raw(sse_completed("resp1"), "text/event-stream"),
+        )
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = model_provider;
+    let original_model = config.model.clone();
+    let conversation_manager = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"));
+
+    // Record a turn in the first session and shut it down cleanly.
+    let first = conversation_manager
+        .new_conversation(config.clone())
+        .await
+        .unwrap();
+    first
+        .conversation
+        .submit(Op::UserInput {
+            items: vec![InputItem::Text {
+                text: "hello world".into(),
+            }],
+        })
+        .await
+        .unwrap();
+    wait_for_event(&first.conversation, |ev| {
+        matches!(ev, EventMsg::TaskComplete(_))
+    })
+    .await;
+    first.conversation.submit(Op::Shutdown).await.unwrap();
+    wait_for_event(&first.conversation, |ev| {
+        matches!(ev, EventMsg::ShutdownComplete)
+    })
+    .await;
+
+    let sessions = list_sessions(&config.codex_home).await.unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].id, first.conversation_id);
+    assert_eq!(sessions[0].preview.as_deref(), Some("hello world"));
+    assert_eq!(sessions[0].model.as_deref(), Some(original_model.as_str()));
+
+    // The recorded model wins over the one in the config used to resume.
+    let mut resume_config = config.clone();
+    resume_config.model = "gpt-4.1".to_string();
+    let resumed = conversation_manager
+        .resume_conversation(resume_config, first.conversation_id)
+        .await
+        .unwrap();
+    assert_eq!(resumed.conversation_id, first.conversation_id);
+    assert_eq!(resumed.session_configured.model, original_model);
+
+    resumed
+        .conversation
+        .submit(Op::UserInput {
+            items: vec![InputItem::Text {
+                text: "after resume".into(),
+            }],
+        })
+        .await
+        .unwrap();
+    wait_for_event(&resumed.conversation, |ev| {
+        matches!(ev, EventMsg::TaskComplete(_))
+    })
+    .await;
+
+    let requests = server.received_requests().await.unwrap();
+    let body = requests[1].body_json::<serde_json::Value>().unwrap();
+    let texts: Vec<&str> = body["input"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|item| item["content"][0]["text"].as_str())
+        .collect();
+    assert!(
+        texts.contains(&"hello world"),
+        "history not restored: {texts:?}"
+    );
+    assert_eq!(texts.last(), Some(&"after resume"));
+
+    // Unknown ids are reported as missing rather than starting a new session.
+    let missing = conversation_manager
+        .resume_conversation(config, uuid::Uuid::new_v4())
+        .await;
+    assert!(matches!(
+        missing,
+        Err(codex_core::error::CodexErr::ConversationNotFound(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn rewound_branch_is_restored_on_resume() {
+    if std::env::var(CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR).is_ok() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .set_body_raw(sse_completed("resp1"), "text/event-stream"),
+        )
+        .expect(4)
+        .mount(&server)
+        .await;
+
+    let model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = model_provider;
+    let conversation_manager = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"));
+    let first = conversation_manager
+        .new_conversation(config.clone())
+        .await
+        .unwrap();
+    let codex = first.conversation;
+
+    for text in ["first turn", "second turn"] {
+        codex
+            .submit(Op::UserInput {
+                items: vec![InputItem::Text { text: text.into() }],
+            })
+            .await
+            .unwrap();
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+    }
+
+    codex.submit(Op::Rewind { num_turns: 1 }).await.unwrap();
+    let EventMsg::ConversationBranch(branch) =
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::ConversationBranch(_))).await
+    else {
+        unreachable!()
+    };
+    assert_eq!(branch.branch_id, 1);
+    assert_eq!(branch.previous_branch_id, 0);
+    assert_eq!(branch.num_turns, 1);
+
+    codex
+        .submit(Op::UserInput {
+            items: vec![InputItem::Text {
+                text: "alternative turn".into(),
+            }],
+        })
+        .await
+        .unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+    codex.submit(Op::Shutdown).await.unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::ShutdownComplete)).await;
+
+    // Resuming continues on the alternative branch.
+    let resumed = conversation_manager
+        .resume_conversation(config, first.conversation_id)
+        .await
+        .unwrap()
+        .conversation;
+    resumed
+        .submit(Op::UserInput {
+            items: vec![InputItem::Text {
+                text: "after resume".into(),
+            }],
+        })
+        .await
+        .unwrap();
+    wait_for_event(&resumed, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    let requests = server.received_requests().await.unwrap();
+    let user_texts = |idx: usize| -> Vec<String> {
+        let body = requests[idx].body_json::<serde_json::Value>().unwrap();
+        body["input"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|item| item["role"] == "user")
+            .filter_map(|item| item["content"][0]["text"].as_str())
+            .filter(|text| !text.starts_with('<'))
+            .map(str::to_string)
+            .collect()
+    };
+    assert_eq!(user_texts(2), vec!["first turn", "alternative turn"]);
+    assert_eq!(
+        user_texts(3),
+        vec!["first turn", "alternative turn", "after resume"]
+    );
+}
@@ -7,10 +7,12 @@ use codex_core::protocol::AgentMessageEvent;
 use codex_core::protocol::AgentReasoningDeltaEvent;
 use codex_core::protocol::AgentReasoningRawContentDeltaEvent;
 use codex_core::protocol::AgentReasoningRawContentEvent;
+use codex_core::protocol::ApplyPatchApprovalRequestEvent;
 use codex_core::protocol::BackgroundEventEvent;
 use codex_core::protocol::ErrorEvent;
 use codex_core::protocol::Event;
 use codex_core::protocol::EventMsg;
+use codex_core::protocol::ExecApprovalRequestEvent;
 use codex_core::protocol::ExecCommandBeginEvent;
 use codex_core::protocol::ExecCommandEndEvent;
 use codex_core::protocol::FileChange;
@@ -170,7 +172,7 @@ impl EventProcessor for EventProcessorWithHumanOutput {
     fn process_event(&mut self, event: Event) -> CodexStatus {
         let Event { id: _, msg } = event;
         match msg {
-            EventMsg::Error(ErrorEvent { message }) => {
+            EventMsg::Error(ErrorEvent { message, .. }) => {
                 let prefix = "ERROR:".style(self.red);
                 ts_println!(self, "{prefix} {message}");
             }
@@ -481,15 +483,48 @@ impl EventProcessor for EventProcessorWithHumanOutput {
                     println!("{}", line.style(self.dimmed));
                 }
             }
-            EventMsg::TurnDiff(TurnDiffEvent { unified_diff }) => {
+            EventMsg::TurnDiff(TurnDiffEvent { unified_diff, .. }) => {
                 ts_println!(self, "{}", "turn diff:".style(self.magenta));
                 println!("{unified_diff}");
             }
-            EventMsg::ExecApprovalRequest(_) => {
-                // Should we exit?
+            EventMsg::ExecApprovalRequest(ExecApprovalRequestEvent { command, .. }) => {
+                // Only sent when approvals are requested over HTTP.
+                ts_println!(
+                    self,
+                    "{} {}",
+                    "approval requested:".style(self.magenta),
+                    escape_command(&command)
+                );
             }
-            EventMsg::ApplyPatchApprovalRequest(_) => {
-                // Should we exit?
+            EventMsg::ApplyPatchApprovalRequest(ApplyPatchApprovalRequestEvent {
+                changes, ..
+            }) => {
+                let mut paths: Vec<String> = changes
+                    .keys()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                paths.sort();
+                ts_println!(
+                    self,
+                    "{} {}",
+                    "approval requested for changes to:".style(self.magenta),
+                    paths.join(", ")
+                );
+            }
+            EventMsg::BudgetApprovalRequest(ev) => {
+                // Only sent when approvals are requested over HTTP.
+                ts_println!(
+                    self,
+                    "{} {ev}",
+                    "approval requested to go over the".style(self.magenta)
+                );
+            }
+            EventMsg::BudgetWarning(ev) => {
+                ts_println!(
+                    self,
+                    "{}",
+                    format!("approaching the {ev}").style(self.dimmed)
+                );
             }
             EventMsg::AgentReasoning(agent_reasoning_event) => {
                 if self.show_agent_reasoning {
@@ -535,7 +570,9 @@ impl EventProcessor for EventProcessorWithHumanOutput {
             EventMsg::McpListToolsResponse(_) => {
                 // Currently ignored in exec output.
             }
-            EventMsg::ListCustomPromptsResponse(_) => {
+            EventMsg::ListCustomPromptsResponse(_)
+            | EventMsg::McpListPromptsResponse(_)
+            | EventMsg::McpGetPromptResponse(_) => {
                 // Currently ignored in exec output.
             }
             EventMsg::TurnAborted(abort_reason) => match abort_reason.reason {
@@ -548,6 +585,80 @@ impl EventProcessor for EventProcessorWithHumanOutput {
             },
             EventMsg::ShutdownComplete => return CodexStatus::Shutdown,
             EventMsg::ConversationHistory(_) => {}
+            EventMsg::ConversationExport(ev) => match ev.path {
+                Some(path) => ts_println!(self, "transcript written to {}", path.display()),
+                None => println!("{}", ev.content),
+            },
+            EventMsg::ConversationBranch(ev) => {
+                ts_println!(
+                    self,
+                    "continuing on branch {} ({} turns)",
+                    ev.branch_id,
+                    ev.num_turns
+                );
+            }
+            EventMsg::BackgroundJobStarted(ev) => {
+                ts_println!(
+                    self,
+                    "{} {} in {}",
+                    format!("background job {}", ev.job_id).style(self.magenta),
+                    escape_command(&ev.command).style(self.bold),
+                    ev.cwd.to_string_lossy(),
+                );
+            }
+            EventMsg::ArtifactCreated(ev) => {
+                ts_println!(
+                    self,
+                    "{}",
+                    format!(
+                        "saved {} bytes of tool output to {}",
+                        ev.bytes,
+                        ev.path.to_string_lossy()
+                    )
+                    .style(self.dimmed)
+                );
+            }
+            EventMsg::McpServerStatus(ev) => {
+                let mut line = format!("MCP server {}: {}", ev.server, ev.status);
+                if let Some(tools) = ev.tools {
+                    line.push_str(&format!(" ({tools} tools)"));
+                }
+                if let Some(message) = &ev.message {
+                    line.push_str(&format!(" - {message}"));
+                }
+                ts_println!(self, "{}", line.style(self.dimmed));
+            }
+            EventMsg::ModelRouted(ev) => {
+                ts_println!(
+                    self,
+                    "{}",
+                    format!("route {}: {} ({})", ev.route, ev.model, ev.provider)
+                        .style(self.dimmed)
+                );
+            }
+            EventMsg::ProviderHealth(ev) => {
+                ts_println!(
+                    self,
+                    "{}",
+                    format!(
+                        "provider {} ({}): {} - {}",
+                        ev.provider, ev.model, ev.status, ev.message
+                    )
+                    .style(self.dimmed)
+                );
+            }
+            EventMsg::BackgroundJobEnded(ev) => {
+                let status = match (ev.killed, ev.exit_code) {
+                    (true, _) => "killed".to_string(),
+                    (false, Some(code)) => format!("exited {code}"),
+                    (false, None) => "exited".to_string(),
+                };
+                ts_println!(
+                    self,
+                    "{} {status}",
+                    format!("background job {}", ev.job_id).style(self.magenta)
+                );
+            }
         }
         CodexStatus::Running
     }
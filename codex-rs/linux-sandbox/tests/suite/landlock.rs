@@ -1,4 +1,5 @@
 #![cfg(target_os = "linux")]
+use codex_core::config_types::ExecLimits;
 use codex_core::config_types::ShellEnvironmentPolicy;
 use codex_core::error::CodexErr;
 use codex_core::error::SandboxErr;
@@ -42,6 +43,7 @@ async fn run_cmd(cmd: &[&str], writable_roots: &[PathBuf], timeout_ms: u64) {
         env: create_env_from_core_vars(),
         with_escalated_permissions: None,
         justification: None,
+        limits: ExecLimits::default(),
     };
 
     let sandbox_policy = SandboxPolicy::WorkspaceWrite {
@@ -60,6 +62,7 @@ async fn run_cmd(cmd: &[&str], writable_roots: &[PathBuf], timeout_ms: u64) {
         SandboxType::LinuxSeccomp,
         &sandbox_policy,
         &codex_linux_sandbox_exe,
+        &Default::default(),
         None,
     )
     .await
@@ -142,6 +145,7 @@ async fn assert_network_blocked(cmd: &[&str]) {
         env: create_env_from_core_vars(),
         with_escalated_permissions: None,
         justification: None,
+        limits: ExecLimits::default(),
     };
 
     let sandbox_policy = SandboxPolicy::new_read_only_policy();
@@ -152,6 +156,7 @@ async fn assert_network_blocked(cmd: &[&str]) {
         SandboxType::LinuxSeccomp,
         &sandbox_policy,
         &codex_linux_sandbox_exe,
+        &Default::default(),
         None,
     )
     .await;
@@ -2,6 +2,8 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::RwLock;
 
+use chrono::Utc;
+
 use crate::AuthMode;
 use crate::CodexAuth;
 
@@ -24,6 +26,9 @@ struct CachedAuth {
 pub struct AuthManager {
     codex_home: PathBuf,
     inner: RwLock<CachedAuth>,
+    /// Serializes refreshes so concurrent callers do not each redeem the
+    /// same (single-use) refresh token.
+    refresh_lock: tokio::sync::Mutex<()>,
 }
 
 impl AuthManager {
@@ -41,6 +46,7 @@ impl AuthManager {
                 preferred_auth_mode,
                 auth,
             }),
+            refresh_lock: tokio::sync::Mutex::new(()),
         }
     }
 
@@ -54,6 +60,7 @@ impl AuthManager {
         Arc::new(Self {
             codex_home: PathBuf::new(),
             inner: RwLock::new(cached),
+            refresh_lock: tokio::sync::Mutex::new(()),
         })
     }
 
@@ -116,6 +123,25 @@ impl AuthManager {
         }
     }
 
+    /// Refresh the ChatGPT access token if it expires within `within`.
+    /// Returns whether a refresh happened. Callers racing on the same
+    /// manager are serialized, and later ones observe the refreshed token
+    /// and skip their own refresh.
+    pub async fn refresh_token_if_expiring(
+        &self,
+        within: chrono::Duration,
+    ) -> std::io::Result<bool> {
+        let _guard = self.refresh_lock.lock().await;
+        let expires_at = match self.auth().and_then(|a| a.get_access_token_expiration()) {
+            Some(expires_at) => expires_at,
+            None => return Ok(false),
+        };
+        if expires_at - within > Utc::now() {
+            return Ok(false);
+        }
+        Ok(self.refresh_token().await?.is_some())
+    }
+
     /// Log out by deleting the on‑disk auth.json (if present). Returns Ok(true)
     /// if a file was removed, Ok(false) if no auth file existed. On success,
     /// reloads the in‑memory auth cache so callers immediately observe the
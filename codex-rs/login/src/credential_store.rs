@@ -0,0 +1,549 @@
+//! Storage for the credential files Codex keeps under `$CODEX_HOME`.
+//!
+//! Callers address credentials by their historical file path (for example
+//! `$CODEX_HOME/auth.json`). Depending on the configured
+//! [`CredentialStoreMode`], the contents either live in that plaintext file
+//! or in a single encrypted vault (`credentials.enc`) whose key is kept in
+//! the OS keyring or in a `0600` key file next to it. The vault with a key
+//! file is the default; the keyring mode falls back to the key file when no
+//! keyring is reachable, as on headless Linux machines without a Secret
+//! Service.
+//!
+//! When an encrypted mode is active, plaintext files left over from an older
+//! version are still read and are moved into the vault on the next write.
+//!
+//! Files are replaced atomically, and changes to the vault hold a lock on
+//! `credentials.lock` so that concurrent writers (e.g. the token refreshers
+//! of different providers) never drop each other's entries.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::str::FromStr;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::Key;
+use chacha20poly1305::KeyInit;
+use chacha20poly1305::Nonce;
+use chacha20poly1305::aead::Aead;
+use rand::RngCore;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Credential files known to Codex; these are what `codex auth migrate`
+/// moves between stores.
+pub const CREDENTIAL_FILE_NAMES: &[&str] = &["auth.json", "azure_auth.json"];
+
+const MODE_FILE: &str = "credential_store";
+const VAULT_FILE: &str = "credentials.enc";
+const KEY_FILE: &str = "credentials.key";
+const LOCK_FILE: &str = "credentials.lock";
+const KEYRING_SERVICE: &str = "codex";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CredentialStoreMode {
+    /// Plaintext JSON files readable only by the current user.
+    File,
+    /// Encrypted vault whose key is stored in `$CODEX_HOME/credentials.key`.
+    #[default]
+    EncryptedFile,
+    /// Encrypted vault whose key is stored in the OS keyring, or in
+    /// `$CODEX_HOME/credentials.key` while the keyring is unavailable.
+    Keyring,
+}
+
+impl CredentialStoreMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CredentialStoreMode::File => "file",
+            CredentialStoreMode::EncryptedFile => "encrypted-file",
+            CredentialStoreMode::Keyring => "keyring",
+        }
+    }
+}
+
+impl fmt::Display for CredentialStoreMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CredentialStoreMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "file" => Ok(CredentialStoreMode::File),
+            "encrypted-file" => Ok(CredentialStoreMode::EncryptedFile),
+            "keyring" => Ok(CredentialStoreMode::Keyring),
+            other => Err(format!(
+                "unknown credential store `{other}` (expected file, encrypted-file or keyring)"
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Mode recorded in `$CODEX_HOME/credential_store`, or the encrypted-file
+/// default when none has been chosen.
+pub fn get_credential_store_mode(codex_home: &Path) -> CredentialStoreMode {
+    match std::fs::read_to_string(codex_home.join(MODE_FILE)) {
+        Ok(contents) => contents.parse().unwrap_or_default(),
+        Err(_) => CredentialStoreMode::default(),
+    }
+}
+
+/// Read the credential stored for `path`. Returns an error of kind
+/// [`ErrorKind::NotFound`] when nothing is stored.
+pub fn read_credential(path: &Path) -> std::io::Result<String> {
+    let (codex_home, name) = split_path(path)?;
+    let mode = get_credential_store_mode(codex_home);
+    if mode != CredentialStoreMode::File
+        && let Some(contents) = read_vault(codex_home, mode)?.remove(name)
+    {
+        return Ok(contents);
+    }
+    std::fs::read_to_string(path)
+}
+
+/// Store `contents` as the credential for `path` using the active mode.
+pub fn write_credential(path: &Path, contents: &str) -> std::io::Result<()> {
+    let (codex_home, name) = split_path(path)?;
+    let _lock = lock_store(codex_home)?;
+    let mode = get_credential_store_mode(codex_home);
+    if mode == CredentialStoreMode::File {
+        return write_private_file(path, contents.as_bytes());
+    }
+
+    let mut entries = read_vault(codex_home, mode)?;
+    entries.insert(name.to_string(), contents.to_string());
+    write_vault(codex_home, mode, &entries)?;
+    remove_if_exists(path)?;
+    Ok(())
+}
+
+/// Remove the credential for `path` from every location it may live in.
+/// Returns `Ok(true)` if anything was removed.
+pub fn delete_credential(path: &Path) -> std::io::Result<bool> {
+    let (codex_home, name) = split_path(path)?;
+    let _lock = lock_store(codex_home)?;
+    let mut removed = remove_if_exists(path)?;
+    let mode = get_credential_store_mode(codex_home);
+    if mode != CredentialStoreMode::File {
+        let mut entries = read_vault(codex_home, mode)?;
+        if entries.remove(name).is_some() {
+            write_vault(codex_home, mode, &entries)?;
+            removed = true;
+        }
+    }
+    Ok(removed)
+}
+
+/// Names of the known credential files that currently have a stored value.
+pub fn list_credentials(codex_home: &Path) -> std::io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for name in CREDENTIAL_FILE_NAMES {
+        match read_credential(&codex_home.join(name)) {
+            Ok(_) => names.push((*name).to_string()),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(names)
+}
+
+/// Move every known credential into the store selected by `to` and make it
+/// the active mode. Returns the names of the credentials that were moved.
+pub fn migrate_credentials(
+    codex_home: &Path,
+    to: CredentialStoreMode,
+) -> std::io::Result<Vec<String>> {
+    let _lock = lock_store(codex_home)?;
+    let from = get_credential_store_mode(codex_home);
+    let mut entries = BTreeMap::new();
+    for name in list_credentials(codex_home)? {
+        let contents = read_credential(&codex_home.join(&name))?;
+        entries.insert(name, contents);
+    }
+
+    if to == CredentialStoreMode::File {
+        for (name, contents) in &entries {
+            write_private_file(&codex_home.join(name), contents.as_bytes())?;
+        }
+        remove_if_exists(&codex_home.join(VAULT_FILE))?;
+    } else {
+        // Always re-encrypt so switching between the two encrypted modes
+        // never leaves the vault bound to a key that is about to be removed.
+        write_vault(codex_home, to, &entries)?;
+        for name in entries.keys() {
+            remove_if_exists(&codex_home.join(name))?;
+        }
+    }
+    write_private_file(&codex_home.join(MODE_FILE), to.as_str().as_bytes())?;
+
+    if from != to {
+        match from {
+            CredentialStoreMode::File => {}
+            // The key file stays when the keyring was unavailable and the
+            // new vault fell back to it.
+            CredentialStoreMode::EncryptedFile => {
+                if to == CredentialStoreMode::File || keyring_holds_key(codex_home) {
+                    remove_if_exists(&codex_home.join(KEY_FILE))?;
+                }
+            }
+            CredentialStoreMode::Keyring => {
+                match keyring_entry(codex_home).and_then(|entry| entry.delete_credential()) {
+                    Ok(()) | Err(keyring::Error::NoEntry) => {}
+                    Err(e) if keyring_unavailable(&e) => {}
+                    Err(e) => return Err(std::io::Error::other(e)),
+                }
+                if to == CredentialStoreMode::File {
+                    remove_if_exists(&codex_home.join(KEY_FILE))?;
+                }
+            }
+        }
+    }
+
+    Ok(entries.into_keys().collect())
+}
+
+fn split_path(path: &Path) -> std::io::Result<(&Path, &str)> {
+    let name = path.file_name().and_then(|n| n.to_str()).ok_or_else(|| {
+        std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid credential path: {}", path.display()),
+        )
+    })?;
+    let codex_home = path.parent().unwrap_or_else(|| Path::new("."));
+    Ok((codex_home, name))
+}
+
+fn read_vault(
+    codex_home: &Path,
+    mode: CredentialStoreMode,
+) -> std::io::Result<BTreeMap<String, String>> {
+    let contents = match std::fs::read_to_string(codex_home.join(VAULT_FILE)) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+    let vault: VaultFile = serde_json::from_str(&contents)?;
+    let key = load_key(codex_home, mode, false)?.ok_or_else(|| {
+        std::io::Error::other(format!(
+            "the key for {VAULT_FILE} is missing from the {mode} credential store"
+        ))
+    })?;
+    let nonce = BASE64.decode(vault.nonce).map_err(std::io::Error::other)?;
+    let ciphertext = BASE64
+        .decode(vault.ciphertext)
+        .map_err(std::io::Error::other)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| std::io::Error::other(format!("failed to decrypt {VAULT_FILE}")))?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+fn write_vault(
+    codex_home: &Path,
+    mode: CredentialStoreMode,
+    entries: &BTreeMap<String, String>,
+) -> std::io::Result<()> {
+    let key = load_key(codex_home, mode, true)?
+        .ok_or_else(|| std::io::Error::other("failed to create credential store key"))?;
+    let mut nonce = [0u8; 12];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let plaintext = serde_json::to_vec(entries)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+        .map_err(|_| std::io::Error::other(format!("failed to encrypt {VAULT_FILE}")))?;
+    let vault = VaultFile {
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    };
+    write_private_file(
+        &codex_home.join(VAULT_FILE),
+        serde_json::to_string_pretty(&vault)?.as_bytes(),
+    )
+}
+
+/// Load the 32-byte vault key for `mode`, generating and storing a new one
+/// when `create` is set and none exists yet. The keyring mode uses the key
+/// file instead while the keyring is unavailable, and still reads a key
+/// file written during such an outage until a new key is created.
+fn load_key(
+    codex_home: &Path,
+    mode: CredentialStoreMode,
+    create: bool,
+) -> std::io::Result<Option<Vec<u8>>> {
+    let existing = match mode {
+        CredentialStoreMode::File => return Ok(None),
+        CredentialStoreMode::EncryptedFile => {
+            match std::fs::read_to_string(codex_home.join(KEY_FILE)) {
+                Ok(encoded) => Some(encoded),
+                Err(e) if e.kind() == ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            }
+        }
+        CredentialStoreMode::Keyring => {
+            match keyring_entry(codex_home).and_then(|entry| entry.get_password()) {
+                Ok(encoded) => Some(encoded),
+                Err(keyring::Error::NoEntry) if create => None,
+                Err(keyring::Error::NoEntry) => {
+                    return load_key(codex_home, CredentialStoreMode::EncryptedFile, false);
+                }
+                Err(e) if keyring_unavailable(&e) => {
+                    return load_key(codex_home, CredentialStoreMode::EncryptedFile, create);
+                }
+                Err(e) => return Err(std::io::Error::other(e)),
+            }
+        }
+    };
+    if let Some(encoded) = existing {
+        let key = BASE64
+            .decode(encoded.trim())
+            .map_err(std::io::Error::other)?;
+        return Ok(Some(key));
+    }
+    if !create {
+        return Ok(None);
+    }
+
+    let mut key = vec![0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut key);
+    let encoded = BASE64.encode(&key);
+    match mode {
+        CredentialStoreMode::File => {}
+        CredentialStoreMode::EncryptedFile => {
+            write_private_file(&codex_home.join(KEY_FILE), encoded.as_bytes())?;
+        }
+        CredentialStoreMode::Keyring => {
+            match keyring_entry(codex_home).and_then(|entry| entry.set_password(&encoded)) {
+                Ok(()) => {}
+                Err(e) if keyring_unavailable(&e) => {
+                    write_private_file(&codex_home.join(KEY_FILE), encoded.as_bytes())?;
+                }
+                Err(e) => return Err(std::io::Error::other(e)),
+            }
+        }
+    }
+    Ok(Some(key))
+}
+
+/// Keyring entries are scoped to the `CODEX_HOME` they belong to so that
+/// separate homes (and tests) never share a key.
+fn keyring_entry(codex_home: &Path) -> keyring::Result<keyring::Entry> {
+    let user = format!("credentials-key:{}", codex_home.display());
+    keyring::Entry::new(KEYRING_SERVICE, &user)
+}
+
+fn keyring_holds_key(codex_home: &Path) -> bool {
+    keyring_entry(codex_home)
+        .and_then(|entry| entry.get_password())
+        .is_ok()
+}
+
+/// Errors meaning there is no usable keyring at all (no Secret Service on
+/// the session bus, a locked or missing store), as opposed to a problem
+/// with the entry itself.
+fn keyring_unavailable(e: &keyring::Error) -> bool {
+    matches!(
+        e,
+        keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_)
+    )
+}
+
+/// Holds an exclusive lock on `$CODEX_HOME/credentials.lock` until the
+/// returned file is dropped, so that read-modify-write cycles of the vault do
+/// not interleave across threads or processes.
+fn lock_store(codex_home: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    {
+        options.mode(0o600);
+    }
+    let file = options.open(codex_home.join(LOCK_FILE))?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Replace `path` with `contents`: the data is written to a `0600` temporary
+/// file next to it, synced and renamed over `path`, so a crash leaves either
+/// the old or the new contents.
+fn write_private_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(contents)?;
+    file.as_file().sync_all()?;
+    file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<bool> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    /// Stands in for a machine without a keyring: every entry fails to build.
+    struct UnavailableKeyring;
+
+    impl keyring::credential::CredentialBuilderApi for UnavailableKeyring {
+        fn build(
+            &self,
+            _target: Option<&str>,
+            _service: &str,
+            _user: &str,
+        ) -> keyring::Result<Box<keyring::credential::Credential>> {
+            Err(keyring::Error::NoStorageAccess(
+                "no Secret Service on the session bus".into(),
+            ))
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    #[test]
+    fn default_mode_encrypts() {
+        let home = tempdir().unwrap();
+        let auth = home.path().join("auth.json");
+        write_credential(&auth, "secret-auth").unwrap();
+
+        assert_eq!(
+            get_credential_store_mode(home.path()),
+            CredentialStoreMode::EncryptedFile
+        );
+        assert!(!auth.exists());
+        let vault = std::fs::read_to_string(home.path().join(VAULT_FILE)).unwrap();
+        assert!(!vault.contains("secret-auth"));
+        assert_eq!(read_credential(&auth).unwrap(), "secret-auth");
+    }
+
+    #[test]
+    fn file_mode_writes_plaintext() {
+        let home = tempdir().unwrap();
+        migrate_credentials(home.path(), CredentialStoreMode::File).unwrap();
+        let auth = home.path().join("auth.json");
+        write_credential(&auth, "{\"a\":1}").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&auth).unwrap(), "{\"a\":1}");
+        assert_eq!(read_credential(&auth).unwrap(), "{\"a\":1}");
+        assert!(delete_credential(&auth).unwrap());
+        assert_eq!(
+            read_credential(&auth).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn migrate_to_encrypted_file_and_back() {
+        let home = tempdir().unwrap();
+        let auth = home.path().join("auth.json");
+        let azure = home.path().join("azure_auth.json");
+        write_credential(&auth, "secret-auth").unwrap();
+        write_credential(&azure, "secret-azure").unwrap();
+
+        let moved = migrate_credentials(home.path(), CredentialStoreMode::EncryptedFile).unwrap();
+        assert_eq!(moved, vec!["auth.json", "azure_auth.json"]);
+        assert_eq!(
+            get_credential_store_mode(home.path()),
+            CredentialStoreMode::EncryptedFile
+        );
+        assert!(!auth.exists());
+        let vault = std::fs::read_to_string(home.path().join(VAULT_FILE)).unwrap();
+        assert!(!vault.contains("secret-auth"));
+        assert_eq!(read_credential(&auth).unwrap(), "secret-auth");
+
+        write_credential(&auth, "rotated").unwrap();
+        migrate_credentials(home.path(), CredentialStoreMode::File).unwrap();
+        assert_eq!(std::fs::read_to_string(&auth).unwrap(), "rotated");
+        assert_eq!(std::fs::read_to_string(&azure).unwrap(), "secret-azure");
+        assert!(!home.path().join(VAULT_FILE).exists());
+        assert!(!home.path().join(KEY_FILE).exists());
+        assert_eq!(
+            get_credential_store_mode(home.path()),
+            CredentialStoreMode::File
+        );
+    }
+
+    #[test]
+    fn keyring_mode_falls_back_to_key_file_without_a_keyring() {
+        keyring::set_default_credential_builder(Box::new(UnavailableKeyring));
+        let home = tempdir().unwrap();
+        let auth = home.path().join("auth.json");
+
+        migrate_credentials(home.path(), CredentialStoreMode::Keyring).unwrap();
+        write_credential(&auth, "secret-auth").unwrap();
+
+        assert!(!auth.exists());
+        assert!(home.path().join(KEY_FILE).exists());
+        let vault = std::fs::read_to_string(home.path().join(VAULT_FILE)).unwrap();
+        assert!(!vault.contains("secret-auth"));
+        assert_eq!(read_credential(&auth).unwrap(), "secret-auth");
+
+        migrate_credentials(home.path(), CredentialStoreMode::EncryptedFile).unwrap();
+        assert_eq!(read_credential(&auth).unwrap(), "secret-auth");
+    }
+
+    #[test]
+    fn encrypted_mode_picks_up_leftover_plaintext() {
+        let home = tempdir().unwrap();
+        migrate_credentials(home.path(), CredentialStoreMode::EncryptedFile).unwrap();
+        let auth = home.path().join("auth.json");
+        std::fs::write(&auth, "legacy").unwrap();
+
+        assert_eq!(read_credential(&auth).unwrap(), "legacy");
+        write_credential(&auth, "legacy-updated").unwrap();
+        assert!(!auth.exists());
+        assert_eq!(read_credential(&auth).unwrap(), "legacy-updated");
+    }
+
+    #[test]
+    fn concurrent_writes_keep_every_entry() {
+        let home = tempdir().unwrap();
+        migrate_credentials(home.path(), CredentialStoreMode::EncryptedFile).unwrap();
+
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let path = home.path().join(format!("cred-{i}.json"));
+                scope.spawn(move || {
+                    for round in 0..5 {
+                        write_credential(&path, &format!("{i}-{round}")).unwrap();
+                    }
+                });
+            }
+        });
+
+        for i in 0..8 {
+            let path = home.path().join(format!("cred-{i}.json"));
+            assert_eq!(read_credential(&path).unwrap(), format!("{i}-4"));
+        }
+    }
+}
@@ -3,13 +3,6 @@ use chrono::Utc;
 use serde::Deserialize;
 use serde::Serialize;
 use std::env;
-use std::fs::File;
-use std::fs::OpenOptions;
-use std::fs::remove_file;
-use std::io::Read;
-use std::io::Write;
-#[cfg(unix)]
-use std::os::unix::fs::OpenOptionsExt;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -22,8 +15,10 @@ pub use crate::server::ShutdownHandle;
 pub use crate::server::run_login_server;
 pub use crate::token_data::TokenData;
 use crate::token_data::parse_id_token;
+use crate::token_data::parse_jwt_expiration;
 
 mod auth_manager;
+pub mod credential_store;
 mod pkce;
 mod server;
 mod token_data;
@@ -64,9 +59,7 @@ impl CodexAuth {
             .ok_or(std::io::Error::other("Token data is not available."))?;
         let token = token_data.refresh_token;
 
-        let refresh_response = try_refresh_token(token)
-            .await
-            .map_err(std::io::Error::other)?;
+        let refresh_response = try_refresh_token(token).await?;
 
         let updated = update_tokens(
             &self.auth_file,
@@ -156,6 +149,13 @@ impl CodexAuth {
         }
     }
 
+    /// When the ChatGPT access token expires, if it is a JWT with an `exp`
+    /// claim. Always `None` in API key mode.
+    pub fn get_access_token_expiration(&self) -> Option<DateTime<Utc>> {
+        self.get_current_token_data()
+            .and_then(|t| parse_jwt_expiration(&t.access_token))
+    }
+
     pub fn get_account_id(&self) -> Option<String> {
         self.get_current_token_data()
             .and_then(|t| t.account_id.clone())
@@ -278,15 +278,10 @@ pub fn get_auth_file(codex_home: &Path) -> PathBuf {
     codex_home.join("auth.json")
 }
 
-/// Delete the auth.json file inside `codex_home` if it exists. Returns `Ok(true)`
-/// if a file was removed, `Ok(false)` if no auth file was present.
+/// Delete the stored auth.json credential inside `codex_home` if it exists.
+/// Returns `Ok(true)` if it was removed, `Ok(false)` if none was present.
 pub fn logout(codex_home: &Path) -> std::io::Result<bool> {
-    let auth_file = get_auth_file(codex_home);
-    match remove_file(&auth_file) {
-        Ok(_) => Ok(true),
-        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
-        Err(err) => Err(err),
-    }
+    credential_store::delete_credential(&get_auth_file(codex_home))
 }
 
 pub fn login_with_api_key(codex_home: &Path, api_key: &str) -> std::io::Result<()> {
@@ -301,9 +296,7 @@ pub fn login_with_api_key(codex_home: &Path, api_key: &str) -> std::io::Result<(
 /// Attempt to read and refresh the `auth.json` file in the given `CODEX_HOME` directory.
 /// Returns the full AuthDotJson structure after refreshing if necessary.
 pub fn try_read_auth_json(auth_file: &Path) -> std::io::Result<AuthDotJson> {
-    let mut file = File::open(auth_file)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
+    let contents = credential_store::read_credential(auth_file)?;
     let auth_dot_json: AuthDotJson = serde_json::from_str(&contents)?;
 
     Ok(auth_dot_json)
@@ -311,16 +304,7 @@ pub fn try_read_auth_json(auth_file: &Path) -> std::io::Result<AuthDotJson> {
 
 fn write_auth_json(auth_file: &Path, auth_dot_json: &AuthDotJson) -> std::io::Result<()> {
     let json_data = serde_json::to_string_pretty(auth_dot_json)?;
-    let mut options = OpenOptions::new();
-    options.truncate(true).write(true).create(true);
-    #[cfg(unix)]
-    {
-        options.mode(0o600);
-    }
-    let mut file = options.open(auth_file)?;
-    file.write_all(json_data.as_bytes())?;
-    file.flush()?;
-    Ok(())
+    credential_store::write_credential(auth_file, &json_data)
 }
 
 async fn update_tokens(
@@ -368,10 +352,19 @@ async fn try_refresh_token(refresh_token: String) -> std::io::Result<RefreshResp
             .map_err(std::io::Error::other)?;
         Ok(refresh_response)
     } else {
-        Err(std::io::Error::other(format!(
-            "Failed to refresh token: {}",
-            response.status()
-        )))
+        // 400 and 401 mean the refresh token was rejected (revoked, expired
+        // or already used); retrying it cannot succeed.
+        let status = response.status();
+        let kind = match status {
+            reqwest::StatusCode::BAD_REQUEST | reqwest::StatusCode::UNAUTHORIZED => {
+                std::io::ErrorKind::PermissionDenied
+            }
+            _ => std::io::ErrorKind::Other,
+        };
+        Err(std::io::Error::new(
+            kind,
+            format!("Failed to refresh token: {status}"),
+        ))
     }
 }
 
@@ -684,10 +677,16 @@ mod tests {
     fn logout_removes_auth_file() -> Result<(), std::io::Error> {
         let dir = tempdir()?;
         login_with_api_key(dir.path(), "sk-test-key")?;
-        assert!(dir.path().join("auth.json").exists());
+        let auth_file = dir.path().join("auth.json");
+        assert!(credential_store::read_credential(&auth_file).is_ok());
         let removed = logout(dir.path())?;
         assert!(removed);
-        assert!(!dir.path().join("auth.json").exists());
+        assert_eq!(
+            credential_store::read_credential(&auth_file)
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::NotFound
+        );
         Ok(())
     }
 }
@@ -1,4 +1,6 @@
 use base64::Engine;
+use chrono::DateTime;
+use chrono::Utc;
 use serde::Deserialize;
 use serde::Serialize;
 use thiserror::Error;
@@ -155,6 +157,21 @@ pub(crate) fn parse_id_token(id_token: &str) -> Result<IdTokenInfo, IdTokenInfoE
     })
 }
 
+#[derive(Deserialize)]
+struct ExpirationClaim {
+    exp: Option<i64>,
+}
+
+/// Expiration (`exp` claim) of a JWT, if it can be decoded and has one.
+pub(crate) fn parse_jwt_expiration(jwt: &str) -> Option<DateTime<Utc>> {
+    let payload_b64 = jwt.split('.').nth(1)?;
+    let payload_bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload_b64)
+        .ok()?;
+    let claims: ExpirationClaim = serde_json::from_slice(&payload_bytes).ok()?;
+    DateTime::from_timestamp(claims.exp?, 0)
+}
+
 fn deserialize_id_token<'de, D>(deserializer: D) -> Result<IdTokenInfo, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -209,4 +226,17 @@ mod tests {
             Some(PlanType::Known(KnownPlan::Pro))
         );
     }
+
+    #[test]
+    fn jwt_expiration_reads_exp_claim() {
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let payload = engine.encode(br#"{"exp":1700000000}"#);
+        let jwt = format!("e30.{payload}.sig");
+        assert_eq!(
+            parse_jwt_expiration(&jwt),
+            DateTime::from_timestamp(1_700_000_000, 0)
+        );
+
+        assert_eq!(parse_jwt_expiration("Access Token"), None);
+    }
 }
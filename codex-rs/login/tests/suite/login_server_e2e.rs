@@ -118,7 +118,7 @@ async fn end_to_end_login_flow_persists_auth_json() {
 
     // Validate auth.json
     let auth_path = codex_home.join("auth.json");
-    let data = std::fs::read_to_string(&auth_path).unwrap();
+    let data = codex_login::credential_store::read_credential(&auth_path).unwrap();
     let json: serde_json::Value = serde_json::from_str(&data).unwrap();
     assert!(
         !json["OPENAI_API_KEY"].is_null(),
@@ -171,7 +171,7 @@ async fn creates_missing_codex_home_dir() {
 
     let auth_path = codex_home.join("auth.json");
     assert!(
-        auth_path.exists(),
+        codex_login::credential_store::read_credential(&auth_path).is_ok(),
         "auth.json should be created even if parent dir was missing"
     );
 }
@@ -23,6 +23,11 @@ use anyhow::Result;
 use anyhow::anyhow;
 use mcp_types::CallToolRequest;
 use mcp_types::CallToolRequestParams;
+use mcp_types::CancelledNotification;
+use mcp_types::CancelledNotificationParams;
+use mcp_types::GetPromptRequest;
+use mcp_types::GetPromptRequestParams;
+use mcp_types::GetPromptResult;
 use mcp_types::InitializeRequest;
 use mcp_types::InitializeRequestParams;
 use mcp_types::InitializedNotification;
@@ -31,11 +36,20 @@ use mcp_types::JSONRPCMessage;
 use mcp_types::JSONRPCNotification;
 use mcp_types::JSONRPCRequest;
 use mcp_types::JSONRPCResponse;
+use mcp_types::ListPromptsRequest;
+use mcp_types::ListPromptsRequestParams;
+use mcp_types::ListPromptsResult;
+use mcp_types::ListResourcesRequest;
+use mcp_types::ListResourcesRequestParams;
+use mcp_types::ListResourcesResult;
 use mcp_types::ListToolsRequest;
 use mcp_types::ListToolsRequestParams;
 use mcp_types::ListToolsResult;
 use mcp_types::ModelContextProtocolNotification;
 use mcp_types::ModelContextProtocolRequest;
+use mcp_types::ReadResourceRequest;
+use mcp_types::ReadResourceRequestParams;
+use mcp_types::ReadResourceResult;
 use mcp_types::RequestId;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
@@ -59,6 +73,38 @@ const CHANNEL_CAPACITY: usize = 128;
 /// Internal representation of a pending request sender.
 type PendingSender = oneshot::Sender<JSONRPCMessage>;
 
+/// Tells the server that the caller gave up on request `id` when the future
+/// waiting for its response is dropped or times out, e.g. because the turn
+/// that made a tool call was interrupted.
+struct CancelOnDrop<'a> {
+    client: &'a McpClient,
+    id: i64,
+    armed: bool,
+}
+
+impl Drop for CancelOnDrop<'_> {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        if let Ok(mut pending) = self.client.pending.try_lock() {
+            pending.remove(&self.id);
+        }
+        let params = CancelledNotificationParams {
+            reason: Some("request cancelled by the client".to_string()),
+            request_id: RequestId::Integer(self.id),
+        };
+        let notification = JSONRPCMessage::Notification(JSONRPCNotification {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            method: CancelledNotification::METHOD.to_string(),
+            params: serde_json::to_value(params).ok(),
+        });
+        if self.client.outgoing_tx.try_send(notification).is_err() {
+            debug!("failed to send cancellation of request {}", self.id);
+        }
+    }
+}
+
 /// A running MCP client instance.
 pub struct McpClient {
     /// Retain this child process until the client is dropped. The Tokio runtime
@@ -167,6 +213,9 @@ impl McpClient {
                         }
                     }
                 }
+                // The server exited or closed its stdout. Fail the requests
+                // still waiting for a reply rather than leave them hanging.
+                pending.lock().await.clear();
             })
         };
 
@@ -237,6 +286,11 @@ impl McpClient {
                 "failed to send message to writer task - channel closed"
             ));
         }
+        let mut cancel_on_drop = CancelOnDrop {
+            client: self,
+            id,
+            armed: true,
+        };
 
         // Await the response, optionally bounded by a timeout.
         let msg = match timeout {
@@ -263,6 +317,7 @@ impl McpClient {
                 .await
                 .map_err(|_| anyhow!("response channel closed before a reply was received"))?,
         };
+        cancel_on_drop.armed = false;
 
         match msg {
             JSONRPCMessage::Response(JSONRPCResponse { result, .. }) => {
@@ -347,6 +402,48 @@ impl McpClient {
         self.send_request::<CallToolRequest>(params, timeout).await
     }
 
+    /// Convenience wrapper around `resources/list`.
+    pub async fn list_resources(
+        &self,
+        params: Option<ListResourcesRequestParams>,
+        timeout: Option<Duration>,
+    ) -> Result<ListResourcesResult> {
+        self.send_request::<ListResourcesRequest>(params, timeout)
+            .await
+    }
+
+    /// Convenience wrapper around `resources/read`.
+    pub async fn read_resource(
+        &self,
+        uri: String,
+        timeout: Option<Duration>,
+    ) -> Result<ReadResourceResult> {
+        let params = ReadResourceRequestParams { uri };
+        self.send_request::<ReadResourceRequest>(params, timeout)
+            .await
+    }
+
+    /// Convenience wrapper around `prompts/list`.
+    pub async fn list_prompts(
+        &self,
+        params: Option<ListPromptsRequestParams>,
+        timeout: Option<Duration>,
+    ) -> Result<ListPromptsResult> {
+        self.send_request::<ListPromptsRequest>(params, timeout)
+            .await
+    }
+
+    /// Convenience wrapper around `prompts/get`.
+    pub async fn get_prompt(
+        &self,
+        name: String,
+        arguments: Option<serde_json::Value>,
+        timeout: Option<Duration>,
+    ) -> Result<GetPromptResult> {
+        let params = GetPromptRequestParams { arguments, name };
+        self.send_request::<GetPromptRequest>(params, timeout).await
+    }
+
     /// Internal helper: route a JSON-RPC *response* object to the pending map.
     async fn dispatch_response(
         resp: JSONRPCResponse,
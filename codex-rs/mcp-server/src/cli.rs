@@ -0,0 +1,99 @@
+use clap::Parser;
+use clap::Subcommand;
+use clap::ValueEnum;
+use codex_core::config_types::McpServerMode;
+
+use crate::transport::Transport;
+
+#[derive(Parser, Debug, Default)]
+#[command(version)]
+pub struct Cli {
+    /// Listen for MCP clients on this TCP port instead of talking to a
+    /// single client over stdin/stdout. Each connection gets its own session.
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Address to listen on with `--port`. Clients can run commands with
+    /// your permissions, so only listen beyond localhost on a trusted
+    /// network.
+    #[arg(long, default_value = "127.0.0.1", requires = "port")]
+    pub host: String,
+
+    /// Speak WebSocket on `--port`, one JSON-RPC message per text frame,
+    /// instead of newline-delimited JSON.
+    #[arg(long, default_value_t = false, requires = "port")]
+    pub websocket: bool,
+
+    /// Speak MCP Streamable HTTP on `--port`, for web-based clients: POST
+    /// messages to `/mcp` and read the replies as server-sent events.
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "port",
+        conflicts_with = "websocket"
+    )]
+    pub http: bool,
+
+    /// Serve clients beyond localhost even though `mcp_server_auth`
+    /// requires neither a token nor a client certificate. Anyone who can
+    /// connect can then run commands with your permissions.
+    #[arg(long, default_value_t = false, requires = "port")]
+    pub allow_unauthenticated: bool,
+
+    /// What clients may do, overriding `mcp_server_mode` in config.toml.
+    /// `read-only` and `standard` deploy restricted servers; see
+    /// docs/config.md.
+    #[arg(long, value_enum)]
+    pub mode: Option<ModeCliArg>,
+
+    #[command(subcommand)]
+    pub command: Option<McpCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum McpCommand {
+    /// Check that the server could serve clients: load the config, check
+    /// the credentials and the model provider, and register the tools. Exits
+    /// with status 1 if a check fails.
+    Selftest {
+        /// Print the report as JSON.
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+#[value(rename_all = "kebab-case")]
+pub enum ModeCliArg {
+    ReadOnly,
+    Standard,
+    Admin,
+}
+
+impl From<ModeCliArg> for McpServerMode {
+    fn from(value: ModeCliArg) -> Self {
+        match value {
+            ModeCliArg::ReadOnly => McpServerMode::ReadOnly,
+            ModeCliArg::Standard => McpServerMode::Standard,
+            ModeCliArg::Admin => McpServerMode::Admin,
+        }
+    }
+}
+
+impl Cli {
+    pub fn transport(&self) -> Transport {
+        match self.port {
+            None => Transport::Stdio,
+            Some(port) if self.http => Transport::Http {
+                host: self.host.clone(),
+                port,
+            },
+            Some(port) if self.websocket => Transport::WebSocket(format!("{}:{port}", self.host)),
+            Some(port) => Transport::Tcp(format!("{}:{port}", self.host)),
+        }
+    }
+
+    pub fn mode(&self) -> Option<McpServerMode> {
+        self.mode.map(Into::into)
+    }
+}
@@ -10,7 +10,9 @@ use codex_core::config::Config;
 use codex_core::config::ConfigOverrides;
 use codex_core::config::ConfigToml;
 use codex_core::config::load_config_as_toml;
+use codex_core::error::CodexErr;
 use codex_core::git_info::git_diff_to_remote;
+use codex_core::list_sessions;
 use codex_core::protocol::ApplyPatchApprovalRequestEvent;
 use codex_core::protocol::Event;
 use codex_core::protocol::EventMsg;
@@ -29,8 +31,10 @@ use uuid::Uuid;
 use crate::error_code::INTERNAL_ERROR_CODE;
 use crate::error_code::INVALID_REQUEST_ERROR_CODE;
 use crate::json_to_toml::json_to_toml;
+use crate::mode;
 use crate::outgoing_message::OutgoingMessageSender;
 use crate::outgoing_message::OutgoingNotification;
+use crate::resources;
 use codex_core::protocol::InputItem as CoreInputItem;
 use codex_core::protocol::Op;
 use codex_login::CLIENT_ID;
@@ -45,6 +49,7 @@ use codex_protocol::mcp_protocol::ApplyPatchApprovalResponse;
 use codex_protocol::mcp_protocol::AuthStatusChangeNotification;
 use codex_protocol::mcp_protocol::ClientRequest;
 use codex_protocol::mcp_protocol::ConversationId;
+use codex_protocol::mcp_protocol::ConversationSummary;
 use codex_protocol::mcp_protocol::EXEC_COMMAND_APPROVAL_METHOD;
 use codex_protocol::mcp_protocol::ExecCommandApprovalParams;
 use codex_protocol::mcp_protocol::ExecCommandApprovalResponse;
@@ -52,12 +57,16 @@ use codex_protocol::mcp_protocol::GetConfigTomlResponse;
 use codex_protocol::mcp_protocol::InputItem as WireInputItem;
 use codex_protocol::mcp_protocol::InterruptConversationParams;
 use codex_protocol::mcp_protocol::InterruptConversationResponse;
+use codex_protocol::mcp_protocol::ListConversationsParams;
+use codex_protocol::mcp_protocol::ListConversationsResponse;
 use codex_protocol::mcp_protocol::LoginChatGptCompleteNotification;
 use codex_protocol::mcp_protocol::LoginChatGptResponse;
 use codex_protocol::mcp_protocol::NewConversationParams;
 use codex_protocol::mcp_protocol::NewConversationResponse;
 use codex_protocol::mcp_protocol::RemoveConversationListenerParams;
 use codex_protocol::mcp_protocol::RemoveConversationSubscriptionResponse;
+use codex_protocol::mcp_protocol::ResumeConversationParams;
+use codex_protocol::mcp_protocol::ResumeConversationResponse;
 use codex_protocol::mcp_protocol::SendUserMessageParams;
 use codex_protocol::mcp_protocol::SendUserMessageResponse;
 use codex_protocol::mcp_protocol::SendUserTurnParams;
@@ -119,6 +128,14 @@ impl CodexMessageProcessor {
                 // created before processing any subsequent messages.
                 self.process_new_conversation(request_id, params).await;
             }
+            ClientRequest::ListConversations { request_id, params } => {
+                self.list_conversations(request_id, params);
+            }
+            ClientRequest::ResumeConversation { request_id, params } => {
+                // Like new_conversation(), the conversation must exist before
+                // subsequent messages are processed.
+                self.resume_conversation(request_id, params).await;
+            }
             ClientRequest::SendUserMessage { request_id, params } => {
                 self.send_user_message(request_id, params).await;
             }
@@ -135,16 +152,22 @@ impl CodexMessageProcessor {
                 self.remove_conversation_listener(request_id, params).await;
             }
             ClientRequest::GitDiffToRemote { request_id, params } => {
-                self.git_diff_to_origin(request_id, params.cwd).await;
+                self.git_diff_to_origin(request_id, params.cwd);
             }
             ClientRequest::LoginChatGpt { request_id } => {
-                self.login_chatgpt(request_id).await;
+                if self.check_login(&request_id).await {
+                    self.login_chatgpt(request_id).await;
+                }
             }
             ClientRequest::CancelLoginChatGpt { request_id, params } => {
-                self.cancel_login_chatgpt(request_id, params.login_id).await;
+                if self.check_login(&request_id).await {
+                    self.cancel_login_chatgpt(request_id, params.login_id).await;
+                }
             }
             ClientRequest::LogoutChatGpt { request_id } => {
-                self.logout_chatgpt(request_id).await;
+                if self.check_login(&request_id).await {
+                    self.logout_chatgpt(request_id).await;
+                }
             }
             ClientRequest::GetAuthStatus { request_id, params } => {
                 self.get_auth_status(request_id, params).await;
@@ -155,6 +178,20 @@ impl CodexMessageProcessor {
         }
     }
 
+    /// Whether the server's mode lets clients log in or out, answering
+    /// `request_id` with an error when it does not.
+    async fn check_login(&self, request_id: &RequestId) -> bool {
+        match mode::check_login(self.config.mcp_server_mode) {
+            Ok(()) => true,
+            Err(forbidden) => {
+                self.outgoing
+                    .send_error(request_id.clone(), forbidden.to_error())
+                    .await;
+                false
+            }
+        }
+    }
+
     async fn login_chatgpt(&mut self, request_id: RequestId) {
         let config = self.config.as_ref();
 
@@ -416,15 +453,30 @@ impl CodexMessageProcessor {
         self.outgoing.send_response(request_id, response).await;
     }
 
+    /// The config of a conversation started or resumed with `params`, as
+    /// far as the server's mode allows.
+    fn conversation_config(
+        &self,
+        params: NewConversationParams,
+    ) -> Result<Config, JSONRPCErrorError> {
+        let mode = self.config.mcp_server_mode;
+        let config_overrides = params.config.as_ref().is_some_and(|c| !c.is_empty());
+        mode::check_new_conversation(mode, params.sandbox, config_overrides)
+            .map_err(|forbidden| forbidden.to_error())?;
+        let mut config = derive_config_from_params(params, self.codex_linux_sandbox_exe.clone())
+            .map_err(|err| JSONRPCErrorError {
+                code: INVALID_REQUEST_ERROR_CODE,
+                message: format!("error deriving config: {err}"),
+                data: None,
+            })?;
+        mode::restrict_config(mode, &mut config);
+        Ok(config)
+    }
+
     async fn process_new_conversation(&self, request_id: RequestId, params: NewConversationParams) {
-        let config = match derive_config_from_params(params, self.codex_linux_sandbox_exe.clone()) {
+        let config = match self.conversation_config(params) {
             Ok(config) => config,
-            Err(err) => {
-                let error = JSONRPCErrorError {
-                    code: INVALID_REQUEST_ERROR_CODE,
-                    message: format!("error deriving config: {err}"),
-                    data: None,
-                };
+            Err(error) => {
                 self.outgoing.send_error(request_id, error).await;
                 return;
             }
@@ -454,6 +506,90 @@ impl CodexMessageProcessor {
         }
     }
 
+    /// Lists the rollouts in a task of its own so that a long listing does
+    /// not hold up the requests after it.
+    fn list_conversations(&self, request_id: RequestId, params: ListConversationsParams) {
+        let codex_home = self.config.codex_home.clone();
+        let outgoing = self.outgoing.clone();
+        tokio::spawn(async move {
+            match list_sessions(&codex_home).await {
+                Ok(sessions) => {
+                    let conversations = sessions
+                        .into_iter()
+                        .take(params.limit.unwrap_or(usize::MAX))
+                        .map(|session| ConversationSummary {
+                            conversation_id: ConversationId(session.id),
+                            path: session.path,
+                            timestamp: session.timestamp,
+                            cwd: session.cwd,
+                            model: session.model,
+                            preview: session.preview,
+                        })
+                        .collect();
+                    outgoing
+                        .send_response(request_id, ListConversationsResponse { conversations })
+                        .await;
+                }
+                Err(err) => {
+                    let error = JSONRPCErrorError {
+                        code: INTERNAL_ERROR_CODE,
+                        message: format!("error listing conversations: {err}"),
+                        data: None,
+                    };
+                    outgoing.send_error(request_id, error).await;
+                }
+            }
+        });
+    }
+
+    async fn resume_conversation(&self, request_id: RequestId, params: ResumeConversationParams) {
+        let ResumeConversationParams {
+            conversation_id,
+            overrides,
+        } = params;
+        let config = match self.conversation_config(overrides.unwrap_or_default()) {
+            Ok(config) => config,
+            Err(error) => {
+                self.outgoing.send_error(request_id, error).await;
+                return;
+            }
+        };
+
+        match self
+            .conversation_manager
+            .resume_conversation(config, conversation_id.0)
+            .await
+        {
+            Ok(NewConversation {
+                conversation_id,
+                session_configured,
+                ..
+            }) => {
+                let response = ResumeConversationResponse {
+                    conversation_id: ConversationId(conversation_id),
+                    model: session_configured.model,
+                };
+                self.outgoing.send_response(request_id, response).await;
+            }
+            Err(CodexErr::ConversationNotFound(_)) => {
+                let error = JSONRPCErrorError {
+                    code: INVALID_REQUEST_ERROR_CODE,
+                    message: format!("conversation not found: {conversation_id}"),
+                    data: None,
+                };
+                self.outgoing.send_error(request_id, error).await;
+            }
+            Err(err) => {
+                let error = JSONRPCErrorError {
+                    code: INTERNAL_ERROR_CODE,
+                    message: format!("error resuming conversation: {err}"),
+                    data: None,
+                };
+                self.outgoing.send_error(request_id, error).await;
+            }
+        }
+    }
+
     async fn send_user_message(&self, request_id: RequestId, params: SendUserMessageParams) {
         let SendUserMessageParams {
             conversation_id,
@@ -479,6 +615,9 @@ impl CodexMessageProcessor {
                 WireInputItem::Text { text } => CoreInputItem::Text { text },
                 WireInputItem::Image { image_url } => CoreInputItem::Image { image_url },
                 WireInputItem::LocalImage { path } => CoreInputItem::LocalImage { path },
+                WireInputItem::Base64Image { data, mime_type } => {
+                    CoreInputItem::Base64Image { data, mime_type }
+                }
             })
             .collect();
 
@@ -500,13 +639,24 @@ impl CodexMessageProcessor {
             conversation_id,
             items,
             cwd,
-            approval_policy,
+            mut approval_policy,
             sandbox_policy,
             model,
             effort,
             summary,
         } = params;
 
+        if let Err(forbidden) = mode::check_turn(
+            self.config.mcp_server_mode,
+            &sandbox_policy,
+            &mut approval_policy,
+        ) {
+            self.outgoing
+                .send_error(request_id, forbidden.to_error())
+                .await;
+            return;
+        }
+
         let Ok(conversation) = self
             .conversation_manager
             .get_conversation(conversation_id.0)
@@ -527,6 +677,9 @@ impl CodexMessageProcessor {
                 WireInputItem::Text { text } => CoreInputItem::Text { text },
                 WireInputItem::Image { image_url } => CoreInputItem::Image { image_url },
                 WireInputItem::LocalImage { path } => CoreInputItem::LocalImage { path },
+                WireInputItem::Base64Image { data, mime_type } => {
+                    CoreInputItem::Base64Image { data, mime_type }
+                }
             })
             .collect();
 
@@ -539,6 +692,7 @@ impl CodexMessageProcessor {
                 model,
                 effort,
                 summary,
+                response_format: None,
             })
             .await;
 
@@ -676,25 +830,28 @@ impl CodexMessageProcessor {
         }
     }
 
-    async fn git_diff_to_origin(&self, request_id: RequestId, cwd: PathBuf) {
-        let diff = git_diff_to_remote(&cwd).await;
-        match diff {
-            Some(value) => {
-                let response = GitDiffToRemoteResponse {
-                    sha: value.sha,
-                    diff: value.diff,
-                };
-                self.outgoing.send_response(request_id, response).await;
-            }
-            None => {
-                let error = JSONRPCErrorError {
-                    code: INVALID_REQUEST_ERROR_CODE,
-                    message: format!("failed to compute git diff to remote for cwd: {cwd:?}"),
-                    data: None,
-                };
-                self.outgoing.send_error(request_id, error).await;
+    /// Runs git in a task of its own, like `list_conversations`.
+    fn git_diff_to_origin(&self, request_id: RequestId, cwd: PathBuf) {
+        let outgoing = self.outgoing.clone();
+        tokio::spawn(async move {
+            match git_diff_to_remote(&cwd).await {
+                Some(value) => {
+                    let response = GitDiffToRemoteResponse {
+                        sha: value.sha,
+                        diff: value.diff,
+                    };
+                    outgoing.send_response(request_id, response).await;
+                }
+                None => {
+                    let error = JSONRPCErrorError {
+                        code: INVALID_REQUEST_ERROR_CODE,
+                        message: format!("failed to compute git diff to remote for cwd: {cwd:?}"),
+                        data: None,
+                    };
+                    outgoing.send_error(request_id, error).await;
+                }
             }
-        }
+        });
     }
 }
 
@@ -707,6 +864,9 @@ async fn apply_bespoke_event_handling(
 ) {
     let Event { id: event_id, msg } = event;
     match msg {
+        EventMsg::ArtifactCreated(_) => {
+            resources::notify_resource_list_changed(&outgoing).await;
+        }
         EventMsg::ApplyPatchApprovalRequest(ApplyPatchApprovalRequestEvent {
             call_id,
             changes,
@@ -734,6 +894,7 @@ async fn apply_bespoke_event_handling(
             command,
             cwd,
             reason,
+            matched_rule,
         }) => {
             let params = ExecCommandApprovalParams {
                 conversation_id,
@@ -741,6 +902,7 @@ async fn apply_bespoke_event_handling(
                 command,
                 cwd,
                 reason,
+                matched_rule,
             };
             let value = serde_json::to_value(&params).unwrap_or_default();
             let rx = outgoing
@@ -752,6 +914,19 @@ async fn apply_bespoke_event_handling(
                 on_exec_approval_response(event_id, rx, conversation).await;
             });
         }
+        // The protocol has no request for budget approvals yet, so the turn
+        // stops at the limit.
+        EventMsg::BudgetApprovalRequest(_) => {
+            if let Err(err) = conversation
+                .submit(Op::BudgetApproval {
+                    id: event_id,
+                    decision: ReviewDecision::Denied,
+                })
+                .await
+            {
+                error!("failed to submit BudgetApproval: {err}");
+            }
+        }
         // If this is a TurnAborted, reply to any pending interrupt requests.
         EventMsg::TurnAborted(turn_aborted_event) => {
             let pending = {
@@ -220,6 +220,107 @@ pub(crate) fn create_tool_for_codex_tool_call_reply_param() -> Tool {
     }
 }
 
+/// A curated prompt served through the MCP `prompts` capability. `{name}`
+/// placeholders in `template` are replaced with the prompt's arguments.
+/// Bump `version` whenever the wording changes, so clients that cache a
+/// rendered prompt can tell it is stale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CodexPromptTemplate {
+    pub name: &'static str,
+    pub version: u32,
+    pub title: &'static str,
+    pub description: &'static str,
+    pub arguments: &'static [CodexPromptArgument],
+    pub template: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CodexPromptArgument {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Substituted when the client omits the argument. `None` makes the
+    /// argument required.
+    pub default: Option<&'static str>,
+}
+
+pub(crate) const CODEX_PROMPT_TEMPLATES: &[CodexPromptTemplate] = &[
+    CodexPromptTemplate {
+        name: "review-changes",
+        version: 1,
+        title: "Review changes",
+        description: "Review the changes on the current branch without modifying them.",
+        arguments: &[
+            CodexPromptArgument {
+                name: "base",
+                description: "Branch or commit to compare against.",
+                default: Some("origin/main"),
+            },
+            CodexPromptArgument {
+                name: "focus",
+                description: "What the review should pay most attention to.",
+                default: Some("correctness, missing tests and readability"),
+            },
+        ],
+        template: "Review the changes in `git diff {base}...HEAD`. Focus on {focus}. \
+List each finding with its file and line, most severe first, and say how to fix it. \
+Do not modify any files.",
+    },
+    CodexPromptTemplate {
+        name: "fix-failing-tests",
+        version: 1,
+        title: "Fix failing tests",
+        description: "Run a test command and fix the code until it passes.",
+        arguments: &[
+            CodexPromptArgument {
+                name: "command",
+                description: "The command that runs the tests, e.g. `cargo test`.",
+                default: None,
+            },
+            CodexPromptArgument {
+                name: "tests",
+                description: "Which tests to fix.",
+                default: Some("every failing test"),
+            },
+        ],
+        template: "Run `{command}` and fix {tests}. Find the root cause instead of weakening \
+assertions or skipping tests, then run `{command}` again to confirm the fix.",
+    },
+    CodexPromptTemplate {
+        name: "triage-error",
+        version: 1,
+        title: "Triage an error",
+        description: "Trace an error message or stack trace to its cause in this repository.",
+        arguments: &[CodexPromptArgument {
+            name: "error",
+            description: "The error message, log excerpt or stack trace.",
+            default: None,
+        }],
+        template: "Triage this error:\n\n```\n{error}\n```\n\nFind where it is raised in this \
+repository, explain the most likely cause and propose a fix. Do not modify any files until \
+I approve the fix.",
+    },
+    CodexPromptTemplate {
+        name: "explain-code",
+        version: 1,
+        title: "Explain code",
+        description: "Explain a file or directory of this repository.",
+        arguments: &[
+            CodexPromptArgument {
+                name: "path",
+                description: "The file or directory to explain.",
+                default: None,
+            },
+            CodexPromptArgument {
+                name: "question",
+                description: "What to explain about it.",
+                default: Some("how it works and where it is used"),
+            },
+        ],
+        template: "Read `{path}` and explain {question}. Cite the files and functions you \
+rely on.",
+    },
+];
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -16,20 +16,29 @@ use codex_core::protocol::EventMsg;
 use codex_core::protocol::ExecApprovalRequestEvent;
 use codex_core::protocol::InputItem;
 use codex_core::protocol::Op;
+use codex_core::protocol::ReviewDecision;
 use codex_core::protocol::Submission;
 use codex_core::protocol::TaskCompleteEvent;
+use codex_core::protocol::TurnAbortReason;
+use codex_core::protocol::TurnAbortedEvent;
 use mcp_types::CallToolResult;
 use mcp_types::ContentBlock;
+use mcp_types::JSONRPCErrorError;
 use mcp_types::RequestId;
 use mcp_types::TextContent;
 use serde_json::json;
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
+use crate::error_code::INTERNAL_ERROR_CODE;
+use crate::error_code::REQUEST_CANCELLED_ERROR_CODE;
 use crate::exec_approval::handle_exec_approval_request;
+use crate::large_results::LargeResults;
 use crate::outgoing_message::OutgoingMessageSender;
 use crate::outgoing_message::OutgoingNotificationMeta;
 use crate::patch_approval::handle_patch_approval_request;
+use crate::progress::ProgressReporter;
+use crate::resources;
 
 pub(crate) const INVALID_PARAMS_ERROR_CODE: i64 = -32602;
 
@@ -37,13 +46,16 @@ pub(crate) const INVALID_PARAMS_ERROR_CODE: i64 = -32602;
 ///
 /// On completion (success or error) the function sends the appropriate
 /// `tools/call` response so the LLM can continue the conversation.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_codex_tool_session(
     id: RequestId,
     initial_prompt: String,
     config: CodexConfig,
     outgoing: Arc<OutgoingMessageSender>,
     conversation_manager: Arc<ConversationManager>,
-    running_requests_id_to_codex_uuid: Arc<Mutex<HashMap<RequestId, Uuid>>>,
+    running_requests_id_to_codex_uuid: Arc<Mutex<HashMap<RequestId, Option<Uuid>>>>,
+    progress: Option<ProgressReporter>,
+    large_results: LargeResults,
 ) {
     let NewConversation {
         conversation_id,
@@ -62,6 +74,7 @@ pub async fn run_codex_tool_session(
                 structured_content: None,
             };
             outgoing.send_response(id.clone(), result).await;
+            running_requests_id_to_codex_uuid.lock().await.remove(&id);
             return;
         }
     };
@@ -85,10 +98,6 @@ pub async fn run_codex_tool_session(
         RequestId::String(s) => s.clone(),
         RequestId::Integer(n) => n.to_string(),
     };
-    running_requests_id_to_codex_uuid
-        .lock()
-        .await
-        .insert(id.clone(), conversation_id);
     let submission = Submission {
         id: sub_id.clone(),
         op: Op::UserInput {
@@ -104,28 +113,37 @@ pub async fn run_codex_tool_session(
         running_requests_id_to_codex_uuid.lock().await.remove(&id);
         return;
     }
+    mark_running(
+        &conversation,
+        &running_requests_id_to_codex_uuid,
+        &id,
+        conversation_id,
+    )
+    .await;
 
     run_codex_tool_session_inner(
         conversation,
         outgoing,
         id,
         running_requests_id_to_codex_uuid,
+        progress,
+        conversation_id,
+        large_results,
     )
     .await;
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run_codex_tool_session_reply(
     conversation: Arc<CodexConversation>,
     outgoing: Arc<OutgoingMessageSender>,
     request_id: RequestId,
     prompt: String,
-    running_requests_id_to_codex_uuid: Arc<Mutex<HashMap<RequestId, Uuid>>>,
+    running_requests_id_to_codex_uuid: Arc<Mutex<HashMap<RequestId, Option<Uuid>>>>,
     session_id: Uuid,
+    progress: Option<ProgressReporter>,
+    large_results: LargeResults,
 ) {
-    running_requests_id_to_codex_uuid
-        .lock()
-        .await
-        .insert(request_id.clone(), session_id);
     if let Err(e) = conversation
         .submit(Op::UserInput {
             items: vec![InputItem::Text { text: prompt }],
@@ -140,21 +158,60 @@ pub async fn run_codex_tool_session_reply(
             .remove(&request_id);
         return;
     }
+    mark_running(
+        &conversation,
+        &running_requests_id_to_codex_uuid,
+        &request_id,
+        session_id,
+    )
+    .await;
 
     run_codex_tool_session_inner(
         conversation,
         outgoing,
         request_id,
         running_requests_id_to_codex_uuid,
+        progress,
+        session_id,
+        large_results,
     )
     .await;
 }
 
+/// Records that `request_id`, whose input was just submitted, runs in
+/// `conversation_id`. A cancel that arrived while the request was starting
+/// removed it from `running_requests_id_to_codex_uuid`; the submitted turn is
+/// interrupted then, which answers the request as cancelled.
+async fn mark_running(
+    conversation: &CodexConversation,
+    running_requests_id_to_codex_uuid: &Mutex<HashMap<RequestId, Option<Uuid>>>,
+    request_id: &RequestId,
+    conversation_id: Uuid,
+) {
+    let cancelled = match running_requests_id_to_codex_uuid
+        .lock()
+        .await
+        .get_mut(request_id)
+    {
+        Some(entry) => {
+            *entry = Some(conversation_id);
+            false
+        }
+        None => true,
+    };
+    if cancelled && let Err(e) = conversation.submit(Op::Interrupt).await {
+        tracing::error!("Failed to submit interrupt to Codex: {e}");
+    }
+}
+
 async fn run_codex_tool_session_inner(
     codex: Arc<CodexConversation>,
     outgoing: Arc<OutgoingMessageSender>,
     request_id: RequestId,
-    running_requests_id_to_codex_uuid: Arc<Mutex<HashMap<RequestId, Uuid>>>,
+    running_requests_id_to_codex_uuid: Arc<Mutex<HashMap<RequestId, Option<Uuid>>>>,
+    mut progress: Option<ProgressReporter>,
+    conversation_id: Uuid,
+    large_results: LargeResults,
 ) {
     let request_id_str = match &request_id {
         RequestId::String(s) => s.clone(),
@@ -172,6 +229,9 @@ async fn run_codex_tool_session_inner(
                         Some(OutgoingNotificationMeta::new(Some(request_id.clone()))),
                     )
                     .await;
+                if let Some(progress) = &mut progress {
+                    progress.on_event(&event.msg).await;
+                }
 
                 match event.msg {
                     EventMsg::ExecApprovalRequest(ExecApprovalRequestEvent {
@@ -179,6 +239,7 @@ async fn run_codex_tool_session_inner(
                         cwd,
                         call_id,
                         reason: _,
+                        matched_rule: _,
                     }) => {
                         handle_exec_approval_request(
                             command,
@@ -221,21 +282,31 @@ async fn run_codex_tool_session_inner(
                         .await;
                         continue;
                     }
+                    EventMsg::BudgetApprovalRequest(_) => {
+                        // There is no elicitation for budgets, so the turn
+                        // stops at the limit.
+                        if let Err(err) = codex
+                            .submit(Op::BudgetApproval {
+                                id: event.id.clone(),
+                                decision: ReviewDecision::Denied,
+                            })
+                            .await
+                        {
+                            tracing::error!("failed to submit BudgetApproval: {err}");
+                        }
+                        continue;
+                    }
                     EventMsg::TaskComplete(TaskCompleteEvent { last_agent_message }) => {
                         let text = match last_agent_message {
                             Some(msg) => msg.clone(),
                             None => "".to_string(),
                         };
-                        let result = CallToolResult {
-                            content: vec![ContentBlock::TextContent(TextContent {
-                                r#type: "text".to_string(),
-                                text,
-                                annotations: None,
-                            })],
-                            is_error: None,
-                            structured_content: None,
-                        };
+                        let result = large_results.tool_result(conversation_id, text).await;
+                        let saved_resource = result.structured_content.is_some();
                         outgoing.send_response(request_id.clone(), result).await;
+                        if saved_resource {
+                            resources::notify_resource_list_changed(&outgoing).await;
+                        }
                         // unregister the id so we don't keep it in the map
                         running_requests_id_to_codex_uuid
                             .lock()
@@ -243,6 +314,31 @@ async fn run_codex_tool_session_inner(
                             .remove(&request_id);
                         break;
                     }
+                    EventMsg::TurnAborted(TurnAbortedEvent { reason }) => {
+                        // An aborted turn never completes, so the call is
+                        // answered here.
+                        let error = match reason {
+                            TurnAbortReason::Interrupted => JSONRPCErrorError {
+                                code: REQUEST_CANCELLED_ERROR_CODE,
+                                message: "request cancelled".to_string(),
+                                data: None,
+                            },
+                            TurnAbortReason::Replaced => JSONRPCErrorError {
+                                code: INTERNAL_ERROR_CODE,
+                                message: "the turn was replaced by a newer one".to_string(),
+                                data: None,
+                            },
+                        };
+                        outgoing.send_error(request_id.clone(), error).await;
+                        running_requests_id_to_codex_uuid
+                            .lock()
+                            .await
+                            .remove(&request_id);
+                        break;
+                    }
+                    EventMsg::ArtifactCreated(_) => {
+                        resources::notify_resource_list_changed(&outgoing).await;
+                    }
                     EventMsg::SessionConfigured(_) => {
                         tracing::error!("unexpected SessionConfigured event");
                     }
@@ -265,6 +361,8 @@ async fn run_codex_tool_session_inner(
                     | EventMsg::McpToolCallEnd(_)
                     | EventMsg::McpListToolsResponse(_)
                     | EventMsg::ListCustomPromptsResponse(_)
+                    | EventMsg::McpListPromptsResponse(_)
+                    | EventMsg::McpGetPromptResponse(_)
                     | EventMsg::ExecCommandBegin(_)
                     | EventMsg::ExecCommandOutputDelta(_)
                     | EventMsg::ExecCommandEnd(_)
@@ -277,8 +375,15 @@ async fn run_codex_tool_session_inner(
                     | EventMsg::WebSearchEnd(_)
                     | EventMsg::GetHistoryEntryResponse(_)
                     | EventMsg::PlanUpdate(_)
-                    | EventMsg::TurnAborted(_)
                     | EventMsg::ConversationHistory(_)
+                    | EventMsg::ConversationBranch(_)
+                    | EventMsg::ConversationExport(_)
+                    | EventMsg::BackgroundJobStarted(_)
+                    | EventMsg::BackgroundJobEnded(_)
+                    | EventMsg::McpServerStatus(_)
+                    | EventMsg::ProviderHealth(_)
+                    | EventMsg::ModelRouted(_)
+                    | EventMsg::BudgetWarning(_)
                     | EventMsg::ShutdownComplete => {
                         // For now, we do not do anything extra for these
                         // events. Note that
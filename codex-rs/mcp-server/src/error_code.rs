@@ -1,2 +1,10 @@
 pub(crate) const INVALID_REQUEST_ERROR_CODE: i64 = -32600;
 pub(crate) const INTERNAL_ERROR_CODE: i64 = -32603;
+/// Answers a `resources/read` of a resource that does not exist.
+pub(crate) const RESOURCE_NOT_FOUND_ERROR_CODE: i64 = -32002;
+/// Answers a tool call refused by the limits in `[mcp_server_limits]`.
+pub(crate) const THROTTLED_ERROR_CODE: i64 = -32003;
+/// Answers a request the server's `--mode` does not allow.
+pub(crate) const FORBIDDEN_ERROR_CODE: i64 = -32004;
+/// Answers a request the client cancelled, as in the Language Server Protocol.
+pub(crate) const REQUEST_CANCELLED_ERROR_CODE: i64 = -32800;
@@ -0,0 +1,120 @@
+//! Readiness checks for `/readyz` and `selftest`: whether a new session
+//! could do any work right now, as opposed to `/healthz`, which only says the
+//! server answers.
+
+use std::time::Duration;
+
+use codex_core::ModelProviderInfo;
+use codex_core::config::Config;
+use codex_login::AuthManager;
+use codex_login::CodexAuth;
+use tokio::net::TcpStream;
+
+/// How long the model provider gets to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The outcome of one readiness check.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Check {
+    pub name: &'static str,
+    pub result: Result<(), String>,
+}
+
+/// Checks that `CODEX_HOME` exists, that there are credentials for the
+/// model provider and that the provider accepts connections.
+pub(crate) async fn readiness(config: &Config, auth_manager: &AuthManager) -> Vec<Check> {
+    let auth = auth_manager.auth();
+    let codex_home = if config.codex_home.is_dir() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} is not a directory",
+            config.codex_home.display()
+        ))
+    };
+    vec![
+        Check {
+            name: "codex_home",
+            result: codex_home,
+        },
+        Check {
+            name: "auth",
+            result: check_auth(&config.model_provider, &auth),
+        },
+        Check {
+            name: "model_provider",
+            result: check_reachable(&config.model_provider.get_full_url(&auth, &config.model))
+                .await,
+        },
+    ]
+}
+
+/// Mirrors how requests to the provider pick their credentials: the
+/// provider's API key if set, otherwise the login in `CODEX_HOME`.
+fn check_auth(provider: &ModelProviderInfo, auth: &Option<CodexAuth>) -> Result<(), String> {
+    match provider.api_key() {
+        Ok(Some(_)) => Ok(()),
+        _ if auth.is_some() => Ok(()),
+        Err(e) => Err(e.to_string()),
+        Ok(None) if provider.requires_openai_auth => {
+            Err("not logged in; run `codex login`".to_string())
+        }
+        Ok(None) => Ok(()),
+    }
+}
+
+async fn check_reachable(url: &str) -> Result<(), String> {
+    let (host, port) = host_and_port(url).ok_or_else(|| format!("invalid URL {url}"))?;
+    match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host.as_str(), port))).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(format!("cannot connect to {host}:{port}: {e}")),
+        Err(_) => Err(format!("timed out connecting to {host}:{port}")),
+    }
+}
+
+fn host_and_port(url: &str) -> Option<(String, u16)> {
+    let url = url::Url::parse(url).ok()?;
+    Some((url.host_str()?.to_string(), url.port_or_known_default()?))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use codex_core::create_oss_provider_with_base_url;
+    use pretty_assertions::assert_eq;
+    use tokio::net::TcpListener;
+
+    fn provider(env_key: Option<&str>, requires_openai_auth: bool) -> ModelProviderInfo {
+        ModelProviderInfo {
+            env_key: env_key.map(str::to_string),
+            requires_openai_auth,
+            ..create_oss_provider_with_base_url("http://localhost:11434/v1")
+        }
+    }
+
+    #[test]
+    fn requires_credentials_the_provider_would_use() {
+        let login = Some(CodexAuth::from_api_key("sk-test"));
+        assert_eq!(check_auth(&provider(None, false), &None), Ok(()));
+        assert!(check_auth(&provider(None, true), &None).is_err());
+        assert_eq!(check_auth(&provider(None, true), &login), Ok(()));
+        let missing = provider(Some("CODEX_HEALTH_TEST_MISSING_KEY"), false);
+        assert!(check_auth(&missing, &None).is_err());
+        assert_eq!(check_auth(&missing, &login), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn checks_that_the_provider_accepts_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert_eq!(check_reachable(&format!("http://{addr}/v1")).await, Ok(()));
+        drop(listener);
+        assert!(check_reachable(&format!("http://{addr}/v1")).await.is_err());
+        assert_eq!(
+            host_and_port("https://api.openai.com/v1/responses"),
+            Some(("api.openai.com".to_string(), 443))
+        );
+    }
+}
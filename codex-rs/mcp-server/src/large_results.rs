@@ -0,0 +1,168 @@
+//! Keeps `codex` tool results within `mcp_server_max_result_bytes`. A larger
+//! result is saved as an artifact of the conversation, which makes it an MCP
+//! resource, and the client is sent its beginning, its size and a link to
+//! read the rest a page at a time.
+
+use std::path::PathBuf;
+
+use mcp_types::CallToolResult;
+use mcp_types::ContentBlock;
+use mcp_types::ResourceLink;
+use mcp_types::TextContent;
+use serde_json::json;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::resources::artifact_uri;
+use crate::resources::artifacts_dir;
+use crate::resources::page_count;
+
+#[derive(Debug, Clone)]
+pub(crate) struct LargeResults {
+    codex_home: PathBuf,
+    /// 0 sends every result inline.
+    max_bytes: usize,
+}
+
+impl LargeResults {
+    pub(crate) fn new(codex_home: PathBuf, max_bytes: usize) -> Self {
+        Self {
+            codex_home,
+            max_bytes,
+        }
+    }
+
+    /// The result of a tool call of `conversation_id` that answered `text`.
+    pub(crate) async fn tool_result(&self, conversation_id: Uuid, text: String) -> CallToolResult {
+        if self.max_bytes == 0 || text.len() <= self.max_bytes {
+            return text_result(text);
+        }
+        let name = format!("result-{}.txt", Uuid::new_v4().simple());
+        let dir = artifacts_dir(&self.codex_home, conversation_id);
+        let saved = async {
+            tokio::fs::create_dir_all(&dir).await?;
+            tokio::fs::write(dir.join(&name), &text).await
+        };
+        if let Err(e) = saved.await {
+            warn!("failed to save a large tool result, sending it inline: {e}");
+            return text_result(text);
+        }
+
+        let uri = artifact_uri(conversation_id, &name);
+        let excerpt = excerpt(&text, self.max_bytes);
+        let pages = page_count(text.len());
+        let notice = format!(
+            "\n\n[Result truncated: showing {} of {} bytes. The full result is the resource \
+             {uri}; read it with resources/read, or one page at a time by appending \
+             `?page=N` (pages 1 to {pages}).]",
+            excerpt.len(),
+            text.len(),
+        );
+        CallToolResult {
+            content: vec![
+                ContentBlock::TextContent(TextContent {
+                    r#type: "text".to_string(),
+                    text: format!("{excerpt}{notice}"),
+                    annotations: None,
+                }),
+                ContentBlock::ResourceLink(ResourceLink {
+                    annotations: None,
+                    description: Some("The full result of the tool call.".to_string()),
+                    mime_type: Some("text/plain".to_string()),
+                    name,
+                    size: i64::try_from(text.len()).ok(),
+                    title: None,
+                    r#type: "resource_link".to_string(),
+                    uri: uri.clone(),
+                }),
+            ],
+            is_error: None,
+            structured_content: Some(json!({
+                "truncated": true,
+                "resource": uri,
+                "totalBytes": text.len(),
+                "pages": pages,
+            })),
+        }
+    }
+}
+
+fn text_result(text: String) -> CallToolResult {
+    CallToolResult {
+        content: vec![ContentBlock::TextContent(TextContent {
+            r#type: "text".to_string(),
+            text,
+            annotations: None,
+        })],
+        is_error: None,
+        structured_content: None,
+    }
+}
+
+/// The start of `text`, at most `max_bytes` long, cut after the last full
+/// line when there is one.
+fn excerpt(text: &str, max_bytes: usize) -> &str {
+    let mut end = max_bytes.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    match text[..end].rfind('\n') {
+        Some(newline) if newline > 0 => &text[..newline],
+        _ => &text[..end],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn saves_results_over_the_limit_as_resources() {
+        let codex_home = TempDir::new().unwrap();
+        let conversation_id = Uuid::new_v4();
+        let large_results = LargeResults::new(codex_home.path().to_path_buf(), 16);
+
+        let small = large_results
+            .tool_result(conversation_id, "all done".to_string())
+            .await;
+        assert_eq!(small, text_result("all done".to_string()));
+
+        let text = "line one\nline two\nline three\n".to_string();
+        let large = large_results
+            .tool_result(conversation_id, text.clone())
+            .await;
+        let ContentBlock::TextContent(summary) = &large.content[0] else {
+            panic!("expected text first: {large:?}");
+        };
+        assert!(
+            summary
+                .text
+                .starts_with("line one\n\n[Result truncated: showing 8 of 29 bytes.")
+        );
+        let ContentBlock::ResourceLink(link) = &large.content[1] else {
+            panic!("expected a resource link: {large:?}");
+        };
+        assert_eq!(
+            large.structured_content,
+            Some(json!({
+                "truncated": true,
+                "resource": link.uri,
+                "totalBytes": 29,
+                "pages": 1,
+            }))
+        );
+        let saved = artifacts_dir(codex_home.path(), conversation_id).join(&link.name);
+        assert_eq!(std::fs::read_to_string(saved).unwrap(), text);
+    }
+
+    #[test]
+    fn cuts_excerpts_at_line_and_character_boundaries() {
+        assert_eq!(excerpt("abc\ndef", 6), "abc");
+        assert_eq!(excerpt("abcdef", 4), "abcd");
+        assert_eq!(excerpt("ééé", 3), "é");
+    }
+}
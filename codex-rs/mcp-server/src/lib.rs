@@ -4,42 +4,68 @@
 use std::io::ErrorKind;
 use std::io::Result as IoResult;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use codex_common::CliConfigOverrides;
+use codex_core::Redactor;
 use codex_core::config::Config;
 use codex_core::config::ConfigOverrides;
+use codex_core::config_types::McpServerMode;
+use codex_login::AuthManager;
 
 use mcp_types::JSONRPCMessage;
-use tokio::io::AsyncBufReadExt;
-use tokio::io::AsyncWriteExt;
-use tokio::io::BufReader;
 use tokio::io::{self};
+use tokio::net::TcpListener;
+use tokio::net::ToSocketAddrs;
 use tokio::sync::mpsc;
-use tracing::debug;
-use tracing::error;
+use tracing::Instrument;
 use tracing::info;
+use tracing::info_span;
+use tracing::warn;
 use tracing_subscriber::EnvFilter;
 
+mod cli;
 mod codex_message_processor;
 mod codex_tool_config;
 mod codex_tool_runner;
 mod error_code;
 mod exec_approval;
+mod health;
 mod json_to_toml;
+mod large_results;
+mod limits;
 pub(crate) mod message_processor;
+mod metrics;
+mod mode;
 mod outgoing_message;
 mod patch_approval;
+mod progress;
+mod prompts;
+mod request_log;
+mod resources;
+mod security;
+mod selftest;
+mod streamable_http;
+mod transport;
 
+use crate::limits::ToolLimiter;
 use crate::message_processor::MessageProcessor;
 use crate::outgoing_message::OutgoingMessage;
 use crate::outgoing_message::OutgoingMessageSender;
+use crate::request_log::RequestLog;
+use crate::request_log::RequestTracker;
+use crate::security::Security;
 
+pub use crate::cli::Cli;
+pub use crate::cli::McpCommand;
 pub use crate::codex_tool_config::CodexToolCallParam;
 pub use crate::codex_tool_config::CodexToolCallReplyParam;
 pub use crate::exec_approval::ExecApprovalElicitRequestParams;
 pub use crate::exec_approval::ExecApprovalResponse;
 pub use crate::patch_approval::PatchApprovalElicitRequestParams;
 pub use crate::patch_approval::PatchApprovalResponse;
+pub use crate::selftest::run_selftest;
+pub use crate::transport::Transport;
 
 /// Size of the bounded channels used to communicate between tasks. The value
 /// is a balance between throughput and memory usage – 128 messages should be
@@ -49,6 +75,9 @@ const CHANNEL_CAPACITY: usize = 128;
 pub async fn run_main(
     codex_linux_sandbox_exe: Option<PathBuf>,
     cli_config_overrides: CliConfigOverrides,
+    transport: Transport,
+    mode: Option<McpServerMode>,
+    allow_unauthenticated: bool,
 ) -> IoResult<()> {
     // Install a simple subscriber so `tracing` output is visible.  Users can
     // control the log level with `RUST_LOG`.
@@ -57,95 +86,228 @@ pub async fn run_main(
         .with_env_filter(EnvFilter::from_default_env())
         .init();
 
-    // Set up channels.
-    let (incoming_tx, mut incoming_rx) = mpsc::channel::<JSONRPCMessage>(CHANNEL_CAPACITY);
-    let (outgoing_tx, mut outgoing_rx) = mpsc::unbounded_channel::<OutgoingMessage>();
-
-    // Task: read from stdin, push to `incoming_tx`.
-    let stdin_reader_handle = tokio::spawn({
-        async move {
-            let stdin = io::stdin();
-            let reader = BufReader::new(stdin);
-            let mut lines = reader.lines();
-
-            while let Some(line) = lines.next_line().await.unwrap_or_default() {
-                match serde_json::from_str::<JSONRPCMessage>(&line) {
-                    Ok(msg) => {
-                        if incoming_tx.send(msg).await.is_err() {
-                            // Receiver gone – nothing left to do.
-                            break;
-                        }
-                    }
-                    Err(e) => error!("Failed to deserialize JSONRPCMessage: {e}"),
-                }
-            }
+    // Derive the base Config eagerly so later components do not need to
+    // work with raw TOML values.
+    let config = load_config(cli_config_overrides, mode)?;
+    info!("serving MCP clients in {} mode", config.mcp_server_mode);
+    let security = Security::from_config(&config.mcp_server_auth).map_err(|e| {
+        std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("error loading mcp_server_auth: {e:#}"),
+        )
+    })?;
+    if security.requires_token() && matches!(transport, Transport::Tcp(_)) {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "bearer tokens need --websocket or --http; raw TCP clients can only be \
+             authenticated with client certificates",
+        ));
+    }
+    let security = Arc::new(security);
+    let context = ServerContext {
+        codex_linux_sandbox_exe,
+        auth_manager: AuthManager::shared(config.codex_home.clone(), config.preferred_auth_method),
+        tool_limiter: Arc::new(ToolLimiter::new(&config.mcp_server_limits)),
+        request_log: Arc::new(RequestLog::new(
+            &codex_core::config::log_dir(&config)?,
+            // The patterns were validated when the config was loaded.
+            Redactor::new(&config.redaction).unwrap_or_default(),
+        )),
+        config: Arc::new(config),
+    };
 
-            debug!("stdin reader finished (EOF)");
+    let tls = security.uses_tls();
+    match transport {
+        Transport::Stdio => {
+            transport::serve_lines(io::stdin(), io::stdout(), context, false).await;
+            Ok(())
+        }
+        Transport::Tcp(addr) => {
+            let listener = bind(&addr, &security, allow_unauthenticated).await?;
+            let scheme = if tls { "tls" } else { "tcp" };
+            info!(
+                "listening for MCP clients on {scheme}://{}",
+                listener.local_addr()?
+            );
+            transport::serve_tcp(listener, security, context).await
         }
-    });
+        Transport::Http { host, port } => {
+            let listener = bind((host.as_str(), port), &security, allow_unauthenticated).await?;
+            let scheme = if tls { "https" } else { "http" };
+            info!(
+                "listening for MCP clients on {scheme}://{}{}",
+                listener.local_addr()?,
+                streamable_http::ENDPOINT_PATH
+            );
+            streamable_http::serve_http(listener, host, security, context).await
+        }
+        Transport::WebSocket(addr) => {
+            let listener = bind(&addr, &security, allow_unauthenticated).await?;
+            let scheme = if tls { "wss" } else { "ws" };
+            info!(
+                "listening for MCP clients on {scheme}://{}",
+                listener.local_addr()?
+            );
+            let host = addr
+                .rsplit_once(':')
+                .map_or(addr.as_str(), |(host, _)| host);
+            transport::serve_websocket(listener, host.to_string(), security, context).await
+        }
+    }
+}
 
-    // Parse CLI overrides once and derive the base Config eagerly so later
-    // components do not need to work with raw TOML values.
+/// Loads the server's config, with `-c` overrides and `--mode` applied.
+pub(crate) fn load_config(
+    cli_config_overrides: CliConfigOverrides,
+    mode: Option<McpServerMode>,
+) -> IoResult<Config> {
     let cli_kv_overrides = cli_config_overrides.parse_overrides().map_err(|e| {
         std::io::Error::new(
             ErrorKind::InvalidInput,
             format!("error parsing -c overrides: {e}"),
         )
     })?;
-    let config = Config::load_with_cli_overrides(cli_kv_overrides, ConfigOverrides::default())
+    let mut config = Config::load_with_cli_overrides(cli_kv_overrides, ConfigOverrides::default())
         .map_err(|e| {
             std::io::Error::new(ErrorKind::InvalidData, format!("error loading config: {e}"))
         })?;
+    if let Some(mode) = mode {
+        config.mcp_server_mode = mode;
+    }
+    Ok(config)
+}
 
-    // Task: process incoming messages.
-    let processor_handle = tokio::spawn({
-        let outgoing_message_sender = OutgoingMessageSender::new(outgoing_tx);
-        let mut processor = MessageProcessor::new(
-            outgoing_message_sender,
-            codex_linux_sandbox_exe,
-            std::sync::Arc::new(config),
+/// Binds `addr`. Clients beyond this machine may only use the server without
+/// authenticating when `allow_unauthenticated` says so, and then with a
+/// warning.
+async fn bind(
+    addr: impl ToSocketAddrs,
+    security: &Security,
+    allow_unauthenticated: bool,
+) -> IoResult<TcpListener> {
+    let listener = TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+    if security.is_open() && !local_addr.ip().is_loopback() {
+        if !allow_unauthenticated {
+            return Err(std::io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "refusing to listen on {local_addr} without authentication: anyone who can \
+                     connect could run commands with your permissions. Configure \
+                     [mcp_server_auth] to require a token or a client certificate, or pass \
+                     --allow-unauthenticated."
+                ),
+            ));
+        }
+        warn!(
+            "listening on {local_addr} without authentication; anyone who can connect can run \
+             commands with your permissions"
         );
-        async move {
-            while let Some(msg) = incoming_rx.recv().await {
-                match msg {
-                    JSONRPCMessage::Request(r) => processor.process_request(r).await,
-                    JSONRPCMessage::Response(r) => processor.process_response(r).await,
-                    JSONRPCMessage::Notification(n) => processor.process_notification(n).await,
-                    JSONRPCMessage::Error(e) => processor.process_error(e),
-                }
-            }
+    }
+    Ok(listener)
+}
+
+/// What every client session is created with. Credentials are shared by
+/// all sessions, so a token refreshed for one client is seen by the others.
+#[derive(Clone)]
+pub(crate) struct ServerContext {
+    codex_linux_sandbox_exe: Option<PathBuf>,
+    config: Arc<Config>,
+    auth_manager: Arc<AuthManager>,
+    tool_limiter: Arc<ToolLimiter>,
+    request_log: Arc<RequestLog>,
+}
 
-            info!("processor task exited (channel closed)");
+#[cfg(test)]
+impl ServerContext {
+    /// A context with the default config and `codex_home` as its home.
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn for_tests(codex_home: &std::path::Path) -> Self {
+        let config = Config::load_from_base_config_with_overrides(
+            codex_core::config::ConfigToml::default(),
+            ConfigOverrides::default(),
+            codex_home.to_path_buf(),
+        )
+        .unwrap();
+        Self {
+            codex_linux_sandbox_exe: None,
+            auth_manager: AuthManager::shared(
+                config.codex_home.clone(),
+                config.preferred_auth_method,
+            ),
+            tool_limiter: Arc::default(),
+            request_log: Arc::new(RequestLog::disabled()),
+            config: Arc::new(config),
         }
-    });
-
-    // Task: write outgoing messages to stdout.
-    let stdout_writer_handle = tokio::spawn(async move {
-        let mut stdout = io::stdout();
-        while let Some(outgoing_message) = outgoing_rx.recv().await {
-            let msg: JSONRPCMessage = outgoing_message.into();
-            match serde_json::to_string(&msg) {
-                Ok(json) => {
-                    if let Err(e) = stdout.write_all(json.as_bytes()).await {
-                        error!("Failed to write to stdout: {e}");
-                        break;
-                    }
-                    if let Err(e) = stdout.write_all(b"\n").await {
-                        error!("Failed to write newline to stdout: {e}");
+    }
+}
+
+/// Processes the messages of one client until `incoming_rx` closes.
+pub(crate) async fn process_messages(
+    context: ServerContext,
+    mut incoming_rx: mpsc::Receiver<JSONRPCMessage>,
+    outgoing_tx: mpsc::UnboundedSender<OutgoingMessage>,
+) {
+    // Task: log the answers to the client's requests on their way out.
+    let requests = Arc::new(RequestTracker::new(context.request_log));
+    let (processor_tx, mut processor_rx) = mpsc::unbounded_channel::<OutgoingMessage>();
+    tokio::spawn(
+        {
+            let requests = requests.clone();
+            async move {
+                while let Some(mut msg) = processor_rx.recv().await {
+                    requests.finish(&mut msg);
+                    if outgoing_tx.send(msg).is_err() {
                         break;
                     }
                 }
-                Err(e) => error!("Failed to serialize JSONRPCMessage: {e}"),
             }
         }
+        .in_current_span(),
+    );
+
+    let outgoing_message_sender = OutgoingMessageSender::new(processor_tx);
+    let mut processor = MessageProcessor::new(
+        outgoing_message_sender,
+        context.codex_linux_sandbox_exe,
+        context.config,
+        context.auth_manager,
+        context.tool_limiter,
+    );
+    while let Some(msg) = incoming_rx.recv().await {
+        match msg {
+            JSONRPCMessage::Request(r) => {
+                let correlation_id = requests.start(&r);
+                processor
+                    .process_request(r)
+                    .instrument(info_span!("mcp_request", %correlation_id))
+                    .await
+            }
+            JSONRPCMessage::Response(r) => processor.process_response(r).await,
+            JSONRPCMessage::Notification(n) => processor.process_notification(n).await,
+            JSONRPCMessage::Error(e) => processor.process_error(e),
+        }
+    }
+
+    info!("processor task exited (channel closed)");
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
 
-        info!("stdout writer exited (channel closed)");
-    });
+    #[tokio::test]
+    async fn refuses_to_serve_the_network_without_authentication() {
+        let security = Security::default();
 
-    // Wait for all tasks to finish.  The typical exit path is the stdin reader
-    // hitting EOF which, once it drops `incoming_tx`, propagates shutdown to
-    // the processor and then to the stdout task.
-    let _ = tokio::join!(stdin_reader_handle, processor_handle, stdout_writer_handle);
+        let error = bind("0.0.0.0:0", &security, false).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        assert!(error.to_string().contains("--allow-unauthenticated"));
 
-    Ok(())
+        assert!(bind("0.0.0.0:0", &security, true).await.is_ok());
+        assert!(bind("127.0.0.1:0", &security, false).await.is_ok());
+    }
 }
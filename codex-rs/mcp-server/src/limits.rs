@@ -0,0 +1,250 @@
+//! Per-tool concurrency and rate limits from `[mcp_server_limits]`. The
+//! limits are shared by every client of the server, and calls over a limit
+//! are refused with a throttling error that says when to retry.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use codex_core::config_types::McpToolLimits;
+use mcp_types::JSONRPCErrorError;
+use serde_json::json;
+
+use crate::error_code::THROTTLED_ERROR_CODE;
+
+/// The window `max_per_minute` counts calls in.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// When a client refused for concurrency should try again. How long the
+/// running calls take is unknown, so this is only a hint.
+const CONCURRENCY_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+pub(crate) struct ToolLimiter {
+    tools: HashMap<String, Arc<ToolLimit>>,
+}
+
+struct ToolLimit {
+    limits: McpToolLimits,
+    state: Mutex<LimitState>,
+}
+
+#[derive(Default)]
+struct LimitState {
+    running: usize,
+    /// When the calls of the last [`RATE_WINDOW`] started.
+    started: VecDeque<Instant>,
+}
+
+/// Held while a call runs; dropping it lets the next call in.
+pub(crate) struct ToolPermit {
+    limit: Arc<ToolLimit>,
+}
+
+impl Drop for ToolPermit {
+    fn drop(&mut self) {
+        let mut state = lock(&self.limit.state);
+        state.running = state.running.saturating_sub(1);
+    }
+}
+
+/// Why a call was refused.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Throttled {
+    tool: String,
+    reason: ThrottleReason,
+    retry_after: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ThrottleReason {
+    Concurrency { max_concurrent: usize },
+    Rate { max_per_minute: u32 },
+}
+
+impl ToolLimiter {
+    pub(crate) fn new(limits: &HashMap<String, McpToolLimits>) -> Self {
+        let tools = limits
+            .iter()
+            .map(|(tool, limits)| {
+                let limit = ToolLimit {
+                    limits: *limits,
+                    state: Mutex::default(),
+                };
+                (tool.clone(), Arc::new(limit))
+            })
+            .collect();
+        Self { tools }
+    }
+
+    /// Admits a call to `tool`. The permit is `None` for tools without
+    /// limits.
+    pub(crate) fn try_acquire(&self, tool: &str) -> Result<Option<ToolPermit>, Throttled> {
+        match self.tools.get(tool) {
+            Some(limit) => limit.try_acquire(tool, Instant::now()).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl ToolLimit {
+    fn try_acquire(self: &Arc<Self>, tool: &str, now: Instant) -> Result<ToolPermit, Throttled> {
+        let mut state = lock(&self.state);
+        while state
+            .started
+            .front()
+            .is_some_and(|started| now.duration_since(*started) >= RATE_WINDOW)
+        {
+            state.started.pop_front();
+        }
+
+        if let Some(max_concurrent) = self.limits.max_concurrent
+            && state.running >= max_concurrent
+        {
+            return Err(Throttled {
+                tool: tool.to_string(),
+                reason: ThrottleReason::Concurrency { max_concurrent },
+                retry_after: CONCURRENCY_RETRY_AFTER,
+            });
+        }
+        if let Some(max_per_minute) = self.limits.max_per_minute
+            && state.started.len() >= max_per_minute as usize
+        {
+            let retry_after = state.started.front().map_or(RATE_WINDOW, |oldest| {
+                RATE_WINDOW - now.duration_since(*oldest)
+            });
+            return Err(Throttled {
+                tool: tool.to_string(),
+                reason: ThrottleReason::Rate { max_per_minute },
+                retry_after,
+            });
+        }
+
+        state.running += 1;
+        state.started.push_back(now);
+        Ok(ToolPermit {
+            limit: self.clone(),
+        })
+    }
+}
+
+impl Throttled {
+    /// The JSON-RPC error answering the refused call. `data` carries the
+    /// limit that was hit and `retryAfterSeconds`.
+    pub(crate) fn to_error(&self) -> JSONRPCErrorError {
+        // Round up so that retrying after the hint never hits the limit again.
+        let retry_after_seconds =
+            self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0);
+        let (message, limit) = match self.reason {
+            ThrottleReason::Concurrency { max_concurrent } => (
+                format!(
+                    "tool `{}` is throttled: {max_concurrent} calls are already running",
+                    self.tool
+                ),
+                json!({ "maxConcurrent": max_concurrent }),
+            ),
+            ThrottleReason::Rate { max_per_minute } => (
+                format!(
+                    "tool `{}` is throttled: at most {max_per_minute} calls per minute",
+                    self.tool
+                ),
+                json!({ "maxPerMinute": max_per_minute }),
+            ),
+        };
+        JSONRPCErrorError {
+            code: THROTTLED_ERROR_CODE,
+            message,
+            data: Some(json!({
+                "tool": self.tool,
+                "limit": limit,
+                "retryAfterSeconds": retry_after_seconds,
+            })),
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn limit(max_concurrent: Option<usize>, max_per_minute: Option<u32>) -> Arc<ToolLimit> {
+        Arc::new(ToolLimit {
+            limits: McpToolLimits {
+                max_concurrent,
+                max_per_minute,
+            },
+            state: Mutex::default(),
+        })
+    }
+
+    #[test]
+    fn caps_concurrent_calls() {
+        let limit = limit(Some(2), None);
+        let now = Instant::now();
+        let first = limit.try_acquire("codex", now).unwrap();
+        let _second = limit.try_acquire("codex", now).unwrap();
+
+        let throttled = limit.try_acquire("codex", now).err().unwrap();
+        assert_eq!(
+            throttled.to_error().data,
+            Some(json!({
+                "tool": "codex",
+                "limit": { "maxConcurrent": 2 },
+                "retryAfterSeconds": 5,
+            }))
+        );
+
+        drop(first);
+        assert!(limit.try_acquire("codex", now).is_ok());
+    }
+
+    #[test]
+    fn limits_calls_per_minute() {
+        let limit = limit(None, Some(2));
+        let start = Instant::now();
+        drop(limit.try_acquire("codex", start).unwrap());
+        drop(
+            limit
+                .try_acquire("codex", start + Duration::from_secs(20))
+                .unwrap(),
+        );
+
+        let throttled = limit
+            .try_acquire("codex", start + Duration::from_millis(30_500))
+            .err()
+            .unwrap();
+        assert_eq!(throttled.retry_after, Duration::from_millis(29_500));
+        assert_eq!(
+            throttled.to_error().data.unwrap()["retryAfterSeconds"],
+            json!(30)
+        );
+
+        // The first call has left the window.
+        assert!(limit.try_acquire("codex", start + RATE_WINDOW).is_ok());
+    }
+
+    #[test]
+    fn tools_without_limits_are_not_counted() {
+        let limiter = ToolLimiter::new(&HashMap::from([(
+            "codex".to_string(),
+            McpToolLimits {
+                max_concurrent: Some(0),
+                max_per_minute: None,
+            },
+        )]));
+        assert!(limiter.try_acquire("codex").is_err());
+        assert!(limiter.try_acquire("codex-reply").unwrap().is_none());
+    }
+}
@@ -1,10 +1,26 @@
+use clap::Parser;
 use codex_arg0::arg0_dispatch_or_else;
 use codex_common::CliConfigOverrides;
+use codex_mcp_server::Cli;
+use codex_mcp_server::McpCommand;
 use codex_mcp_server::run_main;
+use codex_mcp_server::run_selftest;
 
 fn main() -> anyhow::Result<()> {
     arg0_dispatch_or_else(|codex_linux_sandbox_exe| async move {
-        run_main(codex_linux_sandbox_exe, CliConfigOverrides::default()).await?;
+        let cli = Cli::parse();
+        if let Some(McpCommand::Selftest { json }) = cli.command {
+            run_selftest(CliConfigOverrides::default(), cli.mode(), json).await?;
+            return Ok(());
+        }
+        run_main(
+            codex_linux_sandbox_exe,
+            CliConfigOverrides::default(),
+            cli.transport(),
+            cli.mode(),
+            cli.allow_unauthenticated,
+        )
+        .await?;
         Ok(())
     })
 }
@@ -4,14 +4,23 @@ use std::path::PathBuf;
 use crate::codex_message_processor::CodexMessageProcessor;
 use crate::codex_tool_config::CodexToolCallParam;
 use crate::codex_tool_config::CodexToolCallReplyParam;
-use crate::codex_tool_config::create_tool_for_codex_tool_call_param;
-use crate::codex_tool_config::create_tool_for_codex_tool_call_reply_param;
+use crate::codex_tool_runner::INVALID_PARAMS_ERROR_CODE;
 use crate::error_code::INVALID_REQUEST_ERROR_CODE;
+use crate::error_code::RESOURCE_NOT_FOUND_ERROR_CODE;
+use crate::large_results::LargeResults;
+use crate::limits::ToolLimiter;
+use crate::limits::ToolPermit;
+use crate::mode;
 use crate::outgoing_message::OutgoingMessageSender;
+use crate::progress::ProgressReporter;
+use crate::progress::progress_token;
+use crate::prompts;
+use crate::resources;
 use codex_protocol::mcp_protocol::ClientRequest;
 
 use codex_core::ConversationManager;
 use codex_core::config::Config;
+use codex_core::config_types::McpServerMode;
 use codex_core::protocol::Submission;
 use codex_login::AuthManager;
 use mcp_types::CallToolRequestParams;
@@ -25,7 +34,10 @@ use mcp_types::JSONRPCRequest;
 use mcp_types::JSONRPCResponse;
 use mcp_types::ListToolsResult;
 use mcp_types::ModelContextProtocolRequest;
+use mcp_types::ProgressToken;
 use mcp_types::RequestId;
+use mcp_types::ServerCapabilitiesPrompts;
+use mcp_types::ServerCapabilitiesResources;
 use mcp_types::ServerCapabilitiesTools;
 use mcp_types::ServerNotification;
 use mcp_types::TextContent;
@@ -37,24 +49,39 @@ use uuid::Uuid;
 
 pub(crate) struct MessageProcessor {
     codex_message_processor: CodexMessageProcessor,
+    /// Where the artifacts exposed as resources and the user's prompts are
+    /// saved.
+    codex_home: PathBuf,
     outgoing: Arc<OutgoingMessageSender>,
     initialized: bool,
     codex_linux_sandbox_exe: Option<PathBuf>,
     conversation_manager: Arc<ConversationManager>,
-    running_requests_id_to_codex_uuid: Arc<Mutex<HashMap<RequestId, Uuid>>>,
+    /// Conversations of the running Codex tool calls, `None` while the call's
+    /// session is still starting.
+    running_requests_id_to_codex_uuid: Arc<Mutex<HashMap<RequestId, Option<Uuid>>>>,
+    tool_limiter: Arc<ToolLimiter>,
+    large_results: LargeResults,
+    mode: McpServerMode,
 }
 
 impl MessageProcessor {
     /// Create a new `MessageProcessor`, retaining a handle to the outgoing
     /// `Sender` so handlers can enqueue messages to be written to stdout.
+    /// `auth_manager` and `tool_limiter` are shared with the other clients
+    /// of the server, while the conversations started here belong to this
+    /// client alone.
     pub(crate) fn new(
         outgoing: OutgoingMessageSender,
         codex_linux_sandbox_exe: Option<PathBuf>,
         config: Arc<Config>,
+        auth_manager: Arc<AuthManager>,
+        tool_limiter: Arc<ToolLimiter>,
     ) -> Self {
         let outgoing = Arc::new(outgoing);
-        let auth_manager =
-            AuthManager::shared(config.codex_home.clone(), config.preferred_auth_method);
+        let codex_home = config.codex_home.clone();
+        let mode = config.mcp_server_mode;
+        let large_results =
+            LargeResults::new(codex_home.clone(), config.mcp_server_max_result_bytes);
         let conversation_manager = Arc::new(ConversationManager::new(auth_manager.clone()));
         let codex_message_processor = CodexMessageProcessor::new(
             auth_manager,
@@ -65,11 +92,15 @@ impl MessageProcessor {
         );
         Self {
             codex_message_processor,
+            codex_home,
             outgoing,
             initialized: false,
             codex_linux_sandbox_exe,
             conversation_manager,
             running_requests_id_to_codex_uuid: Arc::new(Mutex::new(HashMap::new())),
+            tool_limiter,
+            large_results,
+            mode,
         }
     }
 
@@ -87,6 +118,7 @@ impl MessageProcessor {
 
         // Hold on to the ID so we can respond.
         let request_id = request.id.clone();
+        let progress_token = progress_token(request.params.as_ref());
 
         let client_request = match McpClientRequest::try_from(request) {
             Ok(client_request) => client_request,
@@ -105,13 +137,14 @@ impl MessageProcessor {
                 self.handle_ping(request_id, params).await;
             }
             McpClientRequest::ListResourcesRequest(params) => {
-                self.handle_list_resources(params);
+                self.handle_list_resources(request_id, params).await;
             }
             McpClientRequest::ListResourceTemplatesRequest(params) => {
-                self.handle_list_resource_templates(params);
+                self.handle_list_resource_templates(request_id, params)
+                    .await;
             }
             McpClientRequest::ReadResourceRequest(params) => {
-                self.handle_read_resource(params);
+                self.handle_read_resource(request_id, params).await;
             }
             McpClientRequest::SubscribeRequest(params) => {
                 self.handle_subscribe(params);
@@ -120,16 +153,17 @@ impl MessageProcessor {
                 self.handle_unsubscribe(params);
             }
             McpClientRequest::ListPromptsRequest(params) => {
-                self.handle_list_prompts(params);
+                self.handle_list_prompts(request_id, params).await;
             }
             McpClientRequest::GetPromptRequest(params) => {
-                self.handle_get_prompt(params);
+                self.handle_get_prompt(request_id, params).await;
             }
             McpClientRequest::ListToolsRequest(params) => {
                 self.handle_list_tools(request_id, params).await;
             }
             McpClientRequest::CallToolRequest(params) => {
-                self.handle_call_tool(request_id, params).await;
+                self.handle_call_tool(request_id, params, progress_token)
+                    .await;
             }
             McpClientRequest::SetLevelRequest(params) => {
                 self.handle_set_level(params);
@@ -215,8 +249,11 @@ impl MessageProcessor {
                 completions: None,
                 experimental: None,
                 logging: None,
-                prompts: None,
-                resources: None,
+                prompts: Some(ServerCapabilitiesPrompts { list_changed: None }),
+                resources: Some(ServerCapabilitiesResources {
+                    list_changed: Some(true),
+                    subscribe: None,
+                }),
                 tools: Some(ServerCapabilitiesTools {
                     list_changed: Some(true),
                 }),
@@ -252,26 +289,53 @@ impl MessageProcessor {
             .await;
     }
 
-    fn handle_list_resources(
+    async fn handle_list_resources(
         &self,
+        id: RequestId,
         params: <mcp_types::ListResourcesRequest as mcp_types::ModelContextProtocolRequest>::Params,
     ) {
         tracing::info!("resources/list -> params: {:?}", params);
+        let conversation_ids = self.conversation_manager.conversation_ids().await;
+        let result = resources::list_artifacts(&self.codex_home, &conversation_ids).await;
+        self.send_response::<mcp_types::ListResourcesRequest>(id, result)
+            .await;
     }
 
-    fn handle_list_resource_templates(
+    async fn handle_list_resource_templates(
         &self,
+        id: RequestId,
         params:
             <mcp_types::ListResourceTemplatesRequest as mcp_types::ModelContextProtocolRequest>::Params,
     ) {
         tracing::info!("resources/templates/list -> params: {:?}", params);
+        self.send_response::<mcp_types::ListResourceTemplatesRequest>(
+            id,
+            resources::resource_templates(),
+        )
+        .await;
     }
 
-    fn handle_read_resource(
+    async fn handle_read_resource(
         &self,
+        id: RequestId,
         params: <mcp_types::ReadResourceRequest as mcp_types::ModelContextProtocolRequest>::Params,
     ) {
         tracing::info!("resources/read -> params: {:?}", params);
+        let conversation_ids = self.conversation_manager.conversation_ids().await;
+        match resources::read_artifact(&self.codex_home, &conversation_ids, &params.uri).await {
+            Ok(result) => {
+                self.send_response::<mcp_types::ReadResourceRequest>(id, result)
+                    .await;
+            }
+            Err(message) => {
+                let error = JSONRPCErrorError {
+                    code: RESOURCE_NOT_FOUND_ERROR_CODE,
+                    message,
+                    data: Some(json!({ "uri": params.uri })),
+                };
+                self.outgoing.send_error(id, error).await;
+            }
+        }
     }
 
     fn handle_subscribe(
@@ -288,18 +352,37 @@ impl MessageProcessor {
         tracing::info!("resources/unsubscribe -> params: {:?}", params);
     }
 
-    fn handle_list_prompts(
+    async fn handle_list_prompts(
         &self,
+        id: RequestId,
         params: <mcp_types::ListPromptsRequest as mcp_types::ModelContextProtocolRequest>::Params,
     ) {
         tracing::info!("prompts/list -> params: {:?}", params);
+        let result = prompts::list_prompts(&self.codex_home).await;
+        self.send_response::<mcp_types::ListPromptsRequest>(id, result)
+            .await;
     }
 
-    fn handle_get_prompt(
+    async fn handle_get_prompt(
         &self,
+        id: RequestId,
         params: <mcp_types::GetPromptRequest as mcp_types::ModelContextProtocolRequest>::Params,
     ) {
         tracing::info!("prompts/get -> params: {:?}", params);
+        match prompts::get_prompt(&self.codex_home, &params.name, params.arguments.as_ref()).await {
+            Ok(result) => {
+                self.send_response::<mcp_types::GetPromptRequest>(id, result)
+                    .await;
+            }
+            Err(message) => {
+                let error = JSONRPCErrorError {
+                    code: INVALID_PARAMS_ERROR_CODE,
+                    message,
+                    data: None,
+                };
+                self.outgoing.send_error(id, error).await;
+            }
+        }
     }
 
     async fn handle_list_tools(
@@ -309,10 +392,7 @@ impl MessageProcessor {
     ) {
         tracing::trace!("tools/list -> {params:?}");
         let result = ListToolsResult {
-            tools: vec![
-                create_tool_for_codex_tool_call_param(),
-                create_tool_for_codex_tool_call_reply_param(),
-            ],
+            tools: mode::tools(self.mode),
             next_cursor: None,
         };
 
@@ -324,14 +404,28 @@ impl MessageProcessor {
         &self,
         id: RequestId,
         params: <mcp_types::CallToolRequest as mcp_types::ModelContextProtocolRequest>::Params,
+        progress_token: Option<ProgressToken>,
     ) {
         tracing::info!("tools/call -> params: {:?}", params);
         let CallToolRequestParams { name, arguments } = params;
+        let permit = match self.tool_limiter.try_acquire(&name) {
+            Ok(permit) => permit,
+            Err(throttled) => {
+                tracing::warn!("refusing tools/call: {:?}", throttled);
+                self.outgoing.send_error(id, throttled.to_error()).await;
+                return;
+            }
+        };
+        let progress =
+            progress_token.map(|token| ProgressReporter::new(token, self.outgoing.clone()));
 
         match name.as_str() {
-            "codex" => self.handle_tool_call_codex(id, arguments).await,
+            "codex" => {
+                self.handle_tool_call_codex(id, arguments, progress, permit)
+                    .await
+            }
             "codex-reply" => {
-                self.handle_tool_call_codex_session_reply(id, arguments)
+                self.handle_tool_call_codex_session_reply(id, arguments, progress, permit)
                     .await
             }
             _ => {
@@ -349,28 +443,43 @@ impl MessageProcessor {
             }
         }
     }
-    async fn handle_tool_call_codex(&self, id: RequestId, arguments: Option<serde_json::Value>) {
+    async fn handle_tool_call_codex(
+        &self,
+        id: RequestId,
+        arguments: Option<serde_json::Value>,
+        progress: Option<ProgressReporter>,
+        permit: Option<ToolPermit>,
+    ) {
         let (initial_prompt, config): (String, Config) = match arguments {
             Some(json_val) => match serde_json::from_value::<CodexToolCallParam>(json_val) {
-                Ok(tool_cfg) => match tool_cfg.into_config(self.codex_linux_sandbox_exe.clone()) {
-                    Ok(cfg) => cfg,
-                    Err(e) => {
-                        let result = CallToolResult {
-                            content: vec![ContentBlock::TextContent(TextContent {
-                                r#type: "text".to_owned(),
-                                text: format!(
-                                    "Failed to load Codex configuration from overrides: {e}"
-                                ),
-                                annotations: None,
-                            })],
-                            is_error: Some(true),
-                            structured_content: None,
-                        };
-                        self.send_response::<mcp_types::CallToolRequest>(id, result)
-                            .await;
+                Ok(tool_cfg) => {
+                    if let Err(forbidden) = mode::check_tool_call(self.mode, &tool_cfg) {
+                        self.outgoing.send_error(id, forbidden.to_error()).await;
                         return;
                     }
-                },
+                    match tool_cfg.into_config(self.codex_linux_sandbox_exe.clone()) {
+                        Ok((prompt, mut cfg)) => {
+                            mode::restrict_config(self.mode, &mut cfg);
+                            (prompt, cfg)
+                        }
+                        Err(e) => {
+                            let result = CallToolResult {
+                                content: vec![ContentBlock::TextContent(TextContent {
+                                    r#type: "text".to_owned(),
+                                    text: format!(
+                                        "Failed to load Codex configuration from overrides: {e}"
+                                    ),
+                                    annotations: None,
+                                })],
+                                is_error: Some(true),
+                                structured_content: None,
+                            };
+                            self.send_response::<mcp_types::CallToolRequest>(id, result)
+                                .await;
+                            return;
+                        }
+                    }
+                }
                 Err(e) => {
                     let result = CallToolResult {
                         content: vec![ContentBlock::TextContent(TextContent {
@@ -408,10 +517,19 @@ impl MessageProcessor {
         let outgoing = self.outgoing.clone();
         let conversation_manager = self.conversation_manager.clone();
         let running_requests_id_to_codex_uuid = self.running_requests_id_to_codex_uuid.clone();
+        // Registered before the task starts so that a cancel arriving while
+        // the session starts is not lost.
+        running_requests_id_to_codex_uuid
+            .lock()
+            .await
+            .insert(id.clone(), None);
+        let large_results = self.large_results.clone();
 
         // Spawn an async task to handle the Codex session so that we do not
         // block the synchronous message-processing loop.
         task::spawn(async move {
+            // The call counts against the tool's limits until it finishes.
+            let _permit = permit;
             // Run the Codex session and stream events back to the client.
             crate::codex_tool_runner::run_codex_tool_session(
                 id,
@@ -420,6 +538,8 @@ impl MessageProcessor {
                 outgoing,
                 conversation_manager,
                 running_requests_id_to_codex_uuid,
+                progress,
+                large_results,
             )
             .await;
         });
@@ -429,6 +549,8 @@ impl MessageProcessor {
         &self,
         request_id: RequestId,
         arguments: Option<serde_json::Value>,
+        progress: Option<ProgressReporter>,
+        permit: Option<ToolPermit>,
     ) {
         tracing::info!("tools/call -> params: {:?}", arguments);
 
@@ -511,14 +633,23 @@ impl MessageProcessor {
             }
         };
 
+        // Registered before the task starts so that a cancel arriving before
+        // the prompt is submitted is not lost.
+        running_requests_id_to_codex_uuid
+            .lock()
+            .await
+            .insert(request_id.clone(), None);
+
         // Spawn the long-running reply handler.
         tokio::spawn({
             let codex = codex.clone();
             let outgoing = outgoing.clone();
             let prompt = prompt.clone();
             let running_requests_id_to_codex_uuid = running_requests_id_to_codex_uuid.clone();
+            let large_results = self.large_results.clone();
 
             async move {
+                let _permit = permit;
                 crate::codex_tool_runner::run_codex_tool_session_reply(
                     codex,
                     outgoing,
@@ -526,6 +657,8 @@ impl MessageProcessor {
                     prompt,
                     running_requests_id_to_codex_uuid,
                     session_id,
+                    progress,
+                    large_results,
                 )
                 .await;
             }
@@ -563,9 +696,16 @@ impl MessageProcessor {
 
         // Obtain the session_id while holding the first lock, then release.
         let session_id = {
-            let map_guard = self.running_requests_id_to_codex_uuid.lock().await;
+            let mut map_guard = self.running_requests_id_to_codex_uuid.lock().await;
             match map_guard.get(&request_id) {
-                Some(id) => *id, // Uuid is Copy
+                Some(Some(id)) => *id, // Uuid is Copy
+                Some(None) => {
+                    // The session is still starting; it sees that the request
+                    // is gone and interrupts the turn itself.
+                    tracing::info!("request {request_id_string} cancelled while starting");
+                    map_guard.remove(&request_id);
+                    return;
+                }
                 None => {
                     tracing::warn!("Session not found for request_id: {}", request_id_string);
                     return;
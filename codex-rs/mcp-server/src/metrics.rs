@@ -0,0 +1,275 @@
+//! Request metrics of the HTTP transport, served in the Prometheus text
+//! format at `/metrics`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use mcp_types::CallToolRequest;
+use mcp_types::JSONRPCMessage;
+use mcp_types::JSONRPCRequest;
+use mcp_types::ModelContextProtocolRequest;
+
+/// Upper bounds, in seconds, of the request duration histogram. Codex tool
+/// calls run for minutes, so the buckets reach far beyond the usual web
+/// request latencies.
+const DURATION_BUCKETS: [f64; 9] = [0.01, 0.1, 0.5, 1.0, 5.0, 30.0, 120.0, 600.0, 1800.0];
+
+/// What requests are counted by: the JSON-RPC method and, for `tools/call`,
+/// the tool.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct RequestLabels {
+    method: String,
+    tool: Option<String>,
+}
+
+impl RequestLabels {
+    pub(crate) fn of(request: &JSONRPCRequest) -> Self {
+        let tool = (request.method == CallToolRequest::METHOD)
+            .then(|| request.params.as_ref()?.get("name")?.as_str())
+            .flatten()
+            .map(str::to_string);
+        Self {
+            method: request.method.clone(),
+            tool,
+        }
+    }
+
+    fn render(&self, le: Option<&str>) -> String {
+        let mut labels = format!("method=\"{}\"", escape(&self.method));
+        if let Some(tool) = &self.tool {
+            let _ = write!(labels, ",tool=\"{}\"", escape(tool));
+        }
+        if let Some(le) = le {
+            let _ = write!(labels, ",le=\"{le}\"");
+        }
+        format!("{{{labels}}}")
+    }
+}
+
+/// A request waiting for its answer.
+pub(crate) struct InFlight {
+    labels: RequestLabels,
+    started: Instant,
+}
+
+impl InFlight {
+    pub(crate) fn new(request: &JSONRPCRequest) -> Self {
+        Self {
+            labels: RequestLabels::of(request),
+            started: Instant::now(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct RequestStats {
+    count: u64,
+    errors: u64,
+    /// Requests per bucket of [`DURATION_BUCKETS`], not cumulative.
+    buckets: [u64; DURATION_BUCKETS.len()],
+    duration_sum: f64,
+}
+
+#[derive(Default)]
+struct Inner {
+    requests: BTreeMap<RequestLabels, RequestStats>,
+    sessions_started: u64,
+}
+
+pub(crate) struct Metrics {
+    started: Instant,
+    inner: Mutex<Inner>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            inner: Mutex::default(),
+        }
+    }
+}
+
+impl Metrics {
+    pub(crate) fn session_started(&self) {
+        self.lock().sessions_started += 1;
+    }
+
+    /// Records that `answer` answered the request `in_flight`. JSON-RPC
+    /// errors and tool results flagged `isError` count as errors.
+    pub(crate) fn answered(&self, in_flight: InFlight, answer: &JSONRPCMessage) {
+        let error = match answer {
+            JSONRPCMessage::Error(_) => true,
+            JSONRPCMessage::Response(response) => response
+                .result
+                .get("isError")
+                .and_then(serde_json::Value::as_bool)
+                .unwrap_or(false),
+            JSONRPCMessage::Request(_) | JSONRPCMessage::Notification(_) => return,
+        };
+        self.observe(in_flight.labels, in_flight.started.elapsed(), error);
+    }
+
+    fn observe(&self, labels: RequestLabels, duration: Duration, error: bool) {
+        let seconds = duration.as_secs_f64();
+        let mut inner = self.lock();
+        let stats = inner.requests.entry(labels).or_default();
+        stats.count += 1;
+        if error {
+            stats.errors += 1;
+        }
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|le| seconds <= *le) {
+            stats.buckets[bucket] += 1;
+        }
+        stats.duration_sum += seconds;
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub(crate) fn render(&self, active_sessions: usize) -> String {
+        let inner = self.lock();
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP mcp_uptime_seconds Seconds since the server started.\n\
+             # TYPE mcp_uptime_seconds gauge\n\
+             mcp_uptime_seconds {}",
+            self.started.elapsed().as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "# HELP mcp_sessions_active MCP sessions currently open.\n\
+             # TYPE mcp_sessions_active gauge\n\
+             mcp_sessions_active {active_sessions}\n\
+             # HELP mcp_sessions_total MCP sessions started.\n\
+             # TYPE mcp_sessions_total counter\n\
+             mcp_sessions_total {}",
+            inner.sessions_started
+        );
+
+        out.push_str(
+            "# HELP mcp_requests_total MCP requests answered.\n\
+             # TYPE mcp_requests_total counter\n",
+        );
+        for (labels, stats) in &inner.requests {
+            let _ = writeln!(
+                out,
+                "mcp_requests_total{} {}",
+                labels.render(None),
+                stats.count
+            );
+        }
+        out.push_str(
+            "# HELP mcp_request_errors_total MCP requests answered with an error.\n\
+             # TYPE mcp_request_errors_total counter\n",
+        );
+        for (labels, stats) in &inner.requests {
+            let _ = writeln!(
+                out,
+                "mcp_request_errors_total{} {}",
+                labels.render(None),
+                stats.errors
+            );
+        }
+        out.push_str(
+            "# HELP mcp_request_duration_seconds Time from receiving an MCP request to answering it.\n\
+             # TYPE mcp_request_duration_seconds histogram\n",
+        );
+        for (labels, stats) in &inner.requests {
+            let mut cumulative = 0;
+            for (le, count) in DURATION_BUCKETS.iter().zip(stats.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "mcp_request_duration_seconds_bucket{} {cumulative}",
+                    labels.render(Some(&le.to_string()))
+                );
+            }
+            let _ = writeln!(
+                out,
+                "mcp_request_duration_seconds_bucket{} {}\n\
+                 mcp_request_duration_seconds_sum{} {}\n\
+                 mcp_request_duration_seconds_count{} {}",
+                labels.render(Some("+Inf")),
+                stats.count,
+                labels.render(None),
+                stats.duration_sum,
+                labels.render(None),
+                stats.count
+            );
+        }
+        out
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_types::JSONRPC_VERSION;
+    use mcp_types::RequestId;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn request(method: &str, params: serde_json::Value) -> JSONRPCRequest {
+        JSONRPCRequest {
+            jsonrpc: JSONRPC_VERSION.into(),
+            id: RequestId::Integer(1),
+            method: method.into(),
+            params: Some(params),
+        }
+    }
+
+    #[test]
+    fn counts_requests_by_method_and_tool() {
+        let metrics = Metrics::default();
+        let call = request("tools/call", json!({ "name": "codex", "arguments": {} }));
+        let list = request("tools/list", json!({}));
+        assert_eq!(
+            RequestLabels::of(&call),
+            RequestLabels {
+                method: "tools/call".into(),
+                tool: Some("codex".into()),
+            }
+        );
+
+        metrics.observe(RequestLabels::of(&call), Duration::from_secs(42), false);
+        metrics.observe(RequestLabels::of(&call), Duration::from_millis(50), true);
+        metrics.observe(RequestLabels::of(&list), Duration::from_millis(1), false);
+        metrics.session_started();
+
+        let rendered = metrics.render(1);
+        for line in [
+            "mcp_sessions_active 1",
+            "mcp_sessions_total 1",
+            "mcp_requests_total{method=\"tools/call\",tool=\"codex\"} 2",
+            "mcp_requests_total{method=\"tools/list\"} 1",
+            "mcp_request_errors_total{method=\"tools/call\",tool=\"codex\"} 1",
+            "mcp_request_duration_seconds_bucket{method=\"tools/call\",tool=\"codex\",le=\"0.01\"} 0",
+            "mcp_request_duration_seconds_bucket{method=\"tools/call\",tool=\"codex\",le=\"0.1\"} 1",
+            "mcp_request_duration_seconds_bucket{method=\"tools/call\",tool=\"codex\",le=\"30\"} 1",
+            "mcp_request_duration_seconds_bucket{method=\"tools/call\",tool=\"codex\",le=\"120\"} 2",
+            "mcp_request_duration_seconds_bucket{method=\"tools/call\",tool=\"codex\",le=\"+Inf\"} 2",
+            "mcp_request_duration_seconds_count{method=\"tools/call\",tool=\"codex\"} 2",
+        ] {
+            assert!(
+                rendered.lines().any(|l| l == line),
+                "missing {line}:\n{rendered}"
+            );
+        }
+    }
+}
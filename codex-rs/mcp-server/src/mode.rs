@@ -0,0 +1,269 @@
+//! What the clients of the server may do, set with `--mode` or
+//! `mcp_server_mode`. `admin` allows everything. `standard` keeps clients
+//! from turning the sandbox off, from overriding the config, whose settings
+//! include programs the server runs outside the sandbox, and from changing
+//! the login shared by every client. `read-only` also runs every
+//! conversation in the read-only sandbox without ever asking for approval,
+//! so nothing a client starts can write.
+
+use codex_core::config::Config;
+use codex_core::config_types::McpServerMode;
+use codex_core::protocol::AskForApproval;
+use codex_core::protocol::SandboxPolicy;
+use codex_protocol::config_types::SandboxMode;
+use mcp_types::JSONRPCErrorError;
+use mcp_types::Tool;
+use mcp_types::ToolAnnotations;
+use serde_json::Value;
+use serde_json::json;
+
+use crate::codex_tool_config::CodexToolCallParam;
+use crate::codex_tool_config::create_tool_for_codex_tool_call_param;
+use crate::codex_tool_config::create_tool_for_codex_tool_call_reply_param;
+use crate::error_code::FORBIDDEN_ERROR_CODE;
+
+/// A request the mode does not allow.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Forbidden {
+    mode: McpServerMode,
+    what: String,
+}
+
+impl Forbidden {
+    fn new(mode: McpServerMode, what: impl Into<String>) -> Self {
+        Self {
+            mode,
+            what: what.into(),
+        }
+    }
+
+    pub(crate) fn to_error(&self) -> JSONRPCErrorError {
+        JSONRPCErrorError {
+            code: FORBIDDEN_ERROR_CODE,
+            message: format!(
+                "{} is not allowed: the server runs in {} mode",
+                self.what, self.mode
+            ),
+            data: Some(json!({ "mode": self.mode.to_string() })),
+        }
+    }
+}
+
+/// Checks the sandbox and config overrides a client starts a conversation
+/// with.
+pub(crate) fn check_new_conversation(
+    mode: McpServerMode,
+    sandbox: Option<SandboxMode>,
+    config_overrides: bool,
+) -> Result<(), Forbidden> {
+    if mode == McpServerMode::Admin {
+        return Ok(());
+    }
+    if config_overrides {
+        return Err(Forbidden::new(mode, "overriding the config"));
+    }
+    match (mode, sandbox) {
+        (_, Some(SandboxMode::DangerFullAccess)) => {
+            Err(Forbidden::new(mode, "the danger-full-access sandbox"))
+        }
+        (McpServerMode::ReadOnly, Some(SandboxMode::WorkspaceWrite)) => {
+            Err(Forbidden::new(mode, "the workspace-write sandbox"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks the arguments of a `codex` tool call, which starts a
+/// conversation.
+pub(crate) fn check_tool_call(
+    mode: McpServerMode,
+    param: &CodexToolCallParam,
+) -> Result<(), Forbidden> {
+    check_new_conversation(
+        mode,
+        param.sandbox.clone().map(Into::into),
+        param
+            .config
+            .as_ref()
+            .is_some_and(|config| !config.is_empty()),
+    )
+}
+
+/// Clamps the config of a new conversation, which may come from the server's
+/// config or a profile in it, to what the mode allows.
+pub(crate) fn restrict_config(mode: McpServerMode, config: &mut Config) {
+    if mode == McpServerMode::ReadOnly {
+        config.sandbox_policy = SandboxPolicy::new_read_only_policy();
+        config.approval_policy = AskForApproval::Never;
+    }
+}
+
+/// Checks the sandbox and approval policy of a turn, which replace the ones
+/// the conversation was started with.
+pub(crate) fn check_turn(
+    mode: McpServerMode,
+    sandbox_policy: &SandboxPolicy,
+    approval_policy: &mut AskForApproval,
+) -> Result<(), Forbidden> {
+    match (mode, sandbox_policy) {
+        (McpServerMode::Admin, _) => Ok(()),
+        (McpServerMode::Standard, SandboxPolicy::DangerFullAccess) => {
+            Err(Forbidden::new(mode, "the danger-full-access sandbox"))
+        }
+        (McpServerMode::Standard, _) => Ok(()),
+        (McpServerMode::ReadOnly, SandboxPolicy::ReadOnly) => {
+            *approval_policy = AskForApproval::Never;
+            Ok(())
+        }
+        (McpServerMode::ReadOnly, _) => Err(Forbidden::new(
+            mode,
+            format!("the {sandbox_policy} sandbox"),
+        )),
+    }
+}
+
+/// Checks a request that logs in or out.
+pub(crate) fn check_login(mode: McpServerMode) -> Result<(), Forbidden> {
+    match mode {
+        McpServerMode::Admin => Ok(()),
+        McpServerMode::Standard | McpServerMode::ReadOnly => {
+            Err(Forbidden::new(mode, "changing the login"))
+        }
+    }
+}
+
+/// The tools listed to clients in `mode`.
+pub(crate) fn tools(mode: McpServerMode) -> Vec<Tool> {
+    vec![
+        restrict_tool(mode, create_tool_for_codex_tool_call_param()),
+        restrict_tool(mode, create_tool_for_codex_tool_call_reply_param()),
+    ]
+}
+
+/// `tool` as listed to clients: the arguments the mode refuses are left out
+/// of its schema, and in `read-only` mode it is annotated as read-only.
+pub(crate) fn restrict_tool(mode: McpServerMode, mut tool: Tool) -> Tool {
+    let properties = match &mut tool.input_schema.properties {
+        Some(Value::Object(properties)) => Some(properties),
+        _ => None,
+    };
+    match mode {
+        McpServerMode::Admin => {}
+        McpServerMode::Standard => {
+            if let Some(properties) = properties {
+                properties.remove("config");
+                if let Some(Value::Array(sandboxes)) = properties
+                    .get_mut("sandbox")
+                    .and_then(|sandbox| sandbox.get_mut("enum"))
+                {
+                    sandboxes.retain(|sandbox| sandbox != "danger-full-access");
+                }
+            }
+        }
+        McpServerMode::ReadOnly => {
+            if let Some(properties) = properties {
+                for name in ["config", "sandbox", "approval-policy"] {
+                    properties.remove(name);
+                }
+            }
+            tool.annotations = Some(ToolAnnotations {
+                destructive_hint: Some(false),
+                idempotent_hint: None,
+                open_world_hint: None,
+                read_only_hint: Some(true),
+                title: None,
+            });
+        }
+    }
+    tool
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn refuses_what_the_mode_does_not_allow() {
+        use McpServerMode::*;
+        let danger = Some(SandboxMode::DangerFullAccess);
+        let write = Some(SandboxMode::WorkspaceWrite);
+        assert_eq!(check_new_conversation(Admin, danger, true), Ok(()));
+        assert_eq!(check_new_conversation(Standard, write, false), Ok(()));
+        assert!(check_new_conversation(Standard, None, true).is_err());
+        assert!(check_new_conversation(Standard, danger, false).is_err());
+        assert!(check_new_conversation(ReadOnly, write, false).is_err());
+        assert_eq!(check_new_conversation(ReadOnly, None, false), Ok(()));
+        assert_eq!(check_login(Admin), Ok(()));
+        assert!(check_login(Standard).is_err());
+
+        let error = check_new_conversation(ReadOnly, danger, false)
+            .err()
+            .unwrap()
+            .to_error();
+        assert_eq!(error.code, FORBIDDEN_ERROR_CODE);
+        assert_eq!(
+            error.message,
+            "the danger-full-access sandbox is not allowed: the server runs in read-only mode"
+        );
+        assert_eq!(error.data, Some(json!({ "mode": "read-only" })));
+    }
+
+    #[test]
+    fn read_only_turns_never_ask_for_approval() {
+        let mut approval = AskForApproval::OnRequest;
+        assert!(
+            check_turn(
+                McpServerMode::ReadOnly,
+                &SandboxPolicy::new_workspace_write_policy(),
+                &mut approval,
+            )
+            .is_err()
+        );
+        assert_eq!(
+            check_turn(
+                McpServerMode::ReadOnly,
+                &SandboxPolicy::new_read_only_policy(),
+                &mut approval,
+            ),
+            Ok(())
+        );
+        assert_eq!(approval, AskForApproval::Never);
+
+        let mut approval = AskForApproval::OnRequest;
+        assert!(
+            check_turn(
+                McpServerMode::Standard,
+                &SandboxPolicy::DangerFullAccess,
+                &mut approval,
+            )
+            .is_err()
+        );
+        assert_eq!(approval, AskForApproval::OnRequest);
+    }
+
+    #[test]
+    fn hides_refused_arguments_from_the_tool_schema() {
+        let properties = |mode| {
+            let tool = restrict_tool(mode, create_tool_for_codex_tool_call_param());
+            tool.input_schema.properties.unwrap()
+        };
+
+        let admin = properties(McpServerMode::Admin);
+        assert!(admin.get("config").is_some());
+
+        let standard = properties(McpServerMode::Standard);
+        assert!(standard.get("config").is_none());
+        assert_eq!(
+            standard["sandbox"]["enum"],
+            json!(["read-only", "workspace-write"])
+        );
+
+        let read_only = properties(McpServerMode::ReadOnly);
+        assert!(read_only.get("sandbox").is_none());
+        assert!(read_only.get("approval-policy").is_none());
+        assert!(read_only.get("prompt").is_some());
+    }
+}
@@ -0,0 +1,154 @@
+//! `notifications/progress` for tool calls whose request carries a
+//! `progressToken` in its `_meta`, so clients can show what a Codex task is
+//! doing instead of waiting silently for the result.
+
+use std::sync::Arc;
+
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::ExecCommandBeginEvent;
+use codex_core::protocol::McpToolCallBeginEvent;
+use codex_core::protocol::PatchApplyBeginEvent;
+use mcp_types::ModelContextProtocolNotification;
+use mcp_types::ProgressNotification;
+use mcp_types::ProgressNotificationParams;
+use mcp_types::ProgressToken;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::outgoing_message::OutgoingMessageSender;
+use crate::outgoing_message::OutgoingNotification;
+
+/// Reports the steps of one tool call. How many steps a task takes is not
+/// known in advance, so progress counts up without a total.
+pub(crate) struct ProgressReporter {
+    token: ProgressToken,
+    outgoing: Arc<OutgoingMessageSender>,
+    progress: u64,
+}
+
+impl ProgressReporter {
+    pub(crate) fn new(token: ProgressToken, outgoing: Arc<OutgoingMessageSender>) -> Self {
+        Self {
+            token,
+            outgoing,
+            progress: 0,
+        }
+    }
+
+    /// Reports `msg` if it marks a new step of the task.
+    pub(crate) async fn on_event(&mut self, msg: &EventMsg) {
+        if let Some(message) = describe(msg) {
+            self.report(message).await;
+        }
+    }
+
+    async fn report(&mut self, message: String) {
+        self.progress += 1;
+        let params = ProgressNotificationParams {
+            message: Some(message),
+            progress: self.progress as f64,
+            progress_token: self.token.clone(),
+            total: None,
+        };
+        match serde_json::to_value(params) {
+            Ok(params) => {
+                self.outgoing
+                    .send_notification(OutgoingNotification {
+                        method: ProgressNotification::METHOD.to_string(),
+                        params: Some(params),
+                    })
+                    .await;
+            }
+            Err(e) => warn!("failed to serialize progress notification: {e}"),
+        }
+    }
+}
+
+/// The `progressToken` in the `_meta` of a request's params, if the client
+/// sent one.
+pub(crate) fn progress_token(params: Option<&Value>) -> Option<ProgressToken> {
+    let token = params?.get("_meta")?.get("progressToken")?;
+    serde_json::from_value(token.clone()).ok()
+}
+
+/// A one-line description of the step `msg` starts, for events that start
+/// one.
+fn describe(msg: &EventMsg) -> Option<String> {
+    let message = match msg {
+        EventMsg::TaskStarted(_) => "Working".to_string(),
+        EventMsg::ExecCommandBegin(ExecCommandBeginEvent { command, .. }) => {
+            format!("Running `{}`", join_command(command))
+        }
+        EventMsg::ExecApprovalRequest(_) | EventMsg::ApplyPatchApprovalRequest(_) => {
+            "Waiting for approval".to_string()
+        }
+        EventMsg::PatchApplyBegin(PatchApplyBeginEvent { changes, .. }) => match changes.len() {
+            1 => "Editing 1 file".to_string(),
+            n => format!("Editing {n} files"),
+        },
+        EventMsg::McpToolCallBegin(McpToolCallBeginEvent { invocation, .. }) => {
+            format!("Calling {}.{}", invocation.server, invocation.tool)
+        }
+        EventMsg::WebSearchBegin(_) => "Searching the web".to_string(),
+        EventMsg::BackgroundJobStarted(event) => {
+            format!("Started background job `{}`", join_command(&event.command))
+        }
+        EventMsg::StreamError(event) => format!("Retrying: {}", event.message),
+        EventMsg::BackgroundEvent(event) => event.message.clone(),
+        _ => return None,
+    };
+    Some(message)
+}
+
+fn join_command(command: &[String]) -> String {
+    shlex::try_join(command.iter().map(String::as_str)).unwrap_or_else(|_| command.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codex_core::protocol::McpInvocation;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn reads_the_progress_token_from_meta() {
+        let params = json!({ "name": "codex", "_meta": { "progressToken": "call-1" } });
+        assert_eq!(
+            progress_token(Some(&params)),
+            Some(ProgressToken::String("call-1".to_string()))
+        );
+        let params = json!({ "_meta": { "progressToken": 7 } });
+        assert_eq!(
+            progress_token(Some(&params)),
+            Some(ProgressToken::Integer(7))
+        );
+        assert_eq!(progress_token(Some(&json!({ "name": "codex" }))), None);
+        assert_eq!(progress_token(None), None);
+    }
+
+    #[test]
+    fn describes_the_steps_of_a_task() {
+        let exec = EventMsg::ExecCommandBegin(ExecCommandBeginEvent {
+            call_id: "call".to_string(),
+            command: vec!["cargo".to_string(), "test".to_string(), "a b".to_string()],
+            cwd: "/repo".into(),
+            parsed_cmd: Vec::new(),
+        });
+        assert_eq!(
+            describe(&exec).as_deref(),
+            Some("Running `cargo test 'a b'`")
+        );
+
+        let mcp = EventMsg::McpToolCallBegin(McpToolCallBeginEvent {
+            call_id: "call".to_string(),
+            invocation: McpInvocation {
+                server: "docs".to_string(),
+                tool: "search".to_string(),
+                arguments: None,
+            },
+        });
+        assert_eq!(describe(&mcp).as_deref(), Some("Calling docs.search"));
+        assert_eq!(describe(&EventMsg::ShutdownComplete), None);
+    }
+}
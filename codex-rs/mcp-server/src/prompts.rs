@@ -0,0 +1,209 @@
+//! The MCP `prompts` capability: the curated templates in
+//! [`CODEX_PROMPT_TEMPLATES`] plus the user's own prompts in
+//! `$CODEX_HOME/prompts`, the same ones the TUI offers as slash commands.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use codex_core::custom_prompts::discover_prompts_in_excluding;
+use mcp_types::ContentBlock;
+use mcp_types::GetPromptResult;
+use mcp_types::ListPromptsResult;
+use mcp_types::Prompt;
+use mcp_types::PromptArgument;
+use mcp_types::PromptMessage;
+use mcp_types::Role;
+use mcp_types::TextContent;
+use serde_json::Value;
+
+use crate::codex_tool_config::CODEX_PROMPT_TEMPLATES;
+use crate::codex_tool_config::CodexPromptTemplate;
+
+/// Lists the curated templates followed by the user's prompts. A user prompt
+/// with the name of a template is hidden by it.
+pub(crate) async fn list_prompts(codex_home: &Path) -> ListPromptsResult {
+    let mut prompts: Vec<Prompt> = CODEX_PROMPT_TEMPLATES.iter().map(describe).collect();
+    for custom in
+        discover_prompts_in_excluding(&codex_home.join("prompts"), &template_names()).await
+    {
+        prompts.push(Prompt {
+            arguments: None,
+            description: Some(format!("Custom prompt from {}", custom.path.display())),
+            name: custom.name,
+            title: None,
+        });
+    }
+    ListPromptsResult {
+        next_cursor: None,
+        prompts,
+    }
+}
+
+/// Renders the prompt `name` with `arguments`, a JSON object of strings.
+pub(crate) async fn get_prompt(
+    codex_home: &Path,
+    name: &str,
+    arguments: Option<&Value>,
+) -> Result<GetPromptResult, String> {
+    if let Some(template) = CODEX_PROMPT_TEMPLATES.iter().find(|t| t.name == name) {
+        let text = render(template, arguments)?;
+        return Ok(user_prompt(describe(template).description, text));
+    }
+    discover_prompts_in_excluding(&codex_home.join("prompts"), &template_names())
+        .await
+        .into_iter()
+        .find(|custom| custom.name == name)
+        .map(|custom| user_prompt(None, custom.content))
+        .ok_or_else(|| format!("unknown prompt: {name}"))
+}
+
+fn template_names() -> HashSet<String> {
+    CODEX_PROMPT_TEMPLATES
+        .iter()
+        .map(|t| t.name.to_string())
+        .collect()
+}
+
+fn describe(template: &CodexPromptTemplate) -> Prompt {
+    Prompt {
+        arguments: Some(
+            template
+                .arguments
+                .iter()
+                .map(|argument| PromptArgument {
+                    description: Some(match argument.default {
+                        Some(default) => {
+                            format!("{} Defaults to \"{default}\".", argument.description)
+                        }
+                        None => argument.description.to_string(),
+                    }),
+                    name: argument.name.to_string(),
+                    required: Some(argument.default.is_none()),
+                    title: None,
+                })
+                .collect(),
+        ),
+        description: Some(format!("{} (v{})", template.description, template.version)),
+        name: template.name.to_string(),
+        title: Some(template.title.to_string()),
+    }
+}
+
+fn render(template: &CodexPromptTemplate, arguments: Option<&Value>) -> Result<String, String> {
+    let mut text = template.template.to_string();
+    for argument in template.arguments {
+        let value = match arguments.and_then(|a| a.get(argument.name)) {
+            Some(Value::String(value)) => value.as_str(),
+            Some(_) => {
+                return Err(format!(
+                    "argument `{}` of prompt `{}` must be a string",
+                    argument.name, template.name
+                ));
+            }
+            None => argument.default.ok_or_else(|| {
+                format!(
+                    "missing required argument `{}` of prompt `{}`",
+                    argument.name, template.name
+                )
+            })?,
+        };
+        text = text.replace(&format!("{{{}}}", argument.name), value);
+    }
+    Ok(text)
+}
+
+fn user_prompt(description: Option<String>, text: String) -> GetPromptResult {
+    GetPromptResult {
+        description,
+        messages: vec![PromptMessage {
+            content: ContentBlock::TextContent(TextContent {
+                annotations: None,
+                text,
+                r#type: "text".to_string(),
+            }),
+            role: Role::User,
+        }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn text(result: &GetPromptResult) -> &str {
+        match &result.messages[0].content {
+            ContentBlock::TextContent(content) => &content.text,
+            other => panic!("unexpected content: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn templates_only_use_their_arguments() {
+        for template in CODEX_PROMPT_TEMPLATES {
+            let arguments: serde_json::Map<String, Value> = template
+                .arguments
+                .iter()
+                .map(|a| (a.name.to_string(), json!("x")))
+                .collect();
+            let text = render(template, Some(&Value::Object(arguments))).unwrap();
+            assert!(
+                !text.contains('{'),
+                "{} leaves a placeholder: {text}",
+                template.name
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn renders_templates_and_custom_prompts() {
+        let codex_home = TempDir::new().unwrap();
+        let prompts_dir = codex_home.path().join("prompts");
+        std::fs::create_dir(&prompts_dir).unwrap();
+        std::fs::write(prompts_dir.join("release.md"), "Cut a release.").unwrap();
+        std::fs::write(prompts_dir.join("review-changes.md"), "hidden").unwrap();
+
+        let listed = list_prompts(codex_home.path()).await;
+        let names: Vec<_> = listed.prompts.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "review-changes",
+                "fix-failing-tests",
+                "triage-error",
+                "explain-code",
+                "release"
+            ]
+        );
+
+        let review = get_prompt(
+            codex_home.path(),
+            "review-changes",
+            Some(&json!({ "focus": "error handling" })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            text(&review),
+            "Review the changes in `git diff origin/main...HEAD`. Focus on error handling. \
+List each finding with its file and line, most severe first, and say how to fix it. \
+Do not modify any files."
+        );
+
+        let missing = get_prompt(codex_home.path(), "fix-failing-tests", None).await;
+        assert_eq!(
+            missing.unwrap_err(),
+            "missing required argument `command` of prompt `fix-failing-tests`"
+        );
+
+        let release = get_prompt(codex_home.path(), "release", None)
+            .await
+            .unwrap();
+        assert_eq!(text(&release), "Cut a release.");
+        assert!(get_prompt(codex_home.path(), "nope", None).await.is_err());
+    }
+}
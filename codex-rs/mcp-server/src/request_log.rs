@@ -0,0 +1,387 @@
+//! Structured log of the MCP requests the server answers. Every request is
+//! given a correlation id, which tags the tracing output while the request
+//! is handled, is echoed in the `data` of an error answering it, and keys a
+//! JSON line in `mcp-requests.jsonl` in the log directory with the method,
+//! tool, redacted arguments, duration and outcome. The file is rotated once
+//! it grows past [`MAX_LOG_BYTES`].
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use chrono::Utc;
+use codex_core::Redactor;
+use mcp_types::CallToolRequest;
+use mcp_types::JSONRPCRequest;
+use mcp_types::ModelContextProtocolRequest;
+use mcp_types::RequestId;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::outgoing_message::OutgoingMessage;
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+const LOG_FILENAME: &str = "mcp-requests.jsonl";
+
+/// Size past which the log is rotated.
+const MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Rotated logs kept, as `mcp-requests.jsonl.1` (newest) and up.
+const ROTATED_LOGS: usize = 3;
+
+/// The request log of the server, shared by all of its clients.
+pub(crate) struct RequestLog {
+    file: Option<Mutex<RotatingFile>>,
+    redactor: Redactor,
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    max_len: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct LogLine<'a> {
+    timestamp: String,
+    correlation_id: &'a str,
+    method: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arguments: Option<&'a Value>,
+    duration_ms: u128,
+    outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Ok,
+    /// A tool result flagged `isError`.
+    ToolError,
+    /// A JSON-RPC error.
+    Error,
+}
+
+impl RequestLog {
+    /// Logs to `log_dir`. When the file cannot be opened, requests still get
+    /// correlation ids but are not logged.
+    pub(crate) fn new(log_dir: &Path, redactor: Redactor) -> Self {
+        let file = match RotatingFile::open(log_dir.join(LOG_FILENAME)) {
+            Ok(file) => Some(Mutex::new(file)),
+            Err(e) => {
+                warn!("not logging MCP requests to {}: {e}", log_dir.display());
+                None
+            }
+        };
+        Self { file, redactor }
+    }
+
+    /// A log that writes nothing.
+    #[cfg(test)]
+    pub(crate) fn disabled() -> Self {
+        Self {
+            file: None,
+            redactor: Redactor::default(),
+        }
+    }
+
+    fn write(&self, line: &LogLine<'_>) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let mut json = match serde_json::to_string(line) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("failed to serialize request log line: {e}");
+                return;
+            }
+        };
+        json.push('\n');
+        let mut file = file
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Err(e) = file.append(json.as_bytes()) {
+            warn!("failed to write request log: {e}");
+        }
+    }
+
+    /// Copy of `value` with the secrets in its strings masked.
+    fn redact(&self, value: &Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.redactor.redact(text).into_owned()),
+            Value::Array(items) => Value::Array(items.iter().map(|v| self.redact(v)).collect()),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(key, v)| (key.clone(), self.redact(v)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf) -> std::io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let file = options.open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            len,
+            max_len: MAX_LOG_BYTES,
+        })
+    }
+
+    fn append(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        if self.len > 0 && self.len + bytes.len() as u64 > self.max_len {
+            self.rotate()?;
+        }
+        self.file.write_all(bytes)?;
+        self.len += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Shifts `file.N` to `file.N+1`, dropping the oldest, and starts a new
+    /// file.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{n}", self.path.display()));
+        for n in (1..ROTATED_LOGS).rev() {
+            let from = rotated(n);
+            if from.exists() {
+                std::fs::rename(from, rotated(n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated(1))?;
+        let max_len = self.max_len;
+        *self = Self::open(self.path.clone())?;
+        self.max_len = max_len;
+        Ok(())
+    }
+}
+
+struct PendingRequest {
+    correlation_id: String,
+    method: String,
+    tool: Option<String>,
+    arguments: Option<Value>,
+    started: Instant,
+}
+
+/// The requests of one client that have not been answered yet.
+pub(crate) struct RequestTracker {
+    log: Arc<RequestLog>,
+    pending: Mutex<HashMap<RequestId, PendingRequest>>,
+}
+
+impl RequestTracker {
+    pub(crate) fn new(log: Arc<RequestLog>) -> Self {
+        Self {
+            log,
+            pending: Mutex::default(),
+        }
+    }
+
+    /// Assigns `request` its correlation id.
+    pub(crate) fn start(&self, request: &JSONRPCRequest) -> String {
+        let correlation_id = Uuid::new_v4().simple().to_string();
+        let (tool, arguments) = if request.method == CallToolRequest::METHOD {
+            let params = request.params.as_ref();
+            (
+                params
+                    .and_then(|p| p.get("name"))
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                params.and_then(|p| p.get("arguments")),
+            )
+        } else {
+            (None, request.params.as_ref())
+        };
+        let pending = PendingRequest {
+            correlation_id: correlation_id.clone(),
+            method: request.method.clone(),
+            tool,
+            arguments: arguments.map(|a| self.log.redact(a)),
+            started: Instant::now(),
+        };
+        self.lock().insert(request.id.clone(), pending);
+        correlation_id
+    }
+
+    /// Logs the request `message` answers, if it answers one, and adds the
+    /// correlation id to the `data` of an error.
+    pub(crate) fn finish(&self, message: &mut OutgoingMessage) {
+        let id = match message {
+            OutgoingMessage::Response(response) => &response.id,
+            OutgoingMessage::Error(error) => &error.id,
+            OutgoingMessage::Request(_) | OutgoingMessage::Notification(_) => return,
+        };
+        let Some(pending) = self.lock().remove(id) else {
+            return;
+        };
+
+        let (outcome, error) = match message {
+            OutgoingMessage::Error(error) => {
+                let correlation_id = Value::String(pending.correlation_id.clone());
+                match &mut error.error.data {
+                    Some(Value::Object(data)) => {
+                        data.insert("correlationId".to_string(), correlation_id);
+                    }
+                    data @ None => {
+                        *data = Some(serde_json::json!({ "correlationId": correlation_id }));
+                    }
+                    // Leave data of other shapes as the client expects them.
+                    Some(_) => {}
+                }
+                (Outcome::Error, Some(error.error.message.clone()))
+            }
+            OutgoingMessage::Response(response)
+                if response.result.get("isError").and_then(Value::as_bool) == Some(true) =>
+            {
+                (Outcome::ToolError, None)
+            }
+            _ => (Outcome::Ok, None),
+        };
+
+        self.log.write(&LogLine {
+            timestamp: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            correlation_id: &pending.correlation_id,
+            method: &pending.method,
+            tool: pending.tool.as_deref(),
+            arguments: pending.arguments.as_ref(),
+            duration_ms: pending.started.elapsed().as_millis(),
+            outcome,
+            error: error.as_deref(),
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<RequestId, PendingRequest>> {
+        self.pending
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::outgoing_message::OutgoingError;
+    use crate::outgoing_message::OutgoingResponse;
+    use codex_core::config_types::RedactionConfig;
+    use mcp_types::JSONRPC_VERSION;
+    use mcp_types::JSONRPCErrorError;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn request(id: i64, method: &str, params: Value) -> JSONRPCRequest {
+        JSONRPCRequest {
+            jsonrpc: JSONRPC_VERSION.into(),
+            id: RequestId::Integer(id),
+            method: method.into(),
+            params: Some(params),
+        }
+    }
+
+    fn log_lines(log_dir: &Path) -> Vec<Value> {
+        std::fs::read_to_string(log_dir.join(LOG_FILENAME))
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn logs_answered_requests_with_redacted_arguments() {
+        let log_dir = TempDir::new().unwrap();
+        let redactor = Redactor::new(&RedactionConfig {
+            enabled: true,
+            ..RedactionConfig::default()
+        })
+        .unwrap();
+        let tracker = RequestTracker::new(Arc::new(RequestLog::new(log_dir.path(), redactor)));
+
+        let call = request(
+            1,
+            "tools/call",
+            json!({
+                "name": "codex",
+                "arguments": { "prompt": "use sk-abcdefghijklmnopqrstuvwxyz012345" },
+            }),
+        );
+        let call_id = tracker.start(&call);
+        let list_id = tracker.start(&request(2, "tools/list", json!({})));
+
+        let mut answer = OutgoingMessage::Response(OutgoingResponse {
+            id: RequestId::Integer(1),
+            result: json!({ "content": [], "isError": true }),
+        });
+        tracker.finish(&mut answer);
+        let mut error = OutgoingMessage::Error(OutgoingError {
+            id: RequestId::Integer(2),
+            error: JSONRPCErrorError {
+                code: -32603,
+                message: "boom".into(),
+                data: None,
+            },
+        });
+        tracker.finish(&mut error);
+
+        let OutgoingMessage::Error(error) = error else {
+            panic!("expected an error");
+        };
+        assert_eq!(error.error.data, Some(json!({ "correlationId": list_id })));
+
+        let lines = log_lines(log_dir.path());
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["correlation_id"], json!(call_id));
+        assert_eq!(lines[0]["tool"], json!("codex"));
+        assert_eq!(lines[0]["arguments"], json!({ "prompt": "use [REDACTED]" }));
+        assert_eq!(lines[0]["outcome"], json!("tool_error"));
+        assert_eq!(lines[1]["method"], json!("tools/list"));
+        assert_eq!(lines[1]["outcome"], json!("error"));
+        assert_eq!(lines[1]["error"], json!("boom"));
+    }
+
+    #[test]
+    fn rotates_the_log_file() {
+        let log_dir = TempDir::new().unwrap();
+        let path = log_dir.path().join(LOG_FILENAME);
+        let mut file = RotatingFile::open(path.clone()).unwrap();
+        file.max_len = 10;
+        // Each file holds two lines, so this rotates four times.
+        for _ in 0..10 {
+            file.append(b"12345").unwrap();
+        }
+
+        let rotated: Vec<bool> = (1..=ROTATED_LOGS + 1)
+            .map(|n| log_dir.path().join(format!("{LOG_FILENAME}.{n}")).exists())
+            .collect();
+        assert_eq!(rotated, vec![true, true, true, false]);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 10);
+    }
+}
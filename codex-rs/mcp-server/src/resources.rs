@@ -0,0 +1,286 @@
+//! The artifacts of a client's conversations, the tool outputs saved to files
+//! because they were too large to send to the model, exposed as MCP
+//! resources. Clients that prefer resources to tool results can list and
+//! read them, and learn about new ones from
+//! `notifications/resources/list_changed`. Large artifacts can be read a
+//! page at a time by adding `?page=N` to their URI.
+
+use std::path::Path;
+
+use mcp_types::ListResourceTemplatesResult;
+use mcp_types::ListResourcesResult;
+use mcp_types::ModelContextProtocolNotification;
+use mcp_types::ReadResourceResult;
+use mcp_types::ReadResourceResultContents;
+use mcp_types::Resource;
+use mcp_types::ResourceListChangedNotification;
+use mcp_types::ResourceTemplate;
+use mcp_types::TextResourceContents;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::outgoing_message::OutgoingMessageSender;
+use crate::outgoing_message::OutgoingNotification;
+
+const ARTIFACT_URI_SCHEME: &str = "codex-artifact://";
+
+/// Bytes in a page of an artifact, give or take a split character.
+pub(crate) const PAGE_BYTES: usize = 64 * 1024;
+
+/// Lists the artifacts saved by `conversation_ids`, oldest conversation id
+/// first and by name within a conversation.
+pub(crate) async fn list_artifacts(
+    codex_home: &Path,
+    conversation_ids: &[Uuid],
+) -> ListResourcesResult {
+    let mut conversation_ids = conversation_ids.to_vec();
+    conversation_ids.sort();
+    let mut resources = Vec::new();
+    for conversation_id in conversation_ids {
+        let dir = artifacts_dir(codex_home, conversation_id);
+        let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+            // No artifacts were saved for this conversation.
+            continue;
+        };
+        let mut artifacts = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            let size = match entry.metadata().await {
+                Ok(metadata) if metadata.is_file() => metadata.len(),
+                _ => continue,
+            };
+            artifacts.push(Resource {
+                annotations: None,
+                description: Some(format!(
+                    "Output of tool call {} in conversation {conversation_id}",
+                    name.rsplit_once('.')
+                        .map_or(name.as_str(), |(stem, _)| stem)
+                )),
+                mime_type: Some(mime_type(&name).to_string()),
+                size: i64::try_from(size).ok(),
+                title: None,
+                uri: artifact_uri(conversation_id, &name),
+                name,
+            });
+        }
+        artifacts.sort_by(|a, b| a.name.cmp(&b.name));
+        resources.extend(artifacts);
+    }
+    ListResourcesResult {
+        next_cursor: None,
+        resources,
+    }
+}
+
+pub(crate) fn resource_templates() -> ListResourceTemplatesResult {
+    ListResourceTemplatesResult {
+        next_cursor: None,
+        resource_templates: vec![ResourceTemplate {
+            annotations: None,
+            description: Some(
+                "A tool output of a conversation that was too large to send to the model."
+                    .to_string(),
+            ),
+            mime_type: None,
+            name: "artifact".to_string(),
+            title: Some("Codex artifact".to_string()),
+            uri_template: format!("{ARTIFACT_URI_SCHEME}{{conversation_id}}/{{name}}"),
+        }],
+    }
+}
+
+/// Reads the artifact `uri` names, or one page of it, if it belongs to one
+/// of `conversation_ids`.
+pub(crate) async fn read_artifact(
+    codex_home: &Path,
+    conversation_ids: &[Uuid],
+    uri: &str,
+) -> Result<ReadResourceResult, String> {
+    let (conversation_id, name, page) =
+        parse_artifact_uri(uri).ok_or_else(|| format!("unknown resource: {uri}"))?;
+    if !conversation_ids.contains(&conversation_id) {
+        return Err(format!("unknown resource: {uri}"));
+    }
+    let text = tokio::fs::read_to_string(artifacts_dir(codex_home, conversation_id).join(name))
+        .await
+        .map_err(|e| format!("failed to read {uri}: {e}"))?;
+    let text = match page {
+        Some(page) => {
+            let pages = page_count(text.len());
+            if page == 0 || page > pages {
+                return Err(format!("{uri} has pages 1 to {pages}"));
+            }
+            page_of(&text, page).to_string()
+        }
+        None => text,
+    };
+    Ok(ReadResourceResult {
+        contents: vec![ReadResourceResultContents::TextResourceContents(
+            TextResourceContents {
+                mime_type: Some(mime_type(name).to_string()),
+                text,
+                uri: uri.to_string(),
+            },
+        )],
+    })
+}
+
+/// Tells the client to list the resources again.
+pub(crate) async fn notify_resource_list_changed(outgoing: &OutgoingMessageSender) {
+    outgoing
+        .send_notification(OutgoingNotification {
+            method: ResourceListChangedNotification::METHOD.to_string(),
+            params: None,
+        })
+        .await;
+}
+
+/// Matches where codex-core saves the artifacts of a conversation.
+pub(crate) fn artifacts_dir(codex_home: &Path, conversation_id: Uuid) -> std::path::PathBuf {
+    codex_home
+        .join("artifacts")
+        .join(conversation_id.to_string())
+}
+
+pub(crate) fn artifact_uri(conversation_id: Uuid, name: &str) -> String {
+    format!("{ARTIFACT_URI_SCHEME}{conversation_id}/{name}")
+}
+
+/// Pages of [`PAGE_BYTES`] a text of `len` bytes is read in.
+pub(crate) fn page_count(len: usize) -> usize {
+    len.div_ceil(PAGE_BYTES).max(1)
+}
+
+/// Page `page`, counted from 1, of `text`. Pages are split on character
+/// boundaries.
+fn page_of(text: &str, page: usize) -> &str {
+    let boundary = |index: usize| {
+        let mut index = index.min(text.len());
+        while !text.is_char_boundary(index) {
+            index -= 1;
+        }
+        index
+    };
+    &text[boundary((page - 1) * PAGE_BYTES)..boundary(page * PAGE_BYTES)]
+}
+
+/// The conversation, file name and page in an artifact URI. Only files
+/// directly in the conversation's artifacts directory can be named.
+fn parse_artifact_uri(uri: &str) -> Option<(Uuid, &str, Option<usize>)> {
+    let (path, page) = match uri.split_once('?') {
+        Some((path, query)) => (path, Some(query.strip_prefix("page=")?.parse().ok()?)),
+        None => (uri, None),
+    };
+    let (conversation_id, name) = path.strip_prefix(ARTIFACT_URI_SCHEME)?.split_once('/')?;
+    let conversation_id = Uuid::parse_str(conversation_id).ok()?;
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        warn!("refusing artifact name {name:?}");
+        return None;
+    }
+    Some((conversation_id, name, page))
+}
+
+fn mime_type(name: &str) -> &'static str {
+    if name.ends_with(".json") {
+        "application/json"
+    } else {
+        "text/plain"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn lists_and_reads_the_artifacts_of_known_conversations() {
+        let codex_home = TempDir::new().unwrap();
+        let mine = Uuid::new_v4();
+        let theirs = Uuid::new_v4();
+        for (conversation_id, name) in [
+            (mine, "call_1.txt"),
+            (mine, "call_2.json"),
+            (theirs, "x.txt"),
+        ] {
+            let dir = artifacts_dir(codex_home.path(), conversation_id);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join(name), "output").unwrap();
+        }
+
+        let listed = list_artifacts(codex_home.path(), &[mine]).await;
+        let uris: Vec<_> = listed.resources.iter().map(|r| r.uri.clone()).collect();
+        assert_eq!(
+            uris,
+            vec![
+                format!("codex-artifact://{mine}/call_1.txt"),
+                format!("codex-artifact://{mine}/call_2.json"),
+            ]
+        );
+        assert_eq!(
+            listed.resources[1].mime_type.as_deref(),
+            Some("application/json")
+        );
+
+        let read = read_artifact(codex_home.path(), &[mine], &uris[0])
+            .await
+            .unwrap();
+        assert_eq!(
+            read.contents,
+            vec![ReadResourceResultContents::TextResourceContents(
+                TextResourceContents {
+                    mime_type: Some("text/plain".to_string()),
+                    text: "output".to_string(),
+                    uri: uris[0].clone(),
+                }
+            )]
+        );
+
+        let other = format!("codex-artifact://{theirs}/x.txt");
+        assert!(
+            read_artifact(codex_home.path(), &[mine], &other)
+                .await
+                .is_err()
+        );
+        let page_uri = format!("{}?page=1", uris[0]);
+        let page = read_artifact(codex_home.path(), &[mine], &page_uri)
+            .await
+            .unwrap();
+        assert_eq!(
+            page.contents,
+            vec![ReadResourceResultContents::TextResourceContents(
+                TextResourceContents {
+                    mime_type: Some("text/plain".to_string()),
+                    text: "output".to_string(),
+                    uri: page_uri,
+                }
+            )]
+        );
+        let past_the_end = format!("{}?page=2", uris[0]);
+        assert!(
+            read_artifact(codex_home.path(), &[mine], &past_the_end)
+                .await
+                .is_err()
+        );
+        let escape = format!("codex-artifact://{mine}/../{theirs}/x.txt");
+        assert!(
+            read_artifact(codex_home.path(), &[mine], &escape)
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn splits_pages_on_character_boundaries() {
+        let text = format!("{}é{}", "a".repeat(PAGE_BYTES - 1), "b".repeat(10));
+        assert_eq!(page_count(text.len()), 2);
+        assert_eq!(page_of(&text, 1), "a".repeat(PAGE_BYTES - 1));
+        assert_eq!(page_of(&text, 2), format!("é{}", "b".repeat(10)));
+        assert_eq!(page_count(0), 1);
+    }
+}
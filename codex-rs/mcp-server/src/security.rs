@@ -0,0 +1,544 @@
+//! Who may use a server that listens on a port. Clients prove who they are
+//! with a bearer token named after them, or with a client certificate when
+//! TLS is configured with a client CA. Every connection gets an identity
+//! that its log lines carry, so a shared server shows which client ran what.
+
+use std::fmt;
+use std::io::Result as IoResult;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::extract::connect_info::Connected;
+use axum::serve::IncomingStream;
+use codex_core::config_types::McpServerAuthConfig;
+use codex_core::config_types::McpServerTlsConfig;
+use sha2::Digest;
+use sha2::Sha256;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::RootCertStore;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::pki_types::PrivateKeyDer;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tracing::warn;
+
+/// How long a client has to finish the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The tokens and TLS settings every connection is checked against.
+#[derive(Default)]
+pub(crate) struct Security {
+    tokens: Vec<ClientToken>,
+    tls: Option<TlsAcceptor>,
+    verifies_client_certificates: bool,
+}
+
+struct ClientToken {
+    client: String,
+    token: String,
+}
+
+impl Security {
+    /// Reads the tokens from the environment and the TLS certificates from
+    /// disk, failing on anything missing so that a misconfigured server
+    /// does not start open.
+    pub(crate) fn from_config(config: &McpServerAuthConfig) -> anyhow::Result<Self> {
+        let tokens = config
+            .tokens
+            .iter()
+            .map(|token| {
+                let value = std::env::var(&token.token_env).with_context(|| {
+                    format!(
+                        "environment variable {} with the token of MCP client {} is not set",
+                        token.token_env, token.client
+                    )
+                })?;
+                if value.is_empty() {
+                    anyhow::bail!(
+                        "environment variable {} with the token of MCP client {} is empty",
+                        token.token_env,
+                        token.client
+                    );
+                }
+                Ok(ClientToken {
+                    client: token.client.clone(),
+                    token: value,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let tls = config.tls.as_ref().map(tls_acceptor).transpose()?;
+        Ok(Self {
+            tokens,
+            tls,
+            verifies_client_certificates: config
+                .tls
+                .as_ref()
+                .is_some_and(|tls| tls.client_ca.is_some()),
+        })
+    }
+
+    /// Whether clients must present a bearer token.
+    pub(crate) fn requires_token(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// Whether anyone who can connect may use the server.
+    pub(crate) fn is_open(&self) -> bool {
+        self.tokens.is_empty() && !self.verifies_client_certificates
+    }
+
+    pub(crate) fn uses_tls(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    /// Checks the value of an `Authorization` header and returns the name of
+    /// the client whose token it carries, or `None` when no tokens are
+    /// configured.
+    pub(crate) fn authenticate(
+        &self,
+        authorization: Option<&str>,
+    ) -> Result<Option<String>, AuthError> {
+        if self.tokens.is_empty() {
+            return Ok(None);
+        }
+        let presented = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .ok_or(AuthError::MissingToken)?;
+        self.tokens
+            .iter()
+            .find(|token| constant_time_eq(token.token.as_bytes(), presented.as_bytes()))
+            .map(|token| Some(token.client.clone()))
+            .ok_or(AuthError::InvalidToken)
+    }
+
+    /// Wraps an accepted connection in TLS when configured, verifying the
+    /// client certificate if a client CA is.
+    pub(crate) async fn handshake(
+        &self,
+        stream: TcpStream,
+        addr: SocketAddr,
+    ) -> IoResult<Connection> {
+        let Some(tls) = &self.tls else {
+            return Ok(Connection {
+                io: Box::new(stream),
+                peer: Peer {
+                    addr,
+                    certificate: None,
+                },
+            });
+        };
+        let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, tls.accept(stream))
+            .await
+            .map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::TimedOut, "TLS handshake timed out")
+            })??;
+        let certificate = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(<[CertificateDer]>::first)
+            .map(fingerprint);
+        Ok(Connection {
+            io: Box::new(stream),
+            peer: Peer { addr, certificate },
+        })
+    }
+}
+
+#[cfg(test)]
+impl Security {
+    /// Security that accepts the given `(client, token)` pairs.
+    pub(crate) fn with_tokens(tokens: &[(&str, &str)]) -> Self {
+        Self {
+            tokens: tokens
+                .iter()
+                .map(|(client, token)| ClientToken {
+                    client: client.to_string(),
+                    token: token.to_string(),
+                })
+                .collect(),
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AuthError {
+    MissingToken,
+    InvalidToken,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingToken => f.write_str("missing bearer token"),
+            AuthError::InvalidToken => f.write_str("invalid bearer token"),
+        }
+    }
+}
+
+/// Either side of a plain or TLS connection.
+pub(crate) trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+pub(crate) struct Connection {
+    pub(crate) io: Box<dyn Io>,
+    pub(crate) peer: Peer,
+}
+
+/// The remote end of a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Peer {
+    pub(crate) addr: SocketAddr,
+    /// Fingerprint of the verified client certificate, if one was presented.
+    pub(crate) certificate: Option<String>,
+}
+
+impl Peer {
+    /// The name the client is logged under: the client its token belongs
+    /// to, else its certificate, else its address.
+    pub(crate) fn identity(&self, client: Option<&str>) -> String {
+        match (client, &self.certificate) {
+            (Some(client), _) => format!("{client}@{}", self.addr),
+            (None, Some(certificate)) => format!("{certificate}@{}", self.addr),
+            (None, None) => self.addr.to_string(),
+        }
+    }
+}
+
+/// Most connections that finished their handshake and wait for
+/// `axum::serve` to pick them up.
+const ACCEPTED_QUEUE: usize = 64;
+
+/// A listener for `axum::serve` that performs the TLS handshake of every
+/// connection it accepts. Handshakes run in their own tasks, so a client that
+/// stalls its handshake holds up no one but itself.
+pub(crate) struct SecureListener {
+    connections: mpsc::Receiver<Connection>,
+    local_addr: SocketAddr,
+    accept_loop: JoinHandle<()>,
+}
+
+impl SecureListener {
+    pub(crate) fn new(listener: TcpListener, security: Arc<Security>) -> IoResult<Self> {
+        let local_addr = listener.local_addr()?;
+        let (tx, connections) = mpsc::channel(ACCEPTED_QUEUE);
+        let accept_loop = tokio::spawn(accept_connections(listener, security, tx));
+        Ok(Self {
+            connections,
+            local_addr,
+            accept_loop,
+        })
+    }
+}
+
+impl Drop for SecureListener {
+    fn drop(&mut self) {
+        self.accept_loop.abort();
+    }
+}
+
+async fn accept_connections(
+    listener: TcpListener,
+    security: Arc<Security>,
+    tx: mpsc::Sender<Connection>,
+) {
+    while !tx.is_closed() {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Usually out of file descriptors; give some back before
+                // trying again.
+                warn!("failed to accept connection: {e}");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let security = security.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            match security.handshake(stream, addr).await {
+                Ok(connection) => {
+                    // Fails only when the server is shutting down.
+                    let _ = tx.send(connection).await;
+                }
+                Err(e) => warn!("TLS handshake with {addr} failed: {e}"),
+            }
+        });
+    }
+}
+
+impl axum::serve::Listener for SecureListener {
+    type Io = Box<dyn Io>;
+    type Addr = Peer;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => (connection.io, connection.peer),
+            // The accept loop only stops once this listener is dropped.
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> IoResult<Self::Addr> {
+        Ok(Peer {
+            addr: self.local_addr,
+            certificate: None,
+        })
+    }
+}
+
+impl Connected<IncomingStream<'_, SecureListener>> for Peer {
+    fn connect_info(stream: IncomingStream<'_, SecureListener>) -> Self {
+        stream.remote_addr().clone()
+    }
+}
+
+fn tls_acceptor(config: &McpServerTlsConfig) -> anyhow::Result<TlsAcceptor> {
+    let provider = Arc::new(ring::default_provider());
+    let certs = CertificateDer::pem_file_iter(&config.cert)
+        .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+        .with_context(|| format!("failed to read TLS certificate {}", config.cert.display()))?;
+    let key = PrivateKeyDer::from_pem_file(&config.key)
+        .with_context(|| format!("failed to read TLS key {}", config.key.display()))?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match &config.client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            let cas = CertificateDer::pem_file_iter(client_ca)
+                .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+                .with_context(|| format!("failed to read client CA {}", client_ca.display()))?;
+            for ca in cas {
+                roots.add(ca)?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder.with_single_cert(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// A short, stable name for a certificate: the start of its SHA-256.
+fn fingerprint(certificate: &CertificateDer) -> String {
+    let digest = Sha256::digest(certificate.as_ref());
+    let hex: String = digest[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("cert:{hex}")
+}
+
+/// Compares secrets in time that does not depend on where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rcgen::BasicConstraints;
+    use rcgen::CertificateParams;
+    use rcgen::IsCa;
+    use rcgen::Issuer;
+    use rcgen::KeyPair;
+    use tempfile::TempDir;
+    use tokio_rustls::TlsConnector;
+    use tokio_rustls::rustls::ClientConfig;
+    use tokio_rustls::rustls::pki_types::PrivatePkcs8KeyDer;
+    use tokio_rustls::rustls::pki_types::ServerName;
+
+    fn security() -> Security {
+        Security {
+            tokens: vec![
+                ClientToken {
+                    client: "ci".to_string(),
+                    token: "secret-ci".to_string(),
+                },
+                ClientToken {
+                    client: "ops".to_string(),
+                    token: "secret-ops".to_string(),
+                },
+            ],
+            ..Security::default()
+        }
+    }
+
+    #[test]
+    fn authenticates_bearer_tokens() {
+        let security = security();
+        assert_eq!(
+            security.authenticate(Some("Bearer secret-ops")),
+            Ok(Some("ops".to_string()))
+        );
+        assert_eq!(
+            security.authenticate(Some("Bearer secret")),
+            Err(AuthError::InvalidToken)
+        );
+        assert_eq!(
+            security.authenticate(Some("Basic secret-ci")),
+            Err(AuthError::MissingToken)
+        );
+        assert_eq!(security.authenticate(None), Err(AuthError::MissingToken));
+        assert_eq!(Security::default().authenticate(None), Ok(None));
+    }
+
+    #[test]
+    fn names_clients_by_token_then_certificate() {
+        let peer = Peer {
+            addr: "10.0.0.5:4000".parse().unwrap(),
+            certificate: Some("cert:0011223344556677".to_string()),
+        };
+        assert_eq!(peer.identity(Some("ci")), "ci@10.0.0.5:4000");
+        assert_eq!(peer.identity(None), "cert:0011223344556677@10.0.0.5:4000");
+    }
+
+    #[tokio::test]
+    async fn verifies_client_certificates() {
+        let dir = TempDir::new().unwrap();
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+        let ca = Issuer::new(ca_params, ca_key);
+        let issue = |name: &str| {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec![name.to_string()])
+                .unwrap()
+                .signed_by(&key, &ca)
+                .unwrap();
+            (cert, key)
+        };
+        let (server_cert, server_key) = issue("localhost");
+        let (client_cert, client_key) = issue("ci");
+        let tls = McpServerTlsConfig {
+            cert: dir.path().join("server.pem"),
+            key: dir.path().join("server.key"),
+            client_ca: Some(dir.path().join("ca.pem")),
+        };
+        std::fs::write(&tls.cert, server_cert.pem()).unwrap();
+        std::fs::write(&tls.key, server_key.serialize_pem()).unwrap();
+        std::fs::write(dir.path().join("ca.pem"), ca_cert.pem()).unwrap();
+        let security = Security::from_config(&McpServerAuthConfig {
+            tokens: Vec::new(),
+            tls: Some(tls),
+        })
+        .unwrap();
+        assert!(!security.is_open());
+
+        let mut roots = RootCertStore::empty();
+        roots.add(ca_cert.der().clone()).unwrap();
+        let client_config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let with_certificate = client_config
+            .clone()
+            .with_client_auth_cert(
+                vec![client_cert.der().clone()],
+                PrivatePkcs8KeyDer::from(client_key.serialize_der()).into(),
+            )
+            .unwrap();
+        let without_certificate = client_config.with_no_client_auth();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        for (config, expected) in [
+            (with_certificate, Some(fingerprint(client_cert.der()))),
+            (without_certificate, None),
+        ] {
+            let client = tokio::spawn(async move {
+                let stream = TcpStream::connect(addr).await.unwrap();
+                TlsConnector::from(Arc::new(config))
+                    .connect(ServerName::try_from("localhost").unwrap(), stream)
+                    .await
+            });
+            let (stream, peer_addr) = listener.accept().await.unwrap();
+            let connection = security.handshake(stream, peer_addr).await;
+            let _ = client.await;
+
+            match expected {
+                Some(certificate) => {
+                    assert_eq!(connection.unwrap().peer.certificate, Some(certificate));
+                }
+                None => assert!(connection.is_err()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn stalled_handshakes_do_not_block_other_clients() {
+        let dir = TempDir::new().unwrap();
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+        let ca = Issuer::new(ca_params, ca_key);
+        let server_key = KeyPair::generate().unwrap();
+        let server_cert = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&server_key, &ca)
+            .unwrap();
+        let tls = McpServerTlsConfig {
+            cert: dir.path().join("server.pem"),
+            key: dir.path().join("server.key"),
+            client_ca: None,
+        };
+        std::fs::write(&tls.cert, server_cert.pem()).unwrap();
+        std::fs::write(&tls.key, server_key.serialize_pem()).unwrap();
+        let security = Security::from_config(&McpServerAuthConfig {
+            tokens: Vec::new(),
+            tls: Some(tls),
+        })
+        .unwrap();
+
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let mut listener = SecureListener::new(tcp, Arc::new(security)).unwrap();
+
+        // Connects first and never starts its handshake.
+        let _stalled = TcpStream::connect(addr).await.unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(ca_cert.der().clone()).unwrap();
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let client = tokio::spawn(async move {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            TlsConnector::from(Arc::new(config))
+                .connect(ServerName::try_from("localhost").unwrap(), stream)
+                .await
+        });
+
+        let (_io, peer) = tokio::time::timeout(
+            HANDSHAKE_TIMEOUT / 5,
+            axum::serve::Listener::accept(&mut listener),
+        )
+        .await
+        .unwrap();
+        assert_eq!(peer.certificate, None);
+        assert!(client.await.unwrap().is_ok());
+    }
+}
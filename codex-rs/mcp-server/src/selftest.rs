@@ -0,0 +1,202 @@
+//! `codex mcp selftest`: checks an installation end to end, from loading
+//! the config to registering the tools, and reports each check as passed or
+//! failed, for troubleshooting a server that clients cannot use.
+
+use std::fmt;
+use std::io::Result as IoResult;
+use std::path::Path;
+
+use codex_common::CliConfigOverrides;
+use codex_core::config_types::McpServerMode;
+use codex_login::AuthManager;
+use mcp_types::Tool;
+use serde::Serialize;
+
+use crate::health;
+use crate::load_config;
+use crate::mode;
+use crate::prompts;
+use crate::security::Security;
+
+/// The outcome of `codex mcp selftest`.
+#[derive(Debug, Serialize)]
+struct SelfTestReport {
+    passed: bool,
+    checks: Vec<SelfTestCheck>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct SelfTestCheck {
+    name: &'static str,
+    passed: bool,
+    detail: String,
+}
+
+impl SelfTestCheck {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            passed: true,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            passed: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl SelfTestReport {
+    fn new(checks: Vec<SelfTestCheck>) -> Self {
+        Self {
+            passed: checks.iter().all(|check| check.passed),
+            checks,
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        for check in &self.checks {
+            let status = if check.passed { "PASS" } else { "FAIL" };
+            writeln!(f, "{status}  {:width$}  {}", check.name, check.detail)?;
+        }
+        let passed = self.checks.iter().filter(|c| c.passed).count();
+        write!(f, "{passed} of {} checks passed", self.checks.len())
+    }
+}
+
+/// Runs `codex mcp selftest` and prints its report, as text or as JSON.
+/// Exits with status 1 if a check failed.
+#[allow(clippy::print_stdout)]
+pub async fn run_selftest(
+    cli_config_overrides: CliConfigOverrides,
+    mode: Option<McpServerMode>,
+    json: bool,
+) -> IoResult<()> {
+    let report = selftest(cli_config_overrides, mode).await;
+    if json {
+        println!("{}", report.to_json()?);
+    } else {
+        println!("{report}");
+    }
+    if !report.passed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Runs the checks with the config `codex mcp` would serve with. A config
+/// that does not load stops the run, as every other check depends on it.
+async fn selftest(
+    cli_config_overrides: CliConfigOverrides,
+    mode: Option<McpServerMode>,
+) -> SelfTestReport {
+    let config = match load_config(cli_config_overrides, mode) {
+        Ok(config) => config,
+        Err(e) => return SelfTestReport::new(vec![SelfTestCheck::fail("config", e.to_string())]),
+    };
+    let mut checks = vec![SelfTestCheck::pass(
+        "config",
+        format!("loaded; serving in {} mode", config.mcp_server_mode),
+    )];
+    checks.push(match Security::from_config(&config.mcp_server_auth) {
+        Ok(_) => SelfTestCheck::pass("mcp_server_auth", "loaded"),
+        Err(e) => SelfTestCheck::fail("mcp_server_auth", format!("{e:#}")),
+    });
+    let auth_manager = AuthManager::shared(config.codex_home.clone(), config.preferred_auth_method);
+    for check in health::readiness(&config, &auth_manager).await {
+        checks.push(match check.result {
+            Ok(()) => SelfTestCheck::pass(check.name, "ok"),
+            Err(e) => SelfTestCheck::fail(check.name, e),
+        });
+    }
+    checks.push(check_tools(&mode::tools(config.mcp_server_mode)));
+    checks.push(check_prompts(&config.codex_home).await);
+    SelfTestReport::new(checks)
+}
+
+/// Checks that the tools survive the round trip through JSON a client makes
+/// of `tools/list`.
+fn check_tools(tools: &[Tool]) -> SelfTestCheck {
+    for tool in tools {
+        let round_trip = serde_json::to_value(tool).and_then(serde_json::from_value::<Tool>);
+        match round_trip {
+            Ok(parsed) if parsed == *tool && tool.input_schema.r#type == "object" => {}
+            Ok(_) => {
+                return SelfTestCheck::fail(
+                    "tools",
+                    format!("`{}` has an invalid schema", tool.name),
+                );
+            }
+            Err(e) => {
+                return SelfTestCheck::fail(
+                    "tools",
+                    format!("`{}` does not serialize: {e}", tool.name),
+                );
+            }
+        }
+    }
+    let names: Vec<&str> = tools.iter().map(|tool| tool.name.as_str()).collect();
+    SelfTestCheck::pass("tools", names.join(", "))
+}
+
+async fn check_prompts(codex_home: &Path) -> SelfTestCheck {
+    let prompts = prompts::list_prompts(codex_home).await.prompts;
+    SelfTestCheck::pass("prompts", format!("{} available", prompts.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use mcp_types::ToolInputSchema;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn checks_the_registered_tools() {
+        assert_eq!(
+            check_tools(&mode::tools(McpServerMode::ReadOnly)),
+            SelfTestCheck::pass("tools", "codex, codex-reply")
+        );
+
+        let broken = Tool {
+            input_schema: ToolInputSchema {
+                properties: None,
+                required: None,
+                r#type: "string".to_string(),
+            },
+            ..mode::tools(McpServerMode::Admin).remove(0)
+        };
+        assert_eq!(
+            check_tools(&[broken]),
+            SelfTestCheck::fail("tools", "`codex` has an invalid schema")
+        );
+    }
+
+    #[test]
+    fn reports_every_check() {
+        let report = SelfTestReport::new(vec![
+            SelfTestCheck::pass("config", "loaded"),
+            SelfTestCheck::fail("auth", "not logged in"),
+        ]);
+        assert!(!report.passed);
+        assert_eq!(
+            report.to_string(),
+            "PASS  config  loaded\nFAIL  auth    not logged in\n1 of 2 checks passed"
+        );
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["checks"][1]["name"], "auth");
+        assert_eq!(json["passed"], false);
+    }
+}
@@ -0,0 +1,728 @@
+//! The MCP Streamable HTTP transport, for web-based clients. Clients POST
+//! JSON-RPC messages to a single endpoint and receive the server's messages
+//! as server-sent events, either on the response to a request or on a
+//! stream opened with GET. Sessions are named by the `Mcp-Session-Id`
+//! header, and every event carries an id so a client that lost its stream
+//! can reconnect with `Last-Event-ID` and still get the results of tool
+//! calls that were running. A session belongs to the client that created
+//! it; other clients are told it does not exist. For deployments, `/healthz`,
+//! `/readyz` and `/metrics` serve liveness, readiness and Prometheus metrics
+//! without authentication.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::io::Result as IoResult;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use axum::Router;
+use axum::extract::ConnectInfo;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::response::sse::Event;
+use axum::response::sse::KeepAlive;
+use axum::response::sse::Sse;
+use axum::routing::get;
+use axum::routing::post;
+use futures::StreamExt;
+use mcp_types::JSONRPCMessage;
+use mcp_types::RequestId;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use tracing::error;
+use tracing::info;
+use tracing::info_span;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::CHANNEL_CAPACITY;
+use crate::ServerContext;
+use crate::health;
+use crate::metrics::InFlight;
+use crate::metrics::Metrics;
+use crate::outgoing_message::OutgoingMessage;
+use crate::process_messages;
+use crate::security::AuthError;
+use crate::security::Peer;
+use crate::security::SecureListener;
+use crate::security::Security;
+
+/// Path of the MCP endpoint.
+pub(crate) const ENDPOINT_PATH: &str = "/mcp";
+
+const SESSION_ID_HEADER: &str = "mcp-session-id";
+
+/// Events kept per session for clients that reconnect.
+const MAX_LOGGED_EVENTS: usize = 1024;
+
+#[derive(Clone)]
+struct AppState {
+    context: ServerContext,
+    /// Host the server listens on; browsers on other origins are refused.
+    host: String,
+    security: Arc<Security>,
+    sessions: Arc<Mutex<HashMap<String, Arc<HttpSession>>>>,
+    metrics: Arc<Metrics>,
+}
+
+/// Who sent a request.
+struct Client {
+    /// The client named by the bearer token or the certificate fingerprint,
+    /// if either identified it.
+    principal: Option<String>,
+    /// What the client's log lines carry.
+    identity: String,
+}
+
+struct HttpSession {
+    /// The principal of the client that created the session.
+    owner: Option<String>,
+    incoming_tx: mpsc::Sender<JSONRPCMessage>,
+    streams: Mutex<Streams>,
+    /// Requests not answered yet, timed for the metrics.
+    in_flight: Mutex<HashMap<RequestId, InFlight>>,
+}
+
+/// A server-sent event: one JSON-RPC message and its id within the session.
+#[derive(Debug, Clone, PartialEq)]
+struct StreamEvent {
+    id: u64,
+    data: String,
+}
+
+struct LoggedEvent {
+    event: StreamEvent,
+    /// Whether the event was handed to a stream that was still open.
+    delivered: bool,
+}
+
+/// Where the messages of a session go.
+#[derive(Default)]
+struct Streams {
+    next_event_id: u64,
+    /// Streams of POSTed requests, waiting for their response.
+    pending: HashMap<RequestId, mpsc::UnboundedSender<StreamEvent>>,
+    /// The stream opened with GET, for messages not answering a request.
+    listener: Option<mpsc::UnboundedSender<StreamEvent>>,
+    log: VecDeque<LoggedEvent>,
+}
+
+impl Streams {
+    /// Sends `msg` on the stream of the request it answers, or else on the
+    /// GET stream or any open request stream. Messages no open stream takes
+    /// wait in the log for the client to reconnect.
+    fn deliver(&mut self, msg: &JSONRPCMessage, data: String) {
+        let event = StreamEvent {
+            id: self.next_event_id,
+            data,
+        };
+        self.next_event_id += 1;
+
+        let answers = match msg {
+            JSONRPCMessage::Response(response) => Some(&response.id),
+            JSONRPCMessage::Error(error) => Some(&error.id),
+            JSONRPCMessage::Request(_) | JSONRPCMessage::Notification(_) => None,
+        };
+        let delivered = match answers.and_then(|id| self.pending.remove(id)) {
+            // The request's stream ends once its sender is dropped here.
+            Some(stream) => stream.send(event.clone()).is_ok(),
+            None => self.send_to_any_stream(&event),
+        };
+
+        self.log.push_back(LoggedEvent { event, delivered });
+        if self.log.len() > MAX_LOGGED_EVENTS {
+            self.log.pop_front();
+        }
+    }
+
+    fn send_to_any_stream(&mut self, event: &StreamEvent) -> bool {
+        if let Some(listener) = &self.listener {
+            if listener.send(event.clone()).is_ok() {
+                return true;
+            }
+            self.listener = None;
+        }
+        self.pending.retain(|_, stream| !stream.is_closed());
+        self.pending
+            .values()
+            .any(|stream| stream.send(event.clone()).is_ok())
+    }
+
+    /// Makes `listener` the GET stream, first sending it the events that
+    /// were never delivered and those after `last_event_id`.
+    fn listen(&mut self, listener: mpsc::UnboundedSender<StreamEvent>, last_event_id: Option<u64>) {
+        for logged in &mut self.log {
+            let missed = last_event_id.is_some_and(|last| logged.event.id > last);
+            if !logged.delivered || missed {
+                logged.delivered = listener.send(logged.event.clone()).is_ok();
+            }
+        }
+        self.listener = Some(listener);
+    }
+}
+
+/// Serves MCP clients over Streamable HTTP on `listener` until the server
+/// fails.
+pub(crate) async fn serve_http(
+    listener: TcpListener,
+    host: String,
+    security: Arc<Security>,
+    context: ServerContext,
+) -> IoResult<()> {
+    let state = AppState {
+        context,
+        host,
+        security: security.clone(),
+        sessions: Arc::default(),
+        metrics: Arc::default(),
+    };
+    let app = Router::new()
+        .route(
+            ENDPOINT_PATH,
+            post(handle_post).get(handle_get).delete(handle_delete),
+        )
+        .route("/healthz", get(handle_healthz))
+        .route("/readyz", get(handle_readyz))
+        .route("/metrics", get(handle_metrics))
+        .with_state(state);
+    let listener = SecureListener::new(listener, security)?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<Peer>()).await
+}
+
+async fn handle_post(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let client = match state.authorize(&headers, &peer) {
+        Ok(client) => client,
+        Err(refusal) => return refusal.into_response(),
+    };
+    let msg = match serde_json::from_str::<JSONRPCMessage>(&body) {
+        Ok(msg) => msg,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("invalid JSON-RPC message: {e}"),
+            )
+                .into_response();
+        }
+    };
+
+    let (session_id, session) = match session_id(&headers) {
+        Some(session_id) => match state.session(&session_id, &client) {
+            Some(session) => (session_id, session),
+            None => return (StatusCode::NOT_FOUND, "unknown session").into_response(),
+        },
+        None => match &msg {
+            JSONRPCMessage::Request(request) if request.method == "initialize" => {
+                state.create_session(client)
+            }
+            _ => {
+                return (StatusCode::BAD_REQUEST, "missing Mcp-Session-Id header").into_response();
+            }
+        },
+    };
+
+    let stream = match &msg {
+        JSONRPCMessage::Request(request) => {
+            lock(&session.in_flight).insert(request.id.clone(), InFlight::new(request));
+            let (tx, rx) = mpsc::unbounded_channel();
+            lock(&session.streams)
+                .pending
+                .insert(request.id.clone(), tx);
+            Some(rx)
+        }
+        _ => None,
+    };
+    if session.incoming_tx.send(msg).await.is_err() {
+        state.remove_session(&session_id);
+        return (StatusCode::NOT_FOUND, "session has ended").into_response();
+    }
+
+    let session_header = [(SESSION_ID_HEADER, session_id)];
+    match stream {
+        Some(rx) => (session_header, event_stream(rx)).into_response(),
+        None => (StatusCode::ACCEPTED, session_header).into_response(),
+    }
+}
+
+async fn handle_get(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    headers: HeaderMap,
+) -> Response {
+    let client = match state.authorize(&headers, &peer) {
+        Ok(client) => client,
+        Err(refusal) => return refusal.into_response(),
+    };
+    let Some(session_id) = session_id(&headers) else {
+        return (StatusCode::BAD_REQUEST, "missing Mcp-Session-Id header").into_response();
+    };
+    let Some(session) = state.session(&session_id, &client) else {
+        return (StatusCode::NOT_FOUND, "unknown session").into_response();
+    };
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    lock(&session.streams).listen(tx, last_event_id);
+    ([(SESSION_ID_HEADER, session_id)], event_stream(rx)).into_response()
+}
+
+async fn handle_delete(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    headers: HeaderMap,
+) -> Response {
+    let client = match state.authorize(&headers, &peer) {
+        Ok(client) => client,
+        Err(refusal) => return refusal.into_response(),
+    };
+    let Some(session_id) = session_id(&headers) else {
+        return (StatusCode::BAD_REQUEST, "missing Mcp-Session-Id header").into_response();
+    };
+    if state.session(&session_id, &client).is_none() {
+        return (StatusCode::NOT_FOUND, "unknown session").into_response();
+    }
+    match state.remove_session(&session_id) {
+        Some(_) => StatusCode::OK.into_response(),
+        None => (StatusCode::NOT_FOUND, "unknown session").into_response(),
+    }
+}
+
+/// Liveness: the server answers HTTP requests.
+async fn handle_healthz() -> &'static str {
+    "ok\n"
+}
+
+/// Readiness: one line per check, and 503 if any failed.
+async fn handle_readyz(State(state): State<AppState>) -> Response {
+    let checks = health::readiness(&state.context.config, &state.context.auth_manager).await;
+    let status = if checks.iter().all(|check| check.result.is_ok()) {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body: String = checks
+        .iter()
+        .map(|check| match &check.result {
+            Ok(()) => format!("{}: ok\n", check.name),
+            Err(e) => format!("{}: {e}\n", check.name),
+        })
+        .collect();
+    (status, body).into_response()
+}
+
+async fn handle_metrics(State(state): State<AppState>) -> Response {
+    let active_sessions = lock(&state.sessions).len();
+    (
+        [("content-type", "text/plain; version=0.0.4")],
+        state.metrics.render(active_sessions),
+    )
+        .into_response()
+}
+
+impl AppState {
+    /// Checks the origin and the bearer token of a request.
+    fn authorize(&self, headers: &HeaderMap, peer: &Peer) -> Result<Client, Refusal> {
+        if !origin_allowed(headers, &self.host) {
+            return Err(Refusal::Origin);
+        }
+        let authorization = headers
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        match self.security.authenticate(authorization) {
+            Ok(name) => Ok(Client {
+                identity: peer.identity(name.as_deref()),
+                principal: name.or_else(|| peer.certificate.clone()),
+            }),
+            Err(e) => {
+                warn!("refused MCP request from {}: {e}", peer.addr);
+                Err(Refusal::Auth(e))
+            }
+        }
+    }
+
+    /// The session, if `client` owns it.
+    fn session(&self, session_id: &str, client: &Client) -> Option<Arc<HttpSession>> {
+        lock(&self.sessions)
+            .get(session_id)
+            .filter(|session| session.owner == client.principal)
+            .cloned()
+    }
+
+    /// Dropping the session ends its message processor.
+    fn remove_session(&self, session_id: &str) -> Option<Arc<HttpSession>> {
+        let session = lock(&self.sessions).remove(session_id);
+        if session.is_some() {
+            info!("MCP session {session_id} ended");
+        }
+        session
+    }
+
+    fn create_session(&self, client: Client) -> (String, Arc<HttpSession>) {
+        let session_id = Uuid::new_v4().to_string();
+        let (incoming_tx, incoming_rx) = mpsc::channel::<JSONRPCMessage>(CHANNEL_CAPACITY);
+        let (outgoing_tx, mut outgoing_rx) = mpsc::unbounded_channel::<OutgoingMessage>();
+        let session = Arc::new(HttpSession {
+            owner: client.principal,
+            incoming_tx,
+            streams: Mutex::default(),
+            in_flight: Mutex::default(),
+        });
+        self.metrics.session_started();
+
+        let span = info_span!("mcp_client", client = %client.identity, session = %session_id);
+        tokio::spawn(
+            process_messages(self.context.clone(), incoming_rx, outgoing_tx)
+                .instrument(span.clone()),
+        );
+        // Task: route outgoing messages to the session's streams. It holds
+        // the session weakly so that removing the session ends it.
+        let weak_session = Arc::downgrade(&session);
+        let metrics = self.metrics.clone();
+        tokio::spawn(async move {
+            while let Some(outgoing_message) = outgoing_rx.recv().await {
+                let Some(session) = weak_session.upgrade() else {
+                    break;
+                };
+                let msg: JSONRPCMessage = outgoing_message.into();
+                let answers = match &msg {
+                    JSONRPCMessage::Response(response) => Some(&response.id),
+                    JSONRPCMessage::Error(error) => Some(&error.id),
+                    JSONRPCMessage::Request(_) | JSONRPCMessage::Notification(_) => None,
+                };
+                if let Some(in_flight) = answers.and_then(|id| lock(&session.in_flight).remove(id))
+                {
+                    metrics.answered(in_flight, &msg);
+                }
+                match serde_json::to_string(&msg) {
+                    Ok(data) => lock(&session.streams).deliver(&msg, data),
+                    Err(e) => error!("Failed to serialize JSONRPCMessage: {e}"),
+                }
+            }
+        });
+
+        lock(&self.sessions).insert(session_id.clone(), session.clone());
+        info!(parent: &span, "MCP session {session_id} started");
+        (session_id, session)
+    }
+}
+
+/// Why a request was turned away before reaching its session.
+enum Refusal {
+    Origin,
+    Auth(AuthError),
+}
+
+impl IntoResponse for Refusal {
+    fn into_response(self) -> Response {
+        match self {
+            Refusal::Origin => (StatusCode::FORBIDDEN, "origin not allowed").into_response(),
+            Refusal::Auth(e) => (
+                StatusCode::UNAUTHORIZED,
+                [("www-authenticate", "Bearer")],
+                e.to_string(),
+            )
+                .into_response(),
+        }
+    }
+}
+
+fn event_stream(
+    mut rx: mpsc::UnboundedReceiver<StreamEvent>,
+) -> Sse<impl futures::Stream<Item = Result<Event, Infallible>>> {
+    let events = futures::stream::poll_fn(move |cx| rx.poll_recv(cx))
+        .map(|event| Ok(Event::default().id(event.id.to_string()).data(event.data)));
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+fn session_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(SESSION_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Browsers send an `Origin`; only pages served from this machine or the
+/// host the server listens on may talk to it, which stops other websites
+/// from reaching a local server through DNS rebinding.
+pub(crate) fn origin_allowed(headers: &HeaderMap, host: &str) -> bool {
+    let Some(origin) = headers.get("origin") else {
+        return true;
+    };
+    let Some(origin_host) = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.split_once("://"))
+        .map(|(_, rest)| rest)
+    else {
+        return false;
+    };
+    let origin_host = match origin_host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => origin_host.split(':').next().unwrap_or_default(),
+    };
+    [
+        "localhost",
+        "127.0.0.1",
+        "::1",
+        host.trim_matches(['[', ']']),
+    ]
+    .contains(&origin_host)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use axum::http::HeaderValue;
+    use mcp_types::JSONRPC_VERSION;
+    use mcp_types::JSONRPCNotification;
+    use mcp_types::JSONRPCResponse;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn response(id: i64) -> JSONRPCMessage {
+        JSONRPCMessage::Response(JSONRPCResponse {
+            jsonrpc: JSONRPC_VERSION.into(),
+            id: RequestId::Integer(id),
+            result: json!({}),
+        })
+    }
+
+    fn notification() -> JSONRPCMessage {
+        JSONRPCMessage::Notification(JSONRPCNotification {
+            jsonrpc: JSONRPC_VERSION.into(),
+            method: "codex/event".into(),
+            params: None,
+        })
+    }
+
+    fn drain(rx: &mut mpsc::UnboundedReceiver<StreamEvent>) -> Vec<String> {
+        let mut data = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            data.push(event.data);
+        }
+        data
+    }
+
+    #[test]
+    fn routes_responses_to_their_request_stream() {
+        let mut streams = Streams::default();
+        let (request_tx, mut request_rx) = mpsc::unbounded_channel();
+        let (listener_tx, mut listener_rx) = mpsc::unbounded_channel();
+        streams.pending.insert(RequestId::Integer(1), request_tx);
+        streams.listen(listener_tx, None);
+
+        streams.deliver(&notification(), "progress".into());
+        streams.deliver(&response(1), "result".into());
+
+        assert_eq!(drain(&mut request_rx), vec!["result"]);
+        assert_eq!(drain(&mut listener_rx), vec!["progress"]);
+        assert!(streams.pending.is_empty());
+    }
+
+    #[test]
+    fn replays_missed_events_to_a_reconnecting_client() {
+        let mut streams = Streams::default();
+        let (request_tx, request_rx) = mpsc::unbounded_channel();
+        streams.pending.insert(RequestId::Integer(1), request_tx);
+
+        streams.deliver(&notification(), "progress".into());
+        // The client drops its stream while the call is still running.
+        drop(request_rx);
+        streams.deliver(&response(1), "result".into());
+
+        let (listener_tx, mut listener_rx) = mpsc::unbounded_channel();
+        streams.listen(listener_tx, Some(0));
+        assert_eq!(drain(&mut listener_rx), vec!["result"]);
+
+        let (listener_tx, mut listener_rx) = mpsc::unbounded_channel();
+        streams.listen(listener_tx, None);
+        assert_eq!(drain(&mut listener_rx), Vec::<String>::new());
+    }
+
+    #[test]
+    fn refuses_other_origins() {
+        let headers = |origin: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("origin", HeaderValue::from_str(origin).unwrap());
+            headers
+        };
+
+        assert!(origin_allowed(&HeaderMap::new(), "127.0.0.1"));
+        assert!(origin_allowed(
+            &headers("http://localhost:3000"),
+            "127.0.0.1"
+        ));
+        assert!(origin_allowed(&headers("http://[::1]:3000"), "127.0.0.1"));
+        assert!(origin_allowed(&headers("https://devbox:8443"), "devbox"));
+        assert!(!origin_allowed(
+            &headers("https://evil.example"),
+            "127.0.0.1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn serves_sessions_over_http() {
+        let codex_home = tempfile::TempDir::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}{ENDPOINT_PATH}", listener.local_addr().unwrap());
+        tokio::spawn(serve_http(
+            listener,
+            "127.0.0.1".to_string(),
+            Arc::default(),
+            ServerContext::for_tests(codex_home.path()),
+        ));
+        let client = reqwest::Client::new();
+
+        let response = client
+            .post(&url)
+            .body(crate::transport::tests::initialize_request())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let session_id = response.headers()[SESSION_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = response.text().await.unwrap();
+        assert!(body.contains("id: 0\n"), "{body}");
+        assert!(body.contains("\"serverInfo\""), "{body}");
+
+        let initialized = serde_json::to_string(&notification()).unwrap();
+        let response = client
+            .post(&url)
+            .body(initialized.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        let response = client
+            .post(&url)
+            .header(SESSION_ID_HEADER, &session_id)
+            .body(initialized.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+
+        let response = client
+            .delete(&url)
+            .header(SESSION_ID_HEADER, &session_id)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let response = client
+            .post(&url)
+            .header(SESSION_ID_HEADER, &session_id)
+            .body(initialized)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let root = url.trim_end_matches(ENDPOINT_PATH);
+        let response = client.get(format!("{root}/healthz")).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let metrics = client
+            .get(format!("{root}/metrics"))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(
+            metrics.contains("mcp_requests_total{method=\"initialize\"} 1\n"),
+            "{metrics}"
+        );
+        assert!(metrics.contains("mcp_sessions_active 0\n"), "{metrics}");
+    }
+
+    #[tokio::test]
+    async fn sessions_belong_to_the_client_that_created_them() {
+        let codex_home = tempfile::TempDir::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}{ENDPOINT_PATH}", listener.local_addr().unwrap());
+        tokio::spawn(serve_http(
+            listener,
+            "127.0.0.1".to_string(),
+            Arc::new(Security::with_tokens(&[
+                ("ci", "secret-ci"),
+                ("ops", "secret-ops"),
+            ])),
+            ServerContext::for_tests(codex_home.path()),
+        ));
+        let client = reqwest::Client::new();
+        let initialize = crate::transport::tests::initialize_request();
+
+        let response = client
+            .post(&url)
+            .body(initialize.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let response = client
+            .post(&url)
+            .bearer_auth("wrong")
+            .body(initialize.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        let response = client
+            .post(&url)
+            .bearer_auth("secret-ci")
+            .body(initialize)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let session_id = response.headers()[SESSION_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let initialized = serde_json::to_string(&notification()).unwrap();
+        let response = client
+            .post(&url)
+            .bearer_auth("secret-ops")
+            .header(SESSION_ID_HEADER, &session_id)
+            .body(initialized.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        let response = client
+            .post(&url)
+            .bearer_auth("secret-ci")
+            .header(SESSION_ID_HEADER, &session_id)
+            .body(initialized)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+    }
+}
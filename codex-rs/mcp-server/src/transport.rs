@@ -0,0 +1,444 @@
+//! Transports that carry JSON-RPC messages between MCP clients and the
+//! server: stdin/stdout for a single client launched by its parent, and TCP
+//! or WebSocket listeners that give each connection its own session so the
+//! server can run as a shared network service.
+
+use std::io::Result as IoResult;
+use std::sync::Arc;
+
+use futures::SinkExt;
+use futures::StreamExt;
+use mcp_types::JSONRPCMessage;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::handshake::server::ErrorResponse;
+use tokio_tungstenite::tungstenite::handshake::server::Request;
+use tokio_tungstenite::tungstenite::handshake::server::Response;
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tracing::Instrument;
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+use tracing::info_span;
+use tracing::warn;
+
+use crate::CHANNEL_CAPACITY;
+use crate::ServerContext;
+use crate::outgoing_message::OutgoingMessage;
+use crate::process_messages;
+use crate::security::Connection;
+use crate::security::Security;
+use crate::streamable_http::origin_allowed;
+
+/// How the server talks to its clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    /// Newline-delimited JSON on stdin/stdout.
+    Stdio,
+    /// Newline-delimited JSON on each connection to `host:port`.
+    Tcp(String),
+    /// One JSON-RPC message per text frame on each WebSocket connection to
+    /// `host:port`.
+    WebSocket(String),
+    /// MCP Streamable HTTP: messages are POSTed to `/mcp` and answered with
+    /// server-sent events.
+    Http { host: String, port: u16 },
+}
+
+/// Serves one client over newline-delimited JSON until `reader` reaches EOF
+/// and the session's pending messages are written. With `strict`, the first
+/// line that is not a JSON-RPC message ends the session: a network client
+/// that sends anything else, such as the headers of an HTTP request a web
+/// page made to the port, is not speaking MCP.
+pub(crate) async fn serve_lines<R, W>(
+    reader: R,
+    mut writer: W,
+    context: ServerContext,
+    strict: bool,
+) where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (incoming_tx, incoming_rx) = mpsc::channel::<JSONRPCMessage>(CHANNEL_CAPACITY);
+    let (outgoing_tx, mut outgoing_rx) = mpsc::unbounded_channel::<OutgoingMessage>();
+
+    // Task: read lines from the client, push to `incoming_tx`.
+    let reader_handle = tokio::spawn(
+        async move {
+            let mut lines = BufReader::new(reader).lines();
+            while let Some(line) = lines.next_line().await.unwrap_or_default() {
+                match serde_json::from_str::<JSONRPCMessage>(&line) {
+                    Ok(msg) => {
+                        if incoming_tx.send(msg).await.is_err() {
+                            // Receiver gone – nothing left to do.
+                            break;
+                        }
+                    }
+                    Err(e) if strict => {
+                        warn!("Closing the connection after a line that is not JSON-RPC: {e}");
+                        break;
+                    }
+                    Err(e) => error!("Failed to deserialize JSONRPCMessage: {e}"),
+                }
+            }
+
+            debug!("reader finished (EOF)");
+        }
+        .in_current_span(),
+    );
+
+    let processor_handle =
+        tokio::spawn(process_messages(context, incoming_rx, outgoing_tx).in_current_span());
+
+    // Task: write outgoing messages to the client, one per line.
+    let writer_handle = tokio::spawn(
+        async move {
+            while let Some(outgoing_message) = outgoing_rx.recv().await {
+                let msg: JSONRPCMessage = outgoing_message.into();
+                match serde_json::to_string(&msg) {
+                    Ok(mut json) => {
+                        json.push('\n');
+                        if let Err(e) = writer.write_all(json.as_bytes()).await {
+                            error!("Failed to write message: {e}");
+                            break;
+                        }
+                        if let Err(e) = writer.flush().await {
+                            error!("Failed to flush message: {e}");
+                            break;
+                        }
+                    }
+                    Err(e) => error!("Failed to serialize JSONRPCMessage: {e}"),
+                }
+            }
+
+            info!("writer exited (channel closed)");
+        }
+        .in_current_span(),
+    );
+
+    // The typical exit path is the reader hitting EOF which, once it drops
+    // `incoming_tx`, propagates shutdown to the processor and then to the
+    // writer.
+    let _ = tokio::join!(reader_handle, processor_handle, writer_handle);
+}
+
+/// Accepts TCP connections on `listener` and serves each one as a separate
+/// client. Raw TCP has no headers to carry a bearer token, so clients can
+/// only be authenticated by their TLS certificate. Only returns when
+/// accepting fails.
+pub(crate) async fn serve_tcp(
+    listener: TcpListener,
+    security: Arc<Security>,
+    context: ServerContext,
+) -> IoResult<()> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let security = security.clone();
+        let context = context.clone();
+        tokio::spawn(async move {
+            let Connection { io, peer } = match security.handshake(stream, addr).await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("TLS handshake with {addr} failed: {e}");
+                    return;
+                }
+            };
+            let span = info_span!("mcp_client", client = %peer.identity(None));
+            async move {
+                info!("MCP client connected");
+                let (reader, writer) = tokio::io::split(io);
+                serve_lines(reader, writer, context, true).await;
+                info!("MCP client disconnected");
+            }
+            .instrument(span)
+            .await;
+        });
+    }
+}
+
+/// Accepts WebSocket connections on `listener` and serves each one as a
+/// separate client. Upgrades from web pages are refused unless the page was
+/// served from this machine or `host`. Only returns when accepting fails.
+pub(crate) async fn serve_websocket(
+    listener: TcpListener,
+    host: String,
+    security: Arc<Security>,
+    context: ServerContext,
+) -> IoResult<()> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let security = security.clone();
+        let context = context.clone();
+        let host = host.clone();
+        tokio::spawn(async move {
+            match security.handshake(stream, addr).await {
+                Ok(connection) => {
+                    serve_websocket_connection(connection, &host, &security, context).await;
+                }
+                Err(e) => warn!("TLS handshake with {addr} failed: {e}"),
+            }
+        });
+    }
+}
+
+async fn serve_websocket_connection(
+    Connection { io, peer }: Connection,
+    host: &str,
+    security: &Security,
+    context: ServerContext,
+) {
+    // The origin and bearer token come with the upgrade request.
+    let mut client = None;
+    let check_token = |request: &Request, response: Response| {
+        if !origin_allowed(request.headers(), host) {
+            let mut refusal = ErrorResponse::new(Some("origin not allowed".to_string()));
+            *refusal.status_mut() = StatusCode::FORBIDDEN;
+            return Err(refusal);
+        }
+        let authorization = request
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        match security.authenticate(authorization) {
+            Ok(name) => {
+                client = name;
+                Ok(response)
+            }
+            Err(e) => {
+                let mut refusal = ErrorResponse::new(Some(e.to_string()));
+                *refusal.status_mut() = StatusCode::UNAUTHORIZED;
+                Err(refusal)
+            }
+        }
+    };
+    let socket = match tokio_tungstenite::accept_hdr_async(io, check_token).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("WebSocket handshake with {} failed: {e}", peer.addr);
+            return;
+        }
+    };
+    let span = info_span!("mcp_client", client = %peer.identity(client.as_deref()));
+    // Tasks spawned while the span is entered log under it.
+    let (reader_handle, processor_handle, writer_handle) = {
+        let _entered = span.enter();
+        info!("MCP client connected");
+
+        let (mut sink, mut source) = socket.split();
+        let (incoming_tx, incoming_rx) = mpsc::channel::<JSONRPCMessage>(CHANNEL_CAPACITY);
+        let (outgoing_tx, mut outgoing_rx) = mpsc::unbounded_channel::<OutgoingMessage>();
+
+        // Task: read text frames from the client, push to `incoming_tx`. Pings
+        // are answered by the WebSocket implementation.
+        let reader_handle = tokio::spawn(
+            async move {
+                while let Some(frame) = source.next().await {
+                    let text = match frame {
+                        Ok(Message::Text(text)) => text,
+                        Ok(Message::Close(_)) => break,
+                        Ok(_) => continue,
+                        Err(e) => {
+                            error!("Failed to read WebSocket frame: {e}");
+                            break;
+                        }
+                    };
+                    match serde_json::from_str::<JSONRPCMessage>(&text) {
+                        Ok(msg) => {
+                            if incoming_tx.send(msg).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => error!("Failed to deserialize JSONRPCMessage: {e}"),
+                    }
+                }
+
+                debug!("WebSocket reader finished");
+            }
+            .in_current_span(),
+        );
+
+        let processor_handle =
+            tokio::spawn(process_messages(context, incoming_rx, outgoing_tx).in_current_span());
+
+        // Task: write outgoing messages to the client, one per text frame.
+        let writer_handle = tokio::spawn(
+            async move {
+                while let Some(outgoing_message) = outgoing_rx.recv().await {
+                    let msg: JSONRPCMessage = outgoing_message.into();
+                    match serde_json::to_string(&msg) {
+                        Ok(json) => {
+                            if let Err(e) = sink.send(Message::text(json)).await {
+                                error!("Failed to write WebSocket frame: {e}");
+                                break;
+                            }
+                        }
+                        Err(e) => error!("Failed to serialize JSONRPCMessage: {e}"),
+                    }
+                }
+                let _ = sink.close().await;
+
+                info!("WebSocket writer exited (channel closed)");
+            }
+            .in_current_span(),
+        );
+
+        (reader_handle, processor_handle, writer_handle)
+    };
+    let _ = tokio::join!(reader_handle, processor_handle, writer_handle);
+    info!(parent: &span, "MCP client disconnected");
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use mcp_types::JSONRPC_VERSION;
+    use mcp_types::JSONRPCRequest;
+    use mcp_types::JSONRPCResponse;
+    use mcp_types::RequestId;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tempfile::TempDir;
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::Error as WsError;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::http::HeaderValue;
+
+    pub(crate) fn initialize_request() -> String {
+        serde_json::to_string(&JSONRPCMessage::Request(JSONRPCRequest {
+            jsonrpc: JSONRPC_VERSION.into(),
+            id: RequestId::Integer(7),
+            method: "initialize".into(),
+            params: Some(json!({
+                "capabilities": {},
+                "clientInfo": { "name": "test", "version": "0.0.0" },
+                "protocolVersion": mcp_types::MCP_SCHEMA_VERSION,
+            })),
+        }))
+        .unwrap()
+    }
+
+    fn assert_initialized(message: &str) {
+        let JSONRPCMessage::Response(JSONRPCResponse { id, result, .. }) =
+            serde_json::from_str(message).unwrap()
+        else {
+            panic!("expected a response, got {message}");
+        };
+        assert_eq!(id, RequestId::Integer(7));
+        assert_eq!(result["serverInfo"]["name"], "codex-mcp-server");
+    }
+
+    #[tokio::test]
+    async fn serves_tcp_clients() {
+        let codex_home = TempDir::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_tcp(
+            listener,
+            Arc::default(),
+            ServerContext::for_tests(codex_home.path()),
+        ));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        writer
+            .write_all(format!("{}\n", initialize_request()).as_bytes())
+            .await
+            .unwrap();
+        let line = BufReader::new(reader).lines().next_line().await.unwrap();
+
+        assert_initialized(&line.unwrap());
+    }
+
+    #[tokio::test]
+    async fn serves_websocket_clients() {
+        let codex_home = TempDir::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_websocket(
+            listener,
+            "127.0.0.1".to_string(),
+            Arc::default(),
+            ServerContext::for_tests(codex_home.path()),
+        ));
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}"))
+            .await
+            .unwrap();
+        socket
+            .send(Message::text(initialize_request()))
+            .await
+            .unwrap();
+        let Some(Ok(Message::Text(text))) = socket.next().await else {
+            panic!("expected a text frame");
+        };
+
+        assert_initialized(&text);
+    }
+
+    #[tokio::test]
+    async fn tcp_connection_closes_on_a_line_that_is_not_json_rpc() {
+        let codex_home = TempDir::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_tcp(
+            listener,
+            Arc::default(),
+            ServerContext::for_tests(codex_home.path()),
+        ));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        writer
+            .write_all(
+                format!(
+                    "POST / HTTP/1.1\r\nContent-Type: text/plain\r\n\r\n{}\n",
+                    initialize_request()
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let line = BufReader::new(reader).lines().next_line().await;
+
+        // The server hangs up (EOF, or a reset because the rest of the
+        // request was never read) instead of answering the JSON-RPC body.
+        assert!(!matches!(line, Ok(Some(_))), "unexpected answer: {line:?}");
+    }
+
+    #[tokio::test]
+    async fn websocket_upgrades_from_foreign_origins_are_forbidden() {
+        let codex_home = TempDir::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_websocket(
+            listener,
+            "127.0.0.1".to_string(),
+            Arc::default(),
+            ServerContext::for_tests(codex_home.path()),
+        ));
+
+        let mut request = format!("ws://{addr}").into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("origin", HeaderValue::from_static("https://evil.example"));
+        match tokio_tungstenite::connect_async(request).await {
+            Err(WsError::Http(response)) => assert_eq!(response.status(), StatusCode::FORBIDDEN),
+            Err(e) => panic!("expected a 403, got {e}"),
+            Ok(_) => panic!("expected a 403, the upgrade succeeded"),
+        }
+
+        let mut request = format!("ws://{addr}").into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("origin", HeaderValue::from_static("http://localhost:3000"));
+        assert!(tokio_tungstenite::connect_async(request).await.is_ok());
+    }
+}
@@ -22,6 +22,7 @@ use codex_protocol::mcp_protocol::SendUserMessageParams;
 use codex_protocol::mcp_protocol::SendUserTurnParams;
 
 use mcp_types::CallToolRequestParams;
+use mcp_types::CancelledNotificationParams;
 use mcp_types::ClientCapabilities;
 use mcp_types::Implementation;
 use mcp_types::InitializeRequestParams;
@@ -132,6 +133,10 @@ impl McpProcess {
                 id: RequestId::Integer(request_id),
                 result: json!({
                     "capabilities": {
+                        "prompts": {},
+                        "resources": {
+                            "listChanged": true
+                        },
                         "tools": {
                             "listChanged": true
                         },
@@ -294,6 +299,23 @@ impl McpProcess {
         .await
     }
 
+    /// Send a `notifications/cancelled` for the request with `request_id`.
+    pub async fn send_cancelled_notification(
+        &mut self,
+        request_id: RequestId,
+    ) -> anyhow::Result<()> {
+        let params = CancelledNotificationParams {
+            reason: None,
+            request_id,
+        };
+        self.send_jsonrpc_message(JSONRPCMessage::Notification(JSONRPCNotification {
+            jsonrpc: JSONRPC_VERSION.into(),
+            method: mcp_types::CancelledNotification::METHOD.into(),
+            params: Some(serde_json::to_value(params)?),
+        }))
+        .await
+    }
+
     async fn send_jsonrpc_message(&mut self, message: JSONRPCMessage) -> anyhow::Result<()> {
         eprintln!("writing message to stdin: {message:?}");
         let payload = serde_json::to_string(&message)?;
@@ -418,7 +440,17 @@ impl McpProcess {
     pub async fn read_stream_until_legacy_task_complete_notification(
         &mut self,
     ) -> anyhow::Result<JSONRPCNotification> {
-        eprintln!("in read_stream_until_legacy_task_complete_notification()");
+        self.read_stream_until_legacy_event_notification("task_complete")
+            .await
+    }
+
+    /// Reads notifications until a legacy event of type `event_type` is
+    /// observed: Method "codex/event" with params.msg.type == `event_type`.
+    pub async fn read_stream_until_legacy_event_notification(
+        &mut self,
+        event_type: &str,
+    ) -> anyhow::Result<JSONRPCNotification> {
+        eprintln!("in read_stream_until_legacy_event_notification({event_type})");
 
         loop {
             let message = self.read_jsonrpc_message().await?;
@@ -430,7 +462,7 @@ impl McpProcess {
                                 .get("msg")
                                 .and_then(|m| m.get("type"))
                                 .and_then(|t| t.as_str())
-                                == Some("task_complete")
+                                == Some(event_type)
                         } else {
                             false
                         }
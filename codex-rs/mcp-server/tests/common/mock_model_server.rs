@@ -11,20 +11,32 @@ use wiremock::matchers::path;
 /// Create a mock server that will provide the responses, in order, for
 /// requests to the `/v1/chat/completions` endpoint.
 pub async fn create_mock_chat_completions_server(responses: Vec<String>) -> MockServer {
+    let num_calls = responses.len() as u64;
+    mount_chat_completions(responses, Some(num_calls)).await
+}
+
+/// Like [`create_mock_chat_completions_server`], but without verifying that
+/// every response was requested, for tests that stop a turn part-way.
+pub async fn create_mock_chat_completions_server_unchecked(responses: Vec<String>) -> MockServer {
+    mount_chat_completions(responses, None).await
+}
+
+async fn mount_chat_completions(responses: Vec<String>, expected_calls: Option<u64>) -> MockServer {
     let server = MockServer::start().await;
 
-    let num_calls = responses.len();
     let seq_responder = SeqResponder {
         num_calls: AtomicUsize::new(0),
         responses,
     };
 
-    Mock::given(method("POST"))
+    let mock = Mock::given(method("POST"))
         .and(path("/v1/chat/completions"))
-        .respond_with(seq_responder)
-        .expect(num_calls as u64)
-        .mount(&server)
-        .await;
+        .respond_with(seq_responder);
+    let mock = match expected_calls {
+        Some(expected_calls) => mock.expect(expected_calls),
+        None => mock,
+    };
+    mock.mount(&server).await;
 
     server
 }
@@ -5,6 +5,7 @@ use std::path::Path;
 
 use codex_core::protocol::TurnAbortReason;
 use codex_core::spawn::CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR;
+use codex_mcp_server::CodexToolCallParam;
 use codex_protocol::mcp_protocol::AddConversationListenerParams;
 use codex_protocol::mcp_protocol::InterruptConversationParams;
 use codex_protocol::mcp_protocol::InterruptConversationResponse;
@@ -19,6 +20,7 @@ use tokio::time::timeout;
 
 use mcp_test_support::McpProcess;
 use mcp_test_support::create_mock_chat_completions_server;
+use mcp_test_support::create_mock_chat_completions_server_unchecked;
 use mcp_test_support::create_shell_sse_response;
 use mcp_test_support::to_response;
 
@@ -136,6 +138,121 @@ async fn shell_command_interruption() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cancelled_codex_tool_call() {
+    if std::env::var(CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR).is_ok() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    if let Err(err) = cancelled_codex_tool_call().await {
+        panic!("failure: {err}");
+    }
+}
+
+async fn cancelled_codex_tool_call() -> anyhow::Result<()> {
+    let tmp = TempDir::new()?;
+    let codex_home = tmp.path().join("codex_home");
+    std::fs::create_dir(&codex_home)?;
+    let working_directory = tmp.path().join("workdir");
+    std::fs::create_dir(&working_directory)?;
+
+    // Whether the model is asked again before the cancel lands depends on
+    // timing, so the number of requests is not checked.
+    let server = create_mock_chat_completions_server_unchecked(vec![create_shell_sse_response(
+        vec!["sleep".to_string(), "10".to_string()],
+        Some(&working_directory),
+        Some(10_000),
+        "call_sleep",
+    )?])
+    .await;
+    create_config_toml(&codex_home, server.uri())?;
+
+    let mut mcp = McpProcess::new(&codex_home).await?;
+    timeout(DEFAULT_READ_TIMEOUT, mcp.initialize()).await??;
+
+    let tool_call_id = mcp
+        .send_codex_tool_call(CodexToolCallParam {
+            cwd: Some(working_directory.to_string_lossy().into_owned()),
+            prompt: "run a sleep command".to_string(),
+            ..Default::default()
+        })
+        .await?;
+
+    timeout(
+        DEFAULT_READ_TIMEOUT,
+        mcp.read_stream_until_legacy_event_notification("task_started"),
+    )
+    .await??;
+
+    mcp.send_cancelled_notification(RequestId::Integer(tool_call_id))
+        .await?;
+    let error = timeout(
+        DEFAULT_READ_TIMEOUT,
+        mcp.read_stream_until_error_message(RequestId::Integer(tool_call_id)),
+    )
+    .await??;
+    assert_eq!(error.error.code, -32800);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_codex_tool_call_cancelled_while_starting() {
+    if std::env::var(CODEX_SANDBOX_NETWORK_DISABLED_ENV_VAR).is_ok() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    if let Err(err) = codex_tool_call_cancelled_while_starting().await {
+        panic!("failure: {err}");
+    }
+}
+
+async fn codex_tool_call_cancelled_while_starting() -> anyhow::Result<()> {
+    let tmp = TempDir::new()?;
+    let codex_home = tmp.path().join("codex_home");
+    std::fs::create_dir(&codex_home)?;
+    let working_directory = tmp.path().join("workdir");
+    std::fs::create_dir(&working_directory)?;
+
+    let server = create_mock_chat_completions_server_unchecked(vec![create_shell_sse_response(
+        vec!["sleep".to_string(), "10".to_string()],
+        Some(&working_directory),
+        Some(10_000),
+        "call_sleep",
+    )?])
+    .await;
+    create_config_toml(&codex_home, server.uri())?;
+
+    let mut mcp = McpProcess::new(&codex_home).await?;
+    timeout(DEFAULT_READ_TIMEOUT, mcp.initialize()).await??;
+
+    // Cancelled before its session has been configured.
+    let tool_call_id = mcp
+        .send_codex_tool_call(CodexToolCallParam {
+            cwd: Some(working_directory.to_string_lossy().into_owned()),
+            prompt: "run a sleep command".to_string(),
+            ..Default::default()
+        })
+        .await?;
+    mcp.send_cancelled_notification(RequestId::Integer(tool_call_id))
+        .await?;
+
+    let error = timeout(
+        DEFAULT_READ_TIMEOUT,
+        mcp.read_stream_until_error_message(RequestId::Integer(tool_call_id)),
+    )
+    .await??;
+    assert_eq!(error.error.code, -32800);
+
+    Ok(())
+}
+
 // ---------------------------------------------------------------------------
 // Helpers
 // ---------------------------------------------------------------------------
@@ -1,6 +1,7 @@
 use std::path::Path;
 use std::time::Duration;
 
+use codex_login::credential_store::list_credentials;
 use codex_login::login_with_api_key;
 use codex_protocol::mcp_protocol::CancelLoginChatGptParams;
 use codex_protocol::mcp_protocol::CancelLoginChatGptResponse;
@@ -44,7 +45,10 @@ async fn logout_chatgpt_removes_auth() {
     let codex_home = TempDir::new().unwrap_or_else(|e| panic!("create tempdir: {e}"));
     create_config_toml(codex_home.path()).expect("write config.toml");
     login_with_api_key(codex_home.path(), "sk-test-key").expect("seed api key");
-    assert!(codex_home.path().join("auth.json").exists());
+    assert_eq!(
+        list_credentials(codex_home.path()).expect("list credentials"),
+        vec!["auth.json"]
+    );
 
     let mut mcp = McpProcess::new(codex_home.path())
         .await
@@ -68,7 +72,9 @@ async fn logout_chatgpt_removes_auth() {
     let _ok: LogoutChatGptResponse = to_response(resp).expect("deserialize logout response");
 
     assert!(
-        !codex_home.path().join("auth.json").exists(),
+        list_credentials(codex_home.path())
+            .expect("list credentials")
+            .is_empty(),
         "auth.json should be deleted"
     );
 
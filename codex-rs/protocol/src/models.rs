@@ -210,6 +210,9 @@ impl From<Vec<InputItem>> for ResponseInputItem {
                 .filter_map(|c| match c {
                     InputItem::Text { text } => Some(ContentItem::InputText { text }),
                     InputItem::Image { image_url } => Some(ContentItem::InputImage { image_url }),
+                    InputItem::Base64Image { data, mime_type } => Some(ContentItem::InputImage {
+                        image_url: format!("data:{mime_type};base64,{data}"),
+                    }),
                     InputItem::LocalImage { path } => match std::fs::read(&path) {
                         Ok(bytes) => {
                             let mime = mime_guess::from_path(&path)
@@ -246,6 +249,10 @@ pub struct ShellToolCallParams {
     /// This is the maximum time in milliseconds that the command is allowed to run.
     #[serde(alias = "timeout")]
     pub timeout_ms: Option<u64>,
+    /// Output the command may write before it is killed. Can only lower the
+    /// configured limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_bytes: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub with_escalated_permissions: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -360,6 +367,7 @@ mod tests {
                 command: vec!["ls".to_string(), "-l".to_string()],
                 workdir: Some("/tmp".to_string()),
                 timeout_ms: Some(1000),
+                max_output_bytes: None,
                 with_escalated_permissions: None,
                 justification: None,
             },
@@ -12,6 +12,8 @@ use std::time::Duration;
 
 use crate::custom_prompts::CustomPrompt;
 use mcp_types::CallToolResult;
+use mcp_types::GetPromptResult;
+use mcp_types::Prompt as McpPrompt;
 use mcp_types::Tool as McpTool;
 use serde::Deserialize;
 use serde::Serialize;
@@ -36,6 +38,19 @@ pub struct Submission {
     pub op: Op,
 }
 
+/// A JSON schema the final message of a turn has to match.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ResponseFormat {
+    /// Names the schema for the model, e.g. `migration_plan`.
+    pub name: String,
+    pub schema: serde_json::Value,
+    /// Have providers that support it enforce the schema while generating.
+    /// OpenAI requires every property to be `required` and
+    /// `additionalProperties: false` in strict mode.
+    #[serde(default)]
+    pub strict: bool,
+}
+
 /// Submission operation
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -43,7 +58,9 @@ pub struct Submission {
 #[non_exhaustive]
 pub enum Op {
     /// Abort current task.
-    /// This server sends [`EventMsg::TurnAborted`] in response.
+    /// The model stream is closed, running commands are killed and MCP tool
+    /// calls are cancelled; what the turn produced so far is kept in the
+    /// history. This server sends [`EventMsg::TurnAborted`] in response.
     Interrupt,
 
     /// Input from the user
@@ -77,6 +94,11 @@ pub enum Op {
 
         /// Will only be honored if the model is configured to use reasoning.
         summary: ReasoningSummaryConfig,
+
+        /// When set, the final message of the turn must be JSON matching this
+        /// schema; the model is asked again when it does not.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        response_format: Option<ResponseFormat>,
     },
 
     /// Override parts of the persistent turn context for subsequent turns.
@@ -109,6 +131,12 @@ pub enum Op {
         /// Updated reasoning summary preference (honored only for reasoning-capable models).
         #[serde(skip_serializing_if = "Option::is_none")]
         summary: Option<ReasoningSummaryConfig>,
+
+        /// Turn dry-run mode on or off. In dry-run mode, tools that change
+        /// remote systems return the request they would send instead of
+        /// sending it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        dry_run: Option<bool>,
     },
 
     /// Approve a command execution
@@ -127,6 +155,15 @@ pub enum Op {
         decision: ReviewDecision,
     },
 
+    /// Approve going over a budget, in response to
+    /// [`EventMsg::BudgetApprovalRequest`].
+    BudgetApproval {
+        /// The id of the submission we are approving
+        id: String,
+        /// The user's decision in response to the request.
+        decision: ReviewDecision,
+    },
+
     /// Append an entry to the persistent cross-session message history.
     ///
     /// Note the entry is not guaranteed to be logged if the user has
@@ -147,17 +184,121 @@ pub enum Op {
     /// Reply is delivered via `EventMsg::McpListToolsResponse`.
     ListMcpTools,
 
+    /// Start an MCP server, replacing the running server of the same name,
+    /// and offer its tools to the model from the next turn.
+    /// Reply is delivered via `EventMsg::McpServerStatus`.
+    AddMcpServer {
+        name: String,
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        env: Option<HashMap<String, String>>,
+    },
+
+    /// Stop an MCP server and withdraw its tools.
+    /// Reply is delivered via `EventMsg::McpServerStatus`.
+    RemoveMcpServer { name: String },
+
     /// Request the list of available custom prompts.
     ListCustomPrompts,
 
+    /// Request the prompts offered by MCP servers.
+    /// Reply is delivered via `EventMsg::McpListPromptsResponse`.
+    ListMcpPrompts,
+
+    /// Render a prompt of an MCP server with the given arguments.
+    /// Reply is delivered via `EventMsg::McpGetPromptResponse`.
+    GetMcpPrompt {
+        server: String,
+        name: String,
+        #[serde(default)]
+        arguments: HashMap<String, String>,
+    },
+
     /// Request the agent to summarize the current conversation context.
     /// The agent will use its existing context (either conversation history or previous response id)
     /// to generate a summary which will be returned as an AgentMessage event.
     Compact,
+    /// Rewind the conversation by dropping the last `num_turns` user turns,
+    /// and everything that followed them, from the history. Later input
+    /// continues on a new branch forked from that point; `num_turns: 0` forks
+    /// at the current turn. The abandoned turns stay in the rollout and can be
+    /// returned to with [`Op::SwitchBranch`].
+    /// Reply is delivered via `EventMsg::ConversationBranch`.
+    Rewind { num_turns: u32 },
+
+    /// Continue the conversation on a branch left by an earlier
+    /// [`Op::Rewind`], restoring the history it had at that point.
+    /// Reply is delivered via `EventMsg::ConversationBranch`.
+    SwitchBranch { branch_id: u32 },
+
+    /// Render the current conversation as a transcript. When `path` is set
+    /// the transcript is also written there, relative to the session's cwd.
+    /// Reply is delivered via `EventMsg::ConversationExport`.
+    ExportConversation {
+        format: ExportFormat,
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+
+    /// Run tool calls one after the other without the model, e.g. for a
+    /// scheduled report. The calls go through the same hooks, approvals and
+    /// dry-run handling as calls the model makes. A failed call ends the
+    /// pipeline with [`EventMsg::Error`]; [`EventMsg::TaskComplete`] carries
+    /// the outputs of the calls that ran.
+    RunToolPipeline { steps: Vec<ToolPipelineStep> },
+
+    /// Run the workflow `name` from `$CODEX_HOME/workflows/<name>.toml`, with
+    /// `inputs` overriding its input defaults. Each step is reported with
+    /// [`EventMsg::BackgroundEvent`]; [`EventMsg::TaskComplete`] carries a
+    /// summary of the steps.
+    RunWorkflow {
+        name: String,
+        #[serde(default)]
+        inputs: HashMap<String, String>,
+    },
+
     /// Request to shut down codex instance.
     Shutdown,
 }
 
+/// One tool call of an [`Op::RunToolPipeline`].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ToolPipelineStep {
+    /// Name of the tool as the model would call it, e.g. `kusto_query` or an
+    /// MCP tool's `server__tool`.
+    pub tool: String,
+    /// Arguments of the call; none when omitted.
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+/// Format of a transcript rendered by [`Op::ExportConversation`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Display, TS)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ExportFormat {
+    /// Markdown with tool outputs collapsed in `<details>` blocks.
+    #[default]
+    Markdown,
+    /// A standalone HTML page.
+    Html,
+    /// The items of the conversation as JSON, as sent to the model.
+    Json,
+}
+
+impl ExportFormat {
+    /// File extension conventionally used for the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
 /// Determines the conditions under which the user is consulted to approve
 /// running the command proposed by Codex.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Display, TS)]
@@ -385,6 +526,13 @@ pub enum InputItem {
     LocalImage {
         path: std::path::PathBuf,
     },
+
+    /// Base64-encoded image bytes, e.g. a pasted screenshot.
+    Base64Image {
+        data: String,
+        /// Media type of the image, e.g. `image/png`.
+        mime_type: String,
+    },
 }
 
 /// Event Queue Entry - events from agent
@@ -481,6 +629,13 @@ pub enum EventMsg {
     /// List of custom prompts available to the agent.
     ListCustomPromptsResponse(ListCustomPromptsResponseEvent),
 
+    /// List of prompts offered by MCP servers (response to
+    /// `Op::ListMcpPrompts`).
+    McpListPromptsResponse(McpListPromptsResponseEvent),
+
+    /// A rendered MCP prompt (response to `Op::GetMcpPrompt`).
+    McpGetPromptResponse(McpGetPromptResponseEvent),
+
     PlanUpdate(UpdatePlanArgs),
 
     TurnAborted(TurnAbortedEvent),
@@ -489,13 +644,79 @@ pub enum EventMsg {
     ShutdownComplete,
 
     ConversationHistory(ConversationHistoryResponseEvent),
+
+    /// The conversation moved to another branch in response to
+    /// [`Op::Rewind`] or [`Op::SwitchBranch`].
+    ConversationBranch(ConversationBranchEvent),
+
+    /// A transcript rendered in response to [`Op::ExportConversation`].
+    ConversationExport(ConversationExportEvent),
+
+    /// A command was started in the background with the `background_start`
+    /// tool.
+    BackgroundJobStarted(BackgroundJobStartedEvent),
+
+    /// A background command exited or was killed.
+    BackgroundJobEnded(BackgroundJobEndedEvent),
+
+    /// A tool call's output was too large to send to the model and was
+    /// saved to a file, which the model can read with `read_artifact`.
+    ArtifactCreated(ArtifactCreatedEvent),
+
+    /// An MCP server was started, restarted, stopped or failed.
+    McpServerStatus(McpServerStatusEvent),
+
+    /// A model provider kept failing, was replaced by the failover provider,
+    /// or recovered.
+    ProviderHealth(ProviderHealthEvent),
+
+    /// A `[[model_routes]]` entry chose the model for the next request.
+    ModelRouted(ModelRoutedEvent),
+
+    /// Usage crossed the warning threshold of a budget.
+    BudgetWarning(BudgetEvent),
+
+    /// A budget is used up. The next request to the model is only sent if
+    /// the client answers with an approving [`Op::BudgetApproval`];
+    /// otherwise the turn ends with an error.
+    BudgetApprovalRequest(BudgetEvent),
 }
 
 // Individual event payload types matching each `EventMsg` variant.
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct ErrorEvent {
     pub message: String,
+    /// Stable machine-readable code such as `rate_limit.usage_limit_reached`.
+    /// The part before the dot is the [`ErrorCategory`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<ErrorCategory>,
+    /// Whether trying the same thing again may succeed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub retryable: bool,
+}
+
+/// Broad kind of an error, so clients can react to errors without parsing
+/// their messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorCategory {
+    /// Missing or rejected credentials.
+    Auth,
+    /// A usage limit, budget or provider rate limit was hit.
+    RateLimit,
+    Timeout,
+    NotFound,
+    /// The request was malformed or not supported.
+    Validation,
+    /// The sandbox denied or killed a command.
+    Sandbox,
+    /// The model provider failed or could not be reached.
+    Provider,
+    Internal,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -693,6 +914,25 @@ pub struct ConversationHistoryResponseEvent {
     pub entries: Vec<ResponseItem>,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConversationBranchEvent {
+    /// Branch the conversation now continues on. The initial branch is 0.
+    pub branch_id: u32,
+    /// Branch the conversation was on before this change.
+    pub previous_branch_id: u32,
+    /// Number of user turns in the history of `branch_id`.
+    pub num_turns: usize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConversationExportEvent {
+    pub format: ExportFormat,
+    /// The rendered transcript.
+    pub content: String,
+    /// Where the transcript was written, if a path was requested.
+    pub path: Option<PathBuf>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ExecCommandBeginEvent {
     /// Identifier so this can be paired with the ExecCommandEnd event.
@@ -723,6 +963,154 @@ pub struct ExecCommandEndEvent {
     pub formatted_output: String,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BackgroundJobStartedEvent {
+    /// Identifier used by the `background_*` tools and paired with the
+    /// BackgroundJobEnded event.
+    pub job_id: u32,
+    /// The command running in the background.
+    pub command: Vec<String>,
+    /// The command's working directory.
+    pub cwd: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BackgroundJobEndedEvent {
+    /// Identifier for the BackgroundJobStarted that finished.
+    pub job_id: u32,
+    /// The command's exit code, if it exited on its own.
+    pub exit_code: Option<i32>,
+    /// Whether the job was stopped with `background_kill` or because the
+    /// session ended.
+    pub killed: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ArtifactCreatedEvent {
+    /// The tool call whose output was saved.
+    pub call_id: String,
+    /// Name the `read_artifact` tool takes.
+    pub name: String,
+    /// The file the output was written to.
+    pub path: PathBuf,
+    /// Size of the output.
+    pub bytes: u64,
+    /// `application/json` for MCP tool results, `text/plain` otherwise.
+    pub mime_type: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct McpServerStatusEvent {
+    pub server: String,
+    pub status: McpServerStatus,
+    /// Number of tools the server offers, when it is running.
+    pub tools: Option<usize>,
+    /// Why the server failed or stopped.
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum McpServerStatus {
+    /// The server started, or restarted after a failure.
+    Running,
+    /// The server stopped answering and is being restarted.
+    Restarting,
+    /// The server could not be started; it is retried with increasing
+    /// delays.
+    Failed,
+    /// The server was stopped with [`Op::RemoveMcpServer`].
+    Removed,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProviderHealthEvent {
+    /// Name of the provider, as in `model_providers`.
+    pub provider: String,
+    pub model: String,
+    pub status: ProviderHealthStatus,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ModelRoutedEvent {
+    /// Name of the route that matched.
+    pub route: String,
+    /// Name of the provider, as in `model_providers`.
+    pub provider: String,
+    pub model: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ProviderHealthStatus {
+    /// Requests failed repeatedly; the provider is not used until its
+    /// cooldown has passed.
+    Unhealthy,
+    /// Requests go to the failover provider instead.
+    FailedOver,
+    /// A request succeeded again after the provider was unhealthy.
+    Recovered,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BudgetEvent {
+    pub scope: BudgetScope,
+    pub resource: BudgetResource,
+    pub used: f64,
+    pub limit: f64,
+}
+
+impl fmt::Display for BudgetEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (used, limit) = match self.resource {
+            BudgetResource::CostUsd => {
+                (format!("${:.2}", self.used), format!("${:.2}", self.limit))
+            }
+            BudgetResource::Requests | BudgetResource::Tokens => {
+                (format!("{}", self.used), format!("{}", self.limit))
+            }
+        };
+        let unit = match self.resource {
+            BudgetResource::Requests => " requests",
+            BudgetResource::Tokens => " tokens",
+            BudgetResource::CostUsd => "",
+        };
+        write!(
+            f,
+            "{} {} budget: {used} of {limit}{unit} used",
+            self.scope, self.resource
+        )
+    }
+}
+
+/// Whose usage a budget limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum BudgetScope {
+    /// This session.
+    Session,
+    /// All sessions of the current day, in local time.
+    Daily,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetResource {
+    /// Completed requests to the model.
+    #[strum(serialize = "request")]
+    Requests,
+    /// Tokens, input and output.
+    #[strum(serialize = "token")]
+    Tokens,
+    /// Cost in US dollars, from the prices in the config.
+    #[strum(serialize = "cost")]
+    CostUsd,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecOutputStream {
@@ -752,6 +1140,9 @@ pub struct ExecApprovalRequestEvent {
     /// Optional human-readable reason for the approval (e.g. retry without sandbox).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reason: Option<String>,
+    /// Name of the command rule that required the approval, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched_rule: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -802,6 +1193,32 @@ pub struct PatchApplyEndEvent {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TurnDiffEvent {
     pub unified_diff: String,
+    /// The files changed so far in the turn, in the order of `unified_diff`.
+    #[serde(default)]
+    pub files: Vec<TurnDiffFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TurnDiffFile {
+    /// Path relative to the root of the Git repository, or absolute outside
+    /// of one. For deleted files, the path the file had.
+    pub path: PathBuf,
+    /// Where the file was before it was moved, if it was.
+    pub moved_from: Option<PathBuf>,
+    pub change: TurnDiffFileChange,
+    /// Number of hunks in `unified_diff`; 0 for binary files.
+    pub hunks: usize,
+    pub added_lines: usize,
+    pub removed_lines: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum TurnDiffFileChange {
+    Added,
+    Modified,
+    Deleted,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -820,6 +1237,27 @@ pub struct McpListToolsResponseEvent {
     pub tools: std::collections::HashMap<String, McpTool>,
 }
 
+/// Response payload for `Op::ListMcpPrompts`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct McpListPromptsResponseEvent {
+    pub prompts: Vec<McpPromptInfo>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct McpPromptInfo {
+    pub server: String,
+    pub prompt: McpPrompt,
+}
+
+/// Response payload for `Op::GetMcpPrompt`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct McpGetPromptResponseEvent {
+    pub server: String,
+    pub name: String,
+    /// The prompt's messages, or why it could not be rendered.
+    pub result: Result<GetPromptResult, String>,
+}
+
 /// Response payload for `Op::ListCustomPrompts`.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ListCustomPromptsResponseEvent {
@@ -12,12 +12,14 @@ use codex_core::protocol::AgentReasoningRawContentDeltaEvent;
 use codex_core::protocol::AgentReasoningRawContentEvent;
 use codex_core::protocol::ApplyPatchApprovalRequestEvent;
 use codex_core::protocol::BackgroundEventEvent;
+use codex_core::protocol::BudgetEvent;
 use codex_core::protocol::ErrorEvent;
 use codex_core::protocol::Event;
 use codex_core::protocol::EventMsg;
 use codex_core::protocol::ExecApprovalRequestEvent;
 use codex_core::protocol::ExecCommandBeginEvent;
 use codex_core::protocol::ExecCommandEndEvent;
+use codex_core::protocol::ExportFormat;
 use codex_core::protocol::InputItem;
 use codex_core::protocol::ListCustomPromptsResponseEvent;
 use codex_core::protocol::McpListToolsResponseEvent;
@@ -314,6 +316,16 @@ impl ChatWidget {
         );
     }
 
+    fn on_budget_approval_request(&mut self, id: String, ev: BudgetEvent) {
+        self.flush_answer_stream_with_separator();
+        let request = ApprovalRequest::Budget {
+            id,
+            message: ev.to_string(),
+        };
+        self.bottom_pane.push_approval_request(request);
+        self.request_redraw();
+    }
+
     fn on_exec_command_begin(&mut self, ev: ExecCommandBeginEvent) {
         self.flush_answer_stream_with_separator();
         let ev2 = ev.clone();
@@ -322,9 +334,12 @@ impl ChatWidget {
 
     fn on_exec_command_output_delta(
         &mut self,
-        _ev: codex_core::protocol::ExecCommandOutputDeltaEvent,
+        ev: codex_core::protocol::ExecCommandOutputDeltaEvent,
     ) {
-        // TODO: Handle streaming exec output if/when implemented
+        if let Some(cell) = self.active_exec_cell.as_mut() {
+            cell.push_live_output(&String::from_utf8_lossy(&ev.chunk));
+            self.request_redraw();
+        }
     }
 
     fn on_patch_apply_begin(&mut self, event: PatchApplyBeginEvent) {
@@ -396,6 +411,11 @@ impl ChatWidget {
         debug!("BackgroundEvent: {message}");
     }
 
+    fn on_budget_warning(&mut self, ev: BudgetEvent) {
+        self.add_to_history(history_cell::new_budget_warning(&ev));
+        self.request_redraw();
+    }
+
     fn on_stream_error(&mut self, message: String) {
         // Show stream errors in the transcript so users see retry/backoff info.
         self.add_to_history(history_cell::new_stream_error_event(message));
@@ -505,10 +525,15 @@ impl ChatWidget {
     pub(crate) fn handle_exec_approval_now(&mut self, id: String, ev: ExecApprovalRequestEvent) {
         self.flush_answer_stream_with_separator();
 
+        let reason = match (ev.reason, ev.matched_rule) {
+            (Some(reason), Some(rule)) => Some(format!("{reason} (command rule `{rule}`)")),
+            (None, Some(rule)) => Some(format!("Command rule `{rule}` requires approval")),
+            (reason, None) => reason,
+        };
         let request = ApprovalRequest::Exec {
             id,
             command: ev.command,
-            reason: ev.reason,
+            reason,
         };
         self.bottom_pane.push_approval_request(request);
         self.request_redraw();
@@ -826,6 +851,17 @@ impl ChatWidget {
             SlashCommand::Mcp => {
                 self.add_mcp_output();
             }
+            SlashCommand::Export => {
+                let name = match self.session_id {
+                    Some(id) => format!("codex-transcript-{id}.md"),
+                    None => "codex-transcript.md".to_string(),
+                };
+                self.app_event_tx
+                    .send(AppEvent::CodexOp(Op::ExportConversation {
+                        format: ExportFormat::Markdown,
+                        path: Some(PathBuf::from(name)),
+                    }));
+            }
             #[cfg(debug_assertions)]
             SlashCommand::TestApproval => {
                 use codex_core::protocol::EventMsg;
@@ -980,7 +1016,7 @@ impl ChatWidget {
             EventMsg::TaskStarted(_) => self.on_task_started(),
             EventMsg::TaskComplete(TaskCompleteEvent { .. }) => self.on_task_complete(),
             EventMsg::TokenCount(token_usage) => self.on_token_count(token_usage),
-            EventMsg::Error(ErrorEvent { message }) => self.on_error(message),
+            EventMsg::Error(ErrorEvent { message, .. }) => self.on_error(message),
             EventMsg::TurnAborted(ev) => match ev.reason {
                 TurnAbortReason::Interrupted => {
                     self.on_interrupted_turn();
@@ -992,6 +1028,8 @@ impl ChatWidget {
             EventMsg::PlanUpdate(update) => self.on_plan_update(update),
             EventMsg::ExecApprovalRequest(ev) => self.on_exec_approval_request(id, ev),
             EventMsg::ApplyPatchApprovalRequest(ev) => self.on_apply_patch_approval_request(id, ev),
+            EventMsg::BudgetApprovalRequest(ev) => self.on_budget_approval_request(id, ev),
+            EventMsg::BudgetWarning(ev) => self.on_budget_warning(ev),
             EventMsg::ExecCommandBegin(ev) => self.on_exec_command_begin(ev),
             EventMsg::ExecCommandOutputDelta(delta) => self.on_exec_command_output_delta(delta),
             EventMsg::PatchApplyBegin(ev) => self.on_patch_apply_begin(ev),
@@ -1004,8 +1042,11 @@ impl ChatWidget {
             EventMsg::GetHistoryEntryResponse(ev) => self.on_get_history_entry_response(ev),
             EventMsg::McpListToolsResponse(ev) => self.on_list_mcp_tools(ev),
             EventMsg::ListCustomPromptsResponse(ev) => self.on_list_custom_prompts(ev),
+            EventMsg::McpListPromptsResponse(_) | EventMsg::McpGetPromptResponse(_) => {}
             EventMsg::ShutdownComplete => self.on_shutdown_complete(),
-            EventMsg::TurnDiff(TurnDiffEvent { unified_diff }) => self.on_turn_diff(unified_diff),
+            EventMsg::TurnDiff(TurnDiffEvent { unified_diff, .. }) => {
+                self.on_turn_diff(unified_diff)
+            }
             EventMsg::BackgroundEvent(BackgroundEventEvent { message }) => {
                 self.on_background_event(message)
             }
@@ -1015,6 +1056,39 @@ impl ChatWidget {
                 self.app_event_tx
                     .send(crate::app_event::AppEvent::ConversationHistory(ev));
             }
+            EventMsg::ConversationExport(ev) => self.on_background_event(match ev.path {
+                Some(path) => format!("Transcript saved to {}", path.display()),
+                None => format!("Transcript exported as {}", ev.format),
+            }),
+            EventMsg::ConversationBranch(ev) => self.on_background_event(format!(
+                "Continuing on branch {} ({} turns)",
+                ev.branch_id, ev.num_turns
+            )),
+            EventMsg::BackgroundJobStarted(ev) => {
+                self.add_to_history(history_cell::new_background_job_started(ev));
+                self.request_redraw();
+            }
+            EventMsg::McpServerStatus(ev) => self.on_background_event(match ev.message {
+                Some(message) => format!("MCP server {}: {} ({message})", ev.server, ev.status),
+                None => format!("MCP server {}: {}", ev.server, ev.status),
+            }),
+            EventMsg::ModelRouted(ev) => {
+                self.add_to_history(history_cell::new_model_routed(ev));
+                self.request_redraw();
+            }
+            EventMsg::ProviderHealth(ev) => {
+                self.add_to_history(history_cell::new_provider_health(ev));
+                self.request_redraw();
+            }
+            EventMsg::BackgroundJobEnded(ev) => {
+                self.add_to_history(history_cell::new_background_job_ended(ev));
+                self.request_redraw();
+            }
+            EventMsg::ArtifactCreated(ev) => self.on_background_event(format!(
+                "Saved {} bytes of tool output to {}",
+                ev.bytes,
+                ev.path.display()
+            )),
         }
     }
 
@@ -1091,6 +1165,7 @@ impl ChatWidget {
                     model: Some(model_slug.clone()),
                     effort: Some(effort),
                     summary: None,
+                    dry_run: None,
                 }));
                 tx.send(AppEvent::UpdateModel(model_slug.clone()));
                 tx.send(AppEvent::UpdateReasoningEffort(effort));
@@ -1132,6 +1207,7 @@ impl ChatWidget {
                     model: None,
                     effort: None,
                     summary: None,
+                    dry_run: None,
                 }));
                 tx.send(AppEvent::UpdateAskForApprovalPolicy(approval));
                 tx.send(AppEvent::UpdateSandboxPolicy(sandbox.clone()));
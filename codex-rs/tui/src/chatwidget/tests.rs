@@ -12,14 +12,22 @@ use codex_core::protocol::AgentMessageEvent;
 use codex_core::protocol::AgentReasoningDeltaEvent;
 use codex_core::protocol::AgentReasoningEvent;
 use codex_core::protocol::ApplyPatchApprovalRequestEvent;
+use codex_core::protocol::BackgroundJobEndedEvent;
+use codex_core::protocol::BackgroundJobStartedEvent;
+use codex_core::protocol::BudgetEvent;
+use codex_core::protocol::BudgetResource;
+use codex_core::protocol::BudgetScope;
 use codex_core::protocol::Event;
 use codex_core::protocol::EventMsg;
 use codex_core::protocol::ExecApprovalRequestEvent;
 use codex_core::protocol::ExecCommandBeginEvent;
 use codex_core::protocol::ExecCommandEndEvent;
 use codex_core::protocol::FileChange;
+use codex_core::protocol::ModelRoutedEvent;
 use codex_core::protocol::PatchApplyBeginEvent;
 use codex_core::protocol::PatchApplyEndEvent;
+use codex_core::protocol::ProviderHealthEvent;
+use codex_core::protocol::ProviderHealthStatus;
 use codex_core::protocol::StreamErrorEvent;
 use codex_core::protocol::TaskCompleteEvent;
 use codex_core::protocol::TaskStartedEvent;
@@ -714,6 +722,7 @@ fn approval_modal_exec_snapshot() {
         command: vec!["bash".into(), "-lc".into(), "echo hello world".into()],
         cwd: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
         reason: Some("Model wants to run a command".into()),
+        matched_rule: None,
     };
     chat.handle_codex_event(Event {
         id: "sub-approve".into(),
@@ -880,6 +889,7 @@ fn status_widget_and_approval_modal_snapshot() {
         command: vec!["echo".into(), "hello world".into()],
         cwd: std::path::PathBuf::from("/tmp"),
         reason: Some("Codex wants to run a command".into()),
+        matched_rule: None,
     };
     chat.handle_codex_event(Event {
         id: "sub-approve-exec".into(),
@@ -1259,6 +1269,88 @@ fn stream_error_is_rendered_to_history() {
     assert!(blob.contains("idle timeout waiting for SSE"));
 }
 
+#[test]
+fn budget_warning_is_rendered_as_a_warning() {
+    let (mut chat, mut rx, _op_rx) = make_chatwidget_manual();
+    chat.handle_codex_event(Event {
+        id: "sub-1".into(),
+        msg: EventMsg::BudgetWarning(BudgetEvent {
+            scope: BudgetScope::Daily,
+            resource: BudgetResource::CostUsd,
+            used: 4.5,
+            limit: 5.0,
+        }),
+    });
+
+    let cells = drain_insert_history(&mut rx);
+    assert_eq!(
+        cells.len(),
+        1,
+        "expected one history cell for BudgetWarning"
+    );
+    let blob = lines_to_single_string(&cells[0]);
+    assert!(
+        blob.contains("Approaching the daily cost budget: $4.50 of $5.00 used"),
+        "unexpected budget warning: {blob:?}"
+    );
+    let warning = cells[0]
+        .iter()
+        .flat_map(|line| line.spans.iter())
+        .find(|span| span.content.starts_with('⚠'))
+        .unwrap();
+    assert_eq!(warning.style.fg, Some(ratatui::style::Color::Yellow));
+}
+
+#[test]
+fn routing_health_and_background_jobs_are_rendered_to_history() {
+    let (mut chat, mut rx, _op_rx) = make_chatwidget_manual();
+    let events = vec![
+        EventMsg::ModelRouted(ModelRoutedEvent {
+            route: "cheap".into(),
+            provider: "azure".into(),
+            model: "gpt-4o-mini".into(),
+        }),
+        EventMsg::ProviderHealth(ProviderHealthEvent {
+            provider: "azure".into(),
+            model: "gpt-4o-mini".into(),
+            status: ProviderHealthStatus::FailedOver,
+            message: "3 requests failed; using openai".into(),
+        }),
+        EventMsg::BackgroundJobStarted(BackgroundJobStartedEvent {
+            job_id: 2,
+            command: vec!["npm".into(), "run".into(), "dev".into()],
+            cwd: PathBuf::from("/repo"),
+        }),
+        EventMsg::BackgroundJobEnded(BackgroundJobEndedEvent {
+            job_id: 2,
+            exit_code: Some(1),
+            killed: false,
+        }),
+    ];
+    for msg in events {
+        chat.handle_codex_event(Event {
+            id: "sub-1".into(),
+            msg,
+        });
+    }
+
+    let blobs: Vec<String> = drain_insert_history(&mut rx)
+        .iter()
+        .map(|lines| lines_to_single_string(lines))
+        .collect();
+    assert_eq!(
+        blobs.len(),
+        4,
+        "expected one history cell per event: {blobs:?}"
+    );
+    assert!(blobs[0].contains("route cheap → gpt-4o-mini (azure)"));
+    assert!(blobs[1].contains("provider azure (gpt-4o-mini) failed over"));
+    assert!(blobs[1].contains("3 requests failed; using openai"));
+    assert!(blobs[2].contains("background job 2 started"));
+    assert!(blobs[2].contains("npm run dev"));
+    assert!(blobs[3].contains("background job 2 ✗ exited with code 1"));
+}
+
 #[test]
 fn headers_emitted_on_stream_begin_for_answer_and_not_for_reasoning() {
     let (mut chat, mut rx, _op_rx) = make_chatwidget_manual();
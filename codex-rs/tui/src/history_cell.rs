@@ -13,8 +13,14 @@ use codex_core::plan_tool::PlanItemArg;
 use codex_core::plan_tool::StepStatus;
 use codex_core::plan_tool::UpdatePlanArgs;
 use codex_core::project_doc::discover_project_doc_paths;
+use codex_core::protocol::BackgroundJobEndedEvent;
+use codex_core::protocol::BackgroundJobStartedEvent;
+use codex_core::protocol::BudgetEvent;
 use codex_core::protocol::FileChange;
 use codex_core::protocol::McpInvocation;
+use codex_core::protocol::ModelRoutedEvent;
+use codex_core::protocol::ProviderHealthEvent;
+use codex_core::protocol::ProviderHealthStatus;
 use codex_core::protocol::SandboxPolicy;
 use codex_core::protocol::SessionConfiguredEvent;
 use codex_core::protocol::TokenUsage;
@@ -107,16 +113,28 @@ pub(crate) struct ExecCell {
     start_time: Option<Instant>,
     duration: Option<Duration>,
     include_header: bool,
+    /// Most recent output streamed while the command is still running.
+    live_output: String,
 }
 impl HistoryCell for ExecCell {
     fn display_lines(&self) -> Vec<Line<'static>> {
-        exec_command_lines(
+        let mut lines = exec_command_lines(
             &self.command,
             &self.parsed,
             self.output.as_ref(),
             self.start_time,
             self.include_header,
-        )
+        );
+        if self.output.is_none() {
+            let live: Vec<&str> = self.live_output.lines().collect();
+            let start = live.len().saturating_sub(TOOL_CALL_MAX_LINES);
+            for raw in &live[start..] {
+                let mut line = ansi_escape_line(raw);
+                line.spans.insert(0, "    ".into());
+                lines.push(line.dim());
+            }
+        }
+        lines
     }
 
     fn transcript_lines(&self) -> Vec<Line<'static>> {
@@ -175,7 +193,23 @@ impl WidgetRef for &ExecCell {
     }
 }
 
+/// Upper bound on the live output kept for a running command; only its last
+/// few lines are shown.
+const LIVE_OUTPUT_MAX_BYTES: usize = 8 * 1024;
+
 impl ExecCell {
+    /// Append output streamed from a running command.
+    pub(crate) fn push_live_output(&mut self, chunk: &str) {
+        self.live_output.push_str(chunk);
+        if self.live_output.len() > LIVE_OUTPUT_MAX_BYTES {
+            let mut cut = self.live_output.len() - LIVE_OUTPUT_MAX_BYTES;
+            while !self.live_output.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.live_output.drain(..cut);
+        }
+    }
+
     /// Convert an active exec cell into a failed, completed exec cell.
     /// Replaces the spinner with a red ✗ and sets a zero/elapsed duration.
     pub(crate) fn into_failed(mut self) -> ExecCell {
@@ -360,6 +394,7 @@ pub(crate) fn new_active_exec_command(
         start_time: Some(Instant::now()),
         duration: None,
         include_header,
+        live_output: String::new(),
     }
 }
 
@@ -377,6 +412,7 @@ pub(crate) fn new_completed_exec_command(
         start_time: None,
         duration: Some(duration),
         include_header,
+        live_output: String::new(),
     }
 }
 
@@ -959,6 +995,85 @@ pub(crate) fn new_stream_error_event(message: String) -> PlainHistoryCell {
     PlainHistoryCell { lines }
 }
 
+pub(crate) fn new_budget_warning(ev: &BudgetEvent) -> PlainHistoryCell {
+    let lines: Vec<Line<'static>> = vec![
+        "".into(),
+        vec![
+            padded_emoji("⚠").yellow().bold(),
+            " Approaching the ".into(),
+            ev.to_string().into(),
+        ]
+        .into(),
+    ];
+    PlainHistoryCell { lines }
+}
+
+pub(crate) fn new_model_routed(ev: ModelRoutedEvent) -> PlainHistoryCell {
+    let lines: Vec<Line<'static>> = vec![
+        "".into(),
+        vec![
+            "route ".magenta(),
+            ev.route.bold(),
+            " → ".dim(),
+            ev.model.into(),
+            format!(" ({})", ev.provider).dim(),
+        ]
+        .into(),
+    ];
+    PlainHistoryCell { lines }
+}
+
+pub(crate) fn new_provider_health(ev: ProviderHealthEvent) -> PlainHistoryCell {
+    let status = match ev.status {
+        ProviderHealthStatus::Unhealthy => "unhealthy".red().bold(),
+        ProviderHealthStatus::FailedOver => "failed over".yellow().bold(),
+        ProviderHealthStatus::Recovered => "recovered".green().bold(),
+    };
+    let lines: Vec<Line<'static>> = vec![
+        "".into(),
+        vec![
+            "provider ".magenta(),
+            ev.provider.into(),
+            format!(" ({}) ", ev.model).dim(),
+            status,
+        ]
+        .into(),
+        vec!["  ⎿ ".dim(), ev.message.dim()].into(),
+    ];
+    PlainHistoryCell { lines }
+}
+
+pub(crate) fn new_background_job_started(ev: BackgroundJobStartedEvent) -> PlainHistoryCell {
+    let lines: Vec<Line<'static>> = vec![
+        "".into(),
+        vec![
+            "background job ".magenta(),
+            ev.job_id.to_string().bold(),
+            " started".into(),
+        ]
+        .into(),
+        vec!["  ⎿ ".dim(), strip_bash_lc_and_escape(&ev.command).dim()].into(),
+    ];
+    PlainHistoryCell { lines }
+}
+
+pub(crate) fn new_background_job_ended(ev: BackgroundJobEndedEvent) -> PlainHistoryCell {
+    let outcome: Vec<Span<'static>> = match ev.exit_code {
+        _ if ev.killed => vec!["✗".red().bold(), " killed".into()],
+        Some(0) => vec!["✓".green().bold(), " exited with code 0".into()],
+        Some(code) => vec!["✗".red().bold(), format!(" exited with code {code}").into()],
+        None => vec!["✗".red().bold(), " exited".into()],
+    };
+    let mut spans = vec![
+        "background job ".magenta(),
+        ev.job_id.to_string().bold(),
+        " ".into(),
+    ];
+    spans.extend(outcome);
+    let lines: Vec<Line<'static>> = vec!["".into(), spans.into()];
+    PlainHistoryCell { lines }
+}
+
 /// Render a user‑friendly plan update styled like a checkbox todo list.
 pub(crate) fn new_plan_update(update: UpdatePlanArgs) -> PlainHistoryCell {
     let UpdatePlanArgs { explanation, plan } = update;
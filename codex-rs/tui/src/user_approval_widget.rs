@@ -42,6 +42,11 @@ pub(crate) enum ApprovalRequest {
         reason: Option<String>,
         grant_root: Option<PathBuf>,
     },
+    Budget {
+        id: String,
+        /// Which limit is used up, e.g. "daily cost budget: $5.00 of $5.00 used".
+        message: String,
+    },
 }
 
 /// Options displayed in the *select* mode.
@@ -94,6 +99,29 @@ static PATCH_SELECT_OPTIONS: LazyLock<Vec<SelectOption>> = LazyLock::new(|| {
     ]
 });
 
+static BUDGET_SELECT_OPTIONS: LazyLock<Vec<SelectOption>> = LazyLock::new(|| {
+    vec![
+        SelectOption {
+            label: Line::from(vec!["Y".underlined(), "es".into()]),
+            description: "Continue past the limit for the rest of this turn",
+            key: KeyCode::Char('y'),
+            decision: ReviewDecision::Approved,
+        },
+        SelectOption {
+            label: Line::from(vec!["A".underlined(), "lways".into()]),
+            description: "Continue past the limit for the remainder of this session",
+            key: KeyCode::Char('a'),
+            decision: ReviewDecision::ApprovedForSession,
+        },
+        SelectOption {
+            label: Line::from(vec!["N".underlined(), "o".into()]),
+            description: "Stop the turn",
+            key: KeyCode::Char('n'),
+            decision: ReviewDecision::Denied,
+        },
+    ]
+});
+
 /// A modal prompting the user to approve or deny the pending request.
 pub(crate) struct UserApprovalWidget {
     approval_request: ApprovalRequest,
@@ -177,12 +205,24 @@ impl UserApprovalWidget {
 
                 Paragraph::new(contents).wrap(Wrap { trim: false })
             }
+            ApprovalRequest::Budget { message, .. } => {
+                let contents = vec![
+                    Line::from(vec![
+                        "? ".fg(Color::Cyan),
+                        "Codex has used up its ".bold(),
+                        message.clone().into(),
+                    ]),
+                    Line::from(""),
+                ];
+                Paragraph::new(contents).wrap(Wrap { trim: false })
+            }
         };
 
         Self {
             select_options: match &approval_request {
                 ApprovalRequest::Exec { .. } => &COMMAND_SELECT_OPTIONS,
                 ApprovalRequest::ApplyPatch { .. } => &PATCH_SELECT_OPTIONS,
+                ApprovalRequest::Budget { .. } => &BUDGET_SELECT_OPTIONS,
             },
             approval_request,
             app_event_tx,
@@ -320,6 +360,25 @@ impl UserApprovalWidget {
             ApprovalRequest::ApplyPatch { .. } => {
                 lines.push(Line::from(format!("patch approval decision: {decision:?}")));
             }
+            ApprovalRequest::Budget { message, .. } => {
+                let (mark, verdict, scope) = match decision {
+                    ReviewDecision::Approved => ("✔ ".fg(Color::Green), "approved", " this turn"),
+                    ReviewDecision::ApprovedForSession => {
+                        ("✔ ".fg(Color::Green), "approved", " this session")
+                    }
+                    ReviewDecision::Denied | ReviewDecision::Abort => {
+                        ("✗ ".fg(Color::Red), "did not approve", "")
+                    }
+                };
+                lines.push(Line::from(vec![
+                    mark,
+                    "You ".into(),
+                    verdict.bold(),
+                    " going over the budget".into(),
+                    scope.bold(),
+                    format!(" ({message})").dim(),
+                ]));
+            }
         }
         if !feedback.trim().is_empty() {
             lines.push(Line::from("feedback:"));
@@ -338,6 +397,10 @@ impl UserApprovalWidget {
                 id: id.clone(),
                 decision,
             },
+            ApprovalRequest::Budget { id, .. } => Op::BudgetApproval {
+                id: id.clone(),
+                decision,
+            },
         };
 
         self.app_event_tx.send(AppEvent::CodexOp(op));
@@ -390,6 +453,7 @@ impl WidgetRef for &UserApprovalWidget {
         let title = match &self.approval_request {
             ApprovalRequest::Exec { .. } => "Allow command?",
             ApprovalRequest::ApplyPatch { .. } => "Apply changes?",
+            ApprovalRequest::Budget { .. } => "Go over the budget?",
         };
         Line::from(title).render(title_area, buf);
 